@@ -1,19 +1,33 @@
 // Copyright © 2023 xtasks. All rights reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use xtasks::tasks::powerset::{powerset, PowersetBuilder};
+use xtasks::tasks::powerset::{powerset, Powerset, PowersetBuilder};
 
 pub fn main() {
-    // Example of creating a PowersetBuilder with a specific depth
-    let builder = PowersetBuilder::new(3);
-    // Running the powerset test with the specified builder configuration
-    if let Err(e) = builder.run() {
+    // Example of creating a Powerset configuration with a specific depth
+    let config = PowersetBuilder::new(3)
+        .build()
+        .expect("Failed to build Powerset configuration");
+    // Running the powerset test with the specified configuration
+    if let Err(e) = config.run() {
         eprintln!(
             "Error running powerset test with custom configuration: {:?}",
             e
         );
     }
 
+    // Example of running a Powerset configuration read from a config file
+    let config: Powerset = serde_json::from_str(
+        r#"{"depth": 1, "exclude_no_default_features": true}"#,
+    )
+    .expect("Failed to parse Powerset configuration");
+    if let Err(e) = config.run() {
+        eprintln!(
+            "Error running powerset test with parsed configuration: {:?}",
+            e
+        );
+    }
+
     // Example of performing a CI build with a default powerset of features
     if let Err(e) = powerset() {
         eprintln!(
@@ -104,6 +104,9 @@
 #![crate_name = "xtasks"]
 #![crate_type = "lib"]
 
+/// The `loggers` module provides the structured logging used by the tasks, and a way to
+/// capture the logs in tests.
+pub mod loggers;
 /// The `macros` module offers a collection of convenient macros designed to expedite common operations,
 /// reducing boilerplate and enhancing code readability.
 pub mod macros;
@@ -0,0 +1,169 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Structured logging for the tasks.
+//!
+//! Tasks report what they do with [`task_log`], which builds an `rlg` [`Log`]
+//! stamped with the current time and a random session id, and prints it to
+//! stderr. Tests can collect the logs emitted by a closure with [`capture_logs`]
+//! to check their levels.
+//!
+//! Logs below the process-wide level set with [`set_log_level`], or with the
+//! `XTASKS_LOG` environment variable, are dropped. Below
+//! [`LogLevel::INFO`], the output of child commands is hidden too unless they
+//! fail; see [`is_quiet`].
+
+use dtt::DateTime;
+use std::{
+    cell::RefCell,
+    env,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use vrd::Random;
+
+pub use rlg::{Log, LogFormat, LogLevel};
+
+thread_local! {
+    static CAPTURED_LOGS: RefCell<Option<Vec<Log>>> =
+        const { RefCell::new(None) };
+}
+
+/// The environment variable setting the log level, e.g. `XTASKS_LOG=debug`.
+pub const LOG_ENV: &str = "XTASKS_LOG";
+
+/// The rank of the level set with [`set_log_level`], or `0` if none was set.
+static LOG_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// Returns the rank of `level`, from the most verbose level to the levels
+/// turning logging off.
+const fn rank(level: &LogLevel) -> u8 {
+    match level {
+        LogLevel::ALL | LogLevel::TRACE => 1,
+        LogLevel::DEBUG | LogLevel::VERBOSE => 2,
+        LogLevel::INFO => 3,
+        LogLevel::WARNING => 4,
+        LogLevel::ERROR => 5,
+        LogLevel::FATAL => 6,
+        LogLevel::NONE | LogLevel::DISABLED => 7,
+    }
+}
+
+/// Parses a log level name as accepted by `XTASKS_LOG`: `trace`, `debug`,
+/// `info`, `warn`, `error` or `off`, in any case.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::loggers::{parse_log_level, LogLevel};
+///
+/// assert_eq!(parse_log_level("Warn"), Some(LogLevel::WARNING));
+/// assert_eq!(parse_log_level("loud"), None);
+/// ```
+pub fn parse_log_level(name: &str) -> Option<LogLevel> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "trace" => Some(LogLevel::TRACE),
+        "debug" => Some(LogLevel::DEBUG),
+        "info" => Some(LogLevel::INFO),
+        "warn" | "warning" => Some(LogLevel::WARNING),
+        "error" => Some(LogLevel::ERROR),
+        "off" | "none" => Some(LogLevel::NONE),
+        _ => None,
+    }
+}
+
+/// Sets the process-wide log level: logs below `level` are dropped.
+///
+/// With `None`, the level named by `XTASKS_LOG` applies again, or INFO if it
+/// is unset or invalid.
+pub fn set_log_level(level: Option<&LogLevel>) {
+    LOG_LEVEL.store(level.map_or(0, rank), Ordering::Relaxed);
+}
+
+/// Returns the rank of the process-wide log level: the one set with
+/// [`set_log_level`], else the one named by `XTASKS_LOG`, else INFO.
+fn level_rank() -> u8 {
+    match LOG_LEVEL.load(Ordering::Relaxed) {
+        0 => env::var(LOG_ENV)
+            .ok()
+            .and_then(|name| parse_log_level(&name))
+            .map_or(rank(&LogLevel::INFO), |level| rank(&level)),
+        set => set,
+    }
+}
+
+/// Returns `true` if logs at `level` are emitted.
+pub fn log_enabled(level: &LogLevel) -> bool {
+    rank(level) >= level_rank()
+}
+
+/// Returns `true` if the log level is above INFO, in which case the output of
+/// child commands is only shown when they fail.
+pub fn is_quiet() -> bool {
+    !log_enabled(&LogLevel::INFO)
+}
+
+/// Logs `message` for `component` at `level`, in the common log format, unless
+/// `level` is below the process-wide log level.
+///
+/// The log is printed to stderr, or collected instead while [`capture_logs`]
+/// runs.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::loggers::{capture_logs, task_log, LogLevel};
+///
+/// let ((), logs) =
+///     capture_logs(|| task_log(LogLevel::INFO, "Dependencies", "Starting"));
+/// assert_eq!(logs[0].level, LogLevel::INFO);
+/// assert_eq!(logs[0].description, "Starting");
+/// ```
+pub fn task_log(level: LogLevel, component: &str, message: &str) {
+    if !log_enabled(&level) {
+        return;
+    }
+    let log = Log::new(
+        &Random::default().int(0, 1_000_000_000).to_string(),
+        &DateTime::new().iso_8601,
+        &level,
+        component,
+        message,
+        &LogFormat::CLF,
+    );
+    CAPTURED_LOGS.with(|captured| {
+        match captured.borrow_mut().as_mut() {
+            Some(logs) => logs.push(log),
+            None => eprintln!("[{}] {component}: {message}", log.level),
+        }
+    });
+}
+
+/// Runs `f` and returns its result with the logs it emitted on this thread, in
+/// order.
+///
+/// Captures can be nested; the logs then only go to the innermost one.
+pub fn capture_logs<R, F>(f: F) -> (R, Vec<Log>)
+where
+    F: FnOnce() -> R,
+{
+    struct Restore(Option<Vec<Log>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CAPTURED_LOGS.with(|captured| {
+                *captured.borrow_mut() = previous;
+            });
+        }
+    }
+
+    let previous = CAPTURED_LOGS
+        .with(|captured| captured.borrow_mut().replace(Vec::new()));
+    let restore = Restore(previous);
+    let result = f();
+    let logs = CAPTURED_LOGS
+        .with(|captured| captured.borrow_mut().take())
+        .unwrap_or_default();
+    drop(restore);
+    (result, logs)
+}
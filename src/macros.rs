@@ -63,6 +63,34 @@ macro_rules! assert {
     };
 }
 
+/// Logs a formatted message at INFO level for a component.
+///
+/// The message is dropped when the log level is above INFO, e.g. with
+/// `--quiet` or `XTASKS_LOG=warn`.
+///
+/// # Parameters
+///
+/// * `$component`: The component the message is about, e.g. `"ci"`.
+/// * `args`: The format string and values of the message.
+///
+/// # Examples
+///
+/// ```rust
+/// use xtasks::macro_log_info;
+///
+/// macro_log_info!("ci", "running {} steps", 4);
+/// ```
+#[macro_export]
+macro_rules! macro_log_info {
+    ($component:expr, $($arg:tt)*) => {
+        $crate::loggers::task_log(
+            $crate::loggers::LogLevel::INFO,
+            $component,
+            &std::format!($($arg)*),
+        )
+    };
+}
+
 /// Macros related to executing shell commands.
 ///
 /// Executes a shell command, logs the start and completion of the operation, and handles any errors that occur.
@@ -84,26 +112,15 @@ macro_rules! assert {
 macro_rules! macro_execute_and_log {
     ($command:expr, $package:expr, $operation:expr, $start_message:expr, $complete_message:expr, $error_message:expr) => {{
         use anyhow::{Context, Result as AnyResult};
-        use $crate::loggers::{LogFormat, LogLevel};
-        use $crate::macro_log_info;
+        use $crate::loggers::{task_log, LogLevel};
 
-        macro_log_info!(
-            LogLevel::INFO,
-            $operation,
-            $start_message,
-            LogFormat::CLF
-        );
+        task_log(LogLevel::INFO, $operation, $start_message);
 
         $command
             .run()
             .map(|_| ())
             .map_err(|err| {
-                macro_log_info!(
-                    LogLevel::ERROR,
-                    $operation,
-                    $error_message,
-                    LogFormat::CLF
-                );
+                task_log(LogLevel::ERROR, $operation, $error_message);
                 err
             })
             .with_context(|| {
@@ -115,12 +132,7 @@ macro_rules! macro_execute_and_log {
                 )
             })?;
 
-        macro_log_info!(
-            LogLevel::INFO,
-            $operation,
-            $complete_message,
-            LogFormat::CLF
-        );
+        task_log(LogLevel::INFO, $operation, $complete_message);
         Ok(())
     }};
 }
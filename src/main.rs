@@ -12,14 +12,14 @@
 // a library crate part of the same project.
 extern crate xtasks;
 
-// The `main` function is the entry point of the Rust binary. The `fn main() -> Result<(), E>`
-// signature is a common pattern for binaries that may return an error. Here, `E` is specified
-// as `anyhow::Error`, which means the function can return any error that implements the
-// `std::error::Error` trait, providing flexibility in error handling.
-fn main() -> Result<(), anyhow::Error> {
+// The `main` function is the entry point of the Rust binary. It returns an `ExitCode` rather
+// than a `Result`, so that the category of a failure can be reported to the caller: CI
+// pipelines can then tell a lint failure from a test failure or a missing tool without
+// parsing the logs. The codes are documented in the `xtasks::ops::error` module.
+fn main() -> std::process::ExitCode {
     // This line calls a function `main` within the `tasks` module of the `xtasks` crate.
-    // This is the primary functionality of this binary. The `xtasks::tasks::main()` function
-    // is expected to perform the main operations of this binary and return a `Result`.
-    // If it returns an `Err`, that error will propagate out of this `main` function.
-    xtasks::tasks::main()
+    // This is the primary functionality of this binary. If it returns an `Err`,
+    // `xtasks::ops::error::exit` prints the error with its causes, as returning the `Result`
+    // would, and maps it to its exit code.
+    xtasks::ops::error::exit(xtasks::tasks::main())
 }
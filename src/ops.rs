@@ -8,15 +8,46 @@
 //! making it easier to perform tasks like cleaning up generated files, copying directory contents,
 //!
 use anyhow::{Error as AnyError, Result as AnyResult};
-use dialoguer::{theme::ColorfulTheme, Confirm};
+use dialoguer::{theme::ColorfulTheme, Confirm, Select};
 use fs_extra as fsx;
 use fsx::dir::CopyOptions;
 use glob::glob;
-use std::path::{Path, PathBuf};
+use runner::CommandSpec;
+use std::{
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
 
 // Re-exporting cmd from duct for convenience.
 pub use duct::cmd;
 
+// Re-exporting the loader of the `.env` file for convenience.
+pub use dotenv::load_dotenv;
+
+/// The `archive` module packages directories into archives and checksums them.
+pub mod archive;
+/// The `dotenv` module loads the `.env` file of the workspace.
+pub mod dotenv;
+/// The `error` module categorizes the failures of the tasks into exit codes.
+pub mod error;
+/// The `git` module provides helpers for inspecting git working trees.
+pub mod git;
+/// The `manifest` module reads and edits the version of a `Cargo.toml`.
+pub mod manifest;
+/// The `metadata` module reads the workspace layout from `cargo metadata`.
+pub mod metadata;
+/// The `output` module formats the output of child processes for display.
+pub mod output;
+/// The `runner` module abstracts the execution of external commands so that
+/// tasks can be exercised without spawning real processes.
+pub mod runner;
+/// The `summary` module formats the footer printed after every task.
+pub mod summary;
+/// The `tools` module detects and installs the external tools used by the tasks.
+pub mod tools;
+
 /// Removes files matching a given glob pattern.
 ///
 /// This function searches for files that match the provided glob pattern and removes them,
@@ -49,7 +80,8 @@ pub fn clean_files(pattern: &str) -> AnyResult<()> {
 /// Removes a single file.
 ///
 /// This function attempts to remove a file located at the specified path.
-/// If the file does not exist, it returns an error.
+/// If the file does not exist, it returns an error. In a dry run (see
+/// [`runner::is_dry_run`]), the removal is only printed.
 ///
 /// # Parameters
 ///
@@ -77,11 +109,20 @@ where
             "File not found",
         )));
     }
+    if runner::is_dry_run() {
+        runner::print_dry_run(&format!(
+            "rm {}",
+            runner::shell_quote(&path_ref.to_string_lossy())
+        ));
+        return Ok(());
+    }
     fsx::file::remove(path_ref).map_err(AnyError::new)
 }
 
 /// Removes a directory along with its contents.
 ///
+/// In a dry run (see [`runner::is_dry_run`]), the removal is only printed.
+///
 /// # Parameters
 ///
 /// - `path`: The path of the directory to remove.
@@ -105,9 +146,45 @@ where
             "Directory not found",
         )));
     }
+    if runner::is_dry_run() {
+        runner::print_dry_run(&format!(
+            "rm -r {}",
+            runner::shell_quote(&path_ref.to_string_lossy())
+        ));
+        return Ok(());
+    }
     fsx::dir::remove(path_ref).map_err(AnyError::new)
 }
 
+/// Fails unless `path` is inside `root`, so that a cleanup never removes
+/// anything outside the workspace, even through a symbolic link.
+///
+/// Both paths must exist, as they are compared once resolved.
+///
+/// # Errors
+///
+/// Returns an error if either path cannot be resolved, or if `path` is `root`
+/// itself or lies outside of it.
+pub fn ensure_within(root: &Path, path: &Path) -> AnyResult<()> {
+    let resolve = |path: &Path| {
+        path.canonicalize().map_err(|err| {
+            AnyError::new(err).context(format!(
+                "Failed to resolve {}",
+                path.display()
+            ))
+        })
+    };
+    let (root_dir, resolved) = (resolve(root)?, resolve(path)?);
+    if resolved == root_dir || !resolved.starts_with(&root_dir) {
+        return Err(AnyError::msg(format!(
+            "Refusing to remove '{}', which is outside the workspace root '{}'",
+            path.display(),
+            root_dir.display()
+        )));
+    }
+    Ok(())
+}
+
 /// Checks if a given path exists.
 ///
 /// # Parameters
@@ -124,6 +201,31 @@ where
     Path::exists(path.as_ref())
 }
 
+/// Returns the size of a file in bytes.
+///
+/// # Parameters
+///
+/// - `path`: The file to measure.
+///
+/// # Errors
+///
+/// This function will return an error if the file does not exist or cannot be
+/// inspected.
+pub fn file_size<P>(path: P) -> AnyResult<u64>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    std::fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|err| {
+            AnyError::new(err).context(format!(
+                "Failed to read the size of {}",
+                path.display()
+            ))
+        })
+}
+
 /// Copies the entire contents of a folder to another location.
 ///
 /// # Parameters
@@ -135,7 +237,7 @@ where
 /// # Returns
 ///
 /// A `Result` that is `Ok(u64)` representing the total number of bytes copied, or an `Err` wrapping
-/// an `anyhow::Error` if an error occurred.
+/// an `anyhow::Error` if an error occurred. Nothing is copied in a dry run, which returns `Ok(0)`.
 ///
 /// # Errors
 ///
@@ -152,11 +254,368 @@ where
     let mut opts = CopyOptions::new();
     opts.content_only = true;
     opts.overwrite = overwrite;
+    if runner::is_dry_run() {
+        runner::print_dry_run(&format!(
+            "cp -R {}/. {}",
+            runner::shell_quote(&from.as_ref().to_string_lossy()),
+            runner::shell_quote(&to.as_ref().to_string_lossy())
+        ));
+        return Ok(0);
+    }
     fsx::dir::copy(&from, &to, &opts).map_err(AnyError::new)
 }
 
+/// Copies the file `from` to `to`, creating the missing parent directories of
+/// `to` and overwriting it if it exists.
+///
+/// In a dry run (see [`runner::is_dry_run`]), the copy is only printed.
+///
+/// # Returns
+///
+/// The number of bytes copied, `0` in a dry run.
+///
+/// # Errors
+///
+/// This function will return an error if the directory cannot be created or the
+/// file cannot be copied.
+pub fn copy_file<P, Q>(from: P, to: Q) -> AnyResult<u64>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let (from, to) = (from.as_ref(), to.as_ref());
+    if runner::is_dry_run() {
+        runner::print_dry_run(&format!(
+            "cp {} {}",
+            runner::shell_quote(&from.to_string_lossy()),
+            runner::shell_quote(&to.to_string_lossy())
+        ));
+        return Ok(0);
+    }
+    if let Some(dir) =
+        to.parent().filter(|dir| !dir.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(dir).map_err(AnyError::new)?;
+    }
+    std::fs::copy(from, to).map_err(|err| {
+        AnyError::msg(format!(
+            "Failed to copy {} to {}: {err}",
+            from.display(),
+            to.display()
+        ))
+    })
+}
+
+/// Returns the files under `dir`, relative to it, with their contents. A
+/// missing directory has no files.
+fn dir_files(
+    dir: &Path,
+) -> AnyResult<std::collections::BTreeMap<PathBuf, Vec<u8>>> {
+    let mut files = std::collections::BTreeMap::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        if !current.is_dir() {
+            continue;
+        }
+        let entries = std::fs::read_dir(&current).map_err(|err| {
+            AnyError::new(err).context(format!(
+                "Failed to read {}",
+                current.display()
+            ))
+        })?;
+        for entry in entries {
+            let path = entry.map_err(AnyError::new)?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let contents = std::fs::read(&path).map_err(|err| {
+                    AnyError::new(err).context(format!(
+                        "Failed to read {}",
+                        path.display()
+                    ))
+                })?;
+                let relative = path
+                    .strip_prefix(dir)
+                    .unwrap_or(&path)
+                    .to_path_buf();
+                let _ = files.insert(relative, contents);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Compares the files of the directories `left` and `right`, recursively.
+///
+/// # Returns
+///
+/// The paths, relative to the directories and sorted, of the files that are
+/// missing from either directory or whose contents differ. A missing
+/// directory is compared as an empty one.
+///
+/// # Errors
+///
+/// This function will return an error if a directory or a file cannot be read.
+pub fn diff_dirs<P, Q>(left: P, right: Q) -> AnyResult<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let left = dir_files(left.as_ref())?;
+    let right = dir_files(right.as_ref())?;
+    let mut differing: Vec<PathBuf> = left
+        .iter()
+        .filter(|(path, contents)| right.get(*path) != Some(contents))
+        .map(|(path, _)| path.clone())
+        .chain(
+            right
+                .keys()
+                .filter(|path| !left.contains_key(*path))
+                .cloned(),
+        )
+        .collect();
+    differing.sort();
+    Ok(differing)
+}
+
+/// Writes `contents` to `path` atomically.
+///
+/// The data is first written to a temporary file in the same directory, which is
+/// then renamed over `path`, so readers never observe a partially written file.
+/// Missing parent directories are created. In a dry run (see
+/// [`runner::is_dry_run`]), the write is only printed.
+///
+/// # Parameters
+///
+/// - `path`: The destination file path.
+/// - `contents`: The bytes to write.
+///
+/// # Errors
+///
+/// This function will return an error if the directory cannot be created or the
+/// file cannot be written or renamed.
+pub fn write_atomic<P, C>(path: P, contents: C) -> AnyResult<()>
+where
+    P: AsRef<Path>,
+    C: AsRef<[u8]>,
+{
+    let path = path.as_ref();
+    if runner::is_dry_run() {
+        runner::print_dry_run(&format!(
+            "write {}",
+            runner::shell_quote(&path.to_string_lossy())
+        ));
+        return Ok(());
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let failed = |err: io::Error| {
+        AnyError::new(err).context(error::XtaskError::Io(format!(
+            "Failed to write {}",
+            path.display()
+        )))
+    };
+    std::fs::create_dir_all(&dir).map_err(failed)?;
+    let file_name = path.file_name().ok_or_else(|| {
+        AnyError::msg(format!("Invalid file path: {}", path.display()))
+    })?;
+    let tmp = dir.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    std::fs::write(&tmp, contents).map_err(failed)?;
+    std::fs::rename(&tmp, path).map_err(|err| {
+        let _ = std::fs::remove_file(&tmp);
+        failed(err)
+    })
+}
+
+/// Writes `contents` to `path` only if the file does not already hold them.
+///
+/// Skipping identical writes keeps file modification times stable, which avoids
+/// needless rebuilds and noise in tools that watch the filesystem.
+///
+/// # Parameters
+///
+/// - `path`: The destination file path.
+/// - `contents`: The text to write.
+///
+/// # Returns
+///
+/// A `Result` that is `Ok(true)` if the file was written and `Ok(false)` if it was
+/// already up to date.
+///
+/// # Errors
+///
+/// This function will return an error if the file cannot be written.
+pub fn write_file_if_changed<P>(
+    path: P,
+    contents: &str,
+) -> AnyResult<bool>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    if std::fs::read(path)
+        .is_ok_and(|current| current == contents.as_bytes())
+    {
+        return Ok(false);
+    }
+    write_atomic(path, contents)?;
+    Ok(true)
+}
+
+/// A backup of a file that is restored when dropped, unless it is kept.
+///
+/// The original contents are copied next to the file with an `.xtasks-backup`
+/// suffix, so they survive even if the process is killed before restoring. A file
+/// that did not exist when the backup was taken is removed on restore.
+#[derive(Debug)]
+pub struct FileBackup {
+    path: PathBuf,
+    backup: Option<PathBuf>,
+    done: bool,
+}
+
+impl FileBackup {
+    /// Returns the path of the backed up file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Puts the original contents back and removes the backup.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the original file cannot be restored.
+    pub fn restore(mut self) -> AnyResult<()> {
+        self.restore_in_place()
+    }
+
+    /// Keeps the current contents of the file and discards the backup.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the backup file cannot be removed.
+    pub fn keep(mut self) -> AnyResult<()> {
+        self.done = true;
+        match &self.backup {
+            Some(backup) => {
+                std::fs::remove_file(backup).map_err(AnyError::new)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn restore_in_place(&mut self) -> AnyResult<()> {
+        if self.done {
+            return Ok(());
+        }
+        self.done = true;
+        match &self.backup {
+            Some(backup) => std::fs::rename(backup, &self.path),
+            None if self.path.exists() => {
+                std::fs::remove_file(&self.path)
+            }
+            None => Ok(()),
+        }
+        .map_err(|err| {
+            AnyError::new(err).context(format!(
+                "Failed to restore {}",
+                self.path.display()
+            ))
+        })
+    }
+}
+
+impl Drop for FileBackup {
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_in_place() {
+            eprintln!("warning: {err:#}");
+        }
+    }
+}
+
+/// Backs up a file so that it can be restored after it has been modified.
+///
+/// # Parameters
+///
+/// - `path`: The file to back up. It does not need to exist.
+///
+/// # Returns
+///
+/// A [`FileBackup`] guard that restores the file when dropped.
+///
+/// # Errors
+///
+/// This function will return an error if the file exists but cannot be copied.
+///
+/// In a dry run (see [`runner::is_dry_run`]), the backup is only printed, and
+/// the returned guard leaves the file alone.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> anyhow::Result<()> {
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("Cargo.lock");
+/// std::fs::write(&path, "original")?;
+/// {
+///     let _backup = xtasks::ops::backup_file(&path)?;
+///     std::fs::write(&path, "modified")?;
+/// }
+/// assert_eq!(std::fs::read_to_string(&path)?, "original");
+/// # Ok(())
+/// # }
+/// ```
+pub fn backup_file<P: AsRef<Path>>(path: P) -> AnyResult<FileBackup> {
+    let path = path.as_ref().to_path_buf();
+    if runner::is_dry_run() {
+        // Nothing is modified in a dry run, so there is nothing to restore.
+        runner::print_dry_run(&format!(
+            "back up {}",
+            runner::shell_quote(&path.to_string_lossy())
+        ));
+        return Ok(FileBackup {
+            path,
+            backup: None,
+            done: true,
+        });
+    }
+    let backup = if path.exists() {
+        let mut name =
+            path.file_name().unwrap_or_default().to_os_string();
+        name.push(".xtasks-backup");
+        let backup = path.with_file_name(name);
+        let _ = std::fs::copy(&path, &backup).map_err(|err| {
+            AnyError::new(err).context(format!(
+                "Failed to back up {}",
+                path.display()
+            ))
+        })?;
+        Some(backup)
+    } else {
+        None
+    };
+    Ok(FileBackup {
+        path,
+        backup,
+        done: false,
+    })
+}
+
+/// The environment variable that answers [`confirm`] prompts without asking.
+pub const CONFIRM_ENV: &str = "XTASKS_CONFIRM";
+
 /// Prompts the user to confirm an action.
 ///
+/// The prompt is skipped when the `XTASKS_CONFIRM` environment variable is set to
+/// `yes`/`1`/`true` or `no`/`0`/`false`, which answers it accordingly. When it is
+/// unset, the user is prompted as before.
+///
 /// # Parameters
 ///
 /// - `question`: The question to present to the user.
@@ -168,14 +627,54 @@ where
 ///
 /// # Errors
 ///
-/// This function will return an error if the input interaction fails.
+/// This function will return an error if `XTASKS_CONFIRM` has an unrecognized value
+/// or the input interaction fails.
 pub fn confirm(question: &str) -> AnyResult<bool> {
+    if let Ok(answer) = std::env::var(CONFIRM_ENV) {
+        return match answer.trim().to_ascii_lowercase().as_str() {
+            "y" | "yes" | "1" | "true" => Ok(true),
+            "n" | "no" | "0" | "false" => Ok(false),
+            _ => Err(AnyError::msg(format!(
+                "Unrecognized {CONFIRM_ENV} value '{answer}', expected yes or no"
+            ))),
+        };
+    }
     Confirm::with_theme(&ColorfulTheme::default())
         .with_prompt(question)
         .interact()
         .map_err(AnyError::new)
 }
 
+/// Returns `true` if the user can answer prompts: stdin and stdout are
+/// terminals, and not running in CI (see [`is_ci`]).
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal() && !is_ci()
+}
+
+/// Asks the user to pick one of `items`, with `default` highlighted first.
+///
+/// # Returns
+///
+/// The index of the picked item, or `None` if the user cancelled with `Esc`
+/// or `q`.
+///
+/// # Errors
+///
+/// Returns an error if the input interaction fails, e.g. because the terminal
+/// is not interactive.
+pub fn select(
+    prompt: &str,
+    items: &[String],
+    default: usize,
+) -> AnyResult<Option<usize>> {
+    Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .default(default)
+        .interact_opt()
+        .map_err(AnyError::new)
+}
+
 /// Retrieves the root directory of the cargo project.
 ///
 /// This function assumes that it is called from a binary located in the same cargo workspace,
@@ -189,3 +688,100 @@ pub fn root_dir() -> PathBuf {
     xtask_dir.pop();
     xtask_dir
 }
+
+/// Returns `true` when running under a continuous integration service.
+///
+/// This checks the `CI` environment variable, which is set by GitHub Actions,
+/// GitLab CI, Travis, CircleCI, and most other providers. Empty values and
+/// `false`/`0` are treated as unset.
+pub fn is_ci() -> bool {
+    env_flag("CI")
+}
+
+/// Returns the command that opens `path` with the default application of the
+/// platform, e.g. `xdg-open` on Linux.
+pub fn open_command(path: &Path) -> CommandSpec {
+    let path = path.to_string_lossy();
+    if cfg!(target_os = "macos") {
+        CommandSpec::new("open").arg(path)
+    } else if cfg!(windows) {
+        CommandSpec::new("cmd").args(["/C", "start", ""]).arg(path)
+    } else {
+        CommandSpec::new("xdg-open").arg(path)
+    }
+}
+
+/// Opens `path` in the browser, unless running in CI (see [`is_ci`]).
+///
+/// Returns `true` if the browser was asked to open the file.
+///
+/// # Errors
+///
+/// Returns an error if the opener cannot be run or fails.
+pub fn open_in_browser(path: &Path) -> AnyResult<bool> {
+    if is_ci() {
+        return Ok(false);
+    }
+    let _ = runner::run(&open_command(path))?;
+    Ok(true)
+}
+
+/// Calls `f` until it succeeds, at most `attempts` times, waiting `delay` between
+/// attempts.
+///
+/// This is meant for operations that fail intermittently, such as downloads by
+/// `cargo install` on CI. Failed attempts are logged to stderr.
+///
+/// # Errors
+///
+/// Returns the error of the last attempt if every attempt fails.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use xtasks::ops::retry;
+///
+/// let mut calls = 0;
+/// let result = retry(3, Duration::ZERO, || {
+///     calls += 1;
+///     if calls < 2 { Err(anyhow::anyhow!("flaky")) } else { Ok(calls) }
+/// });
+/// assert_eq!(result.unwrap(), 2);
+/// ```
+pub fn retry<T, F>(
+    attempts: u32,
+    delay: Duration,
+    mut f: F,
+) -> AnyResult<T>
+where
+    F: FnMut() -> AnyResult<T>,
+{
+    let attempts = attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < attempts => {
+                eprintln!(
+                    "Attempt {attempt}/{attempts} failed: {err:#}, retrying in {}s",
+                    delay.as_secs()
+                );
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Returns `true` if the environment variable `name` is set to a value other
+/// than an empty string, `0`, or `false`.
+pub fn env_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|value| {
+        let value = value.trim();
+        !(value.is_empty()
+            || value == "0"
+            || value.eq_ignore_ascii_case("false"))
+    })
+}
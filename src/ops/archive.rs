@@ -0,0 +1,253 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Helpers for packaging directories into archives and checksumming them.
+//!
+//! The archives are created with `tar`, executed through the current
+//! [`runner`]. Zip archives need the bsdtar shipped with Windows and macOS,
+//! which picks the format from the extension. The SHA-256 checksums are
+//! computed in process, so they do not depend on `sha256sum` or `shasum`.
+
+use crate::ops::runner::{self, CommandSpec};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path, str::FromStr};
+
+/// The name of the checksum file written next to the archives.
+pub const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// The format of an archive.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum ArchiveFormat {
+    /// A gzip-compressed tarball, `.tar.gz`.
+    #[default]
+    TarGz,
+    /// A zip archive, `.zip`.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Returns the usual format of the archives for `target`: zip for Windows,
+    /// tar.gz elsewhere.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::ops::archive::ArchiveFormat;
+    ///
+    /// assert_eq!(
+    ///     ArchiveFormat::for_target("x86_64-pc-windows-msvc"),
+    ///     ArchiveFormat::Zip
+    /// );
+    /// assert_eq!(
+    ///     ArchiveFormat::for_target("aarch64-apple-darwin"),
+    ///     ArchiveFormat::TarGz
+    /// );
+    /// ```
+    pub fn for_target(target: &str) -> Self {
+        if target.contains("windows") {
+            Self::Zip
+        } else {
+            Self::TarGz
+        }
+    }
+
+    /// Returns the extension of the archives, without the leading dot.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            Self::TarGz => "tar.gz",
+            Self::Zip => "zip",
+        }
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for ArchiveFormat {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "tar.gz" | "tgz" | "targz" => Ok(Self::TarGz),
+            "zip" => Ok(Self::Zip),
+            _ => Err(AnyError::msg(format!(
+                "Unknown archive format '{value}', expected tar.gz or zip"
+            ))),
+        }
+    }
+}
+
+/// Returns the `tar` invocation packaging the directory `dir` into
+/// `archive`, with `dir` as the top-level directory of the archive.
+pub fn command(
+    format: ArchiveFormat,
+    dir: &Path,
+    archive: &Path,
+) -> CommandSpec {
+    let parent = dir
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let name = dir.file_name().map_or_else(
+        || dir.to_string_lossy(),
+        |name| name.to_string_lossy(),
+    );
+    let flags = match format {
+        ArchiveFormat::TarGz => "-czf",
+        ArchiveFormat::Zip => "-acf",
+    };
+    CommandSpec::new("tar")
+        .arg(flags)
+        .arg(archive.to_string_lossy())
+        .arg("-C")
+        .arg(parent.to_string_lossy())
+        .arg(name)
+}
+
+/// Packages the directory `dir` into `archive`, see [`command`].
+///
+/// # Errors
+///
+/// Returns an error if `tar` fails.
+pub fn create(
+    format: ArchiveFormat,
+    dir: &Path,
+    archive: &Path,
+) -> AnyResult<()> {
+    let command = command(format, dir, archive);
+    let _ = runner::run(&command)
+        .with_context(|| format!("Failed to execute '{command}'"))?;
+    Ok(())
+}
+
+/// The round constants of SHA-256.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b,
+    0x59f111f1, 0x923f82a4, 0xab1c5ed5, 0xd807aa98, 0x12835b01,
+    0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7,
+    0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc,
+    0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152,
+    0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147,
+    0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+    0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819,
+    0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116, 0x1e376c08,
+    0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f,
+    0x682e6ff3, 0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208,
+    0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Returns the SHA-256 digest of `data` as lowercase hexadecimal.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::archive::sha256_hex;
+///
+/// assert_eq!(
+///     sha256_hex(b"abc"),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+pub fn sha256_hex(data: &[u8]) -> String {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f,
+        0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([
+                word[0], word[1], word[2], word[3],
+            ]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7)
+                ^ w[i - 15].rotate_right(18)
+                ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17)
+                ^ w[i - 2].rotate_right(19)
+                ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] =
+            state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6)
+                ^ e.rotate_right(11)
+                ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2)
+                ^ a.rotate_right(13)
+                ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in
+            state.iter_mut().zip([a, b, c, d, e, f, g, h])
+        {
+            *word = word.wrapping_add(value);
+        }
+    }
+    state.iter().map(|word| format!("{word:08x}")).collect()
+}
+
+/// Returns the contents of a `SHA256SUMS` file listing `files` by name, in
+/// the format of `sha256sum`, so it can be verified with
+/// `sha256sum --check SHA256SUMS`.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read.
+pub fn checksums(files: &[impl AsRef<Path>]) -> AnyResult<String> {
+    let mut sums = String::new();
+    for file in files {
+        let file = file.as_ref();
+        let data = fs::read(file).with_context(|| {
+            format!("Failed to read {}", file.display())
+        })?;
+        let name = file.file_name().map_or_else(
+            || file.to_string_lossy(),
+            |name| name.to_string_lossy(),
+        );
+        sums.push_str(&format!("{}  {name}\n", sha256_hex(&data)));
+    }
+    Ok(sums)
+}
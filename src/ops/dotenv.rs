@@ -0,0 +1,192 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Loading of the `.env` file of the workspace into the environment.
+//!
+//! The file holds `KEY=VALUE` lines, e.g. the token of a coverage upload,
+//! which are set before the tasks run. Variables already set in the
+//! environment are never overridden, and the values are never logged.
+
+use crate::{
+    loggers::{task_log, LogLevel},
+    ops::error::XtaskError,
+};
+use anyhow::{Context, Result as AnyResult};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the file loaded from the root of the workspace.
+pub const DOTENV_FILE: &str = ".env";
+
+/// Returns the unquoted `value` of a line, with the escapes of a double-quoted
+/// value replaced and the comment of an unquoted one removed.
+fn unquote(value: &str) -> String {
+    let quoted = |quote: char| {
+        value.len() >= 2
+            && value.starts_with(quote)
+            && value.ends_with(quote)
+    };
+    if quoted('\'') {
+        return value[1..value.len() - 1].to_string();
+    }
+    if quoted('"') {
+        let mut unescaped = String::new();
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match (c, chars.clone().next()) {
+                ('\\', Some('n')) => unescaped.push('\n'),
+                ('\\', Some(escaped @ ('"' | '\\'))) => {
+                    unescaped.push(escaped);
+                }
+                _ => {
+                    unescaped.push(c);
+                    continue;
+                }
+            }
+            let _ = chars.next();
+        }
+        return unescaped;
+    }
+    value
+        .split(" #")
+        .next()
+        .unwrap_or_default()
+        .trim_end()
+        .to_string()
+}
+
+/// Parses the `KEY=VALUE` lines of a `.env` file, in order.
+///
+/// Blank lines and `#` comments are skipped, a leading `export` is allowed,
+/// and values may be single- or double-quoted.
+///
+/// # Errors
+///
+/// Returns an error naming the first line that is not a `KEY=VALUE` line with
+/// a valid variable name. The line itself is left out, as it may hold a
+/// secret.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::dotenv::parse_dotenv;
+///
+/// let vars = parse_dotenv("# upload\nexport TOKEN='abc'\nREGION=eu # main\n")
+///     .unwrap();
+/// assert_eq!(
+///     vars,
+///     [
+///         ("TOKEN".to_string(), "abc".to_string()),
+///         ("REGION".to_string(), "eu".to_string())
+///     ]
+/// );
+/// ```
+pub fn parse_dotenv(
+    contents: &str,
+) -> AnyResult<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let valid = line.split_once('=').filter(|(key, _)| {
+            let key = key.trim();
+            !key.is_empty()
+                && !key.starts_with(|c: char| c.is_ascii_digit())
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        let Some((key, value)) = valid else {
+            return Err(XtaskError::ConfigInvalid(format!(
+                "Invalid line {} in {DOTENV_FILE}, expected KEY=VALUE",
+                index + 1
+            ))
+            .into());
+        };
+        vars.push((key.trim().to_string(), unquote(value.trim())));
+    }
+    Ok(vars)
+}
+
+/// Returns the root of the workspace containing `dir`: the closest ancestor
+/// whose manifest has a `[workspace]` table, or else the closest one with a
+/// manifest, or else `dir`.
+fn workspace_root(dir: &Path) -> PathBuf {
+    let manifests = || {
+        dir.ancestors()
+            .filter(|dir| dir.join("Cargo.toml").is_file())
+    };
+    manifests()
+        .find(|dir| {
+            fs::read_to_string(dir.join("Cargo.toml"))
+                .ok()
+                .and_then(|contents| {
+                    contents.parse::<toml::Table>().ok()
+                })
+                .is_some_and(|manifest| {
+                    manifest.contains_key("workspace")
+                })
+        })
+        .or_else(|| manifests().next())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+/// Loads the variables of the `.env` file at `path`, or of the one at the
+/// root of the current workspace if `path` is `None`, into the environment.
+///
+/// A variable already set in the environment is kept, as is the first
+/// definition of a variable defined twice. The number of loaded variables is
+/// logged at DEBUG level, without their values.
+///
+/// # Returns
+///
+/// The number of variables set.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, see
+/// [`parse_dotenv`]. A workspace without a `.env` file is not an error, a
+/// missing `path` is.
+pub fn load_dotenv(path: Option<&Path>) -> AnyResult<usize> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let dir = env::current_dir().context(XtaskError::Io(
+                "Failed to read the current directory".to_string(),
+            ))?;
+            let path = workspace_root(&dir).join(DOTENV_FILE);
+            if !path.is_file() {
+                return Ok(0);
+            }
+            path
+        }
+    };
+    let contents = fs::read_to_string(&path).with_context(|| {
+        XtaskError::Io(format!("Failed to read {}", path.display()))
+    })?;
+    let vars = parse_dotenv(&contents).with_context(|| {
+        XtaskError::ConfigInvalid(format!(
+            "Failed to parse {}",
+            path.display()
+        ))
+    })?;
+    let mut loaded = 0;
+    for (key, value) in vars {
+        if env::var_os(&key).is_none() {
+            env::set_var(&key, value);
+            loaded += 1;
+        }
+    }
+    task_log(
+        LogLevel::DEBUG,
+        "dotenv",
+        &format!("Loaded {loaded} variables from {}", path.display()),
+    );
+    Ok(loaded)
+}
@@ -0,0 +1,174 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The categories of task failures, and the exit codes they map to.
+//!
+//! The tasks return [`anyhow`] errors. When a failure falls in a known
+//! category, an [`XtaskError`] is part of the error, as the error itself or
+//! as one of its contexts, so callers can [`downcast`](anyhow::Error::downcast_ref)
+//! it. [`exit_code`] maps an error to the exit code of the process, so CI
+//! pipelines can tell failures apart without parsing the logs:
+//!
+//! | Code | Failure                                                              |
+//! |------|----------------------------------------------------------------------|
+//! | 0    | none                                                                 |
+//! | 1    | any other failure                                                    |
+//! | 2    | an invalid configuration, or invalid command-line arguments          |
+//! | 10   | a lint step: `fmt`, `clippy`, `docs`, `typos`, `sort-deps`, `deny`, `unused deps` or `semver` |
+//! | 11   | a test step: `test`, `doc tests`, `miri` or `coverage`               |
+//! | 12   | any other step, e.g. `check` or a custom step                        |
+//! | 20   | a missing tool or toolchain that may not be installed                |
+//! | 74   | a file that cannot be read or written                                |
+
+use anyhow::Error as AnyError;
+use std::{fmt, process::ExitCode};
+
+/// The exit code of any other failure.
+pub const EXIT_FAILURE: u8 = 1;
+/// The exit code of an invalid configuration, like clap's usage errors.
+pub const EXIT_CONFIG: u8 = 2;
+/// The exit code of a failed lint step.
+pub const EXIT_LINT: u8 = 10;
+/// The exit code of a failed test step.
+pub const EXIT_TEST: u8 = 11;
+/// The exit code of any other failed step.
+pub const EXIT_STEP: u8 = 12;
+/// The exit code of a missing tool.
+pub const EXIT_TOOL_MISSING: u8 = 20;
+/// The exit code of a failed read or write, as `EX_IOERR` of `sysexits.h`.
+pub const EXIT_IO: u8 = 74;
+
+/// The steps whose failure exits with [`EXIT_LINT`].
+const LINT_STEPS: [&str; 8] = [
+    "fmt",
+    "clippy",
+    "docs",
+    "typos",
+    "sort-deps",
+    "deny",
+    "unused deps",
+    "semver",
+];
+
+/// The steps whose failure exits with [`EXIT_TEST`].
+const TEST_STEPS: [&str; 4] = ["test", "doc tests", "miri", "coverage"];
+
+/// A categorized task failure.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum XtaskError {
+    /// An external tool is not installed, and may not be installed
+    /// automatically.
+    ToolMissing {
+        /// The missing binary or toolchain.
+        tool: String,
+        /// The command installing it.
+        install: String,
+    },
+    /// A step of a task failed, e.g. the `clippy` step of the CI checks.
+    StepFailed {
+        /// The name of the step, possibly followed by its feature variant or
+        /// package, e.g. `test [all-features]`.
+        step: String,
+    },
+    /// The configuration of the tasks is invalid.
+    ConfigInvalid(String),
+    /// A file cannot be read or written.
+    Io(String),
+    /// Any other failure.
+    Other(String),
+}
+
+impl XtaskError {
+    /// Returns the exit code of the failure, see the [module](self)
+    /// documentation.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::ToolMissing { .. } => EXIT_TOOL_MISSING,
+            Self::StepFailed { step } => step_exit_code(step),
+            Self::ConfigInvalid(_) => EXIT_CONFIG,
+            Self::Io(_) => EXIT_IO,
+            Self::Other(_) => EXIT_FAILURE,
+        }
+    }
+}
+
+impl fmt::Display for XtaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ToolMissing { tool, install } => write!(
+                f,
+                "'{tool}' is not installed, install it with '{install}'"
+            ),
+            Self::StepFailed { step } => {
+                write!(f, "The '{step}' step failed")
+            }
+            Self::ConfigInvalid(message)
+            | Self::Io(message)
+            | Self::Other(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for XtaskError {}
+
+/// Returns the exit code of a failure of the step `step`, ignoring its
+/// feature variant or package.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::error::{step_exit_code, EXIT_LINT, EXIT_TEST};
+///
+/// assert_eq!(step_exit_code("clippy [all-features]"), EXIT_LINT);
+/// assert_eq!(step_exit_code("miri (core)"), EXIT_TEST);
+/// ```
+pub fn step_exit_code(step: &str) -> u8 {
+    let base = step
+        .split(" [")
+        .next()
+        .and_then(|name| name.split(" (").next())
+        .unwrap_or(step);
+    if LINT_STEPS.contains(&base) {
+        EXIT_LINT
+    } else if TEST_STEPS.contains(&base) {
+        EXIT_TEST
+    } else {
+        EXIT_STEP
+    }
+}
+
+/// Returns the [`XtaskError`] of `error`, if it has one: the error itself, one
+/// of its contexts, or one of its sources.
+pub fn category(error: &AnyError) -> Option<&XtaskError> {
+    error.downcast_ref::<XtaskError>().or_else(|| {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<XtaskError>())
+    })
+}
+
+/// Returns the exit code of `error`: the one of its [category], or
+/// [`EXIT_FAILURE`].
+pub fn exit_code(error: &AnyError) -> u8 {
+    category(error).map_or(EXIT_FAILURE, XtaskError::exit_code)
+}
+
+/// Returns the exit code of the process for the `result` of a task, printing
+/// the error and its causes if it failed.
+///
+/// # Examples
+///
+/// ```no_run
+/// fn main() -> std::process::ExitCode {
+///     xtasks::ops::error::exit(xtasks::tasks::main())
+/// }
+/// ```
+pub fn exit(result: anyhow::Result<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error:?}");
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Helpers for inspecting the state of a git working tree.
+//!
+//! All commands are executed through the current [`runner`], so they can be
+//! replaced in tests like any other command.
+
+use crate::ops::runner::{self, CommandSpec};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+/// Returns a `git -C <dir>` invocation with the given arguments.
+pub fn git<I, S>(dir: &Path, args: I) -> CommandSpec
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    CommandSpec::new("git")
+        .arg("-C")
+        .arg(dir.to_string_lossy())
+        .args(args)
+}
+
+/// A single entry of `git status --porcelain`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StatusEntry {
+    /// The two-letter status code, e.g. ` M` or `??`.
+    pub code: String,
+    /// The path of the file, relative to the repository root.
+    pub path: PathBuf,
+}
+
+/// Returns the entries reported by `git status --porcelain` for `dir`.
+///
+/// # Errors
+///
+/// Returns an error if `dir` is not inside a git repository or git cannot be run.
+pub fn status(dir: &Path) -> AnyResult<Vec<StatusEntry>> {
+    let output = runner::query(&git(
+        dir,
+        ["status", "--porcelain", "-z", "--untracked-files=all"],
+    ))?;
+    Ok(parse_status(&output))
+}
+
+/// Parses the NUL-separated output of `git status --porcelain -z`.
+pub fn parse_status(output: &str) -> Vec<StatusEntry> {
+    let mut entries = Vec::new();
+    let mut fields =
+        output.split('\0').filter(|field| !field.is_empty());
+    while let Some(field) = fields.next() {
+        if field.len() < 4 {
+            continue;
+        }
+        let (code, path) = field.split_at(2);
+        // Renames and copies are followed by the original path, which we skip.
+        if code.contains('R') || code.contains('C') {
+            let _ = fields.next();
+        }
+        entries.push(StatusEntry {
+            code: code.to_string(),
+            path: PathBuf::from(&path[1..]),
+        });
+    }
+    entries
+}
+
+/// A snapshot of the dirty files in a working tree, used to work out which files
+/// a command modified.
+///
+/// Besides the porcelain status, the snapshot hashes the contents of each dirty
+/// file, so that further edits to an already modified file are detected too.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    files: BTreeMap<PathBuf, (String, Option<u64>)>,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the working tree containing `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the status of the working tree cannot be read.
+    pub fn take(dir: &Path) -> AnyResult<Self> {
+        let root =
+            runner::query(&git(dir, ["rev-parse", "--show-toplevel"]))?;
+        let root = PathBuf::from(root.trim());
+        let files = status(dir)?
+            .into_iter()
+            .map(|entry| {
+                let hash = fs::read(root.join(&entry.path)).ok().map(
+                    |contents| {
+                        let mut hasher = DefaultHasher::new();
+                        contents.hash(&mut hasher);
+                        hasher.finish()
+                    },
+                );
+                (entry.path, (entry.code, hash))
+            })
+            .collect();
+        Ok(Self { files })
+    }
+
+    /// Returns the files whose status or contents differ from `before`, sorted by path.
+    pub fn changed_since(&self, before: &Self) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = self
+            .files
+            .iter()
+            .filter(|(path, state)| {
+                before.files.get(*path) != Some(state)
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        // Files that were dirty before and are clean now were also touched.
+        changed.extend(
+            before
+                .files
+                .keys()
+                .filter(|path| !self.files.contains_key(*path))
+                .cloned(),
+        );
+        changed.sort();
+        changed
+    }
+}
+
+/// A commit listed by [`log`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LogEntry {
+    /// The full SHA of the commit.
+    pub sha: String,
+    /// The name of the author.
+    pub author: String,
+    /// The first line of the commit message.
+    pub subject: String,
+}
+
+/// The separator of the fields of a [`log`] entry (ASCII unit separator).
+const LOG_FIELD: char = '\u{1f}';
+
+/// The terminator of a [`log`] entry (ASCII record separator).
+const LOG_RECORD: char = '\u{1e}';
+
+/// Returns the commits of `range` in `dir`, newest first, e.g. `v0.1.0..HEAD`,
+/// or all the commits reachable from `HEAD` when `range` is `None`.
+///
+/// # Errors
+///
+/// Returns an error if the range is unknown or git cannot be run.
+pub fn log(
+    dir: &Path,
+    range: Option<&str>,
+) -> AnyResult<Vec<LogEntry>> {
+    let output = runner::query(&git(
+        dir,
+        [
+            "log",
+            "--no-color",
+            "--format=%H%x1f%an%x1f%s%x1e",
+            range.unwrap_or("HEAD"),
+        ],
+    ))?;
+    Ok(parse_log(&output))
+}
+
+/// Parses the output of `git log --format=%H%x1f%an%x1f%s%x1e`.
+pub fn parse_log(output: &str) -> Vec<LogEntry> {
+    output
+        .split(LOG_RECORD)
+        .filter_map(|record| {
+            let mut fields = record.trim_start().splitn(3, LOG_FIELD);
+            let sha = fields.next().filter(|sha| !sha.is_empty())?;
+            Some(LogEntry {
+                sha: sha.to_string(),
+                author: fields.next()?.to_string(),
+                subject: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the most recent tag reachable from `HEAD` in `dir`, or `None` if
+/// there is none.
+///
+/// # Errors
+///
+/// Returns an error if `dir` is not inside a git repository or git cannot be run.
+pub fn latest_tag(dir: &Path) -> AnyResult<Option<String>> {
+    let tags = runner::query(&git(dir, ["tag", "--list"]))?;
+    if tags.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(
+        runner::query(&git(dir, ["describe", "--tags", "--abbrev=0"]))
+            .ok()
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty()),
+    )
+}
+
+/// Returns the abbreviated hash of `HEAD` in `dir`, e.g. `4aee181`.
+///
+/// # Errors
+///
+/// Returns an error if `dir` is not inside a git repository with a commit, or
+/// git cannot be run.
+pub fn short_head(dir: &Path) -> AnyResult<String> {
+    let sha =
+        runner::query(&git(dir, ["rev-parse", "--short", "HEAD"]))?;
+    let sha = sha.trim();
+    if sha.is_empty() {
+        return Err(AnyError::msg(format!(
+            "Failed to read the commit of HEAD in {}",
+            dir.display()
+        )));
+    }
+    Ok(sha.to_string())
+}
+
+/// Makes sure the working tree containing `dir` has no uncommitted changes.
+///
+/// # Errors
+///
+/// Returns an error listing the dirty files, or an error if the status of the
+/// working tree cannot be read.
+pub fn ensure_git_clean(dir: &Path) -> AnyResult<()> {
+    let entries = status(dir)?;
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let files: Vec<String> = entries
+        .iter()
+        .map(|entry| entry.path.display().to_string())
+        .collect();
+    Err(AnyError::msg(format!(
+        "The working tree has uncommitted changes, commit or stash them first: {}",
+        files.join(", ")
+    )))
+}
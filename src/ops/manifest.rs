@@ -0,0 +1,271 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Helpers for reading and editing the version and the `rust-version` of a
+//! `Cargo.toml`.
+//!
+//! The version is edited in place, line by line, so the formatting and the
+//! comments of the manifest are kept.
+
+use crate::ops;
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use std::{fs, path::Path};
+
+/// The sections of a manifest that may hold the package version.
+const VERSION_SECTIONS: [&str; 2] = ["package", "workspace.package"];
+
+/// Returns the package version declared in the manifest `contents`: the
+/// `[package]` version, or the `[workspace.package]` one when the package
+/// inherits it or there is no package.
+///
+/// # Errors
+///
+/// Returns an error if `contents` is not valid TOML or declares no version.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::manifest::parse_version;
+///
+/// let manifest = "[package]\nname = \"app\"\nversion = \"0.1.0\"\n";
+/// assert_eq!(parse_version(manifest).unwrap(), "0.1.0");
+/// ```
+pub fn parse_version(contents: &str) -> AnyResult<String> {
+    let document: toml::Table = toml::from_str(contents)?;
+    package_field(&document, "version").ok_or_else(|| {
+        AnyError::msg("The manifest declares no version")
+    })
+}
+
+/// Returns the repository URL declared in the manifest `contents`, without a
+/// trailing `.git`, or `None` if there is none.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::manifest::parse_repository;
+///
+/// let manifest =
+///     "[package]\nrepository = \"https://github.com/o/app.git\"\n";
+/// assert_eq!(
+///     parse_repository(manifest).as_deref(),
+///     Some("https://github.com/o/app")
+/// );
+/// ```
+pub fn parse_repository(contents: &str) -> Option<String> {
+    let document: toml::Table = toml::from_str(contents).ok()?;
+    let url = package_field(&document, "repository")?;
+    let url = url.trim_end_matches('/');
+    Some(url.strip_suffix(".git").unwrap_or(url).to_string())
+}
+
+/// Returns the repository URL declared in the manifest at `path`, see
+/// [`parse_repository`].
+pub fn package_repository(path: &Path) -> Option<String> {
+    parse_repository(&fs::read_to_string(path).ok()?)
+}
+
+/// Returns the string `key` of the `[package]` section, or of the
+/// `[workspace.package]` one when the package inherits it or there is no
+/// package.
+fn package_field(document: &toml::Table, key: &str) -> Option<String> {
+    let field = |table: Option<&toml::Value>| {
+        table
+            .and_then(|table| table.get(key))
+            .and_then(toml::Value::as_str)
+            .map(ToString::to_string)
+    };
+    field(document.get("package")).or_else(|| {
+        field(
+            document
+                .get("workspace")
+                .and_then(|workspace| workspace.get("package")),
+        )
+    })
+}
+
+/// Returns the package version declared in the manifest at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the manifest cannot be read or declares no version.
+pub fn package_version(path: &Path) -> AnyResult<String> {
+    let contents = fs::read_to_string(path).with_context(|| {
+        format!("Failed to read {}", path.display())
+    })?;
+    parse_version(&contents)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Returns the manifest `contents` with the `version = "..."` lines of its
+/// `[package]` and `[workspace.package]` sections set to `version`.
+///
+/// # Errors
+///
+/// Returns an error if neither section declares a version as a string, e.g.
+/// because the package inherits it and there is no workspace version.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::manifest::with_version;
+///
+/// let manifest = "[package]\nversion = \"0.1.0\" # bumped on release\n";
+/// assert_eq!(
+///     with_version(manifest, "0.2.0").unwrap(),
+///     "[package]\nversion = \"0.2.0\" # bumped on release\n"
+/// );
+/// ```
+pub fn with_version(
+    contents: &str,
+    version: &str,
+) -> AnyResult<String> {
+    with_package_value(contents, "version", version).ok_or_else(|| {
+        AnyError::msg(
+            "The manifest has no version = \"...\" line in [package] or [workspace.package]",
+        )
+    })
+}
+
+/// Returns the manifest `contents` with the `rust-version` of its `[package]`
+/// and `[workspace.package]` sections set to `version`, adding it after the
+/// package version if the manifest declares none.
+///
+/// # Errors
+///
+/// Returns an error if the manifest declares neither a `rust-version` nor a
+/// version to add it after.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::manifest::with_rust_version;
+///
+/// let manifest = "[package]\nversion = \"0.1.0\"\n";
+/// assert_eq!(
+///     with_rust_version(manifest, "1.70").unwrap(),
+///     "[package]\nversion = \"0.1.0\"\nrust-version = \"1.70\"\n"
+/// );
+/// ```
+pub fn with_rust_version(
+    contents: &str,
+    version: &str,
+) -> AnyResult<String> {
+    if let Some(edited) =
+        with_package_value(contents, "rust-version", version)
+    {
+        return Ok(edited);
+    }
+    let mut section = String::new();
+    let mut added = false;
+    let mut lines = Vec::new();
+    for line in contents.split_inclusive('\n') {
+        if let Some(header) = section_header(line) {
+            section = header;
+        }
+        lines.push(line.to_string());
+        if !added
+            && VERSION_SECTIONS.contains(&section.as_str())
+            && replace_value(line, "version", version).is_some()
+        {
+            if !line.ends_with('\n') {
+                lines.push("\n".to_string());
+            }
+            lines.push(format!("rust-version = \"{version}\"\n"));
+            added = true;
+        }
+    }
+    if !added {
+        return Err(AnyError::msg(
+            "The manifest has no version = \"...\" line in [package] or [workspace.package] to add rust-version after",
+        ));
+    }
+    Ok(lines.concat())
+}
+
+/// Returns the name of the section opened by `line`, if it is a header.
+fn section_header(line: &str) -> Option<String> {
+    line.trim()
+        .strip_prefix('[')
+        .and_then(|header| header.split(']').next())
+        .map(|header| header.trim().to_string())
+}
+
+/// Returns the manifest `contents` with the `key = "..."` lines of its
+/// `[package]` and `[workspace.package]` sections set to `value`, or `None`
+/// if there is no such line.
+fn with_package_value(
+    contents: &str,
+    key: &str,
+    value: &str,
+) -> Option<String> {
+    let mut section = String::new();
+    let mut replaced = false;
+    let mut lines = Vec::new();
+    for line in contents.split_inclusive('\n') {
+        if let Some(header) = section_header(line) {
+            section = header;
+        }
+        let edited = VERSION_SECTIONS
+            .contains(&section.as_str())
+            .then(|| replace_value(line, key, value))
+            .flatten();
+        replaced |= edited.is_some();
+        lines.push(edited.unwrap_or_else(|| line.to_string()));
+    }
+    replaced.then(|| lines.concat())
+}
+
+/// Returns `line` with its quoted value replaced by `value` if it is a
+/// `key = "..."` line.
+fn replace_value(line: &str, key: &str, value: &str) -> Option<String> {
+    let (name, quoted) = line.split_once('=')?;
+    if name.trim() != key {
+        return None;
+    }
+    let start = quoted.find('"')?;
+    let end = start + 1 + quoted[start + 1..].find('"')?;
+    Some(format!(
+        "{name}={}\"{value}\"{}",
+        &quoted[..start],
+        &quoted[end + 1..]
+    ))
+}
+
+/// Sets the package version of the manifest at `path` to `version`.
+///
+/// # Errors
+///
+/// Returns an error if the manifest cannot be read or written, or declares no
+/// version that can be edited.
+pub fn set_package_version(
+    path: &Path,
+    version: &str,
+) -> AnyResult<()> {
+    let contents = fs::read_to_string(path).with_context(|| {
+        format!("Failed to read {}", path.display())
+    })?;
+    let edited =
+        with_version(&contents, version).with_context(|| {
+            format!("Failed to edit {}", path.display())
+        })?;
+    ops::write_atomic(path, edited)
+}
+
+/// Sets the `rust-version` of the manifest at `path` to `version`, see
+/// [`with_rust_version`].
+///
+/// # Errors
+///
+/// Returns an error if the manifest cannot be read or written, or has no
+/// package to set the `rust-version` of.
+pub fn set_rust_version(path: &Path, version: &str) -> AnyResult<()> {
+    let contents = fs::read_to_string(path).with_context(|| {
+        format!("Failed to read {}", path.display())
+    })?;
+    let edited =
+        with_rust_version(&contents, version).with_context(|| {
+            format!("Failed to edit {}", path.display())
+        })?;
+    ops::write_atomic(path, edited)
+}
@@ -0,0 +1,564 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Helpers for reading the workspace layout from `cargo metadata`.
+//!
+//! `cargo metadata` is executed through the current [`runner`], so tests can feed
+//! canned output to the tasks that depend on it.
+
+use crate::ops::runner::{self, CommandSpec};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env,
+    path::PathBuf,
+};
+
+/// The parts of the `cargo metadata` output used by the helpers.
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    #[serde(default)]
+    workspace_root: PathBuf,
+    #[serde(default)]
+    target_directory: PathBuf,
+}
+
+/// A package listed by `cargo metadata`.
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    #[serde(default)]
+    version: String,
+    id: String,
+    #[serde(default)]
+    manifest_path: PathBuf,
+    #[serde(default)]
+    metadata: serde_json::Value,
+    #[serde(default)]
+    targets: Vec<Target>,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    license: Option<String>,
+    #[serde(default)]
+    license_file: Option<PathBuf>,
+    #[serde(default)]
+    dependencies: Vec<Dependency>,
+    #[serde(default)]
+    publish: Option<Vec<String>>,
+}
+
+/// A dependency of a package listed by `cargo metadata`.
+#[derive(Deserialize)]
+struct Dependency {
+    name: String,
+    #[serde(default)]
+    kind: Option<String>,
+}
+
+/// A target of a package listed by `cargo metadata`.
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+}
+
+/// A binary target of a workspace member.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BinaryTarget {
+    /// The package the binary belongs to.
+    pub package: String,
+    /// The name of the binary, as passed to `--bin`.
+    pub name: String,
+}
+
+/// The license metadata of a dependency.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PackageLicense {
+    /// The name of the package.
+    pub name: String,
+    /// The version of the package.
+    pub version: String,
+    /// The SPDX expression of the `license` field, if any.
+    pub license: Option<String>,
+    /// The `license-file` field, if any.
+    pub license_file: Option<PathBuf>,
+}
+
+/// A workspace member and the members it depends on.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WorkspaceMember {
+    /// The name of the package.
+    pub name: String,
+    /// The version of the package.
+    pub version: String,
+    /// The workspace members it depends on, for building or at runtime,
+    /// sorted and without duplicates. Development dependencies are left
+    /// out, since they are not needed to publish the package.
+    pub dependencies: Vec<String>,
+    /// Whether the package may be published, i.e. its `publish` field is
+    /// not `false`.
+    pub publishable: bool,
+}
+
+/// Parses the output of `cargo metadata`.
+fn parse(json: &str) -> AnyResult<Metadata> {
+    serde_json::from_str(json)
+        .context("Failed to parse the output of cargo metadata")
+}
+
+/// Returns the `cargo metadata` invocation used by the helpers.
+pub fn command() -> CommandSpec {
+    CommandSpec::new("cargo").args([
+        "metadata",
+        "--format-version",
+        "1",
+        "--no-deps",
+    ])
+}
+
+/// Returns the `cargo metadata` invocation listing the dependencies too.
+pub fn dependencies_command() -> CommandSpec {
+    CommandSpec::new("cargo").args([
+        "metadata",
+        "--format-version",
+        "1",
+    ])
+}
+
+/// Extracts the license metadata of the packages that are not workspace
+/// members from `cargo metadata` output, sorted by name and version.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_package_licenses(
+    json: &str,
+) -> AnyResult<Vec<PackageLicense>> {
+    let metadata = parse(json)?;
+    let mut licenses: Vec<PackageLicense> = metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            !metadata.workspace_members.contains(&package.id)
+        })
+        .map(|package| PackageLicense {
+            name: package.name,
+            version: package.version,
+            license: package
+                .license
+                .filter(|license| !license.trim().is_empty()),
+            license_file: package.license_file,
+        })
+        .collect();
+    licenses.sort_by(|a, b| {
+        (&a.name, &a.version).cmp(&(&b.name, &b.version))
+    });
+    Ok(licenses)
+}
+
+/// Returns the license metadata of the dependencies of the current
+/// workspace.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn package_licenses() -> AnyResult<Vec<PackageLicense>> {
+    parse_package_licenses(&runner::query(&dependencies_command())?)
+}
+
+/// Extracts the names of the workspace members from `cargo metadata` output.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_workspace_members(json: &str) -> AnyResult<Vec<String>> {
+    let metadata = parse(json)?;
+    Ok(metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            metadata.workspace_members.contains(&package.id)
+        })
+        .map(|package| package.name)
+        .collect())
+}
+
+/// Extracts the manifests of the workspace from `cargo metadata` output: the
+/// root manifest, then those of the members, sorted and without duplicates.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_workspace_manifests(
+    json: &str,
+) -> AnyResult<Vec<PathBuf>> {
+    let metadata = parse(json)?;
+    let root = metadata.workspace_root.join("Cargo.toml");
+    let members: BTreeSet<PathBuf> = metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            metadata.workspace_members.contains(&package.id)
+        })
+        .map(|package| package.manifest_path)
+        .filter(|manifest| *manifest != root)
+        .collect();
+    Ok(std::iter::once(root).chain(members).collect())
+}
+
+/// Returns the manifests of the current workspace, see
+/// [`parse_workspace_manifests`].
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn workspace_manifests() -> AnyResult<Vec<PathBuf>> {
+    parse_workspace_manifests(&runner::query(&command())?)
+}
+
+/// Extracts the workspace members and their dependencies on one another
+/// from `cargo metadata` output, in package order.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_member_dependencies(
+    json: &str,
+) -> AnyResult<Vec<WorkspaceMember>> {
+    let metadata = parse(json)?;
+    let members: Vec<Package> = metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            metadata.workspace_members.contains(&package.id)
+        })
+        .collect();
+    let names: BTreeSet<String> =
+        members.iter().map(|package| package.name.clone()).collect();
+    Ok(members
+        .into_iter()
+        .map(|package| WorkspaceMember {
+            dependencies: package
+                .dependencies
+                .into_iter()
+                .filter(|dependency| {
+                    dependency.kind.as_deref() != Some("dev")
+                        && dependency.name != package.name
+                        && names.contains(&dependency.name)
+                })
+                .map(|dependency| dependency.name)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+            publishable: package
+                .publish
+                .map_or(true, |registries| !registries.is_empty()),
+            name: package.name,
+            version: package.version,
+        })
+        .collect())
+}
+
+/// Returns the members of the current workspace and their dependencies on
+/// one another.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn member_dependencies() -> AnyResult<Vec<WorkspaceMember>> {
+    parse_member_dependencies(&runner::query(&command())?)
+}
+
+/// Returns the names of the packages in the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn workspace_members() -> AnyResult<Vec<String>> {
+    parse_workspace_members(&runner::query(&command())?)
+}
+
+/// Extracts the names and versions of the workspace members, and the root of
+/// the workspace, from `cargo metadata` output.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_workspace_packages(
+    json: &str,
+) -> AnyResult<(PathBuf, Vec<(String, String)>)> {
+    let metadata = parse(json)?;
+    let packages = metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            metadata.workspace_members.contains(&package.id)
+        })
+        .map(|package| (package.name, package.version))
+        .collect();
+    Ok((metadata.workspace_root, packages))
+}
+
+/// Returns the root of the current workspace, and the names and versions of
+/// its packages.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn workspace_packages(
+) -> AnyResult<(PathBuf, Vec<(String, String)>)> {
+    parse_workspace_packages(&runner::query(&command())?)
+}
+
+/// Extracts the binary targets of the workspace members from `cargo metadata`
+/// output, in package order.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_binary_targets(
+    json: &str,
+) -> AnyResult<Vec<BinaryTarget>> {
+    let metadata = parse(json)?;
+    Ok(metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            metadata.workspace_members.contains(&package.id)
+        })
+        .flat_map(|package| {
+            let name = package.name;
+            package
+                .targets
+                .into_iter()
+                .filter(|target| {
+                    target.kind.iter().any(|kind| kind == "bin")
+                })
+                .map(move |target| BinaryTarget {
+                    package: name.clone(),
+                    name: target.name,
+                })
+        })
+        .collect())
+}
+
+/// Returns the binary targets of the packages in the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn binary_targets() -> AnyResult<Vec<BinaryTarget>> {
+    parse_binary_targets(&runner::query(&command())?)
+}
+
+/// Checks that every name in `packages` is a member of the current workspace.
+///
+/// # Errors
+///
+/// Returns an error naming the first unknown package and listing the members, or
+/// an error if the members cannot be read.
+pub fn ensure_workspace_members(packages: &[String]) -> AnyResult<()> {
+    if packages.is_empty() {
+        return Ok(());
+    }
+    let members = workspace_members()?;
+    match packages.iter().find(|package| !members.contains(package)) {
+        Some(unknown) => Err(AnyError::msg(format!(
+            "Unknown package '{unknown}', expected one of the workspace members: {}",
+            members.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Extracts the features declared by the workspace members from `cargo
+/// metadata` output, sorted and without duplicates.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_workspace_features(
+    json: &str,
+) -> AnyResult<BTreeSet<String>> {
+    Ok(parse_package_features(json)?
+        .into_values()
+        .flatten()
+        .collect())
+}
+
+/// Extracts the features declared by each workspace member from `cargo
+/// metadata` output, by package name.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo metadata` JSON.
+pub fn parse_package_features(
+    json: &str,
+) -> AnyResult<BTreeMap<String, BTreeSet<String>>> {
+    let metadata = parse(json)?;
+    Ok(metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            metadata.workspace_members.contains(&package.id)
+        })
+        .map(|package| {
+            (package.name, package.features.into_keys().collect())
+        })
+        .collect())
+}
+
+/// Returns the features declared by each package in the current workspace, by
+/// package name.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn package_features(
+) -> AnyResult<BTreeMap<String, BTreeSet<String>>> {
+    parse_package_features(&runner::query(&command())?)
+}
+
+/// Returns the features declared by the packages in the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn workspace_features() -> AnyResult<BTreeSet<String>> {
+    parse_workspace_features(&runner::query(&command())?)
+}
+
+/// Checks that every name in `features` is a feature of a package in the current
+/// workspace.
+///
+/// # Errors
+///
+/// Returns an error naming the first unknown feature and listing the features of
+/// the workspace, or an error if the features cannot be read.
+pub fn ensure_workspace_features(features: &[String]) -> AnyResult<()> {
+    if features.is_empty() {
+        return Ok(());
+    }
+    let known = workspace_features()?;
+    match features.iter().find(|feature| !known.contains(*feature)) {
+        Some(unknown) => Err(AnyError::msg(format!(
+            "Unknown feature '{unknown}', expected one of the workspace features: {}",
+            known.into_iter().collect::<Vec<_>>().join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Extracts the name of the package at the root of the workspace from `cargo
+/// metadata` output.
+///
+/// This is the package whose manifest is the workspace manifest, or the only
+/// member of the workspace.
+///
+/// # Errors
+///
+/// Returns an error if the output cannot be parsed, or if the workspace has
+/// several members and a virtual manifest.
+pub fn parse_root_package(json: &str) -> AnyResult<String> {
+    Ok(root_of(parse(json)?)?.name)
+}
+
+/// Returns the package at the root of the workspace described by `metadata`.
+fn root_of(metadata: Metadata) -> AnyResult<Package> {
+    let manifest = metadata.workspace_root.join("Cargo.toml");
+    let mut members: Vec<Package> = metadata
+        .packages
+        .into_iter()
+        .filter(|package| {
+            metadata.workspace_members.contains(&package.id)
+        })
+        .collect();
+    let root = if members.len() == 1 {
+        Some(0)
+    } else {
+        members
+            .iter()
+            .position(|package| package.manifest_path == manifest)
+    };
+    match root {
+        Some(index) => Ok(members.swap_remove(index)),
+        None => Err(AnyError::msg(format!(
+            "Failed to find the root package of the workspace {}",
+            metadata.workspace_root.display()
+        ))),
+    }
+}
+
+/// Returns the name of the package at the root of the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or names no root package.
+pub fn root_package() -> AnyResult<String> {
+    parse_root_package(&runner::query(&command())?)
+}
+
+/// Extracts the version of the package at the root of the workspace from
+/// `cargo metadata` output, see [`parse_root_package`].
+///
+/// # Errors
+///
+/// Returns an error if the output cannot be parsed or names no root package.
+pub fn parse_root_version(json: &str) -> AnyResult<String> {
+    Ok(root_of(parse(json)?)?.version)
+}
+
+/// Returns the version of the package at the root of the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or names no root package.
+pub fn root_version() -> AnyResult<String> {
+    parse_root_version(&runner::query(&command())?)
+}
+
+/// Extracts the `[package.metadata.xtask]` table of the root package from
+/// `cargo metadata` output, or `null` if the package has none.
+///
+/// # Errors
+///
+/// Returns an error if the output cannot be parsed or names no root package.
+pub fn parse_xtask_metadata(
+    json: &str,
+) -> AnyResult<serde_json::Value> {
+    let mut package = root_of(parse(json)?)?;
+    Ok(package
+        .metadata
+        .get_mut("xtask")
+        .map(serde_json::Value::take)
+        .unwrap_or_default())
+}
+
+/// Returns the `[package.metadata.xtask]` table of the root package of the
+/// current workspace, or `null` if it has none.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or names no root package.
+pub fn xtask_metadata() -> AnyResult<serde_json::Value> {
+    parse_xtask_metadata(&runner::query(&command())?)
+}
+
+/// Returns the target directory of the current workspace: `CARGO_TARGET_DIR`
+/// when it is set, or the directory reported by `cargo metadata` otherwise.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be parsed.
+pub fn target_directory() -> AnyResult<PathBuf> {
+    if let Some(dir) =
+        env::var_os("CARGO_TARGET_DIR").filter(|dir| !dir.is_empty())
+    {
+        return Ok(PathBuf::from(dir));
+    }
+    Ok(parse(&runner::query(&command())?)?.target_directory)
+}
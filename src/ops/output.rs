@@ -0,0 +1,32 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Helpers for presenting the output of child processes.
+
+use std::process::Output;
+
+/// Prefixes every line of `text` with `[prefix] `, so that output from several
+/// sources stays attributable once interleaved or dumped after the fact.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::output::prefix_lines;
+///
+/// assert_eq!(prefix_lines("test", "a\nb\n"), "[test] a\n[test] b\n");
+/// ```
+pub fn prefix_lines(prefix: &str, text: &str) -> String {
+    text.lines()
+        .map(|line| format!("[{prefix}] {line}\n"))
+        .collect()
+}
+
+/// Returns the captured stdout followed by the captured stderr of a process.
+pub fn combined(output: &Output) -> String {
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    text
+}
@@ -0,0 +1,655 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Command execution layer used by the tasks.
+//!
+//! Tasks describe the processes they want to spawn as [`CommandSpec`] values and
+//! hand them to the [`CommandRunner`] that is current for the calling thread. By
+//! default this is the [`DuctRunner`], which really executes the command; tests
+//! (and tooling built on top of `xtasks`) can swap in their own runner for the
+//! duration of a closure with [`with_runner`]. [`RecordingRunner`] is a ready-made
+//! mock that records what would have run instead of spawning anything, and
+//! [`DryRunRunner`] prints it for the `--dry-run` flag.
+
+use anyhow::{Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{ExitStatus, Output},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A description of an external command to execute.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct CommandSpec {
+    /// The program to execute, e.g. `cargo`.
+    pub program: String,
+
+    /// The arguments passed to the program, in order.
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Additional environment variables set on the child process only.
+    #[serde(default)]
+    pub env: Vec<(String, String)>,
+
+    /// The names of the variables of `env` holding secrets, e.g. tokens, whose
+    /// values are left out of the [shell line](Self::shell_line).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_env: Vec<String>,
+
+    /// Whether stdout and stderr are captured instead of inherited.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub capture: bool,
+
+    /// How long the command may run before it is killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+}
+
+impl CommandSpec {
+    /// Creates a new `CommandSpec` for the given program.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::ops::runner::CommandSpec;
+    ///
+    /// let spec = CommandSpec::new("cargo").args(["fmt", "--", "--check"]);
+    /// assert_eq!(spec.to_string(), "cargo fmt -- --check");
+    /// ```
+    pub fn new<S: Into<String>>(program: S) -> Self {
+        Self {
+            program: program.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Appends a single argument.
+    #[must_use]
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Appends several arguments.
+    #[must_use]
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets an environment variable on the child process.
+    #[must_use]
+    pub fn env<K: Into<String>, V: Into<String>>(
+        mut self,
+        key: K,
+        value: V,
+    ) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// Sets an environment variable holding a secret on the child process.
+    ///
+    /// The value is shown as `***` in the [shell line](Self::shell_line), so
+    /// dry runs never print it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::ops::runner::CommandSpec;
+    ///
+    /// let spec = CommandSpec::new("codecov")
+    ///     .arg("upload-process")
+    ///     .secret_env("CODECOV_TOKEN", "s3cr3t");
+    /// assert_eq!(spec.shell_line(), "CODECOV_TOKEN=*** codecov upload-process");
+    /// assert_eq!(spec.env[0].1, "s3cr3t");
+    /// ```
+    #[must_use]
+    pub fn secret_env<K: Into<String>, V: Into<String>>(
+        mut self,
+        key: K,
+        value: V,
+    ) -> Self {
+        let key = key.into();
+        self.secret_env.push(key.clone());
+        self.env.push((key, value.into()));
+        self
+    }
+
+    /// Captures the command's stdout and stderr instead of streaming them.
+    #[must_use]
+    pub const fn captured(mut self) -> Self {
+        self.capture = true;
+        self
+    }
+
+    /// Returns the command line as it would be typed in a POSIX shell, with
+    /// the environment variables first and the words quoted as needed. The
+    /// values of [secret variables](Self::secret_env) are replaced by `***`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::ops::runner::CommandSpec;
+    ///
+    /// let spec = CommandSpec::new("cargo")
+    ///     .args(["test", "--features", "a b"])
+    ///     .env("RUSTFLAGS", "-D warnings");
+    /// assert_eq!(
+    ///     spec.shell_line(),
+    ///     "RUSTFLAGS='-D warnings' cargo test --features 'a b'"
+    /// );
+    /// ```
+    pub fn shell_line(&self) -> String {
+        let mut words: Vec<String> = self
+            .env
+            .iter()
+            .map(|(key, value)| {
+                if self.secret_env.contains(key) {
+                    format!("{key}=***")
+                } else {
+                    format!("{key}={}", shell_quote(value))
+                }
+            })
+            .collect();
+        words.push(shell_quote(&self.program));
+        words.extend(self.args.iter().map(|arg| shell_quote(arg)));
+        words.join(" ")
+    }
+
+    /// Kills the command, and fails with [`std::io::ErrorKind::TimedOut`], when
+    /// it runs longer than `timeout`.
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Quotes `word` for a POSIX shell, leaving it as is when it only holds
+/// characters the shell does not interpret.
+pub fn shell_quote(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word.chars().all(|c| {
+            c.is_ascii_alphanumeric() || "-_./=:,@+%^".contains(c)
+        });
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+impl fmt::Display for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            write!(f, " {arg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Executes [`CommandSpec`]s on behalf of the tasks.
+pub trait CommandRunner {
+    /// Runs the command to completion and returns its output.
+    ///
+    /// Implementations must not treat a non-zero exit status as an error; that
+    /// decision is left to the caller (see [`run`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command could not be spawned at all.
+    fn execute(&self, command: &CommandSpec)
+        -> std::io::Result<Output>;
+
+    /// Returns the path of `program` if it can be found on the `PATH`.
+    ///
+    /// The default implementation searches the real `PATH`; runners that do not
+    /// spawn processes can override it to simulate installed or missing tools.
+    fn which(&self, program: &str) -> Option<PathBuf> {
+        crate::ops::tools::find_in_path(program)
+    }
+
+    /// Runs the command to completion with its stdout piped, calling `on_line`
+    /// with every line it prints, and returns its output.
+    ///
+    /// stderr is printed as usual and left out of the output. The default
+    /// implementation runs the command with [`execute`](Self::execute) and
+    /// hands its lines over once it exits, while [`DuctRunner`] hands over
+    /// each line as soon as it is printed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command could not be spawned at all.
+    fn execute_streaming(
+        &self,
+        command: &CommandSpec,
+        on_line: &mut dyn FnMut(&str),
+    ) -> std::io::Result<Output> {
+        execute_buffered(self, command, on_line)
+    }
+
+    /// Returns `true` if the runner only reports what would run, in which
+    /// case the filesystem helpers of [`ops`](crate::ops) do not act either.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+/// Runs `command` captured with `runner`, then prints its stderr and calls
+/// `on_line` with every line of its stdout, see
+/// [`CommandRunner::execute_streaming`].
+fn execute_buffered<R: CommandRunner + ?Sized>(
+    runner: &R,
+    command: &CommandSpec,
+    on_line: &mut dyn FnMut(&str),
+) -> std::io::Result<Output> {
+    let output = runner.execute(&command.clone().captured())?;
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        on_line(line);
+    }
+    Ok(Output {
+        stderr: Vec::new(),
+        ..output
+    })
+}
+
+/// How often [`DuctRunner`] checks whether a command with a timeout finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The default [`CommandRunner`], backed by `duct`.
+///
+/// Unless the command asks for its output to be captured, the child inherits
+/// stdout and stderr, so output streams to the terminal as it would when running
+/// the command by hand. Commands with a timeout are polled, and killed once it
+/// expires.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DuctRunner;
+
+impl CommandRunner for DuctRunner {
+    fn execute(
+        &self,
+        command: &CommandSpec,
+    ) -> std::io::Result<Output> {
+        let mut expression =
+            duct::cmd(&command.program, &command.args).unchecked();
+        for (key, value) in &command.env {
+            expression = expression.env(key, value);
+        }
+        if command.capture {
+            expression = expression.stdout_capture().stderr_capture();
+        }
+        let Some(timeout) = command.timeout else {
+            return expression.run();
+        };
+        let handle = expression.start()?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(output) = handle.try_wait()? {
+                return Ok(output.clone());
+            }
+            if Instant::now() >= deadline {
+                handle.kill()?;
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!(
+                        "'{command}' timed out after {}s",
+                        timeout.as_secs_f32()
+                    ),
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Reads the stdout of the command line by line while it runs. Commands
+    /// with a timeout are run with [`execute`](Self::execute) instead, and
+    /// their lines handed over once they exit.
+    fn execute_streaming(
+        &self,
+        command: &CommandSpec,
+        on_line: &mut dyn FnMut(&str),
+    ) -> std::io::Result<Output> {
+        if command.timeout.is_some() {
+            return execute_buffered(self, command, on_line);
+        }
+        let mut expression =
+            duct::cmd(&command.program, &command.args).unchecked();
+        for (key, value) in &command.env {
+            expression = expression.env(key, value);
+        }
+        let reader = expression.reader()?;
+        let mut lines = BufReader::new(&reader);
+        let mut stdout = Vec::new();
+        let mut line = Vec::new();
+        while lines.read_until(b'\n', &mut line)? > 0 {
+            on_line(
+                String::from_utf8_lossy(&line)
+                    .trim_end_matches(['\r', '\n']),
+            );
+            stdout.append(&mut line);
+        }
+        // The child has been waited on once its stdout reached EOF.
+        let status = reader
+            .try_wait()?
+            .map(|output| output.status)
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    format!(
+                        "'{command}' did not exit after closing its stdout"
+                    ),
+                )
+            })?;
+        Ok(Output {
+            status,
+            stdout,
+            stderr: Vec::new(),
+        })
+    }
+}
+
+/// The prefix of the lines printed by [`DryRunRunner`] and by the filesystem
+/// helpers of [`ops`](crate::ops) in a dry run.
+pub const DRY_RUN_PREFIX: &str = "DRY-RUN:";
+
+/// Prints `line` as an action skipped by a dry run.
+pub fn print_dry_run(line: &str) {
+    println!("{DRY_RUN_PREFIX} {line}");
+}
+
+/// A [`CommandRunner`] that prints every command instead of executing it, as
+/// the `--dry-run` flag does.
+///
+/// Commands are printed as [shell lines](CommandSpec::shell_line) prefixed with
+/// [`DRY_RUN_PREFIX`], and succeed with empty output, so a task composed of
+/// several steps prints its whole plan. Tools are looked up in the real `PATH`,
+/// so the installation of missing tools is part of the plan, and the read-only
+/// commands run with [`query`] still run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DryRunRunner;
+
+impl CommandRunner for DryRunRunner {
+    fn execute(
+        &self,
+        command: &CommandSpec,
+    ) -> std::io::Result<Output> {
+        print_dry_run(&command.shell_line());
+        Ok(Output {
+            status: exit_status(0),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        })
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+/// Runs a command that only reads state, e.g. `cargo metadata`, and returns its
+/// captured stdout, like [`capture`].
+///
+/// In a dry run (see [`is_dry_run`]), the command still runs, with a
+/// [`DuctRunner`], so the tasks can plan from the real state.
+///
+/// # Errors
+///
+/// See [`capture`].
+pub fn query(command: &CommandSpec) -> AnyResult<String> {
+    if is_dry_run() {
+        with_runner(Arc::new(DuctRunner), || capture(command))
+    } else {
+        capture(command)
+    }
+}
+
+/// Returns `true` if the current runner only reports what would run.
+pub fn is_dry_run() -> bool {
+    current().is_dry_run()
+}
+
+/// A [`CommandRunner`] that records every command instead of executing it.
+///
+/// Commands succeed with empty output unless their command line starts with one
+/// of the prefixes registered with [`RecordingRunner::failing`],
+/// [`RecordingRunner::with_stdout`] or [`RecordingRunner::with_stderr`]. Every program is reported as installed,
+/// unless registered with [`RecordingRunner::missing`], so tasks do not try to
+/// install their tools.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use xtasks::ops::runner::{self, CommandSpec, RecordingRunner};
+///
+/// let recorder = Arc::new(RecordingRunner::default().failing("cargo test"));
+/// runner::with_runner(recorder.clone(), || {
+///     assert!(runner::run(&CommandSpec::new("cargo").arg("fmt")).is_ok());
+///     assert!(runner::run(&CommandSpec::new("cargo").arg("test")).is_err());
+/// });
+/// assert_eq!(recorder.command_lines(), ["cargo fmt", "cargo test"]);
+/// ```
+#[derive(Debug, Default)]
+pub struct RecordingRunner {
+    calls: Mutex<Vec<CommandSpec>>,
+    failing: Vec<String>,
+    stdout: Vec<(String, String)>,
+    stderr: Vec<(String, String)>,
+    missing: Vec<String>,
+}
+
+impl RecordingRunner {
+    /// Makes commands whose command line starts with `prefix` exit with status 1.
+    #[must_use]
+    pub fn failing<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.failing.push(prefix.into());
+        self
+    }
+
+    /// Makes commands whose command line starts with `prefix` print `stdout`.
+    #[must_use]
+    pub fn with_stdout<P, S>(mut self, prefix: P, stdout: S) -> Self
+    where
+        P: Into<String>,
+        S: Into<String>,
+    {
+        self.stdout.push((prefix.into(), stdout.into()));
+        self
+    }
+
+    /// Makes commands whose command line starts with `prefix` print `stderr`.
+    #[must_use]
+    pub fn with_stderr<P, S>(mut self, prefix: P, stderr: S) -> Self
+    where
+        P: Into<String>,
+        S: Into<String>,
+    {
+        self.stderr.push((prefix.into(), stderr.into()));
+        self
+    }
+
+    /// Reports `program` as not installed.
+    #[must_use]
+    pub fn missing<S: Into<String>>(mut self, program: S) -> Self {
+        self.missing.push(program.into());
+        self
+    }
+
+    /// Returns the commands executed so far, in order.
+    pub fn calls(&self) -> Vec<CommandSpec> {
+        self.calls
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns the command lines executed so far, e.g. `cargo fmt -- --check`.
+    pub fn command_lines(&self) -> Vec<String> {
+        self.calls().iter().map(ToString::to_string).collect()
+    }
+}
+
+impl CommandRunner for RecordingRunner {
+    fn execute(
+        &self,
+        command: &CommandSpec,
+    ) -> std::io::Result<Output> {
+        self.calls
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(command.clone());
+        let line = command.to_string();
+        let failed =
+            self.failing.iter().any(|prefix| line.starts_with(prefix));
+        let printed = |outputs: &[(String, String)]| {
+            outputs
+                .iter()
+                .find(|(prefix, _)| line.starts_with(prefix))
+                .map(|(_, output)| output.as_bytes().to_vec())
+                .unwrap_or_default()
+        };
+        Ok(Output {
+            status: exit_status(i32::from(failed)),
+            stdout: printed(&self.stdout),
+            stderr: printed(&self.stderr),
+        })
+    }
+
+    fn which(&self, program: &str) -> Option<PathBuf> {
+        (!self.missing.iter().any(|missing| missing == program))
+            .then(|| PathBuf::from(program))
+    }
+}
+
+/// Returns an `ExitStatus` for the given exit code.
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw(code << 8)
+}
+
+/// Returns an `ExitStatus` for the given exit code.
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code.unsigned_abs())
+}
+
+thread_local! {
+    static CURRENT_RUNNER: RefCell<Option<Arc<dyn CommandRunner>>> =
+        RefCell::new(None);
+}
+
+/// Returns the runner that is current for the calling thread.
+pub fn current() -> Arc<dyn CommandRunner> {
+    CURRENT_RUNNER.with(|current| {
+        current
+            .borrow()
+            .clone()
+            .unwrap_or_else(|| Arc::new(DuctRunner))
+    })
+}
+
+/// Runs `f` with `runner` installed as the current runner for this thread.
+///
+/// The previous runner is restored afterwards, even if `f` panics.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use xtasks::ops::runner::{with_runner, DuctRunner};
+///
+/// let answer = with_runner(Arc::new(DuctRunner), || 42);
+/// assert_eq!(answer, 42);
+/// ```
+pub fn with_runner<R, F>(runner: Arc<dyn CommandRunner>, f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    struct Restore(Option<Arc<dyn CommandRunner>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CURRENT_RUNNER.with(|current| {
+                *current.borrow_mut() = previous;
+            });
+        }
+    }
+
+    let previous = CURRENT_RUNNER
+        .with(|current| current.borrow_mut().replace(runner));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Runs a command with the current runner and checks its exit status.
+///
+/// When the log level is [quiet](crate::loggers::is_quiet), the output of the
+/// command is captured and only printed if it fails.
+///
+/// # Errors
+///
+/// Returns an error if the command cannot be spawned or exits unsuccessfully.
+pub fn run(command: &CommandSpec) -> AnyResult<Output> {
+    let hidden = !command.capture && crate::loggers::is_quiet();
+    let command = if hidden {
+        &command.clone().captured()
+    } else {
+        command
+    };
+    let output = current().execute(command).map_err(|err| {
+        AnyError::new(err)
+            .context(format!("Failed to spawn '{command}'"))
+    })?;
+    if output.status.success() {
+        Ok(output)
+    } else {
+        if hidden {
+            eprint!("{}", crate::ops::output::combined(&output));
+        }
+        Err(AnyError::msg(format!(
+            "'{command}' exited with {}",
+            output.status
+        )))
+    }
+}
+
+/// Runs a command with the current runner and returns its captured stdout.
+///
+/// # Errors
+///
+/// Returns an error if the command cannot be spawned, exits unsuccessfully, or
+/// prints output that is not valid UTF-8. The error includes captured stderr.
+pub fn capture(command: &CommandSpec) -> AnyResult<String> {
+    let command = command.clone().captured();
+    let output = current().execute(&command).map_err(|err| {
+        AnyError::new(err)
+            .context(format!("Failed to spawn '{command}'"))
+    })?;
+    if !output.status.success() {
+        return Err(AnyError::msg(format!(
+            "'{command}' exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    String::from_utf8(output.stdout).map_err(AnyError::new)
+}
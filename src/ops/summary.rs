@@ -0,0 +1,113 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The footer printed after every task, with its status and duration.
+//!
+//! The footer is a single line on stderr, e.g. `✓ ci completed in 3m42s` or
+//! `✗ coverage failed after 12s (step: tarpaulin)`, whose glyph is colored
+//! when stderr is a terminal and `NO_COLOR` is not set. The failing step is
+//! read from the [`XtaskError`] of the error, if it has one.
+
+use super::error::{category, XtaskError};
+use anyhow::Result as AnyResult;
+use std::{
+    env,
+    io::{self, IsTerminal},
+    time::Duration,
+};
+
+/// The ANSI escape code of green text.
+const GREEN: &str = "\x1b[32m";
+/// The ANSI escape code of red text.
+const RED: &str = "\x1b[31m";
+/// The ANSI escape code resetting the text style.
+const RESET: &str = "\x1b[0m";
+
+/// Returns `elapsed` in a compact human form: tenths of a second under ten
+/// seconds, then seconds, minutes and hours.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use xtasks::ops::summary::format_elapsed;
+///
+/// assert_eq!(format_elapsed(Duration::from_millis(1250)), "1.2s");
+/// assert_eq!(format_elapsed(Duration::from_secs(12)), "12s");
+/// assert_eq!(format_elapsed(Duration::from_secs(222)), "3m42s");
+/// assert_eq!(format_elapsed(Duration::from_secs(3723)), "1h02m03s");
+/// ```
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match secs {
+        0..=9 => format!("{:.1}s", elapsed.as_secs_f64()),
+        10..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m{:02}s", secs / 60, secs % 60),
+        _ => format!(
+            "{}h{:02}m{:02}s",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        ),
+    }
+}
+
+/// Returns the footer of the task `task`, which ran for `elapsed` and ended
+/// with `result`, its glyph colored if `color` is `true`.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use xtasks::ops::summary::footer;
+///
+/// assert_eq!(
+///     footer("ci", &Ok(()), Duration::from_secs(222), false),
+///     "✓ ci completed in 3m42s"
+/// );
+/// ```
+pub fn footer(
+    task: &str,
+    result: &AnyResult<()>,
+    elapsed: Duration,
+    color: bool,
+) -> String {
+    let elapsed = format_elapsed(elapsed);
+    let (glyph, code) = match result {
+        Ok(()) => ("✓", GREEN),
+        Err(_) => ("✗", RED),
+    };
+    let glyph = if color {
+        format!("{code}{glyph}{RESET}")
+    } else {
+        glyph.to_string()
+    };
+    match result {
+        Ok(()) => format!("{glyph} {task} completed in {elapsed}"),
+        Err(error) => {
+            let mut line =
+                format!("{glyph} {task} failed after {elapsed}");
+            if let Some(XtaskError::StepFailed { step }) =
+                category(error)
+            {
+                line.push_str(&format!(" (step: {step})"));
+            }
+            line
+        }
+    }
+}
+
+/// Returns `true` if the footer printed on stderr should be colored: stderr
+/// is a terminal and `NO_COLOR` is not set.
+pub fn use_color() -> bool {
+    io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+/// Prints the [`footer`] of the task `task` on stderr.
+pub fn print_footer(
+    task: &str,
+    result: &AnyResult<()>,
+    elapsed: Duration,
+) {
+    eprintln!("{}", footer(task, result, elapsed, use_color()));
+}
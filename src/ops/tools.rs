@@ -0,0 +1,346 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Detection and installation of the external tools used by the tasks.
+//!
+//! Detection goes through [`CommandRunner::which`], so runners used in tests can
+//! pretend a tool is installed or missing.
+//!
+//! [`CommandRunner::which`]: crate::ops::runner::CommandRunner::which
+
+use crate::ops::{
+    error::XtaskError,
+    runner::{self, CommandSpec},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The environment variable that turns off installing missing tools; see
+/// [`ensure_tool`].
+pub const NO_AUTO_INSTALL_ENV: &str = "XTASKS_NO_AUTO_INSTALL";
+
+/// The environment variable that makes [`ensure_tool`] reinstall tools that are
+/// already installed, e.g. to pick up a new release.
+pub const FORCE_INSTALL_ENV: &str = "XTASKS_FORCE_INSTALL";
+
+/// How many times `cargo install` is attempted before giving up.
+pub const INSTALL_ATTEMPTS: u32 = 3;
+
+/// How long to wait before attempting `cargo install` again.
+pub const INSTALL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Searches the directories of the `PATH` environment variable for `program`.
+///
+/// On Windows the `.exe` extension is tried as well.
+pub fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path).find_map(|dir| {
+        let candidate = dir.join(program);
+        if is_executable(&candidate) {
+            return Some(candidate);
+        }
+        let candidate =
+            candidate.with_extension(env::consts::EXE_EXTENSION);
+        is_executable(&candidate).then_some(candidate)
+    })
+}
+
+/// Returns `true` if `path` is a file that can be executed.
+fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        path.metadata().is_ok_and(|metadata| {
+            metadata.is_file()
+                && metadata.permissions().mode() & 0o111 != 0
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Returns the path of `binary` as seen by the current runner.
+pub fn which(binary: &str) -> Option<PathBuf> {
+    runner::current().which(binary)
+}
+
+/// Returns `true` if `binary` is installed.
+pub fn is_installed(binary: &str) -> bool {
+    which(binary).is_some()
+}
+
+/// Returns the `cargo install` invocation for `install_args`.
+pub fn install_command(install_args: &[&str]) -> CommandSpec {
+    CommandSpec::new("cargo")
+        .arg("install")
+        .args(install_args.iter().copied())
+}
+
+/// Installs a tool with `cargo install <install_args>` unless `binary` is already
+/// available.
+///
+/// This is [`ensure_tool`] with automatic installation enabled.
+///
+/// # Parameters
+///
+/// - `binary`: The executable to look for, e.g. `cargo-machete`.
+/// - `install_args`: The arguments passed to `cargo install`, e.g. `["cargo-machete"]`.
+///
+/// # Returns
+///
+/// `true` if the tool was installed by this call, `false` if it was already present.
+///
+/// # Errors
+///
+/// Returns an error if the installation fails.
+pub fn ensure_tool_installed(
+    binary: &str,
+    install_args: &[&str],
+) -> AnyResult<bool> {
+    ensure_tool(binary, install_args, true)
+}
+
+/// Makes sure `binary` is available, installing it with `cargo install
+/// <install_args>` if `auto_install` is set.
+///
+/// Installation is attempted [`INSTALL_ATTEMPTS`] times, as `cargo install` is
+/// prone to network failures on CI. Setting the `XTASKS_NO_AUTO_INSTALL`
+/// environment variable turns off installation even if `auto_install` is set,
+/// and setting `XTASKS_FORCE_INSTALL` reinstalls the tool even if it is present.
+///
+/// # Returns
+///
+/// `true` if the tool was installed by this call, `false` if it was already present.
+///
+/// # Errors
+///
+/// Returns an error with the command to install the tool if it is missing and
+/// may not be installed, or an error if the installation fails.
+pub fn ensure_tool(
+    binary: &str,
+    install_args: &[&str],
+    auto_install: bool,
+) -> AnyResult<bool> {
+    let allowed =
+        auto_install && !crate::ops::env_flag(NO_AUTO_INSTALL_ENV);
+    let forced = allowed && crate::ops::env_flag(FORCE_INSTALL_ENV);
+    if let Some(path) = which(binary).filter(|_| !forced) {
+        println!("Using {binary} from {}", path.display());
+        return Ok(false);
+    }
+    let mut install = install_command(install_args);
+    if !allowed {
+        return Err(XtaskError::ToolMissing {
+            tool: binary.to_string(),
+            install: install.to_string(),
+        }
+        .into());
+    }
+    if forced {
+        install = install.arg("--force");
+        println!("Reinstalling {binary} with '{install}' ({FORCE_INSTALL_ENV} is set)");
+    } else {
+        println!("Installing {binary} with '{install}'");
+    }
+    crate::ops::retry(INSTALL_ATTEMPTS, INSTALL_RETRY_DELAY, || {
+        runner::run(&install)
+    })
+    .with_context(|| format!("Failed to install '{binary}'"))?;
+    Ok(true)
+}
+
+/// Returns a `cargo <subcommand>` invocation, run through `rustup run` when a
+/// toolchain is given.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::tools::cargo_with;
+///
+/// assert_eq!(cargo_with(None, "fmt").to_string(), "cargo fmt");
+/// assert_eq!(
+///     cargo_with(Some("nightly"), "fmt").to_string(),
+///     "rustup run nightly cargo fmt"
+/// );
+/// ```
+pub fn cargo_with(
+    toolchain: Option<&str>,
+    subcommand: &str,
+) -> CommandSpec {
+    match toolchain {
+        Some(toolchain) => CommandSpec::new("rustup")
+            .args(["run", toolchain, "cargo", subcommand]),
+        None => CommandSpec::new("cargo").arg(subcommand),
+    }
+}
+
+/// Returns `true` if rustup can run `rustc` from `toolchain`, e.g. `nightly`.
+pub fn is_toolchain_installed(toolchain: &str) -> bool {
+    runner::query(&CommandSpec::new("rustup").args([
+        "run",
+        toolchain,
+        "rustc",
+        "--version",
+    ]))
+    .is_ok()
+}
+
+/// Makes sure `toolchain` is available, installing it with rustup's minimal
+/// profile and the given `components`, e.g. `rustfmt`, if `auto_install` is
+/// set.
+///
+/// Like [`ensure_tool`], this honors `XTASKS_NO_AUTO_INSTALL` and retries the
+/// installation.
+///
+/// # Returns
+///
+/// `true` if the toolchain was installed by this call, `false` if it was already
+/// present.
+///
+/// # Errors
+///
+/// Returns an error with the command to install the toolchain if it is missing
+/// and may not be installed, or an error if the installation fails.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use xtasks::ops::{
+///     runner::{with_runner, RecordingRunner},
+///     tools::ensure_toolchain,
+/// };
+///
+/// let runner = Arc::new(
+///     RecordingRunner::default().failing("rustup run nightly rustc"),
+/// );
+/// let installed = with_runner(runner.clone(), || {
+///     ensure_toolchain("nightly", &["rustfmt"], true)
+/// });
+/// assert!(installed.unwrap());
+/// assert_eq!(
+///     runner.command_lines()[1],
+///     "rustup toolchain install nightly --profile minimal --component rustfmt"
+/// );
+/// ```
+pub fn ensure_toolchain(
+    toolchain: &str,
+    components: &[&str],
+    auto_install: bool,
+) -> AnyResult<bool> {
+    if is_toolchain_installed(toolchain) {
+        return Ok(false);
+    }
+    let mut install = CommandSpec::new("rustup").args([
+        "toolchain",
+        "install",
+        toolchain,
+        "--profile",
+        "minimal",
+    ]);
+    if !components.is_empty() {
+        install = install.args(["--component", &components.join(",")]);
+    }
+    if !auto_install || crate::ops::env_flag(NO_AUTO_INSTALL_ENV) {
+        return Err(XtaskError::ToolMissing {
+            tool: toolchain.to_string(),
+            install: install.to_string(),
+        }
+        .into());
+    }
+    println!("Installing toolchain {toolchain} with '{install}'");
+    crate::ops::retry(INSTALL_ATTEMPTS, INSTALL_RETRY_DELAY, || {
+        runner::run(&install)
+    })
+    .with_context(|| {
+        format!("Failed to install toolchain '{toolchain}'")
+    })?;
+    Ok(true)
+}
+
+/// Extracts the host target triple from the output of `rustc -vV`.
+///
+/// # Errors
+///
+/// Returns an error if the output has no `host:` line.
+pub fn parse_host_triple(output: &str) -> AnyResult<String> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix("host:"))
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .ok_or_else(|| {
+            AnyError::msg(
+                "Failed to find the host triple in 'rustc -vV'",
+            )
+        })
+}
+
+/// Returns the target triple of the host, e.g. `x86_64-unknown-linux-gnu`.
+///
+/// # Errors
+///
+/// Returns an error if `rustc -vV` fails or reports no host.
+pub fn host_triple() -> AnyResult<String> {
+    parse_host_triple(&runner::query(
+        &CommandSpec::new("rustc").arg("-vV"),
+    )?)
+}
+
+/// Returns `true` if the standard library of `target`, e.g.
+/// `wasm32-unknown-unknown`, is installed for the active toolchain.
+pub fn is_target_installed(target: &str) -> bool {
+    runner::query(&CommandSpec::new("rustup").args([
+        "target",
+        "list",
+        "--installed",
+    ]))
+    .is_ok_and(|installed| {
+        installed.lines().any(|line| line.trim() == target)
+    })
+}
+
+/// Makes sure the standard library of `target` is installed, adding it with
+/// rustup if `auto_install` is set.
+///
+/// Like [`ensure_toolchain`], this honors `XTASKS_NO_AUTO_INSTALL` and retries
+/// the installation.
+///
+/// # Returns
+///
+/// `true` if the target was added by this call, `false` if it was already
+/// installed.
+///
+/// # Errors
+///
+/// Returns an error with the command adding the target if it is missing and may
+/// not be installed, or an error if the installation fails.
+pub fn ensure_target(
+    target: &str,
+    auto_install: bool,
+) -> AnyResult<bool> {
+    if is_target_installed(target) {
+        return Ok(false);
+    }
+    let install =
+        CommandSpec::new("rustup").args(["target", "add", target]);
+    if !auto_install || crate::ops::env_flag(NO_AUTO_INSTALL_ENV) {
+        return Err(XtaskError::ToolMissing {
+            tool: target.to_string(),
+            install: install.to_string(),
+        }
+        .into());
+    }
+    println!("Adding target {target} with '{install}'");
+    crate::ops::retry(INSTALL_ATTEMPTS, INSTALL_RETRY_DELAY, || {
+        runner::run(&install)
+    })
+    .with_context(|| format!("Failed to add target '{target}'"))?;
+    Ok(true)
+}
@@ -56,17 +56,53 @@
 //! This collection of cargo xtasks is distributed under the terms of both the MIT license and
 //! the Apache License (Version 2.0). See LICENSE-APACHE and LICENSE-MIT for details.
 
+use crate::loggers::{set_log_level, LogLevel};
+use crate::ops::{
+    runner::{self, CommandSpec},
+    summary, tools, write_file_if_changed,
+};
 use crate::tasks::{
-    bloat::{deps, time},
-    ci::ci,
-    coverage::coverage,
-    docs::docs,
-    powerset::powerset,
+    audit::{audit, AuditOptionsBuilder, Severity},
+    bench::BenchBuilder,
+    bloat::{
+        size_track, BloatBuilder, BloatMode, DiffThreshold, SizeHistory,
+    },
+    changelog::ChangelogBuilder,
+    ci::{ci, hooks::install_git_hook_in, HookKind},
+    clean::CleanBuilder,
+    clippy::ClippyOptionsBuilder,
+    config::XtaskConfig,
+    coverage::{
+        coverage_badge, coverage_upload, CoverageEngine,
+        CoverageFormat, CoverageProvider,
+    },
+    cross::{CrossBackend, CrossOptionsBuilder},
+    dist::{dist, DistConfigBuilder},
+    docker::DockerOptionsBuilder,
+    docs::{
+        book, doc_coverage, sync_readme, BookAction, DeployBuilder,
+        DocsBuilder,
+    },
+    fmt::FmtOptionsBuilder,
+    init::{scaffold, InitOptionsBuilder},
+    licenses::LicenseFormat,
+    powerset::PowersetStep,
+    profile::{FlamegraphOptionsBuilder, ProfileTarget},
+    publish::PublishOptionsBuilder,
+    release::{ReleaseBuilder, ReleaseVersion},
+    typos::ensure_no_typos,
+    update::UpdateOptionsBuilder,
+    wasm::{BindgenTarget, WasmOptionsBuilder},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command};
+use clap_complete::Shell;
+use std::{
+    env,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use anyhow::{Context, Result as AnyResult};
-use clap::{Arg, Command};
-use duct::cmd;
-use std::env;
 
 /// Analyses the dependencies of the current project to find which ones contribute most to the build size.
 pub mod bloat;
@@ -74,15 +110,104 @@ pub mod bloat;
 /// Implements a variety of CI tasks to validate code quality, run tests, and ensure the stability of the codebase.
 pub mod ci;
 
+/// Removes the artifacts generated by the tasks, and optionally the whole target directory.
+pub mod clean;
+
+/// Reads the default settings of the tasks from `xtask.toml` or the workspace `Cargo.toml`.
+pub mod config;
+
 /// Automate the creation of project documentation, ensuring consistency and completeness across all codebase components.
 pub mod coverage;
 
 /// Streamline the development workflow with tasks designed to automate repetitive tasks and improve efficiency.
 pub mod docs;
 
+/// Scaffolds the xtask crate and the `cargo xtask` alias in a workspace.
+pub mod init;
+
+/// Lists the available tasks with the external tools they need.
+pub mod list;
+
 /// Easily extend and customize tasks to suit the unique requirements of your project.
 pub mod powerset;
 
+/// Registers custom tasks to run alongside the built-in ones.
+pub mod registry;
+
+/// Audits the dependencies for security advisories with cargo-audit.
+pub mod audit;
+
+/// Runs the criterion benchmarks and compares them with a baseline.
+pub mod bench;
+
+/// Packages the release binaries into archives with their checksums.
+pub mod dist;
+
+/// Generates changelog sections from the git history.
+pub mod changelog;
+
+/// Releases the workspace package: version bump, changelog, commit, tag and
+/// publication.
+pub mod release;
+
+/// Checks the public API for semver-incompatible changes against the last release.
+pub mod semver;
+
+/// Spell checks source files and documentation with the `typos` CLI.
+pub mod typos;
+
+/// Reports the workspace, toolchain, tools and environment of the tasks.
+pub mod vars;
+
+/// Checks the development environment and offers to install missing tools.
+pub mod doctor;
+
+/// Formats the workspace with rustfmt, or checks that it is formatted.
+pub mod fmt;
+
+/// Lints the workspace with clippy and the lint sets of the CI checks.
+pub mod clippy;
+
+/// Reports the dependencies that have newer versions.
+pub mod outdated;
+
+/// Vendors the dependencies and checks that the vendored sources are up to
+/// date.
+pub mod vendor;
+
+/// Reports the licenses of the dependencies.
+pub mod licenses;
+
+/// Finds the minimum supported Rust version by bisection.
+pub mod msrv;
+
+/// Publishes the workspace crates to crates.io in dependency order.
+pub mod publish;
+
+/// Builds the crate for WebAssembly and post-processes the module.
+pub mod wasm;
+
+/// Builds and pushes the container image of the release binary.
+pub mod docker;
+
+/// Builds the release binaries for other targets with cross or zigbuild.
+pub mod cross;
+
+/// Runs the cargo-fuzz targets and reports the new crashes.
+pub mod fuzz;
+
+/// Records a flamegraph of a binary, benchmark or test.
+pub mod profile;
+
+/// Sorts the dependency tables of the workspace manifests.
+pub mod manifest;
+
+/// Updates the locked dependencies and tests the workspace with them.
+pub mod update;
+
+pub use registry::XTasks;
+pub use typos::typos;
+
 /// Runs a specified command with `watch`, `-x check`, and `-x test` arguments.
 ///
 /// This function is intended to be used for development purposes, enabling live
@@ -102,7 +227,24 @@ pub mod powerset;
 /// This function will return an error if the external command fails to run, or if any other
 /// error occurs during execution.
 pub fn dev_with_command(command: &str) -> AnyResult<()> {
-    cmd!(command, "watch", "-x", "check", "-x", "test").run()?;
+    dev_with_options(command, false)
+}
+
+/// Runs `command` with `watch`, `-x check`, and `-x test` arguments, clearing
+/// the screen between runs if `clear` is set.
+///
+/// # Errors
+///
+/// This function will return an error if the external command fails to run or
+/// exits unsuccessfully.
+pub fn dev_with_options(command: &str, clear: bool) -> AnyResult<()> {
+    let mut watch = CommandSpec::new(command).arg("watch");
+    if clear {
+        watch = watch.arg("--clear");
+    }
+    let watch = watch.args(["-x", "check", "-x", "test"]);
+    let _ = runner::run(&watch)
+        .with_context(|| format!("Failed to execute '{watch}'"))?;
     Ok(())
 }
 
@@ -139,19 +281,1549 @@ pub fn dev() -> AnyResult<()> {
 /// This function will return an error if any of the installation commands fail to run,
 /// or if any other error occurs during execution.
 pub fn install() -> AnyResult<()> {
-    cmd!("cargo", "install", "cargo-watch").run()?;
-    cmd!("cargo", "install", "cargo-hack").run()?;
-    cmd!("cargo", "install", "cargo-bloat").run()?;
-    cmd!("rustup", "component", "add", "llvm-tools-preview").run()?;
-    cmd!("cargo", "install", "grcov").run()?;
+    install_tools(&[], &[])
+}
+
+/// The tools installed by [`install`], in order.
+pub const DEV_TOOLS: [&str; 5] = [
+    "cargo-watch",
+    "cargo-hack",
+    "cargo-bloat",
+    "llvm-tools-preview",
+    "grcov",
+];
+
+/// Returns the command installing `tool`, one of [`DEV_TOOLS`].
+fn dev_tool_install_command(tool: &str) -> CommandSpec {
+    match tool {
+        "llvm-tools-preview" => CommandSpec::new("rustup").args([
+            "component",
+            "add",
+            "llvm-tools-preview",
+        ]),
+        tool => tools::install_command(&[tool]),
+    }
+}
+
+/// Installs the [`DEV_TOOLS`] named in `only`, or all of them if `only` is
+/// empty, except the ones named in `skip`.
+///
+/// # Errors
+///
+/// This function will return an error if a tool is not one of [`DEV_TOOLS`], or
+/// if any of the installation commands fail to run.
+pub fn install_tools(
+    skip: &[String],
+    only: &[String],
+) -> AnyResult<()> {
+    if let Some(unknown) = skip
+        .iter()
+        .chain(only)
+        .find(|tool| !DEV_TOOLS.contains(&tool.as_str()))
+    {
+        return Err(AnyError::msg(format!(
+            "Unknown tool '{unknown}', expected one of: {}",
+            DEV_TOOLS.join(", ")
+        )));
+    }
+    for tool in DEV_TOOLS {
+        let selected =
+            only.is_empty() || only.iter().any(|name| name == tool);
+        if !selected || skip.iter().any(|name| name == tool) {
+            continue;
+        }
+        let install = dev_tool_install_command(tool);
+        println!("Installing {tool} with '{install}'");
+        let _ = runner::run(&install)
+            .with_context(|| format!("Failed to install '{tool}'"))?;
+    }
     Ok(())
 }
 
+/// Returns the names of the tasks, in the order of the help.
+pub fn task_names() -> Vec<String> {
+    build_cli()
+        .get_subcommands()
+        .filter(|task| !task.is_hide_set())
+        .map(|task| task.get_name().to_string())
+        .collect()
+}
+
+/// Returns the number of single-character insertions, deletions and
+/// substitutions turning `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(
+                substitution
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Returns the task closest to the unknown `name`, if one is close enough to
+/// be a likely typo: at most a third of the characters of `name` differ, or
+/// `name` starts the task name.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::suggest_task;
+///
+/// let tasks = ["ci", "coverage", "powerset"];
+/// assert_eq!(suggest_task("covrage", &tasks), Some("coverage"));
+/// assert_eq!(suggest_task("deploy", &tasks), None);
+/// ```
+pub fn suggest_task<'a, S: AsRef<str>>(
+    name: &str,
+    tasks: &'a [S],
+) -> Option<&'a str> {
+    let limit = (name.chars().count() / 3).max(1);
+    tasks
+        .iter()
+        .map(AsRef::as_ref)
+        .filter_map(|task| {
+            let distance = edit_distance(name, task);
+            (distance <= limit || task.starts_with(name))
+                .then_some((distance, task))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, task)| task)
+}
+
+/// Returns the error message for the unknown task `name` of `cli`: a
+/// suggestion if one is close, then the available tasks.
+fn unknown_task(cli: &Command, name: &str) -> String {
+    let visible =
+        || cli.get_subcommands().filter(|task| !task.is_hide_set());
+    let tasks: Vec<&str> = visible().map(Command::get_name).collect();
+    let mut message = format!("Unknown task '{name}'");
+    if let Some(task) = suggest_task(name, &tasks) {
+        message.push_str(&format!(", did you mean '{task}'?"));
+    }
+    message.push_str("\n\nAvailable tasks:\n");
+    for task in visible() {
+        let about = task
+            .get_about()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        message
+            .push_str(&format!("  {:<15}{about}\n", task.get_name()));
+    }
+    message
+}
+
+/// Asks for a task of `registry` to run when none is given, with the CI checks
+/// highlighted first, then runs it with the options in `args`.
+///
+/// # Errors
+///
+/// Returns an error after printing the help when not attached to a terminal or
+/// when running in CI, so scripts do not silently skip their checks, or an
+/// error from the picked task.
+fn pick_task(registry: &XTasks, args: &[String]) -> AnyResult<()> {
+    let mut cli = registry.command();
+    if !crate::ops::is_interactive() {
+        cli.print_help()?;
+        return Err(AnyError::msg(
+            "No task given, run 'xtask ci' or pass --ci-default to run the CI checks",
+        ));
+    }
+    let tasks = list::tasks(&cli);
+    let items: Vec<String> = tasks
+        .iter()
+        .map(|task| format!("{:<14} {}", task.name, task.description))
+        .collect();
+    let default =
+        tasks.iter().position(|task| task.name == "ci").unwrap_or(0);
+    let Some(picked) =
+        crate::ops::select("Pick a task to run", &items, default)?
+    else {
+        return Ok(());
+    };
+    let mut args = args.to_vec();
+    args.push(tasks[picked].name.clone());
+    registry.run_with_args(&args)
+}
+
+/// Returns the command-line interface without any task: the global flags
+/// shared by the built-in and the [custom](XTasks) tasks.
+pub(crate) fn base_cli() -> Command {
+    Command::new("xtask")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Run the development tasks of a Rust workspace")
+        .long_about("Run the development tasks of a Rust workspace: CI checks, coverage, feature powersets, binary size analysis, documentation and releases.\n\nRunning without a task asks for one to run in a terminal, and prints this help otherwise; --ci-default runs the CI checks instead. The default settings of the tasks are read from xtask.toml or the [package.metadata.xtask] table of Cargo.toml.")
+        .allow_external_subcommands(true)
+        .arg(
+            Arg::new("config-file")
+                .long("config-file")
+                .value_name("PATH")
+                .help("read the default settings from this file instead of xtask.toml or Cargo.toml"),
+        )
+        .arg(
+            Arg::new("ci-default")
+                .long("ci-default")
+                .action(ArgAction::SetTrue)
+                .help("run the CI checks when no task is given, instead of asking for a task"),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .long("dry-run")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("print the commands and file changes of the task instead of running them"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .long("verbose")
+                .short('v')
+                .global(true)
+                .action(ArgAction::Count)
+                .help("log debug messages, and trace messages when repeated; the ci task also passes --verbose to cargo"),
+        )
+        .arg(
+            Arg::new("quiet")
+                .long("quiet")
+                .short('q')
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .help("only log warnings and errors, and only print the output of failing commands"),
+        )
+        .arg(
+            Arg::new("no-dotenv")
+                .long("no-dotenv")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("do not load the variables of the .env file at the workspace root"),
+        )
+        .arg(
+            Arg::new("no-summary")
+                .long("no-summary")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help("do not print the status and duration of the task once it ends"),
+        )
+}
+
+/// Returns `command` with the options of the `bloat` task and of its
+/// deprecated `bloat-deps` and `bloat-time` forms.
+fn bloat_args(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("package")
+                .short('p')
+                .long("package")
+                .help("package to build (defaults to every binary of the workspace)"),
+        )
+        .arg(
+            Arg::new("json")
+                .long("json")
+                .value_name("PATH")
+                .help("also write the report as JSON to this file"),
+        )
+        .arg(
+            Arg::new("top")
+                .short('n')
+                .long("top")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("only list the N largest crates or functions"),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .value_name("PREFIX")
+                .help("only list the crates or functions starting with PREFIX, e.g. my_crate::"),
+        )
+        .arg(
+            Arg::new("release")
+                .long("release")
+                .action(ArgAction::SetTrue)
+                .help("analyse the release build"),
+        )
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("PROFILE")
+                .conflicts_with("release")
+                .help("analyse the build of this cargo profile"),
+        )
+        .arg(
+            Arg::new("target")
+                .long("target")
+                .value_name("TRIPLE")
+                .help("analyse the build for this target, e.g. wasm32-unknown-unknown"),
+        )
+        .arg(
+            Arg::new("markdown")
+                .long("markdown")
+                .value_name("PATH")
+                .help("also write the report as a Markdown table to this file"),
+        )
+        .arg(
+            Arg::new("baseline")
+                .long("baseline")
+                .value_name("PATH")
+                .num_args(0..=1)
+                .default_missing_value(bloat::DEFAULT_BASELINE)
+                .help("compare to this report, or save it there if missing (defaults to target/xtask/bloat.json)"),
+        )
+        .arg(
+            Arg::new("max-growth")
+                .long("max-growth")
+                .value_name("LIMIT")
+                .requires("baseline")
+                .value_parser(|value: &str| {
+                    value
+                        .parse::<DiffThreshold>()
+                        .map_err(|err| err.to_string())
+                })
+                .help("fail when the binary grows by more than this, e.g. 5% or 512KiB"),
+        )
+}
+
+/// The short names of the built-in tasks, as `(task, alias)` pairs, shown by
+/// the help, the `list` task and the shell completions. The aliases of the
+/// deprecated `bloat-deps` and `bloat-time` tasks are hidden with them.
+pub const TASK_ALIASES: [(&str, &str); 5] = [
+    ("coverage", "cov"),
+    ("powerset", "ps"),
+    ("bloat-deps", "bd"),
+    ("bloat-time", "bt"),
+    ("docs", "d"),
+];
+
+/// Returns the command-line interface of the built-in tasks, as run by
+/// [`main_with_args`] and described by the shell completions.
+///
+/// The tasks can also be run by their [aliases](TASK_ALIASES).
+pub fn build_cli() -> Command {
+    let cli = base_cli()
+        .subcommand(
+            Command::new("list")
+                .about("list the tasks and whether the tools they need are installed")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("print the tasks as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("show where the default settings of the tasks are read from")
+                .arg(
+                    Arg::new("show")
+                        .long("show")
+                        .action(ArgAction::SetTrue)
+                        .help("print the settings merged with the built-in defaults, as TOML"),
+                ),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("print the completion script of the tasks for a shell")
+                .arg(
+                    Arg::new("shell")
+                        .required(true)
+                        .value_parser(clap::value_parser!(Shell))
+                        .help("the shell to complete in: bash, zsh, fish, powershell or elvish"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("PATH")
+                        .help("write the script to this file instead of stdout"),
+                ),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("scaffold the xtask crate and the cargo xtask alias in the current workspace")
+                .long_about("Scaffold the xtask pattern in the current workspace: create xtask/Cargo.toml and xtask/src/main.rs delegating to xtasks, add the xtask crate to the workspace members, and define the xtask alias in .cargo/config.toml. Existing xtask files are not overwritten unless --force is given. With --dry-run, the files that would be written are listed instead.")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("overwrite the existing xtask files and alias"),
+                ),
+        )
+        .subcommand(
+            Command::new("coverage")
+                .about("measure the test coverage of the workspace")
+                .long_about("Measure the test coverage of the workspace with cargo-tarpaulin (the default on Linux), cargo-llvm-cov or grcov, write the reports, and optionally fail under a threshold, compare with a baseline or upload the report.")
+                .arg(
+                    Arg::new("dev")
+                        .short('d')
+                        .long("dev")
+                        .action(ArgAction::SetTrue)
+                        .help("include dev-dependencies in the analysis"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("FORMAT")
+                        .action(ArgAction::Append)
+                        .value_parser(|value: &str| {
+                            value
+                                .parse::<CoverageFormat>()
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("report format: html, lcov, xml, json, or stdout (repeatable, defaults to html)"),
+                )
+                .arg(
+                    Arg::new("engine")
+                        .long("engine")
+                        .value_parser(["tarpaulin", "llvm-cov", "grcov"])
+                        .help("the coverage tool to use (detected by default)"),
+                )
+                .arg(
+                    Arg::new("fail-under")
+                        .long("fail-under")
+                        .value_name("PERCENT")
+                        .value_parser(clap::value_parser!(f32))
+                        .help("fail when line coverage is below this percentage"),
+                )
+                .arg(
+                    Arg::new("exclude-files")
+                        .long("exclude-files")
+                        .value_name("GLOB")
+                        .action(ArgAction::Append)
+                        .help("leave matching files out of the measurement (repeatable)"),
+                )
+                .arg(
+                    Arg::new("package")
+                        .short('p')
+                        .long("package")
+                        .action(ArgAction::Append)
+                        .help("the workspace member to measure (repeatable)"),
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .long("output-dir")
+                        .value_name("DIR")
+                        .help("where to write the reports (defaults to target/coverage)"),
+                )
+                .arg(
+                    Arg::new("open")
+                        .long("open")
+                        .action(ArgAction::SetTrue)
+                        .help("open the html report in the browser"),
+                )
+                .arg(
+                    Arg::new("baseline")
+                        .long("baseline")
+                        .value_name("FILE")
+                        .help("compare against this coverage summary or history (defaults to the last run on the default branch)"),
+                )
+                .arg(
+                    Arg::new("badge")
+                        .long("badge")
+                        .value_name("PATH")
+                        .help("write a coverage badge svg after the run"),
+                )
+                .arg(
+                    Arg::new("upload")
+                        .long("upload")
+                        .value_name("PROVIDER")
+                        .value_parser(["codecov", "coveralls"])
+                        .help("upload the lcov report to codecov or coveralls"),
+                )
+                .arg(
+                    Arg::new("summary-only")
+                        .long("summary-only")
+                        .action(ArgAction::SetTrue)
+                        .help("only print the per-module summary, without writing reports"),
+                )
+                .arg(
+                    Arg::new("test-args")
+                        .num_args(1..)
+                        .last(true)
+                        .allow_hyphen_values(true)
+                        .help("arguments passed to the test binaries, after --"),
+                ),
+        )
+        .subcommand(
+            Command::new("vars")
+                .about("print a diagnostics report of the workspace, toolchain, tools and environment")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("print the report as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("check the toolchain, components, tools, git and disk space needed by the tasks")
+                .long_about("Check the development environment in one go: rustc and cargo, the llvm-tools-preview, rustfmt and clippy components, the optional tools with their versions, git and the cleanliness of the working tree, and the disk space of the target directory. Every failed check prints the command fixing it, and the missing tools can be installed on the spot; XTASKS_CONFIRM=yes installs them without asking. Fails if a required check fails."),
+        )
+        .subcommand(
+            Command::new("fmt")
+                .about("format the workspace with rustfmt, or check that it is formatted")
+                .long_about("Format the workspace with rustfmt and list the files it changed, or check that it is formatted with --check. rustfmt runs with the toolchain of the fmt step of the CI checks, e.g. nightly when the ci settings select it.")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("check that the files are formatted instead of formatting them"),
+                )
+                .arg(
+                    Arg::new("package")
+                        .short('p')
+                        .long("package")
+                        .value_name("SPEC")
+                        .action(ArgAction::Append)
+                        .help("format the given package only, can be repeated"),
+                )
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("package")
+                        .help("format every package, including the path dependencies outside the workspace"),
+                ),
+        )
+        .subcommand(
+            Command::new("clippy")
+                .about("lint the workspace with clippy and the lint sets of the CI checks")
+                .long_about("Lint the workspace with clippy, denying warnings. The clippy_warn and clippy_allow lints of the ci settings apply as in the CI checks, followed by the lints given with -W and -A. With --fix, the suggestions are applied to the working tree and the files clippy changed are listed.")
+                .arg(
+                    Arg::new("max")
+                        .long("max")
+                        .action(ArgAction::SetTrue)
+                        .help("warn about the pedantic and nursery lints, for every target and feature"),
+                )
+                .arg(
+                    Arg::new("fix")
+                        .long("fix")
+                        .action(ArgAction::SetTrue)
+                        .help("apply the suggestions, even with uncommitted changes"),
+                )
+                .arg(
+                    Arg::new("warn")
+                        .short('W')
+                        .long("warn")
+                        .value_name("LINT")
+                        .action(ArgAction::Append)
+                        .help("warn about a lint, e.g. clippy::unwrap_used, can be repeated"),
+                )
+                .arg(
+                    Arg::new("allow")
+                        .short('A')
+                        .long("allow")
+                        .value_name("LINT")
+                        .action(ArgAction::Append)
+                        .help("allow a lint, can be repeated"),
+                )
+                .arg(
+                    Arg::new("all-targets")
+                        .long("all-targets")
+                        .action(ArgAction::SetTrue)
+                        .help("lint the tests, examples and benchmarks too"),
+                )
+                .arg(
+                    Arg::new("all-features")
+                        .long("all-features")
+                        .action(ArgAction::SetTrue)
+                        .help("lint with every feature enabled"),
+                ),
+        )
+        .subcommand(
+            Command::new("clean")
+                .about("remove the artifacts generated by the tasks: the coverage reports and the xtask cache unless told otherwise")
+                .long_about("Remove the artifacts generated by the tasks. Without flags, the coverage reports and the xtask cache in target/xtask are removed; --docs adds the API documentation and the rendered book, and --all the whole target directory. Nothing outside the workspace root is removed.")
+                .arg(
+                    Arg::new("all")
+                        .long("all")
+                        .action(ArgAction::SetTrue)
+                        .help("remove every artifact, then the target directory with cargo clean"),
+                )
+                .arg(
+                    Arg::new("coverage")
+                        .long("coverage")
+                        .action(ArgAction::SetTrue)
+                        .help("remove the coverage reports and profiles"),
+                )
+                .arg(
+                    Arg::new("docs")
+                        .long("docs")
+                        .action(ArgAction::SetTrue)
+                        .help("remove the API documentation and the rendered book"),
+                )
+                .arg(
+                    Arg::new("cache")
+                        .long("cache")
+                        .action(ArgAction::SetTrue)
+                        .help("remove the xtask cache in target/xtask"),
+                ),
+        )
+        .subcommand(
+            Command::new("audit")
+                .about("audit the dependencies for security advisories with cargo-audit")
+                .long_about("Run cargo audit and report the vulnerabilities and the warnings (unmaintained, unsound or yanked crates) of the dependencies. The task fails only for the vulnerabilities at or above the --severity threshold, from their CVSS score; the vulnerabilities without a score always fail it.")
+                .arg(
+                    Arg::new("ignore")
+                        .long("ignore")
+                        .value_name("ID")
+                        .action(ArgAction::Append)
+                        .help("ignore this advisory, e.g. RUSTSEC-2020-0071, can be repeated"),
+                )
+                .arg(
+                    Arg::new("severity")
+                        .long("severity")
+                        .value_name("LEVEL")
+                        .value_parser(|value: &str| {
+                            value
+                                .parse::<Severity>()
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("fail for the vulnerabilities at or above this severity: low (default), medium, high or critical"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .value_name("PATH")
+                        .help("save the report as JSON to this file"),
+                ),
+        )
+        .subcommand(
+            Command::new("outdated")
+                .about("report the dependencies with newer versions, semver-compatible or breaking")
+                .long_about("Report the direct dependencies of the workspace that have newer versions, grouped into semver-compatible and breaking updates. cargo outdated is used when it is installed; otherwise the versions locked in Cargo.lock are compared with the crates.io sparse index, which needs network access.")
+                .arg(
+                    Arg::new("exit-code")
+                        .long("exit-code")
+                        .action(ArgAction::SetTrue)
+                        .help("fail when some dependencies have breaking updates, for scheduled CI jobs"),
+                ),
+        )
+        .subcommand(
+            Command::new("vendor")
+                .about("vendor the dependencies with cargo vendor, or check that the vendored sources are up to date")
+                .long_about("Vendor the dependencies with cargo vendor and replace crates.io with the vendored sources in .cargo/config.toml, for offline builds. With --check, the dependencies are vendored into target/xtask instead and compared with the committed directory; the task fails with the crates that differ.")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("check that the vendored sources are up to date instead of vendoring"),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .value_name("DIR")
+                        .default_value(vendor::DEFAULT_VENDOR_DIR)
+                        .help("the directory of the vendored sources, relative to the workspace root"),
+                ),
+        )
+        .subcommand(
+            Command::new("licenses")
+                .about("report the licenses of the dependencies, grouped by SPDX expression")
+                .long_about("Report the licenses of the dependencies from cargo metadata, grouped by SPDX expression, as Markdown, JSON or CSV. The crates without license metadata are listed first. The task fails when a crate's license mentions a denied license, given with --deny or in the license_deny array of [package.metadata.xtask].")
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .default_value("md")
+                        .value_parser(|value: &str| {
+                            value
+                                .parse::<LicenseFormat>()
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("report format: md, json, or csv"),
+                )
+                .arg(
+                    Arg::new("deny")
+                        .long("deny")
+                        .value_name("LICENSE")
+                        .action(ArgAction::Append)
+                        .help("fail for the crates under this license, e.g. GPL-3.0, can be repeated"),
+                ),
+        )
+        .subcommand(
+            Command::new("msrv")
+                .about("find the minimum supported Rust version by bisecting the toolchains")
+                .long_about("Find the minimum supported Rust version by bisecting the minor versions between --lower and --upper: each candidate toolchain is installed with rustup's minimal profile and runs cargo check --all-features --locked. The results are cached in target/xtask until Cargo.lock changes.")
+                .arg(
+                    Arg::new("lower")
+                        .long("lower")
+                        .value_name("VERSION")
+                        .help("the lowest version to check, 1.56 by default"),
+                )
+                .arg(
+                    Arg::new("upper")
+                        .long("upper")
+                        .value_name("VERSION")
+                        .help("the highest version to check, the active rustc by default"),
+                )
+                .arg(
+                    Arg::new("write")
+                        .long("write")
+                        .action(ArgAction::SetTrue)
+                        .help("write the version found into the rust-version of Cargo.toml"),
+                ),
+        )
+        .subcommand(
+            Command::new("publish")
+                .about("publish the workspace crates to crates.io in dependency order")
+                .long_about("Publish the workspace crates to crates.io in dependency order. Every crate whose version is not on the index yet is verified with cargo publish --dry-run, then published, and the task waits for it to show up on the index before publishing the crates depending on it. With --dry-run, the crates are only verified.")
+                .arg(
+                    Arg::new("only")
+                        .long("only")
+                        .value_name("CRATE")
+                        .action(ArgAction::Append)
+                        .help("publish only this crate (repeatable)"),
+                )
+                .arg(
+                    Arg::new("skip")
+                        .long("skip")
+                        .value_name("CRATE")
+                        .action(ArgAction::Append)
+                        .help("leave this crate out (repeatable)"),
+                )
+                .arg(
+                    Arg::new("wait-attempts")
+                        .long("wait-attempts")
+                        .value_name("COUNT")
+                        .default_value("30")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .help("how many times to check the index for a published crate"),
+                )
+                .arg(
+                    Arg::new("wait-secs")
+                        .long("wait-secs")
+                        .value_name("SECONDS")
+                        .default_value("10")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("the delay between two checks of the index"),
+                ),
+        )
+        .subcommand(
+            Command::new("wasm")
+                .about("build the crate for wasm32-unknown-unknown, then run wasm-bindgen and wasm-opt")
+                .long_about("Build the crate for wasm32-unknown-unknown, adding the target with rustup when it is missing, generate the JavaScript bindings with wasm-bindgen, and optimize the module with wasm-opt when --opt-level is given. The tools are installed with cargo install when missing, and the size of the module is reported before and after optimization.")
+                .arg(
+                    Arg::new("package")
+                        .long("package")
+                        .short('p')
+                        .value_name("PACKAGE")
+                        .help("the package to build, the root package by default"),
+                )
+                .arg(
+                    Arg::new("release")
+                        .long("release")
+                        .action(ArgAction::SetTrue)
+                        .help("build with the release profile"),
+                )
+                .arg(
+                    Arg::new("opt-level")
+                        .long("opt-level")
+                        .value_name("LEVEL")
+                        .value_parser(wasm::OPT_LEVELS)
+                        .help("optimize the module with wasm-opt at this level"),
+                )
+                .arg(
+                    Arg::new("bindgen-target")
+                        .long("bindgen-target")
+                        .value_name("TARGET")
+                        .default_value("web")
+                        .value_parser(["web", "nodejs"])
+                        .help("the kind of bindings generated by wasm-bindgen"),
+                )
+                .arg(
+                    Arg::new("no-bindgen")
+                        .long("no-bindgen")
+                        .action(ArgAction::SetTrue)
+                        .help("skip wasm-bindgen and keep the module built by cargo"),
+                )
+                .arg(
+                    Arg::new("out-dir")
+                        .long("out-dir")
+                        .value_name("DIR")
+                        .default_value("pkg")
+                        .help("the directory wasm-bindgen writes the module and its bindings to"),
+                ),
+        )
+        .subcommand(
+            Command::new("docker")
+                .about("build the container image with docker or podman, tagged with the version and the commit")
+                .long_about("Build the container image with docker, or podman when docker is not installed, after checking that the daemon is reachable. The image is tagged name:version, name:latest and name:sha-<short commit>, plus the --tag tags, and its tags are pushed with --push.")
+                .arg(
+                    Arg::new("push")
+                        .long("push")
+                        .action(ArgAction::SetTrue)
+                        .help("push every tag of the image once built"),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .long("tag")
+                        .value_name("TAG")
+                        .action(ArgAction::Append)
+                        .help("an extra tag, or a full name:tag reference (repeatable)"),
+                )
+                .arg(
+                    Arg::new("build-arg")
+                        .long("build-arg")
+                        .value_name("KEY=VALUE")
+                        .action(ArgAction::Append)
+                        .help("a build argument of the Dockerfile (repeatable)"),
+                )
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("the name of the image, the root package name by default"),
+                )
+                .arg(
+                    Arg::new("file")
+                        .long("file")
+                        .short('f')
+                        .value_name("PATH")
+                        .default_value("Dockerfile")
+                        .help("the Dockerfile"),
+                ),
+        )
+        .subcommand(
+            Command::new("cross")
+                .about("build the release binaries for other targets with cross, cargo-zigbuild or cargo")
+                .long_about("Build the release binaries for each --target with the first backend that can: cargo for the host, cross when docker or podman is installed, cargo-zigbuild when zig is installed, then cargo when the target is installed with rustup. The missing backends and targets are installed. Every target is attempted, and the failures are reported with their backend.")
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("TRIPLE")
+                        .action(ArgAction::Append)
+                        .required(true)
+                        .help("a target to build for, e.g. x86_64-unknown-linux-musl (repeatable)"),
+                )
+                .arg(
+                    Arg::new("bin")
+                        .long("bin")
+                        .value_name("NAME")
+                        .action(ArgAction::Append)
+                        .help("a binary to build, every binary by default (repeatable)"),
+                )
+                .arg(
+                    Arg::new("backend")
+                        .long("backend")
+                        .value_name("BACKEND")
+                        .value_parser(["cargo", "cross", "zigbuild"])
+                        .help("build every target with this backend instead of selecting one per target"),
+                ),
+        )
+        .subcommand(
+            Command::new("fuzz")
+                .about("run the cargo-fuzz targets for a time budget and report the new crashes")
+                .long_about("Run the cargo-fuzz targets of the fuzz directory with the nightly toolchain, installing cargo-fuzz when missing. Every target runs for the --time budget, or only --target when given. The task fails when a target fails or writes new artifacts under fuzz/artifacts.")
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("NAME")
+                        .help("the fuzz target to run, every target of cargo fuzz list by default"),
+                )
+                .arg(
+                    Arg::new("time")
+                        .long("time")
+                        .value_name("DURATION")
+                        .default_value("60s")
+                        .value_parser(|value: &str| {
+                            fuzz::parse_duration(value)
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("the time budget of each target, e.g. 60s, 5m or 1h"),
+                ),
+        )
+        .subcommand(
+            Command::new("profile")
+                .about("record a flamegraph of a binary, benchmark or test with cargo flamegraph")
+                .long_about("Record a flamegraph with cargo flamegraph, installing it when missing. On Linux, perf must be installed and allowed to sample by kernel.perf_event_paranoid. Benchmarks are run with the --bench flag criterion expects.")
+                .arg(
+                    Arg::new("bin")
+                        .long("bin")
+                        .value_name("NAME")
+                        .conflicts_with_all(["bench", "test"])
+                        .help("the binary to profile, the default binary otherwise"),
+                )
+                .arg(
+                    Arg::new("bench")
+                        .long("bench")
+                        .value_name("NAME")
+                        .conflicts_with("test")
+                        .help("the benchmark to profile"),
+                )
+                .arg(
+                    Arg::new("test")
+                        .long("test")
+                        .value_name("NAME")
+                        .help("the integration test to profile"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("the flamegraph file, target/xtask/flamegraph.svg by default"),
+                )
+                .arg(
+                    Arg::new("open")
+                        .long("open")
+                        .action(ArgAction::SetTrue)
+                        .help("open the flamegraph in the browser"),
+                )
+                .arg(
+                    Arg::new("args")
+                        .num_args(1..)
+                        .last(true)
+                        .allow_hyphen_values(true)
+                        .help("arguments passed to the profiled program, after --"),
+                ),
+        )
+        .subcommand(
+            Command::new("sort-deps")
+                .about("sort the dependency tables of the workspace manifests")
+                .long_about("Sort the [dependencies], [dev-dependencies] and [build-dependencies] tables of every workspace manifest by crate name, keeping the comments and the inline tables as they are. Only the manifests whose order changes are written. With --check, nothing is written and the task fails listing the unsorted manifests.")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("fail if a manifest is not sorted, without writing"),
+                ),
+        )
+        .subcommand(
+            Command::new("update")
+                .about("update the locked dependencies and optionally run the CI checks with them")
+                .long_about("Update the locked dependencies with cargo update and print the versions that changed in Cargo.lock. With --test, the CI checks run next, and Cargo.lock is rolled back if they fail, unless --keep is given.")
+                .arg(
+                    Arg::new("package")
+                        .short('p')
+                        .long("package")
+                        .value_name("NAME")
+                        .help("the dependency to update, every dependency by default"),
+                )
+                .arg(
+                    Arg::new("precise")
+                        .long("precise")
+                        .value_name("VERSION")
+                        .requires("package")
+                        .help("the exact version the dependency is updated to"),
+                )
+                .arg(
+                    Arg::new("test")
+                        .long("test")
+                        .action(ArgAction::SetTrue)
+                        .help("run the CI checks with the updated dependencies"),
+                )
+                .arg(
+                    Arg::new("keep")
+                        .long("keep")
+                        .action(ArgAction::SetTrue)
+                        .requires("test")
+                        .help("keep the updated Cargo.lock even if the CI checks fail"),
+                ),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("run the criterion benchmarks and compare them with a baseline")
+                .long_about("Run cargo bench with the criterion baseline flags, and print the time and the change of every benchmark as a table. With --max-regression, the task fails when a benchmark got slower than the given percentage.")
+                .arg(
+                    Arg::new("bench")
+                        .long("bench")
+                        .value_name("NAME")
+                        .action(ArgAction::Append)
+                        .help("run this bench target only, can be repeated"),
+                )
+                .arg(
+                    Arg::new("save-baseline")
+                        .long("save-baseline")
+                        .value_name("NAME")
+                        .help("save the results as the criterion baseline with this name"),
+                )
+                .arg(
+                    Arg::new("baseline")
+                        .long("baseline")
+                        .value_name("NAME")
+                        .help("compare the results with the criterion baseline with this name"),
+                )
+                .arg(
+                    Arg::new("max-regression")
+                        .long("max-regression")
+                        .value_name("PERCENT")
+                        .value_parser(clap::value_parser!(f64))
+                        .help("fail when a benchmark got slower than this percentage"),
+                )
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .value_name("PATH")
+                        .help("save the results as JSON to this file"),
+                ),
+        )
+        .subcommand(
+            Command::new("dist")
+                .about("build the release binaries and package them into archives with checksums")
+                .long_about("Build the binaries of the workspace in release mode, package each target with the LICENSE and README files into <package>-v<version>-<target>.tar.gz (.zip for Windows), and write the checksums of the archives to SHA256SUMS. The archives go to the dist directory of the target directory unless --out-dir is given.")
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("TRIPLE")
+                        .action(ArgAction::Append)
+                        .help("package this target instead of the host, can be repeated"),
+                )
+                .arg(
+                    Arg::new("bin")
+                        .long("bin")
+                        .value_name("NAME")
+                        .action(ArgAction::Append)
+                        .help("package this binary only, can be repeated"),
+                )
+                .arg(
+                    Arg::new("out-dir")
+                        .long("out-dir")
+                        .value_name("PATH")
+                        .help("write the archives to this directory"),
+                )
+                .arg(
+                    Arg::new("strip")
+                        .long("strip")
+                        .action(ArgAction::SetTrue)
+                        .help("strip the symbols of the packaged binaries"),
+                ),
+        )
+        .subcommand(
+            Command::new("changelog")
+                .about("generate the changelog section of the current version from the git history")
+                .long_about("Generate the changelog section of the current version from the commits since the latest tag, grouped by their conventional commit prefix: feat, fix, docs, chore, and the others. The commits link to the repository of Cargo.toml when there is one. The section is printed unless --out is given; --check verifies the changelog instead.")
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .value_name("TAG")
+                        .help("list the commits since this tag instead of the latest one"),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .value_name("PATH")
+                        .help("write the section to this changelog, e.g. CHANGELOG.md"),
+                )
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("fail unless the changelog already contains the generated section (CHANGELOG.md unless --out is given)"),
+                )
+                .arg(
+                    Arg::new("skip-dependabot")
+                        .long("skip-dependabot")
+                        .action(ArgAction::SetTrue)
+                        .help("leave out the dependency updates opened by Dependabot"),
+                ),
+        )
+        .subcommand(
+            Command::new("release")
+                .about("bump the version, update the changelog, commit and tag a release")
+                .long_about("Release the workspace package: check that the working tree is clean, run the CI checks, bump the version in Cargo.toml, move the unreleased changes of CHANGELOG.md under the new version, commit, and create an annotated vX.Y.Z tag. Nothing is pushed. With --dry-run, the plan is printed instead.")
+                .arg(
+                    Arg::new("version")
+                        .value_name("VERSION")
+                        .required(true)
+                        .value_parser(|value: &str| {
+                            value
+                                .parse::<ReleaseVersion>()
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("patch, minor, major, or an explicit X.Y.Z version"),
+                )
+                .arg(
+                    Arg::new("publish")
+                        .long("publish")
+                        .action(ArgAction::SetTrue)
+                        .help("publish the package to crates.io after tagging"),
+                )
+                .arg(
+                    Arg::new("no-ci")
+                        .long("no-ci")
+                        .action(ArgAction::SetTrue)
+                        .help("skip the CI checks"),
+                )
+                .arg(
+                    Arg::new("no-changelog")
+                        .long("no-changelog")
+                        .action(ArgAction::SetTrue)
+                        .help("leave the changelog unchanged"),
+                )
+                .arg(
+                    Arg::new("no-tag")
+                        .long("no-tag")
+                        .action(ArgAction::SetTrue)
+                        .help("commit the release without tagging it"),
+                ),
+        )
+        .subcommand(
+            Command::new("install")
+                .about("install the development tools: cargo-watch, cargo-hack, cargo-bloat, llvm-tools-preview and grcov")
+                .arg(
+                    Arg::new("skip")
+                        .long("skip")
+                        .value_name("TOOL")
+                        .action(ArgAction::Append)
+                        .value_parser(DEV_TOOLS)
+                        .conflicts_with("only")
+                        .help("leave this tool out (repeatable)"),
+                )
+                .arg(
+                    Arg::new("only")
+                        .long("only")
+                        .value_name("TOOL")
+                        .action(ArgAction::Append)
+                        .value_parser(DEV_TOOLS)
+                        .help("install only this tool (repeatable)"),
+                ),
+        )
+        .subcommand(
+            Command::new("dev")
+                .about("check and test the project with cargo watch on every change")
+                .arg(
+                    Arg::new("cmd")
+                        .long("cmd")
+                        .value_name("COMMAND")
+                        .default_value("cargo")
+                        .help("the command running watch"),
+                )
+                .arg(
+                    Arg::new("clear")
+                        .long("clear")
+                        .action(ArgAction::SetTrue)
+                        .help("clear the screen between runs"),
+                ),
+        )
+        .subcommand(
+            Command::new("ci")
+                .about("run the CI checks: fmt, clippy, check, test and docs")
+                .long_about("Run the CI checks in order: cargo fmt, clippy, check, test and doc, stopping at the first failure unless configured otherwise, then print a summary of the steps.")
+                .arg(
+                    Arg::new("locked")
+                        .long("locked")
+                        .action(ArgAction::SetTrue)
+                        .help("require Cargo.lock to be up to date"),
+                )
+                .arg(
+                    Arg::new("offline")
+                        .long("offline")
+                        .action(ArgAction::SetTrue)
+                        .help(
+                            "run cargo without accessing the network",
+                        ),
+                )
+                .arg(
+                    Arg::new("fix")
+                        .long("fix")
+                        .action(ArgAction::SetTrue)
+                        .help("apply rustfmt and clippy fixes"),
+                )
+                .arg(
+                    Arg::new("test-args")
+                        .num_args(1..)
+                        .last(true)
+                        .allow_hyphen_values(true)
+                        .help("arguments passed to the test binaries, after --"),
+                )
+                .arg(
+                    Arg::new("coverage")
+                        .long("coverage")
+                        .value_name("PERCENT")
+                        .value_parser(clap::value_parser!(f32))
+                        .help("fail when line coverage is below this percentage"),
+                )
+                .arg(
+                    Arg::new("emit-workflow")
+                        .long("emit-workflow")
+                        .value_name("PATH")
+                        .help("write an equivalent GitHub Actions workflow instead of running"),
+                ),
+        )
+        .subcommand(
+            Command::new("install-hooks")
+                .about("install a git hook that runs the CI checks")
+                .arg(
+                    Arg::new("hook")
+                        .long("hook")
+                        .value_parser(["pre-push", "pre-commit"])
+                        .default_value("pre-push")
+                        .help("the git hook to install"),
+                )
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .action(ArgAction::SetTrue)
+                        .help("replace a hook not installed by xtasks"),
+                ),
+        )
+        .subcommand(
+            Command::new("book")
+                .about("build, serve or test the mdBook user guide")
+                .subcommand_required(true)
+                .subcommand(Command::new("build").about("render the book"))
+                .subcommand(
+                    Command::new("serve")
+                        .about("serve the book and rebuild it on changes")
+                        .arg(
+                            Arg::new("port")
+                                .long("port")
+                                .value_parser(clap::value_parser!(u16))
+                                .default_value("3000")
+                                .help("port the book is served on"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("test").about("test the code samples of the book"),
+                ),
+        )
+        .subcommand(
+            Command::new("readme")
+                .about("generate the README from the crate-level documentation")
+                .arg(
+                    Arg::new("check")
+                        .long("check")
+                        .action(ArgAction::SetTrue)
+                        .help("fail if the README is out of date instead of updating it"),
+                ),
+        )
+        .subcommand(
+            Command::new("typos")
+                .about("check the workspace for spelling mistakes")
+                .long_about("Check the workspace for spelling mistakes with the typos CLI, leaving out the target directory and reading _typos.toml when present. With --fix, the misspellings with a single correction are corrected in place and the corrected files are listed.")
+                .arg(
+                    Arg::new("config")
+                        .long("config")
+                        .value_name("PATH")
+                        .help("use a custom typos configuration file"),
+                )
+                .arg(
+                    Arg::new("fix")
+                        .long("fix")
+                        .action(ArgAction::SetTrue)
+                        .help("correct the misspellings in place"),
+                )
+                .arg(
+                    Arg::new("paths")
+                        .value_name("PATH")
+                        .num_args(1..)
+                        .help("the files or directories to check, the workspace by default"),
+                ),
+        )
+        .subcommand(
+            Command::new("powerset")
+                .about("run clippy and the tests over combinations of features")
+                .long_about("Run clippy and the tests over the combinations of features of the workspace packages with cargo-hack, up to the configured depth.")
+                .arg(
+                    Arg::new("depth")
+                        .long("depth")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(u32).range(1..))
+                        .help("the largest number of features combined (defaults to 2)"),
+                )
+                .arg(
+                    Arg::new("package")
+                        .short('p')
+                        .long("package")
+                        .action(ArgAction::Append)
+                        .help("the workspace member to test (repeatable, defaults to the whole workspace)"),
+                )
+                .arg(
+                    Arg::new("exclude")
+                        .long("exclude")
+                        .value_name("PACKAGE")
+                        .action(ArgAction::Append)
+                        .conflicts_with("package")
+                        .help("leave this workspace member out (repeatable, defaults to xtask)"),
+                )
+                .arg(
+                    Arg::new("only")
+                        .long("only")
+                        .value_name("STEP")
+                        .action(ArgAction::Append)
+                        .value_parser(|value: &str| {
+                            value
+                                .parse::<PowersetStep>()
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("only run this step: clippy, test or doc-test (repeatable)"),
+                )
+                .arg(
+                    Arg::new("summary")
+                        .long("summary")
+                        .action(ArgAction::SetTrue)
+                        .help("run each feature combination separately and summarize the results"),
+                )
+                .arg(
+                    Arg::new("test-args")
+                        .num_args(1..)
+                        .last(true)
+                        .allow_hyphen_values(true)
+                        .help("arguments passed to the test binaries, after --"),
+                ),
+        )
+        .subcommand(
+            bloat_args(Command::new("bloat"))
+                .about("analyse the binaries with cargo-bloat: the largest crates, the largest functions or the slowest crates to build")
+                .long_about("Analyse the binaries with cargo-bloat. --mode deps, the default, lists the crates taking the most space in the binaries and optionally compares them with a saved baseline; --mode functions lists the largest functions, and --mode time the crates taking the longest to build in the package given with -p.")
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .value_parser(["deps", "time", "functions"])
+                        .default_value("deps")
+                        .help("what to analyse: the crates by size, by build time, or the functions by size"),
+                ),
+        )
+        .subcommand(
+            bloat_args(Command::new("bloat-deps"))
+                .hide(true)
+                .about("deprecated, use 'bloat --mode deps'"),
+        )
+        .subcommand(
+            Command::new("bloat-fns")
+                .about("list the largest functions of the binaries")
+                .arg(
+                    Arg::new("package")
+                        .short('p')
+                        .long("package")
+                        .help("package to build (defaults to every binary of the workspace)"),
+                )
+                .arg(
+                    Arg::new("top")
+                        .short('n')
+                        .long("top")
+                        .value_name("N")
+                        .default_value("20")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("only list the N largest functions"),
+                )
+                .arg(
+                    Arg::new("filter")
+                        .long("filter")
+                        .value_name("PREFIX")
+                        .help("only list the functions starting with PREFIX, e.g. my_crate::"),
+                )
+                .arg(
+                    Arg::new("release")
+                        .long("release")
+                        .action(ArgAction::SetTrue)
+                        .help("analyse the release build"),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .value_name("PROFILE")
+                        .conflicts_with("release")
+                        .help("analyse the build of this cargo profile"),
+                )
+                .arg(
+                    Arg::new("target")
+                        .long("target")
+                        .value_name("TRIPLE")
+                        .help("analyse the build for this target, e.g. wasm32-unknown-unknown"),
+                )
+                .arg(
+                    Arg::new("markdown")
+                        .long("markdown")
+                        .value_name("PATH")
+                        .help("also write the report as a Markdown table to this file"),
+                ),
+        )
+        .subcommand(
+            Command::new("size-track")
+                .about("record the size of the release binaries at the current commit")
+                .arg(
+                    Arg::new("report")
+                        .long("report")
+                        .value_name("N")
+                        .num_args(0..=1)
+                        .default_missing_value("10")
+                        .value_parser(clap::value_parser!(usize))
+                        .help("print the last N recorded sizes instead of measuring (defaults to 10)"),
+                )
+                .arg(
+                    Arg::new("markdown")
+                        .long("markdown")
+                        .action(ArgAction::SetTrue)
+                        .help("print the sizes as a Markdown table"),
+                ),
+        )
+        .subcommand(
+            bloat_args(Command::new("bloat-time"))
+                .hide(true)
+                .about("deprecated, use 'bloat --mode time'"),
+        )
+        .subcommand(
+            Command::new("docs")
+                .about("build the API documentation, watching for changes")
+                .arg(
+                    Arg::new("no-watch")
+                        .long("no-watch")
+                        .action(ArgAction::SetTrue)
+                        .help("build the documentation once instead of watching"),
+                )
+                .arg(
+                    Arg::new("open")
+                        .long("open")
+                        .action(ArgAction::SetTrue)
+                        .help("open the documentation in the browser once built"),
+                )
+                .arg(
+                    Arg::new("coverage")
+                        .long("coverage")
+                        .action(ArgAction::SetTrue)
+                        .help("report the documentation coverage with nightly rustdoc"),
+                )
+                .arg(
+                    Arg::new("fail-under")
+                        .long("fail-under")
+                        .value_name("PERCENT")
+                        .value_parser(clap::value_parser!(f32))
+                        .requires("coverage")
+                        .help("fail if the documentation coverage is below PERCENT"),
+                )
+                .arg(
+                    Arg::new("deploy")
+                        .long("deploy")
+                        .action(ArgAction::SetTrue)
+                        .help("build once, then publish the documentation to a branch"),
+                )
+                .arg(
+                    Arg::new("branch")
+                        .long("branch")
+                        .default_value("gh-pages")
+                        .requires("deploy")
+                        .help("branch the documentation is published to"),
+                )
+                .arg(
+                    Arg::new("remote")
+                        .long("remote")
+                        .default_value("origin")
+                        .requires("deploy")
+                        .help("remote the branch is pushed to"),
+                )
+                .arg(
+                    Arg::new("check-links")
+                        .long("check-links")
+                        .action(ArgAction::SetTrue)
+                        .help("build once, then check the links of the documentation"),
+                )
+                .arg(
+                    Arg::new("check-external")
+                        .long("check-external")
+                        .action(ArgAction::SetTrue)
+                        .requires("check-links")
+                        .help("also check external links with HEAD requests"),
+                )
+                .arg(
+                    Arg::new("document-private-items")
+                        .long("document-private-items")
+                        .action(ArgAction::SetTrue)
+                        .help("document private items too"),
+                )
+                .arg(
+                    Arg::new("cfg-docsrs")
+                        .long("cfg-docsrs")
+                        .action(ArgAction::SetTrue)
+                        .help("build like docs.rs, with nightly and --cfg docsrs"),
+                )
+                .arg(
+                    Arg::new("deny-warnings")
+                        .long("deny-warnings")
+                        .action(ArgAction::SetTrue)
+                        .help("fail on rustdoc warnings"),
+                ),
+        );
+    // Rebuilt rather than edited with `mut_subcommand`, which would move the
+    // aliased tasks to the end of the help.
+    let tasks: Vec<Command> = cli
+        .get_subcommands()
+        .map(|task| {
+            TASK_ALIASES
+                .iter()
+                .filter(|(name, _)| *name == task.get_name())
+                .fold(task.clone(), |task, (_, alias)| {
+                    task.visible_alias(*alias)
+                })
+        })
+        .collect();
+    base_cli().subcommands(tasks)
+}
+
+/// Returns the completion script of the tasks for `shell`, completing the
+/// subcommands and flags of [`build_cli`].
+///
+/// # Examples
+///
+/// ```
+/// use clap_complete::Shell;
+/// use xtasks::tasks::completion_script;
+///
+/// assert!(completion_script(Shell::Bash).contains("powerset"));
+/// ```
+pub fn completion_script(shell: Shell) -> String {
+    script(build_cli(), shell)
+}
+
+/// Returns the completion script of `cli` for `shell`.
+fn script(mut cli: Command, shell: Shell) -> String {
+    let name = cli.get_name().to_string();
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut cli, name, &mut script);
+    String::from_utf8_lossy(&script).into_owned()
+}
+
+/// Returns the value of `id` when it was given on the command line, so that it
+/// overrides the configured settings; the defaults of the CLI do not.
+fn cli_value<T>(matches: &ArgMatches, id: &str) -> Option<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    if matches.value_source(id) == Some(ValueSource::CommandLine) {
+        matches.get_one::<T>(id).cloned()
+    } else {
+        None
+    }
+}
+
 /// Sets up the main command-line interface for your xtask project and executes
 /// the specified subcommands.
 ///
+/// The `ci`, `powerset`, `coverage`, `bloat` and `bloat-fns` subcommands
+/// start from the settings found by [`XtaskConfig::discover`], or read from
+/// `--config-file`, and apply their flags on top.
+///
+/// With `--dry-run`, the task runs with a [`DryRunRunner`](runner::DryRunRunner),
+/// which prints the commands instead of running them, and the filesystem
+/// helpers only print their changes. The `clean`, `powerset` and `docs --deploy`
+/// tasks also apply their own dry run.
+///
+/// Without a task, the user picks one from a menu, or the CI checks run with
+/// `--ci-default`, using the configured `ci` settings.
+///
+/// The global `--quiet` and `--verbose` flags set the log level, which
+/// defaults to the one named by `XTASKS_LOG`, or INFO.
+///
+/// The variables of the `.env` file at the root of the workspace are loaded
+/// before the task runs, without overriding the environment, unless
+/// `--no-dotenv` is given, see [`load_dotenv`](crate::ops::load_dotenv).
+///
+/// Once the task ends, a footer with its status and duration is printed on
+/// stderr, see [`summary::footer`], unless `--no-summary` or `--quiet` is
+/// given.
+///
+/// Custom tasks are run alongside the built-in ones with an [`XTasks`]
+/// registry, of which this function is the default.
+///
 /// This function configures and executes various subcommands using `clap`. The available subcommands
-/// include `coverage`, `vars`, `ci`, `powerset`, `bloat-deps`, `bloat-time`, and `docs`.
+/// include `list`, `audit`, `bench`, `dist`, `changelog`, `release`, `completions`, `init`, `install`, `dev`, `clean`, `coverage`, `vars`, `doctor`, `fmt`, `clippy`, `outdated`, `vendor`, `licenses`, `msrv`, `publish`, `wasm`, `docker`, `cross`, `fuzz`, `profile`, `sort-deps`, `update`, `ci`, `powerset`, `bloat`, `bloat-fns`, `size-track`, `docs`, `book`, `readme`, and `typos`.
 ///
 /// # Arguments
 ///
@@ -169,66 +1841,791 @@ pub fn install() -> AnyResult<()> {
 /// - Required arguments for a subcommand are missing.
 /// - There is a problem in setting up or executing the command-line interface.
 pub fn main_with_args(args: &[String]) -> AnyResult<()> {
-    let cli = Command::new("xtask")
-        .subcommand(
-            Command::new("coverage").arg(
-                Arg::new("dev")
-                    .short('d')
-                    .long("dev")
-                    .help("generate an html report"),
+    XTasks::new().with_builtin_tasks().run_with_args(args)
+}
+
+/// Runs the task of `registry` named by `args`: a custom task with its handler,
+/// or a built-in task as described by [`main_with_args`].
+fn run_tasks(registry: &XTasks, args: &[String]) -> AnyResult<()> {
+    let cli = registry.command();
+    let matches = cli.clone().get_matches_from(args);
+    set_log_level(match matches.get_count("verbose") {
+        _ if matches.get_flag("quiet") => Some(&LogLevel::WARNING),
+        0 => None,
+        1 => Some(&LogLevel::DEBUG),
+        _ => Some(&LogLevel::TRACE),
+    });
+    if !matches.get_flag("no-dotenv") {
+        crate::ops::load_dotenv(None)?;
+    }
+    let config = || match matches.get_one::<String>("config-file") {
+        Some(path) => XtaskConfig::load(std::path::Path::new(path)),
+        None => XtaskConfig::discover(std::path::Path::new(".")),
+    };
+    let builtin = || match matches.subcommand() {
+        Some(("vars", sm)) => {
+            let report = vars::report();
+            if sm.get_flag("json") {
+                println!("{}", report.to_json()?);
+            } else {
+                println!("{report}");
+            }
+            Ok(())
+        }
+        Some(("doctor", _)) => {
+            doctor::doctor(std::path::Path::new(".")).map(|_| ())
+        }
+        Some(("completions", sm)) => {
+            let shell = sm
+                .get_one::<Shell>("shell")
+                .copied()
+                .context("please provide a shell")?;
+            let script = script(cli.clone(), shell);
+            match sm.get_one::<String>("out") {
+                Some(out) => crate::ops::write_atomic(out, script),
+                None => {
+                    print!("{script}");
+                    Ok(())
+                }
+            }
+        }
+        Some(("init", sm)) => scaffold(
+            std::path::Path::new("."),
+            InitOptionsBuilder::default()
+                .force(sm.get_flag("force"))
+                .dry_run(sm.get_flag("dry-run"))
+                .build()?,
+        ),
+        Some(("fmt", sm)) => {
+            let toolchain = config()?
+                .ci
+                .build()
+                .context("Failed to build CI configuration")?
+                .fmt_toolchain();
+            let opts = FmtOptionsBuilder::default()
+                .check(sm.get_flag("check"))
+                .packages(
+                    sm.get_many::<String>("package")
+                        .map(|packages| packages.cloned().collect())
+                        .unwrap_or_else(Vec::new),
+                )
+                .all(sm.get_flag("all"))
+                .toolchain(toolchain)
+                .verbose(matches.get_count("verbose") > 0)
+                .build()?;
+            fmt::fmt(opts)
+        }
+        Some(("clippy", sm)) => {
+            let ci = config()?
+                .ci
+                .build()
+                .context("Failed to build CI configuration")?;
+            let lints = |id: &str, configured: Vec<String>| {
+                configured
+                    .into_iter()
+                    .chain(
+                        sm.get_many::<String>(id)
+                            .into_iter()
+                            .flatten()
+                            .cloned(),
+                    )
+                    .collect::<Vec<_>>()
+            };
+            let opts = ClippyOptionsBuilder::default()
+                .max(sm.get_flag("max"))
+                .fix(sm.get_flag("fix"))
+                .warn(lints("warn", ci.clippy_warn))
+                .allow(lints("allow", ci.clippy_allow))
+                .all_targets(sm.get_flag("all-targets"))
+                .all_features(sm.get_flag("all-features"))
+                .annotations(ci.annotations)
+                .verbose(matches.get_count("verbose") > 0)
+                .build()?;
+            clippy::clippy(opts)
+        }
+        Some(("clean", sm)) => CleanBuilder::default()
+            .all(sm.get_flag("all"))
+            .coverage(sm.get_flag("coverage"))
+            .docs(sm.get_flag("docs"))
+            .cache(sm.get_flag("cache"))
+            .dry_run(sm.get_flag("dry-run"))
+            .build()?
+            .run()
+            .map(|_| ()),
+        Some(("outdated", sm)) => {
+            outdated::outdated(sm.get_flag("exit-code")).map(|_| ())
+        }
+        Some(("vendor", sm)) => vendor::vendor(
+            std::path::Path::new(
+                sm.get_one::<String>("dir")
+                    .map_or(vendor::DEFAULT_VENDOR_DIR, String::as_str),
             ),
+            sm.get_flag("check"),
+        ),
+        Some(("licenses", sm)) => licenses::licenses(
+            sm.get_one::<LicenseFormat>("format")
+                .copied()
+                .unwrap_or_default(),
+            &sm.get_many::<String>("deny")
+                .map(|licenses| licenses.cloned().collect::<Vec<_>>())
+                .unwrap_or_default(),
         )
-        .subcommand(Command::new("vars"))
-        .subcommand(Command::new("ci"))
-        .subcommand(Command::new("powerset"))
-        .subcommand(
-            Command::new("bloat-deps").arg(
-                Arg::new("package")
-                    .short('p')
-                    .long("package")
-                    .help("package to build")
-                    .required(true),
-            ),
+        .map(|_| ()),
+        Some(("msrv", sm)) => msrv::msrv(
+            sm.get_one::<String>("lower").map(String::as_str),
+            sm.get_one::<String>("upper").map(String::as_str),
+            sm.get_flag("write"),
         )
-        .subcommand(
-            Command::new("bloat-time").arg(
-                Arg::new("package")
-                    .short('p')
-                    .long("package")
-                    .help("package to build")
-                    .required(true),
-            ),
+        .map(|_| ()),
+        Some(("publish", sm)) => {
+            let crates = |id: &str| -> Vec<String> {
+                sm.get_many::<String>(id)
+                    .map(|crates| crates.cloned().collect())
+                    .unwrap_or_default()
+            };
+            publish::publish(
+                PublishOptionsBuilder::default()
+                    .dry_run(sm.get_flag("dry-run"))
+                    .only(crates("only"))
+                    .skip(crates("skip"))
+                    .wait_attempts(
+                        sm.get_one::<u32>("wait-attempts")
+                            .copied()
+                            .unwrap_or(30),
+                    )
+                    .wait_delay(Duration::from_secs(
+                        sm.get_one::<u64>("wait-secs")
+                            .copied()
+                            .unwrap_or(10),
+                    ))
+                    .build()?,
+            )
+        }
+        Some(("wasm", sm)) => wasm::build(
+            WasmOptionsBuilder::default()
+                .package(sm.get_one::<String>("package").cloned())
+                .release(sm.get_flag("release"))
+                .opt_level(sm.get_one::<String>("opt-level").cloned())
+                .bindgen(!sm.get_flag("no-bindgen"))
+                .bindgen_target(
+                    sm.get_one::<String>("bindgen-target")
+                        .map(|target| target.parse::<BindgenTarget>())
+                        .transpose()?
+                        .unwrap_or_default(),
+                )
+                .out_dir(
+                    sm.get_one::<String>("out-dir")
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| PathBuf::from("pkg")),
+                )
+                .build()?,
         )
-        .subcommand(Command::new("docs"));
-    let matches = cli.get_matches_from(args);
-    println!("Received subcommand: {:?}", matches.subcommand());
-
-    let res = match matches.subcommand() {
-        Some(("vars", _)) => {
-            let root = crate::ops::root_dir();
-            println!("root: {root:?}");
+        .map(|_| ()),
+        Some(("docker", sm)) => {
+            let values = |id: &str| -> Vec<String> {
+                sm.get_many::<String>(id)
+                    .map(|values| values.cloned().collect())
+                    .unwrap_or_default()
+            };
+            docker::build(
+                DockerOptionsBuilder::default()
+                    .name(sm.get_one::<String>("name").cloned())
+                    .tags(values("tag"))
+                    .build_args(values("build-arg"))
+                    .file(
+                        sm.get_one::<String>("file")
+                            .map(PathBuf::from)
+                            .unwrap_or_else(|| {
+                                PathBuf::from("Dockerfile")
+                            }),
+                    )
+                    .push(sm.get_flag("push"))
+                    .build()?,
+            )
+            .map(|_| ())
+        }
+        Some(("cross", sm)) => {
+            let values = |id: &str| -> Vec<String> {
+                sm.get_many::<String>(id)
+                    .map(|values| values.cloned().collect())
+                    .unwrap_or_default()
+            };
+            let paths = cross::build(
+                &values("target"),
+                CrossOptionsBuilder::default()
+                    .binaries(values("bin"))
+                    .backend(
+                        sm.get_one::<String>("backend")
+                            .map(|backend| {
+                                backend.parse::<CrossBackend>()
+                            })
+                            .transpose()?,
+                    )
+                    .build()?,
+            )?;
+            for path in paths {
+                println!("Built {}", path.display());
+            }
             Ok(())
         }
-        Some(("ci", _)) | None => crate::tasks::ci(),
-        Some(("coverage", matches)) => {
-            coverage(matches.contains_id("dev"))
+        Some(("fuzz", sm)) => fuzz::run(
+            sm.get_one::<String>("target").map(String::as_str),
+            sm.get_one::<Duration>("time")
+                .copied()
+                .unwrap_or(Duration::from_secs(60)),
+        )
+        .map(|_| ()),
+        Some(("profile", sm)) => {
+            let target = if let Some(name) = sm.get_one::<String>("bin")
+            {
+                Some(ProfileTarget::Bin(name.clone()))
+            } else if let Some(name) = sm.get_one::<String>("bench") {
+                Some(ProfileTarget::Bench(name.clone()))
+            } else {
+                sm.get_one::<String>("test")
+                    .map(|name| ProfileTarget::Test(name.clone()))
+            };
+            let mut builder = FlamegraphOptionsBuilder::default();
+            let _ = builder
+                .target(target)
+                .args(
+                    sm.get_many::<String>("args")
+                        .map(|args| args.cloned().collect())
+                        .unwrap_or_else(Vec::new),
+                )
+                .open(sm.get_flag("open"));
+            if let Some(output) = sm.get_one::<String>("output") {
+                let _ = builder.output(output);
+            }
+            profile::flamegraph(builder.build()?).map(|_| ())
+        }
+        Some(("sort-deps", sm)) => {
+            let check = sm.get_flag("check");
+            let sorted = manifest::sort(check)?;
+            if !check && !runner::is_dry_run() {
+                if sorted.is_empty() {
+                    println!("All the manifests are sorted");
+                } else {
+                    println!("Sorted {} manifests:", sorted.len());
+                    for path in &sorted {
+                        println!("  {}", path.display());
+                    }
+                }
+            }
+            Ok(())
+        }
+        Some(("update", sm)) => {
+            let opts = UpdateOptionsBuilder::default()
+                .package(sm.get_one::<String>("package").cloned())
+                .precise(sm.get_one::<String>("precise").cloned())
+                .test(sm.get_flag("test"))
+                .keep(sm.get_flag("keep"))
+                .build()?;
+            update::update_with(
+                std::path::Path::new("."),
+                &opts,
+                || config()?.ci.run(),
+            )
+            .map(|_| ())
+        }
+        Some(("audit", sm)) => {
+            let opts = AuditOptionsBuilder::default()
+                .ignore(
+                    sm.get_many::<String>("ignore")
+                        .map(|ids| ids.cloned().collect())
+                        .unwrap_or_else(Vec::new),
+                )
+                .severity(
+                    sm.get_one::<Severity>("severity")
+                        .copied()
+                        .unwrap_or_default(),
+                )
+                .json(sm.get_one::<String>("json").map(PathBuf::from))
+                .build()?;
+            audit(opts).map(|_| ())
         }
-        Some(("docs", _)) => docs(),
-        Some(("powerset", _)) => powerset(),
-        Some(("bloat-deps", sm)) => deps(
-            sm.get_one::<String>("package")
-                .context("please provide a package with -p")?,
+        Some(("bench", sm)) => BenchBuilder::default()
+            .benches(
+                sm.get_many::<String>("bench")
+                    .map(|benches| benches.cloned().collect())
+                    .unwrap_or_else(Vec::new),
+            )
+            .save_baseline(
+                sm.get_one::<String>("save-baseline").cloned(),
+            )
+            .baseline(sm.get_one::<String>("baseline").cloned())
+            .max_regression(
+                sm.get_one::<f64>("max-regression").copied(),
+            )
+            .json(sm.get_one::<String>("json").map(PathBuf::from))
+            .build()?
+            .run()
+            .map(|_| ()),
+        Some(("dist", sm)) => {
+            let config = DistConfigBuilder::default()
+                .targets(
+                    sm.get_many::<String>("target")
+                        .map(|targets| targets.cloned().collect())
+                        .unwrap_or_else(Vec::new),
+                )
+                .binaries(
+                    sm.get_many::<String>("bin")
+                        .map(|binaries| binaries.cloned().collect())
+                        .unwrap_or_else(Vec::new),
+                )
+                .output_dir(
+                    sm.get_one::<String>("out-dir").map(PathBuf::from),
+                )
+                .strip(sm.get_flag("strip"))
+                .build()?;
+            dist(config).map(|_| ())
+        }
+        Some(("changelog", sm)) => ChangelogBuilder::default()
+            .range(
+                sm.get_one::<String>("since")
+                    .map(|tag| format!("{tag}..HEAD")),
+            )
+            .out(sm.get_one::<String>("out").map(PathBuf::from))
+            .check(sm.get_flag("check"))
+            .skip_dependabot(sm.get_flag("skip-dependabot"))
+            .build()?
+            .run()
+            .map(|_| ()),
+        Some(("release", sm)) => ReleaseBuilder::default()
+            .version(
+                sm.get_one::<ReleaseVersion>("version")
+                    .cloned()
+                    .context("please provide a version")?,
+            )
+            .publish(sm.get_flag("publish"))
+            .ci(!sm.get_flag("no-ci"))
+            .changelog(!sm.get_flag("no-changelog"))
+            .tag(!sm.get_flag("no-tag"))
+            .dry_run(sm.get_flag("dry-run"))
+            .build()?
+            .run()
+            .map(|_| ()),
+        Some(("install", sm)) => {
+            let tools = |id: &str| -> Vec<String> {
+                sm.get_many::<String>(id)
+                    .map(|tools| tools.cloned().collect())
+                    .unwrap_or_default()
+            };
+            install_tools(&tools("skip"), &tools("only"))
+        }
+        Some(("dev", sm)) => dev_with_options(
+            sm.get_one::<String>("cmd").map_or("cargo", String::as_str),
+            sm.get_flag("clear"),
         ),
-        Some(("bloat-time", sm)) => time(
-            sm.get_one::<String>("package")
-                .context("please provide a package with -p")?,
+        Some(("list", sm)) => {
+            let tasks = list::tasks(&cli);
+            if sm.get_flag("json") {
+                println!("{}", list::to_json(&tasks)?);
+            } else {
+                print!("{}", list::table(&tasks));
+            }
+            Ok(())
+        }
+        Some(("config", sm)) => {
+            let config = config()?;
+            match &config.source {
+                Some(source) => {
+                    println!("Settings read from {}", source.display());
+                }
+                None => {
+                    println!("No settings found, using the defaults")
+                }
+            }
+            if sm.get_flag("show") {
+                print!("{}", config.to_toml()?);
+            }
+            Ok(())
+        }
+        Some(("ci", sm)) => {
+            let mut builder = config()?.ci;
+            if sm.get_flag("locked") {
+                builder.locked(true);
+            }
+            if sm.get_flag("offline") {
+                builder.offline(true);
+            }
+            if sm.get_flag("fix") {
+                builder.fix(true);
+            }
+            if matches.get_flag("quiet") {
+                builder.quiet(true);
+            }
+            if matches.get_count("verbose") > 0 {
+                builder.verbose(true);
+            }
+            if let Some(threshold) = sm.get_one::<f32>("coverage") {
+                builder.coverage_threshold(*threshold);
+            }
+            if let Some(test_args) = sm.get_many::<String>("test-args")
+            {
+                builder
+                    .test_args(test_args.cloned().collect::<Vec<_>>());
+            }
+            match sm.get_one::<String>("emit-workflow") {
+                Some(path) => {
+                    let workflow = builder
+                        .build()
+                        .context("Failed to build CI configuration")?
+                        .to_github_workflow()?;
+                    if write_file_if_changed(path, &workflow)? {
+                        println!("Wrote {path}");
+                    } else {
+                        println!("{path} is up to date");
+                    }
+                    Ok(())
+                }
+                None => builder.run(),
+            }
+        }
+        Some(("install-hooks", sm)) => {
+            let kind: HookKind = sm
+                .get_one::<String>("hook")
+                .map(String::as_str)
+                .unwrap_or_default()
+                .parse()?;
+            install_git_hook_in(
+                std::path::Path::new("."),
+                kind,
+                sm.get_flag("force"),
+            )
+            .map(|_| ())
+        }
+        None if matches.get_flag("ci-default") => config()?.ci.run(),
+        None => pick_task(registry, args),
+        Some(("coverage", matches)) => {
+            let upload = matches
+                .get_one::<String>("upload")
+                .map(|provider| provider.parse::<CoverageProvider>())
+                .transpose()?;
+            let mut builder = config()?.coverage;
+            if let Some(formats) =
+                matches.get_many::<CoverageFormat>("out")
+            {
+                builder.formats(formats.copied().collect::<Vec<_>>());
+            }
+            if matches.get_flag("dev") {
+                builder.dev(true);
+            }
+            if matches.get_flag("open") {
+                builder.open(true);
+            }
+            if matches.get_flag("summary-only") {
+                builder.summary_only(true);
+            }
+            if let Some(dir) = matches.get_one::<String>("output-dir") {
+                builder.output_dir(dir);
+            }
+            if let Some(baseline) =
+                matches.get_one::<String>("baseline")
+            {
+                builder.baseline(baseline);
+            }
+            if let Some(test_args) =
+                matches.get_many::<String>("test-args")
+            {
+                builder
+                    .test_args(test_args.cloned().collect::<Vec<_>>());
+            }
+            if let Some(engine) = matches.get_one::<String>("engine") {
+                builder.engine(engine.parse::<CoverageEngine>()?);
+            }
+            if let Some(fail_under) =
+                matches.get_one::<f32>("fail-under")
+            {
+                builder.fail_under(*fail_under);
+            }
+            if let Some(patterns) =
+                matches.get_many::<String>("exclude-files")
+            {
+                builder.exclude_files(
+                    patterns.cloned().collect::<Vec<_>>(),
+                );
+            }
+            if let Some(packages) =
+                matches.get_many::<String>("package")
+            {
+                builder.packages(packages.cloned().collect::<Vec<_>>());
+            }
+            let mut coverage = builder
+                .build()
+                .context("Failed to build Coverage configuration")?;
+            if upload.is_some()
+                && !coverage.formats.contains(&CoverageFormat::Lcov)
+            {
+                coverage.formats.push(CoverageFormat::Lcov);
+            }
+            let report = coverage.run()?;
+            if let Some(badge) = matches.get_one::<String>("badge") {
+                coverage_badge(&report, std::path::Path::new(badge))?;
+            }
+            if let Some(provider) = upload {
+                // A dry run writes no report, so the upload is planned
+                // with the path the report would be collected at.
+                let lcov = report
+                    .outputs
+                    .iter()
+                    .find(|path| path.ends_with("lcov.info"))
+                    .cloned()
+                    .or_else(|| {
+                        runner::is_dry_run()
+                            .then(|| {
+                                coverage.report_path(
+                                    coverage.resolved_engine(),
+                                    CoverageFormat::Lcov,
+                                )
+                            })
+                            .flatten()
+                    })
+                    .context("The coverage run wrote no LCOV report to upload")?;
+                coverage_upload(provider, &lcov)?;
+            }
+            Ok(())
+        }
+        Some(("docs", sm)) if sm.get_flag("coverage") => {
+            let coverage = doc_coverage()?;
+            println!("{coverage}");
+            coverage.check(sm.get_one::<f32>("fail-under").copied())
+        }
+        Some(("docs", sm)) => DocsBuilder::default()
+            .watch(!sm.get_flag("no-watch") && !sm.get_flag("deploy"))
+            .deploy(
+                sm.get_flag("deploy")
+                    .then(|| {
+                        DeployBuilder::default()
+                            .branch(
+                                sm.get_one::<String>("branch")
+                                    .cloned()
+                                    .unwrap_or_default(),
+                            )
+                            .remote(
+                                sm.get_one::<String>("remote")
+                                    .cloned()
+                                    .unwrap_or_default(),
+                            )
+                            .dry_run(sm.get_flag("dry-run"))
+                            .build()
+                    })
+                    .transpose()?,
+            )
+            .deny_warnings(sm.get_flag("deny-warnings"))
+            .open(sm.get_flag("open"))
+            .check_links(sm.get_flag("check-links"))
+            .check_external(sm.get_flag("check-external"))
+            .private_items(sm.get_flag("document-private-items"))
+            .cfg_docsrs(sm.get_flag("cfg-docsrs"))
+            .run(),
+        Some(("book", sm)) => book(match sm.subcommand() {
+            Some(("serve", serve)) => BookAction::Serve {
+                port: serve
+                    .get_one::<u16>("port")
+                    .copied()
+                    .unwrap_or(3000),
+            },
+            Some(("test", _)) => BookAction::Test,
+            _ => BookAction::Build,
+        }),
+        Some(("readme", sm)) => {
+            let check = sm.get_flag("check");
+            let readme = std::path::Path::new("README.md");
+            if sync_readme(std::path::Path::new("."), readme, check)? {
+                println!("Updated {}", readme.display());
+            }
+            Ok(())
+        }
+        Some(("typos", sm)) => {
+            let paths: Vec<PathBuf> = sm
+                .get_many::<String>("paths")
+                .map(|paths| paths.map(PathBuf::from).collect())
+                .unwrap_or_default();
+            let fix = sm.get_flag("fix");
+            let report = typos::run_in(
+                std::path::Path::new("."),
+                sm.get_one::<String>("config")
+                    .map(std::path::Path::new),
+                fix,
+                &paths,
+            )?;
+            if fix && !runner::is_dry_run() {
+                println!("{report}");
+            }
+            ensure_no_typos(&report.findings)
+        }
+        Some(("powerset", sm)) => {
+            let mut builder = config()?.powerset;
+            if let Some(depth) = sm.get_one::<u32>("depth") {
+                let _ = builder.depth(*depth);
+            }
+            if let Some(packages) = sm.get_many::<String>("package") {
+                builder.packages(packages.cloned().collect::<Vec<_>>());
+            }
+            if let Some(excluded) = sm.get_many::<String>("exclude") {
+                builder.exclude_packages(
+                    excluded.cloned().collect::<Vec<_>>(),
+                );
+            }
+            if let Some(steps) = sm.get_many::<PowersetStep>("only") {
+                builder.steps(steps.copied().collect::<Vec<_>>());
+            }
+            if sm.get_flag("dry-run") {
+                builder.dry_run(true);
+            }
+            if sm.get_flag("summary") {
+                builder.summary(true);
+            }
+            if let Some(test_args) = sm.get_many::<String>("test-args")
+            {
+                builder
+                    .test_args(test_args.cloned().collect::<Vec<_>>());
+            }
+            builder.run()
+        }
+        Some(("bloat", sm)) => run_bloat(
+            config()?.bloat,
+            sm,
+            sm.get_one::<String>("mode")
+                .map_or(Ok(BloatMode::Deps), |mode| mode.parse())?,
         ),
-        _ => {
-            eprintln!("Error: Unrecognized subcommand");
-            Err(anyhow::Error::msg("Unrecognized subcommand"))
+        Some((name @ ("bloat-deps" | "bloat-time"), sm)) => {
+            let mode = if name == "bloat-deps" {
+                BloatMode::Deps
+            } else {
+                BloatMode::Time
+            };
+            eprintln!(
+                "warning: '{name}' is deprecated, use 'bloat --mode {mode}' instead"
+            );
+            run_bloat(config()?.bloat, sm, mode)
+        }
+        Some(("bloat-fns", sm)) => {
+            run_bloat(config()?.bloat, sm, BloatMode::Functions)
+        }
+        Some(("size-track", sm)) => {
+            let (history, last_n) = match sm.get_one::<usize>("report")
+            {
+                Some(last_n) => (
+                    SizeHistory::load(&bloat::history::history_path()?)?,
+                    *last_n,
+                ),
+                None => {
+                    let entries = size_track()?;
+                    let last_n = entries.len();
+                    (
+                        SizeHistory::load(
+                            &bloat::history::history_path()?,
+                        )?,
+                        last_n,
+                    )
+                }
+            };
+            if sm.get_flag("markdown") {
+                println!("{}", history.to_markdown(last_n));
+            } else {
+                println!("{}", history.table(last_n));
+            }
+            Ok(())
         }
+        Some((name, _)) => Err(AnyError::msg(unknown_task(&cli, name))),
+    };
+    let dispatch = || {
+        if let Some((name, sm)) = matches.subcommand() {
+            if let Some(task) = registry.custom_task(name) {
+                return (task.handler)(sm);
+            }
+            if !registry.has_builtin_tasks() {
+                return Err(AnyError::msg(unknown_task(&cli, name)));
+            }
+        }
+        builtin()
+    };
+    let started = Instant::now();
+    let result = if matches.get_flag("dry-run") {
+        runner::with_runner(Arc::new(runner::DryRunRunner), dispatch)
+    } else {
+        dispatch()
+    };
+    // Without a task, the picked task prints its own footer.
+    let task = match matches.subcommand() {
+        Some((name, _)) => Some(name),
+        None if matches.get_flag("ci-default") => Some("ci"),
+        None => None,
     };
-    res
+    if let Some(task) = task {
+        if !matches.get_flag("no-summary") && !matches.get_flag("quiet")
+        {
+            summary::print_footer(task, &result, started.elapsed());
+        }
+    }
+    result
+}
+
+/// Runs the `bloat` task in `mode`, with the settings of `builder` and the
+/// options of its subcommand `sm` on top.
+///
+/// # Errors
+///
+/// Returns an error if the time mode is given no package, or if the analysis
+/// fails.
+fn run_bloat(
+    mut builder: BloatBuilder,
+    sm: &ArgMatches,
+    mode: BloatMode,
+) -> AnyResult<()> {
+    if let Some(package) = sm.get_one::<String>("package") {
+        builder.package(package.clone());
+    }
+    if let Some(top) = cli_value::<usize>(sm, "top") {
+        builder.top(top);
+    }
+    if sm.get_flag("release") {
+        builder.release(true);
+    }
+    if let Some(profile) = sm.get_one::<String>("profile") {
+        builder.profile(profile.clone());
+    }
+    if let Some(target) = sm.get_one::<String>("target") {
+        builder.target(target.clone());
+    }
+    if mode == BloatMode::Time {
+        // The build times are only printed, so the report options have
+        // nothing to write or compare.
+        let report_flags =
+            ["json", "markdown", "baseline", "max-growth", "filter"];
+        if let Some(flag) =
+            report_flags.into_iter().find(|flag| sm.contains_id(flag))
+        {
+            return Err(AnyError::msg(format!(
+                "--{flag} does not apply to --mode time, which only prints the build times"
+            )));
+        }
+        return builder
+            .build()
+            .context("Failed to build Bloat configuration")?
+            .run_time();
+    }
+    if mode == BloatMode::Functions {
+        builder.functions(true);
+    }
+    if let Some(filter) = sm.get_one::<String>("filter") {
+        builder.filter(filter.clone());
+    }
+    if let Some(markdown) = sm.get_one::<String>("markdown") {
+        builder.markdown(PathBuf::from(markdown));
+    }
+    if let Some(json) = sm.get_one::<String>("json") {
+        builder.output_json(PathBuf::from(json));
+    }
+    if let Some(baseline) = sm.get_one::<String>("baseline") {
+        builder.baseline(PathBuf::from(baseline));
+    }
+    if let Some(limit) = sm.get_one::<DiffThreshold>("max-growth") {
+        builder.max_growth(*limit);
+    }
+    builder.run().map(|_| ())
 }
 
 /// The main entry point of the application.
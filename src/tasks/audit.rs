@@ -0,0 +1,549 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Security audits of the dependencies with `cargo audit`.
+//!
+//! [`audit`] runs `cargo audit --json`, turns the vulnerabilities and the
+//! warnings (unmaintained, unsound or yanked crates) into [`Finding`]s, and
+//! fails only for the vulnerabilities at or above a severity threshold. The
+//! severity is derived from the CVSS v3 vector of the advisory, like
+//! `cargo audit` does.
+
+use crate::ops::{
+    self,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, path::PathBuf, str::FromStr};
+
+/// The severity of a vulnerability, from its CVSS score.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// A score of 0.
+    None,
+    /// A score from 0.1 to 3.9.
+    #[default]
+    Low,
+    /// A score from 4.0 to 6.9.
+    Medium,
+    /// A score from 7.0 to 8.9.
+    High,
+    /// A score from 9.0 to 10.
+    Critical,
+}
+
+impl Severity {
+    /// Returns the severity of a CVSS base `score`.
+    pub fn from_score(score: f64) -> Self {
+        match score {
+            score if score >= 9.0 => Self::Critical,
+            score if score >= 7.0 => Self::High,
+            score if score >= 4.0 => Self::Medium,
+            score if score > 0.0 => Self::Low,
+            _ => Self::None,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+            Self::Critical => "critical",
+        })
+    }
+}
+
+impl FromStr for Severity {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "low" => Ok(Self::Low),
+            "medium" => Ok(Self::Medium),
+            "high" => Ok(Self::High),
+            "critical" => Ok(Self::Critical),
+            _ => Err(AnyError::msg(format!(
+                "Unknown severity '{value}', expected low, medium, high or critical"
+            ))),
+        }
+    }
+}
+
+/// Rounds `value` up to one decimal, as specified by CVSS v3.1.
+fn round_up(value: f64) -> f64 {
+    let scaled = (value * 100_000.0).round() as i64;
+    if scaled % 10_000 == 0 {
+        scaled as f64 / 100_000.0
+    } else {
+        ((scaled / 10_000) + 1) as f64 / 10.0
+    }
+}
+
+/// Returns the base score of a CVSS v3 `vector`, e.g.
+/// `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`, or `None` if a base metric
+/// is missing or invalid.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::audit::cvss_score;
+///
+/// let vector = "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H";
+/// assert_eq!(cvss_score(vector), Some(9.8));
+/// ```
+pub fn cvss_score(vector: &str) -> Option<f64> {
+    let metrics: BTreeMap<&str, &str> = vector
+        .split('/')
+        .filter_map(|metric| metric.split_once(':'))
+        .collect();
+    if !metrics.get("CVSS")?.starts_with('3') {
+        return None;
+    }
+    let changed = match *metrics.get("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector = match *metrics.get("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let complexity = match *metrics.get("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let privileges = match (*metrics.get("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let interaction = match *metrics.get("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    let impact_of = |metric: &str| match *metrics.get(metric)? {
+        "H" => Some(0.56),
+        "L" => Some(0.22),
+        "N" => Some(0.0),
+        _ => None,
+    };
+    let (c, i, a) = (impact_of("C")?, impact_of("I")?, impact_of("A")?);
+    let base = 1.0 - (1.0 - c) * (1.0 - i) * (1.0 - a);
+    let impact = if changed {
+        7.52 * (base - 0.029) - 3.25 * (base - 0.02_f64).powi(15)
+    } else {
+        6.42 * base
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability =
+        8.22 * attack_vector * complexity * privileges * interaction;
+    let total = if changed {
+        1.08 * (impact + exploitability)
+    } else {
+        impact + exploitability
+    };
+    Some(round_up(total.min(10.0)))
+}
+
+/// The kind of a finding of `cargo audit`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum FindingKind {
+    /// A vulnerability, which may fail the audit.
+    #[default]
+    Vulnerability,
+    /// A crate that is no longer maintained.
+    Unmaintained,
+    /// A crate with unsound code.
+    Unsound,
+    /// A crate version that was yanked from the registry.
+    Yanked,
+    /// Another informational advisory.
+    Notice,
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Vulnerability => "vulnerability",
+            Self::Unmaintained => "unmaintained",
+            Self::Unsound => "unsound",
+            Self::Yanked => "yanked",
+            Self::Notice => "notice",
+        })
+    }
+}
+
+/// A vulnerability or a warning reported by `cargo audit`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    /// The id of the advisory, e.g. `RUSTSEC-2020-0071`; empty for a yanked
+    /// crate, which has no advisory.
+    pub id: String,
+    /// The kind of the finding.
+    pub kind: FindingKind,
+    /// The affected package.
+    pub package: String,
+    /// The version of the package in the lock file.
+    pub version: String,
+    /// The title of the advisory.
+    pub title: String,
+    /// The CVSS base score of the advisory, if it has a CVSS vector.
+    pub score: Option<f64>,
+    /// The severity of the advisory, if it has a CVSS vector.
+    pub severity: Option<Severity>,
+    /// The version requirements that fix the advisory, e.g. `>=0.2.23`.
+    pub patched_versions: Vec<String>,
+}
+
+impl Finding {
+    /// Returns `true` if the finding fails an audit with the severity
+    /// `threshold`: a vulnerability at or above it, or without a score.
+    pub fn fails(&self, threshold: Severity) -> bool {
+        self.kind == FindingKind::Vulnerability
+            && self
+                .severity
+                .map_or(true, |severity| severity >= threshold)
+    }
+}
+
+/// The parts of the `cargo audit --json` output used by [`parse_audit`].
+#[derive(Deserialize)]
+struct Output {
+    #[serde(default)]
+    vulnerabilities: Vulnerabilities,
+    #[serde(default)]
+    warnings: BTreeMap<String, Vec<Entry>>,
+}
+
+/// The `vulnerabilities` of the `cargo audit --json` output.
+#[derive(Default, Deserialize)]
+struct Vulnerabilities {
+    #[serde(default)]
+    list: Vec<Entry>,
+}
+
+/// A vulnerability or a warning of the `cargo audit --json` output.
+#[derive(Deserialize)]
+struct Entry {
+    #[serde(default)]
+    advisory: Option<Advisory>,
+    #[serde(default)]
+    versions: Option<Versions>,
+    package: EntryPackage,
+}
+
+/// An advisory of the `cargo audit --json` output.
+#[derive(Deserialize)]
+struct Advisory {
+    id: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    cvss: Option<String>,
+}
+
+/// The versions of an advisory of the `cargo audit --json` output.
+#[derive(Deserialize)]
+struct Versions {
+    #[serde(default)]
+    patched: Vec<String>,
+}
+
+/// The affected package of the `cargo audit --json` output.
+#[derive(Deserialize)]
+struct EntryPackage {
+    name: String,
+    #[serde(default)]
+    version: String,
+}
+
+/// Returns the finding of a `cargo audit --json` `entry`.
+fn finding(kind: FindingKind, entry: Entry) -> Finding {
+    let score = entry
+        .advisory
+        .as_ref()
+        .and_then(|advisory| advisory.cvss.as_deref())
+        .and_then(cvss_score);
+    let (id, title) = entry
+        .advisory
+        .map(|advisory| (advisory.id, advisory.title))
+        .unwrap_or_default();
+    Finding {
+        id,
+        kind,
+        package: entry.package.name,
+        version: entry.package.version,
+        title,
+        score,
+        severity: score.map(Severity::from_score),
+        patched_versions: entry
+            .versions
+            .map(|versions| versions.patched)
+            .unwrap_or_default(),
+    }
+}
+
+/// Parses the output of `cargo audit --json` into its findings, the
+/// vulnerabilities first.
+///
+/// # Errors
+///
+/// Returns an error if the output is not valid `cargo audit` JSON.
+pub fn parse_audit(json: &str) -> AnyResult<Vec<Finding>> {
+    let output: Output = serde_json::from_str(json)
+        .context("Failed to parse the output of cargo audit")?;
+    let mut findings: Vec<Finding> = output
+        .vulnerabilities
+        .list
+        .into_iter()
+        .map(|entry| finding(FindingKind::Vulnerability, entry))
+        .collect();
+    for (kind, entries) in output.warnings {
+        let kind = match kind.as_str() {
+            "unmaintained" => FindingKind::Unmaintained,
+            "unsound" => FindingKind::Unsound,
+            "yanked" => FindingKind::Yanked,
+            _ => FindingKind::Notice,
+        };
+        findings.extend(
+            entries.into_iter().map(|entry| finding(kind, entry)),
+        );
+    }
+    Ok(findings)
+}
+
+/// The result of an audit.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuditReport {
+    /// The severity at or above which a vulnerability fails the audit.
+    pub threshold: Severity,
+    /// The findings that were not ignored.
+    pub findings: Vec<Finding>,
+    /// The findings that were ignored.
+    pub ignored: Vec<Finding>,
+}
+
+impl AuditReport {
+    /// Returns the findings failing the audit, see [`Finding::fails`].
+    pub fn failures(&self) -> impl Iterator<Item = &Finding> {
+        self.findings
+            .iter()
+            .filter(|finding| finding.fails(self.threshold))
+    }
+
+    /// Fails if a finding fails the audit.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the failing advisories.
+    pub fn check(&self) -> AnyResult<()> {
+        let failures: Vec<String> = self
+            .failures()
+            .map(|finding| {
+                format!("{} ({})", finding.id, finding.package)
+            })
+            .collect();
+        if failures.is_empty() {
+            return Ok(());
+        }
+        Err(AnyError::msg(format!(
+            "cargo audit found {} vulnerabilities at or above the {} severity: {}",
+            failures.len(),
+            self.threshold,
+            failures.join(", ")
+        )))
+    }
+}
+
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.findings.is_empty() {
+            write!(f, "No vulnerability or warning found")?;
+        }
+        for (index, finding) in self.findings.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            let severity = match (finding.severity, finding.score) {
+                (Some(severity), Some(score)) => {
+                    format!("{severity} {score:.1}")
+                }
+                _ => "unscored".to_string(),
+            };
+            let id = if finding.id.is_empty() {
+                "-"
+            } else {
+                &finding.id
+            };
+            write!(
+                f,
+                "{id} {} {}@{} [{severity}] {}",
+                finding.kind,
+                finding.package,
+                finding.version,
+                finding.title
+            )?;
+            if !finding.patched_versions.is_empty() {
+                write!(
+                    f,
+                    " (patched: {})",
+                    finding.patched_versions.join(", ")
+                )?;
+            }
+        }
+        if !self.ignored.is_empty() {
+            let ids: Vec<&str> = self
+                .ignored
+                .iter()
+                .map(|finding| finding.id.as_str())
+                .collect();
+            write!(f, "\nIgnored: {}", ids.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// The options of an audit.
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct AuditOptions {
+    /// The advisories to ignore, e.g. `RUSTSEC-2020-0071`.
+    ///
+    /// By default, this is empty.
+    #[builder(default)]
+    #[serde(default)]
+    pub ignore: Vec<String>,
+
+    /// The severity at or above which a vulnerability fails the audit.
+    /// Vulnerabilities without a CVSS score always fail it, and warnings
+    /// never do.
+    ///
+    /// By default, this is set to [`Severity::Low`].
+    #[builder(default)]
+    #[serde(default)]
+    pub severity: Severity,
+
+    /// Saves the [`AuditReport`] as JSON to this file.
+    ///
+    /// By default, this is set to `None`.
+    #[builder(default)]
+    #[serde(default)]
+    pub json: Option<PathBuf>,
+}
+
+/// Returns the report of the `findings` of an audit with `opts`, the ignored
+/// advisories set apart.
+pub fn report(
+    findings: Vec<Finding>,
+    opts: &AuditOptions,
+) -> AuditReport {
+    let (ignored, findings) = findings
+        .into_iter()
+        .partition(|finding| opts.ignore.contains(&finding.id));
+    AuditReport {
+        threshold: opts.severity,
+        findings,
+        ignored,
+    }
+}
+
+/// Audits the dependencies of the workspace with `cargo audit`, installing it
+/// if needed, prints the findings, and saves the report if configured.
+///
+/// # Returns
+///
+/// The report of the audit.
+///
+/// # Errors
+///
+/// Returns an error if cargo-audit cannot be installed or run, if its output
+/// cannot be parsed, if the report cannot be saved, or if a vulnerability is at
+/// or above the severity threshold.
+pub fn audit(opts: AuditOptions) -> AnyResult<AuditReport> {
+    let _ = tools::ensure_tool_installed(
+        "cargo-audit",
+        &["cargo-audit", "--locked"],
+    )?;
+    let command = CommandSpec::new("cargo").args(["audit", "--json"]);
+    // cargo audit exits with 1 when it finds vulnerabilities, so the status
+    // is only an error when there is no report to parse.
+    let output = runner::current()
+        .execute(&command.clone().captured())
+        .with_context(|| format!("Failed to spawn '{command}'"))?;
+    if runner::is_dry_run() {
+        return Ok(report(Vec::new(), &opts));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let findings = parse_audit(&stdout).map_err(|err| {
+        err.context(format!(
+            "'{command}' exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    })?;
+    let report = report(findings, &opts);
+    println!("{report}");
+    if let Some(path) = &opts.json {
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(AnyError::new)?;
+        ops::write_atomic(path, json).with_context(|| {
+            format!("Failed to write {}", path.display())
+        })?;
+    }
+    report.check()?;
+    Ok(report)
+}
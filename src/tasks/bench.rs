@@ -0,0 +1,422 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Criterion benchmarks, with baselines and a regression threshold.
+//!
+//! [`Bench`] runs `cargo bench` with the criterion baseline flags, parses the
+//! estimates and the changes criterion prints, and fails when a benchmark got
+//! slower than the configured percentage. The results are printed as a table
+//! and can be saved as JSON for CI artifacts.
+
+use crate::ops::{
+    self,
+    runner::{self, CommandSpec},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// What criterion concluded about the change of a benchmark.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum ChangeVerdict {
+    /// `Performance has regressed.`
+    Regressed,
+    /// `Performance has improved.`
+    Improved,
+    /// `Change within noise threshold.`
+    WithinNoise,
+    /// `No change in performance detected.`, or no verdict printed.
+    #[default]
+    NoChange,
+}
+
+impl fmt::Display for ChangeVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Regressed => "regressed",
+            Self::Improved => "improved",
+            Self::WithinNoise => "noise",
+            Self::NoChange => "no change",
+        })
+    }
+}
+
+/// The change of the time of a benchmark against the baseline, in percent.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BenchChange {
+    /// The lower bound of the confidence interval.
+    pub lower: f64,
+    /// The estimate of the change; positive when the benchmark got slower.
+    pub estimate: f64,
+    /// The upper bound of the confidence interval.
+    pub upper: f64,
+    /// What criterion concluded.
+    pub verdict: ChangeVerdict,
+}
+
+/// A benchmark reported by criterion.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BenchResult {
+    /// The id of the benchmark, e.g. `parse/large`.
+    pub name: String,
+    /// The estimate of the time, e.g. `26.251 µs`.
+    pub time: String,
+    /// The change against the baseline, if there was one to compare to.
+    pub change: Option<BenchChange>,
+}
+
+/// Returns the values between the brackets of `line`, e.g. the three of
+/// `time:   [26.029 µs 26.251 µs 26.505 µs]`.
+fn bracketed(line: &str) -> Option<&str> {
+    let start = line.find('[')?;
+    let end = start + line[start..].find(']')?;
+    Some(&line[start + 1..end])
+}
+
+/// Parses the three percentages of a criterion `change:` interval.
+fn parse_change(values: &str) -> Option<BenchChange> {
+    let percents: Vec<f64> = values
+        .split_whitespace()
+        .map(|value| value.trim_end_matches('%').parse::<f64>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match percents[..] {
+        [lower, estimate, upper] => Some(BenchChange {
+            lower,
+            estimate,
+            upper,
+            verdict: ChangeVerdict::NoChange,
+        }),
+        _ => None,
+    }
+}
+
+/// Parses the output of criterion benchmarks into their results, in order.
+///
+/// Each benchmark starts with its id followed by a `time:` interval, on the
+/// same line or the next one for long ids. The `change:` interval and the
+/// verdict follow when there is a baseline to compare to. Throughput changes
+/// are ignored, only the time is compared.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::bench::{parse_criterion_output, ChangeVerdict};
+///
+/// let output = "\
+/// fib 20                  time:   [26.029 µs 26.251 µs 26.505 µs]
+///                         change: [+4.1% +6.5% +8.9%] (p = 0.00 < 0.05)
+///                         Performance has regressed.
+/// ";
+/// let results = parse_criterion_output(output);
+/// assert_eq!(results[0].name, "fib 20");
+/// assert_eq!(results[0].time, "26.251 µs");
+/// let change = results[0].change.as_ref().unwrap();
+/// assert_eq!(change.estimate, 6.5);
+/// assert_eq!(change.verdict, ChangeVerdict::Regressed);
+/// ```
+pub fn parse_criterion_output(output: &str) -> Vec<BenchResult> {
+    let mut results: Vec<BenchResult> = Vec::new();
+    let mut pending_name: Option<String> = None;
+    let mut in_change = false;
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("Benchmarking ") {
+            continue;
+        }
+        if let Some(index) = line.find("time:") {
+            let values = bracketed(&line[index..]);
+            if in_change {
+                if let (Some(result), Some(change)) =
+                    (results.last_mut(), values.and_then(parse_change))
+                {
+                    result.change = Some(change);
+                }
+                continue;
+            }
+            let name = line[..index].trim();
+            let name = if name.is_empty() {
+                pending_name.take().unwrap_or_default()
+            } else {
+                name.to_string()
+            };
+            let estimate = values
+                .map(|values| {
+                    let parts: Vec<&str> =
+                        values.split_whitespace().collect();
+                    match parts[..] {
+                        [_, _, value, unit, _, _] => {
+                            format!("{value} {unit}")
+                        }
+                        _ => values.to_string(),
+                    }
+                })
+                .unwrap_or_default();
+            results.push(BenchResult {
+                name,
+                time: estimate,
+                change: None,
+            });
+            pending_name = None;
+            continue;
+        }
+        if let Some(rest) = trimmed.strip_prefix("change:") {
+            in_change = bracketed(rest).is_none();
+            if let (Some(result), Some(change)) = (
+                results.last_mut(),
+                bracketed(rest).and_then(parse_change),
+            ) {
+                result.change = Some(change);
+            }
+            continue;
+        }
+        let verdict = if trimmed
+            .starts_with("Performance has regressed")
+        {
+            Some(ChangeVerdict::Regressed)
+        } else if trimmed.starts_with("Performance has improved") {
+            Some(ChangeVerdict::Improved)
+        } else if trimmed.starts_with("Change within noise threshold") {
+            Some(ChangeVerdict::WithinNoise)
+        } else if trimmed.starts_with("No change in performance") {
+            Some(ChangeVerdict::NoChange)
+        } else {
+            None
+        };
+        if let Some(verdict) = verdict {
+            in_change = false;
+            if let Some(change) = results
+                .last_mut()
+                .and_then(|result| result.change.as_mut())
+            {
+                change.verdict = verdict;
+            }
+            continue;
+        }
+        if in_change || trimmed.starts_with("thrpt:") {
+            continue;
+        }
+        // Anything else at the start of a line is the id of a benchmark whose
+        // results are on the next line, or unrelated output.
+        if !line.starts_with(char::is_whitespace)
+            && !trimmed.contains(':')
+        {
+            pending_name = Some(trimmed.to_string());
+        }
+    }
+    results
+}
+
+/// The results of a benchmark run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BenchSummary {
+    /// The benchmarks, in the order they ran.
+    pub results: Vec<BenchResult>,
+}
+
+impl BenchSummary {
+    /// Returns the benchmarks whose estimated change exceeds `max_regression`
+    /// percent.
+    pub fn regressions(
+        &self,
+        max_regression: f64,
+    ) -> impl Iterator<Item = &BenchResult> {
+        self.results.iter().filter(move |result| {
+            result
+                .change
+                .as_ref()
+                .is_some_and(|change| change.estimate > max_regression)
+        })
+    }
+
+    /// Fails if a benchmark got slower than `max_regression` percent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the regressed benchmarks and their changes.
+    pub fn check(&self, max_regression: f64) -> AnyResult<()> {
+        let regressions: Vec<String> = self
+            .regressions(max_regression)
+            .filter_map(|result| {
+                let change = result.change.as_ref()?;
+                Some(format!(
+                    "{} ({:+.2}%)",
+                    result.name, change.estimate
+                ))
+            })
+            .collect();
+        if regressions.is_empty() {
+            return Ok(());
+        }
+        Err(AnyError::msg(format!(
+            "{} of {} benchmarks regressed by more than {max_regression}%: {}",
+            regressions.len(),
+            self.results.len(),
+            regressions.join(", ")
+        )))
+    }
+
+    /// Saves the summary as JSON to `path`, creating its directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> AnyResult<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(AnyError::new)?;
+        ops::write_atomic(path, json).with_context(|| {
+            format!("Failed to write {}", path.display())
+        })
+    }
+}
+
+impl fmt::Display for BenchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .results
+            .iter()
+            .map(|result| result.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("benchmark".len());
+        let time_width = self
+            .results
+            .iter()
+            .map(|result| result.time.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("time".len());
+        write!(
+            f,
+            "{:<width$}  {:<time_width$}  {:<9}  verdict",
+            "benchmark", "time", "change"
+        )?;
+        for result in &self.results {
+            let (change, verdict) = match &result.change {
+                Some(change) => (
+                    format!("{:+.2}%", change.estimate),
+                    change.verdict.to_string(),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            write!(
+                f,
+                "\n{:<width$}  {:<time_width$}  {change:<9}  {verdict}",
+                result.name, result.time
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The configuration of a benchmark run.
+#[derive(
+    Builder, Clone, Debug, Default, PartialEq, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct Bench {
+    /// The bench targets to run, as passed to `cargo bench --bench`.
+    ///
+    /// By default, this is empty and every bench target runs.
+    #[builder(default)]
+    #[serde(default)]
+    pub benches: Vec<String>,
+
+    /// Saves the results as the criterion baseline with this name.
+    ///
+    /// By default, this is set to `None`.
+    #[builder(default)]
+    #[serde(default)]
+    pub save_baseline: Option<String>,
+
+    /// Compares the results with the criterion baseline with this name instead
+    /// of the previous run.
+    ///
+    /// By default, this is set to `None`.
+    #[builder(default)]
+    #[serde(default)]
+    pub baseline: Option<String>,
+
+    /// Fails when a benchmark got slower than this percentage.
+    ///
+    /// By default, this is set to `None`, and regressions are only reported.
+    #[builder(default)]
+    #[serde(default)]
+    pub max_regression: Option<f64>,
+
+    /// Saves the results as JSON to this file.
+    ///
+    /// By default, this is set to `None`.
+    #[builder(default)]
+    #[serde(default)]
+    pub json: Option<PathBuf>,
+}
+
+impl Bench {
+    /// Returns the `cargo bench` invocation for the configuration.
+    pub fn command(&self) -> CommandSpec {
+        let mut command = CommandSpec::new("cargo").arg("bench");
+        for bench in &self.benches {
+            command = command.args(["--bench", bench]);
+        }
+        let mut criterion = Vec::new();
+        if let Some(name) = &self.save_baseline {
+            criterion.extend(["--save-baseline", name]);
+        }
+        if let Some(name) = &self.baseline {
+            criterion.extend(["--baseline", name]);
+        }
+        if !criterion.is_empty() {
+            command = command.arg("--").args(criterion);
+        }
+        command
+    }
+
+    /// Runs the benchmarks, prints their results, saves them if configured,
+    /// and checks them against the regression threshold.
+    ///
+    /// # Returns
+    ///
+    /// The results of the benchmarks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `cargo bench` fails, if the results cannot be
+    /// saved, or if a benchmark regressed beyond
+    /// [`max_regression`](Bench::max_regression).
+    pub fn run(&self) -> AnyResult<BenchSummary> {
+        let command = self.command();
+        let output = runner::capture(&command).with_context(|| {
+            format!("Failed to execute '{command}'")
+        })?;
+        let summary = BenchSummary {
+            results: parse_criterion_output(&output),
+        };
+        if summary.results.is_empty() {
+            println!("No criterion benchmark results in the output");
+        } else {
+            println!("{summary}");
+        }
+        if let Some(path) = &self.json {
+            summary.save(path)?;
+        }
+        if let Some(max_regression) = self.max_regression {
+            summary.check(max_regression)?;
+        }
+        Ok(summary)
+    }
+}
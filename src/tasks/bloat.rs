@@ -1,15 +1,1109 @@
 // Copyright © 2023 xtasks. All rights reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use anyhow::{Context, Result as AnyResult};
-use duct::cmd;
+use crate::loggers::{task_log, LogLevel};
+use crate::ops::{
+    self,
+    metadata::{self, BinaryTarget},
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use dtt::DateTime;
-use rlg::{macro_log, LogFormat, LogLevel};
-use vrd::Random;
+/// The `diff` module compares two reports to catch size regressions.
+pub mod diff;
+/// The `history` module records the size of the binaries across commits.
+pub mod history;
+
+pub use diff::{bloat_diff, BloatDiff, CrateDelta, DiffThreshold};
+pub use history::{
+    size_history_report, size_track, SizeEntry, SizeHistory,
+};
+
+/// Where the baseline report is kept unless another path is given.
+pub const DEFAULT_BASELINE: &str = "target/xtask/bloat.json";
+
+/// How many entries `cargo bloat` lists unless told otherwise.
+pub const DEFAULT_TOP: usize = 20;
+
+/// What the `bloat` task analyses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BloatMode {
+    /// The crates taking the most space in the binaries.
+    #[default]
+    Deps,
+    /// The crates taking the longest to build.
+    Time,
+    /// The largest functions of the binaries.
+    Functions,
+}
+
+impl BloatMode {
+    /// Every mode, in the order of the help.
+    pub const ALL: [Self; 3] =
+        [Self::Deps, Self::Time, Self::Functions];
+}
+
+impl fmt::Display for BloatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Deps => "deps",
+            Self::Time => "time",
+            Self::Functions => "functions",
+        })
+    }
+}
+
+impl FromStr for BloatMode {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.to_string() == value)
+            .ok_or_else(|| {
+                AnyError::msg(format!(
+                    "Unknown bloat mode '{value}', expected one of: deps, time, functions"
+                ))
+            })
+    }
+}
+
+/// A row of a `cargo bloat` report: a crate, or a function, and what it adds to
+/// the binary.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BloatEntry {
+    /// The name of the crate, or the demangled name of the function.
+    pub name: String,
+    /// The size of the code of the crate or function, in bytes.
+    pub size: u64,
+    /// The share of the binary taken by the crate or function, as a
+    /// percentage.
+    pub percent: f32,
+}
+
+/// The results of a `cargo bloat` run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BloatReport {
+    /// The size of the whole binary, in bytes.
+    pub total_size: u64,
+    /// The rows of the report, largest first.
+    pub entries: Vec<BloatEntry>,
+    /// The cargo profile the binary was built with, e.g. `release`, or `None`
+    /// if the report does not say.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// The target triple the binary was built for, or `None` for the host.
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+/// The report printed by `cargo bloat --message-format json`.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct JsonReport {
+    file_size: u64,
+    #[serde(default)]
+    crates: Vec<JsonEntry>,
+    #[serde(default)]
+    functions: Vec<JsonEntry>,
+}
+
+/// A crate or function of the JSON report.
+#[derive(Deserialize)]
+struct JsonEntry {
+    name: String,
+    size: u64,
+}
+
+impl BloatReport {
+    /// Parses the output of `cargo bloat`, either the JSON printed with
+    /// `--message-format json` or the default table, listing crates with
+    /// `--crates` or functions otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output is neither a JSON report nor a table
+    /// ending with the size of the file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::BloatReport;
+    ///
+    /// let report = BloatReport::parse(
+    ///     r#"{"file-size":2048,"text-section-size":1024,"crates":[{"name":"std","size":512}]}"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(report.total_size, 2048);
+    /// assert_eq!(report.entries[0].percent, 25.0);
+    /// ```
+    pub fn parse(output: &str) -> AnyResult<Self> {
+        let output = output.trim();
+        if output.starts_with('{') {
+            Self::parse_json(output)
+        } else {
+            Self::parse_table(output)
+        }
+    }
+
+    /// Reads a report saved with [`BloatReport::save`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a report.
+    pub fn load(path: &Path) -> AnyResult<Self> {
+        let json = fs::read_to_string(path).with_context(|| {
+            format!("Failed to read {}", path.display())
+        })?;
+        serde_json::from_str(&json).with_context(|| {
+            format!("Failed to parse bloat report {}", path.display())
+        })
+    }
+
+    /// Saves the report as JSON to `path`, creating its directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> AnyResult<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(AnyError::new)?;
+        ops::write_atomic(path, json).with_context(|| {
+            format!("Failed to write {}", path.display())
+        })
+    }
+
+    /// Combines the reports of several binaries into one, adding up the sizes
+    /// of the crates they share.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::BloatReport;
+    ///
+    /// let a = BloatReport::parse(
+    ///     r#"{"file-size":1000,"crates":[{"name":"std","size":300}]}"#,
+    /// )
+    /// .unwrap();
+    /// let combined = BloatReport::combine([&a, &a]);
+    /// assert_eq!(combined.total_size, 2000);
+    /// assert_eq!(combined.entries[0].size, 600);
+    /// assert_eq!(combined.entries[0].percent, 30.0);
+    /// ```
+    pub fn combine<'a, I>(reports: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Self>,
+    {
+        let mut total_size = 0;
+        let mut sizes: BTreeMap<&str, u64> = BTreeMap::new();
+        let mut config = None;
+        for report in reports {
+            let _ = config.get_or_insert_with(|| {
+                (report.profile.clone(), report.target.clone())
+            });
+            total_size += report.total_size;
+            for entry in &report.entries {
+                *sizes.entry(&entry.name).or_default() += entry.size;
+            }
+        }
+        let mut entries: Vec<BloatEntry> = sizes
+            .into_iter()
+            .map(|(name, size)| BloatEntry {
+                name: name.to_string(),
+                size,
+                percent: percent_of(size, total_size),
+            })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.size));
+        let (profile, target) = config.unwrap_or_default();
+        Self {
+            total_size,
+            entries,
+            profile,
+            target,
+        }
+    }
+
+    /// Returns the build the report measured, e.g. `the 'release' profile for
+    /// wasm32-unknown-unknown`.
+    pub fn config(&self) -> String {
+        format!(
+            "the '{}' profile for {}",
+            self.profile.as_deref().unwrap_or("unknown"),
+            self.target.as_deref().unwrap_or("the host")
+        )
+    }
+
+    /// Fails if the report and `baseline` measured different profiles or
+    /// targets, as their sizes cannot be compared. A report that does not say
+    /// which profile it measured matches any profile.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming both builds when they differ.
+    pub fn ensure_comparable(&self, baseline: &Self) -> AnyResult<()> {
+        let profiles_differ = matches!(
+            (&self.profile, &baseline.profile),
+            (Some(current), Some(before)) if current != before
+        );
+        if profiles_differ || self.target != baseline.target {
+            return Err(AnyError::msg(format!(
+                "Cannot compare a report of {} to a baseline of {}, measure both with the same --profile and --target",
+                self.config(),
+                baseline.config()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Parses the JSON printed with `--message-format json`.
+    fn parse_json(json: &str) -> AnyResult<Self> {
+        let report: JsonReport = serde_json::from_str(json)
+            .context("Failed to parse the cargo-bloat JSON report")?;
+        let total_size = report.file_size;
+        Ok(Self {
+            total_size,
+            entries: report
+                .crates
+                .into_iter()
+                .chain(report.functions)
+                .map(|entry| BloatEntry {
+                    percent: percent_of(entry.size, total_size),
+                    name: entry.name,
+                    size: entry.size,
+                })
+                .collect(),
+            ..Self::default()
+        })
+    }
+
+    /// Parses the default table, whose rows read `File% .text% Size Crate`, or
+    /// `File% .text% Size Crate Name` for functions.
+    fn parse_table(table: &str) -> AnyResult<Self> {
+        let mut total_size = None;
+        let mut entries = Vec::new();
+        let mut functions = false;
+        for line in table.lines() {
+            let mut columns = line.split_whitespace();
+            if line.trim_start().starts_with("File ") {
+                functions = columns.any(|column| column == "Name");
+                continue;
+            }
+            let (Some(file), Some(_text), Some(size)) =
+                (columns.next(), columns.next(), columns.next())
+            else {
+                continue;
+            };
+            let (Some(percent), Some(size)) =
+                (parse_percent(file), parse_size(size))
+            else {
+                // The header, or a line printed around the table.
+                continue;
+            };
+            let name = columns.collect::<Vec<_>>().join(" ");
+            if let Some(file_size) = name
+                .strip_prefix(".text section size, the file size is ")
+            {
+                total_size = parse_size(file_size);
+            } else if !(name.starts_with("And ")
+                && name.ends_with("to show more."))
+            {
+                // Functions are listed after their crate.
+                let name = if functions {
+                    name.split_once(' ')
+                        .map_or(name.as_str(), |(_, function)| function)
+                        .to_string()
+                } else {
+                    name
+                };
+                entries.push(BloatEntry {
+                    name,
+                    size,
+                    percent,
+                });
+            }
+        }
+        let total_size = total_size.ok_or_else(|| {
+            AnyError::msg(
+                "Failed to parse the cargo-bloat report: no file size",
+            )
+        })?;
+        Ok(Self {
+            total_size,
+            entries,
+            ..Self::default()
+        })
+    }
+}
+
+impl BloatReport {
+    /// Returns the report with only the entries whose name starts with `prefix`,
+    /// e.g. `my_crate::` to keep the functions of a crate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::BloatReport;
+    ///
+    /// let report = BloatReport::parse(
+    ///     r#"{"file-size":100,"functions":[{"crate":"std","name":"std::rt::lang_start","size":10},{"crate":"app","name":"app::main","size":5}]}"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(report.filtered("app::").entries[0].name, "app::main");
+    /// ```
+    #[must_use]
+    pub fn filtered(&self, prefix: &str) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter(|entry| entry.name.starts_with(prefix))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the report as a table whose name column is titled `heading`,
+    /// e.g. `Crate` or `Function`.
+    pub fn table(&self, heading: &str) -> String {
+        let mut table =
+            format!("{:>6} {:>9} {heading}\n", "File", "Size");
+        for entry in &self.entries {
+            table.push_str(&format!(
+                "{:>5.1}% {:>9} {}\n",
+                entry.percent,
+                format_size(entry.size),
+                entry.name
+            ));
+        }
+        table.push_str(&format!(
+            "{:>6} {:>9} Total",
+            "",
+            format_size(self.total_size)
+        ));
+        table
+    }
+
+    /// Renders the `top` largest entries as a GitHub-flavored Markdown table,
+    /// e.g. for a pull request comment. The other entries are summed up in one
+    /// row.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::BloatReport;
+    ///
+    /// let report = BloatReport::parse(
+    ///     r#"{"file-size":4096,"crates":[{"name":"std","size":1024}]}"#,
+    /// )
+    /// .unwrap();
+    /// assert!(report.to_markdown(10).contains("| std | 1.0KiB | 25.0% |"));
+    /// ```
+    pub fn to_markdown(&self, top: usize) -> String {
+        self.markdown_table("Crate", top)
+    }
+
+    /// Renders the `top` largest entries as a Markdown table whose name column
+    /// is titled `heading`.
+    fn markdown_table(&self, heading: &str, top: usize) -> String {
+        let mut markdown = format!(
+            "| {heading} | Size | File % |\n| --- | ---: | ---: |"
+        );
+        for entry in self.entries.iter().take(top) {
+            markdown.push_str(&format!(
+                "\n| {} | {} | {:.1}% |",
+                // Pipes would end the cell, e.g. in closure names.
+                entry.name.replace('|', "\\|"),
+                format_size(entry.size),
+                entry.percent
+            ));
+        }
+        let others = &self.entries[top.min(self.entries.len())..];
+        if !others.is_empty() {
+            let size = others.iter().map(|entry| entry.size).sum();
+            markdown.push_str(&format!(
+                "\n| *{} more* | {} | {:.1}% |",
+                others.len(),
+                format_size(size),
+                percent_of(size, self.total_size)
+            ));
+        }
+        markdown.push_str(&format!(
+            "\n| **Total** | **{}** | |",
+            format_size(self.total_size)
+        ));
+        markdown
+    }
+}
+
+impl fmt::Display for BloatReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.table("Crate"))
+    }
+}
+
+/// Parses a size printed by `cargo bloat`, e.g. `512B`, `213.6KiB` or
+/// `1.2MiB`, into bytes.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::bloat::parse_size;
+///
+/// assert_eq!(parse_size("512B"), Some(512));
+/// assert_eq!(parse_size("1.5KiB"), Some(1536));
+/// assert_eq!(parse_size("1.2MiB"), Some(1_258_291));
+/// assert_eq!(parse_size("Size"), None);
+/// ```
+pub fn parse_size(size: &str) -> Option<u64> {
+    let digits = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (value, unit) = size.split_at(digits);
+    let multiplier: u64 = match unit {
+        "B" | "" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+    let value: f64 = value.parse().ok()?;
+    Some((value * multiplier as f64) as u64)
+}
+
+/// Formats a size in bytes the way `cargo bloat` does, e.g. `213.6KiB`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::bloat::format_size;
+///
+/// assert_eq!(format_size(512), "512B");
+/// assert_eq!(format_size(1_258_291), "1.2MiB");
+/// ```
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1 << 10 {
+        return format!("{bytes}B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1}{}", UNITS[unit])
+}
+
+/// Returns `size` as a percentage of `total_size`.
+fn percent_of(size: u64, total_size: u64) -> f32 {
+    if total_size == 0 {
+        0.0
+    } else {
+        (size as f64 * 100.0 / total_size as f64) as f32
+    }
+}
+
+/// Parses a percentage column of the table, e.g. `7.6%`.
+fn parse_percent(column: &str) -> Option<f32> {
+    column.strip_suffix('%')?.parse().ok()
+}
+
+/// Represents the configuration for analysing the size of a package with
+/// `cargo bloat`.
+#[derive(Builder, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[builder(setter(into), derive(Debug, Deserialize))]
+#[builder_struct_attr(serde(deny_unknown_fields))]
+pub struct Bloat {
+    /// The package to analyse. By default, this is `None`, and every binary of
+    /// the workspace is analysed.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default)]
+    pub package: Option<String>,
+
+    /// How many crates are listed, the largest first; the others are left out of
+    /// the report. By default, this is `None`, and cargo-bloat's default of
+    /// [`DEFAULT_TOP`] applies.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub top: Option<usize>,
+
+    /// Analyses the release build instead of the debug one. By default, this is
+    /// set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub release: bool,
+
+    /// The cargo profile to build, e.g. `bench` or a custom profile. It takes
+    /// precedence over [`release`](Self::release). By default, this is `None`.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub profile: Option<String>,
+
+    /// The target triple to build for, e.g. `wasm32-unknown-unknown`; it must
+    /// be installed with rustup. By default, this is `None`, and the host is
+    /// analysed.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub target: Option<String>,
+
+    /// The cargo features the package is built with. By default, this is empty,
+    /// and the default features are used.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub features: Vec<String>,
+
+    /// Writes the report as JSON to this file, e.g. to keep it as a CI artifact.
+    /// By default, this is `None`.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub output_json: Option<PathBuf>,
+
+    /// A report of a previous run, e.g. [`DEFAULT_BASELINE`], the current report
+    /// is compared to. When the file does not exist yet, the current report is
+    /// written there instead. By default, this is `None`.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub baseline: Option<PathBuf>,
+
+    /// How much the binary may grow compared to the baseline. By default, it
+    /// may grow by any amount.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub max_growth: DiffThreshold,
+
+    /// Lists the largest functions instead of the crates. By default, this is
+    /// set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub functions: bool,
+
+    /// Keeps only the entries whose name starts with this prefix, e.g.
+    /// `my_crate::` to see the functions of a crate. By default, this is `None`.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub filter: Option<String>,
+
+    /// Writes the report, and its comparison to the baseline if any, as
+    /// Markdown to this file, e.g. for a pull request comment. The file is left
+    /// untouched when its contents do not change. By default, this is `None`.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub markdown: Option<PathBuf>,
+
+    /// Installs cargo-bloat, and adds the [`target`](Self::target) with rustup,
+    /// when they are missing. Otherwise, a missing one is an error naming the
+    /// command that installs it. By default, this is set to `true`; the
+    /// `XTASKS_NO_AUTO_INSTALL` environment variable turns it off.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_auto_install")]
+    pub auto_install: bool,
+}
+
+/// Returns whether a missing cargo-bloat is installed when not configured.
+const fn default_auto_install() -> bool {
+    true
+}
+
+impl Default for Bloat {
+    /// Returns the configuration built from the builder defaults, so both
+    /// describe the same run.
+    fn default() -> Self {
+        BloatBuilder::default()
+            .build()
+            .expect("the builder defaults are a valid configuration")
+    }
+}
+
+impl Bloat {
+    /// Returns the `cargo bloat` invocation reporting the crates of the package
+    /// as JSON.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::BloatBuilder;
+    ///
+    /// let bloat = BloatBuilder::default()
+    ///     .package("xtask")
+    ///     .top(Some(5))
+    ///     .release(true)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     bloat.command().to_string(),
+    ///     "cargo bloat -p xtask --crates --release -n 5 --message-format json"
+    /// );
+    /// ```
+    pub fn command(&self) -> CommandSpec {
+        self.command_for(self.package.as_deref(), None)
+    }
+
+    /// Returns the `cargo bloat --time` invocation reporting how long the crates
+    /// of the package take to build.
+    ///
+    /// The profile, target, features and [`top`](Self::top) apply as for the
+    /// size analysis; the report is printed as a table.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::BloatBuilder;
+    ///
+    /// let bloat = BloatBuilder::default()
+    ///     .package("app")
+    ///     .release(true)
+    ///     .top(Some(5))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     bloat.time_command().to_string(),
+    ///     "cargo bloat -p app --time --release -n 5"
+    /// );
+    /// ```
+    pub fn time_command(&self) -> CommandSpec {
+        let mut command = CommandSpec::new("cargo").arg("bloat");
+        if let Some(package) = &self.package {
+            command = command.args(["-p", package]);
+        }
+        command = command.arg("--time");
+        if let Some(profile) = &self.profile {
+            command = command.args(["--profile", profile]);
+        } else if self.release {
+            command = command.arg("--release");
+        }
+        if let Some(target) = &self.target {
+            command = command.args(["--target", target]);
+        }
+        if let Some(top) = self.top {
+            command = command.args(["-n".to_string(), top.to_string()]);
+        }
+        if !self.features.is_empty() {
+            command = command.args([
+                "--features".to_string(),
+                self.features.join(","),
+            ]);
+        }
+        command
+    }
+
+    /// Runs `cargo bloat --time` for the package and prints how long its crates
+    /// take to build.
+    ///
+    /// The report options, [`output_json`](Self::output_json),
+    /// [`markdown`](Self::markdown) and [`baseline`](Self::baseline), only apply
+    /// to the size analysis and are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no package is set, if the target is missing and cannot
+    /// be added, if the package is not a member of the workspace, if cargo-bloat
+    /// is missing and cannot be installed, or if `cargo bloat` fails.
+    pub fn run_time(&self) -> AnyResult<()> {
+        let Some(package) = &self.package else {
+            return Err(AnyError::msg(
+                "The build time analysis needs a package, please provide a package with -p",
+            ));
+        };
+        task_log(
+            LogLevel::INFO,
+            "Time Analysis",
+            "Starting build time analysis",
+        );
+        if let Some(target) = &self.target {
+            let _ = tools::ensure_target(target, self.auto_install)
+                .with_context(|| {
+                    format!("Cannot analyse the build of {target}")
+                })?;
+        }
+        metadata::ensure_workspace_members(std::slice::from_ref(
+            package,
+        ))?;
+        ensure_cargo_bloat(self.auto_install)?;
+        let command = self.time_command();
+        let _ = runner::run(&command)
+            .map_err(|err| {
+                task_log(
+                    LogLevel::ERROR,
+                    "Time Analysis",
+                    "Build time analysis failed",
+                );
+                err
+            })
+            .with_context(|| {
+                format!(
+                    "Failed to execute 'cargo bloat' for build time analysis on package '{package}'"
+                )
+            })?;
+        task_log(
+            LogLevel::INFO,
+            "Time Analysis",
+            "Build time analysis completed",
+        );
+        Ok(())
+    }
+
+    /// Returns the `cargo bloat` invocation for `bin` of `package`.
+    fn command_for(
+        &self,
+        package: Option<&str>,
+        bin: Option<&str>,
+    ) -> CommandSpec {
+        let mut command = CommandSpec::new("cargo").arg("bloat");
+        if let Some(package) = package {
+            command = command.args(["-p", package]);
+        }
+        if let Some(bin) = bin {
+            command = command.args(["--bin", bin]);
+        }
+        if !self.functions {
+            command = command.arg("--crates");
+        }
+        if let Some(profile) = &self.profile {
+            command = command.args(["--profile", profile]);
+        } else if self.release {
+            command = command.arg("--release");
+        }
+        if let Some(target) = &self.target {
+            command = command.args(["--target", target]);
+        }
+        if let Some(top) = self.top {
+            command = command.args(["-n".to_string(), top.to_string()]);
+        }
+        if !self.features.is_empty() {
+            command = command.args([
+                "--features".to_string(),
+                self.features.join(","),
+            ]);
+        }
+        command.args(["--message-format", "json"])
+    }
+
+    /// Runs `cargo bloat` and returns its report, labelled with what was
+    /// analysed: the package, or each binary of the workspace when no package is
+    /// set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the target is missing and cannot be added, if the
+    /// package is not a member of the workspace, if the workspace has no binary,
+    /// if cargo-bloat is missing and cannot be installed, if `cargo bloat` fails,
+    /// or if its report cannot be parsed.
+    pub fn reports(&self) -> AnyResult<Vec<(String, BloatReport)>> {
+        if let Some(target) = &self.target {
+            let _ = tools::ensure_target(target, self.auto_install)
+                .with_context(|| {
+                    format!("Cannot analyse the binaries for {target}")
+                })?;
+        }
+        if let Some(package) = &self.package {
+            metadata::ensure_workspace_members(std::slice::from_ref(
+                package,
+            ))?;
+            ensure_cargo_bloat(self.auto_install)?;
+            return Ok(vec![(
+                package.clone(),
+                self.analyse(&self.command(), package)?,
+            )]);
+        }
+        let binaries = metadata::binary_targets()?;
+        if binaries.is_empty() {
+            return Err(AnyError::msg(
+                "The workspace has no binary to analyse, pass a package with -p",
+            ));
+        }
+        ensure_cargo_bloat(self.auto_install)?;
+        binaries
+            .iter()
+            .map(|binary| {
+                let label = binary_label(binary);
+                let command = self.command_for(
+                    Some(&binary.package),
+                    Some(&binary.name),
+                );
+                Ok((label.clone(), self.analyse(&command, &label)?))
+            })
+            .collect()
+    }
+
+    /// Runs `cargo bloat` and returns its report, combining the reports of the
+    /// binaries when several are analysed.
+    ///
+    /// # Errors
+    ///
+    /// See [`Bloat::reports`].
+    pub fn report(&self) -> AnyResult<BloatReport> {
+        let mut reports = self.reports()?;
+        if reports.len() == 1 {
+            return Ok(reports.swap_remove(0).1);
+        }
+        Ok(BloatReport::combine(
+            reports.iter().map(|(_, report)| report),
+        ))
+    }
+
+    /// Runs `command` and parses its report, naming `subject` on failure.
+    fn analyse(
+        &self,
+        command: &CommandSpec,
+        subject: &str,
+    ) -> AnyResult<BloatReport> {
+        let failed = || {
+            format!(
+                "Failed to execute 'cargo bloat' for dependency analysis on '{subject}'"
+            )
+        };
+        let output = runner::current()
+            .execute(&command.clone().captured())
+            .with_context(|| format!("Failed to spawn '{command}'"))
+            .with_context(failed)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if let Some(line) = package_not_found(&stderr) {
+                return Err(AnyError::msg(format!(
+                    "cargo bloat could not find the package of '{subject}' ({line}), expected one of the workspace members: {}",
+                    metadata::workspace_members()?.join(", ")
+                )));
+            }
+            return Err(AnyError::msg(format!(
+                "'{command}' exited with {}: {}",
+                output.status,
+                stderr.trim()
+            ))
+            .context(failed()));
+        }
+        let output =
+            String::from_utf8(output.stdout).map_err(AnyError::new)?;
+        let report = BloatReport {
+            profile: Some(self.profile_name().to_string()),
+            target: self.target.clone(),
+            ..BloatReport::parse(&output)?
+        };
+        Ok(match &self.filter {
+            Some(prefix) => report.filtered(prefix),
+            None => report,
+        })
+    }
+
+    /// Returns the name of the cargo profile that is built.
+    fn profile_name(&self) -> &str {
+        match &self.profile {
+            Some(profile) => profile,
+            None if self.release => "release",
+            None => "dev",
+        }
+    }
+
+    /// Returns the title of the name column of the printed tables.
+    const fn heading(&self) -> &'static str {
+        if self.functions {
+            "Function"
+        } else {
+            "Crate"
+        }
+    }
+
+    /// Returns what is analysed, for messages.
+    fn subject(&self) -> &str {
+        self.package.as_deref().unwrap_or("the workspace binaries")
+    }
+
+    /// Runs `cargo bloat`, prints its report, and writes it to
+    /// [`output_json`](Self::output_json) and [`markdown`](Self::markdown) if
+    /// set. When a [`baseline`](Self::baseline) is set, the report is then
+    /// compared to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the analysis fails, or if the binary grew by more than
+    /// [`max_growth`](Self::max_growth). When only the JSON file cannot be
+    /// written, the report is still printed and the error says the analysis
+    /// succeeded.
+    pub fn run(&self) -> AnyResult<BloatReport> {
+        let mut reports = self.reports()?;
+        let report = if reports.len() == 1 {
+            reports.swap_remove(0).1
+        } else {
+            for (label, report) in &reports {
+                println!(
+                    "{label}:\n{}\n",
+                    report.table(self.heading())
+                );
+            }
+            let combined = BloatReport::combine(
+                reports.iter().map(|(_, report)| report),
+            );
+            println!("All binaries:");
+            combined
+        };
+        println!("{}", report.table(self.heading()));
+        if let Some(path) = &self.output_json {
+            report.save(path).with_context(|| {
+                    format!(
+                        "The analysis of '{}' succeeded, but its report could not be written to {}",
+                        self.subject(),
+                        path.display()
+                    )
+                })?;
+        }
+        let diff = match &self.baseline {
+            Some(baseline) => self.compare(&report, baseline)?,
+            None => None,
+        };
+        if let Some(path) = &self.markdown {
+            let mut markdown = report.markdown_table(
+                self.heading(),
+                self.top.unwrap_or(DEFAULT_TOP),
+            );
+            if let Some(diff) = &diff {
+                markdown =
+                    format!("{}\n\n{markdown}", diff.to_markdown());
+            }
+            markdown.push('\n');
+            let _ = ops::write_file_if_changed(path, &markdown)
+                .with_context(|| {
+                    format!(
+                        "The analysis of '{}' succeeded, but its Markdown report could not be written to {}",
+                        self.subject(),
+                        path.display()
+                    )
+                })?;
+        }
+        if let Some(diff) = &diff {
+            diff.check()?;
+        }
+        Ok(report)
+    }
+
+    /// Compares `report` to the baseline in `path`, or saves it there when there
+    /// is no baseline yet.
+    ///
+    /// # Returns
+    ///
+    /// The comparison, or `None` if the report became the baseline.
+    fn compare(
+        &self,
+        report: &BloatReport,
+        path: &Path,
+    ) -> AnyResult<Option<BloatDiff>> {
+        if !path.exists() {
+            eprintln!(
+                "warning: no bloat baseline in {}, saving the current report as the baseline",
+                path.display()
+            );
+            report.save(path)?;
+            return Ok(None);
+        }
+        let diff = bloat_diff(
+            &BloatReport::load(path)?,
+            report,
+            self.max_growth,
+        )?;
+        println!("{diff}");
+        Ok(Some(diff))
+    }
+}
+
+/// Makes sure cargo-bloat is available, installing it if `auto_install` is set.
+fn ensure_cargo_bloat(auto_install: bool) -> AnyResult<()> {
+    let _ = tools::ensure_tool("cargo-bloat", &["cargo-bloat"], auto_install)
+        .context(
+            "The size analysis requires cargo-bloat; the `install` xtask installs it with the other development tools",
+        )?;
+    Ok(())
+}
+
+/// Returns the line of `stderr` saying that cargo could not find a package, if
+/// any, e.g. ``error: package(s) `app` not found in workspace``.
+fn package_not_found(stderr: &str) -> Option<&str> {
+    stderr.lines().map(str::trim).find(|line| {
+        let line = line.to_lowercase();
+        line.contains("package") && line.contains("not found")
+    })
+}
+
+/// Returns how a binary is named in the sections of the output: its name, and
+/// its package when it differs.
+fn binary_label(binary: &BinaryTarget) -> String {
+    if binary.name == binary.package {
+        binary.name.clone()
+    } else {
+        format!("{} ({})", binary.name, binary.package)
+    }
+}
+
+impl BloatBuilder {
+    /// Builds the configuration and runs the analysis, see [`Bloat::run`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration cannot be built or the analysis
+    /// fails.
+    pub fn run(&self) -> AnyResult<BloatReport> {
+        self.build()
+            .context("Failed to build Bloat configuration")?
+            .run()
+    }
+}
+
+/// Analyses the dependencies of `package` and returns how much each of them
+/// contributes to the size of the binary.
+///
+/// # Errors
+///
+/// Returns an error if `cargo bloat` fails, e.g. because the package is not
+/// found or `cargo bloat` is not installed, or if its report cannot be parsed.
+pub fn deps_report(package: &str) -> AnyResult<BloatReport> {
+    BloatBuilder::default()
+        .package(package)
+        .build()
+        .context("Failed to build Bloat configuration")?
+        .report()
+}
+
+/// Lists the `top` largest functions of the binary of `package`.
+///
+/// # Errors
+///
+/// Returns an error if `cargo bloat` fails, e.g. because the package is not
+/// found or `cargo bloat` is not installed, or if its report cannot be parsed.
+pub fn functions(package: &str, top: usize) -> AnyResult<BloatReport> {
+    BloatBuilder::default()
+        .package(package)
+        .top(Some(top))
+        .functions(true)
+        .build()
+        .context("Failed to build Bloat configuration")?
+        .report()
+}
 
 /// Analyses the dependencies of the current project to find which ones contribute most to the build size.
 ///
+/// The report is printed as a table; use [`deps_report`] to inspect it instead.
+///
 /// # Parameters
 ///
 /// * `package` - The name of the package to analyze.
@@ -19,42 +1113,25 @@ use vrd::Random;
 /// Returns an error if the `cargo bloat` command fails to execute. This could happen if the specified package
 /// is not found, or if `cargo bloat` is not installed.
 pub fn deps(package: &str) -> AnyResult<()> {
-    let date = DateTime::new();
-    let log = macro_log!(
-        &Random::default().int(0, 1_000_000_000).to_string(),
-        &date.iso_8601,
-        &LogLevel::INFO,
+    task_log(
+        LogLevel::INFO,
         "Dependencies",
         "Starting dependency analysis",
-        &LogFormat::CLF
     );
-    drop(log);
-
-    cmd!("cargo", "bloat", "-p", package, "--crates")
-        .run()
-        .map(|_| ())
-        .map_err(|err| {
-            // Log the error and then return it
-            let log = macro_log!(
-                &Random::default().int(0, 1_000_000_000).to_string(),
-                &date.iso_8601,
-                &LogLevel::ERROR,
-                "Dependencies",
-                "Dependency analysis failed",
-                &LogFormat::CLF);
-            drop(log);
-            err
-        })
-        .with_context(|| format!("Failed to execute 'cargo bloat' for dependency analysis on package '{package}'"))?;
-    let log = macro_log!(
-        &Random::default().int(0, 1_000_000_000).to_string(),
-        &date.iso_8601,
-        &LogLevel::ERROR,
+    let report = deps_report(package).map_err(|err| {
+        task_log(
+            LogLevel::ERROR,
+            "Dependencies",
+            "Dependency analysis failed",
+        );
+        err
+    })?;
+    println!("{report}");
+    task_log(
+        LogLevel::INFO,
         "Dependencies",
         "Dependency analysis completed",
-        &LogFormat::CLF
     );
-    drop(log);
     Ok(())
 }
 
@@ -69,41 +1146,9 @@ pub fn deps(package: &str) -> AnyResult<()> {
 /// Returns an error if the `cargo bloat` command fails to execute. This could be due to a variety of reasons,
 /// such as the package not being found, or `cargo bloat` not being installed.
 pub fn time(package: &str) -> AnyResult<()> {
-    let date = DateTime::new();
-    let log = macro_log!(
-        &Random::default().int(0, 1_000_000_000).to_string(),
-        &date.iso_8601,
-        &LogLevel::ERROR,
-        "Time Analysis",
-        "Starting build time analysis",
-        &LogFormat::CLF
-    );
-    drop(log);
-
-    cmd!("cargo", "bloat", "-p", package, "--time")
-        .run()
-        .map(|_| ())  // Convert Ok(Output) to Ok(())
-        .map_err(|err| {
-            // Log the error and then return it
-            let log = macro_log!(
-                &Random::default().int(0, 1_000_000_000).to_string(),
-                &date.iso_8601,
-                &LogLevel::ERROR,
-                "Time Analysis",
-                "Build time analysis failed",
-                &LogFormat::CLF);
-            drop(log);
-            err
-        })
-        .with_context(|| format!("Failed to execute 'cargo bloat' for build time analysis on package '{package}'"))?;
-    let log = macro_log!(
-        &Random::default().int(0, 1_000_000_000).to_string(),
-        &date.iso_8601,
-        &LogLevel::ERROR,
-        "Time Analysis",
-        "Build time analysis completed",
-        &LogFormat::CLF
-    );
-    drop(log);
-    Ok(())
+    BloatBuilder::default()
+        .package(package)
+        .build()
+        .context("Failed to build Bloat configuration")?
+        .run_time()
 }
@@ -0,0 +1,390 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Comparison of two `cargo bloat` reports, to catch size regressions.
+//!
+//! A baseline report is usually kept from a previous run, e.g. on the default
+//! branch, and the current report is compared to it with [`bloat_diff`].
+
+use super::{format_size, parse_size, BloatEntry, BloatReport};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// How much the binary may grow before a comparison fails.
+///
+/// Both limits are optional; the binary may grow by any amount when neither is
+/// set.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize,
+)]
+pub struct DiffThreshold {
+    /// The allowed growth, in bytes.
+    #[serde(default)]
+    pub absolute_bytes: Option<u64>,
+    /// The allowed growth, as a percentage of the baseline size.
+    #[serde(default)]
+    pub percent: Option<f32>,
+}
+
+impl FromStr for DiffThreshold {
+    type Err = AnyError;
+
+    /// Parses a percentage such as `5%`, or a size such as `512KiB` or `2048`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::DiffThreshold;
+    ///
+    /// let threshold: DiffThreshold = "5%".parse().unwrap();
+    /// assert_eq!(threshold.percent, Some(5.0));
+    /// let threshold: DiffThreshold = "2MiB".parse().unwrap();
+    /// assert_eq!(threshold.absolute_bytes, Some(2 * 1024 * 1024));
+    /// ```
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let invalid = || {
+            AnyError::msg(format!(
+                "Invalid size threshold '{value}', expected a percentage such as 5% or a size such as 512KiB"
+            ))
+        };
+        match value.strip_suffix('%') {
+            Some(percent) => Ok(Self {
+                percent: Some(
+                    percent.trim().parse().map_err(|_| invalid())?,
+                ),
+                ..Self::default()
+            }),
+            None => Ok(Self {
+                absolute_bytes: Some(
+                    parse_size(value).ok_or_else(invalid)?,
+                ),
+                ..Self::default()
+            }),
+        }
+    }
+}
+
+impl fmt::Display for DiffThreshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.absolute_bytes, self.percent) {
+            (Some(bytes), Some(percent)) => {
+                write!(f, "{} or {percent}%", format_size(bytes))
+            }
+            (Some(bytes), None) => write!(f, "{}", format_size(bytes)),
+            (None, Some(percent)) => write!(f, "{percent}%"),
+            (None, None) => write!(f, "unlimited"),
+        }
+    }
+}
+
+/// The change of a crate's size between two reports.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct CrateDelta {
+    /// The name of the crate.
+    pub name: String,
+    /// The size of the crate in the baseline, or `None` if it was added.
+    pub before: Option<u64>,
+    /// The current size of the crate, or `None` if it was removed.
+    pub after: Option<u64>,
+}
+
+impl CrateDelta {
+    /// Returns the change in bytes; negative when the crate shrank.
+    pub fn delta(&self) -> i64 {
+        signed(self.after.unwrap_or(0))
+            - signed(self.before.unwrap_or(0))
+    }
+
+    /// Returns `true` if the crate is not in the baseline.
+    pub const fn is_added(&self) -> bool {
+        self.before.is_none()
+    }
+
+    /// Returns `true` if the crate is not in the current report.
+    pub const fn is_removed(&self) -> bool {
+        self.after.is_none()
+    }
+}
+
+/// The change in size between a baseline report and the current one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BloatDiff {
+    /// The size of the baseline binary, in bytes.
+    pub before: u64,
+    /// The size of the current binary, in bytes.
+    pub after: u64,
+    /// The crates whose size changed, including added and removed ones, the
+    /// largest growth first.
+    pub crates: Vec<CrateDelta>,
+    /// The growth allowed by the comparison.
+    pub threshold: DiffThreshold,
+}
+
+impl BloatDiff {
+    /// Returns the change of the binary size in bytes; negative when it shrank.
+    pub fn delta(&self) -> i64 {
+        signed(self.after) - signed(self.before)
+    }
+
+    /// Returns the change of the binary size as a percentage of the baseline.
+    pub fn delta_percent(&self) -> f32 {
+        if self.before == 0 {
+            0.0
+        } else {
+            (self.delta() as f64 * 100.0 / self.before as f64) as f32
+        }
+    }
+
+    /// Returns the crates that are not in the baseline.
+    pub fn added(&self) -> impl Iterator<Item = &CrateDelta> {
+        self.crates.iter().filter(|delta| delta.is_added())
+    }
+
+    /// Returns the crates that are not in the current report.
+    pub fn removed(&self) -> impl Iterator<Item = &CrateDelta> {
+        self.crates.iter().filter(|delta| delta.is_removed())
+    }
+
+    /// Returns `true` if the binary grew by more than the threshold allows.
+    pub fn exceeded(&self) -> bool {
+        let growth = self.delta();
+        growth > 0
+            && (self
+                .threshold
+                .absolute_bytes
+                .is_some_and(|bytes| growth > signed(bytes))
+                || self.threshold.percent.is_some_and(|percent| {
+                    self.delta_percent() > percent
+                }))
+    }
+
+    /// Fails if the binary grew by more than the threshold allows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the growth and the threshold when it is exceeded.
+    pub fn check(&self) -> AnyResult<()> {
+        if self.exceeded() {
+            return Err(AnyError::msg(format!(
+                "The binary grew by {} ({:+.1}%), more than the allowed {}",
+                format_size_delta(self.delta()),
+                self.delta_percent(),
+                self.threshold
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the one-line summary, e.g. `size changed 2.7MiB → 2.9MiB
+    /// (+204.8KiB, +7.4%)`.
+    pub fn summary(&self) -> String {
+        format!(
+            "size changed {} → {} ({}, {:+.1}%)",
+            format_size(self.before),
+            format_size(self.after),
+            format_size_delta(self.delta()),
+            self.delta_percent()
+        )
+    }
+
+    /// Renders the comparison as GitHub-flavored Markdown, e.g. for a pull
+    /// request comment: a summary line, then a table of the crates that changed
+    /// with ⬆ for growth and ⬇ for shrinkage.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::{bloat_diff, BloatReport};
+    ///
+    /// let before = BloatReport::parse(r#"{"file-size":2048,"crates":[]}"#).unwrap();
+    /// let after = BloatReport::parse(r#"{"file-size":3072,"crates":[]}"#).unwrap();
+    /// let diff = bloat_diff(&before, &after, Default::default()).unwrap();
+    /// assert_eq!(
+    ///     diff.to_markdown(),
+    ///     "**Binary size:** 2.0KiB → 3.0KiB (⬆ +1.0KiB, +50.0%)"
+    /// );
+    /// ```
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!(
+            "**Binary size:** {} → {} ({}, {:+.1}%)",
+            format_size(self.before),
+            format_size(self.after),
+            markdown_delta(self.delta()),
+            self.delta_percent()
+        );
+        if self.crates.is_empty() {
+            return markdown;
+        }
+        let size = |size: Option<u64>| {
+            size.map_or("-".to_string(), format_size)
+        };
+        markdown.push_str(
+            "\n\n| Crate | Before | After | Change |\n| --- | ---: | ---: | ---: |",
+        );
+        for delta in &self.crates {
+            let note = if delta.is_added() {
+                " (added)"
+            } else if delta.is_removed() {
+                " (removed)"
+            } else {
+                ""
+            };
+            markdown.push_str(&format!(
+                "\n| {}{note} | {} | {} | {} |",
+                delta.name,
+                size(delta.before),
+                size(delta.after),
+                markdown_delta(delta.delta())
+            ));
+        }
+        markdown
+    }
+}
+
+/// Formats a change of size with an arrow for its direction, e.g. `⬆ +1.4KiB`.
+fn markdown_delta(delta: i64) -> String {
+    let arrow = match delta.signum() {
+        1 => "⬆ ",
+        -1 => "⬇ ",
+        _ => "",
+    };
+    format!("{arrow}{}", format_size_delta(delta))
+}
+
+impl fmt::Display for BloatDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary())?;
+        if self.crates.is_empty() {
+            return Ok(());
+        }
+        let width = self
+            .crates
+            .iter()
+            .map(|delta| delta.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("crate".len());
+        let size = |size: Option<u64>| {
+            size.map_or("-".to_string(), format_size)
+        };
+        write!(
+            f,
+            "\n  {:<width$}  {:>9}  {:>9}  {:>10}",
+            "crate", "before", "after", "change"
+        )?;
+        for delta in &self.crates {
+            write!(
+                f,
+                "\n  {:<width$}  {:>9}  {:>9}  {:>10}",
+                delta.name,
+                size(delta.before),
+                size(delta.after),
+                format_size_delta(delta.delta())
+            )?;
+            if delta.is_added() {
+                write!(f, "  (added)")?;
+            } else if delta.is_removed() {
+                write!(f, "  (removed)")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares `current` to `baseline`, listing every crate whose size changed.
+///
+/// # Errors
+///
+/// Returns an error if the reports measured different profiles or targets; see
+/// [`BloatReport::ensure_comparable`].
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::bloat::{bloat_diff, BloatEntry, BloatReport};
+///
+/// let report = |size, crates: &[(&str, u64)]| BloatReport {
+///     total_size: size,
+///     entries: crates
+///         .iter()
+///         .map(|(name, size)| BloatEntry {
+///             name: name.to_string(),
+///             size: *size,
+///             percent: 0.0,
+///         })
+///         .collect(),
+///     ..BloatReport::default()
+/// };
+/// let diff = bloat_diff(
+///     &report(1000, &[("std", 400)]),
+///     &report(1100, &[("std", 450), ("serde", 50)]),
+///     "5%".parse().unwrap(),
+/// )
+/// .unwrap();
+/// assert_eq!(diff.delta(), 100);
+/// assert_eq!(diff.added().count(), 1);
+/// assert!(diff.check().is_err());
+/// ```
+pub fn bloat_diff(
+    baseline: &BloatReport,
+    current: &BloatReport,
+    threshold: DiffThreshold,
+) -> AnyResult<BloatDiff> {
+    current.ensure_comparable(baseline)?;
+    let sizes = |entries: &[BloatEntry]| -> BTreeMap<String, u64> {
+        entries
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.size))
+            .collect()
+    };
+    let before = sizes(&baseline.entries);
+    let after = sizes(&current.entries);
+    let mut crates: Vec<CrateDelta> = before
+        .keys()
+        .chain(after.keys().filter(|name| !before.contains_key(*name)))
+        .map(|name| CrateDelta {
+            name: name.clone(),
+            before: before.get(name).copied(),
+            after: after.get(name).copied(),
+        })
+        .filter(|delta| delta.before != delta.after)
+        .collect();
+    crates.sort_by(|a, b| {
+        b.delta().cmp(&a.delta()).then(a.name.cmp(&b.name))
+    });
+    Ok(BloatDiff {
+        before: baseline.total_size,
+        after: current.total_size,
+        crates,
+        threshold,
+    })
+}
+
+/// Formats a change of size with its sign, e.g. `+1.4KiB` or `-512B`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::bloat::diff::format_size_delta;
+///
+/// assert_eq!(format_size_delta(1434), "+1.4KiB");
+/// assert_eq!(format_size_delta(-512), "-512B");
+/// assert_eq!(format_size_delta(0), "0B");
+/// ```
+pub fn format_size_delta(delta: i64) -> String {
+    let size = format_size(delta.unsigned_abs());
+    match delta.signum() {
+        1 => format!("+{size}"),
+        -1 => format!("-{size}"),
+        _ => size,
+    }
+}
+
+/// Converts a size to a signed number of bytes, saturating on overflow.
+fn signed(size: u64) -> i64 {
+    i64::try_from(size).unwrap_or(i64::MAX)
+}
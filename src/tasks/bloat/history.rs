@@ -0,0 +1,328 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Binary size history, kept across commits to see how the binaries grow.
+//!
+//! [`size_track`] builds the release binaries and records their size in a JSON
+//! Lines file, one [`SizeEntry`] per binary and commit. The file is
+//! [`DEFAULT_HISTORY_FILE`] unless the root package sets `size_history` under
+//! `[package.metadata.xtask]`.
+
+use super::{diff::format_size_delta, format_size};
+use crate::{
+    ops::{
+        self, metadata,
+        runner::{self, CommandSpec},
+    },
+    tasks::coverage::history::current_revision,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use dtt::DateTime;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The file the size history is kept in unless configured otherwise.
+pub const DEFAULT_HISTORY_FILE: &str =
+    "target/xtask/size-history.jsonl";
+
+/// The size of a binary at a commit.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct SizeEntry {
+    /// The git revision the binary was built from.
+    pub sha: String,
+    /// When the binary was measured, in ISO 8601 format.
+    pub date: String,
+    /// The name of the binary.
+    pub binary: String,
+    /// The size of the release binary, in bytes.
+    pub size_bytes: u64,
+}
+
+/// The entries of a size history file, oldest first.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct SizeHistory {
+    /// The recorded sizes, oldest first.
+    pub entries: Vec<SizeEntry>,
+}
+
+impl SizeHistory {
+    /// Parses a history in JSON Lines format, one entry per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first line that is not an entry.
+    pub fn parse(jsonl: &str) -> AnyResult<Self> {
+        let entries = jsonl
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).with_context(|| {
+                    format!(
+                        "Invalid size history entry on line {}",
+                        index + 1
+                    )
+                })
+            })
+            .collect::<AnyResult<_>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Reads the history from `path`. A missing file is an empty history.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or parsed.
+    pub fn load(path: &Path) -> AnyResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let jsonl = fs::read_to_string(path).with_context(|| {
+            format!("Failed to read {}", path.display())
+        })?;
+        Self::parse(&jsonl).with_context(|| {
+            format!("Failed to parse size history {}", path.display())
+        })
+    }
+
+    /// Writes the history to `path`, creating its directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> AnyResult<()> {
+        let mut jsonl = String::new();
+        for entry in &self.entries {
+            jsonl.push_str(&serde_json::to_string(entry)?);
+            jsonl.push('\n');
+        }
+        ops::write_atomic(path, jsonl)
+    }
+
+    /// Adds `entry`, replacing the entry of the same binary at the same commit
+    /// when the commit is measured again.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the entry is new, `false` if it replaced an existing one.
+    pub fn record(&mut self, entry: SizeEntry) -> bool {
+        match self.entries.iter_mut().find(|existing| {
+            existing.sha == entry.sha && existing.binary == entry.binary
+        }) {
+            Some(existing) => {
+                *existing = entry;
+                false
+            }
+            None => {
+                self.entries.push(entry);
+                true
+            }
+        }
+    }
+
+    /// Returns the last `last_n` entries, oldest first, each with its change
+    /// since the previous entry of the same binary, if any.
+    pub fn changes(
+        &self,
+        last_n: usize,
+    ) -> Vec<(&SizeEntry, Option<i64>)> {
+        let mut previous: HashMap<&str, u64> = HashMap::new();
+        let changes: Vec<(&SizeEntry, Option<i64>)> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let delta = previous
+                    .insert(&entry.binary, entry.size_bytes)
+                    .map(|before| {
+                        signed(entry.size_bytes) - signed(before)
+                    });
+                (entry, delta)
+            })
+            .collect();
+        let skip = changes.len().saturating_sub(last_n);
+        changes.into_iter().skip(skip).collect()
+    }
+
+    /// Renders the last `last_n` entries as a GitHub-flavored Markdown table,
+    /// e.g. for a pull request comment.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::bloat::history::SizeHistory;
+    ///
+    /// let history = SizeHistory::parse(concat!(
+    ///     r#"{"sha":"1111111aaa","date":"2024-01-01T00:00:00+00:00","binary":"app","size_bytes":2048}"#,
+    ///     "\n",
+    ///     r#"{"sha":"2222222bbb","date":"2024-01-02T00:00:00+00:00","binary":"app","size_bytes":3072}"#,
+    /// ))
+    /// .unwrap();
+    /// assert!(history
+    ///     .to_markdown(1)
+    ///     .ends_with("| `2222222` | 2024-01-02 | app | 3.0KiB | +1.0KiB |"));
+    /// ```
+    pub fn to_markdown(&self, last_n: usize) -> String {
+        let mut markdown = String::from(
+            "| Commit | Date | Binary | Size | Change |\n\
+             | --- | --- | --- | ---: | ---: |",
+        );
+        for (entry, delta) in self.changes(last_n) {
+            markdown.push_str(&format!(
+                "\n| `{}` | {} | {} | {} | {} |",
+                short_sha(&entry.sha),
+                short_date(&entry.date),
+                entry.binary,
+                format_size(entry.size_bytes),
+                delta.map_or("-".to_string(), format_size_delta)
+            ));
+        }
+        markdown
+    }
+
+    /// Renders the last `last_n` entries as a plain-text table.
+    pub fn table(&self, last_n: usize) -> String {
+        let changes = self.changes(last_n);
+        let width = changes
+            .iter()
+            .map(|(entry, _)| entry.binary.len())
+            .max()
+            .unwrap_or(0)
+            .max("binary".len());
+        let mut table = format!(
+            "{:<7}  {:<10}  {:<width$}  {:>9}  {:>10}",
+            "commit", "date", "binary", "size", "change"
+        );
+        for (entry, delta) in changes {
+            table.push_str(&format!(
+                "\n{:<7}  {:<10}  {:<width$}  {:>9}  {:>10}",
+                short_sha(&entry.sha),
+                short_date(&entry.date),
+                entry.binary,
+                format_size(entry.size_bytes),
+                delta.map_or("-".to_string(), format_size_delta)
+            ));
+        }
+        table
+    }
+}
+
+impl fmt::Display for SizeHistory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.table(self.entries.len()))
+    }
+}
+
+/// Returns the abbreviated form of a revision, as printed by `git log --oneline`.
+fn short_sha(sha: &str) -> &str {
+    sha.get(..7).unwrap_or(sha)
+}
+
+/// Returns the day of an ISO 8601 date and time, e.g. `2024-01-02`.
+fn short_date(date: &str) -> &str {
+    date.get(..10).unwrap_or(date)
+}
+
+/// Converts a size to a signed number of bytes, saturating on overflow.
+fn signed(size: u64) -> i64 {
+    i64::try_from(size).unwrap_or(i64::MAX)
+}
+
+/// Returns the history file configured in a `[package.metadata.xtask]` table, or
+/// [`DEFAULT_HISTORY_FILE`].
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use xtasks::tasks::bloat::history::history_path_from;
+///
+/// let xtask = serde_json::json!({ "size_history": "sizes.jsonl" });
+/// assert_eq!(history_path_from(&xtask), Path::new("sizes.jsonl"));
+/// ```
+pub fn history_path_from(xtask: &serde_json::Value) -> PathBuf {
+    PathBuf::from(
+        xtask
+            .get("size_history")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(DEFAULT_HISTORY_FILE),
+    )
+}
+
+/// Returns the size history file of the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if the workspace cannot be inspected with `cargo metadata`.
+pub fn history_path() -> AnyResult<PathBuf> {
+    Ok(history_path_from(&metadata::xtask_metadata()?))
+}
+
+/// Builds the release binaries of the workspace and records their size at the
+/// current commit in the history file.
+///
+/// # Returns
+///
+/// The recorded entries, one per binary.
+///
+/// # Errors
+///
+/// Returns an error if the workspace is not a git repository, has no binary, or
+/// cannot be built, or if the history cannot be read or written.
+pub fn size_track() -> AnyResult<Vec<SizeEntry>> {
+    let (sha, _) = current_revision().ok_or_else(|| {
+        AnyError::msg(
+            "The size history needs a git repository with a commit",
+        )
+    })?;
+    let binaries = metadata::binary_targets()?;
+    if binaries.is_empty() {
+        return Err(AnyError::msg(
+            "The workspace has no binary to measure",
+        ));
+    }
+    let build = CommandSpec::new("cargo").args(["build", "--release"]);
+    let _ = runner::run(&build)
+        .with_context(|| format!("Failed to execute '{build}'"))?;
+
+    let release_dir = metadata::target_directory()?.join("release");
+    let date = DateTime::new().iso_8601;
+    let path = history_path()?;
+    let mut history = SizeHistory::load(&path)?;
+    let mut entries = Vec::new();
+    for binary in binaries {
+        let file = release_dir.join(format!(
+            "{}{}",
+            binary.name,
+            env::consts::EXE_SUFFIX
+        ));
+        let entry = SizeEntry {
+            sha: sha.clone(),
+            date: date.clone(),
+            binary: binary.name,
+            size_bytes: ops::file_size(&file)?,
+        };
+        let _ = history.record(entry.clone());
+        entries.push(entry);
+    }
+    history.save(&path)?;
+    Ok(entries)
+}
+
+/// Prints the last `last_n` entries of the size history with their changes.
+///
+/// # Errors
+///
+/// Returns an error if the history cannot be found or read.
+pub fn size_history_report(last_n: usize) -> AnyResult<SizeHistory> {
+    let history = SizeHistory::load(&history_path()?)?;
+    println!("{}", history.table(last_n));
+    Ok(history)
+}
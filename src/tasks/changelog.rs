@@ -0,0 +1,465 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Generation of changelog sections from the git history.
+//!
+//! The commits since the latest tag are grouped by their
+//! [conventional commit](https://www.conventionalcommits.org) prefix into
+//! features, fixes, documentation, chores and other changes, and rendered as a
+//! Markdown section for the current version of the package. The commits link
+//! to the repository declared in `Cargo.toml`, when there is one.
+
+use crate::{
+    ops::{
+        git::{self, LogEntry},
+        manifest, write_atomic,
+    },
+    tasks::release::DEFAULT_CHANGELOG,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use dtt::DateTime;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The group of a commit in a changelog section, from its prefix.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum CommitKind {
+    /// `feat:` commits.
+    Feature,
+    /// `fix:` commits.
+    Fix,
+    /// `docs:` commits.
+    Docs,
+    /// `chore:` commits.
+    Chore,
+    /// Commits without a known prefix.
+    Other,
+}
+
+impl CommitKind {
+    /// All the groups, in the order of a changelog section.
+    pub const ALL: [Self; 5] = [
+        Self::Feature,
+        Self::Fix,
+        Self::Docs,
+        Self::Chore,
+        Self::Other,
+    ];
+
+    /// Returns the heading of the group in a changelog section.
+    pub const fn heading(self) -> &'static str {
+        match self {
+            Self::Feature => "Features",
+            Self::Fix => "Bug Fixes",
+            Self::Docs => "Documentation",
+            Self::Chore => "Chores",
+            Self::Other => "Other Changes",
+        }
+    }
+
+    /// Returns the group of the conventional commit type `prefix`, if known.
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix.to_ascii_lowercase().as_str() {
+            "feat" | "feature" => Some(Self::Feature),
+            "fix" => Some(Self::Fix),
+            "docs" | "doc" => Some(Self::Docs),
+            "chore" => Some(Self::Chore),
+            _ => None,
+        }
+    }
+}
+
+/// A commit as listed in a changelog section.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChangelogEntry {
+    /// The group of the commit.
+    pub kind: CommitKind,
+    /// The scope of a conventional commit, e.g. `cli` in `feat(cli): ...`.
+    pub scope: Option<String>,
+    /// The description of the commit, without its prefix.
+    pub summary: String,
+    /// The full SHA of the commit.
+    pub sha: String,
+}
+
+/// Returns the changelog entry of `commit`, parsing a `type(scope)!: summary`
+/// subject.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::ops::git::LogEntry;
+/// use xtasks::tasks::changelog::{classify, CommitKind};
+///
+/// let commit = LogEntry {
+///     sha: "0123456789abcdef".to_string(),
+///     author: "Dev".to_string(),
+///     subject: "fix(cli)!: reject empty names".to_string(),
+/// };
+/// let entry = classify(&commit);
+/// assert_eq!(entry.kind, CommitKind::Fix);
+/// assert_eq!(entry.scope.as_deref(), Some("cli"));
+/// assert_eq!(entry.summary, "reject empty names");
+/// ```
+pub fn classify(commit: &LogEntry) -> ChangelogEntry {
+    let conventional =
+        commit
+            .subject
+            .split_once(':')
+            .and_then(|(prefix, summary)| {
+                let prefix = prefix.strip_suffix('!').unwrap_or(prefix);
+                let (kind, scope) = match prefix.split_once('(') {
+                    Some((kind, scope)) => {
+                        (kind, Some(scope.strip_suffix(')')?.trim()))
+                    }
+                    None => (prefix, None),
+                };
+                Some((
+                    CommitKind::from_prefix(kind)?,
+                    scope.filter(|scope| !scope.is_empty()),
+                    summary.trim(),
+                ))
+            });
+    let (kind, scope, summary) = conventional.unwrap_or((
+        CommitKind::Other,
+        None,
+        commit.subject.trim(),
+    ));
+    ChangelogEntry {
+        kind,
+        scope: scope.map(ToString::to_string),
+        summary: summary.to_string(),
+        sha: commit.sha.clone(),
+    }
+}
+
+/// Returns `true` if `commit` is a dependency update opened by Dependabot.
+pub fn is_dependabot(commit: &LogEntry) -> bool {
+    commit.author.to_ascii_lowercase().starts_with("dependabot")
+}
+
+/// Renders the changelog section of `version`, released on `date`, listing
+/// `entries` by group. The SHAs link to the commits of `repository` when
+/// given.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::changelog::{render, ChangelogEntry, CommitKind};
+///
+/// let entries = [ChangelogEntry {
+///     kind: CommitKind::Feature,
+///     scope: None,
+///     summary: "add a release task".to_string(),
+///     sha: "0123456789abcdef".to_string(),
+/// }];
+/// assert_eq!(
+///     render("0.2.0", "2024-01-31", &entries, None),
+///     "## [0.2.0] - 2024-01-31\n\n### Features\n\n- add a release task (0123456)\n"
+/// );
+/// ```
+pub fn render(
+    version: &str,
+    date: &str,
+    entries: &[ChangelogEntry],
+    repository: Option<&str>,
+) -> String {
+    let mut section = format!("## [{version}] - {date}\n");
+    for kind in CommitKind::ALL {
+        let group: Vec<&ChangelogEntry> =
+            entries.iter().filter(|entry| entry.kind == kind).collect();
+        if group.is_empty() {
+            continue;
+        }
+        section.push_str(&format!("\n### {}\n\n", kind.heading()));
+        for entry in group {
+            let short = entry.sha.get(..7).unwrap_or(&entry.sha);
+            let sha = match repository {
+                Some(url) => {
+                    format!("[{short}]({url}/commit/{})", entry.sha)
+                }
+                None => short.to_string(),
+            };
+            let scope = entry
+                .scope
+                .as_ref()
+                .map(|scope| format!("**{scope}:** "))
+                .unwrap_or_default();
+            section.push_str(&format!(
+                "- {scope}{} ({sha})\n",
+                entry.summary
+            ));
+        }
+    }
+    section
+}
+
+/// Returns today's date as `YYYY-MM-DD`, for the changelog headings.
+pub fn today() -> String {
+    let date = DateTime::new().iso_8601;
+    date.get(..10).unwrap_or(&date).to_string()
+}
+
+/// Returns the line range, heading included, of the section of `version` in
+/// the changelog `lines`, e.g. under `## [0.2.0] - 2024-01-31`.
+fn section_range(
+    lines: &[&str],
+    version: &str,
+) -> Option<(usize, usize)> {
+    let is_heading = |line: &str| {
+        let title = line.trim().strip_prefix("## ")?.trim_start();
+        let title = title.strip_prefix('[').unwrap_or(title);
+        let rest = title.strip_prefix(version)?;
+        rest.chars()
+            .next()
+            .map_or(true, |c| matches!(c, ']' | ' '))
+            .then_some(())
+    };
+    let start =
+        lines.iter().position(|line| is_heading(line).is_some())?;
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| line.starts_with("## "))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+    Some((start, end))
+}
+
+/// Returns the body of the section of `version` in the changelog `contents`,
+/// without its heading, or `None` if there is no such section.
+pub fn section_body(contents: &str, version: &str) -> Option<String> {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let (start, end) = section_range(&lines, version)?;
+    Some(lines[start + 1..end].concat())
+}
+
+/// Returns the changelog `contents` with the generated `section` of `version`.
+///
+/// The body of an existing section of `version` is replaced, its heading and
+/// date kept. Otherwise, the section is inserted above the latest release,
+/// below the `Unreleased` section if any.
+pub fn with_section(
+    contents: &str,
+    version: &str,
+    section: &str,
+) -> String {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let section = if section.ends_with('\n') {
+        section.to_string()
+    } else {
+        format!("{section}\n")
+    };
+    if let Some((start, end)) = section_range(&lines, version) {
+        let body =
+            section.split_once('\n').map_or("", |(_, body)| body);
+        let blank = if end < lines.len() { "\n" } else { "" };
+        return format!(
+            "{}{}{body}{blank}{}",
+            lines[..start].concat(),
+            lines[start],
+            lines[end..].concat()
+        );
+    }
+    let is_release = |line: &&str| {
+        line.starts_with("## ")
+            && !line.to_ascii_lowercase().contains("unreleased")
+    };
+    let at = lines.iter().position(is_release).unwrap_or(lines.len());
+    let before = lines[..at].concat();
+    let separator = if before.is_empty() || before.ends_with("\n\n") {
+        ""
+    } else if before.ends_with('\n') {
+        "\n"
+    } else {
+        "\n\n"
+    };
+    let after = if at < lines.len() { "\n" } else { "" };
+    format!(
+        "{before}{separator}{section}{after}{}",
+        lines[at..].concat()
+    )
+}
+
+/// The configuration of a changelog generation.
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct Changelog {
+    /// The git range of the commits to list, e.g. `v0.1.0..HEAD`.
+    ///
+    /// By default, this is the commits since the latest tag, or the whole
+    /// history when there is no tag.
+    #[builder(default)]
+    #[serde(default)]
+    pub range: Option<String>,
+
+    /// The changelog the section is written to, or checked against. Without
+    /// it, the section is printed.
+    ///
+    /// By default, this is set to `None`.
+    #[builder(default)]
+    #[serde(default)]
+    pub out: Option<PathBuf>,
+
+    /// Verifies that the changelog already contains the generated section for
+    /// the current version instead of writing it.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub check: bool,
+
+    /// Leaves out the dependency updates opened by Dependabot.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub skip_dependabot: bool,
+}
+
+impl Changelog {
+    /// Renders the changelog section of the current version of the package in
+    /// `root`, dated today.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the version of the package cannot be read or the
+    /// git history cannot be listed.
+    pub fn generate_in(&self, root: &Path) -> AnyResult<String> {
+        let manifest = root.join("Cargo.toml");
+        let version = manifest::package_version(&manifest)?;
+        let range = match &self.range {
+            Some(range) => Some(range.clone()),
+            None => {
+                git::latest_tag(root)?.map(|tag| format!("{tag}..HEAD"))
+            }
+        };
+        let entries: Vec<ChangelogEntry> =
+            git::log(root, range.as_deref())?
+                .iter()
+                .filter(|commit| {
+                    !(self.skip_dependabot && is_dependabot(commit))
+                })
+                .map(classify)
+                .collect();
+        Ok(render(
+            &version,
+            &today(),
+            &entries,
+            manifest::package_repository(&manifest).as_deref(),
+        ))
+    }
+
+    /// Generates the changelog section of the package in the current
+    /// directory, then prints it, writes it or checks it.
+    ///
+    /// # Errors
+    ///
+    /// See [`Changelog::run_in`].
+    pub fn run(&self) -> AnyResult<String> {
+        self.run_in(Path::new("."))
+    }
+
+    /// Generates the changelog section of the package in `root`, then prints
+    /// it, writes it to [`out`](Changelog::out) or, in check mode, verifies
+    /// the changelog against it.
+    ///
+    /// # Returns
+    ///
+    /// The generated section.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the section cannot be generated or written, or in
+    /// check mode if the changelog has no section for the current version or
+    /// a different one.
+    pub fn run_in(&self, root: &Path) -> AnyResult<String> {
+        let section = self.generate_in(root)?;
+        if self.check {
+            let file = self
+                .out
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_CHANGELOG));
+            let path = root.join(&file);
+            let contents =
+                fs::read_to_string(&path).with_context(|| {
+                    format!("Failed to read {}", path.display())
+                })?;
+            let version =
+                manifest::package_version(&root.join("Cargo.toml"))?;
+            let expected =
+                section.split_once('\n').map_or("", |(_, body)| body);
+            let body = section_body(&contents, &version).ok_or_else(|| {
+                AnyError::msg(format!(
+                    "{} has no section for {version}, run 'cargo xtask changelog --out {}'",
+                    file.display(),
+                    file.display()
+                ))
+            })?;
+            if body.trim() != expected.trim() {
+                return Err(AnyError::msg(format!(
+                    "The {version} section of {} is out of date, run 'cargo xtask changelog --out {}'",
+                    file.display(),
+                    file.display()
+                )));
+            }
+            println!("{} is up to date for {version}", file.display());
+        } else if let Some(file) = &self.out {
+            let path = root.join(file);
+            let version =
+                manifest::package_version(&root.join("Cargo.toml"))?;
+            let contents = if path.is_file() {
+                fs::read_to_string(&path).with_context(|| {
+                    format!("Failed to read {}", path.display())
+                })?
+            } else {
+                "# Changelog\n".to_string()
+            };
+            write_atomic(
+                &path,
+                with_section(&contents, &version, &section),
+            )?;
+        } else {
+            print!("{section}");
+        }
+        Ok(section)
+    }
+}
+
+/// Renders the changelog section of the current version of the package in the
+/// current directory, listing the commits of the git `range`, or the commits
+/// since the latest tag when `range` is `None`.
+///
+/// # Errors
+///
+/// See [`Changelog::generate_in`].
+pub fn generate(range: Option<&str>) -> AnyResult<String> {
+    ChangelogBuilder::default()
+        .range(range.map(ToString::to_string))
+        .build()
+        .context("Failed to build Changelog configuration")?
+        .generate_in(Path::new("."))
+}
@@ -1,97 +1,2014 @@
-use anyhow::{Context, Result as AnyResult};
+/// The `annotations` module turns cargo diagnostics into GitHub Actions annotations.
+pub mod annotations;
+/// The `hooks` module installs git hooks that run the CI task.
+pub mod hooks;
+/// The `minimal_versions` module builds against the lowest allowed dependency versions.
+pub mod minimal_versions;
+
+pub use hooks::{install_git_hook, HookKind};
+
+use crate::{
+    ops::{
+        error::XtaskError,
+        git, output,
+        runner::{self, CommandSpec},
+        tools,
+    },
+    tasks::clippy::{
+        lint_args as clippy_lint_args, FIX_ARGS as CLIPPY_FIX_ARGS,
+    },
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
 use derive_builder::Builder;
-use duct::cmd;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 /// Represents the configuration for a Continuous Integration (CI) run.
 ///
 /// This struct encapsulates various settings that can be configured for a CI run,
 /// such as whether to run with the nightly compiler or to enable all Clippy lints.
 ///
-#[derive(
-    Builder,
-    Debug,
-    Default,
-    PartialEq,
-    Eq,
-    Hash,
-    Clone,
-    Serialize,
-    Deserialize,
+#[derive(Builder, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[builder(
+    setter(into),
+    derive(Debug, Deserialize),
+    build_fn(validate = "Self::validate")
 )]
-#[builder(setter(into))]
+#[builder_struct_attr(serde(deny_unknown_fields))]
 pub struct CI {
     /// Determines whether to run the CI tasks with the nightly version of the Rust compiler.
     ///
     /// By default, this is set to `false`, meaning that the stable compiler will be used.
     ///
     #[builder(default = "false")]
+    #[serde(default)]
     pub nightly: bool,
 
+    /// The toolchains the built-in steps are run with, in order.
+    ///
+    /// Every toolchain runs its own `fmt`, `clippy`, and `test` steps through
+    /// `rustup run <toolchain>`, and is installed first with rustfmt and clippy if it
+    /// is missing, see [`CI::auto_install`]. By default, this is empty, which runs
+    /// the steps once with the active toolchain, without `rustup run`; an explicit
+    /// `["stable"]` runs them with the stable toolchain instead. Setting `nightly`
+    /// instead of a list is equivalent to `["nightly"]`.
+    ///
+    /// The default is empty rather than `["stable"]` so that a plain run keeps
+    /// using the toolchain the workspace pins, e.g. in `rust-toolchain.toml`, and
+    /// does not install stable behind its back. It still resolves to stable where
+    /// a toolchain must be named, see [`CI::resolved_toolchains`], e.g. in the
+    /// matrix of the emitted workflow.
+    ///
+    #[builder(setter(custom), default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub toolchains: Vec<String>,
+
+    /// Toolchains whose failures are reported as warnings instead of failing the run,
+    /// e.g. `["beta", "nightly"]`.
+    ///
+    /// By default, this is empty.
+    ///
+    #[builder(setter(custom), default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow_failure: Vec<String>,
+
+    /// Installs a missing matrix toolchain, with rustfmt and clippy, before its
+    /// first step. Otherwise, a missing toolchain is an error naming the command
+    /// that installs it. By default, this is set to `true`; the
+    /// `XTASKS_NO_AUTO_INSTALL` environment variable turns it off.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub auto_install: bool,
+
     /// Determines whether to enable all Clippy lints, including pedantic, nursery, and 2018-idioms.
     ///
     /// By default, this is set to `true`, meaning that all Clippy lints will be enabled.
+    /// [`CI::default()`] is the exception and leaves it off.
     ///
     #[builder(default = "true")]
+    #[serde(default = "default_true")]
     pub clippy_max: bool,
+
+    /// Clippy lints warned about on top of the defaults, e.g.
+    /// `clippy::unwrap_used`, passed with `-W` to the `clippy` steps.
+    ///
+    /// By default, this is empty.
+    ///
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clippy_warn: Vec<String>,
+
+    /// Clippy lints allowed, e.g. `clippy::module_name_repetitions`, passed
+    /// with `-A` after the other lints so that they take precedence.
+    ///
+    /// By default, this is empty.
+    ///
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clippy_allow: Vec<String>,
+
+    /// Determines whether the run stops at the first failing step.
+    ///
+    /// When `false`, every step is executed and all failures are reported together.
+    /// By default, this is set to `true`.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub fail_fast: bool,
+
+    /// Passes `--locked` to every cargo invocation that resolves dependencies, so that
+    /// the run fails instead of silently updating `Cargo.lock`.
+    ///
+    /// By default, this is `true` when the `CI` environment variable is set.
+    ///
+    #[builder(default = "crate::ops::is_ci()")]
+    #[serde(default = "crate::ops::is_ci")]
+    pub locked: bool,
+
+    /// Passes `--offline` to every cargo invocation that resolves dependencies.
+    ///
+    /// By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub offline: bool,
+
+    /// Applies fixes instead of only checking for problems.
+    ///
+    /// In fix mode `cargo fmt` rewrites files and `cargo clippy` runs with
+    /// `--fix --allow-dirty --allow-staged`; the run still fails if clippy issues
+    /// remain afterwards. The files modified by the run are listed in the report.
+    /// By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub fix: bool,
+
+    /// Builds the documentation with `cargo doc --no-deps --all-features`, denying
+    /// rustdoc warnings such as broken intra-doc links.
+    ///
+    /// `RUSTDOCFLAGS="-D warnings"` is only set on the `cargo doc` process. By default,
+    /// this is set to `true`.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub docs: bool,
+
+    /// Checks for unused dependencies with `cargo machete`, falling back to
+    /// `cargo +nightly udeps` when only cargo-udeps is installed.
+    ///
+    /// cargo-machete is installed on demand when neither tool is available. By
+    /// default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub unused_deps: bool,
+
+    /// Dependencies that are never reported as unused, for known false positives
+    /// such as crates only referenced from macro expansions.
+    ///
+    #[builder(setter(custom), default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unused_deps_ignore: Vec<String>,
+
+    /// Enforces the advisory, license, ban, and source policies of `deny.toml` with
+    /// `cargo deny check`.
+    ///
+    /// cargo-deny is installed on demand, and a minimal `deny.toml` is offered when
+    /// the project has none. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub deny: bool,
+
+    /// Limits `cargo deny check` to the given checks, out of `advisories`, `bans`,
+    /// `licenses`, and `sources`.
+    ///
+    /// By default, this is empty, meaning that every check runs.
+    ///
+    #[builder(setter(custom), default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_checks: Vec<String>,
+
+    /// Runs `cargo check` before each `test` step, so that compilation errors are
+    /// reported quickly and the tests are skipped when the code does not compile.
+    ///
+    /// By default, this is set to `true`.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub check_first: bool,
+
+    /// Runs the tests under Miri with a nightly toolchain, installing the `miri`
+    /// component first.
+    ///
+    /// The steps can be skipped by setting the `XTASKS_SKIP_MIRI` environment
+    /// variable. By default, this is `None`.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub miri: Option<MiriConfig>,
+
+    /// Fails the run when the line coverage measured by the coverage task is below
+    /// this percentage, e.g. `80.0`.
+    ///
+    /// The check runs after the tests pass, and is skipped with a warning when
+    /// tarpaulin is the only option on a platform it does not support. By
+    /// default, this is `None`.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coverage_threshold: Option<f32>,
+
+    /// Spell checks the workspace with the `typos` CLI, which is installed when
+    /// missing. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub typos: bool,
+
+    /// A custom configuration file passed to `typos --config`.
+    ///
+    /// By default, this is `None`, and `typos` discovers its configuration itself.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typos_config: Option<PathBuf>,
+
+    /// Checks that the dependency tables of the workspace manifests are
+    /// sorted, see [`sort`](crate::tasks::manifest::sort). By default, this
+    /// is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub sort_deps: bool,
+
+    /// Checks that the project compiles with the minimal versions allowed by its
+    /// dependency requirements, using `cargo +nightly update -Z minimal-versions`.
+    ///
+    /// `Cargo.lock` is restored afterwards. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub minimal_versions: bool,
+
+    /// Checks the public API against the last published release with
+    /// `cargo semver-checks check-release`, installing it on demand.
+    ///
+    /// The step fails when the current version number does not accommodate the
+    /// detected changes. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub semver_check: bool,
+
+    /// Emits GitHub Actions annotations for the diagnostics of the `clippy` and
+    /// `test` steps, which then run with `--message-format json`.
+    ///
+    /// The human-readable output is still printed. By default, this is `true` when
+    /// the `GITHUB_ACTIONS` environment variable is `true`.
+    ///
+    #[builder(default = "github_actions()")]
+    #[serde(default = "github_actions")]
+    pub annotations: bool,
+
+    /// Captures the output of each command step and only prints it when the step
+    /// fails, showing a single status line for every passing step instead.
+    ///
+    /// The output of failing steps is kept in the report and printed with the
+    /// summary, prefixed with the step name. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub quiet: bool,
+
+    /// Passes `--verbose` to the cargo invocations of the built-in steps.
+    ///
+    /// By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub verbose: bool,
+
+    /// Arguments passed to the test binaries of the `test` steps, after `--`,
+    /// e.g. `--nocapture`.
+    ///
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub test_args: Vec<String>,
+
+    /// Selects the feature flags passed to the `clippy` and `test` steps.
+    ///
+    /// By default, this is `None`, meaning that the default features are used
+    /// (and all features when `clippy_max` is enabled).
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<FeatureSelection>,
+
+    /// Additional user-defined steps, executed after the built-in steps in insertion order.
+    ///
+    /// Use [`CIBuilder::add_step`] or [`CIBuilder::add_command_step`] to register them.
+    ///
+    #[builder(setter(custom), default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<CiStep>,
 }
 
-impl CIBuilder {
+/// Returns `true` when running inside GitHub Actions.
+fn github_actions() -> bool {
+    std::env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true")
+}
+
+/// Returns `true`, for options that are enabled unless configured otherwise.
+const fn default_true() -> bool {
+    true
+}
+
+impl Default for CI {
+    /// Returns the configuration built from the builder defaults, except for
+    /// [`CI::clippy_max`], which `CI::default()` has always left off.
+    fn default() -> Self {
+        CIBuilder::default()
+            .clippy_max(false)
+            .build()
+            .expect("the builder defaults are a valid configuration")
+    }
+}
+
+/// The environment variable that skips the Miri steps, even when configured.
+pub const SKIP_MIRI_ENV: &str = "XTASKS_SKIP_MIRI";
+
+/// Configures the Miri steps, which run tests under the Miri interpreter to detect
+/// undefined behavior in `unsafe` code.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct MiriConfig {
+    /// The packages to test, each reported as its own step. An empty list tests
+    /// the whole workspace in a single step.
+    pub packages: Vec<String>,
+    /// The flags passed to Miri through `MIRIFLAGS`, e.g. `-Zmiri-strict-provenance`.
+    pub flags: Vec<String>,
+}
+
+impl MiriConfig {
+    /// Returns the steps installing Miri and running the tests under it.
+    ///
+    /// When [`SKIP_MIRI_ENV`] is set, a single `miri` step is returned instead,
+    /// which reports itself as skipped.
+    pub fn steps(&self) -> Vec<CiStep> {
+        if crate::ops::env_flag(SKIP_MIRI_ENV) {
+            return vec![CiStep::closure("miri", || {
+                Err(StepSkipped::new(format!("{SKIP_MIRI_ENV} is set"))
+                    .into())
+            })];
+        }
+
+        let mut steps = vec![CiStep::command(
+            "miri setup",
+            CommandSpec::new("rustup").args([
+                "+nightly",
+                "component",
+                "add",
+                "miri",
+            ]),
+        )];
+        let test = CommandSpec::new("cargo")
+            .args(["+nightly", "miri", "test"]);
+        let test = if self.flags.is_empty() {
+            test
+        } else {
+            test.env("MIRIFLAGS", self.flags.join(" "))
+        };
+        let runs = if self.packages.is_empty() {
+            vec![("miri".to_string(), test)]
+        } else {
+            self.packages
+                .iter()
+                .map(|package| {
+                    (
+                        format!("miri ({package})"),
+                        test.clone().args(["--package", package]),
+                    )
+                })
+                .collect()
+        };
+        for (name, command) in runs {
+            let mut step = CiStep::command(name, command);
+            step.depends_on = vec!["miri setup".to_string()];
+            steps.push(step);
+        }
+        steps
+    }
+}
+
+/// Selects which cargo feature flags the build and test steps are run with.
+///
+/// Every requested variant produces its own run of the steps: `all` maps to
+/// `--all-features`, `none_default` to `--no-default-features` (combined with
+/// `list` when both are set), and `list` alone to `--features a,b`.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(default)]
+pub struct FeatureSelection {
+    /// Runs a variant with `--all-features`.
+    pub all: bool,
+    /// Runs a variant with `--no-default-features`.
+    pub none_default: bool,
+    /// Features to enable explicitly with `--features`.
+    pub list: Vec<String>,
+}
+
+/// A single set of feature flags derived from a [`FeatureSelection`].
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct FeatureVariant {
+    /// A short label identifying the variant in reports.
+    pub label: String,
+    /// The cargo arguments selecting the features.
+    pub args: Vec<String>,
+}
+
+impl FeatureSelection {
+    /// Returns the feature variants to run, in a stable order.
+    ///
+    /// An empty selection yields a single variant with no arguments, which uses the
+    /// crate's default features.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::ci::FeatureSelection;
+    ///
+    /// let selection = FeatureSelection {
+    ///     none_default: true,
+    ///     list: vec!["json".into()],
+    ///     ..FeatureSelection::default()
+    /// };
+    /// let variants = selection.variants();
+    /// assert_eq!(
+    ///     variants[0].args,
+    ///     ["--no-default-features", "--features", "json"]
+    /// );
+    /// ```
+    pub fn variants(&self) -> Vec<FeatureVariant> {
+        let list = self.list.join(",");
+        let mut variants = Vec::new();
+        if self.all {
+            variants.push(FeatureVariant {
+                label: "all-features".to_string(),
+                args: vec!["--all-features".to_string()],
+            });
+        }
+        if self.none_default {
+            let mut variant = FeatureVariant {
+                label: "no-default-features".to_string(),
+                args: vec!["--no-default-features".to_string()],
+            };
+            if !list.is_empty() {
+                variant.label = format!("no-default-features,{list}");
+                variant
+                    .args
+                    .extend(["--features".to_string(), list.clone()]);
+            }
+            variants.push(variant);
+        } else if !list.is_empty() {
+            variants.push(FeatureVariant {
+                label: format!("features={list}"),
+                args: vec!["--features".to_string(), list],
+            });
+        }
+        if variants.is_empty() {
+            variants.push(FeatureVariant::default());
+        }
+        variants
+    }
+
+    /// Returns the cargo arguments for a single build with the selection:
+    /// `--all-features` when `all` is set, and the `none_default` and `list`
+    /// flags combined otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::ci::FeatureSelection;
+    ///
+    /// let selection = FeatureSelection {
+    ///     none_default: true,
+    ///     list: vec!["json".into(), "yaml".into()],
+    ///     ..FeatureSelection::default()
+    /// };
+    /// assert_eq!(
+    ///     selection.combined_args(),
+    ///     ["--no-default-features", "--features", "json,yaml"]
+    /// );
+    /// ```
+    pub fn combined_args(&self) -> Vec<String> {
+        if self.all {
+            return vec!["--all-features".to_string()];
+        }
+        let mut args = Vec::new();
+        if self.none_default {
+            args.push("--no-default-features".to_string());
+        }
+        if !self.list.is_empty() {
+            args.extend([
+                "--features".to_string(),
+                self.list.join(","),
+            ]);
+        }
+        args
+    }
+}
+
+/// The signature of a user-defined CI step.
+type StepFn = dyn Fn() -> AnyResult<()> + Send;
+
+/// A shareable handle to a user-defined step closure.
+#[derive(Clone)]
+struct StepClosure(Arc<Mutex<Box<StepFn>>>);
+
+impl StepClosure {
+    fn call(&self) -> AnyResult<()> {
+        let f = self.0.lock().map_err(|_| {
+            AnyError::msg("A previous run of this step panicked")
+        })?;
+        f()
+    }
+}
+
+impl fmt::Debug for StepClosure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<closure>")
+    }
+}
+
+impl PartialEq for StepClosure {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for StepClosure {}
+
+impl Hash for StepClosure {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<()>().hash(state);
+    }
+}
+
+/// A single step of a CI run.
+///
+/// A step either runs an external command, which is recorded in reports and can be
+/// serialized, or calls a Rust closure registered with [`CIBuilder::add_step`].
+/// Closures cannot be serialized; a deserialized step without a command fails when run.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CiStep {
+    /// The name of the step, as shown in the summary report.
+    pub name: String,
+
+    /// The command executed by the step, if it is a command step.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<CommandSpec>,
+
+    /// The toolchain the step runs with, for the built-in steps of a toolchain matrix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+
+    #[serde(skip)]
+    closure: Option<StepClosure>,
+
+    #[serde(skip)]
+    hint: Option<String>,
+
+    #[serde(skip)]
+    annotate: bool,
+
+    #[serde(skip)]
+    depends_on: Vec<String>,
+}
+
+impl CiStep {
+    /// Creates a step that runs an external command.
+    pub fn command<S: Into<String>>(
+        name: S,
+        command: CommandSpec,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            command: Some(command),
+            toolchain: None,
+            closure: None,
+            hint: None,
+            annotate: false,
+            depends_on: Vec::new(),
+        }
+    }
+
+    /// Creates a step that calls a Rust closure.
+    pub fn closure<S, F>(name: S, f: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn() -> AnyResult<()> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            command: None,
+            toolchain: None,
+            closure: Some(StepClosure(Arc::new(Mutex::new(Box::new(
+                f,
+            ))))),
+            hint: None,
+            annotate: false,
+            depends_on: Vec::new(),
+        }
+    }
+
+    /// Attaches a hint that is appended to the error message when the step fails.
+    #[must_use]
+    pub fn with_hint<S: Into<String>>(mut self, hint: S) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    /// Sets whether the step runs its cargo command with
+    /// `--message-format json` output turned into GitHub Actions annotations.
+    #[must_use]
+    pub fn with_annotations(mut self, annotate: bool) -> Self {
+        self.annotate = annotate;
+        self
+    }
+
+    /// Marks the step as running with the given toolchain.
+    #[must_use]
+    pub fn with_toolchain<S: Into<String>>(
+        mut self,
+        toolchain: S,
+    ) -> Self {
+        self.toolchain = Some(toolchain.into());
+        self
+    }
+
+    /// Returns the context attached to errors of the step's command.
+    fn failure_context(&self, command: &CommandSpec) -> String {
+        match &self.hint {
+            Some(hint) => {
+                format!("Failed to execute '{command}' ({hint})")
+            }
+            None => format!("Failed to execute '{command}'"),
+        }
+    }
+
+    /// Executes the step, capturing the output of its command.
+    ///
+    /// Returns the outcome together with the captured stdout and stderr. Closures
+    /// are executed as usual and produce no captured output.
+    fn execute_captured(&self) -> (AnyResult<()>, String) {
+        let Some(command) =
+            self.command.as_ref().filter(|_| self.closure.is_none())
+        else {
+            return (self.execute(), String::new());
+        };
+        if self.annotate {
+            return self.execute_annotated(command, true);
+        }
+        self.capture_command(command)
+    }
+
+    /// Runs the step's command with its output captured.
+    fn capture_command(
+        &self,
+        command: &CommandSpec,
+    ) -> (AnyResult<()>, String) {
+        let captured = command.clone().captured();
+        let (result, out) = self.command_result(
+            command,
+            runner::current().execute(&captured),
+        );
+        (
+            result,
+            out.map(|out| output::combined(&out)).unwrap_or_default(),
+        )
+    }
+
+    /// Returns the outcome of the step's `command` from the result of
+    /// executing it, along with its output if it could be spawned.
+    fn command_result(
+        &self,
+        command: &CommandSpec,
+        executed: std::io::Result<Output>,
+    ) -> (AnyResult<()>, Option<Output>) {
+        match executed {
+            Ok(out) => {
+                let result = if out.status.success() {
+                    Ok(())
+                } else {
+                    Err(AnyError::msg(format!(
+                        "'{command}' exited with {}",
+                        out.status
+                    ))
+                    .context(self.failure_context(command)))
+                };
+                (result, Some(out))
+            }
+            Err(err) => (
+                Err(AnyError::new(err)
+                    .context(format!("Failed to spawn '{command}'"))
+                    .context(self.failure_context(command))),
+                None,
+            ),
+        }
+    }
+
+    /// Runs the step's cargo command with `--message-format json` output,
+    /// printing GitHub Actions annotations for its diagnostics.
+    ///
+    /// Unless `quiet` is set, the human-readable output is printed line by
+    /// line while the command runs, so long steps show their progress; it is
+    /// returned either way.
+    fn execute_annotated(
+        &self,
+        command: &CommandSpec,
+        quiet: bool,
+    ) -> (AnyResult<()>, String) {
+        let (result, found, human) = if quiet {
+            let (result, text) = self.capture_command(command);
+            let (found, human) =
+                annotations::process_cargo_output(&text);
+            (result, found, human)
+        } else {
+            let mut cargo_output = annotations::CargoOutput::default();
+            let executed = runner::current()
+                .execute_streaming(command, &mut |line| {
+                    print!("{}", cargo_output.push_line(line))
+                });
+            let (result, _) = self.command_result(command, executed);
+            let (found, human) = cargo_output.finish();
+            (result, found, human)
+        };
+        for line in annotations::render_annotations(
+            &found,
+            annotations::MAX_ANNOTATIONS,
+        ) {
+            println!("{line}");
+        }
+        (result, human)
+    }
+
+    /// Executes the step.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command or closure fails, or if the step has nothing to run.
+    pub fn execute(&self) -> AnyResult<()> {
+        match (&self.command, &self.closure) {
+            (_, Some(closure)) => closure.call(),
+            (Some(command), None) if self.annotate => {
+                self.execute_annotated(command, false).0
+            }
+            (Some(command), None) => runner::run(command)
+                .map(|_| ())
+                .with_context(|| self.failure_context(command)),
+            (None, None) => Err(AnyError::msg(format!(
+                "Step '{}' has neither a command nor a closure to run",
+                self.name
+            ))),
+        }
+    }
+}
+
+/// The outcome of a single CI step.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StepStatus {
+    /// The step completed successfully.
+    Passed,
+    /// The step failed with the given error message.
+    Failed(String),
+    /// The step failed on a toolchain listed in `allow_failure`; the failure is
+    /// reported as a warning and does not fail the run.
+    Warned(String),
+    /// The step was not run because an earlier step failed, or because it
+    /// returned a [`StepSkipped`] error.
+    Skipped,
+}
+
+/// An error returned by a step that cannot run in the current environment, e.g.
+/// because its tool does not support the platform.
+///
+/// The step is reported as [`StepStatus::Skipped`] with a warning instead of
+/// failing the run.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StepSkipped {
+    /// Why the step was skipped.
+    pub reason: String,
+}
+
+impl StepSkipped {
+    /// Creates a new `StepSkipped` error with the given reason.
+    pub fn new<S: Into<String>>(reason: S) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for StepSkipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped: {}", self.reason)
+    }
+}
+
+impl std::error::Error for StepSkipped {}
+
+/// The timing and outcome of a single CI step.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StepReport {
+    /// The name of the step.
+    pub name: String,
+    /// The command executed by the step, if it is a command step.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<CommandSpec>,
+    /// The toolchain the step ran with, if it belongs to a toolchain matrix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// The outcome of the step.
+    pub status: StepStatus,
+    /// The captured output of the step, kept for failing steps in quiet mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// How long the step took to run.
+    pub duration: Duration,
+}
+
+/// The summary of a CI run, listing every step in execution order.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct CiReport {
+    /// The reports for each step, in the order the steps were scheduled.
+    pub steps: Vec<StepReport>,
+    /// The files modified by the run, when running in fix mode.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modified_files: Vec<PathBuf>,
+}
+
+impl CiReport {
+    /// Returns `true` if no step failed.
+    pub fn success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    /// Returns the reports of the steps that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step.status, StepStatus::Failed(_)))
+    }
+
+    /// Returns the reports of the steps that failed on an allowed toolchain.
+    pub fn warnings(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step.status, StepStatus::Warned(_)))
+    }
+
+    /// Returns the total time spent running steps.
+    pub fn total_duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.duration).sum()
+    }
+}
+
+impl fmt::Display for CiReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .steps
+            .iter()
+            .map(|step| step.name.len())
+            .max()
+            .unwrap_or(0);
+        // Group the steps by toolchain once more than one toolchain is involved.
+        let grouped = self
+            .steps
+            .iter()
+            .any(|step| step.toolchain != self.steps[0].toolchain);
+        writeln!(f, "CI summary:")?;
+        let mut group = None;
+        for (index, step) in self.steps.iter().enumerate() {
+            if grouped && (index == 0 || step.toolchain != group) {
+                group.clone_from(&step.toolchain);
+                match &step.toolchain {
+                    Some(toolchain) => writeln!(f, " {toolchain}:")?,
+                    None => writeln!(f, " other steps:")?,
+                }
+            }
+            let (label, detail) = match &step.status {
+                StepStatus::Passed => ("PASS", String::new()),
+                StepStatus::Failed(error) => {
+                    ("FAIL", format!("  {error}"))
+                }
+                StepStatus::Warned(error) => {
+                    ("WARN", format!("  {error}"))
+                }
+                StepStatus::Skipped => ("SKIP", String::new()),
+            };
+            writeln!(
+                f,
+                "  {label}  {:<width$}  {:>8.2}s{detail}",
+                step.name,
+                step.duration.as_secs_f64(),
+            )?;
+        }
+        for step in &self.steps {
+            if let Some(text) = &step.output {
+                writeln!(f, "Output of {}:", step.name)?;
+                f.write_str(&output::prefix_lines(&step.name, text))?;
+            }
+        }
+        write!(
+            f,
+            "  total {:.2}s",
+            self.total_duration().as_secs_f64()
+        )?;
+        if !self.modified_files.is_empty() {
+            write!(f, "\nFiles modified:")?;
+            for path in &self.modified_files {
+                write!(f, "\n  {}", path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CI {
+    /// Returns the toolchains the built-in steps run with, taking the legacy
+    /// `nightly` flag into account.
+    pub fn resolved_toolchains(&self) -> Vec<String> {
+        if self.toolchains.is_empty() {
+            let toolchain =
+                if self.nightly { "nightly" } else { "stable" };
+            vec![toolchain.to_string()]
+        } else {
+            self.toolchains.clone()
+        }
+    }
+
+    /// Returns `true` if the built-in steps run through `rustup run` for each of
+    /// the [`resolved_toolchains`](Self::resolved_toolchains), rather than once
+    /// with the active toolchain.
+    fn uses_matrix(&self) -> bool {
+        self.nightly || !self.toolchains.is_empty()
+    }
+
+    /// Returns the toolchain the `fmt` step runs with: the first of the
+    /// [`resolved_toolchains`](Self::resolved_toolchains), or `None` for the
+    /// active toolchain when no toolchain is configured.
+    pub fn fmt_toolchain(&self) -> Option<String> {
+        if self.uses_matrix() {
+            self.resolved_toolchains().into_iter().next()
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if failures on `toolchain` are downgraded to warnings.
+    fn allows_failure(&self, toolchain: Option<&str>) -> bool {
+        toolchain.is_some_and(|toolchain| {
+            self.allow_failure
+                .iter()
+                .any(|allowed| allowed == toolchain)
+        })
+    }
+
+    /// Returns a `cargo <subcommand>` invocation carrying the lockfile and network
+    /// flags of this configuration.
+    ///
+    /// `cargo fmt` does not resolve dependencies and is not built through here.
+    fn cargo(
+        &self,
+        toolchain: Option<&str>,
+        subcommand: &str,
+    ) -> CommandSpec {
+        let mut command = tools::cargo_with(toolchain, subcommand);
+        if self.verbose {
+            command = command.arg("--verbose");
+        }
+        if self.locked {
+            command = command.arg("--locked");
+        }
+        if self.offline {
+            command = command.arg("--offline");
+        }
+        command
+    }
+
+    /// Wraps a cargo invocation in a step, explaining `--locked` failures in
+    /// addition to the given hint.
+    fn cargo_step(
+        &self,
+        name: String,
+        command: CommandSpec,
+        hint: Option<&str>,
+    ) -> CiStep {
+        let mut hints: Vec<&str> = hint.into_iter().collect();
+        if self.locked {
+            hints.push(
+                "running with --locked: if Cargo.lock is out of date, \
+                 run `cargo update` locally and commit the lockfile",
+            );
+        }
+        let step = CiStep::command(name, command);
+        if hints.is_empty() {
+            step
+        } else {
+            step.with_hint(hints.join("; "))
+        }
+    }
+
+    /// Returns the steps of this CI configuration in execution order.
+    ///
+    /// The built-in `fmt`, `clippy`, and `test` steps come first, once per
+    /// toolchain, followed by the user-defined steps in the order they were added.
+    /// When several feature variants are selected, `clippy` and `test` run once per
+    /// variant and are labelled with the variant, e.g. `test [no-default-features]`.
+    pub fn plan(&self) -> Vec<CiStep> {
+        let mut steps = Vec::new();
+        if self.uses_matrix() {
+            for toolchain in &self.resolved_toolchains() {
+                steps.extend(
+                    self.builtin_steps(Some(toolchain))
+                        .into_iter()
+                        .map(|step| step.with_toolchain(toolchain)),
+                );
+            }
+        } else {
+            steps.extend(self.builtin_steps(None));
+        }
+        let optional = self.optional_steps(&steps);
+        steps.extend(optional);
+        steps.extend(self.steps.iter().cloned());
+        steps
+    }
+
+    /// Returns the steps of the optional checks, e.g. Miri or cargo-deny, which
+    /// run once after the built-in steps. The coverage check depends on the
+    /// test steps among `builtin`.
+    fn optional_steps(&self, builtin: &[CiStep]) -> Vec<CiStep> {
+        let mut steps = Vec::new();
+        if let Some(miri) = &self.miri {
+            steps.extend(miri.steps());
+        }
+        if self.deny {
+            let checks = self.deny_checks.clone();
+            steps.push(CiStep::closure("deny", move || {
+                run_cargo_deny(Path::new("."), &checks)
+            }));
+        }
+        if self.unused_deps {
+            let ignore = self.unused_deps_ignore.clone();
+            steps.push(CiStep::closure("unused deps", move || {
+                check_unused_deps(&ignore)
+            }));
+        }
+        if let Some(threshold) = self.coverage_threshold {
+            // Coverage runs once, with the features of the CI run and the
+            // toolchain `fmt` runs with.
+            let coverage = crate::tasks::coverage::Coverage {
+                features: self.features.clone(),
+                toolchain: self.fmt_toolchain(),
+                auto_install: self.auto_install,
+                ..crate::tasks::coverage::Coverage::default()
+            };
+            let mut step = CiStep::closure("coverage", move || {
+                crate::tasks::coverage::check_threshold(
+                    &coverage, threshold,
+                )
+            });
+            // Every test step counts, including the per-variant `test [...]` ones.
+            step.depends_on = builtin
+                .iter()
+                .filter(|step| {
+                    step.name == "test"
+                        || step.name.starts_with("test [")
+                })
+                .map(|step| step.name.clone())
+                .collect();
+            steps.push(step);
+        }
+        if self.typos {
+            let config = self.typos_config.clone();
+            steps.push(CiStep::closure("typos", move || {
+                crate::tasks::typos::typos_with_config(
+                    config.as_deref(),
+                )
+            }));
+        }
+        if self.sort_deps {
+            steps.push(CiStep::closure("sort-deps", || {
+                crate::tasks::manifest::sort(true).map(|_| ())
+            }));
+        }
+        if self.minimal_versions {
+            steps.push(CiStep::closure("minimal versions", || {
+                minimal_versions::check_minimal_versions(Path::new("."))
+            }));
+        }
+        if self.semver_check {
+            steps.push(CiStep::closure("semver", || {
+                let report = crate::tasks::semver::check(None)?;
+                if report.is_compatible() {
+                    Ok(())
+                } else {
+                    Err(AnyError::msg(format!(
+                        "{report} ({})",
+                        report.failures.join(", ")
+                    )))
+                }
+            }));
+        }
+        steps
+    }
+
+    /// Returns the built-in steps for a single toolchain, or for the active
+    /// toolchain when `toolchain` is `None`.
+    fn builtin_steps(&self, toolchain: Option<&str>) -> Vec<CiStep> {
+        let check: &[&str] =
+            if self.fix { &[] } else { &["--", "--check"] };
+        let mut fmt = tools::cargo_with(toolchain, "fmt");
+        if self.verbose {
+            fmt = fmt.arg("--verbose");
+        }
+        let fmt = fmt.args(check.iter().copied());
+
+        let variants = self.features.as_ref().map_or_else(
+            || vec![FeatureVariant::default()],
+            FeatureSelection::variants,
+        );
+        let labelled = variants.len() > 1;
+
+        let mut steps = vec![CiStep::command("fmt", fmt)];
+        for variant in &variants {
+            let name = |step: &str| {
+                if labelled {
+                    format!("{step} [{}]", variant.label)
+                } else {
+                    step.to_string()
+                }
+            };
+
+            let mut clippy = self.cargo(toolchain, "clippy");
+            if self.fix {
+                clippy = clippy.args(CLIPPY_FIX_ARGS);
+            }
+            if self.clippy_max {
+                clippy = clippy.arg("--all-targets");
+                if self.features.is_none() {
+                    clippy = clippy.arg("--all-features");
+                }
+            }
+            clippy = clippy.args(variant.args.iter().cloned());
+            if self.annotations {
+                clippy = clippy.args(["--message-format", "json"]);
+            }
+            clippy = clippy.arg("--").args(clippy_lint_args(
+                self.clippy_max,
+                &self.clippy_warn,
+                &self.clippy_allow,
+            ));
+
+            let mut test = self
+                .cargo(toolchain, "test")
+                .args(variant.args.iter().cloned());
+            if self.annotations {
+                test = test.args(["--message-format", "json"]);
+            }
+            if !self.test_args.is_empty() {
+                test =
+                    test.arg("--").args(self.test_args.iter().cloned());
+            }
+
+            let clippy = self
+                .cargo_step(name("clippy"), clippy, None)
+                .with_annotations(self.annotations);
+            let mut test = self.cargo_step(name("test"), test, None);
+            test.annotate = self.annotations;
+            steps.push(clippy);
+
+            if self.check_first {
+                let mut check =
+                    self.cargo(toolchain, "check").arg("--all-targets");
+                if self.features.is_none() {
+                    check = check.arg("--all-features");
+                }
+                check = check.args(variant.args.iter().cloned());
+                if self.annotations {
+                    check = check.args(["--message-format", "json"]);
+                }
+                let mut check = self.cargo_step(
+                    name("check"),
+                    check,
+                    Some("compilation failed at the check stage, so the tests were skipped"),
+                );
+                check.annotate = self.annotations;
+                test.depends_on = vec![check.name.clone()];
+                steps.push(check);
+            }
+            steps.push(test);
+        }
+
+        if self.docs {
+            let doc = self
+                .cargo(toolchain, "doc")
+                .args(["--no-deps", "--all-features"])
+                .env("RUSTDOCFLAGS", "-D warnings");
+            steps.push(self.cargo_step(
+                "docs".to_string(),
+                doc,
+                Some("rustdoc warnings are denied with RUSTDOCFLAGS=\"-D warnings\""),
+            ));
+        }
+        steps
+    }
+
+    /// Renders a GitHub Actions workflow that runs the same steps as [`CI::run`].
+    ///
+    /// The workflow checks out the repository, installs each configured toolchain
+    /// in a matrix job, and runs the command steps of [`CI::plan`] verbatim,
+    /// followed by the doc tests. Steps that call Rust closures, e.g. `deny` or
+    /// `typos`, are left out with a `# not exported: <step>` comment and a
+    /// warning; [`unexported_steps`](Self::unexported_steps) lists them.
+    /// Toolchains listed in `allow_failure` are marked `continue-on-error`. The output only depends on the configuration, so it can be committed
+    /// and regenerated without spurious diffs: cargo always runs with `--locked`, as it
+    /// does by default on CI, whatever [`CI::locked`] resolved to where the workflow
+    /// was generated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in fix mode, which only makes sense for local runs.
+    pub fn to_github_workflow(&self) -> AnyResult<String> {
+        if self.fix {
+            return Err(AnyError::msg(
+                "Fix mode cannot be emitted as a GitHub Actions workflow",
+            ));
+        }
+
+        // The workflow runs plain cargo commands, whose JSON output nothing would
+        // turn into annotations.
+        let this = Self {
+            annotations: false,
+            locked: true,
+            ..self.clone()
+        };
+        let mut yaml = String::new();
+        yaml.push_str(
+            "# Generated by `cargo xtask ci --emit-workflow`; do not edit by hand.\n",
+        );
+        yaml.push_str("name: CI\n\non:\n  push:\n  pull_request:\n\n");
+        yaml.push_str("jobs:\n  ci:\n");
+        yaml.push_str("    name: CI (${{ matrix.toolchain }})\n");
+        yaml.push_str("    runs-on: ubuntu-latest\n");
+        if !self.allow_failure.is_empty() {
+            let allowed: Vec<String> = self
+                .allow_failure
+                .iter()
+                .map(|toolchain| {
+                    format!("matrix.toolchain == '{toolchain}'")
+                })
+                .collect();
+            yaml.push_str(&format!(
+                "    continue-on-error: ${{{{ {} }}}}\n",
+                allowed.join(" || ")
+            ));
+        }
+        yaml.push_str("    strategy:\n");
+        yaml.push_str(&format!(
+            "      fail-fast: {}\n",
+            self.fail_fast
+        ));
+        yaml.push_str("      matrix:\n        toolchain:\n");
+        for toolchain in self.resolved_toolchains() {
+            yaml.push_str(&format!(
+                "          - {}\n",
+                yaml_scalar(&toolchain)
+            ));
+        }
+        yaml.push_str("    steps:\n");
+        yaml.push_str("      - uses: actions/checkout@v4\n");
+        yaml.push_str("      - name: Install Rust\n        run: |\n");
+        yaml.push_str(
+            "          rustup toolchain install ${{ matrix.toolchain }} --profile minimal --component rustfmt,clippy\n",
+        );
+        yaml.push_str(
+            "          rustup override set ${{ matrix.toolchain }}\n",
+        );
+
+        for step in this.workflow_steps() {
+            match step.command {
+                Some(command) => {
+                    yaml.push_str(&format!(
+                        "      - name: {}\n",
+                        yaml_scalar(&step.name)
+                    ));
+                    if !command.env.is_empty() {
+                        yaml.push_str("        env:\n");
+                        for (key, value) in &command.env {
+                            yaml.push_str(&format!(
+                                "          {key}: {}\n",
+                                yaml_scalar(value)
+                            ));
+                        }
+                    }
+                    yaml.push_str(&format!(
+                        "        run: {}\n",
+                        yaml_scalar(&command.to_string())
+                    ));
+                }
+                None => {
+                    eprintln!(
+                        "warning: the '{}' step runs inside xtasks and is not exported to the workflow",
+                        step.name
+                    );
+                    yaml.push_str(&format!(
+                        "      # not exported: {}\n",
+                        step.name
+                    ));
+                }
+            }
+        }
+        Ok(yaml)
+    }
+
+    /// Returns the names of the steps [`to_github_workflow`](Self::to_github_workflow)
+    /// leaves out because they call Rust closures rather than commands.
+    pub fn unexported_steps(&self) -> Vec<String> {
+        self.workflow_steps()
+            .into_iter()
+            .filter(|step| step.command.is_none())
+            .map(|step| step.name)
+            .collect()
+    }
+
+    /// Returns the steps of the emitted workflow, in order: the built-in steps,
+    /// the optional checks, the custom steps and the doc tests.
+    fn workflow_steps(&self) -> Vec<CiStep> {
+        // The job runs on the matrix toolchain, so the steps need no prefix.
+        let builtin = self.builtin_steps(None);
+        let optional = self.optional_steps(&builtin);
+        let doc_tests = self.cargo(None, "test").arg("--doc");
+        builtin
+            .into_iter()
+            .chain(optional)
+            .chain(self.steps.iter().cloned())
+            .chain([CiStep::command("doc tests", doc_tests)])
+            .collect()
+    }
+
+    /// Executes the configured CI steps and returns the report.
+    ///
+    /// Failing steps are recorded in the report rather than returned as errors.
+    pub fn run_with_report(&self) -> CiReport {
+        let mut report = CiReport::default();
+        let before = if self.fix {
+            git::Snapshot::take(Path::new("."))
+                .map_err(|err| {
+                    eprintln!(
+                        "warning: cannot list files modified by fix mode: {err:#}"
+                    );
+                })
+                .ok()
+        } else {
+            None
+        };
+
+        let mut failed = false;
+        // Steps that failed or were skipped, which their dependents skip on.
+        let mut failed_steps = Vec::new();
+        let mut installed: HashMap<String, Result<(), String>> =
+            HashMap::new();
+        for step in self.plan() {
+            // Steps outside the toolchain matrix depend on every toolchain.
+            let dependency_failed =
+                step.depends_on.iter().any(|dependency| {
+                    failed_steps.iter().any(|(toolchain, name)| {
+                        name == dependency
+                            && (step.toolchain.is_none()
+                                || *toolchain == step.toolchain)
+                    })
+                });
+            if (failed && self.fail_fast) || dependency_failed {
+                failed_steps
+                    .push((step.toolchain.clone(), step.name.clone()));
+                report.steps.push(StepReport {
+                    name: step.name,
+                    command: step.command,
+                    toolchain: step.toolchain,
+                    status: StepStatus::Skipped,
+                    duration: Duration::ZERO,
+                    output: None,
+                });
+                continue;
+            }
+
+            let start = Instant::now();
+            let mut captured = String::new();
+            let mut skipped = None;
+            let mut execute = || {
+                let result = if self.quiet {
+                    let (result, text) = step.execute_captured();
+                    captured = text;
+                    result
+                } else {
+                    step.execute()
+                };
+                match result {
+                    Err(err) if err.is::<StepSkipped>() => {
+                        skipped = Some(err.to_string());
+                        Ok(())
+                    }
+                    result => result.map_err(|err| format!("{err:#}")),
+                }
+            };
+            let result = match &step.toolchain {
+                Some(toolchain) => installed
+                    .entry(toolchain.clone())
+                    .or_insert_with(|| {
+                        tools::ensure_toolchain(
+                            toolchain,
+                            &["rustfmt", "clippy"],
+                            self.auto_install,
+                        )
+                        .map(|_| ())
+                        .map_err(|err| format!("{err:#}"))
+                    })
+                    .clone()
+                    .and_then(|()| execute()),
+                None => execute(),
+            };
+            if result.is_err() || skipped.is_some() {
+                failed_steps
+                    .push((step.toolchain.clone(), step.name.clone()));
+            }
+            if let Some(reason) = &skipped {
+                eprintln!("warning: '{}' {reason}", step.name);
+            }
+            let status = match result {
+                Ok(()) if skipped.is_some() => StepStatus::Skipped,
+                Ok(()) => StepStatus::Passed,
+                Err(error)
+                    if self
+                        .allows_failure(step.toolchain.as_deref()) =>
+                {
+                    StepStatus::Warned(error)
+                }
+                Err(error) => {
+                    failed = true;
+                    StepStatus::Failed(error)
+                }
+            };
+            let duration = start.elapsed();
+            let output = if self.quiet {
+                let outcome = match status {
+                    StepStatus::Passed => "ok",
+                    StepStatus::Warned(_) => "FAILED (allowed)",
+                    StepStatus::Skipped => "skipped",
+                    _ => "FAILED",
+                };
+                println!(
+                    "{} ... {outcome} ({:.2}s)",
+                    step.name,
+                    duration.as_secs_f64()
+                );
+                Some(captured)
+                    .filter(|_| status != StepStatus::Passed)
+                    .filter(|text| !text.is_empty())
+            } else {
+                None
+            };
+            report.steps.push(StepReport {
+                name: step.name,
+                command: step.command,
+                toolchain: step.toolchain,
+                status,
+                duration,
+                output,
+            });
+        }
+
+        if let Some(before) = before {
+            if let Ok(after) = git::Snapshot::take(Path::new(".")) {
+                report.modified_files = after.changed_since(&before);
+            }
+        }
+        report
+    }
+
     /// Executes the configured CI tasks.
     ///
     /// This method runs various cargo commands like `cargo fmt`, `cargo clippy`,
-    /// and `cargo test`, with arguments determined by the CI configuration.
+    /// and `cargo test`, with arguments determined by the CI configuration,
+    /// followed by any user-defined steps, then prints a summary report.
     ///
     /// # Errors
     ///
-    /// Returns an error if any of the cargo commands fail to execute.
+    /// Returns an error if any of the steps fail, with an
+    /// [`XtaskError::StepFailed`] naming the first one.
     pub fn run(&self) -> AnyResult<()> {
-        let CI {
-            nightly,
-            clippy_max,
-        } = self.build().context("Failed to build CI configuration")?;
-
-        if nightly {
-            cmd!(
-                "rustup", "run", "nightly", "cargo", "fmt", "--",
-                "--check"
-            )
-            .run()
-            .context(
-                "Failed to execute 'cargo fmt' with nightly compiler",
-            )?;
-        } else {
-            cmd!("cargo", "fmt", "--", "--check")
-                .run()
-                .context("Failed to execute 'cargo fmt'")?;
+        let report = self.run_with_report();
+        println!("{report}");
+
+        let failed: Vec<String> = report
+            .failures()
+            .map(|step| {
+                let name = match &step.toolchain {
+                    Some(toolchain) => {
+                        format!("'{}' ({toolchain})", step.name)
+                    }
+                    None => format!("'{}'", step.name),
+                };
+                match &step.status {
+                    StepStatus::Failed(error) => {
+                        format!("{name}: {error}")
+                    }
+                    _ => name,
+                }
+            })
+            .collect();
+        let first =
+            report.failures().next().map(|step| step.name.clone());
+        match first {
+            None => Ok(()),
+            Some(step) => {
+                Err(AnyError::new(XtaskError::StepFailed { step })
+                    .context(format!(
+                        "CI failed at step {}",
+                        failed.join("; ")
+                    )))
+            }
         }
+    }
+}
 
-        if clippy_max {
-            cmd!(
-                "cargo",
-                "clippy",
-                "--all-targets",
-                "--all-features",
-                "--",
-                "-D",
-                "warnings",
-                "-W",
-                "clippy::pedantic",
-                "-W",
-                "clippy::nursery"
-            )
+impl CIBuilder {
+    /// Sets the toolchains the built-in steps are run with, e.g.
+    /// `["stable", "beta", "nightly"]`.
+    pub fn toolchains<I, S>(&mut self, toolchains: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.toolchains =
+            Some(toolchains.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the cargo-deny checks to run.
+    pub fn deny_checks<I, S>(&mut self, checks: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.deny_checks =
+            Some(checks.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Rejects the cargo-deny checks that are not one of [`DENY_CHECKS`], so a
+    /// typo is reported when the settings are built rather than when the step runs.
+    fn validate(&self) -> Result<(), String> {
+        match self.deny_checks.iter().flatten().find(|check| {
+            !DENY_CHECKS.contains(&check.as_str())
+        }) {
+            Some(unknown) => Err(format!(
+                "Unknown cargo-deny check '{unknown}', expected one of: {}",
+                DENY_CHECKS.join(", ")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the dependencies that are never reported as unused.
+    pub fn unused_deps_ignore<I, S>(&mut self, crates: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.unused_deps_ignore =
+            Some(crates.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the toolchains whose failures are downgraded to warnings.
+    pub fn allow_failure<I, S>(&mut self, toolchains: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allow_failure =
+            Some(toolchains.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Adds a user-defined step that calls the given closure.
+    ///
+    /// Custom steps run after the built-in steps, in insertion order, and are timed
+    /// and reported under `name` like any other step.
+    pub fn add_step<F>(&mut self, name: &str, f: F) -> &mut Self
+    where
+        F: Fn() -> AnyResult<()> + Send + 'static,
+    {
+        self.steps
+            .get_or_insert_with(Vec::new)
+            .push(CiStep::closure(name, f));
+        self
+    }
+
+    /// Adds a user-defined step that runs an external command.
+    ///
+    /// Unlike closures, command steps are recorded in the report and survive
+    /// serialization of the configuration.
+    pub fn add_command_step<I, S>(
+        &mut self,
+        name: &str,
+        program: &str,
+        args: I,
+    ) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.steps
+            .get_or_insert_with(Vec::new)
+            .push(CiStep::command(
+                name,
+                CommandSpec::new(program).args(args),
+            ));
+        self
+    }
+
+    /// Builds the configuration and executes it, returning the report.
+    ///
+    /// See [`CI::run_with_report`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error only if the configuration cannot be built.
+    pub fn run_with_report(&self) -> AnyResult<CiReport> {
+        Ok(self
+            .build()
+            .context("Failed to build CI configuration")?
+            .run_with_report())
+    }
+
+    /// Builds the configuration and executes the CI tasks.
+    ///
+    /// This is a convenience for `self.build()?.run()`; see [`CI::run`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration cannot be built or any of the steps fail.
+    pub fn run(&self) -> AnyResult<()> {
+        self.build()
+            .context("Failed to build CI configuration")?
             .run()
-            .context("Failed to execute 'cargo clippy'")?;
+    }
+}
+
+/// The checks supported by `cargo deny check`.
+pub const DENY_CHECKS: [&str; 4] =
+    ["advisories", "bans", "licenses", "sources"];
+
+/// The `deny.toml` written when a project has none.
+const DENY_TOML: &str = r#"# Minimal cargo-deny configuration generated by `cargo xtask ci`.
+# See https://embarkstudios.github.io/cargo-deny/ for all options.
+
+[advisories]
+version = 2
+yanked = "deny"
+
+[licenses]
+version = 2
+allow = ["Apache-2.0", "MIT", "Unicode-DFS-2016", "Unicode-3.0"]
+
+[bans]
+multiple-versions = "warn"
+wildcards = "deny"
+
+[sources]
+unknown-registry = "deny"
+unknown-git = "deny"
+"#;
+
+/// Returns the `cargo deny check` invocation for the given checks.
+///
+/// An empty list runs every check.
+///
+/// # Errors
+///
+/// Returns an error if a check is not one of [`DENY_CHECKS`].
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::ci::deny_command;
+///
+/// let command = deny_command(&["bans".into(), "licenses".into()]).unwrap();
+/// assert_eq!(command.to_string(), "cargo deny check bans licenses");
+/// ```
+pub fn deny_command(checks: &[String]) -> AnyResult<CommandSpec> {
+    if let Some(unknown) = checks
+        .iter()
+        .find(|check| !DENY_CHECKS.contains(&check.as_str()))
+    {
+        return Err(AnyError::msg(format!(
+            "Unknown cargo-deny check '{unknown}', expected one of: {}",
+            DENY_CHECKS.join(", ")
+        )));
+    }
+    Ok(CommandSpec::new("cargo")
+        .args(["deny", "check"])
+        .args(checks.iter().cloned()))
+}
+
+/// Makes sure `dir` contains a `deny.toml`, offering to create a minimal one.
+///
+/// The offer goes through [`confirm`](crate::ops::confirm), so it can be answered
+/// non-interactively with the `XTASKS_CONFIRM` environment variable. When that is
+/// unset in CI (see [`is_ci`](crate::ops::is_ci)), the offer is declined without
+/// prompting.
+///
+/// # Returns
+///
+/// `true` if a configuration was created by this call.
+///
+/// # Errors
+///
+/// Returns an error if the file is missing and the offer is declined, or it cannot
+/// be written.
+pub fn scaffold_deny_config(dir: &Path) -> AnyResult<bool> {
+    let path = dir.join("deny.toml");
+    if path.exists() {
+        return Ok(false);
+    }
+    let question =
+        "No deny.toml found. Create a minimal cargo-deny configuration?";
+    let accepted = if std::env::var_os(crate::ops::CONFIRM_ENV)
+        .is_none()
+        && crate::ops::is_ci()
+    {
+        println!("{question} [declined: not interactive in CI]");
+        false
+    } else {
+        crate::ops::confirm(question)?
+    };
+    if !accepted {
+        return Err(AnyError::msg(format!(
+            "cargo-deny needs a configuration at {}; create one with \
+             `cargo deny init` or rerun with {}=yes",
+            path.display(),
+            crate::ops::CONFIRM_ENV
+        )));
+    }
+    crate::ops::write_atomic(&path, DENY_TOML)?;
+    println!("Created {}", path.display());
+    Ok(true)
+}
+
+/// Summarizes the diagnostics of a `cargo deny check` run, e.g.
+/// `2 errors, 1 warning (advisories ok, bans FAILED)`.
+pub fn summarize_deny_output(output: &str) -> String {
+    let count = |prefix: &str| {
+        output
+            .lines()
+            .filter(|line| line.trim_start().starts_with(prefix))
+            .count()
+    };
+    let plural = |count: usize, noun: &str| {
+        if count == 1 {
+            format!("{count} {noun}")
         } else {
-            cmd!("cargo", "clippy", "--", "-D", "warnings")
-                .run()
-                .context("Failed to execute 'cargo clippy'")?;
+            format!("{count} {noun}s")
         }
+    };
+    let mut summary = format!(
+        "{}, {}",
+        plural(count("error["), "error"),
+        plural(count("warning["), "warning")
+    );
+    // cargo-deny ends with a line such as `advisories ok, bans FAILED, ...`.
+    if let Some(checks) =
+        output.lines().rev().map(str::trim).find(|line| {
+            DENY_CHECKS
+                .iter()
+                .any(|check| line.starts_with(&format!("{check} ")))
+        })
+    {
+        summary.push_str(&format!(" ({checks})"));
+    }
+    summary
+}
 
-        cmd!("cargo", "test")
-            .run()
-            .context("Failed to execute 'cargo test'")?;
+/// Runs `cargo deny check` in `dir`, installing cargo-deny and scaffolding its
+/// configuration first when needed.
+fn run_cargo_deny(dir: &Path, checks: &[String]) -> AnyResult<()> {
+    let command = deny_command(checks)?;
+    tools::ensure_tool_installed(
+        "cargo-deny",
+        &["cargo-deny", "--locked"],
+    )?;
+    let _ = scaffold_deny_config(dir)?;
+
+    let output = runner::current()
+        .execute(&command.clone().captured())
+        .with_context(|| format!("Failed to spawn '{command}'"))?;
+    let text = output::combined(&output);
+    eprint!("{text}");
+    if output.status.success() {
         Ok(())
+    } else {
+        Err(AnyError::msg(format!(
+            "'{command}' exited with {}",
+            output.status
+        ))
+        .context(format!(
+            "cargo-deny reported {}",
+            summarize_deny_output(&text)
+        )))
+    }
+}
+
+/// A dependency reported as unused by cargo-machete or cargo-udeps.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct UnusedDependency {
+    /// The workspace package declaring the dependency.
+    pub package: String,
+    /// The name of the unused dependency.
+    pub name: String,
+}
+
+impl fmt::Display for UnusedDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (in {})", self.name, self.package)
+    }
+}
+
+/// Parses the report printed by `cargo machete`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::ci::parse_machete_output;
+///
+/// let output = "cargo-machete found the following unused dependencies in this directory:\n\
+///               app -- ./Cargo.toml:\n\
+///               \tregex\n\n";
+/// let unused = parse_machete_output(output);
+/// assert_eq!(unused[0].package, "app");
+/// assert_eq!(unused[0].name, "regex");
+/// ```
+pub fn parse_machete_output(output: &str) -> Vec<UnusedDependency> {
+    let mut unused = Vec::new();
+    let mut package = None;
+    for line in output.lines() {
+        if let Some((name, manifest)) = line.split_once(" -- ") {
+            if manifest.trim_end().ends_with("Cargo.toml:") {
+                package = Some(name.trim().to_string());
+                continue;
+            }
+        }
+        match &package {
+            Some(current)
+                if line.starts_with(char::is_whitespace)
+                    && !line.trim().is_empty() =>
+            {
+                unused.push(UnusedDependency {
+                    package: current.clone(),
+                    name: line.trim().to_string(),
+                });
+            }
+            _ => package = None,
+        }
+    }
+    unused
+}
+
+/// Parses the report printed by `cargo udeps`.
+///
+/// Each package is introduced by a line such as `` `app v0.1.0 (/path)` ``,
+/// followed by a tree of its unused dependencies in quotes.
+pub fn parse_udeps_output(output: &str) -> Vec<UnusedDependency> {
+    let mut unused = Vec::new();
+    let mut package = None;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('`') {
+            package =
+                header.split_whitespace().next().map(str::to_string);
+        } else if let (Some(current), Some(start)) =
+            (&package, line.find('"'))
+        {
+            let quoted = &line[start + 1..];
+            if let Some(end) = quoted.find('"') {
+                unused.push(UnusedDependency {
+                    package: current.clone(),
+                    name: quoted[..end].to_string(),
+                });
+            }
+        }
+    }
+    unused
+}
+
+/// Removes the dependencies listed in `ignore` from `unused`.
+pub fn filter_ignored_deps(
+    unused: Vec<UnusedDependency>,
+    ignore: &[String],
+) -> Vec<UnusedDependency> {
+    unused
+        .into_iter()
+        .filter(|dependency| !ignore.contains(&dependency.name))
+        .collect()
+}
+
+/// Runs cargo-machete, or cargo-udeps as a fallback, and fails with the list of
+/// unused dependencies that are not ignored.
+fn check_unused_deps(ignore: &[String]) -> AnyResult<()> {
+    let udeps = !tools::is_installed("cargo-machete")
+        && tools::is_installed("cargo-udeps");
+    let (tool, command) = if udeps {
+        (
+            "cargo-udeps",
+            CommandSpec::new("cargo").args([
+                "+nightly",
+                "udeps",
+                "--all-targets",
+            ]),
+        )
+    } else {
+        tools::ensure_tool_installed(
+            "cargo-machete",
+            &["cargo-machete"],
+        )?;
+        ("cargo-machete", CommandSpec::new("cargo").arg("machete"))
+    };
+
+    let output = runner::current()
+        .execute(&command.clone().captured())
+        .with_context(|| format!("Failed to spawn '{command}'"))?;
+    let text = output::combined(&output);
+    let found = if udeps {
+        parse_udeps_output(&text)
+    } else {
+        parse_machete_output(&text)
+    };
+    if found.is_empty() && !output.status.success() {
+        return Err(AnyError::msg(format!(
+            "'{command}' exited with {}: {}",
+            output.status,
+            text.trim()
+        )));
+    }
+
+    let unused = filter_ignored_deps(found, ignore);
+    if unused.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> =
+        unused.iter().map(ToString::to_string).collect();
+    Err(AnyError::msg(format!(
+        "{tool} found unused dependencies: {} (add false positives to `unused_deps_ignore`)",
+        list.join(", ")
+    )))
+}
+
+/// Formats a string as a YAML scalar, quoting it when a plain scalar would be
+/// misread.
+fn yaml_scalar(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.chars().all(|c| {
+            c.is_ascii_alphanumeric() || " _-./=:,+[]".contains(c)
+        })
+        && !value.contains(": ")
+        && !value.ends_with(':')
+        && !value.starts_with(|c: char| "-:[] ".contains(c))
+        && !value.ends_with(' ');
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "''"))
     }
 }
 
@@ -101,7 +2018,6 @@ impl CIBuilder {
 /// 1. Format check using `cargo fmt`.
 /// 2. Linting using `cargo clippy`.
 /// 3. Run tests using `cargo test`.
-/// 4. Run documentation tests using `cargo test --doc`.
 ///
 /// The specific settings for these tasks, such as whether to use the nightly compiler or to enable all Clippy lints, are determined by the default configuration of the `CI` struct.
 ///
@@ -0,0 +1,304 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! GitHub Actions annotations for compiler diagnostics and test failures.
+//!
+//! Cargo is run with `--message-format json`; the JSON diagnostics are turned back
+//! into their human-readable rendering for the log and into `::error` /
+//! `::warning` workflow commands, which GitHub shows inline on pull requests.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The number of annotations emitted per step; GitHub drops the rest anyway.
+pub const MAX_ANNOTATIONS: usize = 10;
+
+/// The severity of an annotation.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum AnnotationLevel {
+    /// Rendered with `::error`.
+    Error,
+    /// Rendered with `::warning`.
+    Warning,
+}
+
+/// A single GitHub Actions annotation.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Annotation {
+    /// The severity of the annotation.
+    pub level: AnnotationLevel,
+    /// The file the annotation points at, relative to the workspace.
+    pub file: Option<String>,
+    /// The first line of the annotated range.
+    pub line: Option<u64>,
+    /// The last line of the annotated range.
+    pub end_line: Option<u64>,
+    /// The first column of the annotated range.
+    pub column: Option<u64>,
+    /// The last column of the annotated range.
+    pub end_column: Option<u64>,
+    /// A short title, such as the lint name or the failing test.
+    pub title: Option<String>,
+    /// The annotation text.
+    pub message: String,
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let command = match self.level {
+            AnnotationLevel::Error => "error",
+            AnnotationLevel::Warning => "warning",
+        };
+        let mut properties = Vec::new();
+        if let Some(file) = &self.file {
+            properties.push(format!("file={}", escape_property(file)));
+        }
+        let numbers = [
+            ("line", self.line),
+            ("endLine", self.end_line),
+            ("col", self.column),
+            ("endColumn", self.end_column),
+        ];
+        for (name, value) in numbers {
+            if let Some(value) = value {
+                properties.push(format!("{name}={value}"));
+            }
+        }
+        if let Some(title) = &self.title {
+            properties
+                .push(format!("title={}", escape_property(title)));
+        }
+        write!(f, "::{command}")?;
+        if !properties.is_empty() {
+            write!(f, " {}", properties.join(","))?;
+        }
+        write!(f, "::{}", escape_data(&self.message))
+    }
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property value of a workflow command.
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    message: Option<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    message: String,
+    level: String,
+    code: Option<DiagnosticCode>,
+    #[serde(default)]
+    spans: Vec<DiagnosticSpan>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticSpan {
+    file_name: String,
+    line_start: u64,
+    line_end: u64,
+    column_start: u64,
+    column_end: u64,
+    is_primary: bool,
+}
+
+/// Splits the output of a cargo command run with `--message-format json`, one
+/// line at a time, so the human-readable output can be printed as it arrives.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::ci::annotations::CargoOutput;
+///
+/// let mut output = CargoOutput::default();
+/// assert_eq!(output.push_line("running 1 test"), "running 1 test\n");
+/// assert_eq!(
+///     output.push_line(r#"{"reason":"build-finished","success":true}"#),
+///     ""
+/// );
+/// let (annotations, human) = output.finish();
+/// assert!(annotations.is_empty());
+/// assert_eq!(human, "running 1 test\n");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CargoOutput {
+    annotations: Vec<Annotation>,
+    human: String,
+}
+
+impl CargoOutput {
+    /// Processes a line of output, collecting the annotation of its
+    /// diagnostic, and returns the human-readable text for the line: the
+    /// rendered diagnostic in place of its JSON, nothing for the other cargo
+    /// messages, and the line itself otherwise.
+    pub fn push_line(&mut self, line: &str) -> String {
+        let text = self.process_line(line);
+        self.human.push_str(&text);
+        text
+    }
+
+    /// Returns the annotations for the compiler diagnostics and failed tests,
+    /// along with the human-readable output of every line.
+    pub fn finish(mut self) -> (Vec<Annotation>, String) {
+        self.annotations.extend(parse_test_failures(&self.human));
+        (self.annotations, self.human)
+    }
+
+    /// Returns the human-readable text for `line`, see
+    /// [`push_line`](Self::push_line).
+    fn process_line(&mut self, line: &str) -> String {
+        if !line.starts_with('{') {
+            return format!("{line}\n");
+        }
+        let Ok(message) = serde_json::from_str::<CargoMessage>(line)
+        else {
+            return format!("{line}\n");
+        };
+        let Some(diagnostic) = message
+            .message
+            .filter(|_| message.reason == "compiler-message")
+        else {
+            return String::new();
+        };
+        let rendered = diagnostic.rendered.clone().unwrap_or_default();
+        if let Some(annotation) = diagnostic_annotation(diagnostic) {
+            if !self.annotations.contains(&annotation) {
+                self.annotations.push(annotation);
+            }
+        }
+        rendered
+    }
+}
+
+/// Splits the output of a cargo command run with `--message-format json`.
+///
+/// Returns the annotations for the compiler diagnostics and failed tests, along
+/// with the human-readable output: rendered diagnostics in place of their JSON,
+/// other cargo messages dropped, and any other line kept as is.
+pub fn process_cargo_output(output: &str) -> (Vec<Annotation>, String) {
+    let mut cargo_output = CargoOutput::default();
+    for line in output.lines() {
+        let _ = cargo_output.push_line(line);
+    }
+    cargo_output.finish()
+}
+
+/// Converts a compiler diagnostic into an annotation, if it points at code.
+fn diagnostic_annotation(diagnostic: Diagnostic) -> Option<Annotation> {
+    let level = match diagnostic.level.as_str() {
+        "error" | "error: internal compiler error" => {
+            AnnotationLevel::Error
+        }
+        "warning" => AnnotationLevel::Warning,
+        _ => return None,
+    };
+    let span = diagnostic.spans.iter().find(|span| span.is_primary)?;
+    Some(Annotation {
+        level,
+        file: Some(span.file_name.clone()),
+        line: Some(span.line_start),
+        end_line: Some(span.line_end),
+        column: Some(span.column_start),
+        end_column: Some(span.column_end),
+        title: diagnostic.code.map(|code| code.code),
+        message: diagnostic.message,
+    })
+}
+
+/// Finds the panics of failed tests in libtest output.
+///
+/// Both the current `panicked at file:line:col:` form, with the message on the
+/// following lines, and the older `panicked at 'message', file:line:col` form are
+/// recognized.
+pub fn parse_test_failures(output: &str) -> Vec<Annotation> {
+    let lines: Vec<&str> = output.lines().collect();
+    let mut annotations = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        let Some(rest) = line.strip_prefix("thread '") else {
+            continue;
+        };
+        let Some((test, rest)) = rest.split_once('\'') else {
+            continue;
+        };
+        let Some((_, rest)) = rest.split_once("panicked at ") else {
+            continue;
+        };
+
+        let (location, message) =
+            if let Some(quoted) = rest.strip_prefix('\'') {
+                match quoted.rsplit_once("', ") {
+                    Some((message, location)) => {
+                        (location, message.to_string())
+                    }
+                    None => continue,
+                }
+            } else {
+                let message: Vec<&str> = lines[index + 1..]
+                    .iter()
+                    .take_while(|line| {
+                        !line.is_empty()
+                            && !line.starts_with("note:")
+                            && !line.starts_with("thread '")
+                    })
+                    .copied()
+                    .collect();
+                (rest.trim_end_matches(':'), message.join("\n"))
+            };
+
+        let mut parts = location.rsplitn(3, ':');
+        let column = parts.next().and_then(|part| part.parse().ok());
+        let line = parts.next().and_then(|part| part.parse().ok());
+        let file = parts.next().map(str::to_string);
+        annotations.push(Annotation {
+            level: AnnotationLevel::Error,
+            file,
+            line,
+            end_line: None,
+            column,
+            end_column: None,
+            title: Some(format!("test {test} failed")),
+            message,
+        });
+    }
+    annotations
+}
+
+/// Renders at most `max` annotations as workflow commands, followed by a line
+/// summarizing how many were left out.
+pub fn render_annotations(
+    annotations: &[Annotation],
+    max: usize,
+) -> Vec<String> {
+    let mut lines: Vec<String> = annotations
+        .iter()
+        .take(max)
+        .map(ToString::to_string)
+        .collect();
+    if annotations.len() > max {
+        lines.push(format!(
+            "{} more diagnostics were not annotated; see the log above",
+            annotations.len() - max
+        ));
+    }
+    lines
+}
@@ -0,0 +1,134 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Git hooks that run the CI task before changes leave the machine.
+
+use crate::ops::{git, runner};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The marker identifying hook scripts written by xtasks.
+const MARKER: &str = "# Installed by xtasks";
+
+/// The git hooks that can be installed.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum HookKind {
+    /// Runs the full `cargo xtask ci` before every push.
+    PrePush,
+    /// Runs the formatting and lint checks before every commit, for speed.
+    PreCommit,
+}
+
+impl HookKind {
+    /// Returns the file name git uses for the hook.
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Self::PrePush => "pre-push",
+            Self::PreCommit => "pre-commit",
+        }
+    }
+
+    /// Returns the shell script installed for the hook.
+    pub fn script(self) -> String {
+        let commands = match self {
+            Self::PrePush => "exec cargo xtask ci\n",
+            Self::PreCommit => {
+                "cargo fmt -- --check\n\
+                 exec cargo clippy --all-targets -- -D warnings\n"
+            }
+        };
+        format!(
+            "#!/bin/sh\n{MARKER}; rerun `cargo xtask install-hooks` to update it.\n\
+             # Skip it once with `git {} --no-verify`.\nset -e\n{commands}",
+            match self {
+                Self::PrePush => "push",
+                Self::PreCommit => "commit",
+            }
+        )
+    }
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.file_name())
+    }
+}
+
+impl FromStr for HookKind {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        match value {
+            "pre-push" => Ok(Self::PrePush),
+            "pre-commit" => Ok(Self::PreCommit),
+            _ => Err(AnyError::msg(format!(
+                "Unknown hook '{value}', expected pre-push or pre-commit"
+            ))),
+        }
+    }
+}
+
+/// Installs a git hook for the repository containing the current directory.
+///
+/// # Errors
+///
+/// Returns an error if the current directory is not inside a git repository, a
+/// hook not written by xtasks already exists, or the hook cannot be written.
+pub fn install_git_hook(kind: HookKind) -> AnyResult<()> {
+    install_git_hook_in(Path::new("."), kind, false).map(|_| ())
+}
+
+/// Installs a git hook for the repository containing `dir` and returns its path.
+///
+/// The hooks directory is resolved with `git rev-parse --git-path hooks`, so the
+/// hook is shared by all worktrees and honours `core.hooksPath`. An existing hook
+/// written by xtasks is replaced; any other hook is only replaced when `force` is
+/// set.
+///
+/// # Errors
+///
+/// Returns an error if `dir` is not inside a git repository, a foreign hook exists
+/// and `force` is not set, or the hook cannot be written.
+pub fn install_git_hook_in(
+    dir: &Path,
+    kind: HookKind,
+    force: bool,
+) -> AnyResult<PathBuf> {
+    let hooks = runner::query(&git::git(
+        dir,
+        ["rev-parse", "--git-path", "hooks"],
+    ))
+    .context("Failed to locate the git hooks directory")?;
+    let hooks = dir.join(hooks.trim());
+    let path = hooks.join(kind.file_name());
+
+    if let Ok(existing) = fs::read_to_string(&path) {
+        if !existing.contains(MARKER) && !force {
+            return Err(AnyError::msg(format!(
+                "{} already exists and was not installed by xtasks; \
+                 rerun with --force to replace it",
+                path.display()
+            )));
+        }
+    }
+
+    crate::ops::write_atomic(&path, kind.script())?;
+    // A dry run writes nothing, so there is no file to make executable.
+    #[cfg(unix)]
+    if !runner::is_dry_run() {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .with_context(|| {
+            format!("Failed to make {} executable", path.display())
+        })?;
+    }
+    println!("Installed {kind} hook at {}", path.display());
+    Ok(path)
+}
@@ -0,0 +1,124 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Verifies that a crate builds with the lowest versions its dependency
+//! requirements allow.
+
+use crate::ops::{
+    backup_file, output,
+    runner::{self, CommandSpec},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use std::path::Path;
+
+/// Resolves the dependencies of the project in `dir` to their minimal versions and
+/// checks that it still compiles.
+///
+/// `Cargo.lock` is backed up first and restored afterwards, whether the check
+/// passes or not, so the real lockfile is left untouched. Resolving minimal
+/// versions requires a nightly toolchain.
+///
+/// # Errors
+///
+/// Returns an error if the dependencies cannot be resolved or the project does not
+/// compile with them, naming the dependencies whose lower bounds are likely too low.
+pub fn check_minimal_versions(dir: &Path) -> AnyResult<()> {
+    let manifest = dir.join("Cargo.toml");
+    let manifest = manifest.to_string_lossy();
+    let backup = backup_file(dir.join("Cargo.lock"))?;
+
+    runner::run(&CommandSpec::new("cargo").args([
+        "+nightly",
+        "update",
+        "-Z",
+        "minimal-versions",
+        "--manifest-path",
+        &manifest,
+    ]))
+    .context("Failed to resolve minimal dependency versions")?;
+
+    let check = CommandSpec::new("cargo").args([
+        "check",
+        "--all-features",
+        "--manifest-path",
+        &manifest,
+    ]);
+    let out = runner::current()
+        .execute(&check.clone().captured())
+        .with_context(|| format!("Failed to spawn '{check}'"));
+    backup.restore()?;
+
+    let out = out?;
+    let text = output::combined(&out);
+    if out.status.success() {
+        return Ok(());
+    }
+    eprint!("{text}");
+    let suspects = suspect_crates(&text);
+    let hint = if suspects.is_empty() {
+        "a dependency's lower version bound is likely too low"
+            .to_string()
+    } else {
+        format!(
+            "the lower version bound of {} is likely too low",
+            suspects
+                .iter()
+                .map(|name| format!("`{name}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    };
+    Err(AnyError::msg(format!("'{check}' exited with {}", out.status))
+        .context(format!(
+            "The project does not compile with minimal dependency versions: {hint}"
+        )))
+}
+
+/// Extracts the names of the crates implicated in a failed build.
+///
+/// Crates are taken, in order of appearance and without duplicates, from
+/// `could not compile` lines, from source locations inside the cargo registry,
+/// and from `in crate` / `unresolved import` messages.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::ci::minimal_versions::suspect_crates;
+///
+/// let output = "error[E0425]: cannot find function `is_ok_and` in crate `semver`";
+/// assert_eq!(suspect_crates(output), ["semver"]);
+/// ```
+pub fn suspect_crates(output: &str) -> Vec<String> {
+    let mut crates: Vec<String> = Vec::new();
+    let mut push = |name: &str| {
+        let name = name.trim_matches(['`', ':']);
+        if !name.is_empty() && !crates.iter().any(|known| known == name)
+        {
+            crates.push(name.to_string());
+        }
+    };
+    for line in output.lines() {
+        for marker in
+            ["could not compile `", "in crate `", "unresolved import `"]
+        {
+            if let Some((_, rest)) = line.split_once(marker) {
+                let end = rest.find(['`', ':']).unwrap_or(rest.len());
+                push(&rest[..end]);
+            }
+        }
+        if let Some((_, rest)) = line.split_once("/registry/src/") {
+            // `<index>/<name>-<version>/src/...`
+            if let Some(package) = rest.split('/').nth(1) {
+                let name = package
+                    .rsplit_once('-')
+                    .filter(|(_, version)| {
+                        version
+                            .starts_with(|c: char| c.is_ascii_digit())
+                    })
+                    .map_or(package, |(name, _)| name);
+                push(name);
+            }
+        }
+    }
+    crates
+}
@@ -0,0 +1,204 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Removal of the artifacts generated by the tasks.
+//!
+//! By default, [`Clean`] removes the coverage reports and the xtask cache in
+//! `target/xtask`, which holds the bloat baselines, the histories and the
+//! powerset summaries. The docs output and the whole target directory are only
+//! removed on request. Nothing outside the workspace root is ever removed.
+
+use crate::{
+    ops::{
+        self,
+        runner::{self, CommandSpec},
+    },
+    tasks::docs::book::{book_output_dir, DEFAULT_BOOK_DIR},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The directory the tasks keep their cache, baselines and histories in.
+pub const CACHE_DIR: &str = "target/xtask";
+
+/// The directories the coverage engines write their reports to.
+pub const COVERAGE_DIRS: [&str; 2] =
+    ["target/coverage", "target/llvm-cov"];
+
+/// Selects the artifacts removed by a cleanup.
+///
+/// When none of `coverage`, `docs` and `cache` is set, the artifacts generated
+/// by the tasks are removed: the coverage reports and the cache.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct Clean {
+    /// Removes every artifact, then the whole target directory with
+    /// `cargo clean`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub all: bool,
+
+    /// Removes the coverage reports and the `.profraw` files left in the
+    /// workspace root.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub coverage: bool,
+
+    /// Removes the API documentation in `target/doc` and the rendered book.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub docs: bool,
+
+    /// Removes the xtask cache in [`CACHE_DIR`].
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub cache: bool,
+
+    /// Prints what would be removed instead of removing it.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// The directory of the book, relative to the workspace root.
+    ///
+    /// By default, this is set to [`DEFAULT_BOOK_DIR`].
+    #[builder(default = "PathBuf::from(DEFAULT_BOOK_DIR)")]
+    #[serde(default = "default_book_dir")]
+    pub book_dir: PathBuf,
+}
+
+/// Returns the directory of the book when not configured.
+fn default_book_dir() -> PathBuf {
+    PathBuf::from(DEFAULT_BOOK_DIR)
+}
+
+impl Default for Clean {
+    /// Returns the configuration built from the builder defaults, so both
+    /// describe the same cleanup.
+    fn default() -> Self {
+        CleanBuilder::default()
+            .build()
+            .expect("the builder defaults are a valid configuration")
+    }
+}
+
+impl Clean {
+    /// Returns `true` if the configuration removes the default artifacts,
+    /// because no kind of artifact was selected.
+    fn is_default(&self) -> bool {
+        !(self.coverage || self.docs || self.cache)
+    }
+
+    /// Returns the existing artifacts of the workspace in `root` selected by
+    /// the configuration, in the order they are removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the workspace root cannot be searched for
+    /// `.profraw` files.
+    pub fn artifacts(&self, root: &Path) -> AnyResult<Vec<PathBuf>> {
+        let mut artifacts = Vec::new();
+        if self.all || self.coverage || self.is_default() {
+            artifacts
+                .extend(COVERAGE_DIRS.iter().map(|dir| root.join(dir)));
+            let pattern = root.join("*.profraw");
+            for profile in glob(&pattern.to_string_lossy())? {
+                artifacts.push(profile?);
+            }
+        }
+        if self.all || self.docs {
+            artifacts.push(root.join("target/doc"));
+            artifacts.push(book_output_dir(&root.join(&self.book_dir)));
+        }
+        if self.all || self.cache || self.is_default() {
+            artifacts.push(root.join(CACHE_DIR));
+        }
+        artifacts.retain(|path| path.exists());
+        Ok(artifacts)
+    }
+
+    /// Removes the selected artifacts of the workspace in the current
+    /// directory.
+    ///
+    /// # Returns
+    ///
+    /// The removed artifacts, or the ones that would be removed in a dry run.
+    ///
+    /// # Errors
+    ///
+    /// See [`Clean::run_in`].
+    pub fn run(&self) -> AnyResult<Vec<PathBuf>> {
+        self.run_in(Path::new("."))
+    }
+
+    /// Removes the selected artifacts of the workspace in `root`, then runs
+    /// `cargo clean` if [`all`](Self::all) is set.
+    ///
+    /// # Returns
+    ///
+    /// The removed artifacts, or the ones that would be removed in a dry run.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `root` is not a cargo workspace, if an artifact
+    /// resolves to a path outside of it, or if an artifact cannot be removed
+    /// or `cargo clean` fails.
+    pub fn run_in(&self, root: &Path) -> AnyResult<Vec<PathBuf>> {
+        let manifest = root.join("Cargo.toml");
+        if !manifest.is_file() {
+            return Err(AnyError::msg(format!(
+                "Refusing to clean '{}', which is not a cargo workspace root",
+                root.display()
+            )));
+        }
+        let artifacts = self.artifacts(root)?;
+        for artifact in &artifacts {
+            ops::ensure_within(root, artifact)?;
+        }
+        for artifact in &artifacts {
+            if self.dry_run {
+                println!("Would remove {}", artifact.display());
+                continue;
+            }
+            if artifact.is_dir() {
+                ops::remove_dir(artifact)
+            } else {
+                ops::remove_file(artifact)
+            }
+            .with_context(|| {
+                format!("Failed to remove {}", artifact.display())
+            })?;
+            println!("Removed {}", artifact.display());
+        }
+        if self.all {
+            let clean = CommandSpec::new("cargo").args([
+                "clean",
+                "--manifest-path",
+                &manifest.to_string_lossy(),
+            ]);
+            if self.dry_run {
+                println!("Would run '{clean}'");
+            } else {
+                let _ = runner::run(&clean).with_context(|| {
+                    format!("Failed to execute '{clean}'")
+                })?;
+            }
+        }
+        Ok(artifacts)
+    }
+}
+
+/// Removes the artifacts generated by the tasks in the current workspace: the
+/// coverage reports and the xtask cache.
+///
+/// # Errors
+///
+/// See [`Clean::run_in`].
+pub fn clean() -> AnyResult<()> {
+    Clean::default().run().map(|_| ())
+}
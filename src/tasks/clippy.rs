@@ -0,0 +1,228 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `clippy` task, which lints the workspace with the same lint sets as
+//! the `clippy` step of the CI checks.
+//!
+//! The lints after `--` are assembled by [`lint_args`], which the CI checks
+//! use too, so `clippy_warn` and `clippy_allow` in the `ci` settings mean the
+//! same in both. In fix mode, the files clippy changed are read from the git
+//! status before and after the run.
+
+use crate::{
+    ops::{
+        error::XtaskError,
+        git,
+        runner::{self, CommandSpec},
+    },
+    tasks::ci::CiStep,
+};
+use anyhow::{Context, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The arguments applying the suggestions of clippy to a working tree that
+/// may have uncommitted changes.
+pub const FIX_ARGS: [&str; 3] =
+    ["--fix", "--allow-dirty", "--allow-staged"];
+
+/// The lint groups warned about by the maximal lint set.
+pub const MAX_LINTS: [&str; 2] =
+    ["clippy::pedantic", "clippy::nursery"];
+
+/// Returns the lint arguments passed to clippy after `--`: warnings are
+/// denied, the [`MAX_LINTS`] are warned about if `max` is set, then the
+/// `warn` lints, then the `allow` lints, which take precedence.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::clippy::lint_args;
+///
+/// let args = lint_args(
+///     true,
+///     &["clippy::unwrap_used".to_string()],
+///     &["clippy::module_name_repetitions".to_string()],
+/// );
+/// assert_eq!(
+///     args.join(" "),
+///     "-D warnings -W clippy::pedantic -W clippy::nursery \
+///      -W clippy::unwrap_used -A clippy::module_name_repetitions"
+/// );
+/// ```
+pub fn lint_args(
+    max: bool,
+    warn: &[String],
+    allow: &[String],
+) -> Vec<String> {
+    let mut args = vec!["-D".to_string(), "warnings".to_string()];
+    let max_lints: &[&str] = if max { &MAX_LINTS } else { &[] };
+    for lint in max_lints {
+        args.extend(["-W".to_string(), (*lint).to_string()]);
+    }
+    for lint in warn {
+        args.extend(["-W".to_string(), lint.clone()]);
+    }
+    for lint in allow {
+        args.extend(["-A".to_string(), lint.clone()]);
+    }
+    args
+}
+
+/// Selects the targets, features and lints of the `clippy` task.
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct ClippyOptions {
+    /// Warns about the [`MAX_LINTS`] and lints every target with every
+    /// feature, like `clippy_max` in the `ci` settings.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub max: bool,
+
+    /// Applies the suggestions of clippy, see [`FIX_ARGS`].
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub fix: bool,
+
+    /// The lints warned about, passed with `-W`.
+    #[builder(default)]
+    #[serde(default)]
+    pub warn: Vec<String>,
+
+    /// The lints allowed, passed with `-A`.
+    #[builder(default)]
+    #[serde(default)]
+    pub allow: Vec<String>,
+
+    /// Lints the tests, examples and benchmarks too.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub all_targets: bool,
+
+    /// Lints with every feature enabled.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub all_features: bool,
+
+    /// Prints GitHub Actions annotations for the diagnostics, see
+    /// [`annotations`](crate::tasks::ci::annotations).
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub annotations: bool,
+
+    /// Passes `--verbose` to cargo.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+impl ClippyOptions {
+    /// Returns the `cargo clippy` invocation of these options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::clippy::ClippyOptionsBuilder;
+    ///
+    /// let options = ClippyOptionsBuilder::default()
+    ///     .fix(true)
+    ///     .allow(vec!["clippy::too_many_lines".to_string()])
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     options.command().to_string(),
+    ///     "cargo clippy --fix --allow-dirty --allow-staged -- -D warnings \
+    ///      -A clippy::too_many_lines"
+    /// );
+    /// ```
+    pub fn command(&self) -> CommandSpec {
+        let mut command = CommandSpec::new("cargo").arg("clippy");
+        if self.verbose {
+            command = command.arg("--verbose");
+        }
+        if self.fix {
+            command = command.args(FIX_ARGS);
+        }
+        if self.all_targets || self.max {
+            command = command.arg("--all-targets");
+        }
+        if self.all_features || self.max {
+            command = command.arg("--all-features");
+        }
+        if self.annotations {
+            command = command.args(["--message-format", "json"]);
+        }
+        command.arg("--").args(lint_args(
+            self.max,
+            &self.warn,
+            &self.allow,
+        ))
+    }
+}
+
+/// Runs clippy as selected by `opts`.
+///
+/// # Returns
+///
+/// The files clippy changed in fix mode, relative to the root of the
+/// repository and sorted by path. The list is empty otherwise, and outside a
+/// git repository.
+///
+/// # Errors
+///
+/// Returns an error if clippy fails or reports a denied lint, which remains
+/// an error in fix mode when clippy cannot fix it.
+pub fn run_clippy(opts: &ClippyOptions) -> AnyResult<Vec<PathBuf>> {
+    let root = Path::new(".");
+    let before = if opts.fix {
+        git::Snapshot::take(root).ok()
+    } else {
+        None
+    };
+    CiStep::command("clippy", opts.command())
+        .with_annotations(opts.annotations)
+        .execute()
+        .with_context(|| XtaskError::StepFailed {
+            step: "clippy".to_string(),
+        })?;
+    Ok(before
+        .and_then(|before| {
+            git::Snapshot::take(root)
+                .ok()
+                .map(|after| after.changed_since(&before))
+        })
+        .unwrap_or_default())
+}
+
+/// Lints the workspace and, in fix mode, prints the files clippy changed.
+///
+/// # Errors
+///
+/// Returns an error if clippy fails or reports a denied lint, see
+/// [`run_clippy`].
+pub fn clippy(opts: ClippyOptions) -> AnyResult<()> {
+    let changed = run_clippy(&opts)?;
+    if !opts.fix || runner::is_dry_run() {
+        return Ok(());
+    }
+    if changed.is_empty() {
+        println!("No files changed");
+    } else {
+        println!("Fixed {} files:", changed.len());
+        for path in &changed {
+            println!("  {}", path.display());
+        }
+    }
+    Ok(())
+}
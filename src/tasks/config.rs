@@ -0,0 +1,249 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Default settings of the tasks, kept in the repository.
+//!
+//! The settings are read from an [`CONFIG_FILE`] file, or from the
+//! `[package.metadata.xtask]` table of the workspace root `Cargo.toml`. Their
+//! `ci`, `powerset`, `coverage` and `bloat` sections hold the fields of
+//! [`CI`], [`Powerset`], [`Coverage`] and [`Bloat`], e.g.
+//!
+//! ```toml
+//! [powerset]
+//! depth = 3
+//! keep_going = true
+//! ```
+//!
+//! The command line overrides these settings, which override the built-in
+//! defaults.
+
+use crate::ops::error::XtaskError;
+use crate::tasks::{
+    bloat::{Bloat, BloatBuilder},
+    ci::{CIBuilder, CI},
+    coverage::{Coverage, CoverageBuilder},
+    powerset::{Powerset, PowersetBuilder},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The file holding the settings, next to the workspace root `Cargo.toml`.
+pub const CONFIG_FILE: &str = "xtask.toml";
+
+/// The settings of the tasks, as builders seeded with the configured fields.
+///
+/// The fields left out of the configuration keep the builder defaults, and
+/// the command line sets its flags on top.
+#[derive(Clone, Debug, Default)]
+pub struct XtaskConfig {
+    /// The file the settings were read from, if any.
+    pub source: Option<PathBuf>,
+    /// The settings of the `ci` task.
+    pub ci: CIBuilder,
+    /// The settings of the `powerset` task.
+    pub powerset: PowersetBuilder,
+    /// The settings of the `coverage` task.
+    pub coverage: CoverageBuilder,
+    /// The settings of the `bloat-deps` and `bloat-fns` tasks.
+    pub bloat: BloatBuilder,
+}
+
+/// The settings of the tasks once merged with the built-in defaults.
+#[derive(Clone, Debug, Serialize)]
+struct EffectiveConfig {
+    ci: CI,
+    powerset: Powerset,
+    coverage: Coverage,
+    bloat: Bloat,
+}
+
+impl XtaskConfig {
+    /// Reads the settings from a table with `ci`, `powerset`, `coverage` and
+    /// `bloat` sections. Other keys are left to the tasks reading them, such
+    /// as `book_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the key, e.g. `powerset.depth`, of the first
+    /// section field that is unknown or has a value of the wrong type, and the
+    /// `source` it comes from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::config::XtaskConfig;
+    ///
+    /// let table = serde_json::json!({ "powerset": { "depth": "three" } });
+    /// let err = XtaskConfig::parse(&table, "xtask.toml").unwrap_err();
+    /// assert!(err
+    ///     .to_string()
+    ///     .starts_with("Invalid setting 'powerset.depth' in xtask.toml"));
+    /// ```
+    pub fn parse(
+        table: &serde_json::Value,
+        source: &str,
+    ) -> AnyResult<Self> {
+        Ok(Self {
+            source: None,
+            ci: section(table, "ci", source)?,
+            powerset: section(table, "powerset", source)?,
+            coverage: section(table, "coverage", source)?,
+            bloat: section(table, "bloat", source)?,
+        })
+    }
+
+    /// Reads the settings from `path`: the whole file for an [`CONFIG_FILE`],
+    /// or the `[package.metadata.xtask]` table of a `Cargo.toml`, which may
+    /// have none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not valid TOML, or has
+    /// an invalid setting.
+    pub fn load(path: &Path) -> AnyResult<Self> {
+        let contents = fs::read_to_string(path).with_context(|| {
+            XtaskError::Io(format!("Failed to read {}", path.display()))
+        })?;
+        let mut document: serde_json::Value = toml::from_str(&contents)
+            .with_context(|| {
+                XtaskError::ConfigInvalid(format!(
+                    "Failed to parse {}",
+                    path.display()
+                ))
+            })?;
+        let table = if path.file_name() == Some("Cargo.toml".as_ref()) {
+            document
+                .pointer_mut("/package/metadata/xtask")
+                .map(serde_json::Value::take)
+                .unwrap_or_default()
+        } else {
+            document
+        };
+        let mut config =
+            Self::parse(&table, &path.display().to_string())?;
+        config.source = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Looks for the settings in `dir` and its parents, up to the workspace
+    /// root.
+    ///
+    /// In each directory, an [`CONFIG_FILE`] comes first, then a `Cargo.toml`
+    /// with a `[package.metadata.xtask]` table. The search stops at the first
+    /// `Cargo.toml` with a `[workspace]` table.
+    ///
+    /// # Returns
+    ///
+    /// The settings found, or the built-in defaults if there are none.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file on the way cannot be read or parsed, or has
+    /// an invalid setting.
+    pub fn discover(dir: &Path) -> AnyResult<Self> {
+        for dir in dir.ancestors() {
+            let file = dir.join(CONFIG_FILE);
+            if file.is_file() {
+                return Self::load(&file);
+            }
+            let manifest = dir.join("Cargo.toml");
+            if !manifest.is_file() {
+                continue;
+            }
+            let contents =
+                fs::read_to_string(&manifest).with_context(|| {
+                    XtaskError::Io(format!(
+                        "Failed to read {}",
+                        manifest.display()
+                    ))
+                })?;
+            let document: toml::Table = toml::from_str(&contents)
+                .with_context(|| {
+                    XtaskError::ConfigInvalid(format!(
+                        "Failed to parse {}",
+                        manifest.display()
+                    ))
+                })?;
+            let configured = document
+                .get("package")
+                .and_then(|package| package.get("metadata"))
+                .and_then(|metadata| metadata.get("xtask"))
+                .is_some();
+            if configured {
+                return Self::load(&manifest);
+            }
+            if document.contains_key("workspace") {
+                break;
+            }
+        }
+        Ok(Self::default())
+    }
+
+    /// Renders the settings merged with the built-in defaults as TOML, one
+    /// section per task.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a section does not build, e.g. because of an
+    /// invalid depth, or cannot be written as TOML.
+    pub fn to_toml(&self) -> AnyResult<String> {
+        let effective =
+            EffectiveConfig {
+                ci: self
+                    .ci
+                    .build()
+                    .context("Failed to build CI configuration")?,
+                powerset: self.powerset.build().context(
+                    "Failed to build Powerset configuration",
+                )?,
+                coverage: self.coverage.build().context(
+                    "Failed to build Coverage configuration",
+                )?,
+                bloat: self
+                    .bloat
+                    .build()
+                    .context("Failed to build Bloat configuration")?,
+            };
+        toml::to_string_pretty(&effective).map_err(AnyError::new)
+    }
+}
+
+/// Deserializes the `name` section of `table` into a builder, which is empty
+/// when the section is missing.
+fn section<B>(
+    table: &serde_json::Value,
+    name: &str,
+    source: &str,
+) -> AnyResult<B>
+where
+    B: DeserializeOwned + Default,
+{
+    let Some(value) = table.get(name) else {
+        return Ok(B::default());
+    };
+    let Some(fields) = value.as_object() else {
+        return Err(XtaskError::ConfigInvalid(format!(
+            "Invalid setting '{name}' in {source}: expected a table"
+        ))
+        .into());
+    };
+    serde_json::from_value(value.clone()).map_err(|err| {
+        // Name the offending key, which serde leaves out of type errors.
+        let key = fields.iter().find_map(|(key, value)| {
+            let field = serde_json::json!({ key.as_str(): value });
+            serde_json::from_value::<B>(field).err().map(|_| key)
+        });
+        let setting = key.map_or_else(
+            || name.to_string(),
+            |key| format!("{name}.{key}"),
+        );
+        XtaskError::ConfigInvalid(format!(
+            "Invalid setting '{setting}' in {source}: {err}"
+        ))
+        .into()
+    })
+}
@@ -1,6 +1,647 @@
-use crate::run_command;
-use anyhow::{Context, Result as AnyResult};
-use duct::cmd;
+use crate::ops::{
+    self, metadata, output,
+    runner::{self, CommandSpec},
+    tools,
+};
+use crate::tasks::ci::{FeatureSelection, StepSkipped};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    env::consts,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// The `badge` module renders a coverage badge for READMEs.
+pub mod badge;
+/// The `engine` module describes the coverage tools the task can drive.
+pub mod engine;
+/// The `history` module records coverage runs and compares them.
+pub mod history;
+/// The `report` module holds the typed results of a coverage run.
+pub mod report;
+/// The `upload` module sends coverage results to hosted coverage services.
+pub mod upload;
+
+pub use badge::coverage_badge;
+pub use engine::CoverageEngine;
+pub use history::{coverage_diff, CoverageDiff};
+pub use report::{CoverageReport, FileCoverage, ModuleCoverage};
+pub use upload::{coverage_upload, CoverageProvider};
+
+/// The file `cargo tarpaulin --out Json` writes its report to.
+pub const JSON_REPORT: &str = "tarpaulin-report.json";
+
+/// The directory the reports are collected in unless configured otherwise.
+pub const DEFAULT_OUTPUT_DIR: &str = "target/coverage";
+
+/// A coverage report format, passed to `cargo tarpaulin --out`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum CoverageFormat {
+    /// An HTML report, `tarpaulin-report.html`.
+    Html,
+    /// An LCOV tracefile, `lcov.info`.
+    Lcov,
+    /// A Cobertura XML report, `cobertura.xml`.
+    Xml,
+    /// A JSON report, `tarpaulin-report.json`.
+    Json,
+    /// A summary printed to stdout.
+    Stdout,
+}
+
+impl CoverageFormat {
+    /// Every format, in the order they are listed in error messages.
+    pub const ALL: [Self; 5] =
+        [Self::Html, Self::Lcov, Self::Xml, Self::Json, Self::Stdout];
+
+    /// Returns the name tarpaulin expects after `--out`, e.g. `Lcov`.
+    pub const fn as_tarpaulin_arg(self) -> &'static str {
+        match self {
+            Self::Html => "Html",
+            Self::Lcov => "Lcov",
+            Self::Xml => "Xml",
+            Self::Json => "Json",
+            Self::Stdout => "Stdout",
+        }
+    }
+}
+
+impl fmt::Display for CoverageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.as_tarpaulin_arg().to_ascii_lowercase())
+    }
+}
+
+impl FromStr for CoverageFormat {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| {
+                format.as_tarpaulin_arg().eq_ignore_ascii_case(value)
+            })
+            .ok_or_else(|| {
+                let valid: Vec<String> =
+                    Self::ALL.iter().map(ToString::to_string).collect();
+                AnyError::msg(format!(
+                    "Unknown coverage format '{value}', expected one of: {}",
+                    valid.join(", ")
+                ))
+            })
+    }
+}
+
+/// Returns the `cargo tarpaulin` invocation writing reports in the given formats.
+///
+/// # Parameters
+///
+/// * `formats` - The report formats, each passed with its own `--out` flag.
+/// * `dev` - If `true`, also passes `--dev` to include dev-dependencies.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::coverage::{command, CoverageFormat};
+///
+/// let command = command(&[CoverageFormat::Lcov, CoverageFormat::Html], false);
+/// assert_eq!(command.to_string(), "cargo tarpaulin --out Lcov --out Html");
+/// ```
+pub fn command(formats: &[CoverageFormat], dev: bool) -> CommandSpec {
+    let mut command = CommandSpec::new("cargo").arg("tarpaulin");
+    for format in formats {
+        command = command.args(["--out", format.as_tarpaulin_arg()]);
+    }
+    if dev {
+        command.arg("--dev")
+    } else {
+        command
+    }
+}
+
+/// Represents the configuration for a code coverage run.
+#[derive(Builder, Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[builder(setter(into), derive(Debug, Deserialize))]
+#[builder_struct_attr(serde(deny_unknown_fields))]
+pub struct Coverage {
+    /// The report formats to write.
+    ///
+    /// By default, this is an HTML report only. An empty list leaves the choice to
+    /// tarpaulin, which prints a summary to stdout.
+    ///
+    #[builder(default = "vec![CoverageFormat::Html]")]
+    #[serde(default = "default_formats")]
+    pub formats: Vec<CoverageFormat>,
+
+    /// Includes dev-dependencies in the analysis. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub dev: bool,
+
+    /// The tool measuring coverage.
+    ///
+    /// By default, this is `None`, and an installed engine is detected with
+    /// [`CoverageEngine::detect`].
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine: Option<CoverageEngine>,
+
+    /// Fails the run when the total line coverage is below this percentage.
+    ///
+    /// By default, this is `None`, and any coverage is accepted.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail_under: Option<f32>,
+
+    /// Glob patterns of source files left out of the measurement, e.g.
+    /// `src/generated/*`. Passed to tarpaulin as `--exclude-files`.
+    ///
+    #[builder(default, setter(into))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_files: Vec<String>,
+
+    /// The workspace members to measure.
+    ///
+    /// By default, this is empty, and the engine's default package is measured.
+    ///
+    #[builder(default, setter(into))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub packages: Vec<String>,
+
+    /// Workspace members left out of the measurement. When set, the whole
+    /// workspace is measured except for these packages.
+    ///
+    #[builder(default, setter(into))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_packages: Vec<String>,
+
+    /// The directory the reports are collected in.
+    ///
+    /// By default, this is `target/coverage`, which keeps the reports out of
+    /// `git status`. An empty path also selects the default.
+    ///
+    #[builder(default = "PathBuf::from(DEFAULT_OUTPUT_DIR)")]
+    #[serde(default = "default_output_dir")]
+    pub output_dir: PathBuf,
+
+    /// Opens the HTML report in the browser once it is written. This is ignored
+    /// in CI. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub open: bool,
+
+    /// A file holding the coverage to compare the run against: a summary or a
+    /// coverage history.
+    ///
+    /// By default, this is `None`, and the run is compared to the last recorded
+    /// run on the default branch, if any.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<PathBuf>,
+
+    /// The drop in percentage points above which a file is listed in the
+    /// comparison with the baseline. By default, this is `0.0`, and every drop
+    /// is listed.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub max_file_drop: f32,
+
+    /// Installs the engine when it is missing. Otherwise, a missing engine is an
+    /// error naming the command that installs it. By default, this is set to
+    /// `true`; the `XTASKS_NO_AUTO_INSTALL` environment variable turns it off.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_auto_install")]
+    pub auto_install: bool,
+
+    /// Arguments passed to the test binaries, after `--`, e.g.
+    /// `--test-threads 1`.
+    ///
+    #[builder(default, setter(into))]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub test_args: Vec<String>,
+
+    /// How long the tests may run. Passed to tarpaulin as `--timeout`, and
+    /// enforced by killing the test run for the other engines.
+    ///
+    /// By default, this is `None`, and the engine's own limits apply.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<Duration>,
+
+    /// The cargo features the tests are built with, combined into a single run
+    /// (see [`FeatureSelection::combined_args`]).
+    ///
+    /// By default, this is `None`, and the default features are used.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<FeatureSelection>,
+
+    /// Only prints the per-module summary table: no report files are written,
+    /// the run is neither compared to the baseline nor recorded in the coverage
+    /// history, and the output of the engine is not echoed. By default, this is
+    /// set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub summary_only: bool,
+
+    /// The toolchain the engine runs with, through `rustup run`, e.g. `nightly`.
+    ///
+    /// By default, this is `None`, and the active toolchain is used.
+    ///
+    #[builder(default, setter(into, strip_option))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+}
+
+/// Returns whether missing engines are installed when not configured.
+const fn default_auto_install() -> bool {
+    true
+}
+
+/// Returns the directory the reports are collected in when none is configured.
+fn default_output_dir() -> PathBuf {
+    PathBuf::from(DEFAULT_OUTPUT_DIR)
+}
+
+/// Returns the formats written when none are configured.
+fn default_formats() -> Vec<CoverageFormat> {
+    vec![CoverageFormat::Html]
+}
+
+impl Default for Coverage {
+    /// Returns the configuration built from the builder defaults, so both
+    /// describe the same run.
+    fn default() -> Self {
+        CoverageBuilder::default()
+            .build()
+            .expect("the builder defaults are a valid configuration")
+    }
+}
+
+impl Coverage {
+    /// Returns the configured engine, or the detected one if none is configured.
+    pub fn resolved_engine(&self) -> CoverageEngine {
+        self.engine.unwrap_or_else(CoverageEngine::detect)
+    }
+
+    /// Returns the configured output directory, or the default one if it is empty.
+    pub fn resolved_output_dir(&self) -> PathBuf {
+        if self.output_dir.as_os_str().is_empty() {
+            default_output_dir()
+        } else {
+            self.output_dir.clone()
+        }
+    }
+
+    /// Returns the path the report in `format` is collected at, or `None` for
+    /// reports printed to stdout.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use xtasks::tasks::coverage::{Coverage, CoverageEngine, CoverageFormat};
+    ///
+    /// let path = Coverage::default()
+    ///     .report_path(CoverageEngine::LlvmCov, CoverageFormat::Lcov);
+    /// assert_eq!(path.as_deref(), Some(Path::new("target/coverage/lcov.info")));
+    /// ```
+    pub fn report_path(
+        &self,
+        engine: CoverageEngine,
+        format: CoverageFormat,
+    ) -> Option<PathBuf> {
+        let path = engine.output_path(format)?;
+        Some(self.resolved_output_dir().join(path.file_name()?))
+    }
+
+    /// Moves the reports `engine` wrote into the output directory, resolving
+    /// relative paths against `root`, and returns their final paths in the order
+    /// of `formats`.
+    ///
+    /// Reports that were not written are left out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output directory cannot be created or a report
+    /// cannot be moved.
+    pub fn collect_reports_in(
+        &self,
+        engine: CoverageEngine,
+        root: &Path,
+    ) -> AnyResult<Vec<PathBuf>> {
+        let dir = root.join(self.resolved_output_dir());
+        fs::create_dir_all(&dir).with_context(|| {
+            format!("Failed to create {}", dir.display())
+        })?;
+        let mut paths = Vec::new();
+        for format in &self.formats {
+            let (Some(written), Some(target)) = (
+                engine.output_path(*format),
+                self.report_path(engine, *format),
+            ) else {
+                continue;
+            };
+            let target = root.join(target);
+            // Tarpaulin writes into the output directory through `--output-dir`.
+            let written = match engine {
+                CoverageEngine::Tarpaulin => target.clone(),
+                _ => root.join(written),
+            };
+            if written != target && written.exists() {
+                if target.is_dir() {
+                    fs::remove_dir_all(&target)?;
+                }
+                fs::rename(&written, &target).with_context(|| {
+                    format!(
+                        "Failed to move {} to {}",
+                        written.display(),
+                        target.display()
+                    )
+                })?;
+            }
+            if target.exists() && !paths.contains(&target) {
+                paths.push(target);
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Returns the commands run for this configuration, in order.
+    pub fn commands(&self) -> Vec<CommandSpec> {
+        self.resolved_engine().commands(self)
+    }
+
+    /// Returns the number of existing files matching the `exclude_files` patterns.
+    pub fn excluded_file_count(&self) -> usize {
+        let mut excluded: Vec<PathBuf> = self
+            .exclude_files
+            .iter()
+            .filter_map(|pattern| glob::glob(pattern).ok())
+            .flatten()
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect();
+        excluded.sort();
+        excluded.dedup();
+        excluded.len()
+    }
+
+    /// Runs the coverage engine, enforces the `fail_under` threshold, and returns
+    /// the parsed results, including the paths of the collected reports.
+    ///
+    /// The engine is installed first when it is missing. Its output is captured,
+    /// and printed afterwards, so that the results can be read from its summary,
+    /// falling back to the JSON report when that format was requested. The
+    /// reports are moved into `output_dir`, and the HTML report is opened when
+    /// `open` is set.
+    ///
+    /// In a dry run (see [`runner::is_dry_run`]), the run stops after the
+    /// engine commands and returns an empty report.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `packages` or `exclude_packages` name a package that is
+    /// not a workspace member, if the engine cannot be installed or fails, or if
+    /// the coverage cannot be determined or is below `fail_under`.
+    ///
+    pub fn run(&self) -> AnyResult<CoverageReport> {
+        if self.summary_only && self.formats != [CoverageFormat::Stdout]
+        {
+            return Self {
+                formats: vec![CoverageFormat::Stdout],
+                ..self.clone()
+            }
+            .run();
+        }
+        let selected: Vec<String> = self
+            .packages
+            .iter()
+            .chain(&self.exclude_packages)
+            .cloned()
+            .collect();
+        metadata::ensure_workspace_members(&selected)?;
+
+        let engine = self.resolved_engine();
+        tools::ensure_tool(
+            engine.binary(),
+            engine.install_args(),
+            self.auto_install,
+        )?;
+
+        let mut text = String::new();
+        for command in self.commands() {
+            let out = runner::current()
+                .execute(&command.clone().captured())
+                .with_context(|| {
+                    format!("Failed to spawn '{command}'")
+                })?;
+            let captured = output::combined(&out);
+            if !self.summary_only {
+                print!("{captured}");
+            }
+            text.push_str(&captured);
+            if !out.status.success() {
+                return Err(AnyError::msg(format!(
+                    "'{command}' exited with {}",
+                    out.status
+                )))
+                .with_context(|| {
+                    format!(
+                        "Failed to execute {engine} for code coverage"
+                    )
+                });
+            }
+        }
+
+        if runner::is_dry_run() {
+            // The engine did not run, so there are no results to read.
+            return Ok(CoverageReport::default());
+        }
+        let outputs = self.collect_reports_in(engine, Path::new(""))?;
+        let json = self
+            .report_path(engine, CoverageFormat::Json)
+            .filter(|_| self.formats.contains(&CoverageFormat::Json));
+        let mut report = match (engine.parse_report(&text), json) {
+            (Some(report), _) => report,
+            (None, Some(path)) => {
+                let json = fs::read_to_string(&path).with_context(|| {
+                    format!("Failed to read {}", path.display())
+                })?;
+                CoverageReport::from_total(engine.parse_json_total(&json)?)
+            }
+            (None, None) => {
+                return Err(AnyError::msg(format!(
+                    "Failed to find the coverage results in the output of {engine}"
+                )))
+            }
+        };
+        report.excluded_files = self.excluded_file_count();
+        if !report.files.is_empty() {
+            print!("{}", report.module_table());
+        }
+        if self.open {
+            let html = self
+                .report_path(engine, CoverageFormat::Html)
+                .filter(|path| outputs.contains(path));
+            if let Some(html) = html {
+                let page = if html.is_dir() {
+                    html.join("index.html")
+                } else {
+                    html
+                };
+                let _ = ops::open_in_browser(&page)?;
+            }
+        }
+        report.outputs = outputs;
+        if report.excluded_files > 0 {
+            println!("{report}");
+        }
+        if !self.summary_only {
+            self.compare_and_record(&report)?;
+        }
+        if let Some(fail_under) = self.fail_under {
+            ensure_threshold(
+                f64::from(report.total_percent),
+                fail_under,
+            )?;
+        }
+        Ok(report)
+    }
+}
+
+impl Coverage {
+    /// Prints how `report` compares to the baseline, and records it in the
+    /// coverage history when running inside a git repository.
+    fn compare_and_record(
+        &self,
+        report: &CoverageReport,
+    ) -> AnyResult<()> {
+        let history_file = Path::new(history::HISTORY_FILE);
+        let mut history = history::CoverageHistory::load(history_file)?;
+        let summary = history::CoverageSummary::from(report);
+        let baseline = match &self.baseline {
+            Some(path) => Some(history::load_baseline(path)?),
+            None => history
+                .latest_on_default_branch()
+                .map(|entry| entry.summary.clone()),
+        };
+        if let Some(baseline) = baseline {
+            println!(
+                "{}",
+                CoverageDiff::between(
+                    &baseline,
+                    &summary,
+                    self.max_file_drop
+                )
+            );
+        }
+        if let Some((sha, branch)) = history::current_revision() {
+            history.record(history::HistoryEntry {
+                sha,
+                branch,
+                summary,
+            });
+            history.save(history_file)?;
+        }
+        Ok(())
+    }
+}
+
+impl CoverageBuilder {
+    /// Builds the configuration and runs the coverage analysis.
+    ///
+    /// This is a convenience for `self.build()?.run()`; see [`Coverage::run`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration cannot be built or the run fails.
+    ///
+    pub fn run(&self) -> AnyResult<CoverageReport> {
+        self.build()
+            .context("Failed to build Coverage configuration")?
+            .run()
+    }
+}
+
+/// Extracts the total line coverage, as a percentage, from the output of
+/// `cargo tarpaulin`.
+///
+/// The last `NN.NN% coverage` summary line wins. Output without one is searched
+/// for a JSON report with a `coverage` total instead.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::coverage::parse_total_percentage;
+///
+/// let output = "|| src/lib.rs: 3/4\n42.86% coverage, 3/7 lines covered\n";
+/// assert_eq!(parse_total_percentage(output), Some(42.86));
+/// assert_eq!(parse_total_percentage("no summary"), None);
+/// ```
+pub fn parse_total_percentage(output: &str) -> Option<f64> {
+    let summary = output.lines().rev().find_map(|line| {
+        let (before, _) = line.split_once("% coverage")?;
+        before.split_whitespace().last()?.parse::<f64>().ok()
+    });
+    summary.or_else(|| {
+        output
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.starts_with('{'))
+            .find_map(|line| parse_json_report(line).ok())
+    })
+}
+
+/// Generates code coverage reports for the current project in the given formats
+/// and returns the parsed results.
+///
+/// # Parameters
+///
+/// * `formats` - The report formats to write, e.g. LCOV for upload and HTML for
+///   browsing.
+/// * `dev` - If `true`, includes dev-dependencies in the analysis.
+///
+/// # Errors
+///
+/// Returns an error if the `cargo tarpaulin` command fails to execute.
+///
+pub fn coverage_with(
+    formats: &[CoverageFormat],
+    dev: bool,
+) -> AnyResult<CoverageReport> {
+    Coverage {
+        formats: formats.to_vec(),
+        dev,
+        ..Coverage::default()
+    }
+    .run()
+}
 
 /// Generates a code coverage report for the current project.
 ///
@@ -13,15 +654,117 @@ use duct::cmd;
 /// Returns an error if the `cargo tarpaulin` command fails to execute.
 ///
 pub fn coverage(dev: bool) -> AnyResult<()> {
-    let coverage_cmd = if dev {
-        cmd!("cargo", "tarpaulin", "--out", "Html", "--dev")
-    } else {
-        cmd!("cargo", "tarpaulin", "--out", "Html")
-    };
-
-    run_command!(
-        coverage_cmd,
-        "Failed to execute 'cargo tarpaulin' for code coverage"
-    );
-    Ok(())
+    coverage_with(&[CoverageFormat::Html], dev).map(|_| ())
+}
+
+/// Returns `true` if `cargo tarpaulin` has a coverage engine for the platform.
+///
+/// The ptrace engine only supports Linux; the LLVM engine, which tarpaulin uses
+/// elsewhere, covers macOS and Windows.
+///
+/// # Parameters
+///
+/// * `os` - The operating system, as in [`std::env::consts::OS`].
+/// * `arch` - The architecture, as in [`std::env::consts::ARCH`].
+///
+pub fn is_supported_platform(os: &str, arch: &str) -> bool {
+    match os {
+        "linux" => matches!(arch, "x86_64" | "aarch64" | "arm"),
+        "macos" | "windows" => matches!(arch, "x86_64" | "aarch64"),
+        _ => false,
+    }
+}
+
+/// Extracts the total line coverage, as a percentage, from a tarpaulin JSON report.
+///
+/// # Errors
+///
+/// Returns an error if the report is not valid JSON or has no `coverage` total.
+///
+pub fn parse_json_report(json: &str) -> AnyResult<f64> {
+    let report: serde_json::Value = serde_json::from_str(json)
+        .context("Failed to parse the tarpaulin JSON report")?;
+    report["coverage"].as_f64().ok_or_else(|| {
+        AnyError::msg("The tarpaulin JSON report has no coverage total")
+    })
+}
+
+/// Checks a line coverage percentage against a minimum.
+///
+/// # Errors
+///
+/// Returns an error such as `coverage 73.2% below threshold 80%` if `coverage`
+/// is lower than `threshold`. The coverage is printed with as many decimals as
+/// it takes to show it below the threshold, e.g. `79.96%` rather than `80.0%`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::coverage::ensure_threshold;
+///
+/// assert!(ensure_threshold(85.0, 80.0).is_ok());
+/// let err = ensure_threshold(73.2, 80.0).unwrap_err();
+/// assert_eq!(err.to_string(), "coverage 73.2% below threshold 80%");
+/// let err = ensure_threshold(79.96, 80.0).unwrap_err();
+/// assert_eq!(err.to_string(), "coverage 79.96% below threshold 80%");
+/// ```
+pub fn ensure_threshold(
+    coverage: f64,
+    threshold: f32,
+) -> AnyResult<()> {
+    // Thresholds are `f32`, so `42.86` widens to slightly more than 42.86.
+    const TOLERANCE: f64 = 1e-4;
+    let threshold_f64 = f64::from(threshold);
+    if coverage + TOLERANCE >= threshold_f64 {
+        return Ok(());
+    }
+    // Below the tolerance, four decimals always tell the two apart.
+    let precision = (1..4)
+        .find(|&precision| {
+            format!("{coverage:.precision$}")
+                .parse::<f64>()
+                .is_ok_and(|shown| shown + TOLERANCE < threshold_f64)
+        })
+        .unwrap_or(4);
+    Err(AnyError::msg(format!(
+        "coverage {coverage:.precision$}% below threshold {threshold}%"
+    )))
+}
+
+/// Fails when the line coverage of the current project is below `threshold`.
+///
+/// Coverage is measured with the settings of `config`, e.g. its features and
+/// toolchain, as a summary-only run: no reports are written and no history is
+/// recorded. Without a configured engine, the one picked by
+/// [`CoverageEngine::detect`] is used.
+///
+/// # Errors
+///
+/// Returns a [`StepSkipped`] error when tarpaulin is picked on a platform it does
+/// not support, and an error if coverage cannot be measured or is below
+/// `threshold`.
+///
+pub fn check_threshold(
+    config: &Coverage,
+    threshold: f32,
+) -> AnyResult<()> {
+    let engine = config.resolved_engine();
+    if engine == CoverageEngine::Tarpaulin
+        && !is_supported_platform(consts::OS, consts::ARCH)
+    {
+        return Err(StepSkipped::new(format!(
+            "cargo tarpaulin does not support {}-{}",
+            consts::OS,
+            consts::ARCH
+        ))
+        .into());
+    }
+    Coverage {
+        engine: Some(engine),
+        fail_under: Some(threshold),
+        summary_only: true,
+        ..config.clone()
+    }
+    .run()
+    .map(|_| ())
 }
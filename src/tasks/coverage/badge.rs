@@ -0,0 +1,96 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! A shields-style coverage badge, rendered without any network access.
+
+use super::CoverageReport;
+use anyhow::{Context, Result as AnyResult};
+use std::path::Path;
+
+/// The SVG template of the badge. `{value}`, `{color}` and the geometry
+/// placeholders are filled in by [`render_badge`].
+const BADGE_TEMPLATE: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="coverage: {value}">
+  <title>coverage: {value}</title>
+  <linearGradient id="s" x2="0" y2="100%">
+    <stop offset="0" stop-color="#bbb" stop-opacity=".1"/>
+    <stop offset="1" stop-opacity=".1"/>
+  </linearGradient>
+  <clipPath id="r">
+    <rect width="{width}" height="20" rx="3" fill="#fff"/>
+  </clipPath>
+  <g clip-path="url(#r)">
+    <rect width="61" height="20" fill="#555"/>
+    <rect x="61" width="{value_width}" height="20" fill="{color}"/>
+    <rect width="{width}" height="20" fill="url(#s)"/>
+  </g>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+    <text x="30.5" y="15" fill="#010101" fill-opacity=".3">coverage</text>
+    <text x="30.5" y="14">coverage</text>
+    <text x="{value_x}" y="15" fill="#010101" fill-opacity=".3">{value}</text>
+    <text x="{value_x}" y="14">{value}</text>
+  </g>
+</svg>
+"##;
+
+/// The width of the `coverage` label, in pixels.
+const LABEL_WIDTH: usize = 61;
+
+/// Returns the color of the badge for a coverage percentage: red below 50%,
+/// yellow below 80%, and green from 80% up.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::coverage::badge::badge_color;
+///
+/// assert_eq!(badge_color(49.9), "#e05d44");
+/// assert_eq!(badge_color(50.0), "#dfb317");
+/// assert_eq!(badge_color(80.0), "#4c1");
+/// ```
+pub fn badge_color(percent: f32) -> &'static str {
+    if percent < 50.0 {
+        "#e05d44"
+    } else if percent < 80.0 {
+        "#dfb317"
+    } else {
+        "#4c1"
+    }
+}
+
+/// Renders the badge SVG for a coverage percentage, e.g. `coverage | 83%`.
+///
+/// The output only depends on `percent`, so regenerating the badge for the
+/// same coverage leaves the file unchanged.
+pub fn render_badge(percent: f32) -> String {
+    let value = format!("{}%", percent.clamp(0.0, 100.0).floor());
+    // Verdana at 11px averages about 7px per character, plus the padding.
+    let value_width = value.len() * 7 + 10;
+    let width = LABEL_WIDTH + value_width;
+    let value_x = LABEL_WIDTH as f32 + value_width as f32 / 2.0;
+    BADGE_TEMPLATE
+        .replace("{value_width}", &value_width.to_string())
+        .replace("{value_x}", &value_x.to_string())
+        .replace("{width}", &width.to_string())
+        .replace("{color}", badge_color(percent))
+        .replace("{value}", &value)
+}
+
+/// Writes a coverage badge for `report` to `dest`, atomically.
+///
+/// # Parameters
+///
+/// * `report` - The results of a coverage run.
+/// * `dest` - Where to write the SVG, e.g. `docs/coverage.svg`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be written.
+pub fn coverage_badge(
+    report: &CoverageReport,
+    dest: &Path,
+) -> AnyResult<()> {
+    crate::ops::write_atomic(dest, render_badge(report.total_percent))
+        .with_context(|| {
+            format!("Failed to write coverage badge {}", dest.display())
+        })
+}
@@ -0,0 +1,471 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The coverage tools the coverage task can drive.
+
+use super::{
+    parse_json_report, parse_total_percentage, Coverage,
+    CoverageFormat, CoverageReport, FileCoverage,
+};
+use crate::ops::{runner::CommandSpec, tools};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{env::consts, fmt, path::PathBuf, str::FromStr};
+
+/// The directory `cargo llvm-cov` reports are written to.
+const LLVM_COV_DIR: &str = "target/llvm-cov";
+
+/// The directory grcov profiles and reports are written to.
+const GRCOV_DIR: &str = "target/coverage";
+
+/// A tool that measures code coverage.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum CoverageEngine {
+    /// `cargo tarpaulin`, which supports Linux best.
+    Tarpaulin,
+    /// `cargo llvm-cov`, based on the LLVM source-based coverage of rustc.
+    LlvmCov,
+    /// `grcov`, aggregating the profiles of a `-Cinstrument-coverage` test build.
+    Grcov,
+}
+
+impl CoverageEngine {
+    /// Every engine, in the order [`CoverageEngine::detect`] prefers them.
+    pub const ALL: [Self; 3] =
+        [Self::Tarpaulin, Self::LlvmCov, Self::Grcov];
+
+    /// Returns the binary that provides the engine, e.g. `cargo-llvm-cov`.
+    pub const fn binary(self) -> &'static str {
+        match self {
+            Self::Tarpaulin => "cargo-tarpaulin",
+            Self::LlvmCov => "cargo-llvm-cov",
+            Self::Grcov => "grcov",
+        }
+    }
+
+    /// Returns the `cargo install` arguments that install the engine.
+    pub const fn install_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Tarpaulin => &["cargo-tarpaulin", "--locked"],
+            Self::LlvmCov => &["cargo-llvm-cov", "--locked"],
+            Self::Grcov => &["grcov", "--locked"],
+        }
+    }
+
+    /// Picks the engine to use when none is configured.
+    ///
+    /// The first installed engine of [`CoverageEngine::ALL`] wins. When none is
+    /// installed, tarpaulin is used on Linux and cargo-llvm-cov elsewhere.
+    pub fn detect() -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|engine| tools::is_installed(engine.binary()))
+            .unwrap_or(if consts::OS == "linux" {
+                Self::Tarpaulin
+            } else {
+                Self::LlvmCov
+            })
+    }
+
+    /// Returns the commands that measure coverage and write the reports, in order.
+    ///
+    /// Every engine also prints a summary the total can be read from. The `dev`
+    /// option only applies to tarpaulin.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::coverage::{Coverage, CoverageEngine, CoverageFormat};
+    ///
+    /// let config = Coverage {
+    ///     formats: vec![CoverageFormat::Lcov],
+    ///     packages: vec!["app".into()],
+    ///     ..Coverage::default()
+    /// };
+    /// let commands = CoverageEngine::LlvmCov.commands(&config);
+    /// assert_eq!(commands[0].to_string(), "cargo llvm-cov --no-report --package app");
+    /// ```
+    pub fn commands(self, config: &Coverage) -> Vec<CommandSpec> {
+        let mut selection = Vec::new();
+        for package in &config.packages {
+            selection
+                .extend(["--package".to_string(), package.clone()]);
+        }
+        if !config.exclude_packages.is_empty() {
+            selection.push("--workspace".to_string());
+            for package in &config.exclude_packages {
+                selection
+                    .extend(["--exclude".to_string(), package.clone()]);
+            }
+        }
+        if let Some(features) = &config.features {
+            selection.extend(features.combined_args());
+        }
+        // Runs the tests: test arguments go last, and engines other than
+        // tarpaulin are stopped from the outside once the timeout expires.
+        let run_tests = |mut command: CommandSpec| {
+            if !config.test_args.is_empty() {
+                command = command
+                    .arg("--")
+                    .args(config.test_args.iter().cloned());
+            }
+            match config.timeout {
+                Some(timeout) if self != Self::Tarpaulin => {
+                    command.timeout(timeout)
+                }
+                _ => command,
+            }
+        };
+
+        let cargo = |subcommand: &str| {
+            tools::cargo_with(config.toolchain.as_deref(), subcommand)
+        };
+        match self {
+            Self::Tarpaulin => {
+                let mut command = cargo("tarpaulin");
+                for format in &config.formats {
+                    command = command
+                        .args(["--out", format.as_tarpaulin_arg()]);
+                }
+                if config.dev {
+                    command = command.arg("--dev");
+                }
+                command = command.arg("--output-dir").arg(
+                    config.resolved_output_dir().to_string_lossy(),
+                );
+                for pattern in &config.exclude_files {
+                    command = command
+                        .args(["--exclude-files", pattern.as_str()]);
+                }
+                if let Some(timeout) = config.timeout {
+                    command = command
+                        .arg("--timeout")
+                        .arg(timeout.as_secs().max(1).to_string());
+                }
+                let selection = selection.into_iter().map(|arg| {
+                    if arg == "--package" {
+                        "--packages".to_string()
+                    } else {
+                        arg
+                    }
+                });
+                vec![run_tests(command.args(selection))]
+            }
+            Self::LlvmCov => {
+                let mut report = cargo("llvm-cov").arg("report");
+                if !config.exclude_files.is_empty() {
+                    let regex: Vec<String> = config
+                        .exclude_files
+                        .iter()
+                        .map(|pattern| glob_to_regex(pattern))
+                        .collect();
+                    report = report
+                        .arg("--ignore-filename-regex")
+                        .arg(regex.join("|"));
+                }
+                let mut commands = vec![run_tests(
+                    cargo("llvm-cov")
+                        .arg("--no-report")
+                        .args(selection),
+                )];
+                for format in &config.formats {
+                    let flag = match format {
+                        CoverageFormat::Html => {
+                            commands.push(report.clone().args([
+                                "--html",
+                                "--output-dir",
+                                LLVM_COV_DIR,
+                            ]));
+                            continue;
+                        }
+                        CoverageFormat::Stdout => continue,
+                        CoverageFormat::Lcov => "--lcov",
+                        CoverageFormat::Xml => "--cobertura",
+                        CoverageFormat::Json => "--json",
+                    };
+                    let path =
+                        self.output_path(*format).unwrap_or_default();
+                    commands.push(report.clone().args([
+                        flag.to_string(),
+                        "--output-path".to_string(),
+                        path.to_string_lossy().into_owned(),
+                    ]));
+                }
+                commands.push(report.arg("--summary-only"));
+                commands
+            }
+            Self::Grcov => {
+                let profiles = format!("{GRCOV_DIR}/profraw");
+                let mut report = CommandSpec::new("grcov").args([
+                    profiles.as_str(),
+                    "--binary-path",
+                    "target/debug/deps",
+                    "--source-dir",
+                    ".",
+                    "--branch",
+                    "--ignore-not-existing",
+                    "--ignore",
+                    "/*",
+                ]);
+                for pattern in &config.exclude_files {
+                    report =
+                        report.args(["--ignore", pattern.as_str()]);
+                }
+                let mut commands = vec![
+                    CommandSpec::new("rustup").args([
+                        "component",
+                        "add",
+                        "llvm-tools-preview",
+                    ]),
+                    run_tests(
+                        cargo("test")
+                            .args(selection)
+                            .env("RUSTFLAGS", "-Cinstrument-coverage")
+                            .env(
+                                "LLVM_PROFILE_FILE",
+                                format!(
+                                    "{profiles}/cargo-test-%p-%m.profraw"
+                                ),
+                            ),
+                    ),
+                ];
+                for format in &config.formats {
+                    let output_type = match format {
+                        CoverageFormat::Stdout => continue,
+                        CoverageFormat::Html => "html",
+                        CoverageFormat::Lcov => "lcov",
+                        CoverageFormat::Xml => "cobertura",
+                        CoverageFormat::Json => "covdir",
+                    };
+                    let path =
+                        self.output_path(*format).unwrap_or_default();
+                    commands.push(report.clone().args([
+                        "--output-types".to_string(),
+                        output_type.to_string(),
+                        "--output-path".to_string(),
+                        path.to_string_lossy().into_owned(),
+                    ]));
+                }
+                commands
+                    .push(report.args(["--output-types", "markdown"]));
+                commands
+            }
+        }
+    }
+
+    /// Returns where the engine writes the report in `format` by default, or
+    /// `None` for reports printed to stdout.
+    ///
+    /// Tarpaulin writes to the output directory of the configuration instead; see
+    /// [`Coverage::report_path`].
+    pub fn output_path(
+        self,
+        format: CoverageFormat,
+    ) -> Option<PathBuf> {
+        let path = match (self, format) {
+            (_, CoverageFormat::Stdout) => return None,
+            (Self::Tarpaulin, CoverageFormat::Html) => {
+                "tarpaulin-report.html".to_string()
+            }
+            (Self::Tarpaulin, CoverageFormat::Lcov) => {
+                "lcov.info".to_string()
+            }
+            (Self::Tarpaulin, CoverageFormat::Xml) => {
+                "cobertura.xml".to_string()
+            }
+            (Self::Tarpaulin, CoverageFormat::Json) => {
+                super::JSON_REPORT.to_string()
+            }
+            (Self::LlvmCov, CoverageFormat::Html) => {
+                format!("{LLVM_COV_DIR}/html")
+            }
+            (Self::LlvmCov, CoverageFormat::Lcov) => {
+                format!("{LLVM_COV_DIR}/lcov.info")
+            }
+            (Self::LlvmCov, CoverageFormat::Xml) => {
+                format!("{LLVM_COV_DIR}/cobertura.xml")
+            }
+            (Self::LlvmCov, CoverageFormat::Json) => {
+                format!("{LLVM_COV_DIR}/coverage.json")
+            }
+            (Self::Grcov, CoverageFormat::Html) => {
+                format!("{GRCOV_DIR}/html")
+            }
+            (Self::Grcov, CoverageFormat::Lcov) => {
+                format!("{GRCOV_DIR}/lcov.info")
+            }
+            (Self::Grcov, CoverageFormat::Xml) => {
+                format!("{GRCOV_DIR}/cobertura.xml")
+            }
+            (Self::Grcov, CoverageFormat::Json) => {
+                format!("{GRCOV_DIR}/covdir.json")
+            }
+        };
+        Some(PathBuf::from(path))
+    }
+
+    /// Extracts the total line coverage, as a percentage, from the output of the
+    /// commands returned by [`CoverageEngine::commands`].
+    ///
+    /// * tarpaulin prints `NN.NN% coverage, covered/total lines covered`;
+    /// * `cargo llvm-cov report --summary-only` ends with a `TOTAL` row whose third
+    ///   percentage is the line coverage;
+    /// * grcov's markdown report ends with `Total coverage: NN.NN%`.
+    pub fn parse_total(self, output: &str) -> Option<f64> {
+        match self {
+            Self::Tarpaulin => parse_total_percentage(output),
+            Self::LlvmCov => output.lines().rev().find_map(|line| {
+                let line = line.trim_start();
+                if !line.starts_with("TOTAL") {
+                    return None;
+                }
+                line.split_whitespace()
+                    .filter_map(|column| column.strip_suffix('%'))
+                    .nth(2)?
+                    .parse()
+                    .ok()
+            }),
+            Self::Grcov => output.lines().rev().find_map(|line| {
+                let (_, total) = line.split_once("Total coverage:")?;
+                total.trim().trim_end_matches('%').parse().ok()
+            }),
+        }
+    }
+
+    /// Parses the results out of the output of the commands returned by
+    /// [`CoverageEngine::commands`], or returns `None` if it has no summary.
+    ///
+    /// tarpaulin reports every file with its uncovered lines, cargo-llvm-cov the
+    /// line counts of every file, and grcov only the total percentage.
+    pub fn parse_report(self, output: &str) -> Option<CoverageReport> {
+        match self {
+            Self::Tarpaulin => CoverageReport::parse_tarpaulin(output),
+            Self::LlvmCov => {
+                let mut report = CoverageReport::default();
+                let mut total = false;
+                for line in output.lines() {
+                    let columns: Vec<&str> =
+                        line.split_whitespace().collect();
+                    if columns.len() < 10
+                        || !columns[3].ends_with('%')
+                        || !columns[9].ends_with('%')
+                    {
+                        continue;
+                    }
+                    let (Ok(lines), Ok(missed)) = (
+                        columns[7].parse::<u64>(),
+                        columns[8].parse::<u64>(),
+                    ) else {
+                        continue;
+                    };
+                    let covered = lines.saturating_sub(missed);
+                    if columns[0] == "TOTAL" {
+                        report.lines_covered = covered;
+                        report.lines_total = lines;
+                        report.total_percent =
+                            self.parse_total(line)? as f32;
+                        total = true;
+                    } else {
+                        report.files.push(FileCoverage {
+                            path: columns[0].into(),
+                            covered,
+                            total: lines,
+                            uncovered_lines: Vec::new(),
+                        });
+                    }
+                }
+                total.then_some(report)
+            }
+            Self::Grcov => {
+                self.parse_total(output).map(CoverageReport::from_total)
+            }
+        }
+    }
+
+    /// Extracts the total line coverage, as a percentage, from the engine's JSON
+    /// report.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report is not valid JSON or has no total.
+    pub fn parse_json_total(self, json: &str) -> AnyResult<f64> {
+        let total = match self {
+            Self::Tarpaulin => return parse_json_report(json),
+            Self::LlvmCov => {
+                let report: serde_json::Value =
+                    serde_json::from_str(json)?;
+                report["data"][0]["totals"]["lines"]["percent"].as_f64()
+            }
+            Self::Grcov => {
+                let report: serde_json::Value =
+                    serde_json::from_str(json)?;
+                report["coveragePercent"].as_f64()
+            }
+        };
+        total.ok_or_else(|| {
+            AnyError::msg(format!(
+                "The {self} JSON report has no coverage total"
+            ))
+        })
+    }
+}
+
+impl fmt::Display for CoverageEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Tarpaulin => "tarpaulin",
+            Self::LlvmCov => "llvm-cov",
+            Self::Grcov => "grcov",
+        })
+    }
+}
+
+impl FromStr for CoverageEngine {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|engine| engine.to_string() == value)
+            .ok_or_else(|| {
+                AnyError::msg(format!(
+                    "Unknown coverage engine '{value}', expected one of: tarpaulin, llvm-cov, grcov"
+                ))
+            })
+    }
+}
+
+/// Translates a glob pattern into the regular expression syntax of
+/// `--ignore-filename-regex`, e.g. `src/gen/*.rs` to `src/gen/[^/]*\.rs`.
+fn glob_to_regex(pattern: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                let _ = chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '.' | '+' | '(' | ')' | '|' | '^' | '$' | '{' | '}'
+            | '[' | ']' | '\\' => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex
+}
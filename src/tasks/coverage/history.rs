@@ -0,0 +1,323 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Coverage history, kept across runs to report regressions.
+//!
+//! Every coverage run records a [`CoverageSummary`] in [`HISTORY_FILE`], keyed by
+//! the git revision and branch it measured. A [`CoverageDiff`] compares a run
+//! against a baseline, either the last run on the default branch or a file given
+//! on the command line.
+
+use super::CoverageReport;
+use crate::ops::{self, git::git, runner};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The file the coverage history is kept in.
+pub const HISTORY_FILE: &str = "target/xtask/coverage-history.json";
+
+/// The branches treated as the default branch of the repository.
+pub const DEFAULT_BRANCHES: [&str; 2] = ["main", "master"];
+
+/// The coverage percentages of a run, as kept in the history.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageSummary {
+    /// The total line coverage, as a percentage.
+    pub total_percent: f32,
+    /// The line coverage of each file with coverable lines, as a percentage.
+    #[serde(default)]
+    pub files: BTreeMap<PathBuf, f32>,
+}
+
+impl From<&CoverageReport> for CoverageSummary {
+    fn from(report: &CoverageReport) -> Self {
+        Self {
+            total_percent: report.total_percent,
+            files: report
+                .files
+                .iter()
+                .filter_map(|file| {
+                    Some((file.path.clone(), file.percent()?))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A run recorded in the history.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// The git revision that was measured.
+    pub sha: String,
+    /// The branch that was checked out.
+    pub branch: String,
+    /// The coverage of the run.
+    pub summary: CoverageSummary,
+}
+
+/// The runs recorded in [`HISTORY_FILE`], oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageHistory {
+    /// The recorded runs, oldest first.
+    #[serde(default)]
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl CoverageHistory {
+    /// Reads the history from `path`. A missing file is an empty history.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a history.
+    pub fn load(path: &Path) -> AnyResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(path).with_context(|| {
+            format!("Failed to read {}", path.display())
+        })?;
+        serde_json::from_str(&json).with_context(|| {
+            format!(
+                "Failed to parse coverage history {}",
+                path.display()
+            )
+        })
+    }
+
+    /// Writes the history to `path`, creating its directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> AnyResult<()> {
+        ops::write_atomic(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Records a run, replacing an earlier run of the same revision and branch.
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.entries.retain(|existing| {
+            existing.sha != entry.sha || existing.branch != entry.branch
+        });
+        self.entries.push(entry);
+    }
+
+    /// Returns the most recent run.
+    pub fn latest(&self) -> Option<&HistoryEntry> {
+        self.entries.last()
+    }
+
+    /// Returns the most recent run on one of the [`DEFAULT_BRANCHES`].
+    pub fn latest_on_default_branch(&self) -> Option<&HistoryEntry> {
+        self.entries.iter().rev().find(|entry| {
+            DEFAULT_BRANCHES.contains(&entry.branch.as_str())
+        })
+    }
+}
+
+/// A file whose coverage changed between two runs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileDelta {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The coverage of the file in the baseline, as a percentage.
+    pub before: f32,
+    /// The current coverage of the file, as a percentage.
+    pub after: f32,
+}
+
+impl FileDelta {
+    /// Returns the change in percentage points; negative when coverage dropped.
+    pub fn delta(&self) -> f32 {
+        self.after - self.before
+    }
+}
+
+/// The change in coverage between a baseline and the current run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageDiff {
+    /// The total coverage of the baseline, as a percentage.
+    pub before: f32,
+    /// The current total coverage, as a percentage.
+    pub after: f32,
+    /// The files whose coverage dropped by more than the allowed delta, largest
+    /// drop first.
+    pub dropped: Vec<FileDelta>,
+}
+
+impl CoverageDiff {
+    /// Compares `current` to `baseline`, listing the files whose coverage dropped
+    /// by more than `max_drop` percentage points.
+    ///
+    /// Files that only appear in one of the runs are not compared.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::coverage::history::{CoverageDiff, CoverageSummary};
+    ///
+    /// let baseline = CoverageSummary {
+    ///     total_percent: 81.4,
+    ///     ..CoverageSummary::default()
+    /// };
+    /// let current = CoverageSummary {
+    ///     total_percent: 79.9,
+    ///     ..CoverageSummary::default()
+    /// };
+    /// let diff = CoverageDiff::between(&baseline, &current, 0.0);
+    /// assert_eq!(diff.summary(), "coverage changed 81.4% → 79.9% (-1.5)");
+    /// ```
+    pub fn between(
+        baseline: &CoverageSummary,
+        current: &CoverageSummary,
+        max_drop: f32,
+    ) -> Self {
+        let mut dropped: Vec<FileDelta> = current
+            .files
+            .iter()
+            .filter_map(|(path, after)| {
+                let before = *baseline.files.get(path)?;
+                (before - after > max_drop).then(|| FileDelta {
+                    path: path.clone(),
+                    before,
+                    after: *after,
+                })
+            })
+            .collect();
+        dropped.sort_by(|a, b| a.delta().total_cmp(&b.delta()));
+        Self {
+            before: baseline.total_percent,
+            after: current.total_percent,
+            dropped,
+        }
+    }
+
+    /// Returns the change of the total coverage in percentage points.
+    pub fn delta(&self) -> f32 {
+        self.after - self.before
+    }
+
+    /// Returns the one-line summary, e.g. `coverage changed 81.4% → 79.9% (-1.5)`.
+    pub fn summary(&self) -> String {
+        format!(
+            "coverage changed {:.1}% → {:.1}% ({:+.1})",
+            self.before,
+            self.after,
+            self.delta()
+        )
+    }
+}
+
+impl fmt::Display for CoverageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.summary())?;
+        let width = self
+            .dropped
+            .iter()
+            .map(|file| file.path.to_string_lossy().len())
+            .max()
+            .unwrap_or(0)
+            .max("file".len());
+        if !self.dropped.is_empty() {
+            write!(
+                f,
+                "\n  {:<width$}  {:>7}  {:>7}  {:>7}",
+                "file", "before", "after", "change"
+            )?;
+        }
+        for file in &self.dropped {
+            write!(
+                f,
+                "\n  {:<width$}  {:>6.1}%  {:>6.1}%  {:>+7.1}",
+                file.path.display(),
+                file.before,
+                file.after,
+                file.delta()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of a baseline file: a whole history or a single summary.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Baseline {
+    Summary(CoverageSummary),
+    History(CoverageHistory),
+}
+
+/// Reads the baseline summary from `path`.
+///
+/// The file may hold a single [`CoverageSummary`] or a [`CoverageHistory`], in
+/// which case the last run on the default branch is used, or the last run if
+/// there is none.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed, or holds no runs.
+pub fn load_baseline(path: &Path) -> AnyResult<CoverageSummary> {
+    let json = fs::read_to_string(path).with_context(|| {
+        format!("Failed to read {}", path.display())
+    })?;
+    let baseline: Baseline =
+        serde_json::from_str(&json).with_context(|| {
+            format!(
+                "Failed to parse coverage baseline {}",
+                path.display()
+            )
+        })?;
+    match baseline {
+        Baseline::Summary(summary) => Ok(summary),
+        Baseline::History(history) => history
+            .latest_on_default_branch()
+            .or_else(|| history.latest())
+            .map(|entry| entry.summary.clone())
+            .ok_or_else(|| {
+                AnyError::msg(format!(
+                    "Coverage baseline {} holds no runs",
+                    path.display()
+                ))
+            }),
+    }
+}
+
+/// Compares the last recorded run in [`HISTORY_FILE`] to the baseline in
+/// `baseline`, listing every file whose coverage dropped.
+///
+/// # Errors
+///
+/// Returns an error if no run has been recorded yet or the baseline cannot be
+/// read.
+pub fn coverage_diff(baseline: &Path) -> AnyResult<CoverageDiff> {
+    let history = CoverageHistory::load(Path::new(HISTORY_FILE))?;
+    let current = history.latest().ok_or_else(|| {
+        AnyError::msg(format!(
+            "No coverage run recorded in {HISTORY_FILE}, run the coverage task first"
+        ))
+    })?;
+    Ok(CoverageDiff::between(
+        &load_baseline(baseline)?,
+        &current.summary,
+        0.0,
+    ))
+}
+
+/// Returns the revision and branch checked out in the current directory, or
+/// `None` outside of a git repository.
+pub fn current_revision() -> Option<(String, String)> {
+    let dir = Path::new(".");
+    let sha = runner::query(&git(dir, ["rev-parse", "HEAD"])).ok()?;
+    let sha = sha.trim();
+    if sha.is_empty() || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let branch =
+        runner::query(&git(dir, ["rev-parse", "--abbrev-ref", "HEAD"]))
+            .ok()?;
+    Some((sha.to_string(), branch.trim().to_string()))
+}
@@ -0,0 +1,338 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Typed coverage results, parsed from the output of the coverage engines.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// The coverage measured for a single source file.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct FileCoverage {
+    /// The path of the file, as reported by the engine.
+    pub path: PathBuf,
+    /// The number of coverable lines that were executed.
+    pub covered: u64,
+    /// The number of coverable lines.
+    pub total: u64,
+    /// The lines that were not executed, as inclusive ranges.
+    #[serde(default)]
+    pub uncovered_lines: Vec<RangeInclusive<u32>>,
+}
+
+impl FileCoverage {
+    /// Returns the line coverage of the file as a percentage, or `None` if the
+    /// file has no coverable lines.
+    pub fn percent(&self) -> Option<f32> {
+        (self.total > 0).then(|| {
+            (self.covered as f64 * 100.0 / self.total as f64) as f32
+        })
+    }
+}
+
+/// The results of a coverage run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageReport {
+    /// The total line coverage, as a percentage.
+    pub total_percent: f32,
+    /// The number of coverable lines that were executed.
+    pub lines_covered: u64,
+    /// The number of coverable lines.
+    pub lines_total: u64,
+    /// The coverage of each file, when the engine reports it.
+    #[serde(default)]
+    pub files: Vec<FileCoverage>,
+    /// The number of files left out of the measurement by `exclude_files`.
+    #[serde(default)]
+    pub excluded_files: usize,
+    /// The paths of the reports written by the run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<PathBuf>,
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}% coverage", self.total_percent)?;
+        if self.lines_total > 0 {
+            write!(
+                f,
+                ", {}/{} lines covered",
+                self.lines_covered, self.lines_total
+            )?;
+        }
+        match self.excluded_files {
+            0 => Ok(()),
+            1 => write!(f, " (1 file excluded)"),
+            count => write!(f, " ({count} files excluded)"),
+        }
+    }
+}
+
+/// The coverage of the files of a top-level module, e.g. `tasks` for the files
+/// under `src/tasks/`.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct ModuleCoverage {
+    /// The name of the module, or `other` for files outside of `src/`.
+    pub name: String,
+    /// The number of coverable lines that were executed.
+    pub covered: u64,
+    /// The number of coverable lines.
+    pub total: u64,
+}
+
+impl ModuleCoverage {
+    /// Returns the line coverage of the module as a percentage, or `None` if it
+    /// has no coverable lines.
+    pub fn percent(&self) -> Option<f32> {
+        (self.total > 0).then(|| {
+            (self.covered as f64 * 100.0 / self.total as f64) as f32
+        })
+    }
+}
+
+/// Returns the top-level module a source file belongs to: the first component
+/// after `src/`, without extension, or `other` for files outside of `src/`.
+fn module_of(path: &Path) -> String {
+    let mut components = path.components().map(|c| c.as_os_str());
+    if components.by_ref().any(|component| component == "src") {
+        if let Some(first) = components.next() {
+            return Path::new(first)
+                .file_stem()
+                .unwrap_or(first)
+                .to_string_lossy()
+                .into_owned();
+        }
+    }
+    "other".to_string()
+}
+
+impl CoverageReport {
+    /// Creates a report that only knows the total percentage.
+    pub fn from_total(total_percent: f64) -> Self {
+        Self {
+            total_percent: total_percent as f32,
+            ..Self::default()
+        }
+    }
+
+    /// Groups the files by top-level module, least covered first.
+    ///
+    /// `src/tasks.rs` and the files under `src/tasks/` make up `tasks`, and
+    /// files outside of `src/`, such as tests and examples, make up `other`.
+    /// Modules without coverable lines come last.
+    pub fn modules(&self) -> Vec<ModuleCoverage> {
+        let mut modules: BTreeMap<String, ModuleCoverage> =
+            BTreeMap::new();
+        for file in &self.files {
+            let name = module_of(&file.path);
+            let module =
+                modules.entry(name.clone()).or_insert(ModuleCoverage {
+                    name,
+                    ..ModuleCoverage::default()
+                });
+            module.covered += file.covered;
+            module.total += file.total;
+        }
+        let mut modules: Vec<ModuleCoverage> =
+            modules.into_values().collect();
+        modules.sort_by(|a, b| match (a.percent(), b.percent()) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+        modules
+    }
+
+    /// Renders the coverage of each module as a table, followed by a `TOTAL`
+    /// row.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::coverage::{CoverageReport, FileCoverage};
+    ///
+    /// let report = CoverageReport {
+    ///     files: vec![FileCoverage {
+    ///         path: "src/lib.rs".into(),
+    ///         covered: 3,
+    ///         total: 4,
+    ///         uncovered_lines: vec![],
+    ///     }],
+    ///     ..CoverageReport::default()
+    /// };
+    /// assert!(report.module_table().ends_with("TOTAL         3      4    75.00%\n"));
+    /// ```
+    pub fn module_table(&self) -> String {
+        let mut rows: Vec<ModuleCoverage> = self.modules();
+        let (covered, total) = if self.lines_total > 0 {
+            (self.lines_covered, self.lines_total)
+        } else {
+            rows.iter().fold((0, 0), |(covered, total), module| {
+                (covered + module.covered, total + module.total)
+            })
+        };
+        rows.push(ModuleCoverage {
+            name: "TOTAL".to_string(),
+            covered,
+            total,
+        });
+        let width = rows
+            .iter()
+            .map(|row| row.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("module".len());
+        let mut table = format!(
+            "{:<width$}  {:>7}  {:>5}  {:>8}\n",
+            "module", "covered", "total", "coverage"
+        );
+        for row in &rows {
+            let percent = row.percent().map_or_else(
+                || "-".to_string(),
+                |p| format!("{p:.2}%"),
+            );
+            table.push_str(&format!(
+                "{:<width$}  {:>7}  {:>5}  {:>8}\n",
+                row.name, row.covered, row.total, percent
+            ));
+        }
+        table
+    }
+
+    /// Parses the output of `cargo tarpaulin`.
+    ///
+    /// The per-file counts come from the `Tested/Total Lines` block, the uncovered
+    /// ranges from the `Uncovered Lines` block, and the totals from the final
+    /// `NN.NN% coverage, covered/total lines covered` line. Returns `None` if the
+    /// output has no such summary line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::coverage::CoverageReport;
+    ///
+    /// let output = "\
+    /// || Uncovered Lines:
+    /// || src/lib.rs: 6, 9-10
+    /// || Tested/Total Lines:
+    /// || src/lib.rs: 7/10
+    /// ||
+    /// 70.00% coverage, 7/10 lines covered
+    /// ";
+    /// let report = CoverageReport::parse_tarpaulin(output).unwrap();
+    /// assert_eq!(report.lines_covered, 7);
+    /// assert_eq!(report.files[0].uncovered_lines, [6..=6, 9..=10]);
+    /// ```
+    pub fn parse_tarpaulin(output: &str) -> Option<Self> {
+        #[derive(PartialEq)]
+        enum Section {
+            None,
+            Uncovered,
+            Tested,
+        }
+
+        let mut report = Self::default();
+        let mut uncovered: Vec<(PathBuf, Vec<RangeInclusive<u32>>)> =
+            Vec::new();
+        let mut section = Section::None;
+        let mut summary = false;
+        for line in output.lines() {
+            if let Some(entry) = line.trim_start().strip_prefix("||") {
+                let entry = entry.trim();
+                if entry == "Uncovered Lines:" {
+                    section = Section::Uncovered;
+                    continue;
+                }
+                if entry == "Tested/Total Lines:" {
+                    section = Section::Tested;
+                    continue;
+                }
+                let Some((path, value)) = entry.rsplit_once(": ")
+                else {
+                    continue;
+                };
+                match section {
+                    Section::Uncovered => uncovered.push((
+                        PathBuf::from(path),
+                        parse_ranges(value),
+                    )),
+                    Section::Tested => {
+                        // Reruns append a delta such as `+1.25%`.
+                        let counts = value
+                            .split_whitespace()
+                            .next()
+                            .unwrap_or("");
+                        if let Some((covered, total)) =
+                            parse_fraction(counts)
+                        {
+                            report.files.push(FileCoverage {
+                                path: PathBuf::from(path),
+                                covered,
+                                total,
+                                uncovered_lines: Vec::new(),
+                            });
+                        }
+                    }
+                    Section::None => {}
+                }
+                continue;
+            }
+
+            let Some((percent, rest)) = line.split_once("% coverage, ")
+            else {
+                continue;
+            };
+            let Some(percent) = percent
+                .split_whitespace()
+                .last()
+                .and_then(|percent| percent.parse::<f32>().ok())
+            else {
+                continue;
+            };
+            let counts = rest.split_whitespace().next().unwrap_or("");
+            let (covered, total) =
+                parse_fraction(counts).unwrap_or((0, 0));
+            report.total_percent = percent;
+            report.lines_covered = covered;
+            report.lines_total = total;
+            summary = true;
+        }
+
+        for (path, ranges) in uncovered {
+            if let Some(file) =
+                report.files.iter_mut().find(|file| file.path == path)
+            {
+                file.uncovered_lines = ranges;
+            }
+        }
+        summary.then_some(report)
+    }
+}
+
+/// Parses `covered/total`.
+fn parse_fraction(value: &str) -> Option<(u64, u64)> {
+    let (covered, total) = value.split_once('/')?;
+    Some((covered.trim().parse().ok()?, total.trim().parse().ok()?))
+}
+
+/// Parses a list of lines and ranges such as `4, 8-10`.
+fn parse_ranges(value: &str) -> Vec<RangeInclusive<u32>> {
+    value
+        .split([',', ' '])
+        .filter(|item| !item.is_empty())
+        .filter_map(|item| match item.split_once('-') {
+            Some((start, end)) => {
+                Some(start.parse().ok()?..=end.parse().ok()?)
+            }
+            None => item.parse().ok().map(|line| line..=line),
+        })
+        .collect()
+}
@@ -0,0 +1,523 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Uploads of coverage results to hosted coverage services.
+//!
+//! Tokens are only read from environment variables. They are handed to the
+//! uploader through files readable by the current user only, or through the
+//! environment of the provider's CLI, so they never appear in a command line,
+//! a log line, or an error message.
+
+use super::history;
+use crate::ops::{
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A hosted coverage service the results can be uploaded to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CoverageProvider {
+    /// [Codecov](https://codecov.io), given the variable holding the upload token.
+    Codecov {
+        /// The environment variable holding the upload token.
+        token_env: String,
+    },
+    /// [Coveralls](https://coveralls.io), given the variable holding the repo token.
+    Coveralls {
+        /// The environment variable holding the repo token.
+        token_env: String,
+    },
+}
+
+impl CoverageProvider {
+    /// Codecov, with the token read from `CODECOV_TOKEN`.
+    pub fn codecov() -> Self {
+        Self::Codecov {
+            token_env: "CODECOV_TOKEN".to_string(),
+        }
+    }
+
+    /// Coveralls, with the token read from `COVERALLS_REPO_TOKEN`.
+    pub fn coveralls() -> Self {
+        Self::Coveralls {
+            token_env: "COVERALLS_REPO_TOKEN".to_string(),
+        }
+    }
+
+    /// Returns the environment variable holding the token.
+    pub fn token_env(&self) -> &str {
+        match self {
+            Self::Codecov { token_env }
+            | Self::Coveralls { token_env } => token_env,
+        }
+    }
+
+    /// Returns the HTTP endpoint the results are posted to.
+    pub const fn default_endpoint(&self) -> &'static str {
+        match self {
+            Self::Codecov { .. } => "https://codecov.io/upload/v2",
+            Self::Coveralls { .. } => {
+                "https://coveralls.io/api/v1/jobs"
+            }
+        }
+    }
+
+    /// Returns the provider's CLI, used instead of the HTTP endpoint when it
+    /// is installed, and the environment variable it reads the token from.
+    const fn cli(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::Codecov { .. } => ("codecovcli", "CODECOV_TOKEN"),
+            Self::Coveralls { .. } => {
+                ("coveralls", "COVERALLS_REPO_TOKEN")
+            }
+        }
+    }
+}
+
+impl fmt::Display for CoverageProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Codecov { .. } => "codecov",
+            Self::Coveralls { .. } => "coveralls",
+        })
+    }
+}
+
+impl FromStr for CoverageProvider {
+    type Err = AnyError;
+
+    fn from_str(s: &str) -> AnyResult<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "codecov" => Ok(Self::codecov()),
+            "coveralls" => Ok(Self::coveralls()),
+            _ => Err(AnyError::msg(format!(
+                "Unknown coverage provider '{s}', expected one of: codecov, coveralls"
+            ))),
+        }
+    }
+}
+
+/// Why an upload failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UploadError {
+    /// The service rejected the token.
+    Auth {
+        /// The provider that was uploaded to.
+        provider: String,
+        /// The HTTP status, 401 or 403.
+        status: u16,
+    },
+    /// The service could not be reached.
+    Network {
+        /// The provider that was uploaded to.
+        provider: String,
+        /// What went wrong, as reported by the uploader.
+        message: String,
+    },
+    /// The service refused the upload for another reason.
+    Rejected {
+        /// The provider that was uploaded to.
+        provider: String,
+        /// The HTTP status.
+        status: u16,
+        /// The body of the response.
+        body: String,
+    },
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auth { provider, status } => write!(
+                f,
+                "{provider} rejected the upload token (HTTP {status})"
+            ),
+            Self::Network { provider, message } => {
+                write!(f, "Failed to reach {provider}: {message}")
+            }
+            Self::Rejected {
+                provider,
+                status,
+                body,
+            } => write!(
+                f,
+                "{provider} rejected the upload (HTTP {status}): {body}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+/// Uploads the LCOV report at `report_path` to `provider`.
+///
+/// The provider's CLI, `codecovcli upload-process` or `coveralls report`, is
+/// used when it is installed; otherwise the report is posted to its HTTP
+/// endpoint with `curl`. The token is never printed, even in a dry run.
+///
+/// # Errors
+///
+/// Returns an error if the token variable is not set or the report is missing,
+/// or an [`UploadError`] if the upload fails. A failed CLI upload is reported
+/// as [`UploadError::Auth`] or [`UploadError::Network`] when its output says
+/// the token was rejected or the service could not be reached.
+pub fn coverage_upload(
+    provider: CoverageProvider,
+    report_path: &Path,
+) -> AnyResult<()> {
+    let (cli, cli_token_env) = provider.cli();
+    if !tools::is_installed(cli) {
+        return coverage_upload_to(
+            &provider,
+            report_path,
+            provider.default_endpoint(),
+        );
+    }
+    let token = read_token(&provider)?;
+    if !runner::is_dry_run() {
+        ensure_report(report_path)?;
+    }
+    let command = match provider {
+        CoverageProvider::Codecov { .. } => {
+            CommandSpec::new(cli).args(["upload-process", "--file"])
+        }
+        CoverageProvider::Coveralls { .. } => {
+            CommandSpec::new(cli).arg("report")
+        }
+    }
+    .arg(report_path.to_string_lossy())
+    .secret_env(cli_token_env, &token)
+    .captured();
+    println!(
+        "Uploading {} to {provider} with '{command}'",
+        report_path.display()
+    );
+    let output =
+        runner::current().execute(&command).map_err(|err| {
+            UploadError::Network {
+                provider: provider.to_string(),
+                message: err.to_string(),
+            }
+        })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let stderr = redact(stderr.trim(), &token);
+    Err(match cli_failure(&provider, &stderr) {
+        Some(error) => error.into(),
+        None => AnyError::msg(format!(
+            "'{command}' exited with {}: {stderr}",
+            output.status
+        )),
+    })
+}
+
+/// Tells from the output of a failed provider CLI whether the token was
+/// rejected or the service could not be reached.
+fn cli_failure(
+    provider: &CoverageProvider,
+    stderr: &str,
+) -> Option<UploadError> {
+    const AUTH: [&str; 6] = [
+        "401",
+        "403",
+        "unauthorized",
+        "forbidden",
+        "invalid token",
+        "token required",
+    ];
+    const NETWORK: [&str; 6] = [
+        "connection refused",
+        "could not resolve",
+        "name or service not known",
+        "failed to establish a new connection",
+        "max retries exceeded",
+        "timed out",
+    ];
+    let lower = stderr.to_lowercase();
+    let provider = provider.to_string();
+    if AUTH.iter().any(|marker| lower.contains(marker)) {
+        let status =
+            if lower.contains("403") || lower.contains("forbidden") {
+                403
+            } else {
+                401
+            };
+        Some(UploadError::Auth { provider, status })
+    } else if NETWORK.iter().any(|marker| lower.contains(marker)) {
+        Some(UploadError::Network {
+            provider,
+            message: stderr.to_string(),
+        })
+    } else {
+        None
+    }
+}
+
+/// Posts the LCOV report at `report_path` to `endpoint` with `curl`, the way
+/// the provider's own uploaders do.
+///
+/// Codecov receives the report as the `file` part of a multipart form, with
+/// the token in the `Authorization` header. Coveralls receives a job as the
+/// `json_file` part, converted from the report.
+///
+/// In a dry run (see [`runner::is_dry_run`]), the upload is only printed.
+///
+/// # Errors
+///
+/// Returns an error if the token variable is not set or the report cannot be
+/// read, an [`UploadError::Auth`] if the token is rejected, an
+/// [`UploadError::Network`] if the endpoint cannot be reached, and an
+/// [`UploadError::Rejected`] for any other failed response.
+pub fn coverage_upload_to(
+    provider: &CoverageProvider,
+    report_path: &Path,
+    endpoint: &str,
+) -> AnyResult<()> {
+    let token = read_token(provider)?;
+    if runner::is_dry_run() {
+        // The report and the secret files holding the token are not written
+        // in a dry run, and there is no response to check.
+        runner::print_dry_run(&format!(
+            "curl {endpoint} with {} for {provider}",
+            report_path.display()
+        ));
+        return Ok(());
+    }
+    ensure_report(report_path)?;
+    let revision = history::current_revision();
+
+    let command = CommandSpec::new("curl").args([
+        "--silent",
+        "--show-error",
+        "--max-time",
+        "120",
+        "--write-out",
+        "\n%{http_code}",
+    ]);
+    let (command, secret) = match provider {
+        CoverageProvider::Codecov { .. } => {
+            let headers = SecretFile::create(
+                "headers",
+                &format!("Authorization: token {token}\n"),
+            )?;
+            let mut query = vec![("service", "xtasks".to_string())];
+            if let Some((sha, branch)) = revision {
+                query.push(("commit", sha));
+                query.push(("branch", branch));
+            }
+            let query: Vec<String> = query
+                .iter()
+                .map(|(key, value)| {
+                    format!("{key}={}", url_encode(value))
+                })
+                .collect();
+            let command = command
+                .arg("--header")
+                .arg(format!("@{}", headers.path.display()))
+                .arg("--form")
+                .arg(format!("file=@{}", report_path.display()))
+                .arg(format!("{endpoint}?{}", query.join("&")));
+            (command, headers)
+        }
+        CoverageProvider::Coveralls { .. } => {
+            let lcov =
+                fs::read_to_string(report_path).with_context(|| {
+                    format!("Failed to read {}", report_path.display())
+                })?;
+            let mut job = serde_json::json!({
+                "repo_token": token,
+                "service_name": "xtasks",
+                "source_files": coveralls_source_files(&lcov),
+            });
+            if let Some((sha, branch)) = revision {
+                job["git"] = serde_json::json!({
+                    "head": { "id": sha },
+                    "branch": branch,
+                });
+            }
+            let job = SecretFile::create("json", &job.to_string())?;
+            let command = command
+                .arg("--form")
+                .arg(format!(
+                    "json_file=@{};type=application/json",
+                    job.path.display()
+                ))
+                .arg(endpoint);
+            (command, job)
+        }
+    };
+
+    println!("Uploading {} to {provider}", report_path.display());
+    let output = runner::current().execute(&command.captured());
+    drop(secret);
+    let network = |message: String| UploadError::Network {
+        provider: provider.to_string(),
+        message: redact(&message, &token),
+    };
+    let output = output.map_err(|err| network(err.to_string()))?;
+    if !output.status.success() {
+        return Err(network(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )
+        .into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (body, status) =
+        stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    let status: u16 = status.trim().parse().unwrap_or(0);
+    match status {
+        200..=299 => Ok(()),
+        401 | 403 => Err(UploadError::Auth {
+            provider: provider.to_string(),
+            status,
+        }
+        .into()),
+        _ => Err(UploadError::Rejected {
+            provider: provider.to_string(),
+            status,
+            body: redact(body.trim(), &token),
+        }
+        .into()),
+    }
+}
+
+/// Reads the provider's token from its environment variable.
+fn read_token(provider: &CoverageProvider) -> AnyResult<String> {
+    let name = provider.token_env();
+    env::var(name)
+        .ok()
+        .filter(|token| !token.trim().is_empty())
+        .map(|token| token.trim().to_string())
+        .ok_or_else(|| {
+            AnyError::msg(format!(
+                "Set {name} to upload coverage to {provider}"
+            ))
+        })
+}
+
+/// Checks that the report to upload exists.
+fn ensure_report(report_path: &Path) -> AnyResult<()> {
+    if report_path.is_file() {
+        Ok(())
+    } else {
+        Err(AnyError::msg(format!(
+            "Coverage report {} not found, generate an LCOV report first",
+            report_path.display()
+        )))
+    }
+}
+
+/// Replaces every occurrence of `token` in `text`.
+fn redact(text: &str, token: &str) -> String {
+    if token.is_empty() {
+        text.to_string()
+    } else {
+        text.replace(token, "***")
+    }
+}
+
+/// Percent-encodes a query parameter value.
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'~' => (byte as char).to_string(),
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Converts an LCOV tracefile into the `source_files` of a Coveralls job: the
+/// hit count of each line, or `null` for lines without code.
+fn coveralls_source_files(lcov: &str) -> Vec<serde_json::Value> {
+    let mut files = Vec::new();
+    let mut current: Option<(String, Vec<Option<u64>>)> = None;
+    for line in lcov.lines() {
+        if let Some(name) = line.strip_prefix("SF:") {
+            current = Some((name.to_string(), Vec::new()));
+        } else if let Some(hits) = line.strip_prefix("DA:") {
+            let mut fields = hits.split(',');
+            let (
+                Some(Ok(number)),
+                Some(Ok(count)),
+                Some((_, coverage)),
+            ) = (
+                fields.next().map(str::parse::<usize>),
+                fields.next().map(str::parse::<u64>),
+                current.as_mut(),
+            )
+            else {
+                continue;
+            };
+            if number > coverage.len() {
+                coverage.resize(number, None);
+            }
+            if number > 0 {
+                coverage[number - 1] = Some(count);
+            }
+        } else if line == "end_of_record" {
+            if let Some((name, coverage)) = current.take() {
+                files.push(serde_json::json!({
+                    "name": name,
+                    "coverage": coverage,
+                }));
+            }
+        }
+    }
+    files
+}
+
+/// A temporary file readable by the current user only, holding a secret for
+/// the uploader. It is removed when dropped.
+#[derive(Debug)]
+struct SecretFile {
+    path: PathBuf,
+}
+
+impl SecretFile {
+    fn create(extension: &str, contents: &str) -> AnyResult<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos());
+        let path = env::temp_dir().join(format!(
+            "xtasks-upload-{}-{nanos}.{extension}",
+            process::id()
+        ));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path).with_context(|| {
+            format!("Failed to create {}", path.display())
+        })?;
+        std::io::Write::write_all(&mut file, contents.as_bytes())?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,383 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `cross` task, which builds the release binaries for other targets.
+//!
+//! Each target gets the first backend that can build it: plain cargo for the
+//! host, `cross` when a container engine is available, `cargo-zigbuild` when
+//! zig is installed, and plain cargo again when the standard library of the
+//! target is installed with rustup. The backend is installed when missing,
+//! per the auto-install policy of [`tools`].
+
+use crate::ops::{
+    error::XtaskError,
+    metadata,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// A way of building for another target.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum CrossBackend {
+    /// `cargo build`, for the host and the targets installed with rustup.
+    Cargo,
+    /// `cross build`, which builds in a container.
+    Cross,
+    /// `cargo zigbuild`, which links with zig.
+    Zigbuild,
+}
+
+impl CrossBackend {
+    /// Every backend, in the order they are listed in error messages.
+    pub const ALL: [Self; 3] =
+        [Self::Cargo, Self::Cross, Self::Zigbuild];
+
+    /// Returns the release build command of the backend for `target`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::cross::CrossBackend;
+    ///
+    /// assert_eq!(
+    ///     CrossBackend::Zigbuild
+    ///         .command("x86_64-unknown-linux-musl")
+    ///         .to_string(),
+    ///     "cargo zigbuild --release --target x86_64-unknown-linux-musl"
+    /// );
+    /// ```
+    pub fn command(self, target: &str) -> CommandSpec {
+        let command = match self {
+            Self::Cargo => CommandSpec::new("cargo").arg("build"),
+            Self::Cross => CommandSpec::new("cross").arg("build"),
+            Self::Zigbuild => CommandSpec::new("cargo").arg("zigbuild"),
+        };
+        command.args(["--release", "--target", target])
+    }
+
+    /// Makes sure the backend can build `target`, installing the tool or
+    /// the target if `auto_install` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if something is missing and may not be installed,
+    /// or if the installation fails.
+    pub fn ensure(
+        self,
+        target: &str,
+        auto_install: bool,
+    ) -> AnyResult<()> {
+        match self {
+            Self::Cargo => {
+                let _ = tools::ensure_target(target, auto_install)?;
+            }
+            Self::Cross => {
+                let _ = tools::ensure_tool(
+                    "cross",
+                    &["cross"],
+                    auto_install,
+                )?;
+            }
+            Self::Zigbuild => {
+                let _ = tools::ensure_tool(
+                    "cargo-zigbuild",
+                    &["cargo-zigbuild"],
+                    auto_install,
+                )?;
+                let _ = tools::ensure_target(target, auto_install)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CrossBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Cargo => "cargo",
+            Self::Cross => "cross",
+            Self::Zigbuild => "zigbuild",
+        })
+    }
+}
+
+impl FromStr for CrossBackend {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|backend| backend.to_string() == value)
+            .ok_or_else(|| {
+                AnyError::msg(format!(
+                    "Unknown cross-compilation backend '{value}', expected one of: cargo, cross, zigbuild"
+                ))
+            })
+    }
+}
+
+/// What is installed on the machine, as far as the backends are concerned.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct ToolProbe {
+    /// The target triple of the host.
+    pub host: String,
+    /// Whether docker or podman is installed, for `cross`.
+    pub container_engine: bool,
+    /// Whether zig is installed, for `cargo-zigbuild`.
+    pub zig: bool,
+    /// The targets whose standard library is installed with rustup.
+    pub installed_targets: Vec<String>,
+}
+
+impl ToolProbe {
+    /// Probes the current machine.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host triple cannot be read.
+    pub fn detect() -> AnyResult<Self> {
+        let installed_targets =
+            runner::query(&CommandSpec::new("rustup").args([
+                "target",
+                "list",
+                "--installed",
+            ]))
+            .map(|installed| {
+                installed
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            host: tools::host_triple()?,
+            container_engine: tools::is_installed("docker")
+                || tools::is_installed("podman"),
+            zig: tools::is_installed("zig"),
+            installed_targets,
+        })
+    }
+
+    /// Returns the backend building `target`: cargo for the host, cross
+    /// with a container engine, zigbuild with zig unless the target uses
+    /// the MSVC toolchain, then cargo if the target is installed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming what to install if no backend can build
+    /// `target`.
+    pub fn select(&self, target: &str) -> AnyResult<CrossBackend> {
+        if target == self.host {
+            Ok(CrossBackend::Cargo)
+        } else if self.container_engine {
+            Ok(CrossBackend::Cross)
+        } else if self.zig && !target.ends_with("-msvc") {
+            Ok(CrossBackend::Zigbuild)
+        } else if self.installed_targets.iter().any(|t| t == target) {
+            Ok(CrossBackend::Cargo)
+        } else {
+            Err(AnyError::msg(format!(
+                "No backend can build {target}: install docker for cross, zig for cargo-zigbuild, or add the target with 'rustup target add {target}'"
+            )))
+        }
+    }
+}
+
+/// The options of the `cross` task.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct CrossOptions {
+    /// The binaries to build. By default, this is empty, and every binary
+    /// of the workspace is built.
+    #[builder(default)]
+    #[serde(default)]
+    pub binaries: Vec<String>,
+
+    /// The backend used for every target. By default, this is `None`, and
+    /// each target gets the backend [selected](ToolProbe::select) for it.
+    #[builder(default)]
+    #[serde(default)]
+    pub backend: Option<CrossBackend>,
+
+    /// The cargo target directory. By default, this is `None`, and the one
+    /// of the workspace is used.
+    #[builder(default)]
+    #[serde(default)]
+    pub target_dir: Option<PathBuf>,
+
+    /// Installs the missing backends and targets. The
+    /// `XTASKS_NO_AUTO_INSTALL` environment variable turns it off.
+    ///
+    /// By default, this is set to `true`.
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub auto_install: bool,
+}
+
+/// Returns `true`, the default of [`CrossOptions::auto_install`].
+const fn default_true() -> bool {
+    true
+}
+
+impl Default for CrossOptions {
+    fn default() -> Self {
+        Self {
+            binaries: Vec::new(),
+            backend: None,
+            target_dir: None,
+            auto_install: true,
+        }
+    }
+}
+
+/// Returns the release binaries `binaries` built for `target` in
+/// `target_dir`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use xtasks::tasks::cross::binary_paths;
+///
+/// assert_eq!(
+///     binary_paths(
+///         Path::new("target"),
+///         "x86_64-pc-windows-gnu",
+///         &["app".to_string()]
+///     ),
+///     [Path::new("target/x86_64-pc-windows-gnu/release/app.exe")]
+/// );
+/// ```
+pub fn binary_paths(
+    target_dir: &Path,
+    target: &str,
+    binaries: &[String],
+) -> Vec<PathBuf> {
+    let suffix = if target.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    };
+    binaries
+        .iter()
+        .map(|binary| {
+            target_dir
+                .join(target)
+                .join("release")
+                .join(format!("{binary}{suffix}"))
+        })
+        .collect()
+}
+
+/// Builds `target` with `backend`, returning the paths of the binaries.
+fn build_target(
+    backend: CrossBackend,
+    target: &str,
+    binaries: &[String],
+    target_dir: &Path,
+    auto_install: bool,
+) -> AnyResult<Vec<PathBuf>> {
+    backend.ensure(target, auto_install)?;
+    let mut command = backend.command(target);
+    for binary in binaries {
+        command = command.args(["--bin", binary]);
+    }
+    let _ = runner::run(&command)?;
+    Ok(binary_paths(target_dir, target, binaries))
+}
+
+/// Builds the binaries of the workspace in release mode for each of
+/// `targets`, with the backend selected for it or the one of `opts`.
+///
+/// Every target is attempted, and the failures are reported together.
+///
+/// # Returns
+///
+/// The paths of the built binaries, target by target, e.g. for the `dist`
+/// task to package.
+///
+/// # Errors
+///
+/// Returns an error if the workspace has no binary, or naming each target
+/// that failed to build with its backend and the reason.
+pub fn build(
+    targets: &[String],
+    opts: CrossOptions,
+) -> AnyResult<Vec<PathBuf>> {
+    let binaries = if opts.binaries.is_empty() {
+        metadata::binary_targets()?
+            .into_iter()
+            .map(|binary| binary.name)
+            .collect()
+    } else {
+        opts.binaries.clone()
+    };
+    if binaries.is_empty() {
+        return Err(AnyError::msg(
+            "The workspace has no binary to build",
+        ));
+    }
+    let target_dir = match &opts.target_dir {
+        Some(dir) => dir.clone(),
+        None => metadata::target_directory()?,
+    };
+    let probe = match opts.backend {
+        Some(_) => ToolProbe::default(),
+        None => ToolProbe::detect()?,
+    };
+
+    let mut paths = Vec::new();
+    let mut failures = Vec::new();
+    for target in targets {
+        let backend = match opts.backend {
+            Some(backend) => backend,
+            None => match probe.select(target) {
+                Ok(backend) => backend,
+                Err(err) => {
+                    failures.push(format!("{target}: {err}"));
+                    continue;
+                }
+            },
+        };
+        println!("Building {target} with {backend}");
+        match build_target(
+            backend,
+            target,
+            &binaries,
+            &target_dir,
+            opts.auto_install,
+        ) {
+            Ok(built) => paths.extend(built),
+            Err(err) => {
+                failures.push(format!("{target} ({backend}): {err:#}"))
+            }
+        }
+    }
+    if failures.is_empty() {
+        return Ok(paths);
+    }
+    Err(AnyError::new(XtaskError::StepFailed {
+        step: "cross".to_string(),
+    })
+    .context(format!(
+        "Failed to build {} of {} targets:\n  {}",
+        failures.len(),
+        targets.len(),
+        failures.join("\n  ")
+    )))
+}
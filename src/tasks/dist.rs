@@ -0,0 +1,247 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Packaging of the release binaries into distributable archives.
+//!
+//! For each target, [`dist`] builds the binaries in release mode, stages them
+//! with the extra files (licenses, README) in a
+//! `<package>-v<version>-<target>` directory, and packages that directory into
+//! an archive of the same name. A `SHA256SUMS` file lists the checksums of the
+//! archives.
+
+use crate::ops::{
+    self,
+    archive::{self, ArchiveFormat, CHECKSUMS_FILE},
+    metadata,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use glob::glob;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The extra files packaged with the binaries unless configured otherwise.
+pub const DEFAULT_EXTRA_FILES: [&str; 2] = ["LICENSE*", "README*"];
+
+/// The configuration of the release archives.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct DistConfig {
+    /// The target triples to package, e.g. `aarch64-apple-darwin`.
+    ///
+    /// By default, this is empty and only the host is packaged.
+    #[builder(default)]
+    #[serde(default)]
+    pub targets: Vec<String>,
+
+    /// The binaries to package.
+    ///
+    /// By default, this is empty and every binary of the workspace is
+    /// packaged.
+    #[builder(default)]
+    #[serde(default)]
+    pub binaries: Vec<String>,
+
+    /// Glob patterns of the files packaged with the binaries, relative to the
+    /// current directory. Patterns matching nothing are ignored.
+    ///
+    /// By default, this is set to [`DEFAULT_EXTRA_FILES`].
+    #[builder(default = "default_extra_files()")]
+    #[serde(default = "default_extra_files")]
+    pub extra_files: Vec<String>,
+
+    /// The format of every archive.
+    ///
+    /// By default, this is `None`: zip for Windows targets, tar.gz for the
+    /// others.
+    #[builder(default)]
+    #[serde(default)]
+    pub format: Option<ArchiveFormat>,
+
+    /// The directory the archives and the checksums are written to.
+    ///
+    /// By default, this is `None`: the `dist` directory of the target
+    /// directory.
+    #[builder(default)]
+    #[serde(default)]
+    pub output_dir: Option<PathBuf>,
+
+    /// Strips the symbols of the packaged binaries with `strip`. Ignored for
+    /// Windows targets.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub strip: bool,
+}
+
+impl Default for DistConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            binaries: Vec::new(),
+            extra_files: default_extra_files(),
+            format: None,
+            output_dir: None,
+            strip: false,
+        }
+    }
+}
+
+/// Returns [`DEFAULT_EXTRA_FILES`] as owned patterns.
+fn default_extra_files() -> Vec<String> {
+    DEFAULT_EXTRA_FILES
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Returns the name of the archive of `package` at `version` for `target`,
+/// without its extension.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::dist::archive_stem;
+///
+/// assert_eq!(
+///     archive_stem("myapp", "1.2.0", "x86_64-unknown-linux-gnu"),
+///     "myapp-v1.2.0-x86_64-unknown-linux-gnu"
+/// );
+/// ```
+pub fn archive_stem(
+    package: &str,
+    version: &str,
+    target: &str,
+) -> String {
+    format!("{package}-v{version}-{target}")
+}
+
+/// Returns the files matched by the glob `patterns`, in pattern order.
+fn extra_files(patterns: &[String]) -> AnyResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        let entries = glob(pattern).map_err(|err| {
+            AnyError::msg(format!("Invalid pattern '{pattern}': {err}"))
+        })?;
+        for path in entries.flatten() {
+            if path.is_file() && !files.contains(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Builds the binaries of the workspace in release mode for each target of
+/// `config`, packages them with the extra files, and writes the checksums of
+/// the archives to `SHA256SUMS`.
+///
+/// In a dry run, the builds, copies and archives are printed instead.
+///
+/// # Returns
+///
+/// The paths of the archives, followed by the path of `SHA256SUMS`.
+///
+/// # Errors
+///
+/// Returns an error if the workspace has no binary, if a build, a copy or an
+/// archive fails, or if the checksums cannot be written.
+pub fn dist(config: DistConfig) -> AnyResult<Vec<PathBuf>> {
+    let package = metadata::root_package()?;
+    let version = metadata::root_version()?;
+    let binaries = if config.binaries.is_empty() {
+        metadata::binary_targets()?
+            .into_iter()
+            .map(|binary| binary.name)
+            .collect()
+    } else {
+        config.binaries.clone()
+    };
+    if binaries.is_empty() {
+        return Err(AnyError::msg(
+            "The workspace has no binary to package",
+        ));
+    }
+    let target_dir = metadata::target_directory()?;
+    let output_dir = config
+        .output_dir
+        .clone()
+        .unwrap_or_else(|| target_dir.join("dist"));
+    let extras = extra_files(&config.extra_files)?;
+
+    let targets: Vec<(String, bool)> = if config.targets.is_empty() {
+        vec![(tools::host_triple()?, false)]
+    } else {
+        config
+            .targets
+            .iter()
+            .map(|target| (target.clone(), true))
+            .collect()
+    };
+    let mut archives = Vec::new();
+    for (target, explicit) in targets {
+        let mut build =
+            CommandSpec::new("cargo").args(["build", "--release"]);
+        for binary in &binaries {
+            build = build.args(["--bin", binary]);
+        }
+        let release_dir = if explicit {
+            build = build.args(["--target", &target]);
+            target_dir.join(&target).join("release")
+        } else {
+            target_dir.join("release")
+        };
+        let _ = runner::run(&build)
+            .with_context(|| format!("Failed to execute '{build}'"))?;
+
+        let stem = archive_stem(&package, &version, &target);
+        let staging = output_dir.join(&stem);
+        if staging.exists() {
+            ops::remove_dir(&staging)?;
+        }
+        let windows = target.contains("windows");
+        let suffix = if windows { ".exe" } else { "" };
+        for binary in &binaries {
+            let file = format!("{binary}{suffix}");
+            let staged = staging.join(&file);
+            let _ = ops::copy_file(release_dir.join(&file), &staged)?;
+            if config.strip && !windows {
+                let strip = CommandSpec::new("strip")
+                    .arg(staged.to_string_lossy());
+                let _ = runner::run(&strip).with_context(|| {
+                    format!("Failed to execute '{strip}'")
+                })?;
+            }
+        }
+        for extra in &extras {
+            if let Some(name) = extra.file_name() {
+                let _ = ops::copy_file(extra, staging.join(name))?;
+            }
+        }
+
+        let format = config
+            .format
+            .unwrap_or_else(|| ArchiveFormat::for_target(&target));
+        let path =
+            output_dir.join(format!("{stem}.{}", format.extension()));
+        archive::create(format, &staging, &path)?;
+        ops::remove_dir(&staging)?;
+        println!("Packaged {}", path.display());
+        archives.push(path);
+    }
+
+    let sums_path = output_dir.join(CHECKSUMS_FILE);
+    let sums = if runner::is_dry_run() {
+        String::new()
+    } else {
+        archive::checksums(&archives)?
+    };
+    ops::write_atomic(&sums_path, sums)?;
+    archives.push(sums_path);
+    Ok(archives)
+}
@@ -0,0 +1,335 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `docker` task, which builds the container image of the release
+//! binary.
+//!
+//! The image is built with docker, or podman when docker is not installed,
+//! after checking that the daemon answers, so a stopped daemon is reported
+//! as such rather than as a failed build. It is tagged with the package
+//! version, `latest` and the short commit hash, and optionally pushed.
+
+use crate::{
+    ops::{
+        error::XtaskError,
+        git, metadata,
+        runner::{self, CommandSpec},
+        tools,
+    },
+    tasks::clean::CACHE_DIR,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The container engines the task can build with, in order of preference.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum ContainerEngine {
+    /// The docker CLI.
+    #[default]
+    Docker,
+    /// The podman CLI, which takes the same arguments.
+    Podman,
+}
+
+impl ContainerEngine {
+    /// Returns the executable of the engine.
+    pub const fn binary(self) -> &'static str {
+        match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+        }
+    }
+
+    /// Returns docker if it is installed, else podman.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if neither is installed.
+    pub fn detect() -> AnyResult<Self> {
+        [Self::Docker, Self::Podman]
+            .into_iter()
+            .find(|engine| tools::is_installed(engine.binary()))
+            .ok_or_else(|| {
+                XtaskError::ToolMissing {
+                    tool: "docker".to_string(),
+                    install: "https://docs.docker.com/get-docker/"
+                        .to_string(),
+                }
+                .into()
+            })
+    }
+
+    /// Makes sure the daemon of the engine answers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the engine if its daemon cannot be reached.
+    pub fn ensure_daemon(self) -> AnyResult<()> {
+        let info = CommandSpec::new(self.binary()).args([
+            "info",
+            "--format",
+            "{{.ServerVersion}}",
+        ]);
+        runner::query(&info).map(|_| ()).map_err(|err| {
+            AnyError::msg(format!(
+                "The {self} daemon is not reachable, start it or check DOCKER_HOST, then retry: {err:#}"
+            ))
+        })
+    }
+}
+
+impl fmt::Display for ContainerEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.binary())
+    }
+}
+
+/// The options of the `docker` task.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct DockerOptions {
+    /// The name of the image, e.g. `ghcr.io/owner/app`. By default, this
+    /// is `None`, and the root package name is used.
+    #[builder(default)]
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The version the image is tagged with. By default, this is `None`,
+    /// and the root package version is used.
+    #[builder(default)]
+    #[serde(default)]
+    pub version: Option<String>,
+
+    /// The extra tags, either full `name:tag` references or tags of the
+    /// image name.
+    ///
+    /// By default, this is set to an empty list.
+    #[builder(default)]
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The `KEY=VALUE` build arguments.
+    ///
+    /// By default, this is set to an empty list.
+    #[builder(default)]
+    #[serde(default)]
+    pub build_args: Vec<String>,
+
+    /// The Dockerfile.
+    ///
+    /// By default, this is set to `Dockerfile`.
+    #[builder(default = "PathBuf::from(\"Dockerfile\")")]
+    #[serde(default = "default_file")]
+    pub file: PathBuf,
+
+    /// The build context.
+    ///
+    /// By default, this is set to `.`.
+    #[builder(default = "PathBuf::from(\".\")")]
+    #[serde(default = "default_context")]
+    pub context: PathBuf,
+
+    /// Pushes every tag of the image once built.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub push: bool,
+
+    /// The engine to build with. By default, this is `None`, and docker is
+    /// used, or podman when docker is not installed.
+    #[builder(default)]
+    #[serde(default)]
+    pub engine: Option<ContainerEngine>,
+
+    /// The file the engine writes the id of the image to.
+    ///
+    /// By default, this is set to `target/xtask/docker-image-id`.
+    #[builder(default = "default_iid_file()")]
+    #[serde(default = "default_iid_file")]
+    pub iid_file: PathBuf,
+}
+
+/// Returns the Dockerfile when not configured.
+fn default_file() -> PathBuf {
+    PathBuf::from("Dockerfile")
+}
+
+/// Returns the build context when not configured.
+fn default_context() -> PathBuf {
+    PathBuf::from(".")
+}
+
+/// Returns the image id file when not configured.
+fn default_iid_file() -> PathBuf {
+    Path::new(CACHE_DIR).join("docker-image-id")
+}
+
+impl Default for DockerOptions {
+    fn default() -> Self {
+        Self {
+            name: None,
+            version: None,
+            tags: Vec::new(),
+            build_args: Vec::new(),
+            file: default_file(),
+            context: default_context(),
+            push: false,
+            engine: None,
+            iid_file: default_iid_file(),
+        }
+    }
+}
+
+impl DockerOptions {
+    /// Returns the `build` command of `engine` for the image tagged with
+    /// `tags`.
+    pub fn command(
+        &self,
+        engine: ContainerEngine,
+        tags: &[String],
+    ) -> CommandSpec {
+        let mut command = CommandSpec::new(engine.binary()).args([
+            "build".to_string(),
+            "--file".to_string(),
+            self.file.to_string_lossy().to_string(),
+        ]);
+        for tag in tags {
+            command = command.args(["--tag", tag]);
+        }
+        for build_arg in &self.build_args {
+            command = command.args(["--build-arg", build_arg]);
+        }
+        command
+            .arg("--iidfile")
+            .arg(self.iid_file.to_string_lossy())
+            .arg(self.context.to_string_lossy())
+    }
+}
+
+/// Returns the tags of the image `name`: `name:version`, `name:latest`,
+/// `name:sha-<sha>`, then the `extra` tags, prefixed with `name:` unless
+/// they are full references.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::docker::image_tags;
+///
+/// assert_eq!(
+///     image_tags("app", "0.1.0", "4aee181", &["edge".to_string()]),
+///     ["app:0.1.0", "app:latest", "app:sha-4aee181", "app:edge"]
+/// );
+/// ```
+pub fn image_tags(
+    name: &str,
+    version: &str,
+    sha: &str,
+    extra: &[String],
+) -> Vec<String> {
+    let mut tags = vec![
+        format!("{name}:{version}"),
+        format!("{name}:latest"),
+        format!("{name}:sha-{sha}"),
+    ];
+    for tag in extra {
+        let full = tag
+            .rsplit('/')
+            .next()
+            .is_some_and(|last| last.contains(':'));
+        let tag = if full {
+            tag.clone()
+        } else {
+            format!("{name}:{tag}")
+        };
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Builds the image with `opts`, then pushes its tags if `opts.push` is
+/// set.
+///
+/// # Returns
+///
+/// The id of the image, e.g. `sha256:…`.
+///
+/// # Errors
+///
+/// Returns an error if no engine is installed or its daemon cannot be
+/// reached, if the name, version or commit cannot be read, or if the build
+/// or a push fails.
+pub fn build(opts: DockerOptions) -> AnyResult<String> {
+    let engine = match opts.engine {
+        Some(engine) => engine,
+        None => ContainerEngine::detect()?,
+    };
+    engine.ensure_daemon()?;
+    let name = match &opts.name {
+        Some(name) => name.clone(),
+        None => metadata::root_package()?,
+    };
+    let version = match &opts.version {
+        Some(version) => version.clone(),
+        None => metadata::root_version()?,
+    };
+    let sha = git::short_head(Path::new("."))?;
+    let tags = image_tags(&name, &version, &sha, &opts.tags);
+
+    if let Some(dir) = opts.iid_file.parent() {
+        if !runner::is_dry_run() && !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir).with_context(|| {
+                XtaskError::Io(format!(
+                    "Failed to create {}",
+                    dir.display()
+                ))
+            })?;
+        }
+    }
+    let _ = runner::run(&opts.command(engine, &tags))
+        .with_context(|| format!("Failed to build the image {name}"))?;
+    if opts.push {
+        for tag in &tags {
+            let _ = runner::run(
+                &CommandSpec::new(engine.binary()).args(["push", tag]),
+            )
+            .with_context(|| format!("Failed to push {tag}"))?;
+        }
+    }
+    if runner::is_dry_run() {
+        return Ok(String::new());
+    }
+    let id = fs::read_to_string(&opts.iid_file)
+        .with_context(|| {
+            XtaskError::Io(format!(
+                "Failed to read {}",
+                opts.iid_file.display()
+            ))
+        })?
+        .trim()
+        .to_string();
+    println!("Built {id}");
+    for tag in &tags {
+        println!("  {tag}");
+    }
+    Ok(id)
+}
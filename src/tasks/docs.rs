@@ -1,42 +1,385 @@
 // Copyright © 2023 xtasks. All rights reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use crate::{run_command, run_std_command, tasks::cmd};
-use anyhow::{Context, Result as AnyResult};
-use std::process::Command;
+use self::links::LinkCheckBuilder;
+use crate::ops::{
+    self, metadata,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// The `doc_coverage` module measures how much of the crate is documented.
+pub mod doc_coverage;
+/// The `links` module checks the links of the generated documentation.
+pub mod links;
+
+/// The `book` module builds, serves and tests an mdBook user guide.
+pub mod book;
+/// The `deploy` module publishes the generated documentation to a branch.
+pub mod deploy;
+
+/// The `readme` module generates the README from the crate documentation.
+pub mod readme;
+
+pub use book::{book, book_build, BookAction};
+pub use deploy::{deploy, Deploy, DeployBuilder};
+pub use doc_coverage::{doc_coverage, DocCoverage};
+pub use links::{check_links, BrokenLink, LinkCheck, LinkReport};
+pub use readme::sync_readme;
+
+/// Represents the configuration for building the documentation.
+#[derive(
+    Builder, Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct Docs {
+    /// Rebuilds the documentation whenever the sources change, with
+    /// `cargo watch`. By default, this is set to `true`; turn it off to build
+    /// once, e.g. on CI.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub watch: bool,
+
+    /// Leaves the dependencies undocumented (`--no-deps`). By default, this is
+    /// set to `true`.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub no_deps: bool,
+
+    /// Documents the crate with `--all-features`. By default, this is set to
+    /// `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub all_features: bool,
+
+    /// Fails on rustdoc warnings, by setting `RUSTDOCFLAGS=-D warnings` on the
+    /// child process. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub deny_warnings: bool,
+
+    /// Opens the generated documentation in the browser once it is built,
+    /// unless running in CI. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub open: bool,
+
+    /// Checks the links of the generated documentation after building it once,
+    /// and fails if any is broken. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub check_links: bool,
+
+    /// Also checks external links when [`Docs::check_links`] is set. By default,
+    /// this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub check_external: bool,
+
+    /// Publishes the generated documentation to a branch after building it
+    /// once, e.g. to `gh-pages`. By default, nothing is published.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub deploy: Option<Deploy>,
+
+    /// Documents private items too (`--document-private-items`), e.g. for
+    /// internal crates. By default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub private_items: bool,
+
+    /// Renders `doc(cfg(...))` annotations like docs.rs does, by building with
+    /// the nightly toolchain and `RUSTDOCFLAGS=--cfg docsrs`. By default, this is
+    /// set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub cfg_docsrs: bool,
+
+    /// Installs the nightly toolchain when [`Docs::cfg_docsrs`] needs it and it
+    /// is missing. Otherwise, a missing toolchain is an error naming the command
+    /// that installs it. By default, this is set to `true`; the
+    /// `XTASKS_NO_AUTO_INSTALL` environment variable turns it off.
+    ///
+    #[builder(default = "true")]
+    #[serde(default = "default_auto_install")]
+    pub auto_install: bool,
+}
+
+/// Returns whether the nightly toolchain is installed when not configured.
+const fn default_auto_install() -> bool {
+    true
+}
+
+/// Returns `true`, for options that are enabled unless configured otherwise.
+const fn default_true() -> bool {
+    true
+}
+
+impl Default for Docs {
+    /// Returns the configuration built from the builder defaults, so both
+    /// describe the same run.
+    fn default() -> Self {
+        DocsBuilder::default()
+            .build()
+            .expect("the builder defaults are a valid configuration")
+    }
+}
+
+/// Returns the path of the index page `cargo doc` writes for `package` under
+/// `target_dir`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use xtasks::tasks::docs::docs_index_path;
+///
+/// assert_eq!(
+///     docs_index_path(Path::new("target"), "my-crate"),
+///     Path::new("target/doc/my_crate/index.html")
+/// );
+/// ```
+pub fn docs_index_path(target_dir: &Path, package: &str) -> PathBuf {
+    target_dir
+        .join("doc")
+        .join(package.replace('-', "_"))
+        .join("index.html")
+}
+
+impl Docs {
+    /// Returns the `cargo doc` invocation for this configuration.
+    pub fn doc_command(&self) -> CommandSpec {
+        let toolchain = self.cfg_docsrs.then_some("nightly");
+        let mut command = tools::cargo_with(toolchain, "doc");
+        if self.no_deps {
+            command = command.arg("--no-deps");
+        }
+        if self.all_features {
+            command = command.arg("--all-features");
+        }
+        if self.private_items {
+            command = command.arg("--document-private-items");
+        }
+        command
+    }
+
+    /// Returns the `RUSTDOCFLAGS` set on the child process, if any.
+    pub fn rustdoc_flags(&self) -> Option<String> {
+        let mut flags = Vec::new();
+        if self.deny_warnings {
+            flags.push("-D warnings");
+        }
+        if self.cfg_docsrs {
+            flags.push("--cfg docsrs");
+        }
+        (!flags.is_empty()).then(|| flags.join(" "))
+    }
+
+    /// Returns the command run for this configuration: `cargo doc`, wrapped in
+    /// `cargo watch -s` when watching.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::docs::DocsBuilder;
+    ///
+    /// let docs = DocsBuilder::default().watch(false).build().unwrap();
+    /// assert_eq!(docs.command().to_string(), "cargo doc --no-deps");
+    /// ```
+    pub fn command(&self) -> CommandSpec {
+        let doc = self.doc_command();
+        let command = if self.watch {
+            CommandSpec::new("cargo")
+                .args(["watch", "-s"])
+                .arg(doc.to_string())
+        } else {
+            doc
+        };
+        match self.rustdoc_flags() {
+            Some(flags) => command.env("RUSTDOCFLAGS", flags),
+            None => command,
+        }
+    }
+
+    /// Makes sure the nightly toolchain is available when
+    /// [`Docs::cfg_docsrs`] is set.
+    fn ensure_toolchain(&self) -> AnyResult<()> {
+        if self.cfg_docsrs {
+            let _ = tools::ensure_toolchain(
+                "nightly",
+                &[],
+                self.auto_install,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the index page of the documentation of the root package, in the
+    /// target directory of the workspace.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the workspace cannot be inspected with
+    /// `cargo metadata`.
+    pub fn output_path(&self) -> AnyResult<PathBuf> {
+        Ok(docs_index_path(
+            &metadata::target_directory()?,
+            &metadata::root_package()?,
+        ))
+    }
+
+    /// Builds the documentation once, without watching, and returns the path of
+    /// its index page. The page is opened in the browser when [`Docs::open`] is
+    /// set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the build fails, or if it did not produce the index
+    /// page.
+    pub fn build_once(&self) -> AnyResult<PathBuf> {
+        let command = Self {
+            watch: false,
+            ..self.clone()
+        }
+        .command();
+        self.ensure_toolchain()?;
+        runner::run(&command).with_context(|| {
+            format!("Failed to execute '{command}'")
+        })?;
+        let path = self.output_path()?;
+        if !path.is_file() {
+            return Err(AnyError::msg(format!(
+                "'{command}' did not generate {}",
+                path.display()
+            )));
+        }
+        if self.open {
+            let _ = ops::open_in_browser(&path)?;
+        }
+        Ok(path)
+    }
+
+    /// Builds the documentation, installing `cargo-watch` first when watching.
+    ///
+    /// When [`Docs::open`], [`Docs::check_links`] or [`Docs::deploy`] is set, the
+    /// documentation is built, opened, checked and published once before
+    /// watching.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `cargo-watch` or the nightly toolchain cannot be
+    /// installed, if the build fails, if a link is broken, or if publishing fails.
+    pub fn run(&self) -> AnyResult<()> {
+        if self.open || self.check_links || self.deploy.is_some() {
+            let index = self.build_once()?;
+            // The index is `<target>/doc/<crate>/index.html`.
+            let doc_dir =
+                index.ancestors().nth(2).unwrap_or(Path::new("."));
+            if self.check_links {
+                let report = LinkCheckBuilder::default()
+                    .check_external(self.check_external)
+                    .build()
+                    .context("Failed to build LinkCheck configuration")?
+                    .run(doc_dir)?;
+                if !report.is_ok() {
+                    return Err(AnyError::msg(report.to_string()));
+                }
+                println!("{report}");
+            }
+            if let Some(deploy) = &self.deploy {
+                deploy.run(doc_dir)?;
+            }
+            if !self.watch {
+                return Ok(());
+            }
+        } else {
+            self.ensure_toolchain()?;
+        }
+        if self.watch {
+            ensure_cargo_watch_installed()?;
+        }
+        let command = self.command();
+        runner::run(&command).with_context(|| {
+            if self.watch {
+                "Failed to execute 'cargo watch' for generating documentation"
+                    .to_string()
+            } else {
+                format!("Failed to execute '{command}'")
+            }
+        })?;
+        Ok(())
+    }
+}
+
+impl DocsBuilder {
+    /// Builds the configuration and builds the documentation.
+    ///
+    /// This is a convenience for `self.build()?.run()`; see [`Docs::run`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration cannot be built or the run fails.
+    ///
+    pub fn run(&self) -> AnyResult<()> {
+        self.build()
+            .context("Failed to build Docs configuration")?
+            .run()
+    }
+}
 
 /// Generates and watches documentation for the current project.
 ///
 /// This function runs `cargo doc` in watch mode, automatically rebuilding
 /// the documentation whenever changes are detected in the source code.
-/// This is particularly useful during development.
+/// This is particularly useful during development. Use [`DocsBuilder`] to
+/// build once instead.
 ///
 /// # Errors
 ///
 /// Returns an `anyhow::Error` if the `cargo watch` or `cargo doc` commands fail to execute.
 /// The error will contain additional context about what went wrong to aid in debugging.
 pub fn docs() -> AnyResult<()> {
-    // Ensure that the necessary tools are installed
-    ensure_cargo_watch_installed()?;
-
-    // Execute the cargo watch command to build and watch the documentation
-    run_command!(
-        cmd!("cargo", "watch", "-s", "cargo doc --no-deps"),
-        "Failed to execute 'cargo watch' for generating documentation"
-    );
+    DocsBuilder::default().run()
+}
 
-    Ok(())
+/// Builds the documentation once and returns the path of its index page, e.g.
+/// `target/doc/my_crate/index.html`.
+///
+/// The target directory honors `CARGO_TARGET_DIR`.
+///
+/// # Errors
+///
+/// Returns an error if the build fails or did not produce the index page.
+pub fn docs_build() -> AnyResult<PathBuf> {
+    DocsBuilder::default()
+        .watch(false)
+        .build()
+        .context("Failed to build Docs configuration")?
+        .build_once()
 }
 
-/// Ensures that the `cargo-watch` tool is installed.
+/// Ensures that the `cargo-watch` tool is installed, installing it only if it
+/// cannot be found; see [`tools::ensure_tool_installed`].
 ///
 /// # Errors
 ///
 /// Returns an `anyhow::Error` if the `cargo install cargo-watch` command fails to execute.
 pub fn ensure_cargo_watch_installed() -> AnyResult<()> {
-    run_std_command!(
-        Command::new("cargo").args(["install", "cargo-watch"]),
-        "Failed to install 'cargo-watch'"
-    );
+    let _ =
+        tools::ensure_tool_installed("cargo-watch", &["cargo-watch"])?;
     Ok(())
 }
@@ -0,0 +1,153 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Building, serving and testing a user guide written with mdBook.
+//!
+//! The book lives in [`DEFAULT_BOOK_DIR`] unless the root package sets another
+//! directory with `book_dir` under `[package.metadata.xtask]`.
+
+use crate::ops::{
+    metadata,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The directory of the book unless configured otherwise.
+pub const DEFAULT_BOOK_DIR: &str = "docs/book";
+
+/// What to do with the book.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub enum BookAction {
+    /// Renders the book with `mdbook build`.
+    Build,
+    /// Serves the book on `localhost` and rebuilds it on changes, with
+    /// `mdbook serve`.
+    Serve {
+        /// The port the book is served on.
+        port: u16,
+    },
+    /// Tests the Rust code samples of the book with `mdbook test`.
+    Test,
+}
+
+/// Returns the directory of the book configured in a `[package.metadata.xtask]`
+/// table, or [`DEFAULT_BOOK_DIR`].
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use xtasks::tasks::docs::book::book_dir_from;
+///
+/// let xtask = serde_json::json!({ "book_dir": "guide" });
+/// assert_eq!(book_dir_from(&xtask), Path::new("guide"));
+/// assert_eq!(book_dir_from(&serde_json::Value::Null), Path::new("docs/book"));
+/// ```
+pub fn book_dir_from(xtask: &serde_json::Value) -> PathBuf {
+    PathBuf::from(
+        xtask
+            .get("book_dir")
+            .and_then(serde_json::Value::as_str)
+            .unwrap_or(DEFAULT_BOOK_DIR),
+    )
+}
+
+/// Returns the directory of the book of the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if the workspace cannot be inspected with `cargo metadata`.
+pub fn book_dir() -> AnyResult<PathBuf> {
+    Ok(book_dir_from(&metadata::xtask_metadata()?))
+}
+
+/// Returns the `mdbook` invocation running `action` on the book in `dir`.
+pub fn book_command(action: BookAction, dir: &Path) -> CommandSpec {
+    let command = CommandSpec::new("mdbook");
+    let dir = dir.to_string_lossy();
+    match action {
+        BookAction::Build => command.arg("build").arg(dir),
+        BookAction::Serve { port } => command
+            .arg("serve")
+            .arg(dir)
+            .args(["--port".to_string(), port.to_string()]),
+        BookAction::Test => command.arg("test").arg(dir),
+    }
+}
+
+/// Returns the directory the book in `dir` is rendered to: the `build-dir` of
+/// its `book.toml`, or `book` next to it.
+pub fn book_output_dir(dir: &Path) -> PathBuf {
+    let config =
+        fs::read_to_string(dir.join("book.toml")).unwrap_or_default();
+    let mut section = "";
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            section = line;
+        } else if section == "[build]" {
+            let build_dir = line
+                .split_once('=')
+                .filter(|(key, _)| key.trim() == "build-dir")
+                .map(|(_, value)| value.trim().trim_matches('"'));
+            if let Some(build_dir) = build_dir {
+                return dir.join(build_dir);
+            }
+        }
+    }
+    dir.join("book")
+}
+
+/// Runs `action` on the book in `dir`, installing mdbook first if needed.
+///
+/// # Returns
+///
+/// The directory the book is rendered to, e.g. to publish it with
+/// [`Deploy`](super::Deploy).
+///
+/// # Errors
+///
+/// Returns an error if `dir` holds no `book.toml`, if mdbook cannot be
+/// installed, or if it fails.
+pub fn book_in(action: BookAction, dir: &Path) -> AnyResult<PathBuf> {
+    if !dir.join("book.toml").is_file() {
+        return Err(AnyError::msg(format!(
+            "No book.toml in {dir}, create a book with 'mdbook init {dir}' or set \
+             `book_dir` under [package.metadata.xtask]",
+            dir = dir.display()
+        )));
+    }
+    let _ = tools::ensure_tool_installed("mdbook", &["mdbook"])?;
+    let command = book_command(action, dir);
+    runner::run(&command)
+        .with_context(|| format!("Failed to execute '{command}'"))?;
+    Ok(book_output_dir(dir))
+}
+
+/// Runs `action` on the book of the current workspace.
+///
+/// # Errors
+///
+/// Returns an error if the book cannot be found, or if mdbook cannot be
+/// installed or fails.
+pub fn book(action: BookAction) -> AnyResult<()> {
+    book_in(action, &book_dir()?).map(|_| ())
+}
+
+/// Builds the book of the current workspace and returns the directory it is
+/// rendered to.
+///
+/// # Errors
+///
+/// Returns an error if the book cannot be found, or if mdbook cannot be
+/// installed or fails.
+pub fn book_build() -> AnyResult<PathBuf> {
+    book_in(BookAction::Build, &book_dir()?)
+}
@@ -0,0 +1,281 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Publication of the generated documentation to a branch, e.g. `gh-pages`.
+//!
+//! The branch is prepared in a temporary worktree, so the checkout of the
+//! repository is left alone. The commit is built with `git commit-tree` on top of
+//! the remote branch, or without a parent when the branch does not exist yet, and
+//! pushed straight to the remote: no local branch is created.
+
+use crate::ops::{
+    self,
+    git::{self, git},
+    metadata, runner,
+};
+use anyhow::{Context, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Represents the configuration for publishing the documentation to a branch.
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct Deploy {
+    /// The branch the documentation is published to. By default, this is
+    /// `gh-pages`.
+    ///
+    #[builder(default = "\"gh-pages\".to_string()")]
+    #[serde(default)]
+    pub branch: String,
+
+    /// The remote the branch is pushed to. By default, this is `origin`.
+    ///
+    #[builder(default = "\"origin\".to_string()")]
+    #[serde(default)]
+    pub remote: String,
+
+    /// The repository the documentation is built from. By default, this is the
+    /// current directory.
+    ///
+    #[builder(default = "PathBuf::from(\".\")")]
+    #[serde(default)]
+    pub source: PathBuf,
+
+    /// The directory of the crate the root `index.html` redirects to, e.g.
+    /// `my_crate`. By default, this is the root package of the workspace.
+    ///
+    #[builder(default)]
+    #[serde(default)]
+    pub crate_dir: Option<String>,
+
+    /// Prints what would be committed instead of committing and pushing it. By
+    /// default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Returns the root `index.html` of the published documentation, redirecting to
+/// the documentation of `crate_dir`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::docs::deploy::redirect_page;
+///
+/// assert!(redirect_page("my_crate")
+///     .contains(r#"content="0; url=my_crate/index.html""#));
+/// ```
+pub fn redirect_page(crate_dir: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="0; url={crate_dir}/index.html">
+<link rel="canonical" href="{crate_dir}/index.html">
+</head>
+<body><a href="{crate_dir}/index.html">Moved to {crate_dir}</a></body>
+</html>
+"#
+    )
+}
+
+impl Deploy {
+    /// Publishes the contents of `dir`, e.g. `target/doc`, to the branch.
+    ///
+    /// The working tree must be clean, and publishing must be confirmed, see
+    /// [`confirm`](crate::ops::confirm). Nothing is committed when the branch
+    /// already holds the same files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the working tree is dirty, or if a git command fails.
+    pub fn run(&self, dir: &Path) -> AnyResult<()> {
+        git::ensure_git_clean(&self.source)?;
+        let sha =
+            runner::capture(&git(&self.source, ["rev-parse", "HEAD"]))
+                .context("Failed to read the current revision")?;
+        let sha = sha.trim();
+        let crate_dir = match &self.crate_dir {
+            Some(crate_dir) => crate_dir.clone(),
+            None => metadata::root_package()?.replace('-', "_"),
+        };
+
+        let worktree = std::env::temp_dir().join(format!(
+            "xtasks-{}-{}-{}",
+            self.branch.replace('/', "-"),
+            std::process::id(),
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_nanos())
+        ));
+        let parent = self.fetch_branch()?;
+        let base = parent.as_deref().unwrap_or("HEAD");
+        runner::run(
+            &git(
+                &self.source,
+                ["worktree", "add", "--detach", "--quiet"],
+            )
+            .arg(worktree.to_string_lossy())
+            .arg(base),
+        )
+        .context("Failed to create a worktree for the deployment")?;
+        let result = self.publish(
+            dir,
+            &worktree,
+            &crate_dir,
+            sha,
+            parent.as_deref(),
+        );
+        let _ = runner::run(
+            &git(&self.source, ["worktree", "remove", "--force"])
+                .arg(worktree.to_string_lossy()),
+        );
+        result
+    }
+
+    /// Fetches the branch from the remote and returns its revision, or `None`
+    /// if the remote has no such branch.
+    fn fetch_branch(&self) -> AnyResult<Option<String>> {
+        let heads = runner::capture(&git(
+            &self.source,
+            ["ls-remote", "--heads", &self.remote, &self.branch],
+        ))
+        .with_context(|| {
+            format!("Failed to list the branches of {}", self.remote)
+        })?;
+        if heads.trim().is_empty() {
+            return Ok(None);
+        }
+        runner::run(&git(
+            &self.source,
+            ["fetch", "--quiet", &self.remote, &self.branch],
+        ))
+        .with_context(|| format!("Failed to fetch {}", self.branch))?;
+        let revision = runner::capture(&git(
+            &self.source,
+            ["rev-parse", "FETCH_HEAD"],
+        ))?;
+        Ok(Some(revision.trim().to_string()))
+    }
+
+    /// Replaces the files of `worktree` with the documentation, then commits and
+    /// pushes them.
+    fn publish(
+        &self,
+        dir: &Path,
+        worktree: &Path,
+        crate_dir: &str,
+        sha: &str,
+        parent: Option<&str>,
+    ) -> AnyResult<()> {
+        runner::run(&git(
+            worktree,
+            ["rm", "-r", "-q", "-f", "--ignore-unmatch", "."],
+        ))?;
+        let _ = ops::copy_contents(dir, worktree, true).with_context(
+            || format!("Failed to copy {}", dir.display()),
+        )?;
+        ops::write_atomic(
+            worktree.join("index.html"),
+            redirect_page(crate_dir),
+        )?;
+        ops::write_atomic(worktree.join(".nojekyll"), "")?;
+        runner::run(&git(worktree, ["add", "-A"]))?;
+
+        // The worktree starts from the remote branch when there is one, so the
+        // staged changes are what the commit adds; a new branch adds every file.
+        let changes = if parent.is_some() {
+            runner::capture(&git(
+                worktree,
+                ["diff", "--cached", "--name-status"],
+            ))?
+        } else {
+            runner::capture(&git(worktree, ["ls-files"]))?
+                .lines()
+                .map(|path| format!("A\t{path}\n"))
+                .collect()
+        };
+        if changes.trim().is_empty() {
+            println!(
+                "{} is up to date, nothing to deploy",
+                self.branch
+            );
+            return Ok(());
+        }
+        let count = changes.lines().count();
+        if self.dry_run {
+            println!(
+                "Would commit {count} changed files to {} on {}:\n{}",
+                self.branch,
+                self.remote,
+                changes.trim_end()
+            );
+            return Ok(());
+        }
+        let question = format!(
+            "Publish {count} changed files to {} on {}?",
+            self.branch, self.remote
+        );
+        if !ops::confirm(&question)? {
+            println!("Deployment cancelled");
+            return Ok(());
+        }
+
+        let tree = runner::capture(&git(worktree, ["write-tree"]))?;
+        let mut commit = git(worktree, ["commit-tree", tree.trim()]);
+        if let Some(parent) = parent {
+            commit = commit.args(["-p", parent]);
+        }
+        let commit =
+            runner::capture(&commit.args([
+                "-m",
+                &format!("Deploy documentation from {sha}"),
+            ]))
+            .context("Failed to commit the documentation")?;
+        let refspec =
+            format!("{}:refs/heads/{}", commit.trim(), self.branch);
+        runner::run(&git(
+            worktree,
+            ["push", "--quiet", &self.remote, &refspec],
+        ))
+        .with_context(|| {
+            format!("Failed to push {} to {}", self.branch, self.remote)
+        })?;
+        println!(
+            "Deployed the documentation of {sha} to {} on {}",
+            self.branch, self.remote
+        );
+        Ok(())
+    }
+}
+
+/// Publishes the contents of `dir` to `branch` on `remote`, see [`Deploy::run`].
+///
+/// # Errors
+///
+/// Returns an error if the working tree is dirty, or if a git command fails.
+pub fn deploy(branch: &str, dir: &Path, remote: &str) -> AnyResult<()> {
+    DeployBuilder::default()
+        .branch(branch)
+        .remote(remote)
+        .build()
+        .context("Failed to build Deploy configuration")?
+        .run(dir)
+}
@@ -0,0 +1,159 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Documentation coverage, as reported by `rustdoc --show-coverage`.
+//!
+//! The report is only available on nightly, so the nightly toolchain must be
+//! installed; it is not installed on demand.
+
+use crate::ops::{
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+/// The documentation coverage of a crate.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocCoverage {
+    /// The share of documented items in the whole crate, as a percentage.
+    pub total_percent: f32,
+    /// The share of documented items in each file with items, as a percentage,
+    /// sorted by path.
+    pub files: Vec<(PathBuf, f32)>,
+}
+
+/// The counts reported by rustdoc for a file.
+#[derive(Deserialize)]
+struct FileCounts {
+    total: u64,
+    with_docs: u64,
+}
+
+impl DocCoverage {
+    /// Parses the JSON printed by `rustdoc --show-coverage --output-format json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the output is not a coverage report.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::docs::DocCoverage;
+    ///
+    /// let coverage = DocCoverage::parse(
+    ///     r#"{"src/lib.rs":{"total":4,"with_docs":3,"total_examples":4,"with_examples":1}}"#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(coverage.total_percent, 75.0);
+    /// ```
+    pub fn parse(json: &str) -> AnyResult<Self> {
+        let counts: BTreeMap<PathBuf, FileCounts> =
+            serde_json::from_str(json.trim()).context(
+                "Failed to parse the rustdoc coverage report",
+            )?;
+        let (documented, total) = counts.values().fold(
+            (0, 0),
+            |(documented, total), file| {
+                (documented + file.with_docs, total + file.total)
+            },
+        );
+        Ok(Self {
+            total_percent: percent(documented, total),
+            files: counts
+                .into_iter()
+                .filter(|(_, file)| file.total > 0)
+                .map(|(path, file)| {
+                    (path, percent(file.with_docs, file.total))
+                })
+                .collect(),
+        })
+    }
+
+    /// Fails if the total coverage is below `fail_under`, a percentage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming both percentages when the coverage is too low.
+    pub fn check(&self, fail_under: Option<f32>) -> AnyResult<()> {
+        match fail_under {
+            Some(minimum) if self.total_percent < minimum => {
+                Err(AnyError::msg(format!(
+                    "Documentation coverage {:.1}% is below the required {minimum}%",
+                    self.total_percent
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns `part` as a percentage of `total`, 100% when there is nothing to
+/// document.
+fn percent(part: u64, total: u64) -> f32 {
+    if total == 0 {
+        100.0
+    } else {
+        (part as f64 * 100.0 / total as f64) as f32
+    }
+}
+
+impl fmt::Display for DocCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .files
+            .iter()
+            .map(|(path, _)| path.to_string_lossy().len())
+            .max()
+            .unwrap_or(0)
+            .max("TOTAL".len());
+        for (path, percent) in &self.files {
+            writeln!(f, "{:<width$}  {percent:>6.1}%", path.display())?;
+        }
+        write!(f, "{:<width$}  {:>6.1}%", "TOTAL", self.total_percent)
+    }
+}
+
+/// Returns the `cargo rustdoc` invocation printing the coverage report of the
+/// library.
+pub fn doc_coverage_command() -> CommandSpec {
+    CommandSpec::new("cargo").args([
+        "+nightly",
+        "rustdoc",
+        "--lib",
+        "--",
+        "-Z",
+        "unstable-options",
+        "--show-coverage",
+        "--output-format",
+        "json",
+    ])
+}
+
+/// Measures the documentation coverage of the library of the current package.
+///
+/// # Errors
+///
+/// Returns an error with the command installing it if the nightly toolchain is
+/// missing, or an error if rustdoc fails or its report cannot be parsed.
+pub fn doc_coverage() -> AnyResult<DocCoverage> {
+    let _ = tools::ensure_toolchain("nightly", &[], false).context(
+        "The documentation coverage report requires nightly rustdoc",
+    )?;
+    let command = doc_coverage_command();
+    let output = runner::capture(&command)
+        .with_context(|| format!("Failed to execute '{command}'"))?;
+    // Cargo may print other lines; the report is the JSON object.
+    let report = output
+        .lines()
+        .rev()
+        .find(|line| line.trim_start().starts_with('{'))
+        .ok_or_else(|| {
+            AnyError::msg(format!(
+                "'{command}' printed no coverage report"
+            ))
+        })?;
+    DocCoverage::parse(report)
+}
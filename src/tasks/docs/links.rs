@@ -0,0 +1,399 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Dead link checking for the HTML generated by `cargo doc`.
+//!
+//! Local links are resolved against the file they appear in, and fragments are
+//! looked up among the `id` and `name` attributes of their target. External links
+//! are only checked when asked to, with `HEAD` requests sent by `curl`.
+
+use crate::ops::runner::{self, CommandSpec};
+use anyhow::{Context, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The pages rustdoc adds at the root of the documentation. Their links to the
+/// root `index.html` only resolve with `--enable-index-page`, and cannot be fixed
+/// by the documented crates anyway.
+const RUSTDOC_PAGES: [&str; 2] = ["help.html", "settings.html"];
+
+/// How many external URLs are passed to a single `curl` invocation.
+const URLS_PER_COMMAND: usize = 100;
+
+/// A link that does not lead anywhere.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct BrokenLink {
+    /// The HTML file containing the link.
+    pub file: PathBuf,
+    /// The `href` of the link, as written in the file.
+    pub href: String,
+    /// Why the link is broken, e.g. `no such file`.
+    pub reason: String,
+}
+
+/// The results of a link check.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct LinkReport {
+    /// The number of links checked.
+    pub checked: usize,
+    /// The broken links, in file order.
+    pub broken: Vec<BrokenLink>,
+}
+
+impl LinkReport {
+    /// Returns `true` if no link is broken.
+    pub fn is_ok(&self) -> bool {
+        self.broken.is_empty()
+    }
+}
+
+impl fmt::Display for LinkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "checked {} links, {} broken",
+            self.checked,
+            self.broken.len()
+        )?;
+        for link in &self.broken {
+            write!(
+                f,
+                "\n  {}: {} ({})",
+                link.file.display(),
+                link.href,
+                link.reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Represents the configuration for checking the links of the documentation.
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct LinkCheck {
+    /// Sends a `HEAD` request to every external `http` and `https` link. By
+    /// default, this is set to `false`.
+    ///
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub check_external: bool,
+
+    /// How many external links are requested at once. By default, this is 8.
+    ///
+    #[builder(default = "8")]
+    #[serde(default)]
+    pub concurrency: usize,
+
+    /// How long a single external request may take. By default, this is 10
+    /// seconds.
+    ///
+    #[builder(default = "Duration::from_secs(10)")]
+    #[serde(default)]
+    pub timeout: Duration,
+}
+
+impl LinkCheck {
+    /// Checks the links of every HTML file under `doc_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the files cannot be read, or if `curl` cannot be run
+    /// to check external links.
+    pub fn run(&self, doc_dir: &Path) -> AnyResult<LinkReport> {
+        let mut files = Vec::new();
+        collect_html_files(doc_dir, &mut files).with_context(|| {
+            format!("Failed to read {}", doc_dir.display())
+        })?;
+        files.sort();
+
+        let mut report = LinkReport::default();
+        let mut ids: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+        let mut external: BTreeMap<String, Vec<PathBuf>> =
+            BTreeMap::new();
+        for file in &files {
+            let rustdoc_page = file.parent() == Some(doc_dir)
+                && RUSTDOC_PAGES
+                    .iter()
+                    .any(|page| file.ends_with(page));
+            if rustdoc_page {
+                continue;
+            }
+            let html = fs::read_to_string(file).with_context(|| {
+                format!("Failed to read {}", file.display())
+            })?;
+            for href in extract_hrefs(&html) {
+                let reason = match classify(&href) {
+                    Link::Ignored => continue,
+                    Link::External => {
+                        if self.check_external {
+                            external
+                                .entry(href)
+                                .or_default()
+                                .push(file.clone());
+                        }
+                        continue;
+                    }
+                    Link::Local(path, fragment) => {
+                        check_local(file, path, fragment, &mut ids)
+                    }
+                };
+                report.checked += 1;
+                if let Some(reason) = reason {
+                    report.broken.push(BrokenLink {
+                        file: file.clone(),
+                        href,
+                        reason,
+                    });
+                }
+            }
+        }
+
+        let urls: Vec<&String> = external.keys().collect();
+        for chunk in urls.chunks(URLS_PER_COMMAND) {
+            let statuses = self.request(chunk)?;
+            for url in chunk {
+                report.checked += external[*url].len();
+                let reason = match statuses.get(url.as_str()) {
+                    Some(0) | None => "request failed".to_string(),
+                    Some(code) if *code >= 400 => {
+                        format!("HTTP {code}")
+                    }
+                    Some(_) => continue,
+                };
+                for file in &external[*url] {
+                    report.broken.push(BrokenLink {
+                        file: file.clone(),
+                        href: (*url).clone(),
+                        reason: reason.clone(),
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Returns the `curl` command sending a `HEAD` request to each of `urls`,
+    /// printing one `<status> <url>` line per request.
+    pub fn external_command(&self, urls: &[&String]) -> CommandSpec {
+        let mut command = CommandSpec::new("curl").args([
+            "--head".to_string(),
+            "--silent".to_string(),
+            "--parallel".to_string(),
+            "--parallel-max".to_string(),
+            self.concurrency.max(1).to_string(),
+            "--max-time".to_string(),
+            self.timeout.as_secs().max(1).to_string(),
+            "--write-out".to_string(),
+            "%{http_code} %{url_effective}\\n".to_string(),
+        ]);
+        for url in urls {
+            command = command.args(["--output", "/dev/null", url]);
+        }
+        command
+    }
+
+    /// Requests `urls` and returns their HTTP status, `0` when the request
+    /// failed.
+    fn request(
+        &self,
+        urls: &[&String],
+    ) -> AnyResult<HashMap<String, u16>> {
+        let command = self.external_command(urls).captured();
+        // curl exits with an error when any request fails, so the status lines
+        // are read whatever the exit status.
+        let output = runner::current()
+            .execute(&command)
+            .with_context(|| format!("Failed to spawn '{command}'"))?;
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let (code, url) = line.trim().split_once(' ')?;
+                Some((url.to_string(), code.parse().ok()?))
+            })
+            .collect())
+    }
+}
+
+/// Checks the links of every HTML file under `doc_dir`, leaving external links
+/// unchecked.
+///
+/// Use [`LinkCheckBuilder`] to check external links too.
+///
+/// # Errors
+///
+/// Returns an error if the files cannot be read.
+pub fn check_links(doc_dir: &Path) -> AnyResult<LinkReport> {
+    LinkCheckBuilder::default()
+        .build()
+        .context("Failed to build LinkCheck configuration")?
+        .run(doc_dir)
+}
+
+/// Returns the `href` attributes of `html`, in order, with `&amp;` decoded.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::docs::links::extract_hrefs;
+///
+/// let html = r#"<a href="a.html#x">a</a> <link href='b.css'>"#;
+/// assert_eq!(extract_hrefs(html), ["a.html#x", "b.css"]);
+/// ```
+pub fn extract_hrefs(html: &str) -> Vec<String> {
+    attribute_values(html, "href")
+}
+
+/// Returns the `id` and `name` attributes of `html`, the targets of fragments.
+pub fn extract_ids(html: &str) -> HashSet<String> {
+    attribute_values(html, "id")
+        .into_iter()
+        .chain(attribute_values(html, "name"))
+        .collect()
+}
+
+/// Returns the values of the attributes called `name` in `html`, quoted or not,
+/// as rustdoc leaves out the quotes where it can.
+fn attribute_values(html: &str, name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let pattern = format!("{name}=");
+    let mut offset = 0;
+    while let Some(found) = html[offset..].find(&pattern) {
+        let before = &html[..offset + found];
+        // Text such as `&lt;a href="..."` in code blocks is not in a tag.
+        let in_tag = before.rfind('<') > before.rfind('>')
+            && before.ends_with(char::is_whitespace);
+        let rest = &html[offset + found + pattern.len()..];
+        let (value, end) = match rest.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let Some(end) = rest[1..].find(quote) else {
+                    break;
+                };
+                (&rest[1..=end], end + 2)
+            }
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+        if in_tag {
+            values.push(value.replace("&amp;", "&"));
+        }
+        offset += found + pattern.len() + end;
+    }
+    values
+}
+
+/// The kinds of links found in the documentation.
+enum Link<'a> {
+    /// A link that is not checked, e.g. `mailto:` or `javascript:`.
+    Ignored,
+    /// An `http` or `https` link.
+    External,
+    /// A link to a file of the documentation, and an optional fragment.
+    Local(&'a str, Option<&'a str>),
+}
+
+/// Tells what kind of link `href` is.
+fn classify(href: &str) -> Link<'_> {
+    if href.starts_with("http://") || href.starts_with("https://") {
+        return Link::External;
+    }
+    let scheme = href
+        .find(':')
+        .is_some_and(|colon| !href[..colon].contains(['/', '#', '?']));
+    if scheme || href.is_empty() || href == "#" {
+        return Link::Ignored;
+    }
+    let (path, fragment) = match href.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (href, None),
+    };
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+    Link::Local(path, fragment.filter(|fragment| !fragment.is_empty()))
+}
+
+/// Checks a local link of `file`, returning why it is broken, if it is.
+fn check_local(
+    file: &Path,
+    path: &str,
+    fragment: Option<&str>,
+    ids: &mut HashMap<PathBuf, HashSet<String>>,
+) -> Option<String> {
+    let mut target = if path.is_empty() {
+        file.to_path_buf()
+    } else {
+        file.parent().unwrap_or(Path::new("")).join(path)
+    };
+    if target.is_dir() {
+        target.push("index.html");
+    }
+    if !target.is_file() {
+        return Some("no such file".to_string());
+    }
+    let fragment = fragment?;
+    let is_html = target
+        .extension()
+        .is_some_and(|extension| extension == "html");
+    if !is_html {
+        return None;
+    }
+    // Source pages highlight line ranges such as `#33-45` with JavaScript; only
+    // the first line has an element.
+    let fragment = match fragment.split_once('-') {
+        Some((first, last))
+            if [first, last].iter().all(|line| {
+                !line.is_empty()
+                    && line.chars().all(|c| c.is_ascii_digit())
+            }) =>
+        {
+            first
+        }
+        _ => fragment,
+    };
+    let targets = ids.entry(target.clone()).or_insert_with(|| {
+        fs::read_to_string(&target)
+            .map(|html| extract_ids(&html))
+            .unwrap_or_default()
+    });
+    (!targets.contains(fragment))
+        .then(|| format!("no element with id '{fragment}'"))
+}
+
+/// Appends the HTML files under `dir` to `files`, recursively.
+fn collect_html_files(
+    dir: &Path,
+    files: &mut Vec<PathBuf>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_html_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "html") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Generation of the README from the crate-level documentation.
+//!
+//! The `//!` block of the crate root is converted to plain Markdown and written
+//! between the [`README_START`] and [`README_END`] markers of the README, so the
+//! rest of the file (badges, license, ...) is left alone.
+
+use crate::ops;
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use std::{collections::HashMap, fs, path::Path};
+
+/// The marker after which the generated section of the README starts.
+pub const README_START: &str = "<!-- xtasks-readme-start -->";
+
+/// The marker before which the generated section of the README ends.
+pub const README_END: &str = "<!-- xtasks-readme-end -->";
+
+/// Returns the contents of the leading `//!` block of `source`, without the
+/// comment markers.
+pub fn crate_docs(source: &str) -> String {
+    let mut docs = Vec::new();
+    for line in source.lines() {
+        let line = line.trim_start();
+        if let Some(doc) = line.strip_prefix("//!") {
+            docs.push(doc.strip_prefix(' ').unwrap_or(doc));
+        } else if !docs.is_empty() {
+            break;
+        } else if !(line.is_empty() || line.starts_with("//")) {
+            // Code before any crate documentation: there is none.
+            break;
+        }
+    }
+    docs.join("\n")
+}
+
+/// Converts rustdoc Markdown to plain Markdown.
+///
+/// Intra-doc links such as ``[`Docs`]`` or `[the runner](crate::ops::runner)`
+/// are replaced by their text, and their reference definitions dropped. Rust
+/// code blocks lose their hidden `# ` lines and are tagged as `rust`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::docs::readme::to_markdown;
+///
+/// assert_eq!(
+///     to_markdown("See [`Docs`] and [docs.rs](https://docs.rs)."),
+///     "See `Docs` and [docs.rs](https://docs.rs)."
+/// );
+/// ```
+pub fn to_markdown(docs: &str) -> String {
+    let definitions: HashMap<&str, &str> = docs
+        .lines()
+        .filter_map(|line| {
+            let (label, target) = line.trim().split_once("]:")?;
+            Some((label.strip_prefix('[')?, target.trim()))
+        })
+        .collect();
+
+    let mut lines = Vec::new();
+    let mut fence: Option<bool> = None;
+    for line in docs.lines() {
+        let trimmed = line.trim_start();
+        if let Some(info) = trimmed.strip_prefix("```") {
+            if fence.take().is_none() {
+                let rust = is_rust_fence(info);
+                fence = Some(rust);
+                lines.push(if rust {
+                    "```rust".to_string()
+                } else {
+                    line.to_string()
+                });
+                continue;
+            }
+            lines.push(line.to_string());
+            continue;
+        }
+        match fence {
+            Some(true) => {
+                if trimmed != "#" && !trimmed.starts_with("# ") {
+                    lines.push(line.to_string());
+                }
+            }
+            Some(false) => lines.push(line.to_string()),
+            None => {
+                let is_definition = trimmed
+                    .strip_prefix('[')
+                    .and_then(|rest| rest.split_once("]:"))
+                    .is_some_and(|(_, target)| {
+                        is_rust_path(target.trim())
+                    });
+                if !is_definition {
+                    lines.push(rewrite_links(line, &definitions));
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// Returns `true` if a code block with this info string is Rust, as rustdoc
+/// treats untagged blocks and attributes such as `no_run` as Rust.
+fn is_rust_fence(info: &str) -> bool {
+    info.split(',').map(str::trim).all(|attribute| {
+        matches!(
+            attribute,
+            "" | "rust"
+                | "no_run"
+                | "ignore"
+                | "should_panic"
+                | "compile_fail"
+                | "edition2015"
+                | "edition2018"
+                | "edition2021"
+        )
+    })
+}
+
+/// Returns `true` if a link target is a Rust path resolved by rustdoc, e.g.
+/// `crate::ops::runner` or `Vec::push()`, rather than a URL or a file.
+fn is_rust_path(target: &str) -> bool {
+    !target.is_empty()
+        && target.chars().all(|c| {
+            c.is_alphanumeric()
+                || matches!(
+                    c,
+                    '_' | ':' | '@' | '!' | '(' | ')' | '<' | '>' | '`'
+                )
+        })
+}
+
+/// Replaces the intra-doc links of a line by their text.
+fn rewrite_links(
+    line: &str,
+    definitions: &HashMap<&str, &str>,
+) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    while let Some(open) = rest.find('[') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let Some(close) = after.find(']') else {
+            out.push_str(&rest[open..]);
+            return out;
+        };
+        let text = &after[..close];
+        let tail = &after[close + 1..];
+        // `[text](target)`
+        if let Some(inline) = tail.strip_prefix('(') {
+            if let Some(end) = inline.find(')') {
+                if is_rust_path(&inline[..end]) {
+                    out.push_str(text);
+                    rest = &inline[end + 1..];
+                    continue;
+                }
+            }
+        }
+        // `[text][label]`
+        if let Some(reference) = tail.strip_prefix('[') {
+            if let Some(end) = reference.find(']') {
+                let label = &reference[..end];
+                let target =
+                    definitions.get(label).copied().unwrap_or(label);
+                if is_rust_path(target) {
+                    out.push_str(text);
+                    rest = &reference[end + 1..];
+                    continue;
+                }
+            }
+        }
+        // `[text]`, resolved by a definition or by rustdoc itself.
+        if !tail.starts_with(['(', '[']) {
+            let target = definitions.get(text).copied().unwrap_or(text);
+            if is_rust_path(target) {
+                out.push_str(text);
+                rest = tail;
+                continue;
+            }
+        }
+        out.push('[');
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Writes the crate-level documentation of the crate in `manifest_dir` to the
+/// README, between the [`README_START`] and [`README_END`] markers.
+///
+/// The crate root is `src/lib.rs`, or `src/main.rs` for a binary crate.
+///
+/// # Parameters
+///
+/// * `manifest_dir` - The directory of the crate's `Cargo.toml`.
+/// * `readme` - The README to update.
+/// * `check_only` - Fails instead of writing when the README is out of date,
+///   e.g. on CI.
+///
+/// # Returns
+///
+/// `true` if the README was updated, `false` if it was already up to date.
+///
+/// # Errors
+///
+/// Returns an error if the files cannot be read or written, if the README has no
+/// markers, or, in check mode, if the README is out of date.
+pub fn sync_readme(
+    manifest_dir: &Path,
+    readme: &Path,
+    check_only: bool,
+) -> AnyResult<bool> {
+    let root = ["src/lib.rs", "src/main.rs"]
+        .iter()
+        .map(|path| manifest_dir.join(path))
+        .find(|path| path.is_file())
+        .ok_or_else(|| {
+            AnyError::msg(format!(
+                "No src/lib.rs or src/main.rs in {}",
+                manifest_dir.display()
+            ))
+        })?;
+    let source = fs::read_to_string(&root).with_context(|| {
+        format!("Failed to read {}", root.display())
+    })?;
+    let current = fs::read_to_string(readme).with_context(|| {
+        format!("Failed to read {}", readme.display())
+    })?;
+
+    let missing = |marker: &str| {
+        AnyError::msg(format!(
+            "{} has no {marker} marker to generate the documentation into",
+            readme.display()
+        ))
+    };
+    let start = current
+        .find(README_START)
+        .ok_or_else(|| missing(README_START))?
+        + README_START.len();
+    let end = current[start..]
+        .find(README_END)
+        .ok_or_else(|| missing(README_END))?
+        + start;
+    let docs = to_markdown(&crate_docs(&source));
+    let updated = format!(
+        "{}\n\n{}\n\n{}",
+        &current[..start],
+        docs.trim(),
+        &current[end..]
+    );
+    if updated == current {
+        return Ok(false);
+    }
+    if check_only {
+        return Err(AnyError::msg(format!(
+            "{} is out of date with {}, run `cargo xtask readme` to update it",
+            readme.display(),
+            root.display()
+        )));
+    }
+    ops::write_file_if_changed(readme, &updated)
+}
@@ -0,0 +1,375 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The checks of the `doctor` task, which verifies the development
+//! environment in one go instead of one failing task at a time.
+//!
+//! Every probe runs through the current [`runner`], so tests can check the
+//! engine against canned environments. A required check that fails makes the
+//! task fail; an optional one only prints how to fix it.
+
+use crate::{
+    ops::{
+        self,
+        error::XtaskError,
+        git, metadata,
+        runner::{self, CommandSpec},
+        tools,
+    },
+    tasks::vars::{self, UNKNOWN},
+};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path};
+
+/// The rustup components the tasks need, as `(component, installed prefix)`
+/// pairs: `llvm-tools-preview` is listed as `llvm-tools-<host>` once
+/// installed.
+pub const REQUIRED_COMPONENTS: [(&str, &str); 3] = [
+    ("llvm-tools-preview", "llvm-tools"),
+    ("rustfmt", "rustfmt"),
+    ("clippy", "clippy"),
+];
+
+/// The free space of the target directory below which the disk check fails:
+/// 1 GiB.
+pub const MIN_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+
+/// The result of a check of the environment.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct Check {
+    /// The name of the check, e.g. `rustc` or `cargo-hack`.
+    pub name: String,
+    /// Whether the tasks cannot run without it.
+    pub required: bool,
+    /// Whether the check passed.
+    pub passed: bool,
+    /// What was found, e.g. a version or `not installed`.
+    pub detail: String,
+    /// The command fixing a failed check, if there is one.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    /// Returns a check named `name`, `required` or not, which `passed` or
+    /// not with `detail`, and is fixed by `fix`.
+    fn new(
+        name: &str,
+        required: bool,
+        passed: bool,
+        detail: impl Into<String>,
+        fix: Option<String>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            required,
+            passed,
+            detail: detail.into(),
+            fix: fix.filter(|_| !passed),
+        }
+    }
+}
+
+/// The checks of the environment, in the order they ran.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct DoctorReport {
+    /// The checks, in order.
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Returns the required checks that failed.
+    pub fn failures(&self) -> Vec<&Check> {
+        self.checks
+            .iter()
+            .filter(|check| check.required && !check.passed)
+            .collect()
+    }
+
+    /// Returns `true` if every required check passed.
+    pub fn is_healthy(&self) -> bool {
+        self.failures().is_empty()
+    }
+
+    /// Returns the tools of [`vars::REPORTED_TOOLS`] that are not installed,
+    /// which `cargo install` can install.
+    pub fn missing_tools(&self) -> Vec<&str> {
+        self.checks
+            .iter()
+            .filter(|check| {
+                !check.passed
+                    && check.detail == "not installed"
+                    && vars::REPORTED_TOOLS
+                        .iter()
+                        .any(|(tool, _)| *tool == check.name)
+            })
+            .map(|check| check.name.as_str())
+            .collect()
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.len())
+            .max()
+            .unwrap_or(0);
+        for (index, check) in self.checks.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            let glyph = if check.passed { "✓" } else { "✗" };
+            let optional =
+                if check.required { "" } else { " (optional)" };
+            write!(
+                f,
+                "{glyph} {:<width$}  {}{optional}",
+                check.name, check.detail
+            )?;
+            if let Some(fix) = &check.fix {
+                write!(f, "\n    fix: {fix}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the space available in the file system from the output of
+/// `df -Pk`, in bytes.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::doctor::parse_df_available;
+///
+/// let output = "Filesystem 1024-blocks Used Available Capacity Mounted on\n\
+///               /dev/sda1 1000 400 600 40% /\n";
+/// assert_eq!(parse_df_available(output), Some(600 * 1024));
+/// ```
+pub fn parse_df_available(output: &str) -> Option<u64> {
+    output
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse::<u64>()
+        .ok()
+        .map(|kib| kib * 1024)
+}
+
+/// Returns the first line printed by `command`, or `None` if it fails.
+fn version_line(command: &CommandSpec) -> Option<String> {
+    runner::query(command).ok().map(|output| {
+        output
+            .lines()
+            .next()
+            .filter(|line| !line.trim().is_empty())
+            .unwrap_or(UNKNOWN)
+            .to_string()
+    })
+}
+
+/// Checks that `program` runs, and reports its version.
+fn check_program(program: &str, fix: &str) -> Check {
+    let version =
+        version_line(&CommandSpec::new(program).arg("--version"));
+    Check::new(
+        program,
+        true,
+        version.is_some(),
+        version.unwrap_or_else(|| "not found".to_string()),
+        Some(fix.to_string()),
+    )
+}
+
+/// Checks the [`REQUIRED_COMPONENTS`] of the active toolchain.
+fn check_components() -> Vec<Check> {
+    let installed = runner::query(&CommandSpec::new("rustup").args([
+        "component",
+        "list",
+        "--installed",
+    ]));
+    REQUIRED_COMPONENTS
+        .iter()
+        .map(|(component, prefix)| {
+            let (passed, detail) = match &installed {
+                Ok(list) => {
+                    let found = list.lines().any(|line| {
+                        let line = line.trim();
+                        line == *prefix
+                            || line.starts_with(&format!("{prefix}-"))
+                    });
+                    let detail = if found {
+                        "installed"
+                    } else {
+                        "not installed"
+                    };
+                    (found, detail)
+                }
+                Err(_) => (false, "rustup not found"),
+            };
+            Check::new(
+                component,
+                true,
+                passed,
+                detail,
+                Some(format!("rustup component add {component}")),
+            )
+        })
+        .collect()
+}
+
+/// Checks the optional tools of the tasks, see [`vars::tool_versions`].
+fn check_tools() -> Vec<Check> {
+    vars::tool_versions()
+        .into_iter()
+        .map(|tool| {
+            let detail = if tool.installed {
+                tool.version
+            } else {
+                "not installed".to_string()
+            };
+            let fix = tools::install_command(&[&tool.name]).to_string();
+            Check::new(
+                &tool.name,
+                false,
+                tool.installed,
+                detail,
+                Some(fix),
+            )
+        })
+        .collect()
+}
+
+/// Checks that the working tree of `root` has no uncommitted changes.
+fn check_git_clean(root: &Path) -> Check {
+    let (passed, detail) = match git::status(root) {
+        Ok(entries) if entries.is_empty() => {
+            (true, "clean".to_string())
+        }
+        Ok(entries) => {
+            (false, format!("{} uncommitted changes", entries.len()))
+        }
+        Err(_) => (false, "not a git repository".to_string()),
+    };
+    Check::new(
+        "git status",
+        false,
+        passed,
+        detail,
+        Some("git stash --include-untracked".to_string()),
+    )
+}
+
+/// Checks that the file system of the target directory of `root` has at
+/// least [`MIN_FREE_SPACE`] available.
+fn check_disk_space(root: &Path) -> Check {
+    let target = metadata::target_directory()
+        .unwrap_or_else(|_| root.join("target"));
+    let dir = if target.is_dir() {
+        target.as_path()
+    } else {
+        root
+    };
+    let available = runner::query(
+        &CommandSpec::new("df").arg("-Pk").arg(dir.to_string_lossy()),
+    )
+    .ok()
+    .and_then(|output| parse_df_available(&output));
+    let (passed, detail) = match available {
+        Some(bytes) => (
+            bytes >= MIN_FREE_SPACE,
+            format!(
+                "{:.1} GiB available",
+                bytes as f64 / 1024_f64.powi(3)
+            ),
+        ),
+        None => (false, UNKNOWN.to_string()),
+    };
+    Check::new(
+        "disk space",
+        false,
+        passed,
+        detail,
+        Some("cargo xtask clean --all".to_string()),
+    )
+}
+
+/// Runs every check of the environment of the workspace at `root`.
+pub fn diagnose(root: &Path) -> DoctorReport {
+    let mut checks = vec![
+        check_program(
+            "rustc",
+            "install Rust with rustup from https://rustup.rs",
+        ),
+        check_program(
+            "cargo",
+            "install Rust with rustup from https://rustup.rs",
+        ),
+    ];
+    checks.extend(check_components());
+    checks.extend(check_tools());
+    checks.push(check_program(
+        "git",
+        "install git from https://git-scm.com/downloads",
+    ));
+    checks.push(check_git_clean(root));
+    checks.push(check_disk_space(root));
+    DoctorReport { checks }
+}
+
+/// Checks the environment of the workspace at `root` and prints the result,
+/// offering to install the missing tools, see [`ops::confirm`].
+///
+/// # Returns
+///
+/// The report of the checks, after installing the tools if accepted.
+///
+/// # Errors
+///
+/// Returns an error if the prompt or an installation fails, or if a required
+/// check fails, which exits with the code of a missing tool.
+pub fn doctor(root: &Path) -> AnyResult<DoctorReport> {
+    let mut report = diagnose(root);
+    println!("{report}");
+    let missing: Vec<String> = report
+        .missing_tools()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    if !missing.is_empty()
+        && ops::confirm(&format!(
+            "Install the missing tools: {}?",
+            missing.join(", ")
+        ))?
+    {
+        for tool in &missing {
+            let install = tools::install_command(&[tool]);
+            println!("Installing {tool} with '{install}'");
+            let _ = runner::run(&install)?;
+        }
+        report = diagnose(root);
+        println!("{report}");
+    }
+    let failures = report.failures();
+    if let Some(first) = failures.first() {
+        let names: Vec<&str> =
+            failures.iter().map(|check| check.name.as_str()).collect();
+        return Err(AnyError::new(XtaskError::ToolMissing {
+            tool: first.name.clone(),
+            install: first.fix.clone().unwrap_or_default(),
+        })
+        .context(format!(
+            "{} required checks failed: {}",
+            failures.len(),
+            names.join(", ")
+        )));
+    }
+    Ok(report)
+}
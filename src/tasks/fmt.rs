@@ -0,0 +1,183 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `fmt` task, which formats the workspace with rustfmt or checks that it
+//! is formatted.
+//!
+//! The task runs with the same toolchain as the `fmt` step of the CI checks,
+//! see [`CI::fmt_toolchain`](crate::tasks::ci::CI::fmt_toolchain), so a tree
+//! formatted locally passes the check in CI. In fix mode, the files rustfmt
+//! changed are read from the git status before and after the run.
+
+use crate::ops::{
+    error::XtaskError,
+    git,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Selects what the `fmt` task formats and how.
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct FmtOptions {
+    /// Checks that the files are formatted instead of formatting them.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub check: bool,
+
+    /// The packages to format, all the members of the workspace if empty.
+    #[builder(default)]
+    #[serde(default)]
+    pub packages: Vec<String>,
+
+    /// Formats every package of the workspace, including the path
+    /// dependencies outside of it.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub all: bool,
+
+    /// The toolchain rustfmt runs with, e.g. `nightly`.
+    ///
+    /// By default, this is set to `None`, the active toolchain.
+    #[builder(default)]
+    #[serde(default)]
+    pub toolchain: Option<String>,
+
+    /// The manifest of the workspace to format.
+    ///
+    /// By default, this is set to `None`, the workspace of the current
+    /// directory.
+    #[builder(default)]
+    #[serde(default)]
+    pub manifest_path: Option<PathBuf>,
+
+    /// Lists the files rustfmt reads.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub verbose: bool,
+}
+
+impl FmtOptions {
+    /// Returns the `cargo fmt` invocation of these options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::fmt::FmtOptionsBuilder;
+    ///
+    /// let options = FmtOptionsBuilder::default()
+    ///     .check(true)
+    ///     .packages(vec!["app".to_string()])
+    ///     .toolchain(Some("nightly".to_string()))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     options.command().to_string(),
+    ///     "rustup run nightly cargo fmt --package app -- --check"
+    /// );
+    /// ```
+    pub fn command(&self) -> CommandSpec {
+        let mut command =
+            tools::cargo_with(self.toolchain.as_deref(), "fmt");
+        if let Some(manifest_path) = &self.manifest_path {
+            command = command
+                .arg("--manifest-path")
+                .arg(manifest_path.to_string_lossy());
+        }
+        if self.all {
+            command = command.arg("--all");
+        }
+        for package in &self.packages {
+            command = command.args(["--package", package]);
+        }
+        if self.verbose {
+            command = command.arg("--verbose");
+        }
+        if self.check {
+            command = command.args(["--", "--check"]);
+        }
+        command
+    }
+
+    /// Returns the directory of the workspace formatted by these options.
+    fn root(&self) -> PathBuf {
+        self.manifest_path
+            .as_deref()
+            .and_then(Path::parent)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+    }
+}
+
+/// Runs rustfmt as selected by `opts`, installing its toolchain if needed.
+///
+/// # Returns
+///
+/// The files rustfmt changed, relative to the root of the repository and
+/// sorted by path. The list is empty in check mode, and outside a git
+/// repository, where the changes cannot be told apart.
+///
+/// # Errors
+///
+/// Returns an error if the toolchain is missing and cannot be installed, or
+/// if rustfmt fails, which in check mode means that a file is not formatted.
+pub fn run_fmt(opts: &FmtOptions) -> AnyResult<Vec<PathBuf>> {
+    if let Some(toolchain) = &opts.toolchain {
+        let _ = tools::ensure_toolchain(toolchain, &["rustfmt"], true)?;
+    }
+    let root = opts.root();
+    let before = if opts.check {
+        None
+    } else {
+        git::Snapshot::take(&root).ok()
+    };
+    let _ = runner::run(&opts.command()).with_context(|| {
+        XtaskError::StepFailed {
+            step: "fmt".to_string(),
+        }
+    })?;
+    Ok(before
+        .and_then(|before| {
+            git::Snapshot::take(&root)
+                .ok()
+                .map(|after| after.changed_since(&before))
+        })
+        .unwrap_or_default())
+}
+
+/// Formats the workspace, or checks that it is formatted, and prints the
+/// files rustfmt changed.
+///
+/// # Errors
+///
+/// Returns an error if rustfmt fails or finds unformatted files, see
+/// [`run_fmt`].
+pub fn fmt(opts: FmtOptions) -> AnyResult<()> {
+    let changed = run_fmt(&opts)?;
+    if opts.check || runner::is_dry_run() {
+        return Ok(());
+    }
+    if changed.is_empty() {
+        println!("No files changed");
+    } else {
+        println!("Formatted {} files:", changed.len());
+        for path in &changed {
+            println!("  {}", path.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,314 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `fuzz` task, which runs the cargo-fuzz targets of the `fuzz`
+//! directory for a time budget.
+//!
+//! The targets run one after the other with the nightly toolchain, each for
+//! the budget given to libFuzzer as `-max_total_time`. The artifacts found
+//! under `fuzz/artifacts` after the runs that were not there before are the
+//! new crashes, and make the task fail.
+
+use crate::ops::{
+    error::XtaskError,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The directory of the cargo-fuzz project, relative to the workspace root.
+pub const FUZZ_DIR: &str = "fuzz";
+
+/// The prefixes of the files libFuzzer writes when a target fails.
+pub const ARTIFACT_PREFIXES: [&str; 4] =
+    ["crash-", "leak-", "timeout-", "oom-"];
+
+/// Parses a time budget: a number of seconds, optionally followed by `s`,
+/// or a number of minutes or hours followed by `m` or `h`.
+///
+/// # Errors
+///
+/// Returns an error if `value` is not a positive whole duration.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+/// use xtasks::tasks::fuzz::parse_duration;
+///
+/// assert_eq!(parse_duration("90").unwrap(), Duration::from_secs(90));
+/// assert_eq!(parse_duration("60s").unwrap(), Duration::from_secs(60));
+/// assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+/// assert!(parse_duration("0s").is_err());
+/// ```
+pub fn parse_duration(value: &str) -> AnyResult<Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().last() {
+        Some((index, 's')) => (&value[..index], 1),
+        Some((index, 'm')) => (&value[..index], 60),
+        Some((index, 'h')) => (&value[..index], 3600),
+        _ => (value, 1),
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|number| *number > 0)
+        .map(|number| Duration::from_secs(number * unit))
+        .ok_or_else(|| {
+            AnyError::msg(format!(
+                "Invalid duration '{value}', expected e.g. 60s, 5m or 1h"
+            ))
+        })
+}
+
+/// Returns the fuzz targets listed in the output of `cargo fuzz list`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::fuzz::parse_fuzz_list;
+///
+/// assert_eq!(
+///     parse_fuzz_list("fuzz_parse\nfuzz_render\n\n"),
+///     ["fuzz_parse", "fuzz_render"]
+/// );
+/// ```
+pub fn parse_fuzz_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Returns the `cargo fuzz <subcommand>` command run with the nightly
+/// toolchain for the fuzz project at `fuzz_dir`.
+fn cargo_fuzz(subcommand: &str, fuzz_dir: &Path) -> CommandSpec {
+    tools::cargo_with(Some("nightly"), "fuzz").args([
+        subcommand.to_string(),
+        "--fuzz-dir".to_string(),
+        fuzz_dir.to_string_lossy().to_string(),
+    ])
+}
+
+/// Returns the command running the fuzz target `target` of the project at
+/// `fuzz_dir` for `duration`.
+///
+/// # Examples
+///
+/// ```
+/// use std::{path::Path, time::Duration};
+/// use xtasks::tasks::fuzz::run_command;
+///
+/// assert_eq!(
+///     run_command(Path::new("fuzz"), "parse", Duration::from_secs(60))
+///         .to_string(),
+///     "rustup run nightly cargo fuzz run --fuzz-dir fuzz parse -- -max_total_time=60"
+/// );
+/// ```
+pub fn run_command(
+    fuzz_dir: &Path,
+    target: &str,
+    duration: Duration,
+) -> CommandSpec {
+    cargo_fuzz("run", fuzz_dir).args([
+        target.to_string(),
+        "--".to_string(),
+        format!("-max_total_time={}", duration.as_secs().max(1)),
+    ])
+}
+
+/// Returns the artifacts libFuzzer wrote under the `artifacts` directory of
+/// `fuzz_dir`, sorted.
+pub fn artifacts(fuzz_dir: &Path) -> BTreeSet<PathBuf> {
+    let mut found = BTreeSet::new();
+    let mut pending = vec![fuzz_dir.join("artifacts")];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.file_name().is_some_and(|name| {
+                let name = name.to_string_lossy();
+                ARTIFACT_PREFIXES
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+            }) {
+                let _ = found.insert(path);
+            }
+        }
+    }
+    found
+}
+
+/// The outcome of a fuzz target.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct FuzzTargetReport {
+    /// The name of the target.
+    pub name: String,
+    /// Whether the run exited successfully.
+    pub passed: bool,
+    /// The artifacts written by the run.
+    pub crashes: Vec<PathBuf>,
+}
+
+/// The outcome of the fuzz targets.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct FuzzReport {
+    /// The time budget of each target.
+    pub duration: Duration,
+    /// The targets, in the order they ran.
+    pub targets: Vec<FuzzTargetReport>,
+}
+
+impl FuzzReport {
+    /// Returns the artifacts written by every target.
+    pub fn crashes(&self) -> Vec<&PathBuf> {
+        self.targets
+            .iter()
+            .flat_map(|target| &target.crashes)
+            .collect()
+    }
+
+    /// Returns the targets whose run failed or wrote artifacts.
+    pub fn failed(&self) -> Vec<&FuzzTargetReport> {
+        self.targets
+            .iter()
+            .filter(|target| {
+                !target.passed || !target.crashes.is_empty()
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for FuzzReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        for target in &self.targets {
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            match (target.passed, target.crashes.len()) {
+                (true, 0) => write!(
+                    f,
+                    "{}: ok ({}s)",
+                    target.name,
+                    self.duration.as_secs()
+                )?,
+                (false, 0) => write!(f, "{}: failed", target.name)?,
+                (_, count) => {
+                    write!(f, "{}: {count} new crashes", target.name)?;
+                    for crash in &target.crashes {
+                        write!(f, "\n  {}", crash.display())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `target`, or every fuzz target of the workspace at `root` if none
+/// is given, for `duration` each.
+///
+/// # Errors
+///
+/// Returns an error if nightly or cargo-fuzz is missing and cannot be
+/// installed, if the targets cannot be listed, or if `cargo` cannot be
+/// spawned. Failing targets are reported, not returned as errors.
+pub fn run_in(
+    root: &Path,
+    target: Option<&str>,
+    duration: Duration,
+) -> AnyResult<FuzzReport> {
+    let fuzz_dir = root.join(FUZZ_DIR);
+    let _ = tools::ensure_toolchain("nightly", &[], true)?;
+    let _ = tools::ensure_tool("cargo-fuzz", &["cargo-fuzz"], true)?;
+    let targets = match target {
+        Some(target) => vec![target.to_string()],
+        None => {
+            let list = cargo_fuzz("list", &fuzz_dir);
+            let targets = parse_fuzz_list(
+                &runner::query(&list)
+                    .context("Failed to list the fuzz targets")?,
+            );
+            if targets.is_empty() {
+                return Err(AnyError::msg(format!(
+                    "No fuzz target in {}, add one with 'cargo fuzz add <name>'",
+                    fuzz_dir.display()
+                )));
+            }
+            targets
+        }
+    };
+
+    let mut report = FuzzReport {
+        duration,
+        targets: Vec::new(),
+    };
+    for name in targets {
+        println!("Fuzzing {name} for {}s", duration.as_secs());
+        let before = artifacts(&fuzz_dir);
+        let command = run_command(&fuzz_dir, &name, duration);
+        let output =
+            runner::current().execute(&command).map_err(|err| {
+                AnyError::new(err)
+                    .context(format!("Failed to spawn '{command}'"))
+            })?;
+        report.targets.push(FuzzTargetReport {
+            name,
+            passed: output.status.success(),
+            crashes: artifacts(&fuzz_dir)
+                .difference(&before)
+                .cloned()
+                .collect(),
+        });
+    }
+    Ok(report)
+}
+
+/// Runs the fuzz targets of the current workspace, see [`run_in`], and
+/// prints the report.
+///
+/// # Errors
+///
+/// Returns an error if the targets cannot run, or naming the targets that
+/// failed and the crashes they found.
+pub fn run(
+    target: Option<&str>,
+    duration: Duration,
+) -> AnyResult<FuzzReport> {
+    let report = run_in(Path::new("."), target, duration)?;
+    println!("{report}");
+    let failed = report.failed();
+    if failed.is_empty() {
+        return Ok(report);
+    }
+    let names: Vec<&str> =
+        failed.iter().map(|target| target.name.as_str()).collect();
+    Err(AnyError::new(XtaskError::StepFailed {
+        step: "fuzz".to_string(),
+    })
+    .context(format!(
+        "{} fuzz targets failed with {} new crashes: {}",
+        failed.len(),
+        report.crashes().len(),
+        names.join(", ")
+    )))
+}
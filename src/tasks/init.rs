@@ -0,0 +1,395 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Scaffolding of the xtask boilerplate in a consuming workspace.
+//!
+//! [`scaffold`] creates the `xtask` crate, whose `main` delegates to
+//! [`crate::tasks::main`], adds it to the members of the workspace, and
+//! defines the `cargo xtask` alias in `.cargo/config.toml`. The existing
+//! manifests are edited line by line, so their formatting and comments are
+//! kept.
+//!
+//! In a [dry run](crate::ops::runner::is_dry_run), the files that would be
+//! written are listed instead.
+
+use crate::ops::{
+    runner::{self, DryRunRunner},
+    write_file_if_changed,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path, sync::Arc};
+
+/// The directory of the xtask crate, relative to the workspace root.
+pub const XTASK_DIR: &str = "xtask";
+
+/// The command run by the `cargo xtask` alias.
+pub const XTASK_ALIAS: &str = "run --package xtask --";
+
+/// The `main.rs` of the xtask crate, which exits with the
+/// [code](crate::ops::error) of the failure, if any.
+pub const XTASK_MAIN: &str = "\
+fn main() -> std::process::ExitCode {
+    xtasks::ops::error::exit(xtasks::tasks::main())
+}
+";
+
+/// The options of [`scaffold`].
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct InitOptions {
+    /// Overwrites the existing xtask files and cargo alias.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub force: bool,
+
+    /// Lists the files that would be written instead of writing them.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Returns the manifest of the xtask crate, depending on `xtasks` at
+/// `version`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::init::xtask_manifest;
+///
+/// assert!(xtask_manifest("0.1.0").contains("xtasks = \"0.1.0\""));
+/// ```
+pub fn xtask_manifest(version: &str) -> String {
+    format!(
+        "[package]
+name = \"xtask\"
+version = \"0.0.0\"
+edition = \"2021\"
+publish = false
+
+[dependencies]
+xtasks = \"{version}\"
+"
+    )
+}
+
+/// Returns the name of the section opened by `line`, if it is a table header.
+fn section(line: &str) -> Option<&str> {
+    let header = line.trim().strip_prefix('[')?;
+    Some(header.split(']').next()?.trim_start_matches('[').trim())
+}
+
+/// Returns the range of the line `key = ...` of the `section` of `contents`,
+/// including its newline.
+fn key_line(
+    contents: &str,
+    section_name: &str,
+    key: &str,
+) -> Option<(usize, usize)> {
+    let mut current = String::new();
+    let mut start = 0;
+    for line in contents.split_inclusive('\n') {
+        if let Some(name) = section(line) {
+            current = name.to_string();
+        } else if current == section_name
+            && line
+                .split_once('=')
+                .is_some_and(|(name, _)| name.trim() == key)
+        {
+            return Some((start, start + line.len()));
+        }
+        start += line.len();
+    }
+    None
+}
+
+/// Returns the offset just after the header line of `section_name` in
+/// `contents`.
+fn after_header(contents: &str, section_name: &str) -> Option<usize> {
+    let mut start = 0;
+    for line in contents.split_inclusive('\n') {
+        start += line.len();
+        if section(line) == Some(section_name) {
+            return Some(start);
+        }
+    }
+    None
+}
+
+/// Returns `contents` with `value = "..."` set as the `key` of
+/// `section_name`, adding the section at the end if it is missing.
+pub(crate) fn with_key(
+    contents: &str,
+    section_name: &str,
+    key: &str,
+    value: &str,
+) -> String {
+    let line = format!("{key} = {value}\n");
+    if let Some((start, end)) = key_line(contents, section_name, key) {
+        return format!(
+            "{}{line}{}",
+            &contents[..start],
+            &contents[end..]
+        );
+    }
+    if let Some(offset) = after_header(contents, section_name) {
+        let newline = if contents[..offset].ends_with('\n') {
+            ""
+        } else {
+            "\n"
+        };
+        return format!(
+            "{}{newline}{line}{}",
+            &contents[..offset],
+            &contents[offset..]
+        );
+    }
+    let separator = match contents {
+        "" => "",
+        _ if contents.ends_with("\n\n") => "",
+        _ if contents.ends_with('\n') => "\n",
+        _ => "\n\n",
+    };
+    format!("{contents}{separator}[{section_name}]\n{line}")
+}
+
+/// Returns the manifest `contents` with `member` added to the members of its
+/// workspace, which is declared if needed, or `None` if it is already a
+/// member.
+///
+/// # Errors
+///
+/// Returns an error if the manifest is not valid TOML.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::init::with_workspace_member;
+///
+/// let manifest = "[workspace]\nmembers = [\"app\"]\n";
+/// assert_eq!(
+///     with_workspace_member(manifest, "xtask").unwrap().unwrap(),
+///     "[workspace]\nmembers = [\"app\", \"xtask\"]\n"
+/// );
+/// ```
+pub fn with_workspace_member(
+    contents: &str,
+    member: &str,
+) -> AnyResult<Option<String>> {
+    let manifest: toml::Table =
+        toml::from_str(contents).context("Invalid manifest")?;
+    let members = manifest
+        .get("workspace")
+        .and_then(|workspace| workspace.get("members"));
+    let Some(members) = members else {
+        return Ok(Some(with_key(
+            contents,
+            "workspace",
+            "members",
+            &format!("[\"{member}\"]"),
+        )));
+    };
+    if members.as_array().is_some_and(|members| {
+        members.iter().any(|name| name.as_str() == Some(member))
+    }) {
+        return Ok(None);
+    }
+    let (start, _) = key_line(contents, "workspace", "members")
+        .ok_or_else(|| {
+            AnyError::msg(
+                "The workspace members are not declared as members = [...]",
+            )
+        })?;
+    let open = start
+        + contents[start..].find('[').ok_or_else(|| {
+            AnyError::msg("The workspace members are not an array")
+        })?;
+    let close = open
+        + contents[open..].find(']').ok_or_else(|| {
+            AnyError::msg("The workspace members are not an array")
+        })?;
+    let items = &contents[open + 1..close];
+    let entry = format!("\"{member}\"");
+    let edited = if items.trim().is_empty() {
+        entry
+    } else if items.contains('\n') {
+        with_array_line(items, &entry)
+    } else {
+        let kept = items.trim_end();
+        let separator = if kept.ends_with(',') { " " } else { ", " };
+        format!("{kept}{separator}{entry}{}", &items[kept.len()..])
+    };
+    Ok(Some(format!(
+        "{}{edited}{}",
+        &contents[..=open],
+        &contents[close..]
+    )))
+}
+
+/// Returns the `items` of a multi-line array with `entry` on a new line after
+/// the last one, indented like it, and with a trailing comma if it has one.
+fn with_array_line(items: &str, entry: &str) -> String {
+    let mut lines: Vec<String> =
+        items.split_inclusive('\n').map(str::to_string).collect();
+    let code = |line: &str| -> usize {
+        line.split('#').next().unwrap_or_default().trim_end().len()
+    };
+    let Some(last) = lines.iter().rposition(|line| code(line) > 0)
+    else {
+        return format!("{entry}{items}");
+    };
+    let line = lines[last].clone();
+    let end = code(&line);
+    let trailing = if line[..end].ends_with(',') {
+        ","
+    } else {
+        lines[last] = format!("{},{}", &line[..end], &line[end..]);
+        ""
+    };
+    let indent: String =
+        line.chars().take_while(|c| c.is_whitespace()).collect();
+    if line.ends_with('\n') {
+        lines.insert(last + 1, format!("{indent}{entry}{trailing}\n"));
+    } else {
+        lines[last].push('\n');
+        lines.insert(last + 1, format!("{indent}{entry}{trailing}"));
+    }
+    lines.concat()
+}
+
+/// Returns the cargo configuration `contents` with the `xtask` alias set to
+/// [`XTASK_ALIAS`], or `None` if it is already set to it.
+///
+/// # Errors
+///
+/// Returns an error if the configuration is not valid TOML, or if it defines
+/// another `xtask` alias and `force` is not set.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::init::with_xtask_alias;
+///
+/// assert_eq!(
+///     with_xtask_alias("", false).unwrap().unwrap(),
+///     "[alias]\nxtask = \"run --package xtask --\"\n"
+/// );
+/// ```
+pub fn with_xtask_alias(
+    contents: &str,
+    force: bool,
+) -> AnyResult<Option<String>> {
+    let config: toml::Table = toml::from_str(contents)
+        .context("Invalid cargo configuration")?;
+    match config.get("alias").and_then(|alias| alias.get("xtask")) {
+        Some(alias) if alias.as_str() == Some(XTASK_ALIAS) => Ok(None),
+        Some(alias) if !force => Err(AnyError::msg(format!(
+            "The xtask alias is already defined as {alias}, use --force to replace it"
+        ))),
+        _ => Ok(Some(with_key(
+            contents,
+            "alias",
+            "xtask",
+            &format!("\"{XTASK_ALIAS}\""),
+        ))),
+    }
+}
+
+/// Reads `path`, or returns an empty string if it does not exist.
+pub(crate) fn read_or_empty(path: &Path) -> AnyResult<String> {
+    if !path.exists() {
+        return Ok(String::new());
+    }
+    fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Scaffolds the xtask crate in the workspace at `root`: `xtask/Cargo.toml`,
+/// `xtask/src/main.rs`, the workspace member and the `cargo xtask` alias.
+///
+/// Files that already hold the expected contents are left untouched.
+///
+/// # Errors
+///
+/// Returns an error if `root` has no `Cargo.toml`, if an xtask file or alias
+/// already exists with other contents and `force` is not set, or if a file
+/// cannot be read or written.
+pub fn scaffold(root: &Path, opts: InitOptions) -> AnyResult<()> {
+    if opts.dry_run && !runner::is_dry_run() {
+        return runner::with_runner(Arc::new(DryRunRunner), || {
+            scaffold(root, opts.clone())
+        });
+    }
+    let manifest_path = root.join("Cargo.toml");
+    if !manifest_path.is_file() {
+        return Err(AnyError::msg(format!(
+            "No Cargo.toml found in {}",
+            root.display()
+        )));
+    }
+    let xtask = root.join(XTASK_DIR);
+    let mut files = vec![
+        (
+            xtask.join("Cargo.toml"),
+            xtask_manifest(env!("CARGO_PKG_VERSION")),
+        ),
+        (xtask.join("src").join("main.rs"), XTASK_MAIN.to_string()),
+    ];
+    let conflicts: Vec<String> = files
+        .iter()
+        .filter(|(path, contents)| {
+            path.exists()
+                && fs::read_to_string(path)
+                    .map_or(true, |current| current != *contents)
+        })
+        .map(|(path, _)| path.display().to_string())
+        .collect();
+    if !conflicts.is_empty() && !opts.force {
+        return Err(AnyError::msg(format!(
+            "Refusing to overwrite {}, use --force to replace them",
+            conflicts.join(", ")
+        )));
+    }
+
+    let manifest = read_or_empty(&manifest_path)?;
+    if let Some(edited) = with_workspace_member(&manifest, XTASK_DIR)
+        .with_context(|| {
+            format!("Failed to edit {}", manifest_path.display())
+        })?
+    {
+        files.push((manifest_path, edited));
+    }
+    let config_path = root.join(".cargo").join("config.toml");
+    let config = read_or_empty(&config_path)?;
+    if let Some(edited) = with_xtask_alias(&config, opts.force)
+        .with_context(|| {
+            format!("Failed to edit {}", config_path.display())
+        })?
+    {
+        files.push((config_path, edited));
+    }
+
+    for (path, contents) in files {
+        if write_file_if_changed(&path, &contents)?
+            && !runner::is_dry_run()
+        {
+            println!("Wrote {}", path.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,412 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `licenses` task, which lists the licenses of the dependencies for
+//! compliance reviews.
+//!
+//! The packages come from `cargo metadata` and are grouped by the SPDX
+//! expression of their `license` field. The crates without license metadata
+//! are listed first rather than skipped, and the crates whose expression
+//! mentions a denied license, e.g. `GPL-3.0`, make the task fail.
+
+use crate::ops::{
+    error::XtaskError,
+    metadata::{self, PackageLicense},
+};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+/// The format the license report is rendered in.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum LicenseFormat {
+    /// A Markdown document with a table of the licenses.
+    #[default]
+    Markdown,
+    /// A JSON document, for tooling.
+    Json,
+    /// One CSV row per crate, for spreadsheets.
+    Csv,
+}
+
+impl LicenseFormat {
+    /// Every format, in the order they are listed in error messages.
+    pub const ALL: [Self; 3] = [Self::Markdown, Self::Json, Self::Csv];
+}
+
+impl fmt::Display for LicenseFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Markdown => "md",
+            Self::Json => "json",
+            Self::Csv => "csv",
+        })
+    }
+}
+
+impl FromStr for LicenseFormat {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        if value.eq_ignore_ascii_case("markdown") {
+            return Ok(Self::Markdown);
+        }
+        Self::ALL
+            .into_iter()
+            .find(|format| {
+                format.to_string().eq_ignore_ascii_case(value)
+            })
+            .ok_or_else(|| {
+                let valid: Vec<String> =
+                    Self::ALL.iter().map(ToString::to_string).collect();
+                AnyError::msg(format!(
+                    "Unknown license report format '{value}', expected one of: {}",
+                    valid.join(", ")
+                ))
+            })
+    }
+}
+
+/// A dependency and its license.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct LicensedCrate {
+    /// The name of the crate.
+    pub name: String,
+    /// The version of the crate.
+    pub version: String,
+    /// The SPDX expression of the license, if the crate declares one.
+    pub license: Option<String>,
+    /// The license file of the crate, if it declares one.
+    pub license_file: Option<String>,
+    /// Whether the license expression mentions a denied license.
+    pub denied: bool,
+}
+
+impl LicensedCrate {
+    /// Returns the license of the crate as listed in the report: its SPDX
+    /// expression, else its license file, else `None`.
+    pub fn label(&self) -> Option<String> {
+        self.license.clone().or_else(|| {
+            self.license_file.as_ref().map(|file| format!("see {file}"))
+        })
+    }
+}
+
+/// The licenses of the dependencies of the workspace.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct LicenseReport {
+    /// The dependencies, sorted by name and version.
+    pub crates: Vec<LicensedCrate>,
+}
+
+/// Returns `true` if the SPDX `expression` mentions the license `denied`,
+/// including its `-only`, `-or-later` and `+` variants.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::licenses::mentions_license;
+///
+/// assert!(mentions_license("MIT OR GPL-3.0-only", "GPL-3.0"));
+/// assert!(mentions_license("(GPL-3.0+ AND MIT)", "gpl-3.0"));
+/// assert!(!mentions_license("LGPL-3.0", "GPL-3.0"));
+/// ```
+pub fn mentions_license(expression: &str, denied: &str) -> bool {
+    expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|id| !id.is_empty())
+        .any(|id| {
+            let id = id
+                .strip_suffix('+')
+                .or_else(|| id.strip_suffix("-or-later"))
+                .or_else(|| id.strip_suffix("-only"))
+                .unwrap_or(id);
+            id.eq_ignore_ascii_case(denied)
+        })
+}
+
+/// Returns the licenses denied in a `[package.metadata.xtask]` table, from
+/// its `license_deny` array.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::licenses::license_deny_from;
+///
+/// let xtask = serde_json::json!({ "license_deny": ["GPL-3.0"] });
+/// assert_eq!(license_deny_from(&xtask), ["GPL-3.0"]);
+/// assert!(license_deny_from(&serde_json::Value::Null).is_empty());
+/// ```
+pub fn license_deny_from(xtask: &serde_json::Value) -> Vec<String> {
+    xtask
+        .get("license_deny")
+        .and_then(serde_json::Value::as_array)
+        .map(|licenses| {
+            licenses
+                .iter()
+                .filter_map(serde_json::Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Quotes a CSV `field` if it holds a comma, a quote or a newline.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl LicenseReport {
+    /// Returns the report of `packages`, flagging the licenses of `deny`.
+    pub fn new(packages: Vec<PackageLicense>, deny: &[String]) -> Self {
+        let crates = packages
+            .into_iter()
+            .map(|package| LicensedCrate {
+                denied: package.license.as_deref().is_some_and(
+                    |license| {
+                        deny.iter().any(|denied| {
+                            mentions_license(license, denied)
+                        })
+                    },
+                ),
+                name: package.name,
+                version: package.version,
+                license: package.license,
+                license_file: package
+                    .license_file
+                    .map(|file| file.display().to_string()),
+            })
+            .collect();
+        Self { crates }
+    }
+
+    /// Returns the crates with neither a license nor a license file.
+    pub fn missing(&self) -> Vec<&LicensedCrate> {
+        self.crates
+            .iter()
+            .filter(|krate| krate.label().is_none())
+            .collect()
+    }
+
+    /// Returns the crates whose license mentions a denied license.
+    pub fn denied(&self) -> Vec<&LicensedCrate> {
+        self.crates.iter().filter(|krate| krate.denied).collect()
+    }
+
+    /// Returns the crates with license metadata, grouped by license.
+    pub fn by_license(&self) -> BTreeMap<String, Vec<&LicensedCrate>> {
+        let mut groups: BTreeMap<String, Vec<&LicensedCrate>> =
+            BTreeMap::new();
+        for krate in &self.crates {
+            if let Some(label) = krate.label() {
+                groups.entry(label).or_default().push(krate);
+            }
+        }
+        groups
+    }
+
+    /// Renders the report as Markdown: the crates without license metadata
+    /// and those with a denied license first, then a table of the licenses.
+    pub fn to_markdown(&self) -> String {
+        let list = |crates: &[&LicensedCrate], license: bool| {
+            crates
+                .iter()
+                .map(|krate| match krate.label().filter(|_| license) {
+                    Some(label) => format!(
+                        "- {} {}: {label}\n",
+                        krate.name, krate.version
+                    ),
+                    None => {
+                        format!("- {} {}\n", krate.name, krate.version)
+                    }
+                })
+                .collect::<String>()
+        };
+        let mut markdown = String::from("# Dependency licenses\n");
+        let missing = self.missing();
+        if !missing.is_empty() {
+            markdown.push_str(&format!(
+                "\n## Missing license metadata ({})\n\n{}",
+                missing.len(),
+                list(&missing, false)
+            ));
+        }
+        let denied = self.denied();
+        if !denied.is_empty() {
+            markdown.push_str(&format!(
+                "\n## Denied licenses ({})\n\n{}",
+                denied.len(),
+                list(&denied, true)
+            ));
+        }
+        markdown.push_str(
+            "\n## Licenses\n\n| License | Crates | Names |\n| --- | --- | --- |\n",
+        );
+        for (license, crates) in self.by_license() {
+            let names: Vec<String> = crates
+                .iter()
+                .map(|krate| {
+                    format!("{} {}", krate.name, krate.version)
+                })
+                .collect();
+            markdown.push_str(&format!(
+                "| {} | {} | {} |\n",
+                license.replace('|', "\\|"),
+                crates.len(),
+                names.join(", ")
+            ));
+        }
+        markdown
+    }
+
+    /// Renders the report as pretty-printed JSON, with the crates grouped by
+    /// license and the missing and denied crates listed apart.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report cannot be serialized.
+    pub fn to_json(&self) -> AnyResult<String> {
+        let licenses: BTreeMap<String, Vec<String>> = self
+            .by_license()
+            .into_iter()
+            .map(|(license, crates)| {
+                let names = crates
+                    .iter()
+                    .map(|krate| {
+                        format!("{} {}", krate.name, krate.version)
+                    })
+                    .collect();
+                (license, names)
+            })
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({
+            "licenses": licenses,
+            "missing": self.missing(),
+            "denied": self.denied(),
+            "crates": self.crates,
+        }))
+        .map_err(AnyError::new)
+    }
+
+    /// Renders the report as CSV, one row per crate.
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("name,version,license,license_file,denied\n");
+        for krate in &self.crates {
+            let row = [
+                csv_field(&krate.name),
+                csv_field(&krate.version),
+                csv_field(krate.license.as_deref().unwrap_or_default()),
+                csv_field(
+                    krate.license_file.as_deref().unwrap_or_default(),
+                ),
+                krate.denied.to_string(),
+            ];
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Renders the report in `format`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the JSON report cannot be serialized.
+    pub fn render(&self, format: LicenseFormat) -> AnyResult<String> {
+        match format {
+            LicenseFormat::Markdown => Ok(self.to_markdown()),
+            LicenseFormat::Json => self.to_json(),
+            LicenseFormat::Csv => Ok(self.to_csv()),
+        }
+    }
+}
+
+/// Returns the license report of the dependencies of the current workspace,
+/// flagging the licenses of `deny`.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be
+/// parsed.
+pub fn report_with(deny: &[String]) -> AnyResult<LicenseReport> {
+    Ok(LicenseReport::new(metadata::package_licenses()?, deny))
+}
+
+/// Returns the license report of the dependencies of the current workspace,
+/// flagging the `license_deny` licenses of its `[package.metadata.xtask]`
+/// table.
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails or its output cannot be
+/// parsed.
+pub fn report() -> AnyResult<LicenseReport> {
+    report_with(&configured_deny())
+}
+
+/// Returns the `license_deny` licenses of the current workspace, none if
+/// its metadata cannot be read.
+fn configured_deny() -> Vec<String> {
+    metadata::xtask_metadata()
+        .map(|xtask| license_deny_from(&xtask))
+        .unwrap_or_default()
+}
+
+/// Prints the license report of the dependencies in `format`, denying the
+/// configured licenses and those of `deny`.
+///
+/// # Errors
+///
+/// Returns an error if the report fails, or naming the crates whose license
+/// is denied.
+pub fn licenses(
+    format: LicenseFormat,
+    deny: &[String],
+) -> AnyResult<LicenseReport> {
+    let mut denied = configured_deny();
+    denied.extend(deny.iter().cloned());
+    let report = report_with(&denied)?;
+    println!("{}", report.render(format)?.trim_end());
+    let flagged = report.denied();
+    if !flagged.is_empty() {
+        let names: Vec<String> = flagged
+            .iter()
+            .map(|krate| {
+                format!(
+                    "{} {} ({})",
+                    krate.name,
+                    krate.version,
+                    krate.license.as_deref().unwrap_or_default()
+                )
+            })
+            .collect();
+        return Err(AnyError::new(XtaskError::StepFailed {
+            step: "licenses".to_string(),
+        })
+        .context(format!(
+            "{} crates have a denied license: {}",
+            flagged.len(),
+            names.join(", ")
+        )));
+    }
+    Ok(report)
+}
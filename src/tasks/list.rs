@@ -0,0 +1,147 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The listing of the available tasks, with the external tools they need.
+//!
+//! The tasks are the subcommands of a `clap` [`Command`], so a workspace that
+//! adds its own subcommands to [`build_cli`](super::build_cli) gets them
+//! listed too. Its tasks have no known tools.
+
+use crate::{ops::tools, tasks::coverage::CoverageEngine};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use clap::Command;
+use serde::{Deserialize, Serialize};
+
+/// An external tool needed by a task, and whether it is installed.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct ToolStatus {
+    /// The name of the binary, e.g. `cargo-hack`.
+    pub name: String,
+    /// Whether the binary is found in `PATH`.
+    pub installed: bool,
+}
+
+/// A task and the external tools it needs.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct TaskInfo {
+    /// The name of the task, as typed on the command line.
+    pub name: String,
+    /// The other names of the task, e.g. `cov` for `coverage`.
+    pub aliases: Vec<String>,
+    /// The one-line description of the task.
+    pub description: String,
+    /// The external tools the task needs.
+    pub tools: Vec<ToolStatus>,
+}
+
+/// Returns the external tools needed by the built-in task `name`.
+///
+/// The coverage task needs the engine it would pick, see
+/// [`CoverageEngine::detect`]. Unknown tasks need none.
+pub fn required_tools(name: &str) -> Vec<&'static str> {
+    match name {
+        "coverage" => vec![CoverageEngine::detect().binary()],
+        "dev" | "docs" => vec!["cargo-watch"],
+        "powerset" => vec!["cargo-hack"],
+        "bloat" | "bloat-deps" | "bloat-fns" | "bloat-time" => {
+            vec!["cargo-bloat"]
+        }
+        "audit" => vec!["cargo-audit"],
+        "book" => vec!["mdbook"],
+        "dist" => vec!["tar"],
+        "typos" => vec!["typos"],
+        "msrv" => vec!["rustup"],
+        "wasm" => vec!["wasm-bindgen"],
+        "fuzz" => vec!["cargo-fuzz"],
+        "profile" => vec!["cargo-flamegraph"],
+        "changelog" | "install-hooks" | "release" | "size-track" => {
+            vec!["git"]
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the tasks of `cli` in the order of its help, with the status of
+/// their tools. The hidden tasks are left out.
+pub fn tasks(cli: &Command) -> Vec<TaskInfo> {
+    cli.get_subcommands()
+        .filter(|task| !task.is_hide_set())
+        .map(|task| TaskInfo {
+            name: task.get_name().to_string(),
+            aliases: task
+                .get_visible_aliases()
+                .map(ToString::to_string)
+                .collect(),
+            description: task
+                .get_about()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+            tools: required_tools(task.get_name())
+                .into_iter()
+                .map(|tool| ToolStatus {
+                    name: tool.to_string(),
+                    installed: tools::is_installed(tool),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Returns the name of `task` followed by its aliases, e.g. `coverage (cov)`.
+fn display_name(task: &TaskInfo) -> String {
+    if task.aliases.is_empty() {
+        task.name.clone()
+    } else {
+        format!("{} ({})", task.name, task.aliases.join(", "))
+    }
+}
+
+/// Renders `tasks` as a table of names and aliases, descriptions and tools,
+/// the missing tools being marked as such.
+pub fn table(tasks: &[TaskInfo]) -> String {
+    let width = tasks
+        .iter()
+        .map(|task| display_name(task).len())
+        .max()
+        .unwrap_or(0)
+        .max("task".len());
+    let mut table =
+        format!("{:<width$}  description [tools]\n", "task");
+    for task in tasks {
+        let mut row = format!(
+            "{:<width$}  {}",
+            display_name(task),
+            task.description
+        );
+        let tools: Vec<String> = task
+            .tools
+            .iter()
+            .map(|tool| {
+                if tool.installed {
+                    tool.name.clone()
+                } else {
+                    format!("{} (missing)", tool.name)
+                }
+            })
+            .collect();
+        if !tools.is_empty() {
+            row.push_str(&format!(" [{}]", tools.join(", ")));
+        }
+        table.push_str(row.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+/// Renders `tasks` as a JSON array, for editor integrations.
+///
+/// # Errors
+///
+/// Returns an error if the tasks cannot be serialized.
+pub fn to_json(tasks: &[TaskInfo]) -> AnyResult<String> {
+    serde_json::to_string_pretty(tasks).map_err(AnyError::new)
+}
@@ -0,0 +1,139 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `sort-deps` task, which sorts the dependency tables of the workspace
+//! manifests.
+//!
+//! The `[dependencies]`, `[dev-dependencies]` and `[build-dependencies]`
+//! tables, including their `[target.'cfg(...)'.*]` variants, are sorted by
+//! crate name with `toml_edit`, so the comments above an entry move with it
+//! and inline tables keep their layout. Only the manifests whose order
+//! changes are written.
+
+use crate::ops::{self, error::XtaskError, metadata};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use std::{fs, path::PathBuf};
+use toml_edit::{DocumentMut, Item, TableLike, Value};
+
+/// The dependency tables sorted by the task.
+pub const DEPENDENCY_TABLES: [&str; 3] =
+    ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Sorts the [`DEPENDENCY_TABLES`] of `table`.
+fn sort_tables(table: &mut dyn TableLike) {
+    for name in DEPENDENCY_TABLES {
+        match table.get_mut(name) {
+            Some(Item::Table(dependencies)) => {
+                dependencies.sort_values();
+            }
+            Some(Item::Value(Value::InlineTable(dependencies))) => {
+                dependencies.sort_values();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the manifest `contents` with its dependency tables sorted by
+/// crate name, the rest of the document being left as is.
+///
+/// # Errors
+///
+/// Returns an error if `contents` is not valid TOML.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::manifest::sort_dependencies;
+///
+/// let manifest = "[dependencies]\n# Errors.\nanyhow = \"1\"\nserde = { version = \"1\", features = [\"derive\"] }\nclap = \"4\"\n";
+/// assert_eq!(
+///     sort_dependencies(manifest).unwrap(),
+///     "[dependencies]\n# Errors.\nanyhow = \"1\"\nclap = \"4\"\nserde = { version = \"1\", features = [\"derive\"] }\n"
+/// );
+/// ```
+pub fn sort_dependencies(contents: &str) -> AnyResult<String> {
+    let mut document: DocumentMut = contents.parse()?;
+    sort_tables(document.as_table_mut());
+    if let Some(targets) =
+        document.get_mut("target").and_then(Item::as_table_like_mut)
+    {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_table_like_mut() {
+                sort_tables(target);
+            }
+        }
+    }
+    let mut sorted = document.to_string();
+    // toml_edit ends the document with a newline, which the manifest may
+    // not have.
+    if !contents.ends_with('\n') && sorted.ends_with('\n') {
+        let _ = sorted.pop();
+    }
+    Ok(sorted)
+}
+
+/// Sorts the dependency tables of `manifests`, or only checks them if
+/// `check` is set.
+///
+/// # Returns
+///
+/// The manifests that were not sorted, which are rewritten unless `check`
+/// is set.
+///
+/// # Errors
+///
+/// Returns an error if a manifest cannot be read, parsed or written, or in
+/// check mode, listing the manifests that are not sorted.
+pub fn sort_manifests(
+    manifests: &[PathBuf],
+    check: bool,
+) -> AnyResult<Vec<PathBuf>> {
+    let mut unsorted = Vec::new();
+    for manifest in manifests {
+        let contents =
+            fs::read_to_string(manifest).with_context(|| {
+                XtaskError::Io(format!(
+                    "Failed to read {}",
+                    manifest.display()
+                ))
+            })?;
+        let sorted =
+            sort_dependencies(&contents).with_context(|| {
+                format!("Failed to parse {}", manifest.display())
+            })?;
+        if sorted == contents {
+            continue;
+        }
+        if !check {
+            ops::write_atomic(manifest, &sorted)?;
+        }
+        unsorted.push(manifest.clone());
+    }
+    if !check || unsorted.is_empty() {
+        return Ok(unsorted);
+    }
+    let list: Vec<String> = unsorted
+        .iter()
+        .map(|manifest| format!("  {}", manifest.display()))
+        .collect();
+    Err(AnyError::new(XtaskError::StepFailed {
+        step: "sort-deps".to_string(),
+    })
+    .context(format!(
+        "{} manifests have unsorted dependencies, run 'cargo xtask sort-deps' to sort them:\n{}",
+        unsorted.len(),
+        list.join("\n")
+    )))
+}
+
+/// Sorts the dependency tables of every manifest of the current workspace,
+/// see [`sort_manifests`].
+///
+/// # Errors
+///
+/// Returns an error if `cargo metadata` fails, see [`sort_manifests`]
+/// otherwise.
+pub fn sort(check: bool) -> AnyResult<Vec<PathBuf>> {
+    sort_manifests(&metadata::workspace_manifests()?, check)
+}
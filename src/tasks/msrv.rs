@@ -0,0 +1,294 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `msrv` task, which finds the minimum supported Rust version of the
+//! workspace instead of only asserting it.
+//!
+//! The minor versions between two bounds are bisected: each candidate
+//! toolchain is installed with rustup's minimal profile and runs
+//! `cargo check --all-features --locked`. The results are cached in the xtask
+//! cache, keyed by the digest of `Cargo.lock`, so a re-run only checks the
+//! versions it has not seen with the same dependencies.
+
+use crate::{
+    ops::{
+        archive::sha256_hex,
+        manifest,
+        runner::{self, CommandSpec},
+        tools, write_file_if_changed,
+    },
+    tasks::clean::CACHE_DIR,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The lowest version bisected by default: the first to support the 2021
+/// edition.
+pub const DEFAULT_LOWER: &str = "1.56";
+
+/// The arguments of the `cargo check` run with each candidate toolchain.
+pub const CHECK_ARGS: [&str; 2] = ["--all-features", "--locked"];
+
+/// Returns the minor version of a Rust `version`, e.g. `70` for `1.70` or
+/// `1.70.0`.
+///
+/// # Errors
+///
+/// Returns an error if `version` is not a `1.Y` or `1.Y.Z` version.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::msrv::parse_minor;
+///
+/// assert_eq!(parse_minor("1.70").unwrap(), 70);
+/// assert_eq!(parse_minor("1.71.1").unwrap(), 71);
+/// assert!(parse_minor("2.0").is_err());
+/// ```
+pub fn parse_minor(version: &str) -> AnyResult<u64> {
+    let mut parts = version.trim().split('.');
+    let minor = match (parts.next(), parts.next(), parts.next()) {
+        (Some("1"), Some(minor), patch)
+            if patch
+                .map_or(true, |patch| patch.parse::<u64>().is_ok()) =>
+        {
+            minor.parse().ok()
+        }
+        _ => None,
+    };
+    match (minor, parts.next()) {
+        (Some(minor), None) => Ok(minor),
+        _ => Err(AnyError::msg(format!(
+            "Invalid Rust version '{version}', expected 1.Y or 1.Y.Z"
+        ))),
+    }
+}
+
+/// Returns the minor versions from `lower` to `upper` included, e.g.
+/// `["1.68", "1.69", "1.70"]`.
+///
+/// # Errors
+///
+/// Returns an error if a bound is not a valid version or `lower` is above
+/// `upper`.
+pub fn candidates(lower: &str, upper: &str) -> AnyResult<Vec<String>> {
+    let (low, high) = (parse_minor(lower)?, parse_minor(upper)?);
+    if low > high {
+        return Err(AnyError::msg(format!(
+            "The lower bound {lower} is above the upper bound {upper}"
+        )));
+    }
+    Ok((low..=high).map(|minor| format!("1.{minor}")).collect())
+}
+
+/// Returns the lowest of the ascending `candidates` for which `check` passes,
+/// or `None` if even the last one fails.
+///
+/// The candidates are bisected, which assumes that every version above one
+/// that passes passes too, so only about `log2(n)` of them are checked.
+///
+/// # Errors
+///
+/// Returns the first error of `check`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::msrv::bisect;
+///
+/// let versions = ["1.60", "1.61", "1.62", "1.63"].map(String::from);
+/// let found = bisect(&versions, |version| Ok(version >= "1.62"));
+/// assert_eq!(found.unwrap().as_deref(), Some("1.62"));
+/// ```
+pub fn bisect<F>(
+    candidates: &[String],
+    mut check: F,
+) -> AnyResult<Option<String>>
+where
+    F: FnMut(&str) -> AnyResult<bool>,
+{
+    let (mut low, mut high) = (0, candidates.len());
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if check(&candidates[middle])? {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    Ok(candidates.get(low).cloned())
+}
+
+/// The cached results of the checks, by version, for one `Cargo.lock`.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct MsrvCache {
+    /// The SHA-256 digest of the `Cargo.lock` the results were checked
+    /// with.
+    pub lockfile: String,
+    /// Whether `cargo check` passed, by version.
+    pub results: BTreeMap<String, bool>,
+}
+
+impl MsrvCache {
+    /// Loads the cache at `path`, keeping its results only if they were
+    /// checked with the `lockfile` digest.
+    pub fn load(path: &Path, lockfile: &str) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Self>(&json).ok())
+            .filter(|cache| cache.lockfile == lockfile)
+            .unwrap_or_else(|| Self {
+                lockfile: lockfile.to_string(),
+                results: BTreeMap::new(),
+            })
+    }
+
+    /// Writes the cache to `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cache cannot be written.
+    pub fn save(&self, path: &Path) -> AnyResult<()> {
+        let _ = write_file_if_changed(
+            path,
+            &serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the path of the check cache of the workspace at `root`.
+pub fn cache_path(root: &Path) -> PathBuf {
+    root.join(CACHE_DIR).join("msrv.json")
+}
+
+/// Installs the `version` toolchain if needed and returns whether
+/// `cargo check --all-features --locked` passes with it in the workspace at
+/// `root`.
+///
+/// # Errors
+///
+/// Returns an error if the toolchain cannot be installed or `cargo` cannot be
+/// spawned.
+pub fn check_version(root: &Path, version: &str) -> AnyResult<bool> {
+    let _ = tools::ensure_toolchain(version, &[], true)?;
+    let command = tools::cargo_with(Some(version), "check")
+        .args(CHECK_ARGS)
+        .arg("--manifest-path")
+        .arg(root.join("Cargo.toml").to_string_lossy())
+        .captured();
+    let output =
+        runner::current().execute(&command).map_err(|err| {
+            AnyError::new(err)
+                .context(format!("Failed to spawn '{command}'"))
+        })?;
+    Ok(output.status.success())
+}
+
+/// Returns the minimum Rust version between `lower` and `upper` with which
+/// the workspace at `root` checks, see [`bisect`] and [`check_version`].
+///
+/// # Errors
+///
+/// Returns an error if the bounds are invalid, if a check cannot run, or if
+/// the workspace does not check with `upper`.
+pub fn find_in(
+    root: &Path,
+    lower: &str,
+    upper: &str,
+) -> AnyResult<String> {
+    let versions = candidates(lower, upper)?;
+    let lockfile = fs::read(root.join("Cargo.lock"))
+        .map(|contents| sha256_hex(&contents))
+        .unwrap_or_default();
+    let path = cache_path(root);
+    let mut cache = MsrvCache::load(&path, &lockfile);
+    let found = bisect(&versions, |version| {
+        if let Some(&passed) = cache.results.get(version) {
+            println!("{version}: {} (cached)", outcome(passed));
+            return Ok(passed);
+        }
+        let passed = check_version(root, version)?;
+        println!("{version}: {}", outcome(passed));
+        if !runner::is_dry_run() {
+            let _ = cache.results.insert(version.to_string(), passed);
+            cache.save(&path)?;
+        }
+        Ok(passed)
+    })?;
+    found.ok_or_else(|| {
+        AnyError::msg(format!(
+            "The workspace does not check with Rust {upper}, the upper bound"
+        ))
+    })
+}
+
+/// Returns how a check is reported.
+fn outcome(passed: bool) -> &'static str {
+    if passed {
+        "ok"
+    } else {
+        "failed"
+    }
+}
+
+/// Returns the minimum Rust version between `lower` and `upper` with which
+/// the current workspace checks, see [`find_in`].
+///
+/// # Errors
+///
+/// Returns an error if the bounds are invalid, if a check cannot run, or if
+/// the workspace does not check with `upper`.
+pub fn find(lower: &str, upper: &str) -> AnyResult<String> {
+    find_in(Path::new("."), lower, upper)
+}
+
+/// Returns the `1.Y` version of the active `rustc`, the default upper bound.
+///
+/// # Errors
+///
+/// Returns an error if `rustc --version` fails or prints no version.
+pub fn active_version() -> AnyResult<String> {
+    let line =
+        runner::query(&CommandSpec::new("rustc").arg("--version"))?;
+    let version = line.split_whitespace().nth(1).unwrap_or_default();
+    Ok(format!("1.{}", parse_minor(version)?))
+}
+
+/// Finds the minimum Rust version of the current workspace between `lower`,
+/// [`DEFAULT_LOWER`] by default, and `upper`, the active `rustc` by default,
+/// and writes it into the `rust-version` of `Cargo.toml` if `write` is set.
+///
+/// # Errors
+///
+/// Returns an error if the version cannot be found or the manifest cannot be
+/// written.
+pub fn msrv(
+    lower: Option<&str>,
+    upper: Option<&str>,
+    write: bool,
+) -> AnyResult<String> {
+    let upper = match upper {
+        Some(upper) => upper.to_string(),
+        None => active_version()?,
+    };
+    let version = find(lower.unwrap_or(DEFAULT_LOWER), &upper)?;
+    println!("MSRV: {version}");
+    if write {
+        manifest::set_rust_version(Path::new("Cargo.toml"), &version)
+            .context("Failed to write the MSRV")?;
+        if !runner::is_dry_run() {
+            println!(
+                "Wrote rust-version = \"{version}\" to Cargo.toml"
+            );
+        }
+    }
+    Ok(version)
+}
@@ -0,0 +1,634 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `outdated` task, which reports the direct dependencies that have newer
+//! versions, split into semver-compatible and breaking updates.
+//!
+//! [`report`] uses `cargo outdated --format json` when cargo-outdated is
+//! installed. Otherwise, the versions locked in `Cargo.lock` are compared with
+//! the sparse index of crates.io, fetched with `curl` a few crates at a time.
+
+use crate::{
+    ops::{
+        error::XtaskError,
+        metadata,
+        runner::{self, CommandSpec},
+        tools,
+    },
+    tasks::{clean::CACHE_DIR, semver::parse_version},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The sparse index of crates.io.
+pub const SPARSE_INDEX_URL: &str = "https://index.crates.io";
+
+/// A direct dependency with a newer version.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub struct OutdatedDependency {
+    /// The name of the crate.
+    pub name: String,
+    /// The locked version.
+    pub current: String,
+    /// The newest version semver-compatible with `current`, if newer than
+    /// it.
+    pub compatible: Option<String>,
+    /// The newest version.
+    pub latest: String,
+}
+
+impl OutdatedDependency {
+    /// Returns `true` if `latest` is not semver-compatible with `current`.
+    pub fn is_breaking(&self) -> bool {
+        !is_compatible(&self.current, &self.latest)
+    }
+}
+
+/// The outdated direct dependencies of the workspace.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct OutdatedReport {
+    /// Where the versions come from: `cargo-outdated` or the crates.io
+    /// index.
+    pub source: String,
+    /// The outdated dependencies, sorted by name.
+    pub dependencies: Vec<OutdatedDependency>,
+}
+
+impl OutdatedReport {
+    /// Returns the dependencies whose newest version is semver-compatible.
+    pub fn compatible(&self) -> Vec<&OutdatedDependency> {
+        self.dependencies
+            .iter()
+            .filter(|dependency| !dependency.is_breaking())
+            .collect()
+    }
+
+    /// Returns the dependencies whose newest version is a breaking update.
+    pub fn breaking(&self) -> Vec<&OutdatedDependency> {
+        self.dependencies
+            .iter()
+            .filter(|dependency| dependency.is_breaking())
+            .collect()
+    }
+}
+
+impl fmt::Display for OutdatedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.dependencies.is_empty() {
+            return write!(
+                f,
+                "All dependencies are up to date ({})",
+                self.source
+            );
+        }
+        let width = |header: &str, lengths: Vec<usize>| {
+            lengths.into_iter().fold(header.len(), usize::max)
+        };
+        let name = width(
+            "name",
+            self.dependencies.iter().map(|d| d.name.len()).collect(),
+        );
+        let current = width(
+            "current",
+            self.dependencies.iter().map(|d| d.current.len()).collect(),
+        );
+        let compatible = width(
+            "compatible",
+            self.dependencies
+                .iter()
+                .map(|d| d.compatible.as_deref().map_or(1, str::len))
+                .collect(),
+        );
+        let groups = [
+            ("Semver-compatible updates", self.compatible()),
+            ("Breaking updates", self.breaking()),
+        ];
+        let mut first = true;
+        for (title, dependencies) in groups {
+            if dependencies.is_empty() {
+                continue;
+            }
+            if !first {
+                writeln!(f)?;
+            }
+            first = false;
+            writeln!(f, "{title} ({}):", dependencies.len())?;
+            write!(
+                f,
+                "  {:<name$}  {:<current$}  {:<compatible$}  latest",
+                "name", "current", "compatible"
+            )?;
+            for dependency in dependencies {
+                write!(
+                    f,
+                    "\n  {:<name$}  {:<current$}  {:<compatible$}  {}",
+                    dependency.name,
+                    dependency.current,
+                    dependency.compatible.as_deref().unwrap_or("-"),
+                    dependency.latest
+                )?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "Source: {}", self.source)
+    }
+}
+
+/// Returns `true` if a dependency on `^current` accepts `version`: they
+/// share the major version, or the minor version below 1.0, or the patch
+/// version below 0.1.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::outdated::is_compatible;
+///
+/// assert!(is_compatible("1.2.3", "1.9.0"));
+/// assert!(!is_compatible("1.2.3", "2.0.0"));
+/// assert!(is_compatible("0.4.1", "0.4.9"));
+/// assert!(!is_compatible("0.4.1", "0.5.0"));
+/// ```
+pub fn is_compatible(current: &str, version: &str) -> bool {
+    match (parse_version(current), parse_version(version)) {
+        (Ok((0, 0, patch)), Ok((0, 0, other))) => patch == other,
+        (Ok((0, minor, _)), Ok((0, other, _))) => minor == other,
+        (Ok((major, _, _)), Ok((other, _, _))) => major == other,
+        _ => false,
+    }
+}
+
+/// Returns the outdated dependencies found in `versions`, the
+/// `(name, current, compatible, latest)` versions of each dependency, sorted
+/// by name.
+fn collect_outdated(
+    versions: impl IntoIterator<Item = (String, String, String, String)>,
+) -> Vec<OutdatedDependency> {
+    let newer = |version: &str, current: &str| {
+        matches!(
+            (parse_version(version), parse_version(current)),
+            (Ok(version), Ok(current)) if version > current
+        )
+    };
+    let mut dependencies: Vec<OutdatedDependency> = versions
+        .into_iter()
+        .filter(|(_, current, _, latest)| newer(latest, current))
+        .map(|(name, current, compatible, latest)| OutdatedDependency {
+            compatible: newer(&compatible, &current)
+                .then_some(compatible),
+            name,
+            current,
+            latest,
+        })
+        .collect();
+    dependencies.sort();
+    dependencies.dedup();
+    dependencies
+}
+
+/// Parses the output of `cargo outdated --format json`, one JSON object per
+/// package of the workspace.
+///
+/// # Errors
+///
+/// Returns an error if a line is not valid JSON.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::outdated::parse_cargo_outdated;
+///
+/// let output = r#"{"crate_name":"app","dependencies":[{"name":"clap","project":"4.4.12","compat":"4.5.4","latest":"4.5.4","kind":"Normal","platform":null}]}"#;
+/// let report = parse_cargo_outdated(output).unwrap();
+/// assert_eq!(report.dependencies[0].compatible.as_deref(), Some("4.5.4"));
+/// assert!(report.breaking().is_empty());
+/// ```
+pub fn parse_cargo_outdated(output: &str) -> AnyResult<OutdatedReport> {
+    let mut versions = Vec::new();
+    for line in output.lines().filter(|line| !line.trim().is_empty()) {
+        let package: serde_json::Value = serde_json::from_str(line)
+            .context("Failed to parse the output of cargo outdated")?;
+        let dependencies = package["dependencies"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        for dependency in dependencies {
+            let field = |key: &str| {
+                dependency[key].as_str().unwrap_or_default().to_string()
+            };
+            versions.push((
+                field("name"),
+                field("project"),
+                field("compat"),
+                field("latest"),
+            ));
+        }
+    }
+    Ok(OutdatedReport {
+        source: "cargo-outdated".to_string(),
+        dependencies: collect_outdated(versions),
+    })
+}
+
+/// Returns the direct registry dependencies of the workspace packages of a
+/// `Cargo.lock` file, as `(name, version)` pairs sorted by name.
+///
+/// # Errors
+///
+/// Returns an error if `lockfile` is not a valid lockfile.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::outdated::parse_lockfile_dependencies;
+///
+/// let lockfile = r#"
+/// [[package]]
+/// name = "app"
+/// version = "0.1.0"
+/// dependencies = ["serde"]
+///
+/// [[package]]
+/// name = "serde"
+/// version = "1.0.193"
+/// source = "registry+https://github.com/rust-lang/crates.io-index"
+/// "#;
+/// assert_eq!(
+///     parse_lockfile_dependencies(lockfile).unwrap(),
+///     [("serde".to_string(), "1.0.193".to_string())]
+/// );
+/// ```
+pub fn parse_lockfile_dependencies(
+    lockfile: &str,
+) -> AnyResult<Vec<(String, String)>> {
+    let lockfile: toml::Table = lockfile.parse().map_err(|err| {
+        XtaskError::ConfigInvalid(format!("Invalid Cargo.lock: {err}"))
+    })?;
+    let packages = lockfile
+        .get("package")
+        .and_then(toml::Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let field = |package: &toml::Value, key: &str| {
+        package
+            .get(key)
+            .and_then(toml::Value::as_str)
+            .map(str::to_string)
+    };
+    let registry: Vec<(String, String)> = packages
+        .iter()
+        .filter(|package| {
+            field(package, "source").is_some_and(|source| {
+                source.starts_with("registry+")
+                    || source.starts_with("sparse+")
+            })
+        })
+        .filter_map(|package| {
+            Some((field(package, "name")?, field(package, "version")?))
+        })
+        .collect();
+    let mut dependencies: Vec<(String, String)> = packages
+        .iter()
+        .filter(|package| field(package, "source").is_none())
+        .filter_map(|package| package.get("dependencies")?.as_array())
+        .flatten()
+        .filter_map(toml::Value::as_str)
+        .filter_map(|dependency| {
+            let mut words = dependency.split_whitespace();
+            let name = words.next()?;
+            let version = words.next();
+            registry
+                .iter()
+                .find(|(locked, locked_version)| {
+                    locked == name
+                        && version.map_or(true, |version| {
+                            version == locked_version
+                        })
+                })
+                .cloned()
+        })
+        .collect();
+    dependencies.sort();
+    dependencies.dedup();
+    Ok(dependencies)
+}
+
+/// Returns the path of the sparse index entry of the crate `name`, e.g.
+/// `se/rd/serde`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::outdated::sparse_index_path;
+///
+/// assert_eq!(sparse_index_path("Serde"), "se/rd/serde");
+/// assert_eq!(sparse_index_path("syn"), "3/s/syn");
+/// assert_eq!(sparse_index_path("cc"), "2/cc");
+/// ```
+pub fn sparse_index_path(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+/// Returns the newest version of an index entry semver-compatible with
+/// `current`, and the newest version, skipping yanked and pre-release
+/// versions.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::outdated::parse_index_entry;
+///
+/// let entry = r#"{"name":"a","vers":"1.2.0","yanked":false}
+/// {"name":"a","vers":"1.3.0","yanked":true}
+/// {"name":"a","vers":"2.0.0-rc.1","yanked":false}
+/// {"name":"a","vers":"2.1.0","yanked":false}"#;
+/// assert_eq!(
+///     parse_index_entry(entry, "1.0.0"),
+///     Some(("1.2.0".to_string(), "2.1.0".to_string()))
+/// );
+/// ```
+pub fn parse_index_entry(
+    entry: &str,
+    current: &str,
+) -> Option<(String, String)> {
+    let versions: Vec<((u64, u64, u64), String)> = entry
+        .lines()
+        .filter_map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).ok()
+        })
+        .filter(|version| version["yanked"] != true)
+        .filter_map(|version| {
+            let vers = version["vers"].as_str()?.to_string();
+            if vers.contains('-') {
+                return None;
+            }
+            Some((parse_version(&vers).ok()?, vers))
+        })
+        .collect();
+    let newest = |versions: &mut dyn Iterator<
+        Item = &((u64, u64, u64), String),
+    >| {
+        versions
+            .max_by_key(|(numbers, _)| *numbers)
+            .map(|(_, v)| v.clone())
+    };
+    let latest = newest(&mut versions.iter())?;
+    let compatible = newest(
+        &mut versions
+            .iter()
+            .filter(|(_, vers)| is_compatible(current, vers)),
+    )
+    .unwrap_or_else(|| current.to_string());
+    Some((compatible, latest))
+}
+
+/// Compares the versions locked in a `Cargo.lock` file with a sparse index,
+/// for the `outdated` task when cargo-outdated is not installed.
+#[derive(Builder, Clone, Debug, PartialEq, Eq, Hash)]
+#[builder(setter(into))]
+pub struct IndexLookup {
+    /// The URL of the sparse index.
+    ///
+    /// By default, this is set to [`SPARSE_INDEX_URL`].
+    #[builder(default = "SPARSE_INDEX_URL.to_string()")]
+    pub index_url: String,
+
+    /// The maximum number of concurrent requests.
+    ///
+    /// By default, this is set to `8`.
+    #[builder(default = "8")]
+    pub concurrency: usize,
+
+    /// The timeout of each request.
+    ///
+    /// By default, this is set to 30 seconds.
+    #[builder(default = "Duration::from_secs(30)")]
+    pub timeout: Duration,
+
+    /// The directory the index entries are downloaded to.
+    ///
+    /// By default, this is set to `target/xtask/outdated`.
+    #[builder(default = "Path::new(CACHE_DIR).join(\"outdated\")")]
+    pub cache_dir: PathBuf,
+}
+
+impl Default for IndexLookup {
+    fn default() -> Self {
+        IndexLookupBuilder::default()
+            .build()
+            .expect("the builder defaults are a valid configuration")
+    }
+}
+
+impl IndexLookup {
+    /// Returns the URL of the index entry of the crate `name`.
+    fn url(&self, name: &str) -> String {
+        format!(
+            "{}/{}",
+            self.index_url.trim_end_matches('/'),
+            sparse_index_path(name)
+        )
+    }
+
+    /// Returns the `curl` command downloading the index entries of `names`
+    /// into the cache directory, printing one `<status> <url>` line per
+    /// request.
+    pub fn command(&self, names: &[&str]) -> CommandSpec {
+        let mut command = CommandSpec::new("curl").args([
+            "--silent".to_string(),
+            "--location".to_string(),
+            "--parallel".to_string(),
+            "--parallel-max".to_string(),
+            self.concurrency.max(1).to_string(),
+            "--max-time".to_string(),
+            self.timeout.as_secs().max(1).to_string(),
+            "--write-out".to_string(),
+            "%{http_code} %{url_effective}\\n".to_string(),
+        ]);
+        for name in names {
+            command = command.arg("--output").arg(
+                self.cache_dir.join(name).to_string_lossy().to_string(),
+            );
+            command = command.arg(self.url(name));
+        }
+        command
+    }
+
+    /// Returns the outdated dependencies among the `(name, version)`
+    /// `dependencies`.
+    ///
+    /// A crate missing from the index is skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cache directory cannot be created, or naming
+    /// the crates whose index entry cannot be fetched, e.g. when offline.
+    pub fn report(
+        &self,
+        dependencies: &[(String, String)],
+    ) -> AnyResult<OutdatedReport> {
+        let mut report = OutdatedReport {
+            source: format!("crates.io index ({})", self.index_url),
+            dependencies: Vec::new(),
+        };
+        if dependencies.is_empty() {
+            return Ok(report);
+        }
+        fs::create_dir_all(&self.cache_dir).with_context(|| {
+            XtaskError::Io(format!(
+                "Failed to create {}",
+                self.cache_dir.display()
+            ))
+        })?;
+        let names: Vec<&str> = dependencies
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let command = self.command(&names).captured();
+        // curl exits with an error when any request fails, so the status
+        // lines are read whatever the exit status.
+        let output = runner::current()
+            .execute(&command)
+            .with_context(|| format!("Failed to spawn '{command}'"))?;
+        let statuses: HashMap<String, u16> =
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| {
+                    let (code, url) = line.trim().split_once(' ')?;
+                    Some((url.to_string(), code.parse().ok()?))
+                })
+                .collect();
+        let mut unreachable = Vec::new();
+        let mut versions = Vec::new();
+        for (name, current) in dependencies {
+            match statuses.get(&self.url(name)) {
+                Some(200) => {
+                    let entry =
+                        fs::read_to_string(self.cache_dir.join(name))
+                            .unwrap_or_default();
+                    if let Some((compatible, latest)) =
+                        parse_index_entry(&entry, current)
+                    {
+                        versions.push((
+                            name.clone(),
+                            current.clone(),
+                            compatible,
+                            latest,
+                        ));
+                    }
+                }
+                Some(404 | 410) => {}
+                _ => unreachable.push(name.as_str()),
+            }
+        }
+        if !unreachable.is_empty() {
+            return Err(AnyError::msg(format!(
+                "Failed to fetch {} of {} crates from {}: {} (are you offline? install cargo-outdated to use its cache instead)",
+                unreachable.len(),
+                dependencies.len(),
+                self.index_url,
+                unreachable.join(", ")
+            )));
+        }
+        report.dependencies = collect_outdated(versions);
+        Ok(report)
+    }
+}
+
+/// Returns the outdated dependencies of the workspace at `root`, from its
+/// `Cargo.lock` and the crates.io index.
+///
+/// # Errors
+///
+/// Returns an error if `Cargo.lock` cannot be read or parsed, or if the index
+/// cannot be reached, see [`IndexLookup::report`].
+pub fn index_report(
+    root: &Path,
+    lookup: &IndexLookup,
+) -> AnyResult<OutdatedReport> {
+    let path = root.join("Cargo.lock");
+    let lockfile = fs::read_to_string(&path).with_context(|| {
+        XtaskError::Io(format!(
+            "Failed to read {}, run `cargo generate-lockfile` first",
+            path.display()
+        ))
+    })?;
+    lookup.report(&parse_lockfile_dependencies(&lockfile)?)
+}
+
+/// Returns the outdated direct dependencies of the current workspace, with
+/// `cargo outdated` if it is installed, or else from the crates.io index.
+///
+/// # Errors
+///
+/// Returns an error if `cargo outdated` fails, or if the fallback fails, see
+/// [`index_report`].
+pub fn report() -> AnyResult<OutdatedReport> {
+    if tools::is_installed("cargo-outdated") {
+        let output = runner::query(&CommandSpec::new("cargo").args([
+            "outdated",
+            "--format",
+            "json",
+            "--root-deps-only",
+            "--workspace",
+        ]))?;
+        return parse_cargo_outdated(&output);
+    }
+    let root = metadata::workspace_packages()
+        .map_or_else(|_| PathBuf::from("."), |(root, _)| root);
+    index_report(&root, &IndexLookup::default())
+}
+
+/// Prints the [`report`] of the outdated dependencies.
+///
+/// # Errors
+///
+/// Returns an error if the report fails, or if `exit_code` is set and some
+/// dependencies have breaking updates, for scheduled CI jobs.
+pub fn outdated(exit_code: bool) -> AnyResult<OutdatedReport> {
+    let report = report()?;
+    println!("{report}");
+    let breaking = report.breaking();
+    if exit_code && !breaking.is_empty() {
+        let names: Vec<String> = breaking
+            .iter()
+            .map(|dependency| {
+                format!("{} {}", dependency.name, dependency.latest)
+            })
+            .collect();
+        return Err(AnyError::new(XtaskError::StepFailed {
+            step: "outdated".to_string(),
+        })
+        .context(format!(
+            "{} dependencies have breaking updates: {}",
+            breaking.len(),
+            names.join(", ")
+        )));
+    }
+    Ok(report)
+}
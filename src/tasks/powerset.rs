@@ -1,37 +1,116 @@
 // Copyright © 2023 xtasks. All rights reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use anyhow::{Context, Result as AnyResult};
+use crate::ops::{
+    metadata, output,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
 use derive_builder::Builder;
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, fmt, path::PathBuf, str::FromStr};
 
-/// Represents the configuration for generating a powerset of features for cargo build runs.
-///
-/// A powerset in this context refers to all possible combinations of features that can be enabled
-/// or disabled for a cargo build.
+/// The `summary` module collects the result of each feature combination.
+pub mod summary;
+
+pub use summary::{
+    CombinationResult, PowersetSummary, DEFAULT_SUMMARY_FILE,
+};
+
+/// A phase of a powerset run, each a `cargo hack` invocation over every feature
+/// combination.
 #[derive(
-    Builder,
+    Clone,
+    Copy,
     Debug,
-    Default,
     PartialEq,
     Eq,
+    PartialOrd,
+    Ord,
     Hash,
-    Clone,
     Serialize,
     Deserialize,
 )]
-#[builder(setter(into))]
+pub enum PowersetStep {
+    /// `cargo hack clippy`, denying warnings.
+    Clippy,
+    /// `cargo hack test`.
+    Test,
+    /// `cargo hack test --doc`.
+    DocTest,
+}
+
+impl PowersetStep {
+    /// Every step, in the order they run.
+    pub const ALL: [Self; 3] =
+        [Self::Clippy, Self::Test, Self::DocTest];
+
+    /// Returns the name of the step on the command line, e.g. `doc-test`.
+    pub const fn as_arg(self) -> &'static str {
+        match self {
+            Self::Clippy => "clippy",
+            Self::Test => "test",
+            Self::DocTest => "doc-test",
+        }
+    }
+}
+
+impl fmt::Display for PowersetStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Clippy => "clippy",
+            Self::Test => "test",
+            Self::DocTest => "doc test",
+        })
+    }
+}
+
+impl FromStr for PowersetStep {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|step| step.as_arg().eq_ignore_ascii_case(value))
+            .ok_or_else(|| {
+                let valid: Vec<&str> =
+                    Self::ALL.iter().map(|step| step.as_arg()).collect();
+                AnyError::msg(format!(
+                    "Unknown powerset step '{value}', expected one of: {}",
+                    valid.join(", ")
+                ))
+            })
+    }
+}
+
+/// Represents the configuration for generating a powerset of features for cargo build runs.
+///
+/// A powerset in this context refers to all possible combinations of features that can be enabled
+/// or disabled for a cargo build.
+#[derive(
+    Builder, Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize,
+)]
+#[builder(
+    setter(into),
+    build_fn(validate = "Self::validate"),
+    derive(Debug, Deserialize)
+)]
+#[builder_struct_attr(serde(deny_unknown_fields))]
 pub struct Powerset {
     /// Specifies the depth of the powerset.
     ///
     /// The depth determines how many features will be considered for each combination in the powerset.
     /// For example, a depth of 2 would consider all combinations of 2 features at a time.
     ///
-    /// Valid values are integers greater than or equal to 1.
+    /// Valid values are integers greater than or equal to 1. Depths above
+    /// [`DEFAULT_DEPTH`] must not exceed the number of features of the tested
+    /// packages, which is checked when the powerset runs.
     ///
-    /// By default, this is set to 2.
-    #[builder(default = "2")]
-    pub depth: i32,
+    /// By default, this is set to [`DEFAULT_DEPTH`].
+    #[builder(default = "DEFAULT_DEPTH", setter(custom))]
+    #[serde(deserialize_with = "deserialize_depth")]
+    pub depth: u32,
 
     /// Determines whether to exclude build runs with no default features enabled.
     ///
@@ -42,60 +121,659 @@ pub struct Powerset {
     /// By default, this is set to `false`.
     #[builder(default = "false")]
     pub exclude_no_default_features: bool,
+
+    /// Features left out of every combination, passed to cargo-hack as
+    /// `--exclude-features`.
+    ///
+    /// By default, this is empty.
+    #[builder(default)]
+    #[serde(default)]
+    pub exclude_features: Vec<String>,
+
+    /// The only features combined, passed to cargo-hack as
+    /// `--include-features`. The other features are never enabled.
+    ///
+    /// By default, this is empty, and every feature is combined.
+    #[builder(default)]
+    #[serde(default)]
+    pub include_features: Vec<String>,
+
+    /// Groups of features that are never enabled together, each passed to
+    /// cargo-hack as `--mutually-exclusive-features`, e.g. alternative
+    /// backends.
+    ///
+    /// By default, this is empty.
+    #[builder(default)]
+    #[serde(default)]
+    pub mutually_exclusive: Vec<Vec<String>>,
+
+    /// The workspace members left out of the run, e.g. the helper crate running
+    /// the tasks.
+    ///
+    /// By default, this is `["xtask"]`. The default is not checked against the
+    /// workspace, so workspaces without an `xtask` package keep working.
+    #[builder(default = "default_exclude_packages()")]
+    #[serde(default = "default_exclude_packages")]
+    pub exclude_packages: Vec<String>,
+
+    /// The only workspace members tested. When set, the exclusions do not
+    /// apply.
+    ///
+    /// By default, this is empty, and the whole workspace is tested.
+    #[builder(default)]
+    #[serde(default)]
+    pub packages: Vec<String>,
+
+    /// The steps to run, in the order of [`PowersetStep::ALL`] whatever their
+    /// order here.
+    ///
+    /// By default, this is every step.
+    #[builder(default = "PowersetStep::ALL.to_vec()")]
+    #[serde(default = "default_steps")]
+    pub steps: Vec<PowersetStep>,
+
+    /// Arguments passed to the test binaries of the `test` and `doc-test`
+    /// steps, after `--`, e.g. `--test-threads 1`.
+    ///
+    /// By default, this is empty.
+    #[builder(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub test_args: Vec<String>,
+
+    /// Prints the feature combinations each package would be tested with, and
+    /// how many runs that makes, instead of running anything.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Runs each feature combination separately, then prints which steps passed
+    /// for each of them, the failures first, and saves that summary to
+    /// [`summary_file`](Self::summary_file). The run does not stop at the first
+    /// failure.
+    ///
+    /// By default, this is set to `false`, and cargo-hack goes through the
+    /// combinations itself.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub summary: bool,
+
+    /// Where the summary is saved as JSON, e.g. to keep it as a CI artifact.
+    ///
+    /// By default, this is [`DEFAULT_SUMMARY_FILE`].
+    #[builder(default = "PathBuf::from(DEFAULT_SUMMARY_FILE)")]
+    #[serde(default = "default_summary_file")]
+    pub summary_file: PathBuf,
+
+    /// Removes the dev-dependencies while running clippy, with cargo-hack's
+    /// `--no-dev-deps`, so they are not built needlessly. The tests need them,
+    /// so the flag is left out of the other steps.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub no_dev_deps: bool,
+
+    /// Passes `--locked`, so the dependencies are not resolved again and
+    /// `Cargo.lock` must be up to date.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub locked: bool,
+
+    /// Keeps going after a failure, with cargo-hack's `--keep-going`, and runs
+    /// the remaining steps too; the error then lists every failing step.
+    ///
+    /// By default, this is set to `false`, and the run stops at the first
+    /// failure.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub keep_going: bool,
+
+    /// Installs cargo-hack when it is missing. Otherwise, a missing cargo-hack
+    /// is an error naming the command that installs it. By default, this is set
+    /// to `true`; the `XTASKS_NO_AUTO_INSTALL` environment variable turns it
+    /// off.
+    #[builder(default = "true")]
+    #[serde(default = "default_auto_install")]
+    pub auto_install: bool,
 }
 
-impl PowersetBuilder {
-    /// Builds and runs a powerset test.
+/// Returns whether a missing cargo-hack is installed when not configured.
+const fn default_auto_install() -> bool {
+    true
+}
+
+/// Returns where the summary is saved when not configured.
+fn default_summary_file() -> PathBuf {
+    PathBuf::from(DEFAULT_SUMMARY_FILE)
+}
+
+/// The oldest cargo-hack release supporting every flag the powerset passes.
+pub const REQUIRED_VERSION: &str = "0.6.0";
+
+/// The depth of the powerset unless configured otherwise.
+pub const DEFAULT_DEPTH: u32 = 2;
+
+/// Deserializes a depth, rejecting zero and negative values with a message
+/// naming the depth.
+fn deserialize_depth<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let depth = i64::deserialize(deserializer)?;
+    u32::try_from(depth)
+        .ok()
+        .filter(|depth| *depth >= 1)
+        .ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid powerset depth {depth}, expected an integer of at least 1"
+            ))
+        })
+}
+
+/// Returns the steps run when not configured.
+fn default_steps() -> Vec<PowersetStep> {
+    PowersetStep::ALL.to_vec()
+}
+
+/// Returns the packages left out of the run when not configured.
+fn default_exclude_packages() -> Vec<String> {
+    vec!["xtask".to_string()]
+}
+
+impl Powerset {
+    /// Returns the `cargo hack` invocations run by [`Powerset::run`], in order.
+    ///
+    /// These are `cargo hack clippy`, `cargo hack test`, and `cargo hack test --doc`
+    /// over the feature powerset described by the configuration, for the
+    /// selected [`steps`](Self::steps).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::powerset::PowersetBuilder;
+    ///
+    /// let powerset = PowersetBuilder::new(1).build().unwrap();
+    /// assert_eq!(
+    ///     powerset.commands()[1].to_string(),
+    ///     "cargo hack --workspace --exclude xtask --feature-powerset --depth 1 test"
+    /// );
+    /// ```
+    pub fn commands(&self) -> Vec<CommandSpec> {
+        self.selected_steps()
+            .into_iter()
+            .map(|step| self.command(step))
+            .collect()
+    }
+
+    /// Returns the selected steps, in the order they run.
+    fn selected_steps(&self) -> Vec<PowersetStep> {
+        PowersetStep::ALL
+            .into_iter()
+            .filter(|step| self.steps.contains(step))
+            .collect()
+    }
+
+    /// Returns the `cargo hack` invocation running `step`.
+    pub fn command(&self, step: PowersetStep) -> CommandSpec {
+        self.step_command(step, self.powerset_args())
+    }
+
+    /// Returns the `cargo hack` invocation running `step` for a single feature
+    /// combination of `package`, as run with [`summary`](Self::summary).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::powerset::{PowersetBuilder, PowersetStep};
+    ///
+    /// let powerset = PowersetBuilder::new(2).build().unwrap();
+    /// assert_eq!(
+    ///     powerset
+    ///         .combination_command(PowersetStep::Test, "app", &["a".to_string()])
+    ///         .to_string(),
+    ///     "cargo hack --package app --no-default-features --features a test"
+    /// );
+    /// ```
+    pub fn combination_command(
+        &self,
+        step: PowersetStep,
+        package: &str,
+        features: &[String],
+    ) -> CommandSpec {
+        let mut args = vec![
+            "--package".to_string(),
+            package.to_string(),
+            "--no-default-features".to_string(),
+        ];
+        if !features.is_empty() {
+            args.push("--features".to_string());
+            args.push(features.join(","));
+        }
+        self.step_command(step, args)
+    }
+
+    /// Returns the `cargo hack` invocation running `step` with `common_args`.
+    fn step_command(
+        &self,
+        step: PowersetStep,
+        mut common_args: Vec<String>,
+    ) -> CommandSpec {
+        if self.no_dev_deps && step == PowersetStep::Clippy {
+            common_args.push("--no-dev-deps".to_string());
+        }
+        if self.locked {
+            common_args.push("--locked".to_string());
+        }
+        let hack = CommandSpec::new("cargo").arg("hack");
+        let test = match step {
+            PowersetStep::Clippy => {
+                return hack
+                    .arg("clippy")
+                    .args(common_args)
+                    .args(["--", "-D", "warnings"])
+            }
+            PowersetStep::Test => hack.args(common_args).arg("test"),
+            PowersetStep::DocTest => {
+                hack.args(common_args).args(["test", "--doc"])
+            }
+        };
+        if self.test_args.is_empty() {
+            test
+        } else {
+            test.arg("--").args(self.test_args.iter().cloned())
+        }
+    }
+
+    /// Returns the arguments making cargo-hack go through the feature powerset
+    /// of the selected packages.
+    fn powerset_args(&self) -> Vec<String> {
+        let mut common_args: Vec<String> = Vec::new();
+        if self.keep_going {
+            common_args.push("--keep-going".to_string());
+        }
+        if self.packages.is_empty() {
+            common_args.push("--workspace".to_string());
+            for package in &self.exclude_packages {
+                common_args.push("--exclude".to_string());
+                common_args.push(package.clone());
+            }
+        } else {
+            for package in &self.packages {
+                common_args.push("--package".to_string());
+                common_args.push(package.clone());
+            }
+        }
+        common_args.push("--feature-powerset".to_string());
+        common_args.push("--depth".to_string());
+        common_args.push(self.depth.to_string());
+        if self.exclude_no_default_features {
+            common_args
+                .push("--exclude-no-default-features".to_string());
+        }
+        if !self.exclude_features.is_empty() {
+            common_args.push("--exclude-features".to_string());
+            common_args.push(self.exclude_features.join(","));
+        }
+        if !self.include_features.is_empty() {
+            common_args.push("--include-features".to_string());
+            common_args.push(self.include_features.join(","));
+        }
+        for group in &self.mutually_exclusive {
+            common_args
+                .push("--mutually-exclusive-features".to_string());
+            common_args.push(group.join(","));
+        }
+        common_args
+    }
+
+    /// Returns the feature combinations cargo-hack tests a package declaring
+    /// `features` with, each the list of features enabled on top of
+    /// `--no-default-features`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::BTreeSet;
+    /// use xtasks::tasks::powerset::PowersetBuilder;
+    ///
+    /// let features: BTreeSet<String> =
+    ///     ["default", "a", "b"].iter().map(|f| f.to_string()).collect();
+    /// let powerset = PowersetBuilder::new(2).build().unwrap();
+    /// // No features, a, b, and a with b.
+    /// assert_eq!(powerset.feature_combinations(&features).len(), 4);
+    /// ```
+    pub fn feature_combinations(
+        &self,
+        features: &BTreeSet<String>,
+    ) -> Vec<Vec<String>> {
+        let candidates = self.candidate_features(features);
+        let exclusive = |combination: &[&String]| {
+            self.mutually_exclusive.iter().all(|group| {
+                combination
+                    .iter()
+                    .filter(|feature| group.contains(feature))
+                    .count()
+                    <= 1
+            })
+        };
+        let depth = usize::try_from(self.depth).unwrap_or(usize::MAX);
+        let mut combinations: Vec<Vec<&String>> = vec![Vec::new()];
+        let mut last: Vec<Vec<&String>> = vec![Vec::new()];
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for combination in &last {
+                // Extend with later features only, so each set appears once.
+                let start = combination.last().map_or(0, |feature| {
+                    candidates
+                        .iter()
+                        .position(|candidate| candidate == feature)
+                        .map_or(0, |index| index + 1)
+                });
+                for feature in &candidates[start..] {
+                    let mut extended = combination.clone();
+                    extended.push(feature);
+                    if exclusive(&extended) {
+                        next.push(extended);
+                    }
+                }
+            }
+            combinations.extend(next.iter().cloned());
+            last = next;
+        }
+        if self.exclude_no_default_features {
+            let _ = combinations.remove(0);
+        }
+        combinations
+            .into_iter()
+            .map(|combination| {
+                combination.into_iter().cloned().collect()
+            })
+            .collect()
+    }
+
+    /// Returns the features of `features` that are combined: all but `default`,
+    /// restricted to the included ones and without the excluded ones.
+    fn candidate_features<'a>(
+        &self,
+        features: &'a BTreeSet<String>,
+    ) -> Vec<&'a String> {
+        features
+            .iter()
+            .filter(|feature| *feature != "default")
+            .filter(|feature| {
+                self.include_features.is_empty()
+                    || self.include_features.contains(feature)
+            })
+            .filter(|feature| !self.exclude_features.contains(feature))
+            .collect()
+    }
+
+    /// Returns `true` if `package` is tested by the configuration.
+    fn is_tested(&self, package: &str) -> bool {
+        if self.packages.is_empty() {
+            !self
+                .exclude_packages
+                .iter()
+                .any(|excluded| excluded == package)
+        } else {
+            self.packages.iter().any(|tested| tested == package)
+        }
+    }
+
+    /// Fails if the depth is above [`DEFAULT_DEPTH`] and exceeds the number of
+    /// features combined for every tested package, as the extra depth would
+    /// only make the run look more thorough than it is.
+    fn ensure_depth_fits(&self) -> AnyResult<()> {
+        if self.depth <= DEFAULT_DEPTH {
+            return Ok(());
+        }
+        let features = metadata::package_features()?
+            .into_iter()
+            .filter(|(package, _)| self.is_tested(package))
+            .map(|(_, features)| {
+                self.candidate_features(&features).len()
+            })
+            .max()
+            .unwrap_or(0);
+        if usize::try_from(self.depth)
+            .map_or(true, |depth| depth > features)
+        {
+            return Err(AnyError::msg(format!(
+                "Powerset depth {} exceeds the {features} features combined for the tested packages, use a depth of at most {}",
+                self.depth,
+                features.max(DEFAULT_DEPTH as usize)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the feature combinations each tested package would be run with,
+    /// by package name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the packages cannot be read with `cargo metadata`.
+    pub fn matrix(&self) -> AnyResult<Vec<(String, Vec<Vec<String>>)>> {
+        Ok(metadata::package_features()?
+            .into_iter()
+            .filter(|(package, _)| self.is_tested(package))
+            .map(|(package, features)| {
+                let combinations = self.feature_combinations(&features);
+                (package, combinations)
+            })
+            .collect())
+    }
+
+    /// Prints the matrix of feature combinations and the number of runs it
+    /// makes.
+    fn print_matrix(&self) -> AnyResult<()> {
+        let matrix = self.matrix()?;
+        let mut total = 0;
+        for (package, combinations) in &matrix {
+            println!(
+                "{package} ({} combinations):",
+                combinations.len()
+            );
+            for combination in combinations {
+                if combination.is_empty() {
+                    println!("  --no-default-features");
+                } else {
+                    println!("  --features {}", combination.join(","));
+                }
+            }
+            total += combinations.len();
+        }
+        let steps = self.selected_steps();
+        println!(
+            "{total} combinations × {} steps = {} runs",
+            steps.len(),
+            total * steps.len()
+        );
+        Ok(())
+    }
+
+    /// Runs a powerset test.
     ///
     /// This function will execute `cargo hack clippy`, `cargo hack test`, and `cargo hack test --doc`
     /// with various feature combinations based on the powerset configuration.
     ///
+    /// `cargo-hack` is installed first if it cannot be found, and must be at
+    /// least [`REQUIRED_VERSION`]. With
+    /// [`dry_run`](Self::dry_run), the feature combinations are printed
+    /// instead. With [`summary`](Self::summary), each combination is run
+    /// separately and the error lists the failing combinations.
+    ///
     /// # Errors
     ///
-    /// This function will return an error if any of the `cargo` commands fail to execute.
+    /// This function will return an error if a package or feature named by the
+    /// configuration is not part of the workspace, if the depth exceeds the
+    /// number of features, if `cargo-hack` cannot be installed or is too old,
+    /// or if any of
+    /// the `cargo` commands fail to execute or exit unsuccessfully. The error
+    /// names the failing phase, `clippy`, `test` or `doc test`, and the feature
+    /// combinations it covered; the failing combination is the last one
+    /// printed by cargo-hack.
     pub fn run(&self) -> AnyResult<()> {
-        let t = self
+        metadata::ensure_workspace_members(&self.packages)?;
+        if self.exclude_packages != default_exclude_packages() {
+            metadata::ensure_workspace_members(&self.exclude_packages)?;
+        }
+        metadata::ensure_workspace_features(&self.named_features())?;
+        self.ensure_depth_fits()?;
+        if self.dry_run {
+            return self.print_matrix();
+        }
+        ensure_cargo_hack(self.auto_install)?;
+        if self.summary {
+            let summary = self.run_combinations()?;
+            println!("{summary}");
+            summary.save(&self.summary_file)?;
+            return summary.check();
+        }
+        let mut failures = Vec::new();
+        for step in self.selected_steps() {
+            let command = self.command(step);
+            let result = runner::run(&command).with_context(|| {
+                format!(
+                    "Powerset {step} failed for {}: '{command}'",
+                    self.scope()
+                )
+            });
+            match result {
+                Err(err) if self.keep_going => failures.push(err),
+                result => {
+                    let _ = result?;
+                }
+            }
+        }
+        match failures.len() {
+            0 => Ok(()),
+            1 => Err(failures.swap_remove(0)),
+            count => {
+                let failures: Vec<String> = failures
+                    .iter()
+                    .map(|err| format!("{err:#}"))
+                    .collect();
+                Err(AnyError::msg(format!(
+                    "{count} powerset steps failed:\n{}",
+                    failures.join("\n")
+                )))
+            }
+        }
+    }
+
+    /// Runs the selected steps for each feature combination of the matrix,
+    /// printing the output of the failing ones, and returns their results.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the matrix cannot be computed or a command cannot be
+    /// spawned; failing steps are recorded in the summary instead.
+    pub fn run_combinations(&self) -> AnyResult<PowersetSummary> {
+        let steps = self.selected_steps();
+        let mut results = Vec::new();
+        for (package, combinations) in self.matrix()? {
+            for features in combinations {
+                let mut result = CombinationResult {
+                    package: package.clone(),
+                    features,
+                    ..CombinationResult::default()
+                };
+                for step in &steps {
+                    let command = self.combination_command(
+                        *step,
+                        &package,
+                        &result.features,
+                    );
+                    let out = runner::current()
+                        .execute(&command.clone().captured())
+                        .with_context(|| {
+                            format!("Failed to spawn '{command}'")
+                        })?;
+                    if out.status.success() {
+                        result.passed.push(*step);
+                    } else {
+                        eprintln!(
+                            "'{command}' failed:\n{}",
+                            output::combined(&out)
+                        );
+                        result.failed.push(*step);
+                    }
+                }
+                results.push(result);
+            }
+        }
+        Ok(PowersetSummary::new(steps, results))
+    }
+
+    /// Returns every feature named by the configuration.
+    fn named_features(&self) -> Vec<String> {
+        self.exclude_features
+            .iter()
+            .chain(&self.include_features)
+            .chain(self.mutually_exclusive.iter().flatten())
+            .cloned()
+            .collect()
+    }
+
+    /// Describes the feature combinations covered by the configuration, for
+    /// error messages.
+    fn scope(&self) -> String {
+        let mut combinations = format!(
+            "the combinations of up to {} features",
+            self.depth
+        );
+        if self.exclude_no_default_features {
+            combinations.push_str(", excluding --no-default-features");
+        }
+        combinations
+    }
+}
+
+impl Default for Powerset {
+    /// Returns the configuration built from the builder defaults, so both
+    /// describe the same run.
+    fn default() -> Self {
+        PowersetBuilder::default()
             .build()
-            .context("Failed to build Powerset configuration")?;
-        let depth = t.depth.to_string();
-        let mut common_args =
-            vec![
-                "--workspace",
-                "--exclude",
-                "xtask",
-                "--feature-powerset",
-                "--depth",
-                &depth,
-            ];
-        if t.exclude_no_default_features {
-            common_args.push("--exclude-no-default-features");
-        }
-
-        let mut clippy_args = common_args.clone();
-        clippy_args.extend(["--", "-D", "warnings"]);
-        std::process::Command::new("cargo")
-            .args(["hack", "clippy"])
-            .args(&clippy_args)
-            .status()
-            .context("Failed to execute 'cargo hack clippy'")?;
-
-        let mut test_args = common_args.clone();
-        test_args.push("test");
-        std::process::Command::new("cargo")
-            .args(["hack"])
-            .args(&test_args)
-            .status()
-            .context("Failed to execute 'cargo hack test'")?;
-
-        let mut doc_test_args = common_args;
-        doc_test_args.extend(["test", "--doc"]);
-        std::process::Command::new("cargo")
-            .args(["hack"])
-            .args(&doc_test_args)
-            .status()
-            .context("Failed to execute 'cargo hack test --doc'")?;
+            .expect("the builder defaults are a valid configuration")
+    }
+}
 
-        Ok(())
+impl PowersetBuilder {
+    /// Sets the depth of the powerset; see [`Powerset::depth`].
+    pub fn depth(&mut self, depth: u32) -> &mut Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Checks the configuration before it is built.
+    fn validate(&self) -> Result<(), String> {
+        match self.depth {
+            Some(0) => Err(
+                "Invalid powerset depth 0, expected an integer of at least 1"
+                    .to_string(),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Builds the configuration and runs a powerset test.
+    ///
+    /// This is a convenience for `self.build()?.run()`; see [`Powerset::run`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the configuration cannot be built or any of the
+    /// `cargo` commands fail to execute.
+    pub fn run(&self) -> AnyResult<()> {
+        self.build()
+            .context("Failed to build Powerset configuration")?
+            .run()
     }
 
     /// Creates a new `PowersetBuilder` instance with a specified depth.
@@ -110,7 +788,7 @@ impl PowersetBuilder {
     /// # use xtasks::tasks::powerset::PowersetBuilder;
     /// let builder = PowersetBuilder::new(3);
     /// ```
-    pub fn new(depth: i32) -> Self {
+    pub fn new(depth: u32) -> Self {
         let mut builder = Self::default();
         builder.depth(depth);
         builder
@@ -124,5 +802,72 @@ impl PowersetBuilder {
 /// Errors if one of the commands failed
 ///
 pub fn powerset() -> AnyResult<()> {
-    PowersetBuilder::default().run()
+    Powerset::default().run()
+}
+
+/// Returns the version printed by `cargo hack --version`, e.g. `cargo-hack
+/// 0.6.28`, as its major, minor and patch numbers.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::powerset::parse_cargo_hack_version;
+///
+/// assert_eq!(parse_cargo_hack_version("cargo-hack 0.6.28\n"), Some((0, 6, 28)));
+/// assert_eq!(parse_cargo_hack_version("unexpected"), None);
+/// ```
+pub fn parse_cargo_hack_version(
+    output: &str,
+) -> Option<(u64, u64, u64)> {
+    let version = output
+        .split_whitespace()
+        .skip_while(|word| *word != "cargo-hack")
+        .nth(1)?;
+    parse_version(version)
+}
+
+/// Parses a `major.minor.patch` version, ignoring any pre-release or build
+/// suffix.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.split(['-', '+']).next()?;
+    let mut numbers = core.split('.').map(|part| part.parse().ok());
+    let version =
+        (numbers.next()??, numbers.next()??, numbers.next()??);
+    numbers.next().is_none().then_some(version)
+}
+
+/// Makes sure cargo-hack is available, installing it if `auto_install` is set,
+/// and is at least [`REQUIRED_VERSION`].
+///
+/// A version that cannot be read from `cargo hack --version` is assumed to be
+/// recent enough, with a warning.
+///
+/// # Errors
+///
+/// Returns an error if cargo-hack is missing and may not be installed, if the
+/// installation fails, or if the installed cargo-hack is older than
+/// [`REQUIRED_VERSION`], naming the command that upgrades it.
+pub fn ensure_cargo_hack(auto_install: bool) -> AnyResult<()> {
+    let install = ["cargo-hack", "--locked"];
+    let _ = tools::ensure_tool("cargo-hack", &install, auto_install)?;
+    let version = CommandSpec::new("cargo").args(["hack", "--version"]);
+    let output = runner::query(&version)
+        .with_context(|| format!("Failed to execute '{version}'"))?;
+    let Some(found) = parse_cargo_hack_version(&output) else {
+        println!(
+            "Could not read the cargo-hack version from '{}', assuming it is at least {REQUIRED_VERSION}",
+            output.trim()
+        );
+        return Ok(());
+    };
+    let required = parse_version(REQUIRED_VERSION)
+        .expect("REQUIRED_VERSION is a valid version");
+    if found < required {
+        let upgrade = tools::install_command(&install).arg("--force");
+        return Err(AnyError::msg(format!(
+            "cargo-hack {}.{}.{} is older than the required {REQUIRED_VERSION}, upgrade it with '{upgrade}'",
+            found.0, found.1, found.2
+        )));
+    }
+    Ok(())
 }
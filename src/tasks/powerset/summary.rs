@@ -0,0 +1,175 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The results of a powerset run, one row per feature combination.
+//!
+//! A [`PowersetSummary`] is printed at the end of a run with
+//! [`summary`](super::Powerset::summary) set, and saved as JSON to
+//! [`DEFAULT_SUMMARY_FILE`] unless configured otherwise, for CI artifacts.
+
+use super::PowersetStep;
+use crate::ops;
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::Path};
+
+/// The file the summary is saved to unless configured otherwise.
+pub const DEFAULT_SUMMARY_FILE: &str = "target/xtask/powerset.json";
+
+/// The results of the steps run for a feature combination of a package.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct CombinationResult {
+    /// The package that was tested.
+    pub package: String,
+    /// The features enabled on top of `--no-default-features`.
+    pub features: Vec<String>,
+    /// The steps that succeeded.
+    pub passed: Vec<PowersetStep>,
+    /// The steps that failed.
+    pub failed: Vec<PowersetStep>,
+}
+
+impl CombinationResult {
+    /// Returns `true` if a step failed for the combination.
+    pub fn is_failure(&self) -> bool {
+        !self.failed.is_empty()
+    }
+
+    /// Returns the combination as passed to cargo, e.g. `app --features a,b`.
+    pub fn label(&self) -> String {
+        if self.features.is_empty() {
+            format!("{} --no-default-features", self.package)
+        } else {
+            format!(
+                "{} --features {}",
+                self.package,
+                self.features.join(",")
+            )
+        }
+    }
+
+    /// Returns how `step` went for the combination: `ok`, `FAILED`, or `-` if
+    /// it did not run.
+    fn status(&self, step: PowersetStep) -> &'static str {
+        if self.failed.contains(&step) {
+            "FAILED"
+        } else if self.passed.contains(&step) {
+            "ok"
+        } else {
+            "-"
+        }
+    }
+}
+
+/// The results of a powerset run, failures first.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct PowersetSummary {
+    /// The steps that were run, in order.
+    pub steps: Vec<PowersetStep>,
+    /// The result of each combination, the failing ones first.
+    pub results: Vec<CombinationResult>,
+}
+
+impl PowersetSummary {
+    /// Creates a summary of `results`, moving the failing combinations first and
+    /// keeping the order of the others.
+    pub fn new(
+        steps: Vec<PowersetStep>,
+        mut results: Vec<CombinationResult>,
+    ) -> Self {
+        results.sort_by_key(|result| !result.is_failure());
+        Self { steps, results }
+    }
+
+    /// Returns the combinations for which a step failed.
+    pub fn failures(&self) -> impl Iterator<Item = &CombinationResult> {
+        self.results.iter().filter(|result| result.is_failure())
+    }
+
+    /// Fails if a step failed for any combination.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error listing the failing combinations and their failing
+    /// steps.
+    pub fn check(&self) -> AnyResult<()> {
+        let failures: Vec<String> = self
+            .failures()
+            .map(|result| {
+                let steps: Vec<String> = result
+                    .failed
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                format!("{} ({})", result.label(), steps.join(", "))
+            })
+            .collect();
+        if failures.is_empty() {
+            return Ok(());
+        }
+        Err(AnyError::msg(format!(
+            "Powerset failed for {} of {} combinations: {}",
+            failures.len(),
+            self.results.len(),
+            failures.join("; ")
+        )))
+    }
+
+    /// Saves the summary as JSON to `path`, creating its directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> AnyResult<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(AnyError::new)?;
+        ops::write_atomic(path, json).with_context(|| {
+            format!("Failed to write {}", path.display())
+        })
+    }
+}
+
+impl fmt::Display for PowersetSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .results
+            .iter()
+            .map(|result| result.label().len())
+            .max()
+            .unwrap_or(0)
+            .max("combination".len());
+        let row = |label: &str, cells: Vec<String>| {
+            let mut row = format!("{label:<width$}");
+            for cell in cells {
+                row.push_str(&format!("  {cell:<8}"));
+            }
+            row.trim_end().to_string()
+        };
+        write!(
+            f,
+            "{}",
+            row(
+                "combination",
+                self.steps.iter().map(ToString::to_string).collect()
+            )
+        )?;
+        for result in &self.results {
+            write!(
+                f,
+                "\n{}",
+                row(
+                    &result.label(),
+                    self.steps
+                        .iter()
+                        .map(|step| result.status(*step).to_string())
+                        .collect()
+                )
+            )?;
+        }
+        Ok(())
+    }
+}
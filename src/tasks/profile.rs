@@ -0,0 +1,275 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `profile` task, which records a flamegraph of a binary, benchmark or
+//! test with `cargo flamegraph`.
+//!
+//! `cargo-flamegraph` is installed when missing. On Linux it samples with
+//! `perf`, which must be installed and allowed to sample by
+//! `kernel.perf_event_paranoid`; both are checked first, so the task fails
+//! with the fix rather than with the error of `perf`.
+
+use crate::{
+    ops::{
+        self,
+        error::XtaskError,
+        runner::{self, CommandSpec},
+        tools,
+    },
+    tasks::clean::CACHE_DIR,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// The file holding the perf sampling restrictions of the kernel.
+pub const PERF_PARANOID_FILE: &str =
+    "/proc/sys/kernel/perf_event_paranoid";
+
+/// The command lifting the perf sampling restrictions of the kernel.
+pub const PERF_SYSCTL_FIX: &str =
+    "sudo sysctl -w kernel.perf_event_paranoid=-1";
+
+/// The highest `kernel.perf_event_paranoid` level at which `perf` can
+/// sample the processes of the user.
+pub const MAX_PERF_PARANOID: i64 = 2;
+
+/// What to profile.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ProfileTarget {
+    /// A binary of the workspace.
+    Bin(String),
+    /// A benchmark, run with the `--bench` flag criterion expects.
+    Bench(String),
+    /// An integration test.
+    Test(String),
+}
+
+impl fmt::Display for ProfileTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bin(name) => write!(f, "binary {name}"),
+            Self::Bench(name) => write!(f, "benchmark {name}"),
+            Self::Test(name) => write!(f, "test {name}"),
+        }
+    }
+}
+
+/// The options of the `profile` task.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct FlamegraphOptions {
+    /// What to profile. By default, this is `None`, and the default binary
+    /// of the workspace is profiled.
+    #[builder(default)]
+    #[serde(default)]
+    pub target: Option<ProfileTarget>,
+
+    /// The arguments passed to the profiled program, e.g. a benchmark
+    /// filter.
+    ///
+    /// By default, this is set to an empty list.
+    #[builder(default)]
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// The SVG file the flamegraph is written to.
+    ///
+    /// By default, this is set to `target/xtask/flamegraph.svg`.
+    #[builder(default = "default_output()")]
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+
+    /// Opens the flamegraph in the browser once written. This is ignored
+    /// in CI.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub open: bool,
+
+    /// Installs `cargo-flamegraph` when it is missing.
+    ///
+    /// By default, this is set to `true`.
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub auto_install: bool,
+}
+
+/// Returns the flamegraph file when not configured.
+fn default_output() -> PathBuf {
+    Path::new(CACHE_DIR).join("flamegraph.svg")
+}
+
+/// Returns `true`, the default of [`FlamegraphOptions::auto_install`].
+const fn default_true() -> bool {
+    true
+}
+
+impl Default for FlamegraphOptions {
+    fn default() -> Self {
+        Self {
+            target: None,
+            args: Vec::new(),
+            output: default_output(),
+            open: false,
+            auto_install: true,
+        }
+    }
+}
+
+impl FlamegraphOptions {
+    /// Returns the `cargo flamegraph` command.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::profile::{FlamegraphOptionsBuilder, ProfileTarget};
+    ///
+    /// let opts = FlamegraphOptionsBuilder::default()
+    ///     .target(ProfileTarget::Bench("parse".to_string()))
+    ///     .args(vec!["small".to_string()])
+    ///     .output("out.svg")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     opts.command().to_string(),
+    ///     "cargo flamegraph --output out.svg --bench parse -- --bench small"
+    /// );
+    /// ```
+    pub fn command(&self) -> CommandSpec {
+        let mut command = CommandSpec::new("cargo").args([
+            "flamegraph".to_string(),
+            "--output".to_string(),
+            self.output.to_string_lossy().to_string(),
+        ]);
+        command = match &self.target {
+            Some(ProfileTarget::Bin(name)) => {
+                command.args(["--bin", name])
+            }
+            Some(ProfileTarget::Bench(name)) => {
+                command.args(["--bench", name])
+            }
+            Some(ProfileTarget::Test(name)) => {
+                command.args(["--test", name])
+            }
+            None => command,
+        };
+        // Without `--bench`, a criterion harness runs each benchmark once
+        // as a test, which leaves nothing to profile.
+        let bench =
+            matches!(self.target, Some(ProfileTarget::Bench(_)));
+        if bench || !self.args.is_empty() {
+            command = command.arg("--");
+        }
+        if bench {
+            command = command.arg("--bench");
+        }
+        command.args(&self.args)
+    }
+}
+
+/// Makes sure `perf` is installed and, when `paranoid` is known, that the
+/// `kernel.perf_event_paranoid` level lets it sample.
+///
+/// # Errors
+///
+/// Returns an error suggesting [`PERF_SYSCTL_FIX`] if `perf` is missing or
+/// may not sample.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use xtasks::ops::runner::{with_runner, RecordingRunner};
+/// use xtasks::tasks::profile::check_perf;
+///
+/// // A recording runner reports every tool as installed.
+/// let runner = Arc::new(RecordingRunner::default());
+/// with_runner(runner, || {
+///     assert!(check_perf(Some(1)).is_ok());
+///     assert!(check_perf(Some(4))
+///         .unwrap_err()
+///         .to_string()
+///         .starts_with("kernel.perf_event_paranoid is 4"));
+/// });
+/// ```
+pub fn check_perf(paranoid: Option<i64>) -> AnyResult<()> {
+    if !tools::is_installed("perf") {
+        return Err(AnyError::new(XtaskError::ToolMissing {
+            tool: "perf".to_string(),
+            install: "sudo apt-get install linux-tools-generic"
+                .to_string(),
+        })
+        .context(format!(
+            "cargo flamegraph samples with perf on Linux: install perf from the linux-tools package of the distribution, then let it sample with '{PERF_SYSCTL_FIX}'"
+        )));
+    }
+    match paranoid {
+        Some(level) if level > MAX_PERF_PARANOID => {
+            Err(AnyError::msg(format!(
+                "kernel.perf_event_paranoid is {level}, which keeps perf from sampling, lower it with '{PERF_SYSCTL_FIX}'"
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Returns the `kernel.perf_event_paranoid` level, if it can be read.
+fn perf_paranoid() -> Option<i64> {
+    fs::read_to_string(PERF_PARANOID_FILE)
+        .ok()
+        .and_then(|level| level.trim().parse().ok())
+}
+
+/// Records a flamegraph with `opts`, then opens it if `opts.open` is set.
+///
+/// # Returns
+///
+/// The path of the flamegraph.
+///
+/// # Errors
+///
+/// Returns an error if `cargo-flamegraph` is missing and may not be
+/// installed, if `perf` cannot sample on Linux, or if the profiled program
+/// fails.
+pub fn flamegraph(opts: FlamegraphOptions) -> AnyResult<PathBuf> {
+    let _ = tools::ensure_tool(
+        "cargo-flamegraph",
+        &["flamegraph"],
+        opts.auto_install,
+    )?;
+    if cfg!(target_os = "linux") {
+        check_perf(perf_paranoid())?;
+    }
+    if let Some(dir) = opts.output.parent() {
+        if !runner::is_dry_run() && !dir.as_os_str().is_empty() {
+            fs::create_dir_all(dir).with_context(|| {
+                XtaskError::Io(format!(
+                    "Failed to create {}",
+                    dir.display()
+                ))
+            })?;
+        }
+    }
+    let what = opts.target.as_ref().map_or_else(
+        || "the default binary".to_string(),
+        ToString::to_string,
+    );
+    let _ = runner::run(&opts.command())
+        .with_context(|| format!("Failed to profile {what}"))?;
+    println!(
+        "Wrote the flamegraph of {what} to {}",
+        opts.output.display()
+    );
+    if opts.open {
+        let _ = ops::open_in_browser(&opts.output)?;
+    }
+    Ok(opts.output)
+}
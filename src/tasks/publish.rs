@@ -0,0 +1,450 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `publish` task, which publishes the crates of a workspace to crates.io
+//! in dependency order.
+//!
+//! The workspace members are sorted so that every crate comes after the
+//! members it depends on. Each crate whose version is not on the index yet is
+//! first verified with `cargo publish --dry-run`, then published, and the
+//! task waits for the new version to show up on the index before publishing
+//! the crates depending on it.
+
+use crate::{
+    ops::{
+        self,
+        error::XtaskError,
+        metadata::{self, WorkspaceMember},
+        runner::{self, CommandSpec, DuctRunner},
+    },
+    tasks::outdated::{sparse_index_path, SPARSE_INDEX_URL},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    sync::Arc,
+    time::Duration,
+};
+
+/// The options of the `publish` task.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct PublishOptions {
+    /// Only verifies the crates with `cargo publish --dry-run`, without
+    /// uploading them.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// The crates to publish, all the publishable members if empty.
+    ///
+    /// By default, this is set to an empty list.
+    #[builder(default)]
+    #[serde(default)]
+    pub only: Vec<String>,
+
+    /// The crates to leave out.
+    ///
+    /// By default, this is set to an empty list.
+    #[builder(default)]
+    #[serde(default)]
+    pub skip: Vec<String>,
+
+    /// The URL of the sparse index checked for the published versions.
+    ///
+    /// By default, this is set to [`SPARSE_INDEX_URL`].
+    #[builder(default = "SPARSE_INDEX_URL.to_string()")]
+    #[serde(default = "default_index_url")]
+    pub index_url: String,
+
+    /// How many times the index is checked for a just-published version
+    /// before giving up.
+    ///
+    /// By default, this is set to `30`.
+    #[builder(default = "30")]
+    #[serde(default = "default_wait_attempts")]
+    pub wait_attempts: u32,
+
+    /// The delay between two checks of the index.
+    ///
+    /// By default, this is set to 10 seconds.
+    #[builder(default = "Duration::from_secs(10)")]
+    #[serde(default = "default_wait_delay")]
+    pub wait_delay: Duration,
+}
+
+/// Returns the index checked when not configured.
+fn default_index_url() -> String {
+    SPARSE_INDEX_URL.to_string()
+}
+
+/// Returns the number of index checks when not configured.
+const fn default_wait_attempts() -> u32 {
+    30
+}
+
+/// Returns the delay between index checks when not configured.
+const fn default_wait_delay() -> Duration {
+    Duration::from_secs(10)
+}
+
+impl Default for PublishOptions {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            only: Vec::new(),
+            skip: Vec::new(),
+            index_url: default_index_url(),
+            wait_attempts: default_wait_attempts(),
+            wait_delay: default_wait_delay(),
+        }
+    }
+}
+
+/// What the task did with a crate.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum PublishStatus {
+    /// Left out by `--only`, `--skip` or `publish = false`.
+    Excluded,
+    /// Its version is already on the index.
+    AlreadyPublished,
+    /// Verified with `cargo publish --dry-run`, in a dry run.
+    Verified,
+    /// Published and visible on the index.
+    Published,
+}
+
+impl fmt::Display for PublishStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Excluded => "excluded",
+            Self::AlreadyPublished => "already published",
+            Self::Verified => "verified",
+            Self::Published => "published",
+        })
+    }
+}
+
+/// A crate of the publication and what was done with it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PublishEntry {
+    /// The name of the crate.
+    pub name: String,
+    /// The version of the crate.
+    pub version: String,
+    /// What the task did with it.
+    pub status: PublishStatus,
+}
+
+/// The crates of the workspace in publication order, and what was done with
+/// each of them.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct PublishReport {
+    /// The crates, in publication order.
+    pub entries: Vec<PublishEntry>,
+}
+
+impl fmt::Display for PublishReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = |header: &str, lengths: Vec<usize>| {
+            lengths.into_iter().fold(header.len(), usize::max)
+        };
+        let name = width(
+            "crate",
+            self.entries.iter().map(|e| e.name.len()).collect(),
+        );
+        let version = width(
+            "version",
+            self.entries.iter().map(|e| e.version.len()).collect(),
+        );
+        write!(
+            f,
+            "{:<name$}  {:<version$}  status",
+            "crate", "version"
+        )?;
+        for entry in &self.entries {
+            write!(
+                f,
+                "\n{:<name$}  {:<version$}  {}",
+                entry.name, entry.version, entry.status
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the workspace `members` sorted so that every member comes after
+/// the members it depends on, alphabetically among the members that are
+/// ready at the same time.
+///
+/// # Errors
+///
+/// Returns an error naming the members of a dependency cycle.
+pub fn publish_order(
+    members: &[WorkspaceMember],
+) -> AnyResult<Vec<WorkspaceMember>> {
+    let mut pending: BTreeMap<&str, &WorkspaceMember> = members
+        .iter()
+        .map(|member| (member.name.as_str(), member))
+        .collect();
+    let mut ordered = Vec::with_capacity(members.len());
+    while !pending.is_empty() {
+        let ready: Vec<&str> = pending
+            .iter()
+            .filter(|(_, member)| {
+                member.dependencies.iter().all(|dependency| {
+                    !pending.contains_key(&**dependency)
+                })
+            })
+            .map(|(name, _)| *name)
+            .collect();
+        if ready.is_empty() {
+            let cycle: Vec<&str> = pending.keys().copied().collect();
+            return Err(AnyError::msg(format!(
+                "The workspace members depend on one another in a cycle: {}",
+                cycle.join(", ")
+            )));
+        }
+        for name in ready {
+            if let Some(member) = pending.remove(name) {
+                ordered.push(member.clone());
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+/// Returns `true` if the sparse index `entry` of a crate lists `version`,
+/// yanked or not.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::publish::index_has_version;
+///
+/// let entry = "{\"name\":\"a\",\"vers\":\"0.1.0\",\"yanked\":true}\n";
+/// assert!(index_has_version(entry, "0.1.0"));
+/// assert!(!index_has_version(entry, "0.2.0"));
+/// ```
+pub fn index_has_version(entry: &str, version: &str) -> bool {
+    entry
+        .lines()
+        .filter_map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).ok()
+        })
+        .any(|release| release["vers"] == version)
+}
+
+/// Returns `true` if `version` of the crate `name` is on the sparse index at
+/// `index_url`, `false` if the crate or the version is not.
+///
+/// # Errors
+///
+/// Returns an error if the index cannot be reached.
+pub fn is_published(
+    index_url: &str,
+    name: &str,
+    version: &str,
+) -> AnyResult<bool> {
+    let url = format!(
+        "{}/{}",
+        index_url.trim_end_matches('/'),
+        sparse_index_path(name)
+    );
+    let output = runner::query(&CommandSpec::new("curl").args([
+        "--silent",
+        "--show-error",
+        "--location",
+        "--header",
+        "Cache-Control: no-cache",
+        "--write-out",
+        "\n%{http_code}",
+        &url,
+    ]))
+    .with_context(|| format!("Failed to fetch {url}"))?;
+    let (entry, status) =
+        output.trim_end().rsplit_once('\n').unwrap_or(("", &output));
+    match status.trim() {
+        "200" => Ok(index_has_version(entry, version)),
+        "404" | "410" => Ok(false),
+        status => Err(AnyError::msg(format!(
+            "Failed to fetch {url}: HTTP {status}"
+        ))),
+    }
+}
+
+/// Returns the `cargo publish` command of the crate `name`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::publish::command;
+///
+/// assert_eq!(
+///     command("app", true).to_string(),
+///     "cargo publish --dry-run -p app"
+/// );
+/// ```
+pub fn command(name: &str, dry_run: bool) -> CommandSpec {
+    let command = CommandSpec::new("cargo").arg("publish");
+    let command = if dry_run {
+        command.arg("--dry-run")
+    } else {
+        command
+    };
+    command.args(["-p", name])
+}
+
+/// Publishes the workspace `members` with `opts`, checking the index with
+/// `is_published(name, version)`.
+///
+/// The crates are verified with `cargo publish --dry-run` in [publication
+/// order](publish_order), then, unless `opts.dry_run` is set, published one
+/// by one, waiting until each version is on the index before the next crate.
+/// The crates whose version is already on the index are skipped.
+///
+/// # Errors
+///
+/// Returns an error if `--only` or `--skip` names an unknown crate, if the
+/// members depend on one another in a cycle, if a `cargo publish` fails, or
+/// if a published version does not show up on the index in time.
+pub fn publish_with<F>(
+    opts: &PublishOptions,
+    members: &[WorkspaceMember],
+    mut is_published: F,
+) -> AnyResult<PublishReport>
+where
+    F: FnMut(&str, &str) -> AnyResult<bool>,
+{
+    let names: BTreeSet<&str> =
+        members.iter().map(|member| member.name.as_str()).collect();
+    if let Some(unknown) = opts
+        .only
+        .iter()
+        .chain(&opts.skip)
+        .find(|name| !names.contains(name.as_str()))
+    {
+        return Err(AnyError::msg(format!(
+            "Unknown crate '{unknown}', expected one of the workspace members: {}",
+            names.into_iter().collect::<Vec<_>>().join(", ")
+        )));
+    }
+
+    let mut report = PublishReport::default();
+    let mut pending = Vec::new();
+    for member in publish_order(members)? {
+        let included = member.publishable
+            && (opts.only.is_empty()
+                || opts.only.contains(&member.name))
+            && !opts.skip.contains(&member.name);
+        let status = if !included {
+            Some(PublishStatus::Excluded)
+        } else if is_published(&member.name, &member.version)? {
+            Some(PublishStatus::AlreadyPublished)
+        } else {
+            pending.push(report.entries.len());
+            None
+        };
+        report.entries.push(PublishEntry {
+            name: member.name,
+            version: member.version,
+            status: status.unwrap_or(PublishStatus::Verified),
+        });
+    }
+
+    for &index in &pending {
+        let entry = &report.entries[index];
+        println!("Verifying {} {}", entry.name, entry.version);
+        verify(&entry.name).with_context(|| {
+            format!("Failed to verify {} {}", entry.name, entry.version)
+        })?;
+    }
+    if opts.dry_run {
+        return Ok(report);
+    }
+
+    for &index in &pending {
+        let entry = &mut report.entries[index];
+        println!("Publishing {} {}", entry.name, entry.version);
+        let _ = runner::run(&command(&entry.name, false)).map_err(
+            |err| {
+                AnyError::new(XtaskError::StepFailed {
+                    step: "publish".to_string(),
+                })
+                .context(format!(
+                    "Failed to publish {} {}: {err:#}",
+                    entry.name, entry.version
+                ))
+            },
+        )?;
+        if !runner::is_dry_run() {
+            ops::retry(opts.wait_attempts, opts.wait_delay, || {
+                if is_published(&entry.name, &entry.version)? {
+                    Ok(())
+                } else {
+                    Err(AnyError::msg(format!(
+                        "{} {} is not on the index yet",
+                        entry.name, entry.version
+                    )))
+                }
+            })
+            .with_context(|| {
+                format!(
+                    "{} {} was published but did not show up on the index",
+                    entry.name, entry.version
+                )
+            })?;
+        }
+        entry.status = PublishStatus::Published;
+    }
+    Ok(report)
+}
+
+/// Runs `cargo publish --dry-run` for the crate `name`, for real even in a
+/// dry run of the task, since it uploads nothing.
+fn verify(name: &str) -> AnyResult<()> {
+    let command = command(name, true);
+    let _ = if runner::is_dry_run() {
+        runner::with_runner(Arc::new(DuctRunner), || {
+            runner::run(&command)
+        })
+    } else {
+        runner::run(&command)
+    }?;
+    Ok(())
+}
+
+/// Publishes the crates of the current workspace with `opts`, see
+/// [`publish_with`], and prints a summary of what was done with each crate.
+///
+/// # Errors
+///
+/// See [`publish_with`].
+pub fn publish(opts: PublishOptions) -> AnyResult<()> {
+    let members = metadata::member_dependencies()?;
+    let report = publish_with(&opts, &members, |name, version| {
+        is_published(&opts.index_url, name, version)
+    })?;
+    println!("{report}");
+    Ok(())
+}
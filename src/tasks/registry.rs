@@ -0,0 +1,195 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Registration of custom tasks alongside the built-in ones.
+//!
+//! An [`XTasks`] collects the custom tasks of a project, each with its own
+//! clap [`Command`] and handler, and runs them with the dispatcher of the
+//! built-in tasks: the custom tasks appear in the help, the `list` task, the
+//! shell completions and the suggestions for unknown tasks, and run with the
+//! global `--dry-run`, `--quiet` and `--verbose` flags. A custom task declares
+//! its aliases on its [`Command`], e.g. with [`Command::visible_alias`]; an
+//! alias taken by another task is an error when the tasks are run.
+//!
+//! ```no_run
+//! use xtasks::tasks::XTasks;
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     XTasks::new()
+//!         .register("deploy", "Deploy the site", |_| {
+//!             println!("Deploying");
+//!             Ok(())
+//!         })
+//!         .with_builtin_tasks()
+//!         .run()
+//! }
+//! ```
+
+use anyhow::{Error as AnyError, Result as AnyResult};
+use clap::{
+    builder::{Str, StyledStr},
+    ArgMatches, Command,
+};
+use std::{collections::BTreeMap, env, fmt, sync::Arc};
+
+/// The handler of a custom task, called with the matches of its subcommand.
+pub type TaskHandler =
+    Arc<dyn Fn(&ArgMatches) -> AnyResult<()> + Send + Sync>;
+
+/// A custom task: its subcommand and its handler.
+#[derive(Clone)]
+pub struct CustomTask {
+    /// The subcommand of the task, with its name, description and arguments.
+    pub command: Command,
+    /// The handler of the task.
+    pub handler: TaskHandler,
+}
+
+impl fmt::Debug for CustomTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomTask")
+            .field("name", &self.command.get_name())
+            .finish_non_exhaustive()
+    }
+}
+
+/// The tasks run by the command-line interface: the registered custom tasks
+/// and, optionally, the built-in ones.
+#[derive(Clone, Debug, Default)]
+pub struct XTasks {
+    tasks: Vec<CustomTask>,
+    builtins: bool,
+}
+
+impl XTasks {
+    /// Returns a registry without any task.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the task `name`, described by `about`, which takes no
+    /// argument and runs `handler`.
+    ///
+    /// A task registered with the name of a built-in task replaces it.
+    pub fn register<F>(
+        self,
+        name: impl Into<Str>,
+        about: impl Into<StyledStr>,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(&ArgMatches) -> AnyResult<()> + Send + Sync + 'static,
+    {
+        self.register_command(Command::new(name).about(about), handler)
+    }
+
+    /// Registers the task run by the subcommand `command`, which declares its
+    /// name, description and arguments, and runs `handler` with its matches.
+    ///
+    /// A task registered with the name of a built-in or of an already
+    /// registered task replaces it.
+    pub fn register_command<F>(
+        self,
+        command: Command,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(&ArgMatches) -> AnyResult<()> + Send + Sync + 'static,
+    {
+        let mut registry = self;
+        registry.tasks.retain(|task| {
+            task.command.get_name() != command.get_name()
+        });
+        registry.tasks.push(CustomTask {
+            command,
+            handler: Arc::new(handler),
+        });
+        registry
+    }
+
+    /// Adds the built-in tasks, listed before the custom ones.
+    pub fn with_builtin_tasks(mut self) -> Self {
+        self.builtins = true;
+        self
+    }
+
+    /// Returns `true` if the built-in tasks are included.
+    pub fn has_builtin_tasks(&self) -> bool {
+        self.builtins
+    }
+
+    /// Returns the custom tasks, in registration order.
+    pub fn custom_tasks(&self) -> &[CustomTask] {
+        &self.tasks
+    }
+
+    /// Returns the custom task `name`, if one is registered.
+    pub fn custom_task(&self, name: &str) -> Option<&CustomTask> {
+        self.tasks
+            .iter()
+            .find(|task| task.command.get_name() == name)
+    }
+
+    /// Returns the command-line interface running the tasks: the global
+    /// flags, the built-in tasks if included, then the custom tasks.
+    pub fn command(&self) -> Command {
+        let builtins: Vec<Command> = if self.builtins {
+            super::build_cli()
+                .get_subcommands()
+                .filter(|task| {
+                    self.custom_task(task.get_name()).is_none()
+                })
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+        super::base_cli().subcommands(builtins).subcommands(
+            self.tasks.iter().map(|task| task.command.clone()),
+        )
+    }
+
+    /// Checks that no name or alias is shared by two tasks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first name or alias taken twice.
+    pub fn validate(&self) -> AnyResult<()> {
+        let cli = self.command();
+        let mut owners: BTreeMap<&str, &str> = BTreeMap::new();
+        for task in cli.get_subcommands() {
+            let name = task.get_name();
+            let aliases = task.get_all_aliases();
+            for alias in std::iter::once(name).chain(aliases) {
+                if let Some(owner) = owners.insert(alias, name) {
+                    return Err(AnyError::msg(format!(
+                        "The task '{name}' cannot be called '{alias}', which is already taken by the task '{owner}'"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the task named by the arguments of the process.
+    ///
+    /// # Errors
+    ///
+    /// See [`XTasks::run_with_args`].
+    pub fn run(&self) -> AnyResult<()> {
+        let args: Vec<String> = env::args().collect();
+        self.run_with_args(&args)
+    }
+
+    /// Runs the task named by `args`, whose first item is the name of the
+    /// program, like [`super::main_with_args`] does for the built-in tasks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if two tasks share a name or an alias, see
+    /// [`XTasks::validate`], or if the task is unknown or fails.
+    pub fn run_with_args(&self, args: &[String]) -> AnyResult<()> {
+        self.validate()?;
+        super::run_tasks(self, args)
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Releases of the workspace package: version bump, changelog, commit, tag and
+//! publication.
+//!
+//! A [`Release`] checks that the working tree is clean, runs the CI checks,
+//! bumps the version in `Cargo.toml`, moves the unreleased changes of the
+//! changelog under the new version, commits, and creates an annotated
+//! `vX.Y.Z` tag. Publishing to crates.io is opt-in. Nothing is pushed.
+//!
+//! In a [dry run](crate::ops::runner::is_dry_run), the commands and the file
+//! changes are printed instead, which makes the plan of the release.
+
+use crate::{
+    ops::{
+        git, manifest,
+        runner::{self, CommandSpec, DryRunRunner},
+        write_atomic,
+    },
+    tasks::{changelog::today, ci, semver::Bump},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// The changelog updated by a release unless configured otherwise.
+pub const DEFAULT_CHANGELOG: &str = "CHANGELOG.md";
+
+/// The version a release moves to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ReleaseVersion {
+    /// The current version bumped as a patch, minor or major release.
+    Bump(Bump),
+    /// An explicit version, e.g. `1.0.0-rc.1`.
+    Exact(String),
+}
+
+impl ReleaseVersion {
+    /// Returns the version released from `current`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `current` cannot be bumped, or if the explicit
+    /// version is invalid or equal to `current`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::release::ReleaseVersion;
+    ///
+    /// let minor: ReleaseVersion = "minor".parse().unwrap();
+    /// assert_eq!(minor.resolve("0.4.2").unwrap(), "0.5.0");
+    /// let exact: ReleaseVersion = "1.0.0".parse().unwrap();
+    /// assert!(exact.resolve("1.0.0").is_err());
+    /// ```
+    pub fn resolve(&self, current: &str) -> AnyResult<String> {
+        match self {
+            Self::Bump(bump) => bump.apply(current),
+            Self::Exact(version) if version == current => {
+                Err(AnyError::msg(format!(
+                    "The version is already {version}"
+                )))
+            }
+            Self::Exact(version) => {
+                let _ = crate::tasks::semver::parse_version(version)?;
+                Ok(version.clone())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ReleaseVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bump(bump) => write!(f, "{bump}"),
+            Self::Exact(version) => f.write_str(version),
+        }
+    }
+}
+
+impl FromStr for ReleaseVersion {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        if let Ok(bump) = value.parse::<Bump>() {
+            return Ok(Self::Bump(bump));
+        }
+        let value = value.strip_prefix('v').unwrap_or(value);
+        crate::tasks::semver::parse_version(value).map_err(|_| {
+            AnyError::msg(format!(
+                "Invalid release '{value}', expected patch, minor, major or X.Y.Z"
+            ))
+        })?;
+        Ok(Self::Exact(value.to_string()))
+    }
+}
+
+/// The configuration of a release.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct Release {
+    /// The version to release.
+    pub version: ReleaseVersion,
+
+    /// Runs the CI checks, on the workspace in the current directory, before
+    /// changing anything.
+    ///
+    /// By default, this is set to `true`.
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub ci: bool,
+
+    /// Moves the changes listed under the `Unreleased` heading of the
+    /// changelog under the new version. A missing changelog is skipped.
+    ///
+    /// By default, this is set to `true`.
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub changelog: bool,
+
+    /// Creates an annotated `vX.Y.Z` tag on the release commit.
+    ///
+    /// By default, this is set to `true`.
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub tag: bool,
+
+    /// Publishes the package to crates.io with `cargo publish`.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub publish: bool,
+
+    /// Prints the commands and the file changes of the release instead of
+    /// making them.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// The changelog, relative to the workspace root.
+    ///
+    /// By default, this is set to [`DEFAULT_CHANGELOG`].
+    #[builder(default = "PathBuf::from(DEFAULT_CHANGELOG)")]
+    #[serde(default = "default_changelog")]
+    pub changelog_file: PathBuf,
+}
+
+/// Returns `true`, the default of the release phases.
+const fn default_true() -> bool {
+    true
+}
+
+/// Returns the changelog when not configured.
+fn default_changelog() -> PathBuf {
+    PathBuf::from(DEFAULT_CHANGELOG)
+}
+
+impl Release {
+    /// Releases the workspace in the current directory.
+    ///
+    /// # Returns
+    ///
+    /// The released version.
+    ///
+    /// # Errors
+    ///
+    /// See [`Release::run_in`].
+    pub fn run(&self) -> AnyResult<String> {
+        self.run_in(Path::new("."))
+    }
+
+    /// Releases the package of the workspace in `root`.
+    ///
+    /// # Returns
+    ///
+    /// The released version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the working tree has uncommitted changes, if the CI
+    /// checks fail, if the version cannot be bumped, if the changelog has no
+    /// `Unreleased` heading, or if a git or cargo command fails.
+    pub fn run_in(&self, root: &Path) -> AnyResult<String> {
+        if self.dry_run && !runner::is_dry_run() {
+            return runner::with_runner(Arc::new(DryRunRunner), || {
+                self.run_in(root)
+            });
+        }
+        git::ensure_git_clean(root)?;
+        let manifest = root.join("Cargo.toml");
+        let current = manifest::package_version(&manifest)?;
+        let version = self.version.resolve(&current)?;
+        println!("Releasing {version} (from {current})");
+
+        if self.ci {
+            ci::ci().context(
+                "The CI checks failed, nothing was released",
+            )?;
+        }
+        manifest::set_package_version(&manifest, &version)?;
+        if root.join("Cargo.lock").is_file() {
+            run(CommandSpec::new("cargo").args([
+                "update",
+                "--workspace",
+                "--manifest-path",
+                &manifest.to_string_lossy(),
+            ]))?;
+        }
+        if self.changelog {
+            let path = root.join(&self.changelog_file);
+            if path.is_file() {
+                let contents =
+                    fs::read_to_string(&path).with_context(|| {
+                        format!("Failed to read {}", path.display())
+                    })?;
+                let updated = changelog_with_release(
+                    &contents,
+                    &version,
+                    &today(),
+                )
+                .with_context(|| {
+                    format!("Failed to update {}", path.display())
+                })?;
+                write_atomic(&path, updated)?;
+            } else {
+                println!(
+                    "No {}, skipping the changelog",
+                    self.changelog_file.display()
+                );
+            }
+        }
+
+        let message = format!("Release {version}");
+        run(git::git(
+            root,
+            ["commit", "--all", "--message", &message],
+        ))?;
+        if self.tag {
+            run(git::git(
+                root,
+                [
+                    "tag",
+                    "--annotate",
+                    &format!("v{version}"),
+                    "--message",
+                    &message,
+                ],
+            ))?;
+        }
+        if self.publish {
+            run(CommandSpec::new("cargo").args([
+                "publish",
+                "--manifest-path",
+                &manifest.to_string_lossy(),
+            ]))?;
+        }
+        println!(
+            "Released {version}, push it with 'git push --follow-tags'"
+        );
+        Ok(version)
+    }
+}
+
+/// Runs `command`, failing with its command line.
+fn run(command: CommandSpec) -> AnyResult<()> {
+    runner::run(&command)
+        .map(|_| ())
+        .with_context(|| format!("Failed to execute '{command}'"))
+}
+
+/// Returns the changelog `contents` with a `## [version] - date` heading
+/// inserted under the `Unreleased` heading, which moves the unreleased changes
+/// under the new version and leaves an empty `Unreleased` section.
+///
+/// # Errors
+///
+/// Returns an error if there is no `## [Unreleased]` or `## Unreleased`
+/// heading.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::release::changelog_with_release;
+///
+/// let changelog = "# Changelog\n\n## [Unreleased]\n\n- Added a task\n";
+/// assert_eq!(
+///     changelog_with_release(changelog, "0.2.0", "2024-01-31").unwrap(),
+///     "# Changelog\n\n## [Unreleased]\n\n## [0.2.0] - 2024-01-31\n\n- Added a task\n"
+/// );
+/// ```
+pub fn changelog_with_release(
+    contents: &str,
+    version: &str,
+    date: &str,
+) -> AnyResult<String> {
+    let mut lines: Vec<String> = contents
+        .split_inclusive('\n')
+        .map(ToString::to_string)
+        .collect();
+    let unreleased = lines
+        .iter()
+        .position(|line| {
+            matches!(
+                line.trim().to_ascii_lowercase().as_str(),
+                "## [unreleased]" | "## unreleased"
+            )
+        })
+        .ok_or_else(|| {
+            AnyError::msg(
+                "The changelog has no '## [Unreleased]' heading",
+            )
+        })?;
+    if !lines[unreleased].ends_with('\n') {
+        lines[unreleased].push('\n');
+    }
+    lines
+        .insert(unreleased + 1, format!("\n## [{version}] - {date}\n"));
+    Ok(lines.concat())
+}
+
+/// Releases the workspace in the current directory with the default phases:
+/// CI checks, version bump, changelog, commit and tag.
+///
+/// # Errors
+///
+/// See [`Release::run_in`].
+pub fn release(version: ReleaseVersion) -> AnyResult<String> {
+    ReleaseBuilder::default()
+        .version(version)
+        .build()
+        .context("Failed to build Release configuration")?
+        .run()
+}
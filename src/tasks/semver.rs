@@ -0,0 +1,263 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Semantic versioning checks against the last published release, backed by
+//! `cargo semver-checks`.
+
+use crate::ops::{
+    output,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// The kind of version bump a release makes.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum Bump {
+    /// A patch release, e.g. `1.2.3` to `1.2.4`.
+    Patch,
+    /// A minor release, e.g. `1.2.3` to `1.3.0`.
+    Minor,
+    /// A major release, e.g. `1.2.3` to `2.0.0`.
+    Major,
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Patch => "patch",
+            Self::Minor => "minor",
+            Self::Major => "major",
+        })
+    }
+}
+
+impl FromStr for Bump {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        match value {
+            "patch" => Ok(Self::Patch),
+            "minor" => Ok(Self::Minor),
+            "major" => Ok(Self::Major),
+            _ => Err(AnyError::msg(format!(
+                "Unknown version bump '{value}', expected patch, minor, or major"
+            ))),
+        }
+    }
+}
+
+impl Bump {
+    /// Returns `version` bumped to the next release of this kind. A
+    /// pre-release or build suffix is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `version` is not of the form `X.Y.Z`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::semver::Bump;
+    ///
+    /// assert_eq!(Bump::Minor.apply("1.2.3").unwrap(), "1.3.0");
+    /// assert_eq!(Bump::Major.apply("0.9.1-rc.1").unwrap(), "1.0.0");
+    /// ```
+    pub fn apply(self, version: &str) -> AnyResult<String> {
+        let (major, minor, patch) = parse_version(version)?;
+        Ok(match self {
+            Self::Patch => format!("{major}.{minor}.{}", patch + 1),
+            Self::Minor => format!("{major}.{}.0", minor + 1),
+            Self::Major => format!("{}.0.0", major + 1),
+        })
+    }
+}
+
+/// Parses the `X.Y.Z` part of a version, ignoring any pre-release or build
+/// suffix.
+///
+/// # Errors
+///
+/// Returns an error if `version` does not start with three numbers separated by
+/// dots.
+pub fn parse_version(version: &str) -> AnyResult<(u64, u64, u64)> {
+    let core =
+        version.trim().split(['-', '+']).next().unwrap_or_default();
+    let numbers: Vec<u64> = core
+        .split('.')
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| {
+            AnyError::msg(format!(
+                "Invalid version '{version}', expected X.Y.Z"
+            ))
+        })?;
+    match numbers[..] {
+        [major, minor, patch] => Ok((major, minor, patch)),
+        _ => Err(AnyError::msg(format!(
+            "Invalid version '{version}', expected X.Y.Z"
+        ))),
+    }
+}
+
+/// The outcome of `cargo semver-checks check-release`.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct SemverReport {
+    /// The number of failed checks that require a new major version.
+    pub major: usize,
+    /// The number of failed checks that require a new minor version.
+    pub minor: usize,
+    /// The identifiers of the failed checks, e.g. `function_missing`.
+    pub failures: Vec<String>,
+}
+
+impl SemverReport {
+    /// Returns `true` if no check failed.
+    pub const fn is_compatible(&self) -> bool {
+        self.major == 0 && self.minor == 0
+    }
+
+    /// Returns the smallest version bump that accommodates the detected changes.
+    pub const fn required_bump(&self) -> Bump {
+        if self.major > 0 {
+            Bump::Major
+        } else if self.minor > 0 {
+            Bump::Minor
+        } else {
+            Bump::Patch
+        }
+    }
+
+    /// Checks that a release with the given bump accommodates the detected changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the required bump if `bump` is too small, e.g. a
+    /// patch release containing breaking changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use xtasks::tasks::semver::{Bump, SemverReport};
+    ///
+    /// let report = SemverReport { major: 1, ..SemverReport::default() };
+    /// assert!(report.ensure_release_allowed(Bump::Patch).is_err());
+    /// assert!(report.ensure_release_allowed(Bump::Major).is_ok());
+    /// ```
+    pub fn ensure_release_allowed(&self, bump: Bump) -> AnyResult<()> {
+        let required = self.required_bump();
+        if bump >= required {
+            return Ok(());
+        }
+        Err(AnyError::msg(format!(
+            "Refusing a {bump} release: cargo semver-checks found changes requiring \
+             a {required} release ({} major, {} minor{})",
+            self.major,
+            self.minor,
+            if self.failures.is_empty() {
+                String::new()
+            } else {
+                format!("; {}", self.failures.join(", "))
+            }
+        )))
+    }
+}
+
+impl fmt::Display for SemverReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_compatible() {
+            return f.write_str("no semver update required");
+        }
+        write!(
+            f,
+            "semver requires a new {} version: {} major and {} minor checks failed",
+            self.required_bump(),
+            self.major,
+            self.minor
+        )
+    }
+}
+
+/// Parses the output of `cargo semver-checks check-release`.
+///
+/// The counts are summed over the `Summary` lines of every checked package, and
+/// the failed check identifiers are taken from the `--- failure <id>: ... ---`
+/// headings.
+pub fn parse_output(output: &str) -> SemverReport {
+    let mut report = SemverReport::default();
+    for line in output.lines().map(str::trim) {
+        if let Some(heading) = line.strip_prefix("--- failure ") {
+            if let Some((id, _)) = heading.split_once(':') {
+                report.failures.push(id.to_string());
+            }
+        } else if line.starts_with("Summary ") {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            for pair in words.windows(2) {
+                let count = pair[0].parse::<usize>();
+                match (count, pair[1]) {
+                    (Ok(count), "major") => report.major += count,
+                    (Ok(count), "minor") => report.minor += count,
+                    _ => {}
+                }
+            }
+        }
+    }
+    report
+}
+
+/// Returns the `cargo semver-checks check-release` invocation for a package.
+pub fn command(package: Option<&str>) -> CommandSpec {
+    let command = CommandSpec::new("cargo")
+        .args(["semver-checks", "check-release"]);
+    match package {
+        Some(package) => command.args(["--package", package]),
+        None => command,
+    }
+}
+
+/// Compares the public API of the workspace, or of `package`, against its last
+/// published release.
+///
+/// cargo-semver-checks is installed first when it is missing. Failed checks are
+/// reported in the returned [`SemverReport`] rather than as an error.
+///
+/// # Errors
+///
+/// Returns an error if cargo-semver-checks cannot be installed or run, or exits
+/// unsuccessfully without reporting any failed check.
+pub fn check(package: Option<&str>) -> AnyResult<SemverReport> {
+    tools::ensure_tool_installed(
+        "cargo-semver-checks",
+        &["cargo-semver-checks", "--locked"],
+    )?;
+    let command = command(package);
+    let out = runner::current()
+        .execute(&command.clone().captured())
+        .with_context(|| format!("Failed to spawn '{command}'"))?;
+    let text = output::combined(&out);
+    print!("{text}");
+
+    let report = parse_output(&text);
+    if !out.status.success() && report.is_compatible() {
+        return Err(AnyError::msg(format!(
+            "'{command}' exited with {}",
+            out.status
+        )));
+    }
+    Ok(report)
+}
@@ -0,0 +1,299 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Spell checking of source files and documentation, backed by the `typos` CLI.
+//!
+//! Besides the check run by the CI, the typos can be corrected in place with
+//! `--write-changes`, in which case the corrected files are read from the git
+//! status before and after the run.
+
+use crate::ops::{
+    git,
+    runner::{self, CommandSpec},
+    tools,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// A misspelling reported by `typos`.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct TypoFinding {
+    /// The file containing the typo.
+    pub path: PathBuf,
+    /// The 1-based line number of the typo.
+    pub line: usize,
+    /// The misspelled word.
+    pub found: String,
+    /// The suggested corrections, joined with ` or `, if `typos` has any.
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for TypoFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: `{}`",
+            self.path.display(),
+            self.line,
+            self.found
+        )?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " should be `{suggestion}`")?;
+        }
+        Ok(())
+    }
+}
+
+/// A single line of `typos --format json` output.
+#[derive(Deserialize)]
+struct Message {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    path: PathBuf,
+    #[serde(default)]
+    line_num: usize,
+    #[serde(default)]
+    typo: String,
+    #[serde(default)]
+    corrections: Vec<String>,
+}
+
+/// Parses the output of `typos --format json` into a list of findings.
+///
+/// Lines that are not JSON, and messages other than typos (such as skipped binary
+/// files), are ignored.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::typos::parse_output;
+///
+/// let output = r#"{"type":"typo","path":"README.md","line_num":3,"byte_offset":7,"typo":"teh","corrections":["the"]}"#;
+/// let findings = parse_output(output);
+/// assert_eq!(findings[0].to_string(), "README.md:3: `teh` should be `the`");
+/// ```
+pub fn parse_output(output: &str) -> Vec<TypoFinding> {
+    output
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Message>(line).ok())
+        .filter(|message| message.kind == "typo")
+        .map(|message| TypoFinding {
+            path: message.path,
+            line: message.line_num,
+            found: message.typo,
+            suggestion: (!message.corrections.is_empty())
+                .then(|| message.corrections.join(" or ")),
+        })
+        .collect()
+}
+
+/// Returns the `typos` invocation, optionally using a custom configuration file.
+pub fn command(config: Option<&Path>) -> CommandSpec {
+    let command = CommandSpec::new("typos").args(["--format", "json"]);
+    match config {
+        Some(config) => {
+            command.arg("--config").arg(config.to_string_lossy())
+        }
+        None => command,
+    }
+}
+
+/// The configuration files `typos` reads from the root of a workspace, in
+/// order of precedence.
+pub const CONFIG_FILES: [&str; 3] =
+    ["typos.toml", "_typos.toml", ".typos.toml"];
+
+/// Returns the `typos` configuration file of the workspace at `root`, if it
+/// has one of [`CONFIG_FILES`].
+pub fn config_file(root: &Path) -> Option<PathBuf> {
+    CONFIG_FILES
+        .iter()
+        .map(|name| root.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Returns the `typos` invocation checking `paths`, or correcting them in
+/// place if `fix` is set, with the `target` directory left out.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+/// use xtasks::tasks::typos::run_command;
+///
+/// assert_eq!(
+///     run_command(None, true, &[PathBuf::from("src")]).to_string(),
+///     "typos --format json --write-changes --exclude target/ src"
+/// );
+/// ```
+pub fn run_command(
+    config: Option<&Path>,
+    fix: bool,
+    paths: &[PathBuf],
+) -> CommandSpec {
+    let command = command(config);
+    let command = if fix {
+        command.arg("--write-changes")
+    } else {
+        command
+    };
+    command
+        .args(["--exclude", "target/"])
+        .args(paths.iter().map(|path| path.to_string_lossy()))
+}
+
+/// The outcome of a `typos` run. It displays as the list of corrected
+/// files, the findings being reported by [`ensure_no_typos`].
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct TypoReport {
+    /// The misspellings left, all of them in check mode, and those without
+    /// a single correction in fix mode.
+    pub findings: Vec<TypoFinding>,
+    /// The files corrected in fix mode, relative to the root of the
+    /// repository and sorted by path.
+    pub fixed: Vec<PathBuf>,
+}
+
+impl fmt::Display for TypoReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.fixed.is_empty() {
+            f.write_str("No files corrected")?;
+        } else {
+            write!(f, "Corrected {} files:", self.fixed.len())?;
+            for path in &self.fixed {
+                write!(f, "\n  {}", path.display())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turns a list of findings into a result.
+///
+/// # Errors
+///
+/// Returns an error listing every finding, one per line, if `findings` is not
+/// empty.
+pub fn ensure_no_typos(findings: &[TypoFinding]) -> AnyResult<()> {
+    if findings.is_empty() {
+        return Ok(());
+    }
+    let list: Vec<String> = findings
+        .iter()
+        .map(|finding| format!("  {finding}"))
+        .collect();
+    Err(AnyError::msg(format!(
+        "typos found {} misspelling{}:\n{}",
+        findings.len(),
+        if findings.len() == 1 { "" } else { "s" },
+        list.join("\n")
+    )))
+}
+
+/// Spell checks the workspace and returns the misspellings found.
+///
+/// The `typos` CLI is installed first when it is missing.
+///
+/// # Errors
+///
+/// Returns an error if `typos` cannot be installed or run, or exits unsuccessfully
+/// without reporting any typo.
+pub fn check(config: Option<&Path>) -> AnyResult<Vec<TypoFinding>> {
+    tools::ensure_tool_installed("typos", &["typos-cli", "--locked"])?;
+    findings_of(&command(config))
+}
+
+/// Runs the `typos` `command` and returns the misspellings it reports.
+fn findings_of(command: &CommandSpec) -> AnyResult<Vec<TypoFinding>> {
+    let out = runner::current()
+        .execute(&command.clone().captured())
+        .with_context(|| format!("Failed to spawn '{command}'"))?;
+    let findings = parse_output(&String::from_utf8_lossy(&out.stdout));
+    if !out.status.success() && findings.is_empty() {
+        return Err(AnyError::msg(format!(
+            "'{command}' exited with {}: {}",
+            out.status,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(findings)
+}
+
+/// Spell checks `paths` of the workspace at `root`, the whole workspace if
+/// empty, and corrects the misspellings in place if `fix` is set.
+///
+/// The configuration file is `config` if given, else the one of `root`, see
+/// [`config_file`]. The `typos` CLI is installed first when it is missing.
+///
+/// # Errors
+///
+/// Returns an error if `typos` cannot be installed or run, or exits
+/// unsuccessfully without reporting any typo. The misspellings found are
+/// reported, not returned as errors.
+pub fn run_in(
+    root: &Path,
+    config: Option<&Path>,
+    fix: bool,
+    paths: &[PathBuf],
+) -> AnyResult<TypoReport> {
+    tools::ensure_tool_installed("typos", &["typos-cli", "--locked"])?;
+    let config =
+        config.map(Path::to_path_buf).or_else(|| config_file(root));
+    let paths = if paths.is_empty() {
+        vec![root.to_path_buf()]
+    } else {
+        paths.to_vec()
+    };
+    let before = if fix {
+        git::Snapshot::take(root).ok()
+    } else {
+        None
+    };
+    let findings =
+        findings_of(&run_command(config.as_deref(), fix, &paths))?;
+    let fixed = before
+        .and_then(|before| {
+            git::Snapshot::take(root)
+                .ok()
+                .map(|after| after.changed_since(&before))
+        })
+        .unwrap_or_default();
+    Ok(TypoReport { findings, fixed })
+}
+
+/// Spell checks `paths` of the current workspace, see [`run_in`], and
+/// corrects the misspellings in place if `fix` is set.
+///
+/// # Errors
+///
+/// See [`run_in`].
+pub fn run(fix: bool, paths: &[PathBuf]) -> AnyResult<TypoReport> {
+    run_in(Path::new("."), None, fix, paths)
+}
+
+/// Spell checks the workspace with an optional custom configuration file.
+///
+/// # Errors
+///
+/// Returns an error if `typos` cannot be run or finds any misspelling.
+pub fn typos_with_config(config: Option<&Path>) -> AnyResult<()> {
+    ensure_no_typos(&check(config)?)
+}
+
+/// Spell checks the workspace with the `typos` CLI.
+///
+/// # Errors
+///
+/// Returns an error if `typos` cannot be run or finds any misspelling.
+pub fn typos() -> AnyResult<()> {
+    typos_with_config(None)
+}
@@ -0,0 +1,323 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `update` task, which updates the locked dependencies and optionally
+//! tests the workspace with them, like a local dependabot.
+//!
+//! `Cargo.lock` is backed up before `cargo update`, and the versions locked
+//! before and after are compared to show what changed. With `--test`, the CI
+//! checks run next, and the lockfile is rolled back if they fail, unless the
+//! update is kept with `--keep`.
+
+use crate::ops::{
+    backup_file,
+    error::XtaskError,
+    runner::{self, CommandSpec},
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+};
+
+/// The options of the `update` task.
+#[derive(
+    Builder,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+#[builder(setter(into))]
+pub struct UpdateOptions {
+    /// The dependency to update. By default, this is `None`, and every
+    /// dependency is updated.
+    #[builder(default)]
+    #[serde(default)]
+    pub package: Option<String>,
+
+    /// The exact version [`package`](Self::package) is updated to. By
+    /// default, this is `None`, and the latest compatible version is used.
+    #[builder(default)]
+    #[serde(default)]
+    pub precise: Option<String>,
+
+    /// Runs the CI checks with the updated dependencies, rolling
+    /// `Cargo.lock` back if they fail.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub test: bool,
+
+    /// Keeps the updated `Cargo.lock` even if the CI checks fail.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub keep: bool,
+}
+
+impl UpdateOptions {
+    /// Returns the `cargo update` command of the workspace in `dir`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use xtasks::tasks::update::UpdateOptionsBuilder;
+    ///
+    /// let opts = UpdateOptionsBuilder::default()
+    ///     .package("serde".to_string())
+    ///     .precise("1.0.200".to_string())
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     opts.command(Path::new(".")).to_string(),
+    ///     "cargo update --manifest-path ./Cargo.toml -p serde --precise 1.0.200"
+    /// );
+    /// ```
+    pub fn command(&self, dir: &Path) -> CommandSpec {
+        let mut command = CommandSpec::new("cargo").args([
+            "update".to_string(),
+            "--manifest-path".to_string(),
+            dir.join("Cargo.toml").to_string_lossy().to_string(),
+        ]);
+        if let Some(package) = &self.package {
+            command = command.args(["-p", package]);
+        }
+        if let Some(precise) = &self.precise {
+            command = command.args(["--precise", precise]);
+        }
+        command
+    }
+}
+
+/// A dependency whose locked version changed.
+#[derive(
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub struct LockfileChange {
+    /// The name of the crate.
+    pub name: String,
+    /// The version locked before, `None` for an added crate.
+    pub old: Option<String>,
+    /// The version locked after, `None` for a removed crate.
+    pub new: Option<String>,
+}
+
+/// The changes between two versions of `Cargo.lock`.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct LockfileDiff {
+    /// The changes, sorted by crate name.
+    pub changes: Vec<LockfileChange>,
+}
+
+impl LockfileDiff {
+    /// Returns `true` if no locked version changed.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl fmt::Display for LockfileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("Cargo.lock is up to date");
+        }
+        let version = |version: &Option<String>| {
+            version.clone().unwrap_or_else(|| "-".to_string())
+        };
+        let name = self
+            .changes
+            .iter()
+            .map(|change| change.name.len())
+            .fold("crate".len(), usize::max);
+        let old = self
+            .changes
+            .iter()
+            .map(|change| version(&change.old).len())
+            .fold("old".len(), usize::max);
+        write!(f, "{:<name$}  {:<old$}   new", "crate", "old")?;
+        for change in &self.changes {
+            write!(
+                f,
+                "\n{:<name$}  {:<old$} \u{2192} {}",
+                change.name,
+                version(&change.old),
+                version(&change.new)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the versions of each package locked in the `Cargo.lock`
+/// `contents`.
+///
+/// # Errors
+///
+/// Returns an error if `contents` is not a valid lockfile.
+pub fn parse_locked_versions(
+    contents: &str,
+) -> AnyResult<BTreeMap<String, BTreeSet<String>>> {
+    let lockfile: toml::Table = contents.parse().map_err(|err| {
+        XtaskError::ConfigInvalid(format!("Invalid Cargo.lock: {err}"))
+    })?;
+    let mut versions: BTreeMap<String, BTreeSet<String>> =
+        BTreeMap::new();
+    for package in lockfile
+        .get("package")
+        .and_then(toml::Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+    {
+        let field = |key: &str| package.get(key)?.as_str();
+        if let (Some(name), Some(version)) =
+            (field("name"), field("version"))
+        {
+            let _ = versions
+                .entry(name.to_string())
+                .or_default()
+                .insert(version.to_string());
+        }
+    }
+    Ok(versions)
+}
+
+/// Returns the changes between the `before` and `after` contents of
+/// `Cargo.lock`.
+///
+/// When several versions of a crate are locked, the versions that went away
+/// are paired in order with the versions that appeared.
+///
+/// # Errors
+///
+/// Returns an error if either version is not a valid lockfile.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::update::diff_lockfiles;
+///
+/// let before = "[[package]]\nname = \"serde\"\nversion = \"1.0.193\"\n";
+/// let after = "[[package]]\nname = \"serde\"\nversion = \"1.0.200\"\n";
+/// assert_eq!(
+///     diff_lockfiles(before, after).unwrap().to_string(),
+///     "crate  old       new\nserde  1.0.193 \u{2192} 1.0.200"
+/// );
+/// ```
+pub fn diff_lockfiles(
+    before: &str,
+    after: &str,
+) -> AnyResult<LockfileDiff> {
+    let before = parse_locked_versions(before)?;
+    let after = parse_locked_versions(after)?;
+    let names: BTreeSet<&String> =
+        before.keys().chain(after.keys()).collect();
+    let empty = BTreeSet::new();
+    let mut changes = Vec::new();
+    for name in names {
+        let old = before.get(name).unwrap_or(&empty);
+        let new = after.get(name).unwrap_or(&empty);
+        let mut removed = old.difference(new);
+        let mut added = new.difference(old);
+        loop {
+            let change = LockfileChange {
+                name: name.clone(),
+                old: removed.next().cloned(),
+                new: added.next().cloned(),
+            };
+            if change.old.is_none() && change.new.is_none() {
+                break;
+            }
+            changes.push(change);
+        }
+    }
+    Ok(LockfileDiff { changes })
+}
+
+/// Updates the dependencies of the workspace in `dir` with `opts`, then,
+/// if `opts.test` is set, runs the CI checks with `ci`.
+///
+/// # Returns
+///
+/// The changes made to `Cargo.lock`, empty in a dry run.
+///
+/// # Errors
+///
+/// Returns an error if `--precise` is given without `--package`, if
+/// `cargo update` fails, or if the CI checks fail, in which case
+/// `Cargo.lock` is restored unless `opts.keep` is set.
+pub fn update_with<F>(
+    dir: &Path,
+    opts: &UpdateOptions,
+    ci: F,
+) -> AnyResult<LockfileDiff>
+where
+    F: FnOnce() -> AnyResult<()>,
+{
+    if opts.precise.is_some() && opts.package.is_none() {
+        return Err(AnyError::msg(
+            "--precise requires the dependency to update with --package",
+        ));
+    }
+    let command = opts.command(dir);
+    if runner::is_dry_run() {
+        let _ = runner::run(&command)?;
+        return Ok(LockfileDiff::default());
+    }
+
+    let lockfile = dir.join("Cargo.lock");
+    let before = fs::read_to_string(&lockfile).unwrap_or_default();
+    let backup = backup_file(&lockfile)?;
+    let _ = runner::run(&command)
+        .context("Failed to update the dependencies")?;
+    let after = fs::read_to_string(&lockfile).with_context(|| {
+        XtaskError::Io(format!("Failed to read {}", lockfile.display()))
+    })?;
+    let diff = diff_lockfiles(&before, &after)?;
+    println!("{diff}");
+    if !opts.test || diff.is_empty() {
+        backup.keep()?;
+        return Ok(diff);
+    }
+
+    println!("Running the CI checks with the updated dependencies");
+    match ci() {
+        Ok(()) => {
+            backup.keep()?;
+            Ok(diff)
+        }
+        Err(err) if opts.keep => {
+            backup.keep()?;
+            Err(err.context(
+                "The CI checks failed with the updated dependencies, Cargo.lock was kept as updated",
+            ))
+        }
+        Err(err) => {
+            backup.restore()?;
+            Err(err.context(
+                "The CI checks failed with the updated dependencies, Cargo.lock was rolled back",
+            ))
+        }
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The diagnostics report of the `vars` task: the workspace, the toolchain,
+//! the CI environment, the external tools and the relevant environment
+//! variables.
+//!
+//! The commands are run through the current [`runner`], so tests can feed
+//! canned versions to the report. A probe that fails does not fail the report:
+//! its value is [`UNKNOWN`].
+
+use crate::{
+    loggers::LOG_ENV,
+    ops::{
+        self, metadata,
+        runner::{self, CommandSpec},
+        tools,
+    },
+    tasks::ci::SKIP_MIRI_ENV,
+};
+use anyhow::{Error as AnyError, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, env, fmt, path::PathBuf};
+
+/// The value of a probe that failed.
+pub const UNKNOWN: &str = "unknown";
+
+/// The external tools of the tasks, with the arguments printing their version.
+pub const REPORTED_TOOLS: [(&str, &[&str]); 5] = [
+    ("cargo-watch", &["cargo", "watch", "--version"]),
+    ("cargo-tarpaulin", &["cargo", "tarpaulin", "--version"]),
+    ("cargo-bloat", &["cargo", "bloat", "--version"]),
+    ("cargo-hack", &["cargo", "hack", "--version"]),
+    ("grcov", &["grcov", "--version"]),
+];
+
+/// The environment variables reported when set.
+pub const REPORTED_ENV: [&str; 14] = [
+    "CARGO_HOME",
+    "CARGO_TARGET_DIR",
+    "CARGO_BUILD_TARGET",
+    "RUSTUP_HOME",
+    "RUSTUP_TOOLCHAIN",
+    "RUSTFLAGS",
+    "RUSTDOCFLAGS",
+    "CI",
+    "NO_COLOR",
+    LOG_ENV,
+    tools::NO_AUTO_INSTALL_ENV,
+    tools::FORCE_INSTALL_ENV,
+    ops::CONFIRM_ENV,
+    SKIP_MIRI_ENV,
+];
+
+/// The CI providers, with the environment variable each one sets.
+const CI_PROVIDERS: [(&str, &str); 7] = [
+    ("GITHUB_ACTIONS", "GitHub Actions"),
+    ("GITLAB_CI", "GitLab CI"),
+    ("CIRCLECI", "CircleCI"),
+    ("TRAVIS", "Travis CI"),
+    ("BUILDKITE", "Buildkite"),
+    ("TF_BUILD", "Azure Pipelines"),
+    ("JENKINS_URL", "Jenkins"),
+];
+
+/// A package of the workspace.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct PackageVersion {
+    /// The name of the package.
+    pub name: String,
+    /// The version of the package.
+    pub version: String,
+}
+
+/// An external tool of the tasks, and its version.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct ToolVersion {
+    /// The name of the binary, e.g. `cargo-hack`.
+    pub name: String,
+    /// Whether the binary is found in `PATH`.
+    pub installed: bool,
+    /// The version of the tool, or [`UNKNOWN`] if it is not installed or
+    /// its version cannot be read.
+    pub version: String,
+}
+
+/// The diagnostics report printed by the `vars` task.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub struct EnvReport {
+    /// The root directory of the workspace.
+    pub root: PathBuf,
+    /// The packages of the workspace, empty if `cargo metadata` fails.
+    pub packages: Vec<PackageVersion>,
+    /// The target triple of the host, e.g. `x86_64-unknown-linux-gnu`.
+    pub host: String,
+    /// The version line of `rustc`.
+    pub rustc: String,
+    /// The version line of `cargo`.
+    pub cargo: String,
+    /// The detected CI provider, `CI` for an unknown one, or `None` outside CI.
+    pub ci: Option<String>,
+    /// The external tools of the tasks.
+    pub tools: Vec<ToolVersion>,
+    /// The relevant environment variables that are set.
+    pub env: BTreeMap<String, String>,
+}
+
+/// Extracts the version number from the first line of `output`, e.g.
+/// `0.6.15` from `cargo-hack 0.6.15`.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::vars::parse_version;
+///
+/// assert_eq!(
+///     parse_version("cargo-tarpaulin version: 0.27.3\n").as_deref(),
+///     Some("0.27.3")
+/// );
+/// assert_eq!(parse_version("no version"), None);
+/// ```
+pub fn parse_version(output: &str) -> Option<String> {
+    output
+        .lines()
+        .next()?
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('v'))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .map(ToString::to_string)
+}
+
+/// Returns the output of the command `args`, or `None` if it fails.
+fn probe(args: &[&str]) -> Option<String> {
+    let (program, args) = args.split_first()?;
+    runner::query(
+        &CommandSpec::new(*program).args(args.iter().copied()),
+    )
+    .ok()
+}
+
+/// Returns the first line printed by `args`, or [`UNKNOWN`] if the command
+/// fails or prints nothing.
+fn version_line(args: &[&str]) -> String {
+    probe(args)
+        .and_then(|output| output.lines().next().map(str::to_string))
+        .filter(|line| !line.trim().is_empty())
+        .unwrap_or_else(|| UNKNOWN.to_string())
+}
+
+/// Returns the version of the tool `name`, probed with `args`.
+fn tool_version(name: &str, args: &[&str]) -> ToolVersion {
+    let installed = tools::is_installed(name);
+    let version = installed
+        .then(|| probe(args).and_then(|output| parse_version(&output)))
+        .flatten()
+        .unwrap_or_else(|| UNKNOWN.to_string());
+    ToolVersion {
+        name: name.to_string(),
+        installed,
+        version,
+    }
+}
+
+/// Returns the versions of the [`REPORTED_TOOLS`], in order.
+pub fn tool_versions() -> Vec<ToolVersion> {
+    REPORTED_TOOLS
+        .iter()
+        .map(|(name, args)| tool_version(name, args))
+        .collect()
+}
+
+/// Returns the CI provider of the environment, `CI` for an unknown one, or
+/// `None` outside CI.
+pub fn ci_provider() -> Option<String> {
+    CI_PROVIDERS
+        .iter()
+        .find(|(variable, _)| env::var_os(variable).is_some())
+        .map(|(_, provider)| (*provider).to_string())
+        .or_else(|| ops::is_ci().then(|| "CI".to_string()))
+}
+
+/// Returns the diagnostics report of the current workspace and environment.
+///
+/// Every probe that fails is reported as [`UNKNOWN`]; the root falls back to
+/// [`root_dir`](ops::root_dir) if `cargo metadata` fails.
+pub fn report() -> EnvReport {
+    let (root, packages) = metadata::workspace_packages()
+        .unwrap_or_else(|_| (ops::root_dir(), Vec::new()));
+    EnvReport {
+        root,
+        packages: packages
+            .into_iter()
+            .map(|(name, version)| PackageVersion { name, version })
+            .collect(),
+        host: tools::host_triple()
+            .unwrap_or_else(|_| UNKNOWN.to_string()),
+        rustc: version_line(&["rustc", "--version"]),
+        cargo: version_line(&["cargo", "--version"]),
+        ci: ci_provider(),
+        tools: tool_versions(),
+        env: REPORTED_ENV
+            .iter()
+            .filter_map(|name| {
+                env::var(name)
+                    .ok()
+                    .map(|value| ((*name).to_string(), value))
+            })
+            .collect(),
+    }
+}
+
+impl EnvReport {
+    /// Renders the report as pretty-printed JSON, for tooling.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the report cannot be serialized.
+    pub fn to_json(&self) -> AnyResult<String> {
+        serde_json::to_string_pretty(self).map_err(AnyError::new)
+    }
+}
+
+impl fmt::Display for EnvReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "root: {}", self.root.display())?;
+        writeln!(f, "packages:")?;
+        for package in &self.packages {
+            writeln!(f, "  {} {}", package.name, package.version)?;
+        }
+        writeln!(f, "host: {}", self.host)?;
+        writeln!(f, "rustc: {}", self.rustc)?;
+        writeln!(f, "cargo: {}", self.cargo)?;
+        writeln!(f, "ci: {}", self.ci.as_deref().unwrap_or("none"))?;
+        writeln!(f, "tools:")?;
+        let width = self
+            .tools
+            .iter()
+            .map(|tool| tool.name.len())
+            .max()
+            .unwrap_or(0);
+        for tool in &self.tools {
+            let version = if tool.installed {
+                tool.version.as_str()
+            } else {
+                "not installed"
+            };
+            writeln!(f, "  {:<width$}  {version}", tool.name)?;
+        }
+        write!(f, "env:")?;
+        for (name, value) in &self.env {
+            write!(f, "\n  {name}={value}")?;
+        }
+        Ok(())
+    }
+}
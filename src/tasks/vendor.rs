@@ -0,0 +1,180 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `vendor` task, which keeps the sources vendored with `cargo vendor` in
+//! sync with `Cargo.lock`, for offline builds.
+//!
+//! [`vendor`] vendors the dependencies and points `.cargo/config.toml` at
+//! them, leaving the file untouched when it already does. In check mode, the
+//! dependencies are vendored into the xtask cache instead and compared with
+//! the committed directory, so a forgotten re-vendor fails the check.
+
+use crate::{
+    ops::{
+        self,
+        error::XtaskError,
+        runner::{self, CommandSpec},
+        write_file_if_changed,
+    },
+    tasks::{
+        clean::CACHE_DIR,
+        init::{read_or_empty, with_key},
+    },
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use std::{
+    fs,
+    path::{Component, Path},
+};
+
+/// The directory the dependencies are vendored into by default.
+pub const DEFAULT_VENDOR_DIR: &str = "vendor";
+
+/// The name of the source replacing crates.io in `.cargo/config.toml`.
+pub const VENDORED_SOURCES: &str = "vendored-sources";
+
+/// Returns the `cargo vendor` command vendoring the dependencies of the
+/// workspace at `root` into `dir`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use xtasks::tasks::vendor::command;
+///
+/// assert_eq!(
+///     command(Path::new("app"), Path::new("vendor")).to_string(),
+///     "cargo vendor --manifest-path app/Cargo.toml vendor"
+/// );
+/// ```
+pub fn command(root: &Path, dir: &Path) -> CommandSpec {
+    CommandSpec::new("cargo").args([
+        "vendor".to_string(),
+        "--manifest-path".to_string(),
+        root.join("Cargo.toml").to_string_lossy().to_string(),
+        dir.to_string_lossy().to_string(),
+    ])
+}
+
+/// Returns the cargo configuration `contents` with crates.io replaced by the
+/// sources vendored in `dir`, unchanged if it already is.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::vendor::with_vendored_sources;
+///
+/// let config = with_vendored_sources("", "vendor");
+/// assert_eq!(
+///     config,
+///     "[source.crates-io]\nreplace-with = \"vendored-sources\"\n\n\
+///      [source.vendored-sources]\ndirectory = \"vendor\"\n"
+/// );
+/// assert_eq!(with_vendored_sources(&config, "vendor"), config);
+/// ```
+pub fn with_vendored_sources(contents: &str, dir: &str) -> String {
+    let contents = with_key(
+        contents,
+        "source.crates-io",
+        "replace-with",
+        &format!("\"{VENDORED_SOURCES}\""),
+    );
+    with_key(
+        &contents,
+        &format!("source.{VENDORED_SOURCES}"),
+        "directory",
+        &toml::Value::String(dir.to_string()).to_string(),
+    )
+}
+
+/// Returns the crates whose vendored sources differ between the directories
+/// `committed` and `fresh`, sorted by name.
+///
+/// # Errors
+///
+/// Returns an error if a directory cannot be read.
+pub fn differing_crates(
+    committed: &Path,
+    fresh: &Path,
+) -> AnyResult<Vec<String>> {
+    let mut crates: Vec<String> = ops::diff_dirs(committed, fresh)?
+        .iter()
+        .filter_map(|path| match path.components().next() {
+            Some(Component::Normal(name)) => {
+                Some(name.to_string_lossy().to_string())
+            }
+            _ => None,
+        })
+        .collect();
+    crates.dedup();
+    Ok(crates)
+}
+
+/// Vendors the dependencies of the workspace at `root` into `dir`, relative
+/// to `root`, or checks that `dir` is up to date if `check` is set.
+///
+/// # Errors
+///
+/// Returns an error if `cargo vendor` fails, if `.cargo/config.toml` cannot
+/// be updated, or in check mode, naming the crates that differ from the
+/// committed sources.
+pub fn vendor_in(
+    root: &Path,
+    dir: &Path,
+    check: bool,
+) -> AnyResult<()> {
+    if !check {
+        let _ = runner::run(&command(root, &root.join(dir)))?;
+        let config_path = root.join(".cargo").join("config.toml");
+        let config = with_vendored_sources(
+            &read_or_empty(&config_path)?,
+            &dir.to_string_lossy(),
+        );
+        if write_file_if_changed(&config_path, &config)?
+            && !runner::is_dry_run()
+        {
+            println!("Wrote {}", config_path.display());
+        }
+        return Ok(());
+    }
+
+    let fresh = root.join(CACHE_DIR).join("vendor");
+    if fresh.is_dir() && !runner::is_dry_run() {
+        fs::remove_dir_all(&fresh).with_context(|| {
+            XtaskError::Io(format!(
+                "Failed to remove {}",
+                fresh.display()
+            ))
+        })?;
+    }
+    let _ = runner::run(&command(root, &fresh))?;
+    if runner::is_dry_run() {
+        return Ok(());
+    }
+    let crates = differing_crates(&root.join(dir), &fresh)?;
+    let _ = fs::remove_dir_all(&fresh);
+    if crates.is_empty() {
+        println!("{} is up to date", dir.display());
+        return Ok(());
+    }
+    Err(AnyError::new(XtaskError::StepFailed {
+        step: "vendor".to_string(),
+    })
+    .context(format!(
+        "The sources vendored in {} are out of date for {} crates: {} (run `cargo xtask vendor`)",
+        dir.display(),
+        crates.len(),
+        crates.join(", ")
+    )))
+}
+
+/// Vendors the dependencies of the current workspace into `dir`, or checks
+/// that `dir` is up to date if `check` is set, see [`vendor_in`].
+///
+/// # Errors
+///
+/// Returns an error if vendoring fails or, in check mode, if the vendored
+/// sources are out of date.
+pub fn vendor(dir: &Path, check: bool) -> AnyResult<()> {
+    vendor_in(Path::new("."), dir, check)
+}
@@ -0,0 +1,386 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! The `wasm` task, which builds the crate for `wasm32-unknown-unknown` and
+//! post-processes the module.
+//!
+//! The target is added with rustup when it is missing, the crate is built
+//! with cargo, then the module goes through `wasm-bindgen` to generate the
+//! JavaScript bindings and `wasm-opt` to shrink it. Both tools are installed
+//! with `cargo install` when missing; `wasm-bindgen-cli` is pinned to the
+//! `wasm-bindgen` version of `Cargo.lock`, since the two must match.
+
+use crate::{
+    ops::{
+        self, metadata,
+        runner::{self, CommandSpec},
+        tools,
+    },
+    tasks::bloat::format_size,
+};
+use anyhow::{Context, Error as AnyError, Result as AnyResult};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// The target triple the crate is built for.
+pub const WASM_TARGET: &str = "wasm32-unknown-unknown";
+
+/// The optimization levels accepted by `wasm-opt`, passed as `-O<level>`.
+pub const OPT_LEVELS: [&str; 7] = ["0", "1", "2", "3", "4", "s", "z"];
+
+/// The kind of JavaScript bindings generated by `wasm-bindgen`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub enum BindgenTarget {
+    /// An ES module loaded by browsers.
+    #[default]
+    Web,
+    /// A CommonJS module for Node.js.
+    Nodejs,
+}
+
+impl fmt::Display for BindgenTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Web => "web",
+            Self::Nodejs => "nodejs",
+        })
+    }
+}
+
+impl FromStr for BindgenTarget {
+    type Err = AnyError;
+
+    fn from_str(value: &str) -> AnyResult<Self> {
+        match value {
+            "web" => Ok(Self::Web),
+            "nodejs" => Ok(Self::Nodejs),
+            _ => Err(AnyError::msg(format!(
+                "Unknown wasm-bindgen target '{value}', expected web or nodejs"
+            ))),
+        }
+    }
+}
+
+/// The options of the `wasm` task.
+#[derive(
+    Builder, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[builder(setter(into))]
+pub struct WasmOptions {
+    /// The package to build. By default, this is `None`, and the root
+    /// package of the workspace is built.
+    #[builder(default)]
+    #[serde(default)]
+    pub package: Option<String>,
+
+    /// Builds with the release profile.
+    ///
+    /// By default, this is set to `false`.
+    #[builder(default = "false")]
+    #[serde(default)]
+    pub release: bool,
+
+    /// The cargo target directory.
+    ///
+    /// By default, this is set to `target`.
+    #[builder(default = "PathBuf::from(\"target\")")]
+    #[serde(default = "default_target_dir")]
+    pub target_dir: PathBuf,
+
+    /// The directory `wasm-bindgen` writes the module and its bindings to.
+    ///
+    /// By default, this is set to `pkg`.
+    #[builder(default = "PathBuf::from(\"pkg\")")]
+    #[serde(default = "default_out_dir")]
+    pub out_dir: PathBuf,
+
+    /// Runs `wasm-bindgen` on the module.
+    ///
+    /// By default, this is set to `true`.
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub bindgen: bool,
+
+    /// The kind of bindings generated by `wasm-bindgen`.
+    ///
+    /// By default, this is set to [`BindgenTarget::Web`].
+    #[builder(default)]
+    #[serde(default)]
+    pub bindgen_target: BindgenTarget,
+
+    /// The level `wasm-opt` optimizes the module with, one of
+    /// [`OPT_LEVELS`]. By default, this is `None`, and `wasm-opt` is not
+    /// run.
+    #[builder(default)]
+    #[serde(default)]
+    pub opt_level: Option<String>,
+
+    /// Installs the target and the tools when they are missing.
+    ///
+    /// By default, this is set to `true`.
+    #[builder(default = "true")]
+    #[serde(default = "default_true")]
+    pub auto_install: bool,
+}
+
+/// Returns the target directory when not configured.
+fn default_target_dir() -> PathBuf {
+    PathBuf::from("target")
+}
+
+/// Returns the bindings directory when not configured.
+fn default_out_dir() -> PathBuf {
+    PathBuf::from("pkg")
+}
+
+/// Returns `true`, the default of the post-processing switches.
+const fn default_true() -> bool {
+    true
+}
+
+impl Default for WasmOptions {
+    fn default() -> Self {
+        Self {
+            package: None,
+            release: false,
+            target_dir: default_target_dir(),
+            out_dir: default_out_dir(),
+            bindgen: true,
+            bindgen_target: BindgenTarget::default(),
+            opt_level: None,
+            auto_install: true,
+        }
+    }
+}
+
+impl WasmOptions {
+    /// Returns the `cargo build` command building `package` for
+    /// [`WASM_TARGET`].
+    pub fn build_command(&self, package: &str) -> CommandSpec {
+        let mut command = CommandSpec::new("cargo").args([
+            "build",
+            "--target",
+            WASM_TARGET,
+            "-p",
+            package,
+            "--target-dir",
+        ]);
+        command = command.arg(self.target_dir.to_string_lossy());
+        if self.release {
+            command = command.arg("--release");
+        }
+        command
+    }
+
+    /// Returns the module built by cargo for `package`.
+    pub fn artifact(&self, package: &str) -> PathBuf {
+        self.target_dir
+            .join(WASM_TARGET)
+            .join(if self.release { "release" } else { "debug" })
+            .join(format!("{}.wasm", package.replace('-', "_")))
+    }
+
+    /// Returns the `wasm-bindgen` command processing `module`.
+    pub fn bindgen_command(&self, module: &Path) -> CommandSpec {
+        CommandSpec::new("wasm-bindgen").args([
+            "--target".to_string(),
+            self.bindgen_target.to_string(),
+            "--out-dir".to_string(),
+            self.out_dir.to_string_lossy().to_string(),
+            module.to_string_lossy().to_string(),
+        ])
+    }
+}
+
+/// Returns the `wasm-opt` command optimizing `module` in place at `level`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use xtasks::tasks::wasm::opt_command;
+///
+/// assert_eq!(
+///     opt_command(Path::new("app.wasm"), "z").to_string(),
+///     "wasm-opt -Oz app.wasm -o app.wasm"
+/// );
+/// ```
+pub fn opt_command(module: &Path, level: &str) -> CommandSpec {
+    let module = module.to_string_lossy().to_string();
+    CommandSpec::new("wasm-opt").args([
+        format!("-O{level}"),
+        module.clone(),
+        "-o".to_string(),
+        module,
+    ])
+}
+
+/// Returns the version of the package `name` locked in the `Cargo.lock`
+/// `contents`, the newest one if several are.
+///
+/// # Examples
+///
+/// ```
+/// use xtasks::tasks::wasm::locked_version;
+///
+/// let lockfile = "[[package]]\nname = \"wasm-bindgen\"\nversion = \"0.2.92\"\n";
+/// assert_eq!(
+///     locked_version(lockfile, "wasm-bindgen").as_deref(),
+///     Some("0.2.92")
+/// );
+/// assert_eq!(locked_version(lockfile, "serde"), None);
+/// ```
+pub fn locked_version(contents: &str, name: &str) -> Option<String> {
+    let lockfile: toml::Table = toml::from_str(contents).ok()?;
+    lockfile
+        .get("package")?
+        .as_array()?
+        .iter()
+        .filter(|package| {
+            package.get("name").and_then(toml::Value::as_str)
+                == Some(name)
+        })
+        .filter_map(|package| {
+            package.get("version").and_then(toml::Value::as_str)
+        })
+        .max_by_key(|version| {
+            crate::tasks::semver::parse_version(version).ok()
+        })
+        .map(ToString::to_string)
+}
+
+/// The size of the module before and after `wasm-opt`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+)]
+pub struct WasmSize {
+    /// The size in bytes before optimization.
+    pub before: u64,
+    /// The size in bytes after optimization, if `wasm-opt` ran.
+    pub after: Option<u64>,
+}
+
+impl fmt::Display for WasmSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.after {
+            None => f.write_str(&format_size(self.before)),
+            Some(after) => {
+                let delta = after as i128 - self.before as i128;
+                let percent = if self.before == 0 {
+                    0.0
+                } else {
+                    delta as f64 * 100.0 / self.before as f64
+                };
+                write!(
+                    f,
+                    "{} -> {} ({}{}, {percent:+.1}%)",
+                    format_size(self.before),
+                    format_size(after),
+                    if delta < 0 { "-" } else { "+" },
+                    format_size(delta.unsigned_abs() as u64)
+                )
+            }
+        }
+    }
+}
+
+/// Builds the crate for [`WASM_TARGET`] and post-processes the module with
+/// `opts`, printing its size before and after optimization.
+///
+/// # Returns
+///
+/// The path of the final module.
+///
+/// # Errors
+///
+/// Returns an error if the target or a tool is missing and may not be
+/// installed, if a command fails, or if the module cannot be found.
+pub fn build(opts: WasmOptions) -> AnyResult<PathBuf> {
+    if let Some(level) = &opts.opt_level {
+        if !OPT_LEVELS.contains(&level.as_str()) {
+            return Err(AnyError::msg(format!(
+                "Unknown wasm-opt level '{level}', expected one of: {}",
+                OPT_LEVELS.join(", ")
+            )));
+        }
+    }
+    let package = match &opts.package {
+        Some(package) => package.clone(),
+        None => metadata::root_package()?,
+    };
+    let _ = tools::ensure_target(WASM_TARGET, opts.auto_install)?;
+    let _ = runner::run(&opts.build_command(&package))
+        .context("Failed to build the wasm module")?;
+
+    let mut module = opts.artifact(&package);
+    if opts.bindgen {
+        let version = fs::read_to_string("Cargo.lock").ok().and_then(
+            |lockfile| locked_version(&lockfile, "wasm-bindgen"),
+        );
+        let mut install = vec!["wasm-bindgen-cli"];
+        if let Some(version) = &version {
+            install.extend(["--version", version]);
+        }
+        let _ = tools::ensure_tool(
+            "wasm-bindgen",
+            &install,
+            opts.auto_install,
+        )?;
+        let _ = runner::run(&opts.bindgen_command(&module))
+            .context("Failed to generate the wasm bindings")?;
+        module = opts
+            .out_dir
+            .join(format!("{}_bg.wasm", package.replace('-', "_")));
+    }
+    // In a dry run, nothing was built to measure.
+    let measured = !runner::is_dry_run();
+    let before = if measured {
+        ops::file_size(&module)?
+    } else {
+        0
+    };
+    let mut after = None;
+    if let Some(level) = &opts.opt_level {
+        let _ = tools::ensure_tool(
+            "wasm-opt",
+            &["wasm-opt"],
+            opts.auto_install,
+        )?;
+        let _ = runner::run(&opt_command(&module, level))
+            .context("Failed to optimize the wasm module")?;
+        if measured {
+            after = Some(ops::file_size(&module)?);
+        }
+    }
+    if measured {
+        println!(
+            "{}: {}",
+            module.display(),
+            WasmSize { before, after }
+        );
+    }
+    Ok(module)
+}
@@ -0,0 +1,280 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::audit::{
+        audit, cvss_score, parse_audit, report, AuditOptionsBuilder,
+        AuditReport, FindingKind, Severity,
+    };
+
+    /// The output of `cargo audit --json` for a lock file with three
+    /// vulnerabilities, one of them without a CVSS vector, an unmaintained
+    /// crate and a yanked crate.
+    const FIXTURE: &str = r#"{
+  "database": {
+    "advisory-count": 585,
+    "last-commit": "8a5e4b6b1d8a5d1b3c0c1f8c4d2e4b6b1d8a5d1b",
+    "last-updated": "2023-11-02T10:15:31+01:00"
+  },
+  "lockfile": { "dependency-count": 214 },
+  "settings": {
+    "target_arch": null,
+    "target_os": null,
+    "severity": null,
+    "ignore": [],
+    "informational_warnings": ["unmaintained", "unsound", "notice"]
+  },
+  "vulnerabilities": {
+    "found": true,
+    "count": 3,
+    "list": [
+      {
+        "advisory": {
+          "id": "RUSTSEC-2023-0071",
+          "package": "rsa",
+          "title": "Marvin Attack: potential key recovery through timing sidechannels",
+          "date": "2023-11-22",
+          "cvss": "CVSS:3.1/AV:N/AC:H/PR:N/UI:N/S:U/C:H/I:N/A:N"
+        },
+        "versions": { "patched": [], "unaffected": [] },
+        "affected": null,
+        "package": { "name": "rsa", "version": "0.9.3" }
+      },
+      {
+        "advisory": {
+          "id": "RUSTSEC-2022-0093",
+          "package": "ed25519-dalek",
+          "title": "Double Public Key Signing Function Oracle Attack on `ed25519-dalek`",
+          "date": "2022-06-11",
+          "cvss": "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"
+        },
+        "versions": { "patched": [">=2"], "unaffected": [] },
+        "affected": null,
+        "package": { "name": "ed25519-dalek", "version": "1.0.1" }
+      },
+      {
+        "advisory": {
+          "id": "RUSTSEC-2020-0071",
+          "package": "time",
+          "title": "Potential segfault in the time crate",
+          "date": "2020-11-18",
+          "cvss": null
+        },
+        "versions": {
+          "patched": [">=0.2.23"],
+          "unaffected": ["=0.2.0", "=0.2.1"]
+        },
+        "affected": null,
+        "package": { "name": "time", "version": "0.1.45" }
+      }
+    ]
+  },
+  "warnings": {
+    "unmaintained": [
+      {
+        "kind": "unmaintained",
+        "package": { "name": "ansi_term", "version": "0.12.1" },
+        "advisory": {
+          "id": "RUSTSEC-2021-0139",
+          "package": "ansi_term",
+          "title": "ansi_term is Unmaintained",
+          "cvss": null
+        },
+        "affected": null,
+        "versions": { "patched": [], "unaffected": [] }
+      }
+    ],
+    "yanked": [
+      {
+        "kind": "yanked",
+        "package": { "name": "crossbeam-utils", "version": "0.8.7" },
+        "advisory": null,
+        "affected": null,
+        "versions": null
+      }
+    ]
+  }
+}"#;
+
+    /// Tests the CVSS base scores and their severities.
+    #[test]
+    fn test_cvss_score() {
+        let scores = [
+            ("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H", 9.8),
+            ("CVSS:3.1/AV:N/AC:H/PR:N/UI:N/S:U/C:H/I:N/A:N", 5.9),
+            ("CVSS:3.0/AV:L/AC:L/PR:L/UI:N/S:C/C:H/I:H/A:H", 8.8),
+            ("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N", 6.1),
+            ("CVSS:3.1/AV:P/AC:H/PR:H/UI:R/S:U/C:N/I:N/A:N", 0.0),
+        ];
+        for (vector, score) in scores {
+            assert_eq!(cvss_score(vector), Some(score), "{vector}");
+        }
+        assert_eq!(
+            cvss_score("CVSS:2.0/AV:N/AC:L/Au:N/C:P/I:P/A:P"),
+            None
+        );
+        assert_eq!(
+            cvss_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H"),
+            None
+        );
+        assert_eq!(Severity::from_score(9.8), Severity::Critical);
+        assert_eq!(Severity::from_score(5.9), Severity::Medium);
+        assert_eq!(Severity::from_score(0.0), Severity::None);
+        assert_eq!("HIGH".parse::<Severity>().unwrap(), Severity::High);
+        assert!("severe".parse::<Severity>().is_err());
+    }
+
+    /// Tests the parsing of the vulnerabilities and the warnings.
+    #[test]
+    fn test_parse_audit() {
+        let findings = parse_audit(FIXTURE).unwrap();
+        let summary: Vec<(&str, FindingKind, &str, Option<Severity>)> =
+            findings
+                .iter()
+                .map(|finding| {
+                    (
+                        finding.id.as_str(),
+                        finding.kind,
+                        finding.package.as_str(),
+                        finding.severity,
+                    )
+                })
+                .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    "RUSTSEC-2023-0071",
+                    FindingKind::Vulnerability,
+                    "rsa",
+                    Some(Severity::Medium)
+                ),
+                (
+                    "RUSTSEC-2022-0093",
+                    FindingKind::Vulnerability,
+                    "ed25519-dalek",
+                    Some(Severity::Critical)
+                ),
+                (
+                    "RUSTSEC-2020-0071",
+                    FindingKind::Vulnerability,
+                    "time",
+                    None
+                ),
+                (
+                    "RUSTSEC-2021-0139",
+                    FindingKind::Unmaintained,
+                    "ansi_term",
+                    None
+                ),
+                ("", FindingKind::Yanked, "crossbeam-utils", None),
+            ]
+        );
+        assert_eq!(findings[0].score, Some(5.9));
+        assert_eq!(findings[2].patched_versions, [">=0.2.23"]);
+        assert_eq!(findings[4].version, "0.8.7");
+        assert!(parse_audit("not json").is_err());
+    }
+
+    /// Tests that only the vulnerabilities at or above the threshold, or
+    /// without a score, fail the audit.
+    #[test]
+    fn test_severity_gating() {
+        let findings = parse_audit(FIXTURE).unwrap();
+        let gate = |severity: Severity, ignore: &[&str]| {
+            let opts = AuditOptionsBuilder::default()
+                .severity(severity)
+                .ignore(
+                    ignore
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>(),
+                )
+                .build()
+                .unwrap();
+            report(findings.clone(), &opts)
+        };
+        let failing = |report: &AuditReport| -> Vec<String> {
+            report
+                .failures()
+                .map(|finding| finding.id.clone())
+                .collect()
+        };
+
+        let low = gate(Severity::Low, &[]);
+        assert_eq!(
+            failing(&low),
+            [
+                "RUSTSEC-2023-0071",
+                "RUSTSEC-2022-0093",
+                "RUSTSEC-2020-0071"
+            ]
+        );
+        let high = gate(Severity::High, &[]);
+        assert_eq!(
+            failing(&high),
+            ["RUSTSEC-2022-0093", "RUSTSEC-2020-0071"]
+        );
+        assert_eq!(
+            high.check().unwrap_err().to_string(),
+            "cargo audit found 2 vulnerabilities at or above the high \
+             severity: RUSTSEC-2022-0093 (ed25519-dalek), \
+             RUSTSEC-2020-0071 (time)"
+        );
+
+        let ignored = gate(
+            Severity::High,
+            &["RUSTSEC-2022-0093", "RUSTSEC-2020-0071"],
+        );
+        assert!(ignored.check().is_ok());
+        assert_eq!(ignored.findings.len(), 3);
+        assert_eq!(ignored.ignored.len(), 2);
+        assert!(ignored.to_string().ends_with(
+            "Ignored: RUSTSEC-2022-0093, RUSTSEC-2020-0071"
+        ));
+    }
+
+    /// Tests the `cargo audit` invocation and the JSON report.
+    #[test]
+    fn test_audit_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("audit.json");
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo audit", FIXTURE),
+        );
+        let opts = AuditOptionsBuilder::default()
+            .severity(Severity::Critical)
+            .ignore(vec!["RUSTSEC-2022-0093".to_string()])
+            .json(Some(json.clone()))
+            .build()
+            .unwrap();
+        let error =
+            with_runner(recorder.clone(), || audit(opts)).unwrap_err();
+        assert!(error.to_string().contains("RUSTSEC-2020-0071 (time)"));
+        assert!(recorder
+            .command_lines()
+            .contains(&"cargo audit --json".to_string()));
+        let saved: AuditReport = serde_json::from_str(
+            &std::fs::read_to_string(&json).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(saved.threshold, Severity::Critical);
+        assert_eq!(saved.findings.len(), 4);
+        assert_eq!(saved.ignored[0].id, "RUSTSEC-2022-0093");
+
+        let passing = AuditOptionsBuilder::default()
+            .severity(Severity::Medium)
+            .ignore(vec!["RUSTSEC-2020-0071".to_string()])
+            .build()
+            .unwrap();
+        let error = with_runner(recorder, || audit(passing))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("RUSTSEC-2023-0071 (rsa)"));
+        assert!(!error.contains("RUSTSEC-2020-0071"));
+    }
+}
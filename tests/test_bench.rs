@@ -0,0 +1,163 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::bench::{
+        parse_criterion_output, BenchBuilder, BenchSummary,
+        ChangeVerdict,
+    };
+
+    /// The output of a first criterion run, without a baseline, including a
+    /// long id printed on its own line.
+    const FIRST_RUN: &str = "\
+Gnuplot not found, using plotters backend
+Benchmarking fib 20
+Benchmarking fib 20: Warming up for 3.0000 s
+Benchmarking fib 20: Collecting 100 samples in estimated 5.0797 s (192k iterations)
+Benchmarking fib 20: Analyzing
+fib 20                  time:   [26.029 µs 26.251 µs 26.505 µs]
+Found 11 outliers among 100 measurements (11.00%)
+  6 (6.00%) high mild
+  5 (5.00%) high severe
+
+parse/a_rather_long_benchmark_name
+                        time:   [1.2001 ms 1.2100 ms 1.2200 ms]
+";
+
+    /// The output of a run compared with a baseline, including a throughput
+    /// benchmark whose change spans several lines.
+    const COMPARED_RUN: &str = "\
+fib 20                  time:   [27.029 µs 27.951 µs 28.505 µs]
+                        change: [+4.1023% +6.4795% +8.9836%] (p = 0.00 < 0.05)
+                        Performance has regressed.
+Found 2 outliers among 100 measurements (2.00%)
+  2 (2.00%) high mild
+
+parse/a_rather_long_benchmark_name
+                        time:   [1.1001 ms 1.1100 ms 1.1200 ms]
+                        change: [-9.2000% -8.2645% -7.1000%] (p = 0.00 < 0.05)
+                        Performance has improved.
+
+checksum/4096           time:   [2.0 µs 2.1 µs 2.2 µs]
+                        thrpt:  [1.7 GiB/s 1.8 GiB/s 1.9 GiB/s]
+                 change:
+                        time:   [-1.0000% +0.5000% +2.0000%] (p = 0.52 > 0.05)
+                        thrpt:  [-1.9608% -0.4975% +1.0101%]
+                        No change in performance detected.
+";
+
+    /// Tests the parsing of a run without a baseline.
+    #[test]
+    fn test_parse_first_run() {
+        let results = parse_criterion_output(FIRST_RUN);
+        let names: Vec<&str> =
+            results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["fib 20", "parse/a_rather_long_benchmark_name"]
+        );
+        assert_eq!(results[0].time, "26.251 µs");
+        assert_eq!(results[1].time, "1.2100 ms");
+        assert!(results.iter().all(|result| result.change.is_none()));
+    }
+
+    /// Tests the parsing of the changes and verdicts against a baseline.
+    #[test]
+    fn test_parse_compared_run() {
+        let results = parse_criterion_output(COMPARED_RUN);
+        assert_eq!(results.len(), 3);
+        let changes: Vec<(f64, ChangeVerdict)> = results
+            .iter()
+            .map(|result| {
+                let change = result.change.as_ref().unwrap();
+                (change.estimate, change.verdict)
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (6.4795, ChangeVerdict::Regressed),
+                (-8.2645, ChangeVerdict::Improved),
+                (0.5, ChangeVerdict::NoChange)
+            ]
+        );
+        let first = results[0].change.as_ref().unwrap();
+        assert_eq!((first.lower, first.upper), (4.1023, 8.9836));
+        assert_eq!(results[2].name, "checksum/4096");
+    }
+
+    /// Tests the regression threshold and the summary table.
+    #[test]
+    fn test_regression_threshold() {
+        let summary = BenchSummary {
+            results: parse_criterion_output(COMPARED_RUN),
+        };
+        assert!(summary.check(10.0).is_ok());
+        assert!(summary.check(6.5).is_ok());
+        let error = summary.check(5.0).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "1 of 3 benchmarks regressed by more than 5%: fib 20 (+6.48%)"
+        );
+        assert!(summary.check(-10.0).is_err());
+        assert_eq!(
+            summary.to_string(),
+            "\
+benchmark                           time       change     verdict
+fib 20                              27.951 µs  +6.48%     regressed
+parse/a_rather_long_benchmark_name  1.1100 ms  -8.26%     improved
+checksum/4096                       2.1 µs     +0.50%     no change"
+        );
+    }
+
+    /// Tests the `cargo bench` invocation, the JSON results and the failure
+    /// of a run that regressed.
+    #[test]
+    fn test_bench_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let json = dir.path().join("bench.json");
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo bench", COMPARED_RUN),
+        );
+        let bench = BenchBuilder::default()
+            .benches(vec!["parsing".to_string()])
+            .baseline(Some("main".to_string()))
+            .max_regression(Some(5.0))
+            .json(Some(json.clone()))
+            .build()
+            .unwrap();
+        let error =
+            with_runner(recorder.clone(), || bench.run()).unwrap_err();
+        assert!(error.to_string().contains("fib 20"));
+        assert_eq!(
+            recorder.command_lines(),
+            ["cargo bench --bench parsing -- --baseline main"]
+        );
+        let saved: BenchSummary = serde_json::from_str(
+            &std::fs::read_to_string(&json).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(saved.results.len(), 3);
+
+        let save = BenchBuilder::default()
+            .save_baseline(Some("main".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            save.command().to_string(),
+            "cargo bench -- --save-baseline main"
+        );
+        assert_eq!(
+            BenchBuilder::default()
+                .build()
+                .unwrap()
+                .command()
+                .to_string(),
+            "cargo bench"
+        );
+    }
+}
@@ -5,12 +5,23 @@
 mod tests {
     use xtasks::tasks::bloat::deps;
 
-    /// Tests the `deps` function with a valid package name.
+    /// Tests the `deps` function with a workspace package.
     /// This test expects the function to complete successfully.
     #[test]
-    fn test_deps_with_real_command() {
-        let package = "clap";
-        let result = deps(package);
+    fn test_deps_with_workspace_package() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":100,"text-section-size":50,"crates":[{"name":"std","size":40}]}"#,
+                ),
+        );
+        let package = "xtask";
+        let result = with_runner(recorder, || deps(package));
         assert!(result.is_ok(), "Expected Ok, got {result:?}");
     }
 
@@ -50,4 +61,1284 @@ mod tests {
         let result = deps(package);
         assert!(result.is_err(), "Expected Err, got {result:?}");
     }
+
+    /// The output of `cargo metadata --no-deps` for a workspace with an `xtask`
+    /// binary, and a `tools` package with two binaries and a library.
+    const METADATA: &str = r#"{
+        "packages": [
+            {
+                "name": "xtask",
+                "id": "xtask 0.1.0",
+                "targets": [{ "name": "xtask", "kind": ["bin"] }]
+            },
+            {
+                "name": "tools",
+                "id": "tools 0.1.0",
+                "targets": [
+                    { "name": "tools", "kind": ["lib"] },
+                    { "name": "fmt", "kind": ["bin"] },
+                    { "name": "lint", "kind": ["bin"] }
+                ]
+            }
+        ],
+        "workspace_members": ["xtask 0.1.0", "tools 0.1.0"]
+    }"#;
+
+    /// A report printed by `cargo bloat --crates`.
+    const TABLE: &str = "    Finished release [optimized] target(s) in 0.07s
+    Analyzing target/release/xtask
+
+ File  .text     Size Crate
+ 7.6%  29.5% 213.6KiB std
+ 5.2%  20.1% 145.5KiB clap
+ 0.9%   3.5%  25.3KiB xtasks
+ 0.0%   0.1%     512B [Unknown]
+ 2.2%   8.6%  62.4KiB And 45 more crates. Use -N to show more.
+26.0% 100.0% 723.8KiB .text section size, the file size is 2.7MiB
+
+Note: numbers above are a result of guesswork. They are not 100% correct and never will be.
+";
+
+    /// Tests that the crates table is parsed, without its summary rows.
+    #[test]
+    fn test_bloat_report_parse_table() {
+        use xtasks::tasks::bloat::{BloatEntry, BloatReport};
+
+        let report = BloatReport::parse(TABLE).unwrap();
+        assert_eq!(report.total_size, 2_831_155);
+        assert_eq!(report.entries.len(), 4);
+        assert_eq!(
+            report.entries[1],
+            BloatEntry {
+                name: "clap".to_string(),
+                size: 148_992,
+                percent: 5.2,
+            }
+        );
+        assert_eq!(report.entries[3].name, "[Unknown]");
+        assert_eq!(report.entries[3].size, 512);
+    }
+
+    /// Tests that the JSON report is parsed, with percentages of the file
+    /// size.
+    #[test]
+    fn test_bloat_report_parse_json() {
+        use xtasks::tasks::bloat::BloatReport;
+
+        let report = BloatReport::parse(
+            r#"{"file-size":4000,"text-section-size":2000,"crates":[{"name":"std","size":1000},{"name":"clap","size":500}]}"#,
+        )
+        .unwrap();
+        assert_eq!(report.total_size, 4000);
+        assert_eq!(report.entries[0].name, "std");
+        assert_eq!(report.entries[0].percent, 25.0);
+        assert_eq!(report.entries[1].size, 500);
+        assert_eq!(report.entries[1].percent, 12.5);
+    }
+
+    /// Tests that output which is not a report is rejected.
+    #[test]
+    fn test_bloat_report_parse_invalid() {
+        use xtasks::tasks::bloat::BloatReport;
+
+        assert!(BloatReport::parse("error: no such package").is_err());
+        assert!(BloatReport::parse("{\"crates\": 1}").is_err());
+    }
+
+    /// Tests that `deps_report` asks cargo-bloat for JSON and parses it.
+    #[test]
+    fn test_deps_report_mocked() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::deps_report;
+
+        let recorder = Arc::new(RecordingRunner::default()
+.with_stdout("cargo metadata", METADATA).with_stdout(
+            "cargo bloat",
+            r#"{"file-size":100,"text-section-size":50,"crates":[{"name":"std","size":40}]}"#,
+        ));
+        let report =
+            with_runner(recorder.clone(), || deps_report("xtask"))
+                .unwrap();
+        assert_eq!(
+            recorder.command_lines(),
+            [
+                "cargo metadata --format-version 1 --no-deps",
+                "cargo bloat -p xtask --crates --message-format json"
+            ]
+        );
+        assert_eq!(report.entries[0].percent, 40.0);
+        assert_eq!(
+            report.to_string(),
+            "  File      Size Crate\n 40.0%       40B std\n            100B Total"
+        );
+    }
+
+    /// Tests that the default configuration, the builder defaults and an
+    /// empty configuration file describe the same run.
+    #[test]
+    fn test_bloat_default() {
+        use xtasks::tasks::bloat::{Bloat, BloatBuilder};
+
+        let bloat = Bloat::default();
+        assert!(bloat.auto_install);
+        assert_eq!(bloat, BloatBuilder::default().build().unwrap());
+        assert_eq!(serde_json::from_str::<Bloat>("{}").unwrap(), bloat);
+    }
+
+    /// Tests that every option of the configuration maps to a flag.
+    #[test]
+    fn test_bloat_command_flags() {
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let bloat = BloatBuilder::default()
+            .package("xtask")
+            .top(Some(3))
+            .release(true)
+            .features(vec!["cli".to_string(), "json".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(
+            bloat.command().to_string(),
+            "cargo bloat -p xtask --crates --release -n 3 --features cli,json --message-format json"
+        );
+
+        let bloat = BloatBuilder::default()
+            .package("xtask")
+            .release(true)
+            .profile(Some("wasm-release".to_string()))
+            .target(Some("wasm32-unknown-unknown".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            bloat.command().to_string(),
+            "cargo bloat -p xtask --crates --profile wasm-release --target wasm32-unknown-unknown --message-format json"
+        );
+    }
+
+    /// Tests that a target missing from rustup is added before building, or
+    /// fails with the command adding it when installing is turned off, and that
+    /// an installed one is analysed and recorded in the report.
+    #[test]
+    fn test_bloat_target() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::{Bloat, BloatBuilder};
+
+        let bloat = BloatBuilder::default()
+            .package("xtask")
+            .release(true)
+            .target(Some("wasm32-unknown-unknown".to_string()))
+            .build()
+            .unwrap();
+
+        let recorder =
+            Arc::new(RecordingRunner::default().with_stdout(
+                "rustup target list",
+                "x86_64-unknown-linux-gnu\n",
+            ));
+        let manual = Bloat {
+            auto_install: false,
+            ..bloat.clone()
+        };
+        let err = with_runner(recorder.clone(), || manual.report())
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains(
+                "install it with 'rustup target add wasm32-unknown-unknown'"
+            ),
+            "{err:#}"
+        );
+        assert_eq!(
+            recorder.command_lines(),
+            ["rustup target list --installed"]
+        );
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout(
+                    "rustup target list",
+                    "x86_64-unknown-linux-gnu\n",
+                )
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":1000,"crates":[{"name":"std","size":500}]}"#,
+                ),
+        );
+        with_runner(recorder.clone(), || bloat.report()).unwrap();
+        assert_eq!(
+            recorder.command_lines()[..2],
+            [
+                "rustup target list --installed",
+                "rustup target add wasm32-unknown-unknown"
+            ]
+        );
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout(
+                    "rustup target list",
+                    "wasm32-unknown-unknown\nx86_64-unknown-linux-gnu\n",
+                )
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":1000,"crates":[{"name":"std","size":500}]}"#,
+                ),
+        );
+        let report = with_runner(recorder, || bloat.report()).unwrap();
+        assert_eq!(report.profile.as_deref(), Some("release"));
+        assert_eq!(
+            report.target.as_deref(),
+            Some("wasm32-unknown-unknown")
+        );
+    }
+
+    /// Tests that a mocked run writes its report to the JSON file.
+    #[test]
+    fn test_bloat_writes_json_report() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::{BloatBuilder, BloatReport};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("artifacts/bloat.json");
+        let recorder = Arc::new(RecordingRunner::default()
+.with_stdout("cargo metadata", METADATA).with_stdout(
+            "cargo bloat",
+            r#"{"file-size":200,"text-section-size":100,"crates":[{"name":"std","size":50}]}"#,
+        ));
+        let report = with_runner(recorder, || {
+            BloatBuilder::default()
+                .package("xtask")
+                .output_json(Some(path.clone()))
+                .run()
+        })
+        .unwrap();
+        let written: BloatReport = serde_json::from_str(
+            &std::fs::read_to_string(&path).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(written, report);
+        assert_eq!(written.entries[0].percent, 25.0);
+    }
+
+    /// Tests that failing to write the JSON file is reported as such, not as a
+    /// failed analysis.
+    #[test]
+    fn test_bloat_json_write_error_keeps_analysis() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let blocker = dir.path().join("file");
+        std::fs::write(&blocker, "").unwrap();
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":200,"crates":[]}"#,
+                ),
+        );
+        let err = with_runner(recorder, || {
+            BloatBuilder::default()
+                .package("xtask")
+                .output_json(Some(blocker.join("bloat.json")))
+                .run()
+        })
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with(
+                "The analysis of 'xtask' succeeded, but its report could not be written"
+            ),
+            "{err:#}"
+        );
+    }
+
+    /// Builds a report of a binary of `total_size` bytes with `crates`.
+    fn report(
+        total_size: u64,
+        crates: &[(&str, u64)],
+    ) -> xtasks::tasks::bloat::BloatReport {
+        xtasks::tasks::bloat::BloatReport {
+            total_size,
+            entries: crates
+                .iter()
+                .map(|(name, size)| xtasks::tasks::bloat::BloatEntry {
+                    name: (*name).to_string(),
+                    size: *size,
+                    percent: 0.0,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Tests that growth, shrinkage, and added and removed crates are listed,
+    /// the largest growth first.
+    #[test]
+    fn test_bloat_diff_deltas() {
+        use xtasks::tasks::bloat::{
+            bloat_diff, CrateDelta, DiffThreshold,
+        };
+
+        let diff = bloat_diff(
+            &report(
+                10_000,
+                &[("std", 4000), ("clap", 3000), ("log", 100)],
+            ),
+            &report(
+                12_000,
+                &[("std", 4000), ("clap", 2500), ("serde", 2600)],
+            ),
+            DiffThreshold::default(),
+        )
+        .unwrap();
+        assert_eq!(diff.delta(), 2000);
+        assert_eq!(diff.delta_percent(), 20.0);
+        assert_eq!(
+            diff.crates,
+            [
+                CrateDelta {
+                    name: "serde".to_string(),
+                    before: None,
+                    after: Some(2600),
+                },
+                CrateDelta {
+                    name: "log".to_string(),
+                    before: Some(100),
+                    after: None,
+                },
+                CrateDelta {
+                    name: "clap".to_string(),
+                    before: Some(3000),
+                    after: Some(2500),
+                },
+            ]
+        );
+        assert_eq!(diff.added().count(), 1);
+        assert_eq!(diff.removed().next().unwrap().name, "log");
+        // No threshold: any growth is allowed.
+        assert!(diff.check().is_ok());
+        assert_eq!(
+            diff.to_string(),
+            "size changed 9.8KiB → 11.7KiB (+2.0KiB, +20.0%)
+  crate     before      after      change
+  serde          -     2.5KiB     +2.5KiB  (added)
+  log         100B          -       -100B  (removed)
+  clap      2.9KiB     2.4KiB       -500B"
+        );
+    }
+
+    /// Tests the thresholds against growth and shrinkage.
+    #[test]
+    fn test_bloat_diff_thresholds() {
+        use xtasks::tasks::bloat::{bloat_diff, DiffThreshold};
+
+        let baseline = report(1_000_000, &[("std", 500_000)]);
+        let grown = report(1_060_000, &[("std", 560_000)]);
+        let shrunk = report(900_000, &[("std", 400_000)]);
+        let percent: DiffThreshold = "5%".parse().unwrap();
+        let bytes: DiffThreshold = "100KiB".parse().unwrap();
+
+        let diff = bloat_diff(&baseline, &grown, percent).unwrap();
+        assert!(diff.exceeded());
+        assert_eq!(
+            diff.check().unwrap_err().to_string(),
+            "The binary grew by +58.6KiB (+6.0%), more than the allowed 5%"
+        );
+        assert!(!bloat_diff(&baseline, &grown, bytes)
+            .unwrap()
+            .exceeded());
+        assert!(!bloat_diff(&baseline, &shrunk, percent)
+            .unwrap()
+            .exceeded());
+        assert!(!bloat_diff(&baseline, &baseline, bytes)
+            .unwrap()
+            .exceeded());
+        assert!("five percent".parse::<DiffThreshold>().is_err());
+    }
+
+    /// Tests the Markdown rendering of a report, with long names kept whole and
+    /// the entries past the top summed up.
+    #[test]
+    fn test_bloat_report_markdown() {
+        let report = xtasks::tasks::bloat::BloatReport {
+            total_size: 10_240,
+            entries: vec![
+                xtasks::tasks::bloat::BloatEntry {
+                    name: "a_crate_with_a_rather_long_name_indeed"
+                        .to_string(),
+                    size: 4096,
+                    percent: 40.0,
+                },
+                xtasks::tasks::bloat::BloatEntry {
+                    name: "std".to_string(),
+                    size: 2048,
+                    percent: 20.0,
+                },
+                xtasks::tasks::bloat::BloatEntry {
+                    name: "log".to_string(),
+                    size: 512,
+                    percent: 5.0,
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            report.to_markdown(2),
+            "| Crate | Size | File % |
+| --- | ---: | ---: |
+| a_crate_with_a_rather_long_name_indeed | 4.0KiB | 40.0% |
+| std | 2.0KiB | 20.0% |
+| *1 more* | 512B | 5.0% |
+| **Total** | **10.0KiB** | |"
+        );
+    }
+
+    /// Tests the Markdown rendering of a comparison with growth, shrinkage, and
+    /// an added and a removed crate.
+    #[test]
+    fn test_bloat_diff_markdown() {
+        use xtasks::tasks::bloat::{bloat_diff, DiffThreshold};
+
+        let diff = bloat_diff(
+            &report(
+                10_000,
+                &[("std", 4000), ("clap", 3000), ("log", 100)],
+            ),
+            &report(
+                12_000,
+                &[("std", 4000), ("clap", 2500), ("serde", 2600)],
+            ),
+            DiffThreshold::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            diff.to_markdown(),
+            "**Binary size:** 9.8KiB → 11.7KiB (⬆ +2.0KiB, +20.0%)
+
+| Crate | Before | After | Change |
+| --- | ---: | ---: | ---: |
+| serde (added) | - | 2.5KiB | ⬆ +2.5KiB |
+| log (removed) | 100B | - | ⬇ -100B |
+| clap | 2.9KiB | 2.4KiB | ⬇ -500B |"
+        );
+    }
+
+    /// Tests that a run writes the comparison and the report as Markdown, even
+    /// when the binary grew too much, and leaves an unchanged file alone.
+    #[test]
+    fn test_bloat_writes_markdown() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::{BloatBuilder, DiffThreshold};
+
+        let dir = tempfile::tempdir().unwrap();
+        let baseline = dir.path().join("bloat.json");
+        let markdown = dir.path().join("bloat.md");
+        report(1000, &[("std", 500)]).save(&baseline).unwrap();
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":2000,"crates":[{"name":"std","size":1500}]}"#,
+                ),
+        );
+        let err = with_runner(recorder, || {
+            BloatBuilder::default()
+                .package("xtask")
+                .baseline(Some(baseline.clone()))
+                .max_growth("10%".parse::<DiffThreshold>().unwrap())
+                .markdown(Some(markdown.clone()))
+                .run()
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("more than the allowed 10%"));
+        let written = std::fs::read_to_string(&markdown).unwrap();
+        assert!(
+            written.starts_with(
+                "**Binary size:** 1000B → 2.0KiB (⬆ +1000B, +100.0%)"
+            ),
+            "{written}"
+        );
+        assert!(written.ends_with("| **Total** | **2.0KiB** | |\n"));
+    }
+
+    /// Tests that reports of different profiles or targets are not compared.
+    #[test]
+    fn test_bloat_diff_mismatched_configs() {
+        use xtasks::tasks::bloat::{
+            bloat_diff, BloatReport, DiffThreshold,
+        };
+
+        let measured =
+            |profile: &str, target: Option<&str>| BloatReport {
+                profile: Some(profile.to_string()),
+                target: target.map(str::to_string),
+                ..report(1000, &[("std", 500)])
+            };
+        let host = measured("release", None);
+        let wasm = measured("release", Some("wasm32-unknown-unknown"));
+        let dev = measured("dev", None);
+
+        assert!(
+            bloat_diff(&host, &host, DiffThreshold::default()).is_ok()
+        );
+        assert_eq!(
+            bloat_diff(&host, &wasm, DiffThreshold::default())
+                .unwrap_err()
+                .to_string(),
+            "Cannot compare a report of the 'release' profile for wasm32-unknown-unknown \
+             to a baseline of the 'release' profile for the host, measure both with the \
+             same --profile and --target"
+        );
+        assert!(
+            bloat_diff(&dev, &host, DiffThreshold::default()).is_err()
+        );
+        // A report that does not say which profile it measured matches any.
+        assert!(bloat_diff(
+            &report(1000, &[]),
+            &dev,
+            DiffThreshold::default()
+        )
+        .is_ok());
+    }
+
+    /// Tests that a missing baseline is written instead of failing, and that the
+    /// next run is compared to it.
+    #[test]
+    fn test_bloat_baseline_flow() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::{
+            BloatBuilder, BloatReport, DiffThreshold,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let baseline = dir.path().join("target/xtask/bloat.json");
+        let run = |json: &str| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo metadata", METADATA)
+                    .with_stdout("cargo bloat", json),
+            );
+            with_runner(recorder, || {
+                BloatBuilder::default()
+                    .package("xtask")
+                    .baseline(Some(baseline.clone()))
+                    .max_growth("10%".parse::<DiffThreshold>().unwrap())
+                    .run()
+            })
+        };
+
+        let first = run(r#"{"file-size":1000,"crates":[{"name":"std","size":500}]}"#)
+            .unwrap();
+        assert_eq!(BloatReport::load(&baseline).unwrap(), first);
+        assert!(run(
+            r#"{"file-size":1050,"crates":[{"name":"std","size":550}]}"#
+        )
+        .is_ok());
+        let err =
+            run(r#"{"file-size":1200,"crates":[{"name":"std","size":700}]}"#)
+                .unwrap_err();
+        assert!(err.to_string().contains("more than the allowed 10%"));
+        // The baseline is only written when missing.
+        assert_eq!(BloatReport::load(&baseline).unwrap(), first);
+    }
+
+    /// Tests that the only binary of a workspace is analysed when no package is
+    /// given.
+    #[test]
+    fn test_bloat_detects_single_binary() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let metadata = r#"{
+            "packages": [{
+                "name": "app",
+                "id": "app 0.1.0",
+                "targets": [
+                    { "name": "app", "kind": ["lib"] },
+                    { "name": "app", "kind": ["bin"] }
+                ]
+            }],
+            "workspace_members": ["app 0.1.0"]
+        }"#;
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", metadata)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":100,"crates":[{"name":"std","size":40}]}"#,
+                ),
+        );
+        let report = with_runner(recorder.clone(), || {
+            BloatBuilder::default().run()
+        })
+        .unwrap();
+        assert_eq!(
+            recorder.command_lines()[1],
+            "cargo bloat -p app --bin app --crates --message-format json"
+        );
+        assert_eq!(report.total_size, 100);
+    }
+
+    /// Tests that every binary of the workspace is analysed and the reports are
+    /// combined.
+    #[test]
+    fn test_bloat_analyses_every_binary() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat -p xtask",
+                    r#"{"file-size":1000,"crates":[{"name":"std","size":300},{"name":"clap","size":200}]}"#,
+                )
+                .with_stdout(
+                    "cargo bloat -p tools",
+                    r#"{"file-size":500,"crates":[{"name":"std","size":150}]}"#,
+                ),
+        );
+        let (reports, combined) = with_runner(recorder.clone(), || {
+            let bloat = BloatBuilder::default().build().unwrap();
+            (bloat.reports().unwrap(), bloat.report().unwrap())
+        });
+        let labels: Vec<&str> =
+            reports.iter().map(|(label, _)| label.as_str()).collect();
+        assert_eq!(labels, ["xtask", "fmt (tools)", "lint (tools)"]);
+        assert_eq!(
+            recorder.command_lines()[1..4],
+            [
+                "cargo bloat -p xtask --bin xtask --crates --message-format json",
+                "cargo bloat -p tools --bin fmt --crates --message-format json",
+                "cargo bloat -p tools --bin lint --crates --message-format json",
+            ]
+        );
+        assert_eq!(combined.total_size, 2000);
+        assert_eq!(combined.entries[0].name, "std");
+        assert_eq!(combined.entries[0].size, 600);
+        assert_eq!(combined.entries[0].percent, 30.0);
+        assert_eq!(combined.entries[1].size, 200);
+    }
+
+    /// Tests that an unknown package is rejected with the list of members,
+    /// without running cargo-bloat.
+    #[test]
+    fn test_bloat_unknown_package() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA),
+        );
+        let err = with_runner(recorder.clone(), || {
+            BloatBuilder::default().package("nope").run()
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown package 'nope', expected one of the workspace members: xtask, tools"
+        );
+        assert_eq!(recorder.calls().len(), 1);
+    }
+
+    /// Tests that a missing cargo-bloat is installed before the analysis, or
+    /// named with its install command when installing is turned off.
+    #[test]
+    fn test_bloat_missing_cargo_bloat() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .missing("cargo-bloat"),
+        );
+        let err = with_runner(recorder.clone(), || {
+            BloatBuilder::default()
+                .package("xtask")
+                .auto_install(false)
+                .run()
+        })
+        .unwrap_err();
+        let message = format!("{err:#}");
+        assert!(
+            message.contains(
+                "install it with 'cargo install cargo-bloat'"
+            ),
+            "{message}"
+        );
+        assert!(message.contains("the `install` xtask"), "{message}");
+        assert!(!recorder
+            .command_lines()
+            .iter()
+            .any(|line| line.starts_with("cargo bloat")));
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":1000,"crates":[]}"#,
+                )
+                .missing("cargo-bloat"),
+        );
+        with_runner(recorder.clone(), || {
+            BloatBuilder::default().package("xtask").run()
+        })
+        .unwrap();
+        let lines = recorder.command_lines();
+        assert_eq!(lines[1], "cargo install cargo-bloat");
+        assert!(lines[2].starts_with("cargo bloat -p xtask"));
+    }
+
+    /// Tests that a package cargo-bloat cannot find is reported with the
+    /// workspace members, and that other failures keep cargo's message.
+    #[test]
+    fn test_bloat_failure_messages() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let run = |stderr: &str| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo metadata", METADATA)
+                    .failing("cargo bloat")
+                    .with_stderr("cargo bloat", stderr),
+            );
+            with_runner(recorder, || {
+                BloatBuilder::default().package("tools").run()
+            })
+            .unwrap_err()
+        };
+
+        assert_eq!(
+            run("error: package(s) `tools` not found in workspace `/ws`\n")
+                .to_string(),
+            "cargo bloat could not find the package of 'tools' (error: package(s) `tools` \
+             not found in workspace `/ws`), expected one of the workspace members: xtask, tools"
+        );
+        let err = run("error: linking with `cc` failed\n");
+        assert_eq!(
+            err.to_string(),
+            "Failed to execute 'cargo bloat' for dependency analysis on 'tools'"
+        );
+        assert!(
+            format!("{err:#}")
+                .ends_with("error: linking with `cc` failed"),
+            "{err:#}"
+        );
+    }
+
+    /// Tests that the analyses log their start and completion at INFO, and
+    /// their failures at ERROR.
+    #[test]
+    fn test_bloat_log_levels() {
+        use std::sync::Arc;
+        use xtasks::loggers::{capture_logs, LogLevel};
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::{deps, time};
+
+        let levels =
+            |logs: Vec<xtasks::loggers::Log>| -> Vec<LogLevel> {
+                logs.into_iter().map(|log| log.level).collect()
+            };
+        let succeeding = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout(
+                    "cargo bloat",
+                    r#"{"file-size":100,"crates":[]}"#,
+                ),
+        );
+        let failing = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .failing("cargo bloat"),
+        );
+
+        for analysis in [deps, time] {
+            let (result, logs) =
+                with_runner(succeeding.clone(), || {
+                    capture_logs(|| analysis("xtask"))
+                });
+            assert!(result.is_ok(), "{result:?}");
+            assert_eq!(levels(logs), [LogLevel::INFO, LogLevel::INFO]);
+
+            let (result, logs) = with_runner(failing.clone(), || {
+                capture_logs(|| analysis("xtask"))
+            });
+            assert!(result.is_err());
+            assert_eq!(levels(logs), [LogLevel::INFO, LogLevel::ERROR]);
+        }
+    }
+
+    /// A report printed by `cargo bloat -n 4`, listing functions.
+    const FUNCTIONS_TABLE: &str = "    Analyzing target/debug/xtask
+
+ File  .text     Size          Crate Name
+ 1.2%   4.6%  33.4KiB         xtasks xtasks::tasks::tasks
+ 0.6%   2.4%  17.1KiB clap_builder clap_builder::parser::parser::Parser::get_matches_with
+ 0.3%   1.2%   8.8KiB         xtasks <xtasks::tasks::bloat::Bloat as core::fmt::Debug>::fmt
+ 0.2%   0.9%   6.4KiB            std std::backtrace_rs::symbolize::gimli::resolve
+21.4%  80.1% 580.2KiB                And 14553 smaller methods. Use -n N to show more.
+26.7% 100.0% 724.4KiB                .text section size, the file size is 2.6MiB
+";
+
+    /// Tests that function tables are parsed without their crate column, and
+    /// that names with spaces are kept whole.
+    #[test]
+    fn test_bloat_report_parse_functions() {
+        use xtasks::tasks::bloat::BloatReport;
+
+        let report = BloatReport::parse(FUNCTIONS_TABLE).unwrap();
+        let names: Vec<&str> = report
+            .entries
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "xtasks::tasks::tasks",
+                "clap_builder::parser::parser::Parser::get_matches_with",
+                "<xtasks::tasks::bloat::Bloat as core::fmt::Debug>::fmt",
+                "std::backtrace_rs::symbolize::gimli::resolve",
+            ]
+        );
+        assert_eq!(report.entries[0].size, 34_201);
+        assert_eq!(report.entries[0].percent, 1.2);
+
+        let json = BloatReport::parse(
+            r#"{"file-size":1000,"text-section-size":500,"functions":[{"crate":"std","name":"std::rt::lang_start","size":100}]}"#,
+        )
+        .unwrap();
+        assert_eq!(json.entries[0].name, "std::rt::lang_start");
+        assert_eq!(json.entries[0].percent, 10.0);
+    }
+
+    /// Tests that entries are filtered by the prefix of their name.
+    #[test]
+    fn test_bloat_report_filtered() {
+        use xtasks::tasks::bloat::BloatReport;
+
+        let report = BloatReport::parse(FUNCTIONS_TABLE)
+            .unwrap()
+            .filtered("xtasks::");
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].name, "xtasks::tasks::tasks");
+        assert_eq!(report.total_size, 2_726_297);
+        assert!(report
+            .table("Function")
+            .starts_with("  File      Size Function\n  1.2%   33.4KiB xtasks::tasks::tasks\n"));
+    }
+
+    /// Tests that function mode leaves out `--crates` and applies the filter to
+    /// the parsed results.
+    #[test]
+    fn test_bloat_functions_mocked() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::{functions, BloatBuilder};
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout("cargo bloat", FUNCTIONS_TABLE),
+        );
+        let (all, filtered) = with_runner(recorder.clone(), || {
+            (
+                functions("xtask", 4).unwrap(),
+                BloatBuilder::default()
+                    .package("xtask")
+                    .functions(true)
+                    .filter(Some("std::".to_string()))
+                    .build()
+                    .unwrap()
+                    .report()
+                    .unwrap(),
+            )
+        });
+        assert_eq!(
+            recorder.command_lines()[1],
+            "cargo bloat -p xtask -n 4 --message-format json"
+        );
+        assert_eq!(all.entries.len(), 4);
+        assert_eq!(filtered.entries.len(), 1);
+        assert_eq!(
+            filtered.entries[0].name,
+            "std::backtrace_rs::symbolize::gimli::resolve"
+        );
+    }
+
+    /// Runs `line` through `main_with_args` with cargo-bloat printing
+    /// `stdout`, returning the result and the `cargo bloat` command lines.
+    fn run_cli(
+        line: &[&str],
+        stdout: &str,
+    ) -> (anyhow::Result<()>, Vec<String>) {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::main_with_args;
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .with_stdout("cargo bloat", stdout),
+        );
+        let args: Vec<String> =
+            line.iter().map(ToString::to_string).collect();
+        let result =
+            with_runner(recorder.clone(), || main_with_args(&args));
+        let lines = recorder
+            .command_lines()
+            .into_iter()
+            .filter(|line| line.starts_with("cargo bloat"))
+            .collect();
+        (result, lines)
+    }
+
+    /// Tests the three modes of the `bloat` task, and that the deprecated
+    /// `bloat-deps` and `bloat-time` tasks run the same commands.
+    #[test]
+    fn test_bloat_modes() {
+        const CRATES: &str = r#"{"file-size":100,"text-section-size":50,"crates":[{"name":"std","size":40}]}"#;
+        let deps = "cargo bloat -p xtask --crates --release -n 5 --message-format json";
+
+        let (result, lines) = run_cli(
+            &[
+                "xtask",
+                "bloat",
+                "-p",
+                "xtask",
+                "--release",
+                "--top",
+                "5",
+            ],
+            CRATES,
+        );
+        result.unwrap();
+        assert_eq!(lines, [deps]);
+        let (result, lines) = run_cli(
+            &["xtask", "bd", "-p", "xtask", "--release", "--top", "5"],
+            CRATES,
+        );
+        result.unwrap();
+        assert_eq!(lines, [deps]);
+
+        let (result, lines) = run_cli(
+            &[
+                "xtask",
+                "bloat",
+                "--mode",
+                "functions",
+                "-p",
+                "xtask",
+                "-n",
+                "4",
+                "--filter",
+                "std::",
+            ],
+            FUNCTIONS_TABLE,
+        );
+        result.unwrap();
+        assert_eq!(
+            lines,
+            ["cargo bloat -p xtask -n 4 --message-format json"]
+        );
+
+        for line in [
+            &["xtask", "bloat", "--mode", "time", "-p", "xtask"][..],
+            &["xtask", "bloat-time", "-p", "xtask"],
+        ] {
+            let (result, lines) = run_cli(line, "");
+            result.unwrap();
+            assert_eq!(lines, ["cargo bloat -p xtask --time"]);
+        }
+        let (result, lines) =
+            run_cli(&["xtask", "bloat", "--mode", "time"], "");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("please provide a package with -p"));
+        assert!(lines.is_empty());
+    }
+
+    /// Tests that time mode passes the build options on to `cargo bloat
+    /// --time`, rejects the report options, and reports unknown packages.
+    #[test]
+    fn test_bloat_time_options() {
+        let (result, lines) = run_cli(
+            &[
+                "xtask",
+                "bloat",
+                "--mode",
+                "time",
+                "-p",
+                "xtask",
+                "--profile",
+                "bench",
+                "-n",
+                "3",
+            ],
+            "",
+        );
+        result.unwrap();
+        assert_eq!(
+            lines,
+            ["cargo bloat -p xtask --time --profile bench -n 3"]
+        );
+
+        for flag in ["--json=out.json", "--baseline", "--filter=std::"]
+        {
+            let (result, lines) = run_cli(
+                &[
+                    "xtask", "bloat", "--mode", "time", "-p", "xtask",
+                    flag,
+                ],
+                "",
+            );
+            let err = result.unwrap_err().to_string();
+            assert!(
+                err.contains("does not apply to --mode time"),
+                "{err}"
+            );
+            assert!(lines.is_empty());
+        }
+
+        let (result, lines) = run_cli(
+            &["xtask", "bloat", "--mode", "time", "-p", "nope"],
+            "",
+        );
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("Unknown package 'nope'"), "{err}");
+        assert!(lines.is_empty());
+    }
+
+    /// Tests that the deps and functions modes pass the report options on:
+    /// `--filter` and `--json` shape the written report, and `--baseline` with
+    /// `--max-growth` fails the run once the binary grew too much.
+    #[test]
+    fn test_bloat_report_options_in_each_mode() {
+        use xtasks::tasks::bloat::BloatReport;
+
+        const CRATES: &str = r#"{"file-size":100,"text-section-size":50,"crates":[{"name":"std","size":40},{"name":"clap","size":20}]}"#;
+        let grown_crates = CRATES.replace("100", "200");
+        let grown_functions =
+            FUNCTIONS_TABLE.replace("2.6MiB", "3.6MiB");
+        for (mode, stdout, grown, prefix) in [
+            ("deps", CRATES, grown_crates.as_str(), "clap"),
+            (
+                "functions",
+                FUNCTIONS_TABLE,
+                grown_functions.as_str(),
+                "std::",
+            ),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let json = dir.path().join("report.json");
+            let baseline = dir.path().join("baseline.json");
+            let json_arg = format!("--json={}", json.display());
+            let baseline_arg =
+                format!("--baseline={}", baseline.display());
+            let cli = |extra: &[&str], stdout: &str| {
+                let mut line = vec![
+                    "xtask", "bloat", "--mode", mode, "-p", "xtask",
+                ];
+                line.extend_from_slice(extra);
+                run_cli(&line, stdout)
+            };
+
+            let (result, lines) =
+                cli(&[json_arg.as_str(), "--filter", prefix], stdout);
+            result.unwrap();
+            assert_eq!(lines.len(), 1, "{mode}");
+            let report = BloatReport::load(&json).unwrap();
+            assert!(!report.entries.is_empty(), "{mode}");
+            assert!(
+                report
+                    .entries
+                    .iter()
+                    .all(|entry| entry.name.starts_with(prefix)),
+                "{mode}: {report:?}"
+            );
+
+            let (result, _) = cli(&[baseline_arg.as_str()], stdout);
+            result.unwrap();
+            assert!(baseline.exists(), "{mode}");
+            let (result, _) = cli(
+                &[baseline_arg.as_str(), "--max-growth", "10%"],
+                grown,
+            );
+            let err = result.unwrap_err().to_string();
+            assert!(
+                err.contains("more than the allowed 10%"),
+                "{mode}: {err}"
+            );
+        }
+    }
+
+    /// Tests that time mode honours `auto_install` when cargo-bloat is missing.
+    #[test]
+    fn test_bloat_time_without_auto_install() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::bloat::BloatBuilder;
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .missing("cargo-bloat"),
+        );
+        let bloat = BloatBuilder::default()
+            .package("xtask")
+            .auto_install(false)
+            .build()
+            .unwrap();
+        let err = with_runner(recorder.clone(), || bloat.run_time())
+            .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("cargo install"),
+            "{err:#}"
+        );
+        assert!(recorder
+            .command_lines()
+            .iter()
+            .all(|line| !line.starts_with("cargo install")
+                && !line.starts_with("cargo bloat")));
+    }
+
+    /// Tests that the deprecated tasks print a deprecation note, and are
+    /// left out of the help.
+    #[test]
+    fn test_bloat_deprecated_tasks() {
+        use assert_cmd::Command;
+
+        let output = Command::cargo_bin("xtasks")
+            .unwrap()
+            .args(["--no-summary", "bloat-time"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.starts_with(
+                "warning: 'bloat-time' is deprecated, use 'bloat --mode time' instead\n"
+            ),
+            "{stderr}"
+        );
+        assert!(stderr.contains("please provide a package with -p"));
+
+        let help = Command::cargo_bin("xtasks")
+            .unwrap()
+            .arg("--help")
+            .output()
+            .unwrap();
+        let help = String::from_utf8_lossy(&help.stdout);
+        assert!(help.contains("\n  bloat "), "{help}");
+        assert!(!help.contains("bloat-deps"));
+        assert!(!help.contains("bloat-time"));
+    }
+
+    /// A size history of two binaries over three commits, oldest first.
+    const HISTORY: &str = concat!(
+        r#"{"sha":"aaaaaaa111","date":"2024-01-01T10:00:00+00:00","binary":"xtask","size_bytes":2097152}"#,
+        "\n",
+        r#"{"sha":"aaaaaaa111","date":"2024-01-01T10:00:00+00:00","binary":"fmt","size_bytes":1024}"#,
+        "\n",
+        r#"{"sha":"bbbbbbb222","date":"2024-01-02T10:00:00+00:00","binary":"xtask","size_bytes":2150400}"#,
+        "\n",
+        r#"{"sha":"ccccccc333","date":"2024-01-03T10:00:00+00:00","binary":"xtask","size_bytes":2099200}"#,
+        "\n",
+    );
+
+    /// Tests that measuring a commit again replaces its entry instead of
+    /// adding another one.
+    #[test]
+    fn test_size_history_record_dedup() {
+        use xtasks::tasks::bloat::{SizeEntry, SizeHistory};
+
+        let mut history = SizeHistory::parse(HISTORY).unwrap();
+        let entry = |sha: &str, size_bytes| SizeEntry {
+            sha: sha.to_string(),
+            date: "2024-01-04T10:00:00+00:00".to_string(),
+            binary: "xtask".to_string(),
+            size_bytes,
+        };
+        assert!(history.record(entry("ddddddd444", 2_100_000)));
+        assert!(!history.record(entry("ddddddd444", 2_200_000)));
+        assert_eq!(history.entries.len(), 5);
+        assert_eq!(history.entries[4].size_bytes, 2_200_000);
+    }
+
+    /// Tests that a history survives a round trip through a JSON Lines file,
+    /// and that a missing file is an empty history.
+    #[test]
+    fn test_size_history_save_load() {
+        use xtasks::tasks::bloat::SizeHistory;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xtask").join("size-history.jsonl");
+        assert!(SizeHistory::load(&path).unwrap().entries.is_empty());
+
+        let history = SizeHistory::parse(HISTORY).unwrap();
+        history.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), HISTORY);
+        assert_eq!(SizeHistory::load(&path).unwrap(), history);
+
+        std::fs::write(&path, "not json\n").unwrap();
+        let error = SizeHistory::load(&path).unwrap_err();
+        assert!(format!("{error:#}").contains("line 1"), "{error:#}");
+    }
+
+    /// Tests that changes are computed against the previous entry of the same
+    /// binary, and rendered as text and Markdown tables.
+    #[test]
+    fn test_size_history_changes() {
+        use xtasks::tasks::bloat::SizeHistory;
+
+        let history = SizeHistory::parse(HISTORY).unwrap();
+        let deltas: Vec<_> = history
+            .changes(10)
+            .into_iter()
+            .map(|(entry, delta)| (entry.binary.as_str(), delta))
+            .collect();
+        assert_eq!(
+            deltas,
+            [
+                ("xtask", None),
+                ("fmt", None),
+                ("xtask", Some(53_248)),
+                ("xtask", Some(-51_200)),
+            ]
+        );
+        assert_eq!(history.changes(2).len(), 2);
+
+        assert_eq!(
+            history.to_markdown(2),
+            "| Commit | Date | Binary | Size | Change |\n\
+             | --- | --- | --- | ---: | ---: |\n\
+             | `bbbbbbb` | 2024-01-02 | xtask | 2.1MiB | +52.0KiB |\n\
+             | `ccccccc` | 2024-01-03 | xtask | 2.0MiB | -50.0KiB |"
+        );
+        let table = history.table(1);
+        assert_eq!(table.lines().count(), 2);
+        assert!(table.lines().next().unwrap().starts_with("commit"));
+        assert!(table.ends_with("-50.0KiB"), "{table}");
+    }
 }
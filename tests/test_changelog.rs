@@ -0,0 +1,238 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, process::Command};
+    use xtasks::ops::git::{self, LogEntry};
+    use xtasks::tasks::changelog::{
+        classify, section_body, today, with_section, ChangelogBuilder,
+        CommitKind,
+    };
+
+    /// Runs `git` in `dir` and returns its trimmed output.
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// Commits an empty change with `subject`, by `author` if given.
+    fn commit(dir: &Path, subject: &str, author: Option<&str>) {
+        let mut args =
+            vec!["commit", "--quiet", "--allow-empty", "-m", subject];
+        if let Some(author) = author {
+            args.extend(["--author", author]);
+        }
+        let _ = git(dir, &args);
+    }
+
+    /// Creates a package tagged v0.1.0, followed by commits of every style.
+    fn repository() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let _ = git(root, &["init", "--quiet"]);
+        let _ = git(root, &["config", "user.email", "dev@example.com"]);
+        let _ = git(root, &["config", "user.name", "Dev"]);
+        let _ = git(root, &["config", "commit.gpgsign", "false"]);
+        fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"app\"\nversion = \"0.2.0\"\nrepository = \"https://github.com/o/app.git\"\n",
+        )
+        .unwrap();
+        let _ = git(root, &["add", "--all"]);
+        commit(root, "Initial commit", None);
+        let _ = git(root, &["tag", "v0.1.0"]);
+        commit(root, "feat(cli): add a changelog task", None);
+        commit(root, "fix: keep the comments of Cargo.toml", None);
+        commit(root, "docs: describe the release flow", None);
+        commit(
+            root,
+            "chore(deps): bump serde from 1.0.1 to 1.0.2",
+            Some("dependabot[bot] <support@github.com>"),
+        );
+        commit(root, "chore: tidy the CI workflow", None);
+        commit(root, "Merge the typo fixes", None);
+        dir
+    }
+
+    /// Returns the SHA of the commit with `subject`.
+    fn sha(dir: &Path, subject: &str) -> String {
+        git::log(dir, None)
+            .unwrap()
+            .into_iter()
+            .find(|commit| commit.subject == subject)
+            .unwrap()
+            .sha
+    }
+
+    /// Tests the section generated since the latest tag, grouped by prefix,
+    /// without the Dependabot updates and with links to the commits.
+    #[test]
+    fn test_generate_since_latest_tag() {
+        let dir = repository();
+        let root = dir.path();
+        let section = ChangelogBuilder::default()
+            .skip_dependabot(true)
+            .build()
+            .unwrap()
+            .generate_in(root)
+            .unwrap();
+
+        let link = |subject: &str| {
+            let sha = sha(root, subject);
+            format!(
+                "[{}](https://github.com/o/app/commit/{sha})",
+                &sha[..7]
+            )
+        };
+        let expected = format!(
+            "## [0.2.0] - {}\n\n\
+             ### Features\n\n- **cli:** add a changelog task ({})\n\n\
+             ### Bug Fixes\n\n- keep the comments of Cargo.toml ({})\n\n\
+             ### Documentation\n\n- describe the release flow ({})\n\n\
+             ### Chores\n\n- tidy the CI workflow ({})\n\n\
+             ### Other Changes\n\n- Merge the typo fixes ({})\n",
+            today(),
+            link("feat(cli): add a changelog task"),
+            link("fix: keep the comments of Cargo.toml"),
+            link("docs: describe the release flow"),
+            link("chore: tidy the CI workflow"),
+            link("Merge the typo fixes"),
+        );
+        assert_eq!(section, expected);
+
+        let with_dependabot = ChangelogBuilder::default()
+            .build()
+            .unwrap()
+            .generate_in(root)
+            .unwrap();
+        assert!(with_dependabot
+            .contains("- **deps:** bump serde from 1.0.1 to 1.0.2"));
+    }
+
+    /// Tests an explicit range and a history without tags.
+    #[test]
+    fn test_generate_ranges() {
+        let dir = repository();
+        let root = dir.path();
+        let since_fix = format!(
+            "{}..HEAD",
+            sha(root, "fix: keep the comments of Cargo.toml")
+        );
+        let section = ChangelogBuilder::default()
+            .range(Some(since_fix))
+            .build()
+            .unwrap()
+            .generate_in(root)
+            .unwrap();
+        assert!(!section.contains("### Features"));
+        assert!(!section.contains("### Bug Fixes"));
+        assert!(section.contains("### Documentation"));
+
+        let _ = git(root, &["tag", "--delete", "v0.1.0"]);
+        let section = ChangelogBuilder::default()
+            .build()
+            .unwrap()
+            .generate_in(root)
+            .unwrap();
+        assert!(section.contains("- Initial commit ("));
+    }
+
+    /// Tests that `--out` writes the section, that writing it again keeps a
+    /// single section, and that `--check` passes then fails on a stale file.
+    #[test]
+    fn test_out_and_check() {
+        let dir = repository();
+        let root = dir.path();
+        let path = root.join("CHANGELOG.md");
+        fs::write(
+            &path,
+            "# Changelog\n\n## [Unreleased]\n\n## [0.1.0] - 2023-12-01\n\n- First release\n",
+        )
+        .unwrap();
+        let check =
+            ChangelogBuilder::default().check(true).build().unwrap();
+        assert!(check
+            .run_in(root)
+            .unwrap_err()
+            .to_string()
+            .contains("no section for 0.2.0"));
+
+        let write = ChangelogBuilder::default()
+            .out(Some("CHANGELOG.md".into()))
+            .build()
+            .unwrap();
+        let section = write.run_in(root).unwrap();
+        let _ = write.run_in(root).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.matches("## [0.2.0]").count(), 1);
+        assert_eq!(
+            contents,
+            format!(
+                "# Changelog\n\n## [Unreleased]\n\n{section}\n## [0.1.0] - 2023-12-01\n\n- First release\n"
+            )
+        );
+        let _ = check.run_in(root).unwrap();
+
+        commit(root, "feat: add a --check flag", None);
+        assert!(check
+            .run_in(root)
+            .unwrap_err()
+            .to_string()
+            .contains("out of date"));
+    }
+
+    /// Tests the parsing of commit subjects of various styles.
+    #[test]
+    fn test_classify() {
+        let entry = |subject: &str| {
+            classify(&LogEntry {
+                sha: "0123456789".to_string(),
+                author: "Dev".to_string(),
+                subject: subject.to_string(),
+            })
+        };
+        assert_eq!(entry("feat!: drop 1.60").kind, CommitKind::Feature);
+        assert_eq!(entry("Docs: fix a typo").kind, CommitKind::Docs);
+        let other = entry("refactor(runner): split the module");
+        assert_eq!(other.kind, CommitKind::Other);
+        assert_eq!(other.summary, "refactor(runner): split the module");
+        assert_eq!(entry("Fix: the build").summary, "the build");
+        assert_eq!(entry("fix(: broken").kind, CommitKind::Other);
+        assert_eq!(entry("Note: no prefix").kind, CommitKind::Other);
+    }
+
+    /// Tests where a new section goes in changelogs of various shapes.
+    #[test]
+    fn test_with_section() {
+        let section = "## [0.2.0] - 2024-01-31\n\n- Added\n";
+        assert_eq!(
+            with_section("# Changelog\n", "0.2.0", section),
+            format!("# Changelog\n\n{section}")
+        );
+        assert_eq!(
+            with_section(
+                "# Changelog\n\n## 0.1.0\n\n- First\n",
+                "0.2.0",
+                section
+            ),
+            format!("# Changelog\n\n{section}\n## 0.1.0\n\n- First\n")
+        );
+        let released = "## [0.2.0] - 2024-02-02\n\n- Old\n";
+        assert_eq!(
+            with_section(released, "0.2.0", section),
+            "## [0.2.0] - 2024-02-02\n\n- Added\n"
+        );
+        assert_eq!(
+            section_body(released, "0.2.0").as_deref(),
+            Some("\n- Old\n")
+        );
+        assert_eq!(section_body(released, "0.2"), None);
+    }
+}
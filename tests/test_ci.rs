@@ -2,80 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
-    ffi::OsStr,
     io::Result,
     os::unix::process::ExitStatusExt,
-    process::{Command, ExitStatus, Output},
+    process::{ExitStatus, Output},
 };
 
-/// A trait defining a set of methods for running system commands.
+/// A trait defining how system commands are run.
 ///
-/// This trait abstracts the functionality to run system commands,
-/// providing methods to configure and execute them.
+/// This trait abstracts the execution of system commands, so that it can be
+/// mocked.
 trait CommandRunner {
-    /// Creates a new command runner instance to run a given program.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// let cmd_runner = CommandRunner::new("ls");
-    /// ```
-    ///
-    /// # Parameters
-    ///
-    /// - `program`: The program to run.
-    ///
-    /// # Returns
-    ///
-    /// A new instance of the implementing type.
-    fn new<S: AsRef<OsStr>>(program: S) -> Self
-    where
-        Self: Sized;
-
-    /// Adds arguments to the command to be run.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// let cmd_runner = CommandRunner::new("ls").args(&["-l", "-a"]);
-    /// ```
-    ///
-    /// # Parameters
-    ///
-    /// - `args`: An iterator of arguments to pass to the command.
-    ///
-    /// # Returns
-    ///
-    /// The command runner instance with the added arguments.
-    fn args<I, S>(self, args: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<OsStr>,
-        Self: Sized;
-
-    /// Adds an environment variable to the command.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// let cmd_runner = CommandRunner::new("printenv")
-    ///     .env("KEY", "value");
-    /// ```
-    ///
-    /// # Parameters
-    ///
-    /// - `key`: The environment variable key.
-    /// - `value`: The environment variable value.
-    ///
-    /// # Returns
-    ///
-    /// The command runner instance with the added environment variable.
-    fn env<K, V>(self, key: K, value: V) -> Self
-    where
-        K: AsRef<OsStr>,
-        V: AsRef<OsStr>,
-        Self: Sized;
-
     /// Executes the command, returning the output.
     ///
     /// # Returns
@@ -84,73 +20,6 @@ trait CommandRunner {
     fn spawn(&mut self) -> Result<Output>;
 }
 
-/// A wrapper around the standard library's `Command` struct.
-///
-/// This struct provides an implementation of the `CommandRunner` trait,
-/// allowing for the execution of system commands.
-struct RealCommand(Command);
-
-impl CommandRunner for RealCommand {
-    /// Creates a new `RealCommand` instance to run a given program.
-    ///
-    /// # Parameters
-    ///
-    /// - `program`: The program to run.
-    ///
-    /// # Returns
-    ///
-    /// A new `RealCommand` instance.
-    fn new<S: AsRef<OsStr>>(program: S) -> Self {
-        Self(Command::new(program))
-    }
-
-    /// Adds arguments to the command to be run.
-    ///
-    /// # Parameters
-    ///
-    /// - `args`: An iterator of arguments to pass to the command.
-    ///
-    /// # Returns
-    ///
-    /// The `RealCommand` instance with the added arguments.
-    fn args<I, S>(mut self, args: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<OsStr>,
-    {
-        self.0.args(args);
-        self
-    }
-
-    /// Adds an environment variable to the command.
-    ///
-    /// # Parameters
-    ///
-    /// - `key`: The environment variable key.
-    /// - `value`: The environment variable value.
-    ///
-    /// # Returns
-    ///
-    /// The `RealCommand` instance with the added environment variable.
-    fn env<K, V>(mut self, key: K, value: V) -> Self
-    where
-        K: AsRef<OsStr>,
-        V: AsRef<OsStr>,
-    {
-        self.0.env(key, value);
-        self
-    }
-
-    /// Executes the command, returning the output.
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing the command's output or an error.
-    fn spawn(&mut self) -> Result<Output> {
-        self.0.output()
-    }
-}
-
 /// A mock command runner for testing purposes.
 ///
 /// This struct is used for testing command execution, allowing for the configuration of
@@ -159,8 +28,6 @@ struct MockCommand {
     status: ExitStatus,
     stdout: Vec<u8>,
     stderr: Vec<u8>,
-    args: Vec<String>,
-    env: Vec<(String, String)>,
 }
 
 impl MockCommand {
@@ -174,8 +41,6 @@ impl MockCommand {
             status: ExitStatus::from_raw(0),
             stdout: Vec::new(),
             stderr: Vec::new(),
-            args: Vec::new(),
-            env: Vec::new(),
         }
     }
 
@@ -209,49 +74,6 @@ impl MockCommand {
 }
 
 impl CommandRunner for MockCommand {
-    /// Creates a new `MockCommand` instance, ignoring the provided command.
-    ///
-    /// # Returns
-    ///
-    /// A new `MockCommand` instance.
-    fn new<S: AsRef<OsStr>>(_cmd: S) -> Self {
-        Self::new()
-    }
-
-    /// Adds arguments to the mock command.
-    ///
-    /// # Returns
-    ///
-    /// The `MockCommand` instance with the added arguments.
-    fn args<I, S>(mut self, args: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<OsStr>,
-    {
-        self.args.extend(
-            args.into_iter()
-                .map(|s| s.as_ref().to_string_lossy().to_string()),
-        );
-        self
-    }
-
-    /// Adds an environment variable to the mock command.
-    ///
-    /// # Returns
-    ///
-    /// The `MockCommand` instance with the added environment variable.
-    fn env<K, V>(mut self, key: K, value: V) -> Self
-    where
-        K: AsRef<OsStr>,
-        V: AsRef<OsStr>,
-    {
-        self.env.push((
-            key.as_ref().to_string_lossy().to_string(),
-            value.as_ref().to_string_lossy().to_string(),
-        ));
-        self
-    }
-
     /// Simulates the execution of the mock command, returning the configured output.
     ///
     /// # Returns
@@ -269,7 +91,11 @@ impl CommandRunner for MockCommand {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use xtasks::tasks::ci::CI;
+    use std::sync::Arc;
+    use xtasks::{
+        ops::runner::{self, CommandSpec, RecordingRunner},
+        tasks::ci::{CIBuilder, FeatureSelection, StepStatus, CI},
+    };
 
     /// Tests the functionality of the CI struct.
     #[test]
@@ -279,6 +105,32 @@ mod tests {
         assert!(!ci.clippy_max);
     }
 
+    /// Tests that the default configuration matches the builder defaults apart
+    /// from `clippy_max`.
+    #[test]
+    fn test_ci_default_matches_builder() {
+        let ci = CI::default();
+        assert!(ci.fail_fast);
+        assert!(ci.toolchains.is_empty());
+        assert_eq!(
+            ci,
+            CIBuilder::default().clippy_max(false).build().unwrap()
+        );
+    }
+
+    /// Tests that a configuration file leaving out every option
+    /// deserializes into the builder defaults.
+    #[test]
+    fn test_ci_deserialized_defaults() {
+        let ci: CI = serde_json::from_str("{}").unwrap();
+        assert_eq!(ci, CIBuilder::default().build().unwrap());
+
+        let ci: CI =
+            serde_json::from_str(r#"{ "fail_fast": false }"#).unwrap();
+        assert!(ci.clippy_max);
+        assert!(!ci.fail_fast);
+    }
+
     /// Tests the functionality of the `MockCommand` struct.
     #[test]
     fn test_mock_command() {
@@ -294,4 +146,1578 @@ mod tests {
         assert_eq!(mock_cmd.status, exit_status);
         assert_eq!(&mock_cmd.stdout, output);
     }
+
+    /// Tests that custom steps run after the built-in steps, in insertion order,
+    /// and that their outcomes appear in the report under their given names.
+    #[test]
+    fn test_custom_steps_report_and_ordering() {
+        let runner = Arc::new(RecordingRunner::default());
+        let report = runner::with_runner(runner.clone(), || {
+            CIBuilder::default()
+                .add_step("schemas", || Ok(()))
+                .add_step("broken", || {
+                    Err(anyhow::anyhow!("schema mismatch"))
+                })
+                .run_with_report()
+        })
+        .unwrap();
+
+        let names: Vec<&str> =
+            report.steps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "fmt", "clippy", "check", "test", "docs", "schemas",
+                "broken"
+            ]
+        );
+        assert_eq!(report.steps[5].status, StepStatus::Passed);
+        assert_eq!(
+            report.steps[6].status,
+            StepStatus::Failed("schema mismatch".to_string())
+        );
+        assert!(!report.success());
+        assert_eq!(runner.calls().len(), 5);
+        assert!(report.to_string().contains("FAIL  broken"));
+    }
+
+    /// Tests that a failing step skips the remaining steps when fail-fast is enabled
+    /// and that every step still runs when it is disabled.
+    #[test]
+    fn test_custom_steps_fail_fast() {
+        let runner = Arc::new(RecordingRunner::default());
+        let report = runner::with_runner(runner.clone(), || {
+            CIBuilder::default()
+                .add_step("broken", || Err(anyhow::anyhow!("boom")))
+                .add_step("after", || Ok(()))
+                .run_with_report()
+        })
+        .unwrap();
+        assert_eq!(report.steps[6].status, StepStatus::Skipped);
+
+        let report = runner::with_runner(runner, || {
+            CIBuilder::default()
+                .fail_fast(false)
+                .add_step("broken", || Err(anyhow::anyhow!("boom")))
+                .add_step("after", || Ok(()))
+                .run_with_report()
+        })
+        .unwrap();
+        assert_eq!(report.steps[6].status, StepStatus::Passed);
+    }
+
+    /// Tests that command steps are executed through the runner and survive serialization.
+    #[test]
+    fn test_custom_command_step() {
+        let runner = Arc::new(RecordingRunner::default());
+        let ci = CIBuilder::default()
+            .add_command_step(
+                "schemas",
+                "jsonschema",
+                ["assets/a.json"],
+            )
+            .build()
+            .unwrap();
+        let report = runner::with_runner(runner.clone(), || {
+            CIBuilder::default()
+                .add_command_step(
+                    "schemas",
+                    "jsonschema",
+                    ["assets/a.json"],
+                )
+                .run()
+        });
+        assert!(report.is_ok(), "Expected Ok, got {report:?}");
+        let calls = runner.calls();
+        assert_eq!(
+            calls.last().unwrap().to_string(),
+            "jsonschema assets/a.json"
+        );
+
+        let json = serde_json::to_string(&ci).unwrap();
+        let restored: CI = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.steps, ci.steps);
+    }
+
+    /// Tests that a `CI` value can be run directly, without reconstructing a builder.
+    #[test]
+    fn test_ci_default_run() {
+        let runner = Arc::new(RecordingRunner::default());
+        let result =
+            runner::with_runner(runner.clone(), || CI::default().run());
+        assert!(result.is_ok(), "Expected Ok, got {result:?}");
+
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "cargo fmt -- --check",
+                "cargo clippy -- -D warnings",
+                "cargo check --all-targets --all-features",
+                "cargo test",
+                "cargo doc --no-deps --all-features"
+            ]
+        );
+    }
+
+    /// Tests that a `CI` deserialized from a configuration file runs the same steps
+    /// as the builder it was produced from.
+    #[test]
+    fn test_ci_deserialized_run() {
+        let ci: CI = serde_json::from_str(
+            r#"{ "nightly": true, "clippy_max": true }"#,
+        )
+        .unwrap();
+        let runner = Arc::new(RecordingRunner::default());
+        let report = runner::with_runner(runner.clone(), || {
+            ci.run_with_report()
+        });
+        assert!(report.success());
+
+        let calls = runner.calls();
+        assert_eq!(
+            calls[0].to_string(),
+            "rustup run nightly rustc --version"
+        );
+        assert_eq!(
+            calls[1].to_string(),
+            "rustup run nightly cargo fmt -- --check"
+        );
+    }
+
+    /// Returns the command lines of the planned command steps.
+    fn planned(ci: &CI) -> Vec<(String, String)> {
+        ci.plan()
+            .into_iter()
+            .filter_map(|step| {
+                step.command
+                    .map(|command| (step.name, command.to_string()))
+            })
+            .collect()
+    }
+
+    /// Tests the argument sets generated for each feature selection.
+    #[test]
+    fn test_feature_selection_arguments() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .clippy_max(false)
+            .features(FeatureSelection {
+                list: vec!["json".into(), "yaml".into()],
+                ..FeatureSelection::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            planned(&ci)[1..4],
+            [
+                (
+                    "clippy".to_string(),
+                    "cargo clippy --features json,yaml -- -D warnings"
+                        .to_string()
+                ),
+                (
+                    "check".to_string(),
+                    "cargo check --all-targets --features json,yaml"
+                        .to_string()
+                ),
+                (
+                    "test".to_string(),
+                    "cargo test --features json,yaml".to_string()
+                ),
+            ]
+        );
+
+        let ci = CIBuilder::default()
+            .locked(false)
+            .features(FeatureSelection {
+                none_default: true,
+                ..FeatureSelection::default()
+            })
+            .build()
+            .unwrap();
+        assert_eq!(
+            planned(&ci)[1].1,
+            "cargo clippy --all-targets --no-default-features -- -D warnings -W clippy::pedantic -W clippy::nursery"
+        );
+        assert_eq!(
+            planned(&ci)[3].1,
+            "cargo test --no-default-features"
+        );
+    }
+
+    /// Tests that several variants run the steps once per variant, labelled in the report.
+    #[test]
+    fn test_feature_selection_variants() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .clippy_max(false)
+            .features(FeatureSelection {
+                all: true,
+                none_default: true,
+                list: Vec::new(),
+            })
+            .build()
+            .unwrap();
+        let names: Vec<String> =
+            planned(&ci).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            [
+                "fmt",
+                "clippy [all-features]",
+                "check [all-features]",
+                "test [all-features]",
+                "clippy [no-default-features]",
+                "check [no-default-features]",
+                "test [no-default-features]",
+                "docs",
+            ]
+        );
+        assert_eq!(planned(&ci)[3].1, "cargo test --all-features");
+
+        let runner = Arc::new(RecordingRunner::default());
+        let report =
+            runner::with_runner(runner, || ci.run_with_report());
+        assert!(report
+            .to_string()
+            .contains("test [no-default-features]"));
+    }
+
+    /// Tests that `--locked` and `--offline` reach every dependency-resolving cargo step.
+    #[test]
+    fn test_locked_offline_propagation() {
+        let ci = CIBuilder::default()
+            .locked(true)
+            .offline(true)
+            .build()
+            .unwrap();
+        let lines = planned(&ci);
+        assert_eq!(lines[0].1, "cargo fmt -- --check");
+        assert!(lines[1].1.starts_with(
+            "cargo clippy --locked --offline --all-targets"
+        ));
+        assert_eq!(
+            lines[2].1,
+            "cargo check --locked --offline --all-targets --all-features"
+        );
+        assert_eq!(lines[3].1, "cargo test --locked --offline");
+
+        let ci = CIBuilder::default().locked(false).build().unwrap();
+        assert!(planned(&ci)
+            .iter()
+            .all(|(_, line)| !line.contains("--locked")
+                && !line.contains("--offline")));
+    }
+
+    /// Tests that a failing `--locked` step suggests committing the lockfile.
+    #[test]
+    fn test_locked_failure_hint() {
+        let err = runner::with_runner(
+            Arc::new(RecordingRunner::default().failing("cargo test")),
+            || CIBuilder::default().locked(true).run(),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("commit the lockfile"),
+            "{err}"
+        );
+    }
+
+    /// Tests that `locked` defaults to true when the `CI` environment variable is set.
+    #[test]
+    fn test_locked_ci_env_default() {
+        std::env::set_var("CI", "true");
+        assert!(CIBuilder::default().build().unwrap().locked);
+        std::env::set_var("CI", "false");
+        assert!(!CIBuilder::default().build().unwrap().locked);
+        std::env::remove_var("CI");
+        assert!(!CIBuilder::default().build().unwrap().locked);
+    }
+
+    /// Tests the argument sets used by fix mode.
+    #[test]
+    fn test_fix_mode_arguments() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .clippy_max(false)
+            .fix(true)
+            .build()
+            .unwrap();
+        let lines = planned(&ci);
+        assert_eq!(lines[0].1, "cargo fmt");
+        assert_eq!(
+            lines[1].1,
+            "cargo clippy --fix --allow-dirty --allow-staged -- -D warnings"
+        );
+
+        let ci = CIBuilder::default()
+            .nightly(true)
+            .fix(true)
+            .build()
+            .unwrap();
+        assert_eq!(planned(&ci)[0].1, "rustup run nightly cargo fmt");
+    }
+
+    /// Tests that the files modified between two snapshots of a scratch repository
+    /// are listed, including further edits to an already modified file.
+    #[test]
+    fn test_fix_mode_modified_files() {
+        use std::{fs, path::PathBuf, process::Command};
+        use xtasks::ops::git::Snapshot;
+
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=xtasks",
+                    "-c",
+                    "user.email=x@t.s",
+                ])
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?} failed");
+        };
+        git(&["init", "-q"]);
+        fs::write(dir.path().join("clean.rs"), "fn a() {}\n").unwrap();
+        fs::write(dir.path().join("dirty.rs"), "fn b() {}\n").unwrap();
+        fs::write(dir.path().join("same.rs"), "fn c() {}\n").unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "init"]);
+        fs::write(dir.path().join("dirty.rs"), "fn b( ) {}\n").unwrap();
+        fs::write(dir.path().join("same.rs"), "fn c( ) {}\n").unwrap();
+
+        let before = Snapshot::take(dir.path()).unwrap();
+        fs::write(dir.path().join("clean.rs"), "fn a( ) {}\n").unwrap();
+        fs::write(dir.path().join("dirty.rs"), "fn b() {}\n").unwrap();
+        fs::write(dir.path().join("new.rs"), "fn d() {}\n").unwrap();
+        let after = Snapshot::take(dir.path()).unwrap();
+
+        assert_eq!(
+            after.changed_since(&before),
+            [
+                PathBuf::from("clean.rs"),
+                PathBuf::from("dirty.rs"),
+                PathBuf::from("new.rs")
+            ]
+        );
+    }
+
+    /// Tests that the emitted workflow is valid YAML and mirrors the planned steps.
+    #[test]
+    fn test_github_workflow() {
+        let ci = CIBuilder::default()
+            .locked(true)
+            .nightly(true)
+            .features(FeatureSelection {
+                all: false,
+                none_default: true,
+                list: vec!["serde".to_string()],
+            })
+            .build()
+            .unwrap();
+        let yaml = ci.to_github_workflow().unwrap();
+        assert_eq!(yaml, ci.to_github_workflow().unwrap());
+
+        let workflow: serde_yaml::Value =
+            serde_yaml::from_str(&yaml).unwrap();
+        let job = &workflow["jobs"]["ci"];
+        assert_eq!(
+            job["strategy"]["matrix"]["toolchain"][0],
+            "nightly"
+        );
+        let runs: Vec<&str> = job["steps"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .filter_map(|step| step["run"].as_str())
+            .collect();
+        assert!(runs.contains(&"cargo fmt -- --check"));
+        assert!(runs.contains(
+            &"cargo test --locked --no-default-features --features serde"
+        ));
+        assert!(runs.contains(&"cargo test --locked --doc"));
+    }
+
+    /// Tests the exact workflow emitted for a toolchain matrix, which must not
+    /// depend on whether it is generated locally or on CI.
+    #[test]
+    fn test_github_workflow_snapshot() {
+        let workflow = |locked| {
+            CIBuilder::default()
+                .locked(locked)
+                .toolchains(["stable", "nightly"])
+                .allow_failure(["nightly"])
+                .add_command_step("audit", "cargo", ["audit"])
+                .build()
+                .unwrap()
+                .to_github_workflow()
+                .unwrap()
+        };
+        assert_eq!(workflow(false), workflow(true));
+        assert_eq!(
+            workflow(false),
+            r#"# Generated by `cargo xtask ci --emit-workflow`; do not edit by hand.
+name: CI
+
+on:
+  push:
+  pull_request:
+
+jobs:
+  ci:
+    name: CI (${{ matrix.toolchain }})
+    runs-on: ubuntu-latest
+    continue-on-error: ${{ matrix.toolchain == 'nightly' }}
+    strategy:
+      fail-fast: true
+      matrix:
+        toolchain:
+          - stable
+          - nightly
+    steps:
+      - uses: actions/checkout@v4
+      - name: Install Rust
+        run: |
+          rustup toolchain install ${{ matrix.toolchain }} --profile minimal --component rustfmt,clippy
+          rustup override set ${{ matrix.toolchain }}
+      - name: fmt
+        run: cargo fmt -- --check
+      - name: clippy
+        run: cargo clippy --locked --all-targets --all-features -- -D warnings -W clippy::pedantic -W clippy::nursery
+      - name: check
+        run: cargo check --locked --all-targets --all-features
+      - name: test
+        run: cargo test --locked
+      - name: docs
+        env:
+          RUSTDOCFLAGS: '-D warnings'
+        run: cargo doc --locked --no-deps --all-features
+      - name: audit
+        run: cargo audit
+      - name: doc tests
+        run: cargo test --locked --doc
+"#
+        );
+    }
+
+    /// Tests that the optional checks running inside xtasks are listed in the
+    /// workflow as not exported, between the built-in and the custom steps.
+    #[test]
+    fn test_github_workflow_unexported_steps() {
+        let ci = CIBuilder::default()
+            .deny(true)
+            .typos(true)
+            .add_command_step("audit", "cargo", ["audit"])
+            .build()
+            .unwrap();
+        assert_eq!(ci.unexported_steps(), ["deny", "typos"]);
+
+        let yaml = ci.to_github_workflow().unwrap();
+        assert!(
+            yaml.contains(
+                "        run: cargo doc --locked --no-deps --all-features\n      \
+                 # not exported: deny\n      # not exported: typos\n      \
+                 - name: audit\n"
+            ),
+            "{yaml}"
+        );
+        serde_yaml::from_str::<serde_yaml::Value>(&yaml).unwrap();
+
+        let plain = CIBuilder::default().build().unwrap();
+        assert!(plain.unexported_steps().is_empty());
+        assert!(!plain
+            .to_github_workflow()
+            .unwrap()
+            .contains("not exported"));
+    }
+
+    /// Tests that fix mode cannot be turned into a workflow.
+    #[test]
+    fn test_github_workflow_rejects_fix_mode() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .fix(true)
+            .build()
+            .unwrap();
+        assert!(ci.to_github_workflow().is_err());
+    }
+
+    /// Tests that an unchanged workflow file is not rewritten.
+    #[test]
+    fn test_write_file_if_changed() {
+        use xtasks::ops::write_file_if_changed;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".github/workflows/ci.yml");
+        assert!(write_file_if_changed(&path, "name: CI\n").unwrap());
+        assert!(!write_file_if_changed(&path, "name: CI\n").unwrap());
+        assert!(write_file_if_changed(&path, "name: Other\n").unwrap());
+    }
+
+    /// Tests that every toolchain runs the built-in steps through `rustup run`,
+    /// while custom steps run once at the end.
+    #[test]
+    fn test_toolchain_matrix_prefixes() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .clippy_max(false)
+            .toolchains(["stable", "beta"])
+            .add_command_step("audit", "cargo", ["audit"])
+            .build()
+            .unwrap();
+        let steps = ci.plan();
+        let lines: Vec<(Option<String>, String)> = steps
+            .iter()
+            .map(|step| {
+                (
+                    step.toolchain.clone(),
+                    step.command.as_ref().unwrap().to_string(),
+                )
+            })
+            .collect();
+        let stable = Some("stable".to_string());
+        let beta = Some("beta".to_string());
+        assert_eq!(
+            lines,
+            [
+                (
+                    stable.clone(),
+                    "rustup run stable cargo fmt -- --check".into()
+                ),
+                (
+                    stable.clone(),
+                    "rustup run stable cargo clippy -- -D warnings"
+                        .into()
+                ),
+                (
+                    stable.clone(),
+                    "rustup run stable cargo check --all-targets --all-features"
+                        .into()
+                ),
+                (stable.clone(), "rustup run stable cargo test".into()),
+                (
+                    stable,
+                    "rustup run stable cargo doc --no-deps --all-features"
+                        .into()
+                ),
+                (
+                    beta.clone(),
+                    "rustup run beta cargo fmt -- --check".into()
+                ),
+                (
+                    beta.clone(),
+                    "rustup run beta cargo clippy -- -D warnings"
+                        .into()
+                ),
+                (
+                    beta.clone(),
+                    "rustup run beta cargo check --all-targets --all-features"
+                        .into()
+                ),
+                (beta.clone(), "rustup run beta cargo test".into()),
+                (
+                    beta,
+                    "rustup run beta cargo doc --no-deps --all-features"
+                        .into()
+                ),
+                (None, "cargo audit".into()),
+            ]
+        );
+
+        // The legacy flag maps to a nightly-only matrix.
+        let ci = CIBuilder::default().nightly(true).build().unwrap();
+        assert_eq!(ci.resolved_toolchains(), ["nightly"]);
+        assert_eq!(CI::default().resolved_toolchains(), ["stable"]);
+    }
+
+    /// Tests that a missing toolchain is installed before its first step.
+    #[test]
+    fn test_toolchain_installed_on_demand() {
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .failing("rustup run beta rustc --version"),
+        );
+        let report = runner::with_runner(runner.clone(), || {
+            CIBuilder::default()
+                .locked(false)
+                .toolchains(["beta"])
+                .run_with_report()
+                .unwrap()
+        });
+        assert!(report.success());
+        let calls = runner.command_lines();
+        assert_eq!(calls[0], "rustup run beta rustc --version");
+        assert_eq!(
+            calls[1],
+            "rustup toolchain install beta --profile minimal --component rustfmt,clippy"
+        );
+        assert_eq!(calls[2], "rustup run beta cargo fmt -- --check");
+        assert_eq!(calls.len(), 7);
+    }
+
+    /// Tests that a missing toolchain is reported rather than installed when
+    /// auto-install is off.
+    #[test]
+    fn test_toolchain_not_installed_without_auto_install() {
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .failing("rustup run beta rustc --version"),
+        );
+        let report = runner::with_runner(runner.clone(), || {
+            CIBuilder::default()
+                .locked(false)
+                .toolchains(["beta"])
+                .auto_install(false)
+                .run_with_report()
+                .unwrap()
+        });
+        assert!(!report.success());
+        assert!(runner
+            .command_lines()
+            .iter()
+            .all(|line| !line.starts_with("rustup toolchain install")));
+        match &report.steps[0].status {
+            StepStatus::Failed(error) => assert!(
+                error.contains("rustup toolchain install beta"),
+                "{error}"
+            ),
+            status => panic!("expected a failure, got {status:?}"),
+        }
+    }
+
+    /// Tests that an explicit `["stable"]` runs a stable matrix, while the
+    /// default runs with the active toolchain.
+    #[test]
+    fn test_toolchain_explicit_stable() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .toolchains(["stable"])
+            .build()
+            .unwrap();
+        assert_eq!(ci.fmt_toolchain().as_deref(), Some("stable"));
+        assert!(ci
+            .plan()
+            .iter()
+            .take(4)
+            .all(|step| step.toolchain.as_deref() == Some("stable")));
+
+        let ci = CIBuilder::default().locked(false).build().unwrap();
+        assert_eq!(ci.fmt_toolchain(), None);
+        assert!(ci.plan().iter().all(|step| step.toolchain.is_none()));
+    }
+
+    /// Tests that failures on a toolchain listed in `allow_failure` are downgraded
+    /// to warnings and do not stop the run.
+    #[test]
+    fn test_toolchain_allow_failure() {
+        struct NightlyFails;
+        impl runner::CommandRunner for NightlyFails {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                let code = i32::from(
+                    command.args[1] == "nightly"
+                        && command.args[3] == "clippy",
+                );
+                Ok(Output {
+                    status: ExitStatus::from_raw(code << 8),
+                    stdout: Vec::new(),
+                    stderr: Vec::new(),
+                })
+            }
+        }
+
+        let mut builder = CIBuilder::default();
+        builder
+            .locked(false)
+            .toolchains(["nightly", "stable"])
+            .allow_failure(["nightly"]);
+        let report =
+            runner::with_runner(Arc::new(NightlyFails), || {
+                builder.run_with_report().unwrap()
+            });
+        assert!(report.success());
+        assert_eq!(report.warnings().count(), 1);
+        assert!(matches!(
+            report.steps[1].status,
+            StepStatus::Warned(_)
+        ));
+        assert!(report.steps[3..]
+            .iter()
+            .all(|step| step.status == StepStatus::Passed));
+        let summary = report.to_string();
+        assert!(summary.contains(" nightly:"), "{summary}");
+        assert!(summary.contains("  WARN  clippy"), "{summary}");
+
+        let err = runner::with_runner(Arc::new(NightlyFails), || {
+            builder.allow_failure(Vec::<String>::new()).run()
+        })
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("'clippy' (nightly)"),
+            "{err}"
+        );
+    }
+
+    /// Tests that quiet mode only keeps the output of the failing step, while
+    /// passing steps are reported on a single line.
+    #[test]
+    fn test_quiet_mode_failure_output() {
+        struct ChattyRunner;
+        impl runner::CommandRunner for ChattyRunner {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                assert!(command.capture, "{command} was not captured");
+                let step = command.args[0].clone();
+                let code = i32::from(step == "clippy");
+                Ok(Output {
+                    status: ExitStatus::from_raw(code << 8),
+                    stdout: format!("{step} stdout\n").into_bytes(),
+                    stderr: format!("{step} stderr\n").into_bytes(),
+                })
+            }
+        }
+
+        let report =
+            runner::with_runner(Arc::new(ChattyRunner), || {
+                CIBuilder::default()
+                    .locked(false)
+                    .fail_fast(false)
+                    .quiet(true)
+                    .run_with_report()
+                    .unwrap()
+            });
+        assert!(!report.success());
+        assert_eq!(report.steps[0].output, None);
+        assert_eq!(
+            report.steps[1].output.as_deref(),
+            Some("clippy stdout\nclippy stderr\n")
+        );
+        assert_eq!(report.steps[2].output, None);
+
+        let summary = report.to_string();
+        assert!(
+            summary.contains("[clippy] clippy stdout\n"),
+            "{summary}"
+        );
+        assert!(
+            summary.contains("[clippy] clippy stderr\n"),
+            "{summary}"
+        );
+        assert!(!summary.contains("fmt stdout"), "{summary}");
+        assert!(!summary.contains("test stdout"), "{summary}");
+    }
+
+    /// Tests that verbose mode passes `--verbose` to cargo.
+    #[test]
+    fn test_verbose_mode_arguments() {
+        let ci = CIBuilder::default()
+            .locked(true)
+            .clippy_max(false)
+            .verbose(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> =
+            planned(&ci).into_iter().map(|(_, line)| line).collect();
+        assert_eq!(
+            lines,
+            [
+                "cargo fmt --verbose -- --check",
+                "cargo clippy --verbose --locked -- -D warnings",
+                "cargo check --verbose --locked --all-targets --all-features",
+                "cargo test --verbose --locked",
+                "cargo doc --verbose --locked --no-deps --all-features"
+            ]
+        );
+    }
+
+    /// Tests that the docs step denies rustdoc warnings on its own process only.
+    #[test]
+    fn test_docs_step_environment() {
+        let runner = Arc::new(RecordingRunner::default());
+        let report = runner::with_runner(runner.clone(), || {
+            CIBuilder::default()
+                .locked(false)
+                .add_command_step("audit", "cargo", ["audit"])
+                .run_with_report()
+                .unwrap()
+        });
+        assert!(report.success());
+        assert_eq!(report.steps[4].name, "docs");
+
+        let calls = runner.calls();
+        let docs = &calls[4];
+        assert_eq!(
+            docs.to_string(),
+            "cargo doc --no-deps --all-features"
+        );
+        assert_eq!(
+            docs.env,
+            [("RUSTDOCFLAGS".to_string(), "-D warnings".to_string())]
+        );
+        assert!(calls
+            .iter()
+            .filter(|call| call.args[0] != "doc")
+            .all(|call| call.env.is_empty()));
+        assert!(std::env::var_os("RUSTDOCFLAGS").is_none());
+    }
+
+    /// Tests that a docs failure explains that rustdoc warnings are denied.
+    #[test]
+    fn test_docs_step_failure_context() {
+        struct DocsFail;
+        impl runner::CommandRunner for DocsFail {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                let code = i32::from(command.args[0] == "doc");
+                Ok(Output {
+                    status: ExitStatus::from_raw(code << 8),
+                    stdout: Vec::new(),
+                    stderr: Vec::new(),
+                })
+            }
+        }
+
+        let err = runner::with_runner(Arc::new(DocsFail), || {
+            CIBuilder::default().locked(false).run()
+        })
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("rustdoc warnings are denied"),
+            "{err}"
+        );
+
+        let ci = CIBuilder::default().docs(false).build().unwrap();
+        assert!(ci.plan().iter().all(|step| step.name != "docs"));
+    }
+
+    const MACHETE_OUTPUT: &str = "\
+Analyzing dependencies of crates in this directory...
+cargo-machete found the following unused dependencies in this directory:
+app -- ./Cargo.toml:
+\tregex
+\tserde_derive
+helper -- ./helper/Cargo.toml:
+\tonce_cell
+
+If you believe cargo-machete has detected an unused dependency incorrectly,
+you can add the dependency to the list of dependencies to ignore in the
+`[package.metadata.cargo-machete]` section of the appropriate Cargo.toml.
+
+Done!
+";
+
+    const UDEPS_OUTPUT: &str = "\
+unused dependencies:
+`app v0.1.0 (/work/app)`
+\u{2514}\u{2500}\u{2500}\u{2500} dependencies
+    \u{251c}\u{2500}\u{2500}\u{2500} \"regex\"
+    \u{2514}\u{2500}\u{2500}\u{2500} \"serde_derive\"
+Note: They might be false-positive.
+      For example, `cargo-udeps` cannot detect usage of crates that are only used in doc-tests.
+";
+
+    /// Tests that both report formats are parsed into the same dependency list.
+    #[test]
+    fn test_unused_deps_parsers() {
+        use xtasks::tasks::ci::{
+            parse_machete_output, parse_udeps_output, UnusedDependency,
+        };
+
+        let dep = |package: &str, name: &str| UnusedDependency {
+            package: package.to_string(),
+            name: name.to_string(),
+        };
+        assert_eq!(
+            parse_machete_output(MACHETE_OUTPUT),
+            [
+                dep("app", "regex"),
+                dep("app", "serde_derive"),
+                dep("helper", "once_cell")
+            ]
+        );
+        assert_eq!(
+            parse_udeps_output(UDEPS_OUTPUT),
+            [dep("app", "regex"), dep("app", "serde_derive")]
+        );
+        assert!(parse_machete_output("Done!\n").is_empty());
+    }
+
+    /// Tests that ignored dependencies are filtered out of the findings and of
+    /// the error reported by the step.
+    #[test]
+    fn test_unused_deps_ignore_filtering() {
+        use std::path::PathBuf;
+        use xtasks::tasks::ci::{
+            filter_ignored_deps, parse_machete_output,
+        };
+
+        let kept = filter_ignored_deps(
+            parse_machete_output(MACHETE_OUTPUT),
+            &["serde_derive".to_string()],
+        );
+        let names: Vec<&str> =
+            kept.iter().map(|dep| dep.name.as_str()).collect();
+        assert_eq!(names, ["regex", "once_cell"]);
+
+        struct MacheteRunner;
+        impl runner::CommandRunner for MacheteRunner {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                let machete = command.args[0] == "machete";
+                Ok(Output {
+                    status: ExitStatus::from_raw(
+                        i32::from(machete) << 8,
+                    ),
+                    stdout: if machete {
+                        MACHETE_OUTPUT.as_bytes().to_vec()
+                    } else {
+                        Vec::new()
+                    },
+                    stderr: Vec::new(),
+                })
+            }
+
+            fn which(&self, program: &str) -> Option<PathBuf> {
+                (program == "cargo-machete")
+                    .then(|| PathBuf::from(program))
+            }
+        }
+
+        let mut builder = CIBuilder::default();
+        builder
+            .locked(false)
+            .unused_deps(true)
+            .unused_deps_ignore(["serde_derive"]);
+        let err = runner::with_runner(Arc::new(MacheteRunner), || {
+            builder.run()
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("regex (in app), once_cell (in helper)"),
+            "{err}"
+        );
+        assert!(!err.contains("serde_derive"), "{err}");
+
+        builder.unused_deps_ignore([
+            "serde_derive",
+            "regex",
+            "once_cell",
+        ]);
+        runner::with_runner(Arc::new(MacheteRunner), || builder.run())
+            .unwrap();
+    }
+
+    /// Tests the `cargo deny check` arguments for a check list.
+    #[test]
+    fn test_deny_check_arguments() {
+        use xtasks::tasks::ci::{deny_command, summarize_deny_output};
+
+        assert_eq!(
+            deny_command(&[]).unwrap().to_string(),
+            "cargo deny check"
+        );
+        assert_eq!(
+            deny_command(&["advisories".into(), "sources".into()])
+                .unwrap()
+                .to_string(),
+            "cargo deny check advisories sources"
+        );
+        let err = deny_command(&["licences".into()]).unwrap_err();
+        assert!(err.to_string().contains("'licences'"), "{err}");
+
+        let output =
+            "error[rejected]: failed to satisfy license requirements
+warning[duplicate]: found 2 duplicate entries for crate 'syn'
+error[banned]: crate 'openssl' is explicitly banned
+advisories ok, bans FAILED, licenses FAILED, sources ok
+";
+        assert_eq!(
+            summarize_deny_output(output),
+            "2 errors, 1 warning (advisories ok, bans FAILED, licenses FAILED, sources ok)"
+        );
+    }
+
+    /// Tests that unknown cargo-deny checks are rejected when the settings are
+    /// built, and known ones are kept.
+    #[test]
+    fn test_deny_checks_validated_on_build() {
+        let err = CIBuilder::default()
+            .deny_checks(["bans", "licences"])
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("'licences'"), "{err}");
+
+        let ci = CIBuilder::default()
+            .deny_checks(["bans", "licenses"])
+            .build()
+            .unwrap();
+        assert_eq!(ci.deny_checks, ["bans", "licenses"]);
+    }
+
+    /// Tests that a missing `deny.toml` is scaffolded when the prompt is answered
+    /// through the environment, and left alone once it exists.
+    #[test]
+    fn test_deny_config_scaffold() {
+        use xtasks::tasks::ci::scaffold_deny_config;
+
+        let declined = tempfile::tempdir().unwrap();
+        let created = tempfile::tempdir().unwrap();
+
+        std::env::set_var("XTASKS_CONFIRM", "no");
+        let err = scaffold_deny_config(declined.path()).unwrap_err();
+        std::env::set_var("XTASKS_CONFIRM", "yes");
+        let first = scaffold_deny_config(created.path()).unwrap();
+        let second = scaffold_deny_config(created.path()).unwrap();
+        std::env::remove_var("XTASKS_CONFIRM");
+
+        assert!(
+            err.to_string().contains("XTASKS_CONFIRM=yes"),
+            "{err}"
+        );
+        assert!(!declined.path().join("deny.toml").exists());
+        assert!(first);
+        assert!(!second);
+        let config =
+            std::fs::read_to_string(created.path().join("deny.toml"))
+                .unwrap();
+        assert!(config.contains("[licenses]"));
+    }
+
+    const CARGO_JSON_OUTPUT: &str = r#"{"reason":"compiler-artifact","package_id":"app 0.1.0","target":{"name":"app"},"fresh":true}
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"message":"unused variable: `count`","code":{"code":"unused_variables","explanation":null},"level":"warning","spans":[{"file_name":"src/lib.rs","byte_start":40,"byte_end":45,"line_start":3,"line_end":3,"column_start":9,"column_end":14,"is_primary":true,"text":[],"label":null}],"children":[],"rendered":"warning: unused variable: `count`\n --> src/lib.rs:3:9\n"}}
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"message":"mismatched types","code":{"code":"E0308","explanation":null},"level":"error","spans":[{"file_name":"src/main.rs","byte_start":10,"byte_end":12,"line_start":7,"line_end":8,"column_start":5,"column_end":6,"is_primary":true,"text":[],"label":null}],"children":[],"rendered":"error[E0308]: mismatched types\n"}}
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"message":"1 warning emitted","code":null,"level":"warning","spans":[],"children":[],"rendered":"warning: 1 warning emitted\n"}}
+{"reason":"build-finished","success":false}
+running 2 tests
+test tests::adds ... ok
+test tests::parses ... FAILED
+
+failures:
+
+---- tests::parses stdout ----
+thread 'tests::parses' (4242) panicked at src/parse.rs:12:5:
+assertion `left == right` failed
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+"#;
+
+    /// Tests that cargo JSON diagnostics and test panics become annotations,
+    /// while the human-readable output is preserved.
+    #[test]
+    fn test_annotations_from_cargo_output() {
+        use xtasks::tasks::ci::annotations::{
+            process_cargo_output, render_annotations,
+        };
+
+        let (annotations, human) =
+            process_cargo_output(CARGO_JSON_OUTPUT);
+        assert!(
+            human.starts_with("warning: unused variable: `count`\n")
+        );
+        assert!(human.contains("error[E0308]: mismatched types\n"));
+        assert!(human.contains("test tests::parses ... FAILED\n"));
+        assert!(!human.contains("\"reason\""));
+
+        let lines = render_annotations(&annotations, 10);
+        assert_eq!(
+            lines,
+            [
+                "::warning file=src/lib.rs,line=3,endLine=3,col=9,endColumn=14,title=unused_variables::unused variable: `count`",
+                "::error file=src/main.rs,line=7,endLine=8,col=5,endColumn=6,title=E0308::mismatched types",
+                "::error file=src/parse.rs,line=12,col=5,title=test tests%3A%3Aparses failed::assertion `left == right` failed%0A  left: 1%0A right: 2",
+            ]
+        );
+
+        let capped = render_annotations(&annotations, 1);
+        assert_eq!(capped.len(), 2);
+        assert_eq!(
+            capped[1],
+            "2 more diagnostics were not annotated; see the log above"
+        );
+    }
+
+    /// Tests that annotation mode switches clippy and test to JSON output and
+    /// prints the annotations of a failing step.
+    #[test]
+    fn test_annotations_mode_arguments() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .clippy_max(false)
+            .docs(false)
+            .annotations(true)
+            .build()
+            .unwrap();
+        let lines: Vec<String> =
+            planned(&ci).into_iter().map(|(_, line)| line).collect();
+        assert_eq!(
+            lines,
+            [
+                "cargo fmt -- --check",
+                "cargo clippy --message-format json -- -D warnings",
+                "cargo check --all-targets --all-features --message-format json",
+                "cargo test --message-format json"
+            ]
+        );
+        assert!(!ci
+            .to_github_workflow()
+            .unwrap()
+            .contains("--message-format"));
+
+        struct JsonRunner;
+        impl runner::CommandRunner for JsonRunner {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                let test = command.args[0] == "test";
+                Ok(Output {
+                    status: ExitStatus::from_raw(i32::from(test) << 8),
+                    stdout: if test {
+                        CARGO_JSON_OUTPUT.as_bytes().to_vec()
+                    } else {
+                        Vec::new()
+                    },
+                    stderr: Vec::new(),
+                })
+            }
+        }
+        let report = runner::with_runner(Arc::new(JsonRunner), || {
+            CIBuilder::default()
+                .locked(false)
+                .annotations(true)
+                .quiet(true)
+                .run_with_report()
+                .unwrap()
+        });
+        let output = report.steps[3].output.as_deref().unwrap();
+        assert!(output.contains("test tests::parses ... FAILED\n"));
+        assert!(!output.contains("compiler-message"));
+    }
+
+    /// Tests that the annotated steps stream their output unless quiet.
+    #[test]
+    fn test_annotations_stream_output() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct StreamingRunner {
+            recorder: RecordingRunner,
+            streamed: Mutex<Vec<String>>,
+        }
+        impl runner::CommandRunner for StreamingRunner {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                self.recorder.execute(command)
+            }
+
+            fn execute_streaming(
+                &self,
+                command: &CommandSpec,
+                on_line: &mut dyn FnMut(&str),
+            ) -> Result<Output> {
+                self.streamed.lock().unwrap().push(command.to_string());
+                on_line("test tests::adds ... ok");
+                self.recorder.execute(command)
+            }
+        }
+
+        let run = |quiet: bool| {
+            let runner = Arc::new(StreamingRunner::default());
+            let report = runner::with_runner(runner.clone(), || {
+                CIBuilder::default()
+                    .locked(false)
+                    .clippy_max(false)
+                    .check_first(false)
+                    .docs(false)
+                    .annotations(true)
+                    .quiet(quiet)
+                    .run_with_report()
+                    .unwrap()
+            });
+            assert!(report.success());
+            let streamed = runner.streamed.lock().unwrap().clone();
+            streamed
+        };
+        assert_eq!(
+            run(false),
+            [
+                "cargo clippy --message-format json -- -D warnings",
+                "cargo test --message-format json"
+            ]
+        );
+        assert!(run(true).is_empty());
+    }
+
+    /// Tests hook installation in a scratch repository: contents, executable bit,
+    /// overwrite protection, and resolution from a linked worktree.
+    #[test]
+    fn test_install_git_hook() {
+        use std::{fs, os::unix::fs::PermissionsExt, process::Command};
+        use xtasks::tasks::ci::{hooks::install_git_hook_in, HookKind};
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        fs::create_dir(&repo).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=xtasks",
+                    "-c",
+                    "user.email=x@t.s",
+                ])
+                .args(args)
+                .current_dir(&repo)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?} failed");
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "init"]);
+
+        let path = install_git_hook_in(&repo, HookKind::PrePush, false)
+            .unwrap();
+        assert_eq!(path, repo.join(".git/hooks/pre-push"));
+        let script = fs::read_to_string(&path).unwrap();
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("exec cargo xtask ci\n"));
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+
+        // Our own hook is replaced silently, a foreign one only with force.
+        install_git_hook_in(&repo, HookKind::PrePush, false).unwrap();
+        let pre_commit = repo.join(".git/hooks/pre-commit");
+        fs::write(&pre_commit, "#!/bin/sh\nmake lint\n").unwrap();
+        let err =
+            install_git_hook_in(&repo, HookKind::PreCommit, false)
+                .unwrap_err();
+        assert!(err.to_string().contains("--force"), "{err}");
+        assert_eq!(
+            fs::read_to_string(&pre_commit).unwrap(),
+            "#!/bin/sh\nmake lint\n"
+        );
+        install_git_hook_in(&repo, HookKind::PreCommit, true).unwrap();
+        assert!(fs::read_to_string(&pre_commit)
+            .unwrap()
+            .contains("cargo clippy --all-targets -- -D warnings"));
+
+        // Hooks installed from a linked worktree land in the shared hooks dir.
+        let worktree = dir.path().join("worktree");
+        git(&["worktree", "add", "-q", worktree.to_str().unwrap()]);
+        fs::remove_file(repo.join(".git/hooks/pre-push")).unwrap();
+        let path =
+            install_git_hook_in(&worktree, HookKind::PrePush, false)
+                .unwrap();
+        assert_eq!(
+            fs::canonicalize(path).unwrap(),
+            fs::canonicalize(repo.join(".git/hooks/pre-push")).unwrap()
+        );
+
+        // A dry run resolves the hooks dir but writes nothing.
+        fs::remove_file(repo.join(".git/hooks/pre-push")).unwrap();
+        let path =
+            runner::with_runner(Arc::new(runner::DryRunRunner), || {
+                install_git_hook_in(&repo, HookKind::PrePush, false)
+            })
+            .unwrap();
+        assert_eq!(path, repo.join(".git/hooks/pre-push"));
+        assert!(!path.exists());
+    }
+
+    /// Tests that `cargo check` runs right before the tests and that a failing
+    /// check skips the tests, even when fail-fast is disabled.
+    #[test]
+    fn test_check_first_skips_tests() {
+        let report = runner::with_runner(
+            Arc::new(RecordingRunner::default().failing("cargo check")),
+            || {
+                CIBuilder::default()
+                    .locked(false)
+                    .fail_fast(false)
+                    .run_with_report()
+                    .unwrap()
+            },
+        );
+        let statuses: Vec<(&str, &StepStatus)> = report
+            .steps
+            .iter()
+            .map(|step| (step.name.as_str(), &step.status))
+            .collect();
+        assert_eq!(statuses[0], ("fmt", &StepStatus::Passed));
+        assert_eq!(statuses[1], ("clippy", &StepStatus::Passed));
+        assert_eq!(statuses[2].0, "check");
+        assert!(matches!(
+            statuses[2].1,
+            StepStatus::Failed(error)
+                if error.contains("compilation failed at the check stage")
+        ));
+        assert_eq!(statuses[3], ("test", &StepStatus::Skipped));
+        assert_eq!(statuses[4], ("docs", &StepStatus::Passed));
+
+        let ci = CIBuilder::default()
+            .locked(false)
+            .check_first(false)
+            .build()
+            .unwrap();
+        assert!(planned(&ci).iter().all(|(name, _)| name != "check"));
+    }
+
+    /// Tests that crates implicated in a failed build are extracted from the output.
+    #[test]
+    fn test_minimal_versions_suspects() {
+        use xtasks::tasks::ci::minimal_versions::suspect_crates;
+
+        let output = "\
+error[E0599]: no method named `is_some_and` found
+  --> /home/ci/.cargo/registry/src/index.crates.io-6f17d22bba15001f/toml_edit-0.19.0/src/de.rs:12:5
+error: could not compile `toml_edit` (lib) due to 1 previous error
+error[E0433]: failed to resolve: could not find `Lazy` in crate `once_cell`
+";
+        assert_eq!(suspect_crates(output), ["toml_edit", "once_cell"]);
+        assert!(suspect_crates("warning: unused import").is_empty());
+    }
+
+    /// Tests that the lockfile is restored when the minimal-versions check fails.
+    #[test]
+    fn test_minimal_versions_restores_lockfile() {
+        use std::fs;
+        use xtasks::tasks::ci::minimal_versions::check_minimal_versions;
+
+        /// Rewrites the lockfile on `update` and fails the `check`.
+        struct Downgrade(std::path::PathBuf);
+        impl runner::CommandRunner for Downgrade {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                let check = command.args[0] == "check";
+                if !check {
+                    fs::write(&self.0, "minimal").unwrap();
+                }
+                Ok(Output {
+                    status: ExitStatus::from_raw(i32::from(check) << 8),
+                    stdout: Vec::new(),
+                    stderr:
+                        b"error: could not compile `semver` (lib)\n"
+                            .to_vec(),
+                })
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let lockfile = dir.path().join("Cargo.lock");
+        fs::write(&lockfile, "original").unwrap();
+        let error = runner::with_runner(
+            Arc::new(Downgrade(lockfile.clone())),
+            || check_minimal_versions(dir.path()).unwrap_err(),
+        );
+        assert!(format!("{error:#}").contains(
+            "the lower version bound of `semver` is likely too low"
+        ));
+        assert_eq!(fs::read_to_string(&lockfile).unwrap(), "original");
+        assert!(!dir.path().join("Cargo.lock.xtasks-backup").exists());
+
+        let ci = CIBuilder::default()
+            .locked(false)
+            .minimal_versions(true)
+            .build()
+            .unwrap();
+        assert!(ci
+            .plan()
+            .iter()
+            .any(|step| step.name == "minimal versions"));
+    }
+
+    /// Tests that a step returning `StepSkipped` is skipped rather than failed,
+    /// and that the coverage check waits for the tests to pass.
+    #[test]
+    fn test_coverage_threshold_step() {
+        use xtasks::tasks::ci::StepSkipped;
+
+        let report = runner::with_runner(
+            Arc::new(RecordingRunner::default()),
+            || {
+                CIBuilder::default()
+                    .locked(false)
+                    .add_step("coverage", || {
+                        Err(StepSkipped::new(
+                            "cargo tarpaulin does not support freebsd-x86_64",
+                        )
+                        .into())
+                    })
+                    .run_with_report()
+                    .unwrap()
+            },
+        );
+        assert!(report.success());
+        assert_eq!(
+            report.steps.last().unwrap().status,
+            StepStatus::Skipped
+        );
+
+        let ci = CIBuilder::default()
+            .locked(false)
+            .fail_fast(false)
+            .coverage_threshold(80.0)
+            .build()
+            .unwrap();
+        let report = runner::with_runner(
+            Arc::new(RecordingRunner::default().failing("cargo test")),
+            || ci.run_with_report(),
+        );
+        let coverage = report.steps.last().unwrap();
+        assert_eq!(coverage.name, "coverage");
+        assert_eq!(coverage.status, StepStatus::Skipped);
+    }
+
+    /// Tests that the coverage check is skipped when `check` fails, since the
+    /// skipped tests count as a failed dependency.
+    #[test]
+    fn test_coverage_threshold_skipped_after_check_failure() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .fail_fast(false)
+            .coverage_threshold(80.0)
+            .build()
+            .unwrap();
+        let report = runner::with_runner(
+            Arc::new(RecordingRunner::default().failing("cargo check")),
+            || ci.run_with_report(),
+        );
+        let status = |name: &str| {
+            report
+                .steps
+                .iter()
+                .find(|step| step.name == name)
+                .map(|step| step.status.clone())
+                .unwrap()
+        };
+        assert_eq!(status("test"), StepStatus::Skipped);
+        assert_eq!(status("coverage"), StepStatus::Skipped);
+    }
+
+    /// Tests that the coverage check waits for every feature variant's tests.
+    #[test]
+    fn test_coverage_threshold_skipped_after_variant_failure() {
+        let ci = CIBuilder::default()
+            .locked(false)
+            .fail_fast(false)
+            .check_first(false)
+            .features(FeatureSelection {
+                all: true,
+                none_default: true,
+                ..FeatureSelection::default()
+            })
+            .coverage_threshold(80.0)
+            .build()
+            .unwrap();
+        let report = runner::with_runner(
+            Arc::new(
+                RecordingRunner::default()
+                    .failing("cargo test --no-default-features"),
+            ),
+            || ci.run_with_report(),
+        );
+        let coverage = report.steps.last().unwrap();
+        assert_eq!(coverage.name, "coverage");
+        assert_eq!(coverage.status, StepStatus::Skipped);
+    }
+
+    /// Tests the rustup and Miri command sequence, and that the Miri steps are
+    /// skipped when `XTASKS_SKIP_MIRI` is set.
+    #[test]
+    fn test_miri_steps() {
+        use xtasks::tasks::ci::{MiriConfig, SKIP_MIRI_ENV};
+
+        let ci = CIBuilder::default()
+            .locked(false)
+            .check_first(false)
+            .docs(false)
+            .miri(MiriConfig {
+                packages: vec!["arena".to_string(), "ffi".to_string()],
+                flags: vec!["-Zmiri-strict-provenance".to_string()],
+            })
+            .build()
+            .unwrap();
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .failing("cargo +nightly miri test --package ffi"),
+        );
+        let report = runner::with_runner(runner.clone(), || {
+            ci.run_with_report()
+        });
+        let calls = runner.calls();
+        let lines: Vec<String> =
+            calls.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            lines[3..],
+            [
+                "rustup +nightly component add miri",
+                "cargo +nightly miri test --package arena",
+                "cargo +nightly miri test --package ffi",
+            ]
+        );
+        assert_eq!(
+            calls[4].env,
+            [(
+                "MIRIFLAGS".to_string(),
+                "-Zmiri-strict-provenance".to_string()
+            )]
+        );
+        let statuses: Vec<(&str, bool)> = report.steps[3..]
+            .iter()
+            .map(|step| {
+                (step.name.as_str(), step.status == StepStatus::Passed)
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ("miri setup", true),
+                ("miri (arena)", true),
+                ("miri (ffi)", false)
+            ]
+        );
+
+        std::env::set_var(SKIP_MIRI_ENV, "1");
+        let runner = Arc::new(RecordingRunner::default());
+        let report = runner::with_runner(runner.clone(), || {
+            ci.run_with_report()
+        });
+        std::env::remove_var(SKIP_MIRI_ENV);
+        assert!(report.success());
+        assert_eq!(runner.calls().len(), 3);
+        let miri = report.steps.last().unwrap();
+        assert_eq!(
+            (miri.name.as_str(), &miri.status),
+            ("miri", &StepStatus::Skipped)
+        );
+    }
+
+    /// Tests that the arguments after `--` reach the test step verbatim.
+    #[test]
+    fn test_ci_cli_test_args() {
+        use xtasks::tasks::main_with_args;
+
+        let args: Vec<String> =
+            ["xtask", "ci", "--", "--nocapture", "--skip", "slow test"]
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+        let recorder = Arc::new(RecordingRunner::default());
+        runner::with_runner(recorder.clone(), || main_with_args(&args))
+            .unwrap();
+        let calls = recorder.calls();
+        let test = calls
+            .iter()
+            .find(|call| {
+                call.args.first().map(String::as_str) == Some("test")
+            })
+            .unwrap();
+        assert!(test.args.ends_with(&[
+            "--".to_string(),
+            "--nocapture".to_string(),
+            "--skip".to_string(),
+            "slow test".to_string(),
+        ]));
+        assert!(test
+            .shell_line()
+            .ends_with(" -- --nocapture --skip 'slow test'"));
+    }
 }
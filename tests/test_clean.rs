@@ -0,0 +1,170 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, sync::Arc};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::clean::{Clean, CleanBuilder};
+
+    /// The artifacts created in the fake workspace, relative to its root.
+    const ARTIFACTS: [&str; 7] = [
+        "target/coverage/html/index.html",
+        "target/llvm-cov/lcov.info",
+        "default_123.profraw",
+        "target/doc/app/index.html",
+        "docs/book/book/index.html",
+        "target/xtask/bloat.json",
+        "target/debug/app",
+    ];
+
+    /// Creates a workspace with every kind of artifact.
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), "[workspace]\n")
+            .unwrap();
+        for artifact in ARTIFACTS {
+            let path = dir.path().join(artifact);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "artifact").unwrap();
+        }
+        dir
+    }
+
+    /// Returns the artifacts still present in the workspace.
+    fn remaining(root: &Path) -> Vec<&'static str> {
+        ARTIFACTS
+            .into_iter()
+            .filter(|artifact| root.join(artifact).exists())
+            .collect()
+    }
+
+    /// Tests that each flag removes its artifacts only, and that the default
+    /// cleanup removes the coverage reports and the cache.
+    #[test]
+    fn test_clean_selection() {
+        let run = |clean: Clean| {
+            let dir = workspace();
+            let _ = clean.run_in(dir.path()).unwrap();
+            remaining(dir.path())
+        };
+
+        assert_eq!(
+            run(Clean::default()),
+            [
+                "target/doc/app/index.html",
+                "docs/book/book/index.html",
+                "target/debug/app",
+            ]
+        );
+        assert_eq!(
+            run(CleanBuilder::default()
+                .coverage(true)
+                .build()
+                .unwrap()),
+            [
+                "target/doc/app/index.html",
+                "docs/book/book/index.html",
+                "target/xtask/bloat.json",
+                "target/debug/app",
+            ]
+        );
+        assert_eq!(
+            run(CleanBuilder::default().docs(true).build().unwrap()),
+            [
+                "target/coverage/html/index.html",
+                "target/llvm-cov/lcov.info",
+                "default_123.profraw",
+                "target/xtask/bloat.json",
+                "target/debug/app",
+            ]
+        );
+        assert_eq!(
+            run(CleanBuilder::default()
+                .cache(true)
+                .docs(true)
+                .build()
+                .unwrap()),
+            [
+                "target/coverage/html/index.html",
+                "target/llvm-cov/lcov.info",
+                "default_123.profraw",
+                "target/debug/app",
+            ]
+        );
+    }
+
+    /// Tests that `all` removes every artifact and runs `cargo clean`, and
+    /// that a dry run removes nothing.
+    #[test]
+    fn test_clean_all_and_dry_run() {
+        let dir = workspace();
+        let recorder = Arc::new(RecordingRunner::default());
+        let removed = with_runner(recorder.clone(), || {
+            CleanBuilder::default()
+                .all(true)
+                .dry_run(true)
+                .build()
+                .unwrap()
+                .run_in(dir.path())
+        })
+        .unwrap();
+        assert_eq!(removed.len(), 6);
+        assert_eq!(remaining(dir.path()), ARTIFACTS);
+        assert!(recorder.calls().is_empty());
+
+        let _ = with_runner(recorder.clone(), || {
+            CleanBuilder::default()
+                .all(true)
+                .build()
+                .unwrap()
+                .run_in(dir.path())
+        })
+        .unwrap();
+        assert_eq!(remaining(dir.path()), ["target/debug/app"]);
+        let manifest = dir.path().join("Cargo.toml");
+        assert_eq!(
+            recorder.command_lines(),
+            [format!(
+                "cargo clean --manifest-path {}",
+                manifest.display()
+            )]
+        );
+    }
+
+    /// Tests that nothing is removed outside a workspace root or through a
+    /// link leaving it.
+    #[test]
+    fn test_clean_outside_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("target/xtask")).unwrap();
+        let err = Clean::default().run_in(dir.path()).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("which is not a cargo workspace root"),
+            "{err}"
+        );
+        assert!(dir.path().join("target/xtask").exists());
+
+        #[cfg(unix)]
+        {
+            let root = workspace();
+            let outside = tempfile::tempdir().unwrap();
+            fs::write(outside.path().join("keep.txt"), "keep").unwrap();
+            fs::remove_dir_all(root.path().join("target/xtask"))
+                .unwrap();
+            std::os::unix::fs::symlink(
+                outside.path(),
+                root.path().join("target/xtask"),
+            )
+            .unwrap();
+            let err = Clean::default().run_in(root.path()).unwrap_err();
+            assert!(
+                err.to_string().contains("outside the workspace root"),
+                "{err}"
+            );
+            assert!(outside.path().join("keep.txt").exists());
+            assert!(root.path().join("target/coverage").exists());
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::{
+        ci::CIBuilder,
+        clippy::{
+            lint_args, run_clippy, ClippyOptions, ClippyOptionsBuilder,
+        },
+    };
+
+    /// Returns the command line run by the task with `opts`.
+    fn command_line(opts: &ClippyOptions) -> String {
+        let runner = Arc::new(RecordingRunner::default());
+        let _ =
+            with_runner(runner.clone(), || run_clippy(opts)).unwrap();
+        let lines = runner.command_lines();
+        let clippy: Vec<&String> = lines
+            .iter()
+            .filter(|line| line.starts_with("cargo clippy"))
+            .collect();
+        assert_eq!(clippy.len(), 1, "{lines:?}");
+        clippy[0].clone()
+    }
+
+    /// Tests the arguments of the task across flag combinations.
+    #[test]
+    fn test_clippy_command() {
+        assert_eq!(
+            command_line(&ClippyOptions::default()),
+            "cargo clippy -- -D warnings"
+        );
+        assert_eq!(
+            command_line(
+                &ClippyOptionsBuilder::default()
+                    .max(true)
+                    .build()
+                    .unwrap()
+            ),
+            "cargo clippy --all-targets --all-features -- -D warnings -W clippy::pedantic -W clippy::nursery"
+        );
+        assert_eq!(
+            command_line(
+                &ClippyOptionsBuilder::default()
+                    .all_targets(true)
+                    .warn(vec!["clippy::unwrap_used".to_string()])
+                    .allow(vec![
+                        "clippy::too_many_lines".to_string(),
+                        "dead_code".to_string(),
+                    ])
+                    .build()
+                    .unwrap()
+            ),
+            "cargo clippy --all-targets -- -D warnings -W clippy::unwrap_used -A clippy::too_many_lines -A dead_code"
+        );
+        assert_eq!(
+            command_line(
+                &ClippyOptionsBuilder::default()
+                    .fix(true)
+                    .all_features(true)
+                    .verbose(true)
+                    .build()
+                    .unwrap()
+            ),
+            "cargo clippy --verbose --fix --allow-dirty --allow-staged --all-features -- -D warnings"
+        );
+        assert_eq!(
+            command_line(
+                &ClippyOptionsBuilder::default()
+                    .annotations(true)
+                    .build()
+                    .unwrap()
+            ),
+            "cargo clippy --message-format json -- -D warnings"
+        );
+    }
+
+    /// Tests that a denied lint fails the task as a lint step.
+    #[test]
+    fn test_clippy_failure() {
+        let runner = Arc::new(
+            RecordingRunner::default().failing("cargo clippy"),
+        );
+        let err = with_runner(runner, || {
+            run_clippy(&ClippyOptions::default())
+        })
+        .unwrap_err();
+        assert_eq!(
+            xtasks::ops::error::exit_code(&err),
+            xtasks::ops::error::EXIT_LINT
+        );
+    }
+
+    /// Tests that the CI checks pass the configured lints the same way.
+    #[test]
+    fn test_ci_shares_lint_sets() {
+        let ci = CIBuilder::default()
+            .clippy_max(true)
+            .clippy_warn(vec!["clippy::unwrap_used".to_string()])
+            .clippy_allow(vec!["clippy::too_many_lines".to_string()])
+            .docs(false)
+            .check_first(false)
+            .annotations(false)
+            .locked(false)
+            .build()
+            .unwrap();
+        let clippy = ci
+            .plan()
+            .into_iter()
+            .find(|step| step.name == "clippy")
+            .and_then(|step| step.command)
+            .unwrap();
+        let expected =
+            lint_args(true, &ci.clippy_warn, &ci.clippy_allow)
+                .join(" ");
+        assert!(
+            clippy.to_string().ends_with(&format!("-- {expected}")),
+            "{clippy}"
+        );
+        assert!(expected.ends_with(
+            "-W clippy::unwrap_used -A clippy::too_many_lines"
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, sync::Arc};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::{config::XtaskConfig, main_with_args};
+
+    /// Tests that the settings are found in `xtask.toml` first, then in the
+    /// `Cargo.toml` metadata, searching the parents up to the workspace root.
+    #[test]
+    fn test_config_discovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let member = dir.path().join("crates/app");
+        fs::create_dir_all(&member).unwrap();
+        fs::write(
+            member.join("Cargo.toml"),
+            "[package]\nname = \"app\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/app\"]\n\n\
+             [package]\nname = \"root\"\n\n\
+             [package.metadata.xtask.powerset]\ndepth = 1\n",
+        )
+        .unwrap();
+
+        let config = XtaskConfig::discover(&member).unwrap();
+        assert_eq!(
+            config.source.as_deref(),
+            Some(dir.path().join("Cargo.toml").as_path())
+        );
+        assert_eq!(config.powerset.build().unwrap().depth, 1);
+
+        fs::write(
+            dir.path().join("xtask.toml"),
+            "[powerset]\ndepth = 3\n\n[ci]\nnightly = true\n",
+        )
+        .unwrap();
+        let config = XtaskConfig::discover(&member).unwrap();
+        assert_eq!(
+            config.source.as_deref(),
+            Some(dir.path().join("xtask.toml").as_path())
+        );
+        assert_eq!(config.powerset.build().unwrap().depth, 3);
+        assert!(config.ci.build().unwrap().nightly);
+
+        // The search stops at the workspace root.
+        let outer = tempfile::tempdir().unwrap();
+        let root = outer.path().join("workspace");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(
+            outer.path().join("xtask.toml"),
+            "[powerset]\ndepth = 3\n",
+        )
+        .unwrap();
+        fs::write(root.join("Cargo.toml"), "[workspace]\n").unwrap();
+        let config = XtaskConfig::discover(&root).unwrap();
+        assert!(config.source.is_none());
+        assert_eq!(config.powerset.build().unwrap().depth, 2);
+    }
+
+    /// Tests that the command line overrides the configured settings, which
+    /// override the built-in defaults.
+    #[test]
+    fn test_config_precedence() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |config: &str, flags: &[&str]| {
+            let path = dir.path().join("xtask.toml");
+            fs::write(&path, config).unwrap();
+            let mut args = vec![
+                "xtask".to_string(),
+                "--config-file".to_string(),
+                path.to_string_lossy().into_owned(),
+                "powerset".to_string(),
+                "--only".to_string(),
+                "test".to_string(),
+            ];
+            args.extend(flags.iter().map(ToString::to_string));
+            let recorder = Arc::new(RecordingRunner::default());
+            with_runner(recorder.clone(), || main_with_args(&args))
+                .unwrap();
+            recorder.command_lines().pop().unwrap()
+        };
+
+        assert_eq!(
+            run("", &[]),
+            "cargo hack --workspace --exclude xtask --feature-powerset \
+             --depth 2 test"
+        );
+        assert_eq!(
+            run("[powerset]\ndepth = 1\nkeep_going = true\n", &[]),
+            "cargo hack --keep-going --workspace --exclude xtask \
+             --feature-powerset --depth 1 test"
+        );
+        assert_eq!(
+            run(
+                "[powerset]\ndepth = 1\nkeep_going = true\n",
+                &["--depth", "2"]
+            ),
+            "cargo hack --keep-going --workspace --exclude xtask \
+             --feature-powerset --depth 2 test"
+        );
+        assert_eq!(
+            run("[powerset]\nsteps = [\"Clippy\"]\n", &[]),
+            "cargo hack --workspace --exclude xtask --feature-powerset \
+             --depth 2 test"
+        );
+    }
+
+    /// Tests that invalid settings are reported with their key and file.
+    #[test]
+    fn test_config_errors() {
+        let parse = |table: serde_json::Value| {
+            XtaskConfig::parse(&table, "xtask.toml")
+                .unwrap_err()
+                .to_string()
+        };
+        let message = parse(serde_json::json!({
+            "powerset": { "keep_going": true, "depth": "three" }
+        }));
+        assert!(
+            message.starts_with(
+                "Invalid setting 'powerset.depth' in xtask.toml: invalid type"
+            ),
+            "{message}"
+        );
+        let message = parse(
+            serde_json::json!({ "coverage": { "fail_undr": 80 } }),
+        );
+        assert!(
+            message.starts_with(
+                "Invalid setting 'coverage.fail_undr' in xtask.toml: \
+                 unknown field `fail_undr`"
+            ),
+            "{message}"
+        );
+        assert_eq!(
+            parse(serde_json::json!({ "bloat": 3 })),
+            "Invalid setting 'bloat' in xtask.toml: expected a table"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xtask.toml");
+        fs::write(&path, "[powerset\ndepth = 1\n").unwrap();
+        let err = XtaskConfig::load(&path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Failed to parse {}", path.display())
+        );
+        assert!(
+            XtaskConfig::load(Path::new("missing/xtask.toml")).is_err()
+        );
+    }
+
+    /// Tests that `config --show` renders the merged settings as TOML.
+    #[test]
+    fn test_config_show() {
+        let config = XtaskConfig::parse(
+            &serde_json::json!({ "powerset": { "depth": 1 } }),
+            "xtask.toml",
+        )
+        .unwrap();
+        let rendered = config.to_toml().unwrap();
+        for section in ["[ci]", "[powerset]", "[coverage]", "[bloat]"] {
+            assert!(rendered.contains(section), "{rendered}");
+        }
+        assert!(rendered.contains("depth = 1"), "{rendered}");
+        let parsed: toml::Table = toml::from_str(&rendered).unwrap();
+        assert_eq!(
+            parsed["powerset"]["exclude_packages"][0].as_str(),
+            Some("xtask")
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xtask.toml");
+        fs::write(&path, "[powerset]\ndepth = 1\n").unwrap();
+        let args: Vec<String> = [
+            "xtask",
+            "--config-file",
+            &path.to_string_lossy(),
+            "config",
+            "--show",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        main_with_args(&args).unwrap();
+    }
+}
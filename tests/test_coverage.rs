@@ -6,61 +6,22 @@ mod tests {
     use std::ffi::OsStr;
     use std::io::Result;
     use std::os::unix::process::ExitStatusExt;
-    use std::process::{Command, ExitStatus, Output};
+    use std::process::{ExitStatus, Output};
 
     trait CommandRunner {
-        fn new<S: AsRef<OsStr>>(program: S) -> Self
-        where
-            Self: Sized;
         fn args<I, S>(self, args: I) -> Self
         where
             I: IntoIterator<Item = S>,
             S: AsRef<OsStr>,
             Self: Sized;
-        fn env<K, V>(self, key: K, value: V) -> Self
-        where
-            K: AsRef<OsStr>,
-            V: AsRef<OsStr>,
-            Self: Sized;
         fn spawn(&mut self) -> Result<Output>;
     }
 
-    struct RealCommand(Command);
-
-    impl CommandRunner for RealCommand {
-        fn new<S: AsRef<OsStr>>(program: S) -> Self {
-            Self(Command::new(program))
-        }
-
-        fn args<I, S>(mut self, args: I) -> Self
-        where
-            I: IntoIterator<Item = S>,
-            S: AsRef<OsStr>,
-        {
-            self.0.args(args);
-            self
-        }
-
-        fn env<K, V>(mut self, key: K, value: V) -> Self
-        where
-            K: AsRef<OsStr>,
-            V: AsRef<OsStr>,
-        {
-            self.0.env(key, value);
-            self
-        }
-
-        fn spawn(&mut self) -> Result<Output> {
-            self.0.output()
-        }
-    }
-
     struct MockCommand {
         status: ExitStatus,
         stdout: Vec<u8>,
         stderr: Vec<u8>,
         args: Vec<String>,
-        env: Vec<(String, String)>,
     }
 
     impl MockCommand {
@@ -70,7 +31,6 @@ mod tests {
                 stdout: Vec::new(),
                 stderr: Vec::new(),
                 args: Vec::new(),
-                env: Vec::new(),
             }
         }
 
@@ -86,16 +46,6 @@ mod tests {
     }
 
     impl CommandRunner for MockCommand {
-        fn new<S: AsRef<OsStr>>(_cmd: S) -> Self {
-            Self {
-                status: ExitStatus::from_raw(0),
-                stdout: Vec::new(),
-                stderr: Vec::new(),
-                args: Vec::new(),
-                env: Vec::new(),
-            }
-        }
-
         fn args<I, S>(mut self, args: I) -> Self
         where
             I: IntoIterator<Item = S>,
@@ -108,18 +58,6 @@ mod tests {
             self
         }
 
-        fn env<K, V>(mut self, key: K, value: V) -> Self
-        where
-            K: AsRef<OsStr>,
-            V: AsRef<OsStr>,
-        {
-            self.env.push((
-                key.as_ref().to_string_lossy().to_string(),
-                value.as_ref().to_string_lossy().to_string(),
-            ));
-            self
-        }
-
         fn spawn(&mut self) -> Result<Output> {
             Ok(Output {
                 status: self.status,
@@ -129,10 +67,8 @@ mod tests {
         }
     }
 
-    #[test]
-    fn test_coverage() {
-        // Using MockCommand for testing
-        let example_output = br"
+    /// The output of `cargo tarpaulin` for a project with two source files.
+    const TARPAULIN_OUTPUT: &str = r"
 Jan 30 21:43:33.715  INFO cargo_tarpaulin::config: Creating config
 Jan 30 21:43:33.908  INFO cargo_tarpaulin: Running Tarpaulin
 Jan 30 21:43:33.908  INFO cargo_tarpaulin: Building project
@@ -157,6 +93,25 @@ Jan 30 21:43:35.563  INFO cargo_tarpaulin::report: Coverage Results:
 ||
 42.86% coverage, 3/7 lines covered
 ";
+
+    /// The output of `cargo metadata --no-deps` for a two-member workspace.
+    const METADATA_OUTPUT: &str = r#"{
+  "packages": [
+    { "name": "app", "id": "path+file:///ws/app#0.1.0" },
+    { "name": "xtask", "id": "path+file:///ws/xtask#0.1.0" }
+  ],
+  "workspace_members": [
+    "path+file:///ws/app#0.1.0",
+    "path+file:///ws/xtask#0.1.0"
+  ],
+  "target_directory": "/ws/target",
+  "version": 1
+}"#;
+
+    #[test]
+    fn test_coverage() {
+        // Using MockCommand for testing
+        let example_output = TARPAULIN_OUTPUT.as_bytes();
         let cmd = MockCommand::new("cargo")
             .args(["tarpaulin"])
             .status(ExitStatus::from_raw(0))
@@ -167,4 +122,1481 @@ Jan 30 21:43:35.563  INFO cargo_tarpaulin::report: Coverage Results:
         assert!(cmd.status.success());
         assert_eq!(cmd.stdout, example_output);
     }
+
+    /// Tests that canned tarpaulin JSON summaries go through the threshold check.
+    #[test]
+    fn test_coverage_threshold() {
+        use xtasks::tasks::coverage::{
+            ensure_threshold, parse_json_report,
+        };
+
+        let report = r#"{"files":[],"covered":732,"coverable":1000,"coverage":73.2}"#;
+        let coverage = parse_json_report(report).unwrap();
+        assert_eq!(
+            ensure_threshold(coverage, 80.0).unwrap_err().to_string(),
+            "coverage 73.2% below threshold 80%"
+        );
+        assert!(ensure_threshold(coverage, 73.2).is_ok());
+        assert_eq!(
+            ensure_threshold(79.96, 80.0).unwrap_err().to_string(),
+            "coverage 79.96% below threshold 80%"
+        );
+        assert_eq!(
+            ensure_threshold(79.9996, 80.0).unwrap_err().to_string(),
+            "coverage 79.9996% below threshold 80%"
+        );
+
+        let full = r#"{"files":[],"covered":7,"coverable":7,"coverage":100.0}"#;
+        assert!(ensure_threshold(
+            parse_json_report(full).unwrap(),
+            99.5
+        )
+        .is_ok());
+        assert!(parse_json_report(r#"{"files":[]}"#).is_err());
+        assert!(parse_json_report("42.86% coverage").is_err());
+    }
+
+    /// Tests the platforms on which the coverage check runs.
+    #[test]
+    fn test_coverage_platforms() {
+        use xtasks::tasks::coverage::is_supported_platform;
+
+        assert!(is_supported_platform("linux", "x86_64"));
+        assert!(is_supported_platform("macos", "aarch64"));
+        assert!(!is_supported_platform("freebsd", "x86_64"));
+        assert!(!is_supported_platform("linux", "riscv64"));
+    }
+
+    /// Tests the `--out` flags assembled for single and multiple formats.
+    #[test]
+    fn test_coverage_formats() {
+        use xtasks::tasks::coverage::{command, CoverageFormat};
+
+        assert_eq!(
+            command(&[CoverageFormat::Html], false).to_string(),
+            "cargo tarpaulin --out Html"
+        );
+        assert_eq!(
+            command(&[CoverageFormat::Lcov, CoverageFormat::Xml], true)
+                .to_string(),
+            "cargo tarpaulin --out Lcov --out Xml --dev"
+        );
+        assert_eq!(
+            "LCOV".parse::<CoverageFormat>().unwrap(),
+            CoverageFormat::Lcov
+        );
+        assert_eq!(
+            "pdf".parse::<CoverageFormat>().unwrap_err().to_string(),
+            "Unknown coverage format 'pdf', expected one of: html, lcov, xml, json, stdout"
+        );
+    }
+
+    /// Tests that the `coverage` subcommand accepts repeated `--out` flags.
+    #[test]
+    fn test_coverage_cli_formats() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{self, RecordingRunner},
+            tasks::main_with_args,
+        };
+
+        let run = |args: &[&str]| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo tarpaulin", TARPAULIN_OUTPUT),
+            );
+            let args: Vec<String> =
+                args.iter().map(ToString::to_string).collect();
+            runner::with_runner(recorder.clone(), || {
+                main_with_args(&args)
+            })
+            .unwrap();
+            // Leave out the git commands looking up the revision for the history.
+            recorder
+                .command_lines()
+                .into_iter()
+                .filter(|line| !line.starts_with("git "))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            run(&[
+                "xtask", "coverage", "--out", "lcov", "--out", "html"
+            ]),
+            ["cargo tarpaulin --out Lcov --out Html --output-dir target/coverage"]
+        );
+        assert_eq!(
+            run(&["xtask", "coverage"]),
+            ["cargo tarpaulin --out Html --output-dir target/coverage"]
+        );
+    }
+
+    /// Tests that `--dev` is a flag, off unless passed.
+    #[test]
+    fn test_coverage_cli_dev() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{self, RecordingRunner},
+            tasks::main_with_args,
+        };
+
+        let run = |args: &[&str]| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo tarpaulin", TARPAULIN_OUTPUT),
+            );
+            let args: Vec<String> =
+                args.iter().map(ToString::to_string).collect();
+            runner::with_runner(recorder.clone(), || {
+                main_with_args(&args)
+            })
+            .unwrap();
+            recorder.command_lines()[0].clone()
+        };
+        assert_eq!(
+            run(&["xtask", "coverage", "--engine", "tarpaulin"]),
+            "cargo tarpaulin --out Html --output-dir target/coverage"
+        );
+        assert_eq!(
+            run(&["xtask", "coverage", "--engine", "tarpaulin", "--dev"]),
+            "cargo tarpaulin --out Html --dev --output-dir target/coverage"
+        );
+        assert_eq!(
+            run(&["xtask", "coverage", "--engine", "tarpaulin", "-d"]),
+            "cargo tarpaulin --out Html --dev --output-dir target/coverage"
+        );
+    }
+
+    /// A runner that answers every command with the given stdout.
+    struct OutputRunner(&'static str);
+
+    impl xtasks::ops::runner::CommandRunner for OutputRunner {
+        fn execute(
+            &self,
+            _command: &xtasks::ops::runner::CommandSpec,
+        ) -> Result<Output> {
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: self.0.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            })
+        }
+
+        fn which(&self, program: &str) -> Option<std::path::PathBuf> {
+            Some(program.into())
+        }
+    }
+
+    /// Tests that the total is read from several captured tarpaulin outputs.
+    #[test]
+    fn test_parse_total_percentage() {
+        use xtasks::tasks::coverage::parse_total_percentage;
+
+        assert_eq!(
+            parse_total_percentage(TARPAULIN_OUTPUT),
+            Some(42.86)
+        );
+        let logged = "\
+Mar 02 10:01:12.000  INFO cargo_tarpaulin::report: Coverage Results:
+|| Tested/Total Lines:
+|| src/main.rs: 10/10
+Mar 02 10:01:12.001  INFO cargo_tarpaulin: 100.00% coverage, 10/10 lines covered
+";
+        assert_eq!(parse_total_percentage(logged), Some(100.0));
+        let json =
+            r#"{"files":[],"covered":5,"coverable":8,"coverage":62.5}"#;
+        assert_eq!(parse_total_percentage(json), Some(62.5));
+        assert_eq!(parse_total_percentage("test result: ok."), None);
+    }
+
+    /// Tests that the default configuration, the builder defaults and an
+    /// empty configuration file describe the same run.
+    #[test]
+    fn test_coverage_default() {
+        use xtasks::tasks::coverage::{
+            Coverage, CoverageBuilder, CoverageFormat,
+        };
+
+        let coverage = Coverage::default();
+        assert_eq!(coverage.formats, [CoverageFormat::Html]);
+        assert!(coverage.auto_install);
+        assert_eq!(
+            coverage,
+            CoverageBuilder::default().build().unwrap()
+        );
+        assert_eq!(
+            serde_json::from_str::<Coverage>("{}").unwrap(),
+            coverage
+        );
+    }
+
+    /// Tests the pass and fail decisions of `fail_under`.
+    #[test]
+    fn test_coverage_fail_under() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner,
+            tasks::coverage::{CoverageBuilder, CoverageFormat},
+        };
+
+        let run = |fail_under: f32| {
+            runner::with_runner(
+                Arc::new(OutputRunner(TARPAULIN_OUTPUT)),
+                || {
+                    CoverageBuilder::default()
+                        .formats([CoverageFormat::Lcov])
+                        .fail_under(fail_under)
+                        .run()
+                },
+            )
+        };
+        assert!(run(40.0).is_ok());
+        assert!(run(42.86).is_ok());
+        assert_eq!(
+            run(80.0).unwrap_err().to_string(),
+            "coverage 42.9% below threshold 80%"
+        );
+
+        let err = runner::with_runner(
+            Arc::new(OutputRunner("test result: ok.")),
+            || CoverageBuilder::default().fail_under(80.0).run(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("coverage results"), "{err}");
+    }
+
+    /// Tests that the threshold check runs a summary-only coverage with the
+    /// features and toolchain it is given.
+    #[test]
+    fn test_coverage_check_threshold_settings() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{with_runner, RecordingRunner},
+            tasks::{
+                ci::FeatureSelection,
+                coverage::{check_threshold, Coverage, CoverageEngine},
+            },
+        };
+
+        let llvm_cov = "\
+TOTAL              12               2 83.33%          4                 0   100.00%     40             6 85.00%
+";
+        let recorder =
+            Arc::new(RecordingRunner::default().with_stdout(
+                "rustup run stable cargo llvm-cov report",
+                llvm_cov,
+            ));
+        let config = Coverage {
+            engine: Some(CoverageEngine::LlvmCov),
+            toolchain: Some("stable".into()),
+            features: Some(FeatureSelection {
+                all: true,
+                ..FeatureSelection::default()
+            }),
+            ..Coverage::default()
+        };
+        let err = with_runner(recorder.clone(), || {
+            check_threshold(&config, 90.0)
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "coverage 85.0% below threshold 90%"
+        );
+        let commands: Vec<String> = recorder
+            .command_lines()
+            .into_iter()
+            .filter(|line| line.starts_with("rustup run"))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "rustup run stable cargo llvm-cov --no-report --all-features",
+                "rustup run stable cargo llvm-cov report --summary-only"
+            ]
+        );
+    }
+
+    /// Tests the commands each coverage engine runs.
+    #[test]
+    fn test_coverage_engine_commands() {
+        use xtasks::tasks::coverage::{
+            Coverage, CoverageEngine, CoverageFormat,
+        };
+
+        let config = Coverage {
+            formats: vec![CoverageFormat::Lcov, CoverageFormat::Html],
+            ..Coverage::default()
+        };
+        let lines = |engine: CoverageEngine| -> Vec<String> {
+            engine
+                .commands(&config)
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+        assert_eq!(
+            lines(CoverageEngine::Tarpaulin),
+            ["cargo tarpaulin --out Lcov --out Html --output-dir target/coverage"]
+        );
+        assert_eq!(
+            lines(CoverageEngine::LlvmCov),
+            [
+                "cargo llvm-cov --no-report",
+                "cargo llvm-cov report --lcov --output-path target/llvm-cov/lcov.info",
+                "cargo llvm-cov report --html --output-dir target/llvm-cov",
+                "cargo llvm-cov report --summary-only",
+            ]
+        );
+
+        let grcov = CoverageEngine::Grcov.commands(&config);
+        let report = "grcov target/coverage/profraw --binary-path target/debug/deps \
+                      --source-dir . --branch --ignore-not-existing --ignore /*";
+        assert_eq!(
+            lines(CoverageEngine::Grcov),
+            [
+                "rustup component add llvm-tools-preview".to_string(),
+                "cargo test".to_string(),
+                format!("{report} --output-types lcov --output-path target/coverage/lcov.info"),
+                format!("{report} --output-types html --output-path target/coverage/html"),
+                format!("{report} --output-types markdown"),
+            ]
+        );
+        assert_eq!(
+            grcov[1].env[0],
+            (
+                "RUSTFLAGS".to_string(),
+                "-Cinstrument-coverage".to_string()
+            )
+        );
+    }
+
+    /// Tests the totals read from the summary of each engine.
+    #[test]
+    fn test_coverage_engine_totals() {
+        use xtasks::tasks::coverage::CoverageEngine;
+
+        let llvm_cov = "\
+Filename      Regions  Missed Regions  Cover  Functions  Missed Functions  Executed  Lines  Missed Lines  Cover
+src/lib.rs         12               2 83.33%          4                 0   100.00%     40             6 85.00%
+TOTAL              12               2 83.33%          4                 0   100.00%     40             6 85.00%
+";
+        assert_eq!(
+            CoverageEngine::LlvmCov.parse_total(llvm_cov),
+            Some(85.0)
+        );
+        assert_eq!(
+            CoverageEngine::Grcov.parse_total(
+                "| src/lib.rs | 75.00% |\n\nTotal coverage: 75.00%\n"
+            ),
+            Some(75.0)
+        );
+        assert_eq!(
+            CoverageEngine::Tarpaulin.parse_total(TARPAULIN_OUTPUT),
+            Some(42.86)
+        );
+        assert_eq!(
+            CoverageEngine::Grcov
+                .parse_json_total(
+                    r#"{"coveragePercent":61.5,"children":{}}"#
+                )
+                .unwrap(),
+            61.5
+        );
+        assert_eq!(
+            "llvm-cov".parse::<CoverageEngine>().unwrap(),
+            CoverageEngine::LlvmCov
+        );
+    }
+
+    /// Tests that the first installed engine is preferred when none is configured.
+    #[test]
+    fn test_coverage_engine_detection() {
+        use std::{path::PathBuf, sync::Arc};
+        use xtasks::{
+            ops::runner::{self, CommandRunner, CommandSpec},
+            tasks::coverage::CoverageEngine,
+        };
+
+        struct Installed(&'static [&'static str]);
+        impl CommandRunner for Installed {
+            fn execute(
+                &self,
+                _command: &CommandSpec,
+            ) -> Result<Output> {
+                unreachable!("detection must not spawn commands")
+            }
+
+            fn which(&self, program: &str) -> Option<PathBuf> {
+                self.0
+                    .contains(&program)
+                    .then(|| PathBuf::from(program))
+            }
+        }
+
+        let detect = |installed| {
+            runner::with_runner(Arc::new(Installed(installed)), || {
+                CoverageEngine::detect()
+            })
+        };
+        assert_eq!(
+            detect(&["grcov", "cargo-llvm-cov"]),
+            CoverageEngine::LlvmCov
+        );
+        assert_eq!(detect(&["grcov"]), CoverageEngine::Grcov);
+        assert_eq!(
+            detect(&["grcov", "cargo-tarpaulin"]),
+            CoverageEngine::Tarpaulin
+        );
+    }
+
+    /// Tests that the embedded tarpaulin output is parsed into a typed report.
+    #[test]
+    fn test_coverage_report_fixture() {
+        use std::path::PathBuf;
+        use xtasks::tasks::coverage::{CoverageReport, FileCoverage};
+
+        let report =
+            CoverageReport::parse_tarpaulin(TARPAULIN_OUTPUT).unwrap();
+        assert_eq!(
+            report,
+            CoverageReport {
+                total_percent: 42.86,
+                lines_covered: 3,
+                lines_total: 7,
+                files: vec![
+                    FileCoverage {
+                        path: PathBuf::from("src/lib.rs"),
+                        covered: 3,
+                        total: 4,
+                        uncovered_lines: vec![6..=6],
+                    },
+                    FileCoverage {
+                        path: PathBuf::from("src/unused.rs"),
+                        covered: 0,
+                        total: 3,
+                        uncovered_lines: vec![4..=6],
+                    },
+                ],
+                ..CoverageReport::default()
+            }
+        );
+        assert_eq!(report.files[1].percent(), Some(0.0));
+
+        let json = serde_json::to_string(&report).unwrap();
+        let restored: CoverageReport =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, report);
+        assert!(CoverageReport::parse_tarpaulin("test result: ok.")
+            .is_none());
+    }
+
+    /// Tests a multi-file report with line lists, ranges, deltas from a previous
+    /// run, and a file without coverable lines.
+    #[test]
+    fn test_coverage_report_multi_file() {
+        use xtasks::tasks::coverage::CoverageReport;
+
+        let output = "\
+May 05 09:12:44.101  INFO cargo_tarpaulin::report: Coverage Results:
+|| Uncovered Lines:
+|| src/ops.rs: 12, 20-24, 31
+|| src/tasks/ci.rs: 100-101
+|| Tested/Total Lines:
+|| src/empty.rs: 0/0 +0.00%
+|| src/ops.rs: 43/50 -2.00%
+|| src/tasks/ci.rs: 198/200 +1.00%
+||
+96.15% coverage, 241/250 lines covered, +0.40% change in coverage
+";
+        let report = CoverageReport::parse_tarpaulin(output).unwrap();
+        assert_eq!(report.total_percent, 96.15);
+        assert_eq!(
+            (report.lines_covered, report.lines_total),
+            (241, 250)
+        );
+        let files: Vec<(&str, u64, u64)> = report
+            .files
+            .iter()
+            .map(|file| {
+                (file.path.to_str().unwrap(), file.covered, file.total)
+            })
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("src/empty.rs", 0, 0),
+                ("src/ops.rs", 43, 50),
+                ("src/tasks/ci.rs", 198, 200)
+            ]
+        );
+        assert!(report.files[0].uncovered_lines.is_empty());
+        assert_eq!(report.files[0].percent(), None);
+        assert_eq!(
+            report.files[1].uncovered_lines,
+            [12..=12, 20..=24, 31..=31]
+        );
+        assert_eq!(report.files[2].uncovered_lines, [100..=101]);
+    }
+
+    /// Tests that file exclusions and package selections become engine flags.
+    #[test]
+    fn test_coverage_exclusion_flags() {
+        use xtasks::tasks::coverage::{
+            CoverageBuilder, CoverageEngine, CoverageFormat,
+        };
+
+        let config = CoverageBuilder::default()
+            .formats(vec![CoverageFormat::Lcov])
+            .exclude_files(vec![
+                "src/gen/*.rs".to_string(),
+                "benches/**".to_string(),
+            ])
+            .packages(vec!["app".to_string()])
+            .exclude_packages(vec!["xtask".to_string()])
+            .build()
+            .unwrap();
+        let lines = |engine: CoverageEngine| -> Vec<String> {
+            engine
+                .commands(&config)
+                .iter()
+                .map(ToString::to_string)
+                .collect()
+        };
+        assert_eq!(
+            lines(CoverageEngine::Tarpaulin),
+            ["cargo tarpaulin --out Lcov --output-dir target/coverage \
+              --exclude-files src/gen/*.rs \
+              --exclude-files benches/** --packages app --workspace \
+              --exclude xtask"]
+        );
+        assert_eq!(
+            lines(CoverageEngine::LlvmCov)[..2],
+            [
+                "cargo llvm-cov --no-report --package app --workspace --exclude xtask",
+                "cargo llvm-cov report --ignore-filename-regex \
+                 src/gen/[^/]*\\.rs|benches/.* --lcov --output-path \
+                 target/llvm-cov/lcov.info",
+            ]
+        );
+        let grcov = lines(CoverageEngine::Grcov);
+        assert_eq!(
+            grcov[1],
+            "cargo test --package app --workspace --exclude xtask"
+        );
+        assert!(grcov[2].contains(
+            "--ignore /* --ignore src/gen/*.rs --ignore benches/**"
+        ));
+    }
+
+    /// Tests that packages which are not workspace members are rejected
+    /// before the engine runs.
+    #[test]
+    fn test_coverage_unknown_package() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::coverage::{
+            CoverageBuilder, CoverageEngine,
+        };
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA_OUTPUT),
+        );
+        let err = with_runner(recorder.clone(), || {
+            CoverageBuilder::default()
+                .engine(CoverageEngine::Tarpaulin)
+                .packages(vec![
+                    "app".to_string(),
+                    "missing".to_string(),
+                ])
+                .run()
+                .unwrap_err()
+        });
+        assert_eq!(
+            err.to_string(),
+            "Unknown package 'missing', expected one of the workspace members: app, xtask"
+        );
+        assert_eq!(
+            recorder.command_lines(),
+            ["cargo metadata --format-version 1 --no-deps"]
+        );
+    }
+
+    /// Tests the summary line of a report with excluded files.
+    #[test]
+    fn test_coverage_report_display() {
+        use xtasks::tasks::coverage::CoverageReport;
+
+        let mut report =
+            CoverageReport::parse_tarpaulin(TARPAULIN_OUTPUT).unwrap();
+        assert_eq!(
+            report.to_string(),
+            "42.86% coverage, 3/7 lines covered"
+        );
+        report.excluded_files = 2;
+        assert_eq!(
+            report.to_string(),
+            "42.86% coverage, 3/7 lines covered (2 files excluded)"
+        );
+    }
+
+    /// A coverage engine that writes a dummy file for every report it is asked
+    /// for, the way `cargo llvm-cov report` and `cargo tarpaulin` do.
+    struct WritingRunner;
+
+    impl xtasks::ops::runner::CommandRunner for WritingRunner {
+        fn execute(
+            &self,
+            command: &xtasks::ops::runner::CommandSpec,
+        ) -> Result<Output> {
+            let args = &command.args;
+            for (i, arg) in args.iter().enumerate() {
+                match arg.as_str() {
+                    "--output-path" => {
+                        let path = std::path::Path::new(&args[i + 1]);
+                        std::fs::create_dir_all(
+                            path.parent().unwrap(),
+                        )?;
+                        std::fs::write(path, "report")?;
+                    }
+                    "--output-dir" if args[0] == "tarpaulin" => {
+                        let dir = std::path::Path::new(&args[i + 1]);
+                        std::fs::create_dir_all(dir)?;
+                        for name in
+                            ["tarpaulin-report.html", "lcov.info"]
+                        {
+                            std::fs::write(dir.join(name), "report")?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: TARPAULIN_OUTPUT.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            })
+        }
+
+        fn which(&self, program: &str) -> Option<std::path::PathBuf> {
+            Some(program.into())
+        }
+    }
+
+    /// Tests that the reports end up in the output directory and that their
+    /// final paths are returned.
+    #[test]
+    fn test_coverage_output_dir() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::with_runner;
+        use xtasks::tasks::coverage::{
+            CoverageBuilder, CoverageEngine, CoverageFormat,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let output_dir = dir.path().join("reports");
+        let report = with_runner(Arc::new(WritingRunner), || {
+            CoverageBuilder::default()
+                .engine(CoverageEngine::Tarpaulin)
+                .formats(vec![
+                    CoverageFormat::Html,
+                    CoverageFormat::Lcov,
+                    CoverageFormat::Stdout,
+                ])
+                .output_dir(&output_dir)
+                .run()
+                .unwrap()
+        });
+        assert_eq!(
+            report.outputs,
+            [
+                output_dir.join("tarpaulin-report.html"),
+                output_dir.join("lcov.info")
+            ]
+        );
+    }
+
+    /// Tests that reports written outside the output directory are moved into it.
+    #[test]
+    fn test_coverage_collect_reports() {
+        use std::fs;
+        use xtasks::tasks::coverage::{
+            CoverageBuilder, CoverageEngine, CoverageFormat,
+        };
+
+        let root = tempfile::tempdir().unwrap();
+        let written = root.path().join("target/llvm-cov");
+        fs::create_dir_all(written.join("html")).unwrap();
+        fs::write(written.join("html/index.html"), "<html>").unwrap();
+        fs::write(written.join("lcov.info"), "TN:").unwrap();
+
+        let config = CoverageBuilder::default()
+            .formats(vec![
+                CoverageFormat::Lcov,
+                CoverageFormat::Xml,
+                CoverageFormat::Html,
+            ])
+            .build()
+            .unwrap();
+        let paths = config
+            .collect_reports_in(CoverageEngine::LlvmCov, root.path())
+            .unwrap();
+        let output_dir = root.path().join("target/coverage");
+        assert_eq!(
+            paths,
+            [output_dir.join("lcov.info"), output_dir.join("html")]
+        );
+        assert!(output_dir.join("html/index.html").is_file());
+        assert!(!written.join("lcov.info").exists());
+        assert!(!written.join("html").exists());
+    }
+
+    /// Returns a report with the given per-file `(path, covered, total)` lines.
+    fn fabricated_report(
+        files: &[(&str, u64, u64)],
+    ) -> xtasks::tasks::coverage::CoverageReport {
+        use xtasks::tasks::coverage::{CoverageReport, FileCoverage};
+
+        let files: Vec<FileCoverage> = files
+            .iter()
+            .map(|(path, covered, total)| FileCoverage {
+                path: path.into(),
+                covered: *covered,
+                total: *total,
+                uncovered_lines: Vec::new(),
+            })
+            .collect();
+        let lines_covered = files.iter().map(|file| file.covered).sum();
+        let lines_total = files.iter().map(|file| file.total).sum();
+        CoverageReport {
+            total_percent: (lines_covered as f64 * 100.0
+                / lines_total as f64) as f32,
+            lines_covered,
+            lines_total,
+            files,
+            ..CoverageReport::default()
+        }
+    }
+
+    /// Tests that the diff of two reports lists the files whose coverage
+    /// dropped by more than the allowed delta, along with the total delta.
+    #[test]
+    fn test_coverage_diff() {
+        use std::path::PathBuf;
+        use xtasks::tasks::coverage::history::{
+            CoverageDiff, CoverageSummary,
+        };
+
+        let baseline = CoverageSummary::from(&fabricated_report(&[
+            ("src/lib.rs", 90, 100),
+            ("src/ops.rs", 80, 100),
+            ("src/removed.rs", 17, 20),
+        ]));
+        let current = CoverageSummary::from(&fabricated_report(&[
+            ("src/lib.rs", 70, 100),
+            ("src/ops.rs", 79, 100),
+            ("src/new.rs", 12, 30),
+        ]));
+        assert_eq!(baseline.total_percent, 85.0);
+        assert_eq!(current.total_percent, 70.0);
+
+        let diff = CoverageDiff::between(&baseline, &current, 0.0);
+        assert_eq!(
+            diff.summary(),
+            "coverage changed 85.0% → 70.0% (-15.0)"
+        );
+        let dropped: Vec<(PathBuf, f32)> = diff
+            .dropped
+            .iter()
+            .map(|file| (file.path.clone(), file.delta()))
+            .collect();
+        assert_eq!(
+            dropped,
+            [
+                (PathBuf::from("src/lib.rs"), -20.0),
+                (PathBuf::from("src/ops.rs"), -1.0)
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "coverage changed 85.0% → 70.0% (-15.0)
+  file         before    after   change
+  src/lib.rs    90.0%    70.0%    -20.0
+  src/ops.rs    80.0%    79.0%     -1.0"
+        );
+
+        let diff = CoverageDiff::between(&baseline, &current, 5.0);
+        assert_eq!(diff.dropped.len(), 1);
+        assert_eq!(diff.dropped[0].path, PathBuf::from("src/lib.rs"));
+        let diff = CoverageDiff::between(&current, &baseline, 0.0);
+        assert_eq!(
+            diff.summary(),
+            "coverage changed 70.0% → 85.0% (+15.0)"
+        );
+        assert!(diff.dropped.is_empty());
+    }
+
+    /// Tests that runs are recorded per revision and branch, and that a history
+    /// file used as baseline yields the last run on the default branch.
+    #[test]
+    fn test_coverage_history() {
+        use xtasks::tasks::coverage::history::{
+            load_baseline, CoverageHistory, CoverageSummary,
+            HistoryEntry,
+        };
+
+        let entry =
+            |sha: &str, branch: &str, percent: f32| HistoryEntry {
+                sha: sha.to_string(),
+                branch: branch.to_string(),
+                summary: CoverageSummary {
+                    total_percent: percent,
+                    ..CoverageSummary::default()
+                },
+            };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xtask/coverage-history.json");
+        let mut history = CoverageHistory::load(&path).unwrap();
+        assert!(history.entries.is_empty());
+        history.record(entry("aaa", "main", 80.0));
+        history.record(entry("bbb", "feature", 70.0));
+        history.record(entry("aaa", "main", 81.4));
+        history.save(&path).unwrap();
+
+        let history = CoverageHistory::load(&path).unwrap();
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(history.latest().unwrap().branch, "main");
+        assert_eq!(load_baseline(&path).unwrap().total_percent, 81.4);
+
+        let summary = dir.path().join("summary.json");
+        std::fs::write(&summary, r#"{"total_percent": 75.5}"#).unwrap();
+        assert_eq!(
+            load_baseline(&summary).unwrap().total_percent,
+            75.5
+        );
+        std::fs::write(&summary, "{}").unwrap();
+        assert!(load_baseline(&summary).is_err());
+    }
+
+    /// Tests that a missing engine is installed before it runs.
+    #[test]
+    fn test_coverage_installs_missing_engine() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::coverage::{
+            CoverageBuilder, CoverageEngine,
+        };
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .missing("cargo-tarpaulin")
+                .with_stdout("cargo tarpaulin", TARPAULIN_OUTPUT),
+        );
+        let report = with_runner(recorder.clone(), || {
+            CoverageBuilder::default()
+                .engine(CoverageEngine::Tarpaulin)
+                .formats(vec![])
+                .run()
+        })
+        .unwrap();
+        assert_eq!(report.total_percent, 42.86);
+        assert_eq!(
+            recorder.command_lines()[..2],
+            [
+                "cargo install cargo-tarpaulin --locked",
+                "cargo tarpaulin --output-dir target/coverage"
+            ]
+        );
+    }
+
+    /// Tests that a missing engine is an error naming the install command when
+    /// automatic installation is turned off.
+    #[test]
+    fn test_coverage_missing_engine_without_auto_install() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::coverage::{
+            CoverageBuilder, CoverageEngine,
+        };
+
+        let recorder = Arc::new(
+            RecordingRunner::default().missing("cargo-llvm-cov"),
+        );
+        let err = with_runner(recorder.clone(), || {
+            CoverageBuilder::default()
+                .engine(CoverageEngine::LlvmCov)
+                .auto_install(false)
+                .run()
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'cargo-llvm-cov' is not installed, install it with 'cargo install cargo-llvm-cov --locked'"
+        );
+        assert!(recorder.command_lines().is_empty());
+    }
+
+    /// Tests that test arguments come last, after the engine and feature flags,
+    /// and how the timeout is applied for each engine.
+    #[test]
+    fn test_coverage_test_args_and_timeout() {
+        use std::time::Duration;
+        use xtasks::tasks::{
+            ci::FeatureSelection,
+            coverage::{CoverageBuilder, CoverageEngine},
+        };
+
+        let config = CoverageBuilder::default()
+            .formats(vec![])
+            .packages(vec!["app".to_string()])
+            .features(FeatureSelection {
+                list: vec!["json".to_string()],
+                ..FeatureSelection::default()
+            })
+            .test_args(vec![
+                "--test-threads".to_string(),
+                "1".to_string(),
+            ])
+            .timeout(Duration::from_secs(120))
+            .build()
+            .unwrap();
+
+        let tarpaulin = CoverageEngine::Tarpaulin.commands(&config);
+        assert_eq!(
+            tarpaulin[0].to_string(),
+            "cargo tarpaulin --output-dir target/coverage --timeout 120 \
+             --packages app --features json -- --test-threads 1"
+        );
+        assert_eq!(tarpaulin[0].timeout, None);
+
+        let llvm_cov = CoverageEngine::LlvmCov.commands(&config);
+        assert_eq!(
+            llvm_cov[0].to_string(),
+            "cargo llvm-cov --no-report --package app --features json \
+             -- --test-threads 1"
+        );
+        assert_eq!(llvm_cov[0].timeout, Some(Duration::from_secs(120)));
+        assert!(llvm_cov[1..].iter().all(|command| {
+            command.timeout.is_none()
+                && !command.args.contains(&"--".into())
+        }));
+
+        let grcov = CoverageEngine::Grcov.commands(&config);
+        assert_eq!(
+            grcov[1].to_string(),
+            "cargo test --package app --features json -- --test-threads 1"
+        );
+        assert_eq!(grcov[1].timeout, Some(Duration::from_secs(120)));
+    }
+
+    /// Tests that arguments after `--` on the command line reach the tests.
+    #[test]
+    fn test_coverage_cli_test_args() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{with_runner, RecordingRunner},
+            tasks::main_with_args,
+        };
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo tarpaulin", TARPAULIN_OUTPUT),
+        );
+        let args: Vec<String> = [
+            "xtask",
+            "coverage",
+            "--engine",
+            "tarpaulin",
+            "--",
+            "--test-threads",
+            "1",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        with_runner(recorder.clone(), || main_with_args(&args))
+            .unwrap();
+        assert_eq!(
+            recorder.command_lines()[0],
+            "cargo tarpaulin --out Html --output-dir target/coverage \
+             -- --test-threads 1"
+        );
+    }
+
+    /// The badge rendered for 83% coverage.
+    const BADGE_83: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="92" height="20" role="img" aria-label="coverage: 83%">
+  <title>coverage: 83%</title>
+  <linearGradient id="s" x2="0" y2="100%">
+    <stop offset="0" stop-color="#bbb" stop-opacity=".1"/>
+    <stop offset="1" stop-opacity=".1"/>
+  </linearGradient>
+  <clipPath id="r">
+    <rect width="92" height="20" rx="3" fill="#fff"/>
+  </clipPath>
+  <g clip-path="url(#r)">
+    <rect width="61" height="20" fill="#555"/>
+    <rect x="61" width="31" height="20" fill="#4c1"/>
+    <rect width="92" height="20" fill="url(#s)"/>
+  </g>
+  <g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+    <text x="30.5" y="15" fill="#010101" fill-opacity=".3">coverage</text>
+    <text x="30.5" y="14">coverage</text>
+    <text x="76.5" y="15" fill="#010101" fill-opacity=".3">83%</text>
+    <text x="76.5" y="14">83%</text>
+  </g>
+</svg>
+"##;
+
+    /// Tests the rendered badge SVG against snapshots and its color buckets.
+    #[test]
+    fn test_coverage_badge_render() {
+        use xtasks::tasks::coverage::badge::{
+            badge_color, render_badge,
+        };
+
+        assert_eq!(render_badge(83.4), BADGE_83);
+        assert_eq!(
+            render_badge(7.0),
+            BADGE_83
+                .replace("92", "85")
+                .replace("width=\"31\"", "width=\"24\"")
+                .replace("#4c1", "#e05d44")
+                .replace("76.5", "73")
+                .replace("83%", "7%")
+        );
+        assert_eq!(
+            render_badge(100.0),
+            BADGE_83
+                .replace("92", "99")
+                .replace("width=\"31\"", "width=\"38\"")
+                .replace("76.5", "80")
+                .replace("83%", "100%")
+        );
+
+        assert_eq!(badge_color(0.0), "#e05d44");
+        assert_eq!(badge_color(49.99), "#e05d44");
+        assert_eq!(badge_color(50.0), "#dfb317");
+        assert_eq!(badge_color(79.99), "#dfb317");
+        assert_eq!(badge_color(80.0), "#4c1");
+        assert_eq!(badge_color(100.0), "#4c1");
+        assert!(render_badge(79.9).contains("fill=\"#dfb317\""));
+        assert!(render_badge(79.9).contains(">79%<"));
+    }
+
+    /// Tests that the badge is written to disk, identically for the same report.
+    #[test]
+    fn test_coverage_badge_file() {
+        use xtasks::tasks::coverage::{coverage_badge, CoverageReport};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("docs/coverage.svg");
+        let report = CoverageReport::from_total(83.0);
+        coverage_badge(&report, &path).unwrap();
+        let first = std::fs::read_to_string(&path).unwrap();
+        coverage_badge(&report, &path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), first);
+        assert_eq!(first, BADGE_83);
+    }
+
+    /// Serves a single HTTP request with the given response and returns the
+    /// endpoint along with a handle yielding the raw request.
+    fn serve_once(
+        status: u16,
+        body: &'static str,
+    ) -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{Read, Write};
+
+        let listener =
+            std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint =
+            format!("http://{}/upload", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            let mut continued = false;
+            loop {
+                let text =
+                    String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n")
+                {
+                    let head = head.to_ascii_lowercase();
+                    if head.contains("expect: 100-continue")
+                        && !continued
+                    {
+                        stream
+                            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                            .unwrap();
+                        continued = true;
+                    }
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.strip_prefix("content-length: ")
+                        })
+                        .map_or(0, |length| {
+                            length.trim().parse().unwrap()
+                        });
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 {status} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (endpoint, handle)
+    }
+
+    /// A runner that really executes commands and keeps their command lines,
+    /// which is what ends up in logs.
+    #[derive(Default)]
+    struct LoggingRunner(std::sync::Mutex<Vec<String>>);
+
+    impl xtasks::ops::runner::CommandRunner for LoggingRunner {
+        fn execute(
+            &self,
+            command: &xtasks::ops::runner::CommandSpec,
+        ) -> Result<Output> {
+            self.0.lock().unwrap().push(command.to_string());
+            xtasks::ops::runner::DuctRunner.execute(command)
+        }
+    }
+
+    /// The LCOV report uploaded by the tests.
+    const LCOV: &str = "SF:src/lib.rs\nDA:2,1\nDA:3,0\nend_of_record\n";
+
+    /// Tests that the report is posted to Codecov as a multipart form, with
+    /// the token in a header but never in a command line.
+    #[test]
+    fn test_coverage_upload_codecov() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::with_runner,
+            tasks::coverage::upload::{
+                coverage_upload_to, CoverageProvider,
+            },
+        };
+
+        std::env::set_var(
+            "XTASKS_TEST_CODECOV_TOKEN",
+            "s3cr3t-codecov",
+        );
+        let provider = CoverageProvider::Codecov {
+            token_env: "XTASKS_TEST_CODECOV_TOKEN".to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("lcov.info");
+        std::fs::write(&report, LCOV).unwrap();
+
+        let (endpoint, server) = serve_once(200, "{}");
+        let logs = Arc::new(LoggingRunner::default());
+        with_runner(logs.clone(), || {
+            coverage_upload_to(&provider, &report, &endpoint)
+        })
+        .unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /upload?service=xtasks"));
+        assert!(
+            request.contains("Authorization: token s3cr3t-codecov\r\n")
+        );
+        assert!(request.contains(
+            "Content-Disposition: form-data; name=\"file\"; filename=\"lcov.info\""
+        ));
+        assert!(request.contains(LCOV));
+        let logs = logs.0.lock().unwrap();
+        assert!(logs.iter().any(|line| line.starts_with("curl ")));
+        assert!(logs.iter().all(|line| !line.contains("s3cr3t")));
+    }
+
+    /// Tests that Coveralls receives a job converted from the LCOV report.
+    #[test]
+    fn test_coverage_upload_coveralls() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::with_runner,
+            tasks::coverage::upload::{
+                coverage_upload_to, CoverageProvider,
+            },
+        };
+
+        std::env::set_var(
+            "XTASKS_TEST_COVERALLS_TOKEN",
+            "s3cr3t-coveralls",
+        );
+        let provider = CoverageProvider::Coveralls {
+            token_env: "XTASKS_TEST_COVERALLS_TOKEN".to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("lcov.info");
+        std::fs::write(&report, LCOV).unwrap();
+
+        let (endpoint, server) = serve_once(200, "{}");
+        let logs = Arc::new(LoggingRunner::default());
+        with_runner(logs.clone(), || {
+            coverage_upload_to(&provider, &report, &endpoint)
+        })
+        .unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /upload "));
+        assert!(request.contains("name=\"json_file\""));
+        assert!(request.contains("Content-Type: application/json"));
+        assert!(request.contains("\"repo_token\":\"s3cr3t-coveralls\""));
+        assert!(request.contains(
+            "\"source_files\":[{\"coverage\":[null,1,0],\"name\":\"src/lib.rs\"}]"
+        ));
+        assert!(logs
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|line| !line.contains("s3cr3t")));
+    }
+
+    /// Tests that the provider CLI gets the token from the environment
+    /// without showing it in its command line.
+    #[test]
+    fn test_coverage_upload_cli() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{with_runner, RecordingRunner},
+            tasks::coverage::upload::{
+                coverage_upload, CoverageProvider,
+            },
+        };
+
+        std::env::set_var("XTASKS_TEST_CLI_TOKEN", "s3cr3t-cli");
+        let provider = CoverageProvider::Codecov {
+            token_env: "XTASKS_TEST_CLI_TOKEN".to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("lcov.info");
+        std::fs::write(&report, LCOV).unwrap();
+
+        let recorder = Arc::new(RecordingRunner::default());
+        with_runner(recorder.clone(), || {
+            coverage_upload(provider, &report)
+        })
+        .unwrap();
+        let calls = recorder.calls();
+        assert_eq!(
+            calls[0].shell_line(),
+            format!(
+                "CODECOV_TOKEN=*** codecovcli upload-process --file {}",
+                report.display()
+            )
+        );
+        assert_eq!(
+            calls[0].env,
+            [("CODECOV_TOKEN".to_string(), "s3cr3t-cli".to_string())]
+        );
+    }
+
+    /// Tests that a rejected token and an unreachable service are told apart
+    /// when the provider CLI fails, with the token redacted.
+    #[test]
+    fn test_coverage_upload_cli_errors() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{with_runner, RecordingRunner},
+            tasks::coverage::upload::{
+                coverage_upload, CoverageProvider, UploadError,
+            },
+        };
+
+        std::env::set_var("XTASKS_TEST_CLI_ERROR_TOKEN", "s3cr3t-cli");
+        let provider = CoverageProvider::Codecov {
+            token_env: "XTASKS_TEST_CLI_ERROR_TOKEN".to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("lcov.info");
+        std::fs::write(&report, LCOV).unwrap();
+        let upload = |stderr: &str| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .failing("codecovcli")
+                    .with_stderr("codecovcli", stderr),
+            );
+            with_runner(recorder, || {
+                coverage_upload(provider.clone(), &report)
+            })
+            .unwrap_err()
+        };
+
+        let err = upload(
+            "error: Upload failed: {\"detail\":\"Unauthorized\"} (401) for s3cr3t-cli",
+        );
+        assert_eq!(
+            err.downcast_ref::<UploadError>(),
+            Some(&UploadError::Auth {
+                provider: "codecov".to_string(),
+                status: 401,
+            })
+        );
+
+        let err = upload(
+            "error: Max retries exceeded with url: /upload (Connection refused)",
+        );
+        match err.downcast_ref::<UploadError>() {
+            Some(UploadError::Network { provider, message }) => {
+                assert_eq!(provider, "codecov");
+                assert!(message.contains("Connection refused"));
+            }
+            other => panic!("expected a network error, got {other:?}"),
+        }
+
+        let err = upload("error: commit s3cr3t-cli not found");
+        assert!(err.downcast_ref::<UploadError>().is_none());
+        let message = format!("{err:#}");
+        assert!(message.contains("commit *** not found"), "{message}");
+    }
+
+    /// Tests that rejected tokens and unreachable services are told apart, and
+    /// that the token is redacted from errors.
+    #[test]
+    fn test_coverage_upload_errors() {
+        use xtasks::tasks::coverage::upload::{
+            coverage_upload_to, CoverageProvider, UploadError,
+        };
+
+        std::env::set_var("XTASKS_TEST_UPLOAD_TOKEN", "s3cr3t-upload");
+        let provider = CoverageProvider::Codecov {
+            token_env: "XTASKS_TEST_UPLOAD_TOKEN".to_string(),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let report = dir.path().join("lcov.info");
+        std::fs::write(&report, LCOV).unwrap();
+
+        let (endpoint, server) =
+            serve_once(401, "bad token s3cr3t-upload");
+        let err = coverage_upload_to(&provider, &report, &endpoint)
+            .unwrap_err();
+        let _ = server.join().unwrap();
+        assert_eq!(
+            err.downcast_ref::<UploadError>(),
+            Some(&UploadError::Auth {
+                provider: "codecov".to_string(),
+                status: 401
+            })
+        );
+
+        let (endpoint, server) =
+            serve_once(422, "token s3cr3t-upload is fine");
+        let err = coverage_upload_to(&provider, &report, &endpoint)
+            .unwrap_err();
+        let _ = server.join().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "codecov rejected the upload (HTTP 422): token *** is fine"
+        );
+
+        let closed =
+            std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint =
+            format!("http://{}/upload", closed.local_addr().unwrap());
+        drop(closed);
+        let err = coverage_upload_to(&provider, &report, &endpoint)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<UploadError>(),
+            Some(UploadError::Network { .. })
+        ));
+        assert!(!format!("{err:#}").contains("s3cr3t"));
+
+        let missing = CoverageProvider::Codecov {
+            token_env: "XTASKS_TEST_UNSET_TOKEN".to_string(),
+        };
+        assert_eq!(
+            coverage_upload_to(&missing, &report, &endpoint)
+                .unwrap_err()
+                .to_string(),
+            "Set XTASKS_TEST_UNSET_TOKEN to upload coverage to codecov"
+        );
+    }
+
+    /// Tests the per-module table rendered for a synthetic report.
+    #[test]
+    fn test_coverage_module_table() {
+        let mut report = fabricated_report(&[
+            ("src/lib.rs", 9, 10),
+            ("src/ops.rs", 40, 50),
+            ("src/ops/runner.rs", 20, 30),
+            ("/home/ci/xtasks/src/tasks/ci.rs", 198, 200),
+            ("src/tasks.rs", 2, 20),
+            ("tests/test_ops.rs", 5, 10),
+            ("examples/example.rs", 0, 0),
+            ("src/macros.rs", 0, 0),
+        ]);
+        let modules: Vec<(String, u64, u64)> = report
+            .modules()
+            .into_iter()
+            .map(|module| (module.name, module.covered, module.total))
+            .collect();
+        assert_eq!(
+            modules[0],
+            ("other".to_string(), 5, 10),
+            "files outside of src/ are grouped together"
+        );
+        assert_eq!(
+            report.module_table(),
+            "\
+module  covered  total  coverage
+other         5     10    50.00%
+ops          60     80    75.00%
+lib           9     10    90.00%
+tasks       200    220    90.91%
+macros        0      0         -
+TOTAL       274    320    85.62%
+"
+        );
+
+        report.lines_covered = 0;
+        report.lines_total = 0;
+        assert!(report
+            .module_table()
+            .ends_with("TOTAL       274    320    85.62%\n"));
+    }
+
+    /// Tests that `--summary-only` writes no report files.
+    #[test]
+    fn test_coverage_cli_summary_only() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{with_runner, RecordingRunner},
+            tasks::main_with_args,
+        };
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo tarpaulin", TARPAULIN_OUTPUT),
+        );
+        let args: Vec<String> = [
+            "xtask",
+            "coverage",
+            "--engine",
+            "tarpaulin",
+            "--out",
+            "lcov",
+            "--summary-only",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        with_runner(recorder.clone(), || main_with_args(&args))
+            .unwrap();
+        assert_eq!(
+            recorder.command_lines()[0],
+            "cargo tarpaulin --out Stdout --output-dir target/coverage"
+        );
+    }
 }
@@ -0,0 +1,157 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::cross::{
+        build, CrossBackend, CrossOptionsBuilder, ToolProbe,
+    };
+
+    const HOST: &str = "x86_64-unknown-linux-gnu";
+
+    /// Returns a probe of a host with the given tools.
+    fn probe(container_engine: bool, zig: bool) -> ToolProbe {
+        ToolProbe {
+            host: HOST.to_string(),
+            container_engine,
+            zig,
+            installed_targets: vec![
+                HOST.to_string(),
+                "wasm32-unknown-unknown".to_string(),
+            ],
+        }
+    }
+
+    /// Tests the backend selected for each target depending on the tools.
+    #[test]
+    fn test_backend_selection() {
+        let musl = "x86_64-unknown-linux-musl";
+        let msvc = "x86_64-pc-windows-msvc";
+        let wasm = "wasm32-unknown-unknown";
+
+        let docker = probe(true, true);
+        assert_eq!(docker.select(HOST).unwrap(), CrossBackend::Cargo);
+        assert_eq!(docker.select(musl).unwrap(), CrossBackend::Cross);
+
+        let zig = probe(false, true);
+        assert_eq!(zig.select(musl).unwrap(), CrossBackend::Zigbuild);
+        assert!(zig.select(msvc).is_err());
+        assert_eq!(zig.select(wasm).unwrap(), CrossBackend::Zigbuild);
+
+        let bare = probe(false, false);
+        assert_eq!(bare.select(wasm).unwrap(), CrossBackend::Cargo);
+        assert_eq!(
+            bare.select(musl).unwrap_err().to_string(),
+            "No backend can build x86_64-unknown-linux-musl: install docker for cross, zig for cargo-zigbuild, or add the target with 'rustup target add x86_64-unknown-linux-musl'"
+        );
+    }
+
+    /// Tests that the tools are probed through the runner.
+    #[test]
+    fn test_probe_detect() {
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .missing("docker")
+                .missing("zig")
+                .with_stdout("rustc -vV", format!("host: {HOST}\n"))
+                .with_stdout(
+                    "rustup target list",
+                    format!("{HOST}\nwasm32-unknown-unknown\n"),
+                ),
+        );
+        let probe = with_runner(runner, ToolProbe::detect).unwrap();
+        assert_eq!(probe.host, HOST);
+        assert!(probe.container_engine, "podman is installed");
+        assert!(!probe.zig);
+        assert_eq!(probe.installed_targets.len(), 2);
+    }
+
+    /// Tests the commands run for each target and the returned paths.
+    #[test]
+    fn test_cross_build_commands() {
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .missing("docker")
+                .missing("podman")
+                .missing("cargo-zigbuild")
+                .with_stdout("rustc -vV", format!("host: {HOST}\n"))
+                .with_stdout("rustup target list", format!("{HOST}\n")),
+        );
+        let targets =
+            [HOST.to_string(), "x86_64-pc-windows-gnu".to_string()];
+        let opts = CrossOptionsBuilder::default()
+            .binaries(vec!["app".to_string()])
+            .target_dir(Some(Path::new("target").to_path_buf()))
+            .build()
+            .unwrap();
+        let paths =
+            with_runner(runner.clone(), || build(&targets, opts))
+                .unwrap();
+        assert_eq!(
+            paths,
+            [
+                Path::new(
+                    "target/x86_64-unknown-linux-gnu/release/app"
+                ),
+                Path::new(
+                    "target/x86_64-pc-windows-gnu/release/app.exe"
+                ),
+            ]
+        );
+        let builds: Vec<String> = runner
+            .command_lines()
+            .into_iter()
+            .filter(|line| {
+                line.starts_with("cargo build")
+                    || line.starts_with("cargo zigbuild")
+                    || line.starts_with("cargo install")
+                    || line.starts_with("rustup target add")
+            })
+            .collect();
+        assert_eq!(
+            builds,
+            [
+                "cargo build --release --target x86_64-unknown-linux-gnu --bin app",
+                "cargo install cargo-zigbuild",
+                "rustup target add x86_64-pc-windows-gnu",
+                "cargo zigbuild --release --target x86_64-pc-windows-gnu --bin app",
+            ]
+        );
+    }
+
+    /// Tests that the failures are reported per target with the backend,
+    /// after every target was attempted.
+    #[test]
+    fn test_cross_failures_per_target() {
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .failing("cross build --release --target aarch64"),
+        );
+        let targets = [
+            "aarch64-unknown-linux-gnu".to_string(),
+            "x86_64-unknown-linux-musl".to_string(),
+        ];
+        let opts = CrossOptionsBuilder::default()
+            .binaries(vec!["app".to_string()])
+            .backend(Some(CrossBackend::Cross))
+            .target_dir(Some(Path::new("target").to_path_buf()))
+            .build()
+            .unwrap();
+        let err = with_runner(runner.clone(), || build(&targets, opts))
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with(
+                "Failed to build 1 of 2 targets:\n  aarch64-unknown-linux-gnu (cross): "
+            ),
+            "{message}"
+        );
+        assert!(runner.command_lines().contains(
+            &"cross build --release --target x86_64-unknown-linux-musl --bin app"
+                .to_string()
+        ));
+        assert_eq!(xtasks::ops::error::exit_code(&err), 12);
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::Path,
+        process::{Command, Output},
+        sync::Arc,
+    };
+    use xtasks::ops::archive::{
+        self, sha256_hex, ArchiveFormat, CHECKSUMS_FILE,
+    };
+    use xtasks::ops::runner::{
+        with_runner, CommandRunner, CommandSpec, DuctRunner,
+        RecordingRunner,
+    };
+    use xtasks::ops::tools::parse_host_triple;
+    use xtasks::tasks::dist::{dist, DistConfigBuilder};
+
+    const HOST: &str = "x86_64-unknown-linux-gnu";
+
+    /// Runs `tar` for real, so the archives are created, and records the other
+    /// commands, answering `cargo metadata` and `rustc -vV` with canned output.
+    #[derive(Debug)]
+    struct TarOnlyRunner {
+        recorder: RecordingRunner,
+    }
+
+    impl TarOnlyRunner {
+        /// Returns a runner describing a workspace with the `myapp` binary at
+        /// version 1.2.0, built in `root/target`.
+        fn new(root: &Path) -> Self {
+            let metadata = serde_json::json!({
+                "packages": [{
+                    "name": "myapp",
+                    "version": "1.2.0",
+                    "id": "myapp 1.2.0",
+                    "manifest_path": root.join("Cargo.toml"),
+                    "targets": [{ "name": "myapp", "kind": ["bin"] }],
+                }],
+                "workspace_members": ["myapp 1.2.0"],
+                "workspace_root": root,
+                "target_directory": root.join("target"),
+            });
+            Self {
+                recorder: RecordingRunner::default()
+                    .with_stdout("cargo metadata", metadata.to_string())
+                    .with_stdout(
+                        "rustc -vV",
+                        format!("rustc 1.75.0\nhost: {HOST}\n"),
+                    ),
+            }
+        }
+    }
+
+    impl CommandRunner for TarOnlyRunner {
+        fn execute(
+            &self,
+            command: &CommandSpec,
+        ) -> std::io::Result<Output> {
+            if command.program == "tar" {
+                DuctRunner.execute(command)
+            } else {
+                self.recorder.execute(command)
+            }
+        }
+    }
+
+    /// Creates a workspace with a built fixture binary and a license.
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let release = dir.path().join("target/release");
+        fs::create_dir_all(&release).unwrap();
+        fs::write(release.join("myapp"), "#!/bin/sh\necho myapp\n")
+            .unwrap();
+        fs::write(dir.path().join("LICENSE-MIT"), "MIT").unwrap();
+        dir
+    }
+
+    /// Returns the sorted entries of the tarball `archive`.
+    fn entries(archive: &Path) -> Vec<String> {
+        let output = Command::new("tar")
+            .arg("-tzf")
+            .arg(archive)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let mut entries: Vec<String> =
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.trim_end_matches('/').to_string())
+                .collect();
+        entries.sort();
+        entries
+    }
+
+    /// Tests the packaging of the host build: the build command, the archive
+    /// name and contents, and the checksum file.
+    #[test]
+    fn test_dist_host() {
+        std::env::remove_var("CARGO_TARGET_DIR");
+        let dir = workspace();
+        let root = dir.path();
+        let runner = Arc::new(TarOnlyRunner::new(root));
+        let config = DistConfigBuilder::default()
+            .extra_files(vec![format!("{}/LICENSE*", root.display())])
+            .strip(true)
+            .build()
+            .unwrap();
+        let artifacts =
+            with_runner(runner.clone(), || dist(config)).unwrap();
+
+        let dist_dir = root.join("target/dist");
+        let archive =
+            dist_dir.join(format!("myapp-v1.2.0-{HOST}.tar.gz"));
+        assert_eq!(
+            artifacts,
+            [archive.clone(), dist_dir.join(CHECKSUMS_FILE)]
+        );
+        let stem = format!("myapp-v1.2.0-{HOST}");
+        assert_eq!(
+            entries(&archive),
+            [
+                stem.clone(),
+                format!("{stem}/LICENSE-MIT"),
+                format!("{stem}/myapp")
+            ]
+        );
+        assert!(!dist_dir.join(&stem).exists());
+
+        let sums = fs::read_to_string(&artifacts[1]).unwrap();
+        assert_eq!(
+            sums,
+            format!(
+                "{}  {stem}.tar.gz\n",
+                sha256_hex(&fs::read(&archive).unwrap())
+            )
+        );
+
+        let lines = runner.recorder.command_lines();
+        assert!(lines.contains(
+            &"cargo build --release --bin myapp".to_string()
+        ));
+        assert!(lines.iter().any(|line| line.starts_with("strip ")
+            && line.ends_with(&format!("{stem}/myapp"))));
+    }
+
+    /// Tests that explicit targets are built with `--target`, read from their
+    /// own directory, and written to the output directory.
+    #[test]
+    fn test_dist_targets() {
+        std::env::remove_var("CARGO_TARGET_DIR");
+        let dir = workspace();
+        let root = dir.path();
+        let targets =
+            ["aarch64-apple-darwin", "x86_64-unknown-linux-musl"];
+        for target in targets {
+            let release =
+                root.join("target").join(target).join("release");
+            fs::create_dir_all(&release).unwrap();
+            fs::write(release.join("myapp"), target).unwrap();
+        }
+        let runner = Arc::new(TarOnlyRunner::new(root));
+        let config = DistConfigBuilder::default()
+            .targets(targets.map(ToString::to_string).to_vec())
+            .extra_files(Vec::new())
+            .output_dir(Some(root.join("out")))
+            .build()
+            .unwrap();
+        let artifacts =
+            with_runner(runner.clone(), || dist(config)).unwrap();
+
+        let names: Vec<String> = artifacts
+            .iter()
+            .map(|path| {
+                assert_eq!(
+                    path.parent(),
+                    Some(root.join("out").as_path())
+                );
+                path.file_name().unwrap().to_string_lossy().to_string()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "myapp-v1.2.0-aarch64-apple-darwin.tar.gz",
+                "myapp-v1.2.0-x86_64-unknown-linux-musl.tar.gz",
+                CHECKSUMS_FILE
+            ]
+        );
+        assert_eq!(
+            entries(&artifacts[1]),
+            [
+                "myapp-v1.2.0-x86_64-unknown-linux-musl",
+                "myapp-v1.2.0-x86_64-unknown-linux-musl/myapp"
+            ]
+        );
+        let sums = fs::read_to_string(&artifacts[2]).unwrap();
+        assert_eq!(sums.lines().count(), 2);
+        for (archive, line) in artifacts.iter().zip(sums.lines()) {
+            assert!(line
+                .starts_with(&sha256_hex(&fs::read(archive).unwrap())));
+        }
+        let lines = runner.recorder.command_lines();
+        for target in targets {
+            assert!(lines.contains(&format!(
+                "cargo build --release --bin myapp --target {target}"
+            )));
+        }
+        assert!(!lines.iter().any(|line| line.starts_with("rustc")));
+    }
+
+    /// Tests the SHA-256 implementation against the standard test vectors.
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            sha256_hex(&vec![b'a'; 1_000_000]),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    /// Tests the archive formats and the `tar` invocations.
+    #[test]
+    fn test_archive_formats() {
+        assert_eq!(
+            "tgz".parse::<ArchiveFormat>().unwrap(),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(
+            "ZIP".parse::<ArchiveFormat>().unwrap(),
+            ArchiveFormat::Zip
+        );
+        assert!("rar".parse::<ArchiveFormat>().is_err());
+        assert_eq!(
+            archive::command(
+                ArchiveFormat::Zip,
+                Path::new("dist/app-v1.0.0-x86_64-pc-windows-msvc"),
+                Path::new("dist/app.zip")
+            )
+            .to_string(),
+            "tar -acf dist/app.zip -C dist app-v1.0.0-x86_64-pc-windows-msvc"
+        );
+        assert_eq!(
+            parse_host_triple(
+                "rustc 1.75.0\nhost: aarch64-apple-darwin\n"
+            )
+            .unwrap(),
+            "aarch64-apple-darwin"
+        );
+        assert!(parse_host_triple("rustc 1.75.0\n").is_err());
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::docker::{
+        build, image_tags, ContainerEngine, DockerOptionsBuilder,
+    };
+
+    /// Tests the computed tags and the extra ones.
+    #[test]
+    fn test_image_tags() {
+        assert_eq!(
+            image_tags(
+                "ghcr.io/owner/app",
+                "1.2.0",
+                "4aee181",
+                &[
+                    "edge".to_string(),
+                    "latest".to_string(),
+                    "localhost:5000/app:nightly".to_string(),
+                ]
+            ),
+            [
+                "ghcr.io/owner/app:1.2.0",
+                "ghcr.io/owner/app:latest",
+                "ghcr.io/owner/app:sha-4aee181",
+                "ghcr.io/owner/app:edge",
+                "localhost:5000/app:nightly",
+            ]
+        );
+    }
+
+    /// Tests that podman is used when docker is missing, and that a
+    /// missing engine is reported.
+    #[test]
+    fn test_engine_fallback() {
+        let detect = |runner: RecordingRunner| {
+            with_runner(Arc::new(runner), ContainerEngine::detect)
+        };
+        assert_eq!(
+            detect(RecordingRunner::default()).unwrap(),
+            ContainerEngine::Docker
+        );
+        assert_eq!(
+            detect(RecordingRunner::default().missing("docker"))
+                .unwrap(),
+            ContainerEngine::Podman
+        );
+        let err = detect(
+            RecordingRunner::default()
+                .missing("docker")
+                .missing("podman"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            xtasks::ops::error::exit_code(&err),
+            xtasks::ops::error::EXIT_TOOL_MISSING
+        );
+    }
+
+    /// Tests the build and push commands, and the returned image id.
+    #[test]
+    fn test_docker_build_and_push() {
+        let dir = tempfile::tempdir().unwrap();
+        let iid_file = dir.path().join("iid");
+        fs::write(&iid_file, "sha256:abc123\n").unwrap();
+        let opts = DockerOptionsBuilder::default()
+            .name(Some("app".to_string()))
+            .version(Some("0.3.0".to_string()))
+            .tags(vec!["edge".to_string()])
+            .build_args(vec!["PROFILE=release".to_string()])
+            .push(true)
+            .iid_file(iid_file.clone())
+            .build()
+            .unwrap();
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .missing("docker")
+                .with_stdout("git -C . rev-parse", "4aee181\n"),
+        );
+        let id = with_runner(runner.clone(), || build(opts)).unwrap();
+        assert_eq!(id, "sha256:abc123");
+        let lines = runner.command_lines();
+        assert_eq!(lines[0], "podman info --format {{.ServerVersion}}");
+        assert_eq!(lines[1], "git -C . rev-parse --short HEAD");
+        assert_eq!(
+            lines[2..],
+            [
+                format!(
+                    "podman build --file Dockerfile --tag app:0.3.0 --tag app:latest --tag app:sha-4aee181 --tag app:edge --build-arg PROFILE=release --iidfile {} .",
+                    iid_file.display()
+                ),
+                "podman push app:0.3.0".to_string(),
+                "podman push app:latest".to_string(),
+                "podman push app:sha-4aee181".to_string(),
+                "podman push app:edge".to_string(),
+            ]
+        );
+    }
+
+    /// Tests that an unreachable daemon fails before building.
+    #[test]
+    fn test_docker_daemon_unreachable() {
+        let runner =
+            Arc::new(RecordingRunner::default().failing("docker info"));
+        let err = with_runner(runner.clone(), || {
+            build(
+                DockerOptionsBuilder::default()
+                    .name(Some("app".to_string()))
+                    .build()
+                    .unwrap(),
+            )
+        })
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("The docker daemon is not reachable"),
+            "{err}"
+        );
+        assert_eq!(runner.command_lines().len(), 1);
+    }
+}
@@ -6,63 +6,22 @@ mod tests {
     use std::ffi::OsStr;
     use std::io::Result;
     use std::os::unix::process::ExitStatusExt;
-    use std::process::{Command, ExitStatus, Output};
+    use std::process::{ExitStatus, Output};
 
     trait CommandRunner {
-        fn new(cmd: &str) -> Self
-        where
-            Self: Sized;
         fn args<I, S>(self, args: I) -> Self
         where
             I: IntoIterator<Item = S>,
             S: AsRef<OsStr>,
             Self: Sized;
-        fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(
-            self,
-            key: K,
-            value: V,
-        ) -> Self
-        where
-            Self: Sized;
         fn spawn(&mut self) -> Result<Output>;
     }
 
-    struct RealCommand(Command);
-
-    impl CommandRunner for RealCommand {
-        fn new(cmd: &str) -> Self {
-            Self(Command::new(cmd))
-        }
-
-        fn args<I, S>(mut self, args: I) -> Self
-        where
-            I: IntoIterator<Item = S>,
-            S: AsRef<OsStr>,
-        {
-            self.0.args(args);
-            self
-        }
-
-        fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(
-            mut self,
-            key: K,
-            value: V,
-        ) -> Self {
-            self.0.env(key, value);
-            self
-        }
-
-        fn spawn(&mut self) -> Result<Output> {
-            self.0.output()
-        }
-    }
-
     struct MockCommand {
         status: ExitStatus,
         stdout: Vec<u8>,
         stderr: Vec<u8>,
         args: Vec<String>,
-        env: Vec<(String, String)>,
     }
 
     impl MockCommand {
@@ -72,7 +31,6 @@ mod tests {
                 stdout: Vec::new(),
                 stderr: Vec::new(),
                 args: Vec::new(),
-                env: Vec::new(),
             }
         }
 
@@ -88,10 +46,6 @@ mod tests {
     }
 
     impl CommandRunner for MockCommand {
-        fn new(cmd: &str) -> Self {
-            Self::new(cmd)
-        }
-
         fn args<I, S>(mut self, args: I) -> Self
         where
             I: IntoIterator<Item = S>,
@@ -104,18 +58,6 @@ mod tests {
             self
         }
 
-        fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(
-            mut self,
-            key: K,
-            value: V,
-        ) -> Self {
-            self.env.push((
-                key.as_ref().to_string_lossy().to_string(),
-                value.as_ref().to_string_lossy().to_string(),
-            ));
-            self
-        }
-
         fn spawn(&mut self) -> Result<Output> {
             Ok(Output {
                 status: self.status,
@@ -158,4 +100,736 @@ mod tests {
             "Documentation generated successfully"
         );
     }
+
+    /// Tests that the default configuration, the builder defaults and an
+    /// empty configuration file describe the same run.
+    #[test]
+    fn test_docs_default() {
+        use xtasks::tasks::docs::{Docs, DocsBuilder};
+
+        let docs = Docs::default();
+        assert!(docs.watch);
+        assert!(docs.no_deps);
+        assert!(docs.auto_install);
+        assert_eq!(docs, DocsBuilder::default().build().unwrap());
+        assert_eq!(serde_json::from_str::<Docs>("{}").unwrap(), docs);
+    }
+
+    /// Tests that the default configuration runs cargo-watch, installing it
+    /// only when it cannot be found.
+    #[test]
+    fn test_docs_watch_mode() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::DocsBuilder;
+
+        let recorder =
+            Arc::new(RecordingRunner::default().missing("cargo-watch"));
+        with_runner(recorder.clone(), || DocsBuilder::default().run())
+            .unwrap();
+        assert_eq!(
+            recorder.command_lines(),
+            [
+                "cargo install cargo-watch",
+                "cargo watch -s cargo doc --no-deps"
+            ]
+        );
+
+        let recorder = Arc::new(RecordingRunner::default());
+        with_runner(recorder.clone(), || DocsBuilder::default().run())
+            .unwrap();
+        assert_eq!(
+            recorder.command_lines(),
+            ["cargo watch -s cargo doc --no-deps"]
+        );
+        assert!(recorder.calls()[0].env.is_empty());
+    }
+
+    /// Tests that a one-shot build never involves cargo-watch, and that denied
+    /// warnings only apply to the child process.
+    #[test]
+    fn test_docs_one_shot_mode() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::DocsBuilder;
+
+        let recorder = Arc::new(RecordingRunner::default());
+        with_runner(recorder.clone(), || {
+            DocsBuilder::default()
+                .watch(false)
+                .all_features(true)
+                .deny_warnings(true)
+                .run()
+        })
+        .unwrap();
+        let calls = recorder.calls();
+        assert_eq!(
+            recorder.command_lines(),
+            ["cargo doc --no-deps --all-features"]
+        );
+        assert_eq!(
+            calls[0].env,
+            [("RUSTDOCFLAGS".to_string(), "-D warnings".to_string())]
+        );
+    }
+
+    /// Tests the `docs` subcommand flags.
+    #[test]
+    fn test_docs_cli_flags() {
+        use std::sync::Arc;
+        use xtasks::{
+            ops::runner::{with_runner, RecordingRunner},
+            tasks::main_with_args,
+        };
+
+        let recorder = Arc::new(RecordingRunner::default());
+        let args: Vec<String> =
+            ["xtask", "docs", "--no-watch", "--deny-warnings"]
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+        with_runner(recorder.clone(), || main_with_args(&args))
+            .unwrap();
+        assert_eq!(recorder.command_lines(), ["cargo doc --no-deps"]);
+        assert_eq!(recorder.calls()[0].env[0].0, "RUSTDOCFLAGS");
+    }
+
+    /// Returns `cargo metadata` output for a workspace rooted at `root`, whose
+    /// root package is `my-app`.
+    fn metadata_output(root: &std::path::Path) -> String {
+        format!(
+            r#"{{
+                "packages": [{{
+                    "name": "my-app",
+                    "id": "my-app 0.1.0",
+                    "manifest_path": "{root}/Cargo.toml"
+                }}],
+                "workspace_members": ["my-app 0.1.0"],
+                "workspace_root": "{root}",
+                "target_directory": "{root}/target"
+            }}"#,
+            root = root.display()
+        )
+    }
+
+    /// Tests that a one-shot build returns the index page of the root package,
+    /// and fails clearly when `cargo doc` did not produce it.
+    #[test]
+    fn test_docs_build_output_path() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::docs_build;
+
+        let dir = tempfile::tempdir().unwrap();
+        let recorder =
+            Arc::new(RecordingRunner::default().with_stdout(
+                "cargo metadata",
+                metadata_output(dir.path()),
+            ));
+        let err =
+            with_runner(recorder.clone(), docs_build).unwrap_err();
+        assert!(err.to_string().contains("did not generate"), "{err}");
+
+        let index = dir.path().join("target/doc/my_app/index.html");
+        std::fs::create_dir_all(index.parent().unwrap()).unwrap();
+        std::fs::write(&index, "<html></html>").unwrap();
+        let path = with_runner(recorder.clone(), docs_build).unwrap();
+        assert_eq!(path, index);
+        assert_eq!(recorder.command_lines()[0], "cargo doc --no-deps");
+    }
+
+    /// Tests that `--open` opens the index page, except in CI.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_docs_open_suppressed_in_ci() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::DocsBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let index = dir.path().join("target/doc/my_app/index.html");
+        std::fs::create_dir_all(index.parent().unwrap()).unwrap();
+        std::fs::write(&index, "<html></html>").unwrap();
+        let docs = DocsBuilder::default()
+            .watch(false)
+            .open(true)
+            .build()
+            .unwrap();
+        let run = |ci: &str| {
+            std::env::set_var("CI", ci);
+            let recorder =
+                Arc::new(RecordingRunner::default().with_stdout(
+                    "cargo metadata",
+                    metadata_output(dir.path()),
+                ));
+            with_runner(recorder.clone(), || docs.run()).unwrap();
+            recorder.command_lines()
+        };
+        let in_ci = run("true");
+        let locally = run("0");
+        std::env::remove_var("CI");
+        assert!(!in_ci.iter().any(|line| line.starts_with("xdg-open")));
+        assert_eq!(
+            locally.last().unwrap(),
+            &format!("xdg-open {}", index.display())
+        );
+    }
+
+    /// Tests the flags, `RUSTDOCFLAGS` and toolchain of every combination of
+    /// `private_items` and `cfg_docsrs`.
+    #[test]
+    fn test_docs_private_items_and_cfg_docsrs() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::DocsBuilder;
+
+        let cases = [
+            (false, false, vec!["cargo doc --no-deps"], None),
+            (
+                true,
+                false,
+                vec!["cargo doc --no-deps --document-private-items"],
+                None,
+            ),
+            (
+                false,
+                true,
+                vec![
+                    "rustup run nightly rustc --version",
+                    "rustup run nightly cargo doc --no-deps",
+                ],
+                Some("--cfg docsrs"),
+            ),
+            (
+                true,
+                true,
+                vec![
+                    "rustup run nightly rustc --version",
+                    "rustup run nightly cargo doc --no-deps --document-private-items",
+                ],
+                Some("--cfg docsrs"),
+            ),
+        ];
+        for (private_items, cfg_docsrs, lines, flags) in cases {
+            let recorder = Arc::new(RecordingRunner::default());
+            with_runner(recorder.clone(), || {
+                DocsBuilder::default()
+                    .watch(false)
+                    .private_items(private_items)
+                    .cfg_docsrs(cfg_docsrs)
+                    .run()
+            })
+            .unwrap();
+            assert_eq!(recorder.command_lines(), lines);
+            let env = recorder.calls().last().unwrap().env.clone();
+            assert_eq!(
+                env.iter()
+                    .find(|(key, _)| key == "RUSTDOCFLAGS")
+                    .map(|(_, value)| value.as_str()),
+                flags
+            );
+        }
+    }
+
+    /// Tests that a missing nightly toolchain is installed, or reported with the
+    /// command installing it when installation is off.
+    #[test]
+    fn test_docs_cfg_docsrs_missing_nightly() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::DocsBuilder;
+
+        let docs = DocsBuilder::default()
+            .watch(false)
+            .cfg_docsrs(true)
+            .deny_warnings(true)
+            .build()
+            .unwrap();
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .failing("rustup run nightly rustc"),
+        );
+        with_runner(recorder.clone(), || docs.run()).unwrap();
+        assert_eq!(
+            recorder.command_lines()[1..],
+            [
+                "rustup toolchain install nightly --profile minimal",
+                "rustup run nightly cargo doc --no-deps",
+            ]
+        );
+        assert_eq!(
+            recorder.calls()[2].env,
+            [(
+                "RUSTDOCFLAGS".to_string(),
+                "-D warnings --cfg docsrs".to_string()
+            )]
+        );
+
+        let recorder = Arc::new(
+            RecordingRunner::default().failing("rustup run nightly"),
+        );
+        let docs = xtasks::tasks::docs::Docs {
+            auto_install: false,
+            ..docs
+        };
+        let err =
+            with_runner(recorder.clone(), || docs.run()).unwrap_err();
+        assert!(
+            err.to_string().contains(
+                "install it with 'rustup toolchain install nightly --profile minimal'"
+            ),
+            "{err}"
+        );
+        assert_eq!(recorder.calls().len(), 1);
+    }
+
+    /// Writes a small documentation tree with one broken local link and one bad
+    /// fragment, and returns its directory.
+    fn link_fixture() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let krate = dir.path().join("my_app");
+        std::fs::create_dir_all(krate.join("module")).unwrap();
+        std::fs::write(
+            krate.join("index.html"),
+            r##"<h1 id="top">my_app</h1>
+<a href="#top">top</a>
+<a href="module/index.html">module</a>
+<a href="struct.Config.html#method.new">new</a>
+<a href="struct.Missing.html">missing</a>
+<a href=struct.Config.html#12-14>lines</a>
+<a href="https://example.com/gone">external</a>
+<a href="mailto:team@example.com">mail</a>"##,
+        )
+        .unwrap();
+        std::fs::write(
+            krate.join("struct.Config.html"),
+            r##"<section id="method.build"></section>
+<a href=#12 id=12>12</a>
+<a href="index.html#top">back</a>"##,
+        )
+        .unwrap();
+        std::fs::write(
+            krate.join("module/index.html"),
+            r##"<a href="../struct.Config.html">Config</a>"##,
+        )
+        .unwrap();
+        dir
+    }
+
+    /// Tests that local links and fragments are checked, and external links are
+    /// left alone by default.
+    #[test]
+    fn test_check_links() {
+        use xtasks::tasks::docs::{check_links, BrokenLink};
+
+        let dir = link_fixture();
+        let report = check_links(dir.path()).unwrap();
+        let krate = dir.path().join("my_app");
+        assert_eq!(report.checked, 8);
+        assert_eq!(
+            report.broken,
+            [
+                BrokenLink {
+                    file: krate.join("index.html"),
+                    href: "struct.Config.html#method.new".to_string(),
+                    reason: "no element with id 'method.new'"
+                        .to_string(),
+                },
+                BrokenLink {
+                    file: krate.join("index.html"),
+                    href: "struct.Missing.html".to_string(),
+                    reason: "no such file".to_string(),
+                },
+            ]
+        );
+        assert!(report
+            .to_string()
+            .starts_with("checked 8 links, 2 broken\n  "));
+    }
+
+    /// Tests that external links are requested with curl when asked to.
+    #[test]
+    fn test_check_external_links() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::links::LinkCheckBuilder;
+
+        let dir = link_fixture();
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("curl", "404 https://example.com/gone\n"),
+        );
+        let report = with_runner(recorder.clone(), || {
+            LinkCheckBuilder::default()
+                .check_external(true)
+                .concurrency(4_usize)
+                .build()
+                .unwrap()
+                .run(dir.path())
+        })
+        .unwrap();
+        assert_eq!(
+            recorder.command_lines(),
+            [concat!(
+                "curl --head --silent --parallel --parallel-max 4 --max-time 10 ",
+                "--write-out %{http_code} %{url_effective}\\n ",
+                "--output /dev/null https://example.com/gone"
+            )]
+        );
+        assert_eq!(report.checked, 9);
+        assert_eq!(report.broken[2].reason, "HTTP 404");
+    }
+
+    /// Runs git in `dir`, panicking if it fails, and returns its stdout.
+    fn git_in(dir: &std::path::Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {args:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Tests publishing documentation to a branch of a local bare remote: a dry
+    /// run pushes nothing, the first deployment creates an orphan branch, and
+    /// the next one adds a commit on top of it.
+    #[test]
+    fn test_docs_deploy_to_bare_remote() {
+        use xtasks::tasks::docs::DeployBuilder;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let remote = tmp.path().join("remote.git");
+        let source = tmp.path().join("source");
+        let doc = tmp.path().join("doc");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::create_dir_all(doc.join("my_app")).unwrap();
+        std::fs::write(doc.join("my_app/index.html"), "v1").unwrap();
+        let _ =
+            git_in(tmp.path(), &["init", "-q", "--bare", "remote.git"]);
+        let _ = git_in(&source, &["init", "-q"]);
+        let _ = git_in(&source, &["config", "user.name", "Docs Bot"]);
+        let _ = git_in(
+            &source,
+            &["config", "user.email", "docs@example.com"],
+        );
+        std::fs::write(source.join("lib.rs"), "").unwrap();
+        let _ = git_in(&source, &["add", "lib.rs"]);
+        let _ =
+            git_in(&source, &["commit", "-q", "-m", "Initial commit"]);
+        let _ = git_in(
+            &source,
+            &["remote", "add", "origin", &remote.to_string_lossy()],
+        );
+        let sha = git_in(&source, &["rev-parse", "HEAD"]);
+        std::env::set_var("XTASKS_CONFIRM", "yes");
+
+        let deploy = DeployBuilder::default()
+            .source(source.clone())
+            .crate_dir(Some("my_app".to_string()))
+            .dry_run(true)
+            .build()
+            .unwrap();
+        deploy.run(&doc).unwrap();
+        assert!(git_in(&remote, &["branch", "--list"]).is_empty());
+
+        let deploy = xtasks::tasks::docs::Deploy {
+            dry_run: false,
+            ..deploy
+        };
+        deploy.run(&doc).unwrap();
+        let log =
+            git_in(&remote, &["log", "--format=%s %P", "gh-pages"]);
+        assert_eq!(
+            log.trim(),
+            format!("Deploy documentation from {} ", sha.trim()).trim()
+        );
+        assert_eq!(
+            git_in(&remote, &["show", "gh-pages:my_app/index.html"]),
+            "v1"
+        );
+        assert!(git_in(&remote, &["show", "gh-pages:index.html"])
+            .contains("url=my_app/index.html"));
+        assert!(!git_in(
+            &remote,
+            &["ls-tree", "--name-only", "gh-pages"]
+        )
+        .contains("lib.rs"));
+
+        std::fs::write(doc.join("my_app/index.html"), "v2").unwrap();
+        deploy.run(&doc).unwrap();
+        assert_eq!(
+            git_in(&remote, &["rev-list", "--count", "gh-pages"])
+                .trim(),
+            "2"
+        );
+        assert_eq!(
+            git_in(&remote, &["show", "gh-pages:my_app/index.html"]),
+            "v2"
+        );
+        // Nothing changed: no new commit.
+        deploy.run(&doc).unwrap();
+        assert_eq!(
+            git_in(&remote, &["rev-list", "--count", "gh-pages"])
+                .trim(),
+            "2"
+        );
+
+        std::fs::write(source.join("lib.rs"), "// dirty").unwrap();
+        let err = deploy.run(&doc).unwrap_err();
+        assert!(
+            err.to_string().contains("uncommitted changes"),
+            "{err}"
+        );
+        std::env::remove_var("XTASKS_CONFIRM");
+    }
+
+    const README_LIB_RS: &str = r#"// Copyright © 2023 my_app. All rights reserved.
+
+//! # my_app
+//!
+//! Builds [`Config`]s with [the builder](crate::builder::Builder), see
+//! [`Config::new`][new] or [the guide](https://example.com/guide).
+//!
+//! ```no_run
+//! # use my_app::Config;
+//! let config = Config::new();
+//! ```
+//!
+//! [new]: crate::Config::new
+
+pub struct Config;
+"#;
+
+    const README_MD: &str = "Badges\n\n<!-- xtasks-readme-start -->\nstale\n<!-- xtasks-readme-end -->\n\nLicense\n";
+
+    const README_EXPECTED: &str = r#"Badges
+
+<!-- xtasks-readme-start -->
+
+# my_app
+
+Builds `Config`s with the builder, see
+`Config::new` or [the guide](https://example.com/guide).
+
+```rust
+let config = Config::new();
+```
+
+<!-- xtasks-readme-end -->
+
+License
+"#;
+
+    /// Tests that the crate documentation is rewritten to plain Markdown between
+    /// the README markers, and that check mode fails on a stale README.
+    #[test]
+    fn test_sync_readme() {
+        use xtasks::tasks::docs::sync_readme;
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/lib.rs"), README_LIB_RS)
+            .unwrap();
+        let readme = dir.path().join("README.md");
+        std::fs::write(&readme, README_MD).unwrap();
+
+        let err = sync_readme(dir.path(), &readme, true).unwrap_err();
+        assert!(err.to_string().contains("is out of date"), "{err}");
+        assert_eq!(
+            std::fs::read_to_string(&readme).unwrap(),
+            README_MD
+        );
+
+        assert!(sync_readme(dir.path(), &readme, false).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&readme).unwrap(),
+            README_EXPECTED
+        );
+        assert!(!sync_readme(dir.path(), &readme, true).unwrap());
+        assert!(!sync_readme(dir.path(), &readme, false).unwrap());
+
+        std::fs::write(&readme, "No markers\n").unwrap();
+        let err = sync_readme(dir.path(), &readme, false).unwrap_err();
+        assert!(
+            err.to_string().contains("xtasks-readme-start"),
+            "{err}"
+        );
+    }
+
+    /// Tests that the book directory comes from `[package.metadata.xtask]`, and
+    /// defaults to `docs/book`.
+    #[test]
+    fn test_book_dir_resolution() {
+        use std::path::Path;
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::book::book_dir;
+
+        let configured = r#"{
+            "packages": [{
+                "name": "my-app",
+                "id": "my-app 0.1.0",
+                "metadata": { "xtask": { "book_dir": "guide" } }
+            }],
+            "workspace_members": ["my-app 0.1.0"]
+        }"#;
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", configured),
+        );
+        assert_eq!(
+            with_runner(recorder, book_dir).unwrap(),
+            Path::new("guide")
+        );
+
+        let default = configured.replace(
+            r#""metadata": { "xtask": { "book_dir": "guide" } }"#,
+            r#""metadata": null"#,
+        );
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", default),
+        );
+        assert_eq!(
+            with_runner(recorder, book_dir).unwrap(),
+            Path::new("docs/book")
+        );
+    }
+
+    /// Tests that a directory without `book.toml` is reported before running
+    /// anything.
+    #[test]
+    fn test_book_missing() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::book::{book_in, BookAction};
+
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = Arc::new(RecordingRunner::default());
+        let err = with_runner(recorder.clone(), || {
+            book_in(BookAction::Build, dir.path())
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("No book.toml"), "{err}");
+        assert!(recorder.calls().is_empty());
+    }
+
+    /// Tests the mdbook commands of each action, and the returned output
+    /// directory.
+    #[test]
+    fn test_book_commands() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::book::{book_in, BookAction};
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("book.toml"),
+            "[book]\ntitle = \"Guide\"\n\n[build]\nbuild-dir = \"out\"\n",
+        )
+        .unwrap();
+        let recorder =
+            Arc::new(RecordingRunner::default().missing("mdbook"));
+        let outputs = with_runner(recorder.clone(), || {
+            [
+                BookAction::Build,
+                BookAction::Serve { port: 4000 },
+                BookAction::Test,
+            ]
+            .map(|action| book_in(action, dir.path()).unwrap())
+        });
+        assert!(outputs
+            .iter()
+            .all(|output| *output == dir.path().join("out")));
+        let book = dir.path().display();
+        assert_eq!(
+            recorder.command_lines(),
+            [
+                "cargo install mdbook".to_string(),
+                format!("mdbook build {book}"),
+                "cargo install mdbook".to_string(),
+                format!("mdbook serve {book} --port 4000"),
+                "cargo install mdbook".to_string(),
+                format!("mdbook test {book}"),
+            ]
+        );
+    }
+
+    /// The report printed by `rustdoc --show-coverage --output-format json`.
+    const DOC_COVERAGE_OUTPUT: &str = r#"{"src/lib.rs":{"total":1,"with_docs":1,"total_examples":1,"with_examples":1},"src/ops.rs":{"total":21,"with_docs":15,"total_examples":19,"with_examples":2},"src/tasks/empty.rs":{"total":0,"with_docs":0,"total_examples":0,"with_examples":0},"src/tasks/docs.rs":{"total":18,"with_docs":14,"total_examples":17,"with_examples":3}}"#;
+
+    /// Tests parsing a rustdoc coverage report, and the `fail_under` gate.
+    #[test]
+    fn test_doc_coverage() {
+        use std::path::PathBuf;
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::doc_coverage;
+
+        let recorder =
+            Arc::new(RecordingRunner::default().with_stdout(
+                "cargo +nightly rustdoc",
+                format!("{DOC_COVERAGE_OUTPUT}\n"),
+            ));
+        let coverage =
+            with_runner(recorder.clone(), doc_coverage).unwrap();
+        assert_eq!(
+            recorder.command_lines(),
+            [
+                "rustup run nightly rustc --version",
+                "cargo +nightly rustdoc --lib -- -Z unstable-options --show-coverage --output-format json",
+            ]
+        );
+        assert_eq!(coverage.total_percent, 75.0);
+        assert_eq!(
+            coverage.files,
+            [
+                (PathBuf::from("src/lib.rs"), 100.0),
+                (PathBuf::from("src/ops.rs"), 1500.0 / 21.0),
+                (PathBuf::from("src/tasks/docs.rs"), 1400.0 / 18.0),
+            ]
+        );
+        assert_eq!(
+            coverage.to_string(),
+            "src/lib.rs          100.0%\n\
+             src/ops.rs           71.4%\n\
+             src/tasks/docs.rs    77.8%\n\
+             TOTAL                75.0%"
+        );
+        assert!(coverage.check(None).is_ok());
+        assert!(coverage.check(Some(75.0)).is_ok());
+        let err = coverage.check(Some(80.0)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Documentation coverage 75.0% is below the required 80%"
+        );
+    }
+
+    /// Tests that a missing nightly toolchain is reported with the command
+    /// installing it, without installing it.
+    #[test]
+    fn test_doc_coverage_without_nightly() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::tasks::docs::doc_coverage;
+
+        let recorder = Arc::new(
+            RecordingRunner::default().failing("rustup run nightly"),
+        );
+        let err =
+            with_runner(recorder.clone(), doc_coverage).unwrap_err();
+        assert!(
+            format!("{err:#}").contains(
+                "install it with 'rustup toolchain install nightly"
+            ),
+            "{err:#}"
+        );
+        assert_eq!(recorder.calls().len(), 1);
+    }
 }
@@ -0,0 +1,184 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::Arc};
+    use xtasks::ops::error::{exit_code, EXIT_TOOL_MISSING};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::doctor::{
+        diagnose, doctor, parse_df_available, DoctorReport,
+    };
+
+    /// The output of `df -Pk` with 50 GiB available.
+    const DF: &str = "Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/sda1 104857600 52428800 52428800 50% /\n";
+
+    /// Returns a runner answering the probes of a healthy environment.
+    fn healthy() -> RecordingRunner {
+        RecordingRunner::default()
+            .with_stdout("rustc --version", "rustc 1.75.0 (82e1608df)\n")
+            .with_stdout("cargo --version", "cargo 1.75.0 (1d8b05cdd)\n")
+            .with_stdout(
+                "rustup component list",
+                "cargo-x86_64-unknown-linux-gnu\nclippy-x86_64-unknown-linux-gnu\nllvm-tools-x86_64-unknown-linux-gnu\nrustfmt-x86_64-unknown-linux-gnu\n",
+            )
+            .with_stdout("cargo watch", "cargo-watch 8.4.0\n")
+            .with_stdout("cargo tarpaulin", "cargo-tarpaulin version: 0.27.3\n")
+            .with_stdout("cargo bloat", "cargo-bloat 0.11.1\n")
+            .with_stdout("cargo hack", "cargo-hack 0.6.15\n")
+            .with_stdout("grcov", "grcov 0.8.19\n")
+            .with_stdout("git --version", "git version 2.43.0\n")
+            .with_stdout("df", DF)
+    }
+
+    /// Returns the names of the checks of `report` that failed.
+    fn failed(report: &DoctorReport) -> Vec<&str> {
+        report
+            .checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.name.as_str())
+            .collect()
+    }
+
+    /// Tests the checks of a healthy environment.
+    #[test]
+    fn test_healthy_environment() {
+        let recorder = Arc::new(healthy());
+        let report = with_runner(recorder, || diagnose(Path::new(".")));
+        assert!(report.is_healthy());
+        assert!(failed(&report).is_empty(), "{report}");
+        let names: Vec<&str> = report
+            .checks
+            .iter()
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "rustc",
+                "cargo",
+                "llvm-tools-preview",
+                "rustfmt",
+                "clippy",
+                "cargo-watch",
+                "cargo-tarpaulin",
+                "cargo-bloat",
+                "cargo-hack",
+                "grcov",
+                "git",
+                "git status",
+                "disk space"
+            ]
+        );
+        let text = report.to_string();
+        assert!(text.starts_with(
+            "✓ rustc               rustc 1.75.0 (82e1608df)\n"
+        ));
+        assert!(
+            text.contains("✓ cargo-hack          0.6.15 (optional)\n")
+        );
+        assert!(text.ends_with(
+            "✓ disk space          50.0 GiB available (optional)"
+        ));
+        assert!(!text.contains("fix:"));
+
+        let recorder = Arc::new(healthy());
+        let report =
+            with_runner(recorder.clone(), || doctor(Path::new(".")))
+                .unwrap();
+        assert!(report.is_healthy());
+        assert!(!recorder
+            .command_lines()
+            .iter()
+            .any(|line| line.starts_with("cargo install")));
+    }
+
+    /// Tests the checks of a broken environment, with the commands fixing
+    /// them, and the installation of the missing tools.
+    #[test]
+    fn test_broken_environment() {
+        let broken = || {
+            RecordingRunner::default()
+                .failing("rustc")
+                .with_stdout("cargo --version", "cargo 1.75.0\n")
+                .with_stdout(
+                    "rustup component list",
+                    "rustfmt-x86_64-unknown-linux-gnu\n",
+                )
+                .with_stdout("cargo watch", "cargo-watch 8.4.0\n")
+                .failing("cargo bloat")
+                .missing("cargo-hack")
+                .missing("grcov")
+                .with_stdout("git -C", " M src/lib.rs\0?? notes.txt\0")
+                .with_stdout(
+                    "df",
+                    "Filesystem 1024-blocks Used Available Capacity Mounted on\n/dev/sda1 1000 900 100 90% /\n",
+                )
+        };
+        let report = with_runner(Arc::new(broken()), || {
+            diagnose(Path::new("."))
+        });
+        assert_eq!(
+            failed(&report),
+            [
+                "rustc",
+                "llvm-tools-preview",
+                "clippy",
+                "cargo-hack",
+                "grcov",
+                "git status",
+                "disk space"
+            ]
+        );
+        let failures: Vec<&str> = report
+            .failures()
+            .iter()
+            .map(|check| check.name.as_str())
+            .collect();
+        assert_eq!(failures, ["rustc", "llvm-tools-preview", "clippy"]);
+        assert_eq!(report.missing_tools(), ["cargo-hack", "grcov"]);
+        let bloat = &report.checks[7];
+        assert!(bloat.passed);
+        assert_eq!(bloat.detail, "unknown");
+
+        let text = report.to_string();
+        assert!(text.contains("✗ clippy              not installed\n    fix: rustup component add clippy\n"));
+        assert!(text.contains("✗ grcov               not installed (optional)\n    fix: cargo install grcov\n"));
+        assert!(text.contains(
+            "✗ git status          2 uncommitted changes (optional)\n"
+        ));
+        assert!(text.contains("✗ disk space          0.0 GiB available (optional)\n    fix: cargo xtask clean --all"));
+
+        std::env::set_var("XTASKS_CONFIRM", "yes");
+        let recorder = Arc::new(broken());
+        let error =
+            with_runner(recorder.clone(), || doctor(Path::new(".")))
+                .unwrap_err();
+        std::env::remove_var("XTASKS_CONFIRM");
+        assert_eq!(
+            error.to_string(),
+            "3 required checks failed: rustc, llvm-tools-preview, clippy"
+        );
+        assert_eq!(exit_code(&error), EXIT_TOOL_MISSING);
+        let installs: Vec<String> = recorder
+            .command_lines()
+            .into_iter()
+            .filter(|line| line.starts_with("cargo install"))
+            .collect();
+        assert_eq!(
+            installs,
+            ["cargo install cargo-hack", "cargo install grcov"]
+        );
+    }
+
+    /// Tests the parsing of the available space.
+    #[test]
+    fn test_parse_df_available() {
+        assert_eq!(
+            parse_df_available(DF),
+            Some(50 * 1024 * 1024 * 1024)
+        );
+        assert_eq!(parse_df_available("df: /nope: No such file"), None);
+    }
+}
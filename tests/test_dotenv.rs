@@ -0,0 +1,135 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// The environment and the log level are process-wide, so the tests changing
+// them live in their own test binary and use their own variables.
+#[cfg(test)]
+mod tests {
+    use assert_cmd::Command;
+    use std::{env, fs};
+    use xtasks::loggers::{capture_logs, set_log_level, LogLevel};
+    use xtasks::ops::dotenv::parse_dotenv;
+    use xtasks::ops::error::{exit_code, EXIT_CONFIG, EXIT_IO};
+    use xtasks::ops::load_dotenv;
+
+    /// Returns `pairs` as owned strings.
+    fn owned(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Tests the syntax of the `.env` files.
+    #[test]
+    fn test_parse_dotenv() {
+        assert_eq!(
+            parse_dotenv(
+                "\n# comment\nA=1\nexport B = two words \nC=\"x\\ny \\\"z\\\"\"\nD='#raw'\nE=value # note\nF=\n"
+            )
+            .unwrap(),
+            owned(&[
+                ("A", "1"),
+                ("B", "two words"),
+                ("C", "x\ny \"z\""),
+                ("D", "#raw"),
+                ("E", "value"),
+                ("F", ""),
+            ])
+        );
+        for invalid in
+            ["TOKEN secret", "=secret", "1A=secret", "A-B=secret"]
+        {
+            let error = parse_dotenv(&format!("A=1\n{invalid}\n"))
+                .unwrap_err()
+                .to_string();
+            assert_eq!(
+                error,
+                "Invalid line 2 in .env, expected KEY=VALUE"
+            );
+            assert_eq!(
+                exit_code(&parse_dotenv(invalid).unwrap_err()),
+                EXIT_CONFIG
+            );
+        }
+    }
+
+    /// Tests that the environment takes precedence over the file, as does
+    /// the first definition of a variable, and that the values are not
+    /// logged.
+    #[test]
+    fn test_load_dotenv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(
+            &path,
+            "XTASKS_TEST_DOTENV_TOKEN=s3cr3t-token\nXTASKS_TEST_DOTENV_SET=from-file\nXTASKS_TEST_DOTENV_TOKEN=second\n",
+        )
+        .unwrap();
+        env::set_var("XTASKS_TEST_DOTENV_SET", "from-env");
+
+        set_log_level(Some(&LogLevel::DEBUG));
+        let (loaded, logs) = capture_logs(|| load_dotenv(Some(&path)));
+        set_log_level(None);
+        assert_eq!(loaded.unwrap(), 1);
+        assert_eq!(
+            env::var("XTASKS_TEST_DOTENV_TOKEN").unwrap(),
+            "s3cr3t-token"
+        );
+        assert_eq!(
+            env::var("XTASKS_TEST_DOTENV_SET").unwrap(),
+            "from-env"
+        );
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].level, LogLevel::DEBUG);
+        assert!(logs[0]
+            .description
+            .starts_with("Loaded 1 variables from "));
+        for log in &logs {
+            assert!(!log.description.contains("s3cr3t"));
+            assert!(!log.description.contains("from-file"));
+        }
+
+        // Loading again sets nothing, as every variable is now set.
+        assert_eq!(load_dotenv(Some(&path)).unwrap(), 0);
+        let missing =
+            load_dotenv(Some(&dir.path().join("missing.env")));
+        assert_eq!(exit_code(&missing.unwrap_err()), EXIT_IO);
+    }
+
+    /// Tests that the binary loads the `.env` file of the workspace root from
+    /// a member directory, unless `--no-dotenv` is given.
+    #[test]
+    fn test_binary_dotenv() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\"]\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("app/src")).unwrap();
+        fs::write(
+            dir.path().join("app/Cargo.toml"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join(".env"), "RUSTDOCFLAGS=--dotenv\n")
+            .unwrap();
+        let vars = |flags: &[&str]| {
+            let output = Command::cargo_bin("xtasks")
+                .unwrap()
+                .current_dir(dir.path().join("app"))
+                .env_remove("RUSTDOCFLAGS")
+                .args(flags)
+                .args(["vars", "--json"])
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            let report: serde_json::Value =
+                serde_json::from_slice(&output.stdout).unwrap();
+            report["env"]["RUSTDOCFLAGS"].clone()
+        };
+        assert_eq!(vars(&[]), "--dotenv");
+        assert!(vars(&["--no-dotenv"]).is_null());
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, process::Command, sync::Arc};
+    use xtasks::ops::{
+        self,
+        runner::{self, with_runner, CommandSpec, DryRunRunner},
+    };
+
+    /// Runs the binary with `args` in this workspace, with only the tools in
+    /// `tools` installed, and returns its stdout.
+    fn dry_run(args: &[&str], tools: &Path) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_xtasks"))
+            .args(args)
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .env("PATH", tools)
+            .env_remove("CI")
+            .env_remove("RUSTFLAGS")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Returns the lines printed for the skipped actions.
+    fn plan(stdout: &str) -> Vec<&str> {
+        stdout
+            .lines()
+            .filter(|line| line.starts_with(runner::DRY_RUN_PREFIX))
+            .collect()
+    }
+
+    /// Tests the plans printed by `ci` and `coverage` with `--dry-run`.
+    #[test]
+    fn test_dry_run_plans() {
+        let tools = tempfile::tempdir().unwrap();
+        let stdout = dry_run(&["--dry-run", "ci"], tools.path());
+        assert_eq!(
+            plan(&stdout),
+            [
+                "DRY-RUN: cargo fmt -- --check",
+                "DRY-RUN: cargo clippy --all-targets --all-features -- \
+                 -D warnings -W clippy::pedantic -W clippy::nursery",
+                "DRY-RUN: cargo check --all-targets --all-features",
+                "DRY-RUN: cargo test",
+                "DRY-RUN: RUSTDOCFLAGS='-D warnings' cargo doc --no-deps \
+                 --all-features",
+            ]
+        );
+        assert!(stdout.contains("CI summary:"), "{stdout}");
+
+        let stdout = dry_run(
+            &["coverage", "--engine", "llvm-cov", "--dry-run"],
+            tools.path(),
+        );
+        assert_eq!(
+            plan(&stdout),
+            [
+                "DRY-RUN: cargo install cargo-llvm-cov --locked",
+                "DRY-RUN: cargo llvm-cov --no-report",
+                "DRY-RUN: cargo llvm-cov report --html --output-dir \
+                 target/llvm-cov",
+                "DRY-RUN: cargo llvm-cov report --summary-only",
+            ]
+        );
+    }
+
+    /// Tests that a coverage upload is planned without printing the token.
+    #[test]
+    fn test_dry_run_upload() {
+        let tools = tempfile::tempdir().unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_xtasks"))
+            .args([
+                "--dry-run",
+                "coverage",
+                "--engine",
+                "llvm-cov",
+                "--upload",
+                "codecov",
+            ])
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .env("PATH", tools.path())
+            .env("CODECOV_TOKEN", "s3cr3t-dry-run")
+            .env_remove("CI")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            plan(&stdout).last(),
+            Some(
+                &"DRY-RUN: curl https://codecov.io/upload/v2 with \
+                  target/coverage/lcov.info for codecov"
+            )
+        );
+        assert!(!stdout.contains("s3cr3t"), "{stdout}");
+    }
+
+    /// Tests that the filesystem helpers leave the files alone in a dry run.
+    #[test]
+    fn test_dry_run_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("report.json");
+        fs::write(&file, "{}").unwrap();
+        let copy = dir.path().join("copy");
+        with_runner(Arc::new(DryRunRunner), || {
+            assert!(runner::is_dry_run());
+            ops::remove_file(&file).unwrap();
+            ops::remove_dir(dir.path()).unwrap();
+            assert_eq!(
+                ops::copy_contents(dir.path(), &copy, true).unwrap(),
+                0
+            );
+            ops::write_atomic(&file, "[]").unwrap();
+            drop(ops::backup_file(&file).unwrap());
+            assert!(!dir
+                .path()
+                .join("report.json.xtasks-backup")
+                .exists());
+            assert!(runner::run(&CommandSpec::new("false")).is_ok());
+            let version = runner::query(
+                &CommandSpec::new("cargo").arg("--version"),
+            )
+            .unwrap();
+            assert!(version.starts_with("cargo "), "{version}");
+        });
+        assert!(!runner::is_dry_run());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "{}");
+        assert!(!copy.exists());
+    }
+
+    /// Tests that the printed command lines are quoted for a shell.
+    #[test]
+    fn test_shell_line() {
+        let spec = CommandSpec::new("git")
+            .args(["commit", "-m", "it's done", ""])
+            .env("GIT_EDITOR", "true");
+        assert_eq!(
+            spec.shell_line(),
+            "GIT_EDITOR=true git commit -m 'it'\\''s done' ''"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Context, Error as AnyError};
+    use assert_cmd::Command;
+    use std::{fs, sync::Arc};
+    use xtasks::ops::error::{
+        category, exit_code, step_exit_code, XtaskError, EXIT_CONFIG,
+        EXIT_FAILURE, EXIT_IO, EXIT_LINT, EXIT_STEP, EXIT_TEST,
+        EXIT_TOOL_MISSING,
+    };
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::ops::tools::{ensure_target, ensure_tool};
+    use xtasks::tasks::{ci::CIBuilder, config::XtaskConfig};
+
+    /// Returns the failure of the step `step`.
+    fn step(step: &str) -> XtaskError {
+        XtaskError::StepFailed {
+            step: step.to_string(),
+        }
+    }
+
+    /// Tests the exit code of each category, directly or under contexts.
+    #[test]
+    fn test_exit_codes() {
+        let cases = [
+            (
+                XtaskError::ToolMissing {
+                    tool: "cargo-hack".to_string(),
+                    install: "cargo install cargo-hack".to_string(),
+                },
+                EXIT_TOOL_MISSING,
+            ),
+            (step("fmt"), EXIT_LINT),
+            (step("clippy [all-features]"), EXIT_LINT),
+            (step("test"), EXIT_TEST),
+            (step("miri (core)"), EXIT_TEST),
+            (step("check"), EXIT_STEP),
+            (XtaskError::ConfigInvalid("bad".to_string()), EXIT_CONFIG),
+            (XtaskError::Io("Failed to write x".to_string()), EXIT_IO),
+            (XtaskError::Other("other".to_string()), EXIT_FAILURE),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{error}");
+            let wrapped = AnyError::new(error.clone())
+                .context("outer")
+                .context("outermost");
+            assert_eq!(exit_code(&wrapped), code, "{error}");
+            assert_eq!(category(&wrapped), Some(&error));
+            let as_context = Err::<(), _>(std::fmt::Error)
+                .context(error.clone())
+                .unwrap_err();
+            assert_eq!(exit_code(&as_context), code, "{error}");
+        }
+        assert_eq!(exit_code(&AnyError::msg("uncategorized")), 1);
+        assert_eq!(step_exit_code("unused deps"), EXIT_LINT);
+        assert_eq!(
+            step_exit_code("doc tests [no-default-features]"),
+            11
+        );
+        assert_eq!(step_exit_code("deploy"), EXIT_STEP);
+    }
+
+    /// Tests the categories of the errors returned by the tasks.
+    #[test]
+    fn test_task_errors() {
+        let recorder =
+            Arc::new(RecordingRunner::default().missing("cargo-hack"));
+        let missing = with_runner(recorder, || {
+            ensure_tool("cargo-hack", &["cargo-hack"], false)
+        })
+        .unwrap_err();
+        assert_eq!(exit_code(&missing), EXIT_TOOL_MISSING);
+        assert!(matches!(
+            missing.downcast_ref::<XtaskError>(),
+            Some(XtaskError::ToolMissing { tool, .. }) if tool == "cargo-hack"
+        ));
+
+        let recorder =
+            Arc::new(RecordingRunner::default().with_stdout(
+                "rustup target list",
+                "x86_64-unknown-linux-gnu\n",
+            ));
+        let target = with_runner(recorder, || {
+            ensure_target("wasm32-unknown-unknown", false)
+        })
+        .unwrap_err();
+        assert_eq!(exit_code(&target), EXIT_TOOL_MISSING);
+        assert_eq!(
+            target.to_string(),
+            "'wasm32-unknown-unknown' is not installed, install it with \
+             'rustup target add wasm32-unknown-unknown'"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xtask.toml");
+        fs::write(&path, "[ci]\nlocked = \"yes\"\n").unwrap();
+        assert_eq!(
+            exit_code(&XtaskConfig::load(&path).unwrap_err()),
+            EXIT_CONFIG
+        );
+        assert_eq!(
+            exit_code(
+                &XtaskConfig::load(&dir.path().join("missing.toml"))
+                    .unwrap_err()
+            ),
+            EXIT_IO
+        );
+
+        let failing = Arc::new(
+            RecordingRunner::default().failing("cargo clippy"),
+        );
+        let lint = with_runner(failing, || {
+            CIBuilder::default().locked(false).run()
+        })
+        .unwrap_err();
+        assert!(lint
+            .to_string()
+            .starts_with("CI failed at step 'clippy'"));
+        assert_eq!(category(&lint), Some(&step("clippy")));
+        assert_eq!(exit_code(&lint), EXIT_LINT);
+
+        let failing =
+            Arc::new(RecordingRunner::default().failing("cargo test"));
+        let test = with_runner(failing, || {
+            CIBuilder::default().locked(false).run()
+        })
+        .unwrap_err();
+        assert_eq!(exit_code(&test), EXIT_TEST);
+    }
+
+    /// Tests the exit code of the binary for an invalid configuration.
+    #[test]
+    fn test_binary_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xtask.toml");
+        fs::write(&path, "[powerset\ndepth = 1\n").unwrap();
+        let output = Command::cargo_bin("xtasks")
+            .unwrap()
+            .args(["--config-file", path.to_str().unwrap(), "ci"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(i32::from(EXIT_CONFIG)));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with("✗ ci failed after"), "{stderr}");
+        assert!(
+            stderr.contains("\nError: Failed to parse"),
+            "{stderr}"
+        );
+        assert!(stderr.contains("Caused by:"), "{stderr}");
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process::Command, sync::Arc};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::{
+        ci::CIBuilder,
+        fmt::{run_fmt, FmtOptions, FmtOptionsBuilder},
+    };
+
+    /// Tests the `cargo fmt` invocations of the check and fix modes, scoped
+    /// to packages or to the whole workspace.
+    #[test]
+    fn test_fmt_command() {
+        assert_eq!(
+            FmtOptions::default().command().to_string(),
+            "cargo fmt"
+        );
+        let check =
+            FmtOptionsBuilder::default().check(true).build().unwrap();
+        assert_eq!(check.command().to_string(), "cargo fmt -- --check");
+        let packages = FmtOptionsBuilder::default()
+            .packages(vec!["app".to_string(), "lib".to_string()])
+            .verbose(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            packages.command().to_string(),
+            "cargo fmt --package app --package lib --verbose"
+        );
+        let all = FmtOptionsBuilder::default()
+            .all(true)
+            .check(true)
+            .toolchain(Some("nightly".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            all.command().to_string(),
+            "rustup run nightly cargo fmt --all -- --check"
+        );
+    }
+
+    /// Tests that a missing toolchain is installed with rustfmt before
+    /// rustfmt runs with it.
+    #[test]
+    fn test_fmt_installs_toolchain() {
+        let runner = Arc::new(
+            RecordingRunner::default()
+                .failing("rustup run nightly rustc"),
+        );
+        let opts = FmtOptionsBuilder::default()
+            .check(true)
+            .toolchain(Some("nightly".to_string()))
+            .build()
+            .unwrap();
+        let _ = with_runner(runner.clone(), || run_fmt(&opts)).unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "rustup run nightly rustc --version",
+                "rustup toolchain install nightly --profile minimal --component rustfmt",
+                "rustup run nightly cargo fmt -- --check"
+            ]
+        );
+    }
+
+    /// Tests that the task uses the toolchain of the `fmt` step of CI.
+    #[test]
+    fn test_fmt_toolchain_matches_ci() {
+        let default = CIBuilder::default().build().unwrap();
+        assert_eq!(default.fmt_toolchain(), None);
+        let nightly =
+            CIBuilder::default().nightly(true).build().unwrap();
+        assert_eq!(nightly.fmt_toolchain().as_deref(), Some("nightly"));
+        let matrix = CIBuilder::default()
+            .toolchains(vec!["beta".to_string(), "stable".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(matrix.fmt_toolchain().as_deref(), Some("beta"));
+    }
+
+    /// Tests that a failed check is reported as a failed `fmt` step.
+    #[test]
+    fn test_fmt_check_failure() {
+        let runner =
+            Arc::new(RecordingRunner::default().failing("cargo fmt"));
+        let opts =
+            FmtOptionsBuilder::default().check(true).build().unwrap();
+        let err =
+            with_runner(runner.clone(), || run_fmt(&opts)).unwrap_err();
+        assert_eq!(
+            xtasks::ops::error::exit_code(&err),
+            xtasks::ops::error::EXIT_LINT
+        );
+        assert_eq!(runner.command_lines(), ["cargo fmt -- --check"]);
+    }
+
+    /// Tests that the files rustfmt changed in a scratch repository are
+    /// reported, and that a formatted tree reports none.
+    #[test]
+    fn test_fmt_reports_changed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args([
+                    "-c",
+                    "user.name=xtasks",
+                    "-c",
+                    "user.email=x@t.s",
+                ])
+                .args(args)
+                .current_dir(dir.path())
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {args:?} failed");
+        };
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"scratch\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+        )
+        .unwrap();
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "pub fn a() {}\n")
+            .unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}\n")
+            .unwrap();
+        git(&["init", "-q"]);
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "init"]);
+        fs::write(
+            dir.path().join("src/lib.rs"),
+            "pub fn a( )   {\n}\n",
+        )
+        .unwrap();
+
+        let opts = FmtOptionsBuilder::default()
+            .manifest_path(Some(dir.path().join("Cargo.toml")))
+            .build()
+            .unwrap();
+        assert_eq!(
+            run_fmt(&opts).unwrap(),
+            [PathBuf::from("src/lib.rs")]
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+            "pub fn a() {}\n"
+        );
+        assert!(run_fmt(&opts).unwrap().is_empty());
+    }
+}
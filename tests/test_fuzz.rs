@@ -0,0 +1,176 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        process::Output,
+        sync::Arc,
+        time::Duration,
+    };
+    use xtasks::ops::runner::{
+        with_runner, CommandRunner, CommandSpec, RecordingRunner,
+    };
+    use xtasks::tasks::fuzz::{
+        artifacts, parse_duration, parse_fuzz_list, run_in,
+    };
+
+    /// A runner recording the commands, where running the fuzz target
+    /// `crashing` writes a crash artifact, like libFuzzer does.
+    #[derive(Debug)]
+    struct FuzzRunner {
+        recorder: RecordingRunner,
+        fuzz_dir: PathBuf,
+        crashing: &'static str,
+    }
+
+    impl CommandRunner for FuzzRunner {
+        fn execute(
+            &self,
+            command: &CommandSpec,
+        ) -> std::io::Result<Output> {
+            let line = command.to_string();
+            if line.contains(&format!(" {} -- ", self.crashing)) {
+                let dir =
+                    self.fuzz_dir.join("artifacts").join(self.crashing);
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join("crash-1a2b3c"), [0xff, 0x00])?;
+            }
+            self.recorder.execute(command)
+        }
+
+        fn which(&self, program: &str) -> Option<PathBuf> {
+            self.recorder.which(program)
+        }
+    }
+
+    /// Creates a fuzz project with an artifact left by an earlier run.
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("fuzz/artifacts/parse");
+        fs::create_dir_all(&old).unwrap();
+        fs::write(old.join("crash-old"), "").unwrap();
+        fs::write(old.join(".gitkeep"), "").unwrap();
+        dir
+    }
+
+    /// Tests the time budgets accepted by `--time`.
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(
+            parse_duration("45").unwrap(),
+            Duration::from_secs(45)
+        );
+        assert_eq!(
+            parse_duration(" 2h ").unwrap(),
+            Duration::from_secs(7200)
+        );
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("1d").is_err());
+        assert_eq!(
+            parse_duration("-5s").unwrap_err().to_string(),
+            "Invalid duration '-5s', expected e.g. 60s, 5m or 1h"
+        );
+    }
+
+    /// Tests the parsing of the fuzz target list.
+    #[test]
+    fn test_parse_fuzz_list() {
+        assert_eq!(parse_fuzz_list("  a\n\nb  \n"), ["a", "b"]);
+        assert!(parse_fuzz_list("\n").is_empty());
+    }
+
+    /// Tests that only the files libFuzzer writes count as artifacts.
+    #[test]
+    fn test_artifacts() {
+        let dir = workspace();
+        let fuzz_dir = dir.path().join("fuzz");
+        assert_eq!(
+            artifacts(&fuzz_dir).into_iter().collect::<Vec<_>>(),
+            [fuzz_dir.join("artifacts/parse/crash-old")]
+        );
+        assert!(artifacts(Path::new("no/such/fuzz")).is_empty());
+    }
+
+    /// Tests that every listed target runs and that only the artifacts of
+    /// this run are reported.
+    #[test]
+    fn test_run_reports_new_crashes() {
+        let dir = workspace();
+        let fuzz_dir = dir.path().join("fuzz");
+        let runner = Arc::new(FuzzRunner {
+            recorder: RecordingRunner::default()
+                .with_stdout(
+                    "rustup run nightly cargo fuzz list",
+                    "parse\nrender\n",
+                )
+                .failing("rustup run nightly cargo fuzz run"),
+            fuzz_dir: fuzz_dir.clone(),
+            crashing: "render",
+        });
+        let report = with_runner(runner.clone(), || {
+            run_in(dir.path(), None, Duration::from_secs(30))
+        })
+        .unwrap();
+
+        let lines = runner.recorder.command_lines();
+        let fuzz_dir = fuzz_dir.display();
+        assert!(lines.contains(&format!(
+            "rustup run nightly cargo fuzz run --fuzz-dir {fuzz_dir} parse -- -max_total_time=30"
+        )));
+        assert!(lines.contains(&format!(
+            "rustup run nightly cargo fuzz run --fuzz-dir {fuzz_dir} render -- -max_total_time=30"
+        )));
+
+        let names: Vec<&str> =
+            report.targets.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["parse", "render"]);
+        assert!(report.targets[0].crashes.is_empty());
+        assert_eq!(
+            report.crashes(),
+            [&dir.path().join("fuzz/artifacts/render/crash-1a2b3c")]
+        );
+        assert_eq!(report.failed().len(), 2);
+        assert!(report
+            .to_string()
+            .starts_with("parse: failed\nrender: 1 new crashes\n  "));
+    }
+
+    /// Tests that a given target runs without listing the targets, and
+    /// passes when it writes no artifact.
+    #[test]
+    fn test_run_single_target() {
+        let dir = workspace();
+        let runner = Arc::new(FuzzRunner {
+            recorder: RecordingRunner::default(),
+            fuzz_dir: dir.path().join("fuzz"),
+            crashing: "render",
+        });
+        let report = with_runner(runner.clone(), || {
+            run_in(dir.path(), Some("parse"), Duration::from_secs(90))
+        })
+        .unwrap();
+
+        assert!(!runner
+            .recorder
+            .command_lines()
+            .iter()
+            .any(|line| line.contains("fuzz list")));
+        assert!(report.failed().is_empty());
+        assert_eq!(report.to_string(), "parse: ok (90s)");
+    }
+
+    /// Tests the error when the fuzz project has no target.
+    #[test]
+    fn test_run_without_targets() {
+        let dir = workspace();
+        let runner = Arc::new(RecordingRunner::default());
+        let err = with_runner(runner, || {
+            run_in(dir.path(), None, Duration::from_secs(60))
+        })
+        .unwrap_err();
+        assert!(err.to_string().starts_with("No fuzz target in "));
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+    use xtasks::tasks::init::{
+        scaffold, with_workspace_member, with_xtask_alias,
+        xtask_manifest, InitOptions, InitOptionsBuilder, XTASK_MAIN,
+    };
+
+    /// The manifest of a single-package workspace.
+    const PACKAGE: &str = "\
+[package]
+name = \"app\"
+version = \"0.1.0\"
+edition = \"2021\"
+";
+
+    /// Returns the files under `root`, relative to it and sorted.
+    fn files(root: &Path) -> Vec<String> {
+        fn walk(root: &Path, dir: &Path, files: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    walk(root, &path, files);
+                } else {
+                    let relative = path.strip_prefix(root).unwrap();
+                    files.push(
+                        relative.to_string_lossy().replace('\\', "/"),
+                    );
+                }
+            }
+        }
+        let mut files = Vec::new();
+        walk(root, root, &mut files);
+        files.sort();
+        files
+    }
+
+    /// Returns the contents of `path` under `root`.
+    fn read(root: &Path, path: &str) -> String {
+        fs::read_to_string(root.join(path)).unwrap()
+    }
+
+    /// Returns a temporary workspace with the manifest `manifest`.
+    fn workspace(manifest: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.toml"), manifest).unwrap();
+        dir
+    }
+
+    /// Tests the files scaffolded in a single-package workspace.
+    #[test]
+    fn test_scaffold_package() {
+        let dir = workspace(PACKAGE);
+        let root = dir.path();
+        scaffold(root, InitOptions::default()).unwrap();
+
+        assert_eq!(
+            files(root),
+            [
+                ".cargo/config.toml",
+                "Cargo.toml",
+                "xtask/Cargo.toml",
+                "xtask/src/main.rs"
+            ]
+        );
+        assert_eq!(
+            read(root, "Cargo.toml"),
+            format!("{PACKAGE}\n[workspace]\nmembers = [\"xtask\"]\n")
+        );
+        assert_eq!(
+            read(root, ".cargo/config.toml"),
+            "[alias]\nxtask = \"run --package xtask --\"\n"
+        );
+        assert_eq!(
+            read(root, "xtask/Cargo.toml"),
+            format!(
+                "\
+[package]
+name = \"xtask\"
+version = \"0.0.0\"
+edition = \"2021\"
+publish = false
+
+[dependencies]
+xtasks = \"{}\"
+",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert_eq!(
+            read(root, "xtask/src/main.rs"),
+            "fn main() -> std::process::ExitCode {\n    xtasks::ops::error::exit(xtasks::tasks::main())\n}\n"
+        );
+
+        // Scaffolding again is a no-op.
+        scaffold(root, InitOptions::default()).unwrap();
+        assert_eq!(files(root).len(), 4);
+        assert_eq!(
+            read(root, "Cargo.toml").matches("xtask").count(),
+            1
+        );
+    }
+
+    /// Tests that the existing workspace members and aliases are kept.
+    #[test]
+    fn test_scaffold_workspace() {
+        let dir = workspace(
+            "\
+# The workspace of the app.
+[workspace]
+members = [
+    \"app\",
+    \"cli\", # the command line
+]
+resolver = \"2\"
+",
+        );
+        let root = dir.path();
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        fs::write(
+            root.join(".cargo/config.toml"),
+            "[build]\njobs = 4\n\n[alias]\nb = \"build\"\n",
+        )
+        .unwrap();
+        scaffold(root, InitOptions::default()).unwrap();
+
+        assert_eq!(
+            read(root, "Cargo.toml"),
+            "\
+# The workspace of the app.
+[workspace]
+members = [
+    \"app\",
+    \"cli\", # the command line
+    \"xtask\",
+]
+resolver = \"2\"
+"
+        );
+        assert_eq!(
+            read(root, ".cargo/config.toml"),
+            "[build]\njobs = 4\n\n[alias]\nxtask = \"run --package xtask --\"\nb = \"build\"\n"
+        );
+    }
+
+    /// Tests that existing xtask files and aliases are only replaced with
+    /// `force`.
+    #[test]
+    fn test_scaffold_force() {
+        let dir = workspace(PACKAGE);
+        let root = dir.path();
+        fs::create_dir_all(root.join("xtask/src")).unwrap();
+        fs::write(root.join("xtask/src/main.rs"), "fn main() {}\n")
+            .unwrap();
+        fs::create_dir_all(root.join(".cargo")).unwrap();
+        fs::write(
+            root.join(".cargo/config.toml"),
+            "[alias]\nxtask = \"run -p tasks --\"\n",
+        )
+        .unwrap();
+
+        let error = scaffold(root, InitOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Refusing to overwrite"));
+        assert!(error.contains("main.rs"));
+        assert_eq!(read(root, "Cargo.toml"), PACKAGE);
+        assert!(!root.join("xtask/Cargo.toml").exists());
+
+        fs::remove_file(root.join("xtask/src/main.rs")).unwrap();
+        assert!(scaffold(root, InitOptions::default()).is_err());
+
+        let force =
+            InitOptionsBuilder::default().force(true).build().unwrap();
+        scaffold(root, force).unwrap();
+        assert_eq!(read(root, "xtask/src/main.rs"), XTASK_MAIN);
+        assert_eq!(
+            read(root, ".cargo/config.toml"),
+            "[alias]\nxtask = \"run --package xtask --\"\n"
+        );
+    }
+
+    /// Tests that a dry run writes nothing.
+    #[test]
+    fn test_scaffold_dry_run() {
+        let dir = workspace(PACKAGE);
+        let root = dir.path();
+        let opts = InitOptionsBuilder::default()
+            .dry_run(true)
+            .build()
+            .unwrap();
+        scaffold(root, opts).unwrap();
+        assert_eq!(files(root), ["Cargo.toml"]);
+        assert_eq!(read(root, "Cargo.toml"), PACKAGE);
+
+        let empty = tempfile::tempdir().unwrap();
+        assert!(scaffold(empty.path(), InitOptions::default()).is_err());
+    }
+
+    /// Tests the edits of the manifest and the cargo configuration.
+    #[test]
+    fn test_manifest_edits() {
+        assert_eq!(
+            with_workspace_member(
+                "[workspace]\nmembers = []\n",
+                "xtask"
+            )
+            .unwrap()
+            .unwrap(),
+            "[workspace]\nmembers = [\"xtask\"]\n"
+        );
+        assert_eq!(
+            with_workspace_member(
+                "[workspace]\nresolver = \"2\"\n",
+                "xtask"
+            )
+            .unwrap()
+            .unwrap(),
+            "[workspace]\nmembers = [\"xtask\"]\nresolver = \"2\"\n"
+        );
+        assert_eq!(
+            with_workspace_member(
+                "[workspace]\nmembers = [\"a\", \"xtask\"]\n",
+                "xtask"
+            )
+            .unwrap(),
+            None
+        );
+        assert_eq!(
+            with_workspace_member(
+                "[workspace]\nmembers = [\n  \"a\",\n  \"b\"]\n",
+                "xtask"
+            )
+            .unwrap()
+            .unwrap(),
+            "[workspace]\nmembers = [\n  \"a\",\n  \"b\",\n  \"xtask\"]\n"
+        );
+        assert!(with_workspace_member("[workspace", "xtask").is_err());
+        assert_eq!(
+            with_xtask_alias(
+                "[alias]\nxtask = \"run --package xtask --\"\n",
+                false
+            )
+            .unwrap(),
+            None
+        );
+        assert!(with_xtask_alias("[alias]\nxtask = \"run\"\n", false)
+            .unwrap_err()
+            .to_string()
+            .contains("--force"));
+        assert!(
+            xtask_manifest("1.2.3").ends_with("xtasks = \"1.2.3\"\n")
+        );
+    }
+}
@@ -0,0 +1,159 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use xtasks::ops::metadata::parse_package_licenses;
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::licenses::{
+        licenses, mentions_license, LicenseFormat, LicenseReport,
+    };
+
+    /// The output of `cargo metadata` for a workspace member depending on
+    /// crates under permissive, copyleft, file-only and missing licenses.
+    const METADATA: &str = r#"{
+        "packages": [
+            {"name": "app", "version": "0.1.0", "id": "app 0.1.0 (path+file:///app)", "license": "MIT"},
+            {"name": "serde", "version": "1.0.193", "id": "serde 1.0.193", "license": "MIT OR Apache-2.0"},
+            {"name": "anyhow", "version": "1.0.77", "id": "anyhow 1.0.77", "license": "MIT OR Apache-2.0"},
+            {"name": "readline", "version": "2.1.0", "id": "readline 2.1.0", "license": "GPL-3.0-or-later"},
+            {"name": "ring", "version": "0.17.8", "id": "ring 0.17.8", "license": null, "license_file": "LICENSE"},
+            {"name": "mystery", "version": "0.3.0", "id": "mystery 0.3.0", "license": " "}
+        ],
+        "workspace_members": ["app 0.1.0 (path+file:///app)"]
+    }"#;
+
+    /// Returns the report of the fixture, denying GPL-3.0.
+    fn report() -> LicenseReport {
+        LicenseReport::new(
+            parse_package_licenses(METADATA).unwrap(),
+            &["GPL-3.0".to_string()],
+        )
+    }
+
+    /// Tests that the workspace members are excluded, the dependencies
+    /// sorted, and the blank licenses treated as missing.
+    #[test]
+    fn test_parse_package_licenses() {
+        let licenses = parse_package_licenses(METADATA).unwrap();
+        let names: Vec<&str> =
+            licenses.iter().map(|krate| krate.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["anyhow", "mystery", "readline", "ring", "serde"]
+        );
+        assert_eq!(licenses[1].license, None);
+        assert!(parse_package_licenses("not json").is_err());
+    }
+
+    /// Tests the Markdown report, with the missing and denied crates first.
+    #[test]
+    fn test_markdown_report() {
+        assert_eq!(
+            report().render(LicenseFormat::Markdown).unwrap(),
+            "# Dependency licenses\n\
+             \n## Missing license metadata (1)\n\n\
+             - mystery 0.3.0\n\
+             \n## Denied licenses (1)\n\n\
+             - readline 2.1.0: GPL-3.0-or-later\n\
+             \n## Licenses\n\n\
+             | License | Crates | Names |\n\
+             | --- | --- | --- |\n\
+             | GPL-3.0-or-later | 1 | readline 2.1.0 |\n\
+             | MIT OR Apache-2.0 | 2 | anyhow 1.0.77, serde 1.0.193 |\n\
+             | see LICENSE | 1 | ring 0.17.8 |\n"
+        );
+    }
+
+    /// Tests the JSON and CSV reports.
+    #[test]
+    fn test_json_and_csv_reports() {
+        let report = report();
+        let json: serde_json::Value = serde_json::from_str(
+            &report.render(LicenseFormat::Json).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            json["licenses"]["MIT OR Apache-2.0"],
+            serde_json::json!(["anyhow 1.0.77", "serde 1.0.193"])
+        );
+        assert_eq!(json["missing"][0]["name"], "mystery");
+        assert_eq!(json["denied"][0]["name"], "readline");
+        assert_eq!(json["crates"].as_array().unwrap().len(), 5);
+
+        assert_eq!(
+            report.render(LicenseFormat::Csv).unwrap(),
+            "name,version,license,license_file,denied\n\
+             anyhow,1.0.77,MIT OR Apache-2.0,,false\n\
+             mystery,0.3.0,,,false\n\
+             readline,2.1.0,GPL-3.0-or-later,,true\n\
+             ring,0.17.8,,LICENSE,false\n\
+             serde,1.0.193,MIT OR Apache-2.0,,false\n"
+        );
+    }
+
+    /// Tests the matching of denied licenses in SPDX expressions.
+    #[test]
+    fn test_mentions_license() {
+        assert!(mentions_license("GPL-3.0", "GPL-3.0"));
+        assert!(mentions_license("MIT OR GPL-3.0-only", "GPL-3.0"));
+        assert!(mentions_license(
+            "(Apache-2.0 AND GPL-3.0+)",
+            "GPL-3.0"
+        ));
+        assert!(!mentions_license("LGPL-3.0", "GPL-3.0"));
+        assert!(!mentions_license("GPL-2.0", "GPL-3.0"));
+    }
+
+    /// Tests the parsing of the `--format` values.
+    #[test]
+    fn test_format_parsing() {
+        assert_eq!(
+            "md".parse::<LicenseFormat>().unwrap(),
+            LicenseFormat::Markdown
+        );
+        assert_eq!(
+            "markdown".parse::<LicenseFormat>().unwrap(),
+            LicenseFormat::Markdown
+        );
+        assert_eq!(
+            "JSON".parse::<LicenseFormat>().unwrap(),
+            LicenseFormat::Json
+        );
+        assert_eq!(
+            "csv".parse::<LicenseFormat>().unwrap(),
+            LicenseFormat::Csv
+        );
+        assert_eq!(
+            "xml".parse::<LicenseFormat>().unwrap_err().to_string(),
+            "Unknown license report format 'xml', expected one of: md, json, csv"
+        );
+    }
+
+    /// Tests that a denied license fails the task, naming the crates.
+    #[test]
+    fn test_denied_license_fails() {
+        let runner = || {
+            Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo metadata", METADATA),
+            )
+        };
+        let report =
+            with_runner(runner(), || licenses(LicenseFormat::Csv, &[]))
+                .unwrap();
+        assert!(report.denied().is_empty());
+        assert_eq!(report.missing().len(), 1);
+
+        let err = with_runner(runner(), || {
+            licenses(LicenseFormat::Markdown, &["GPL-3.0".to_string()])
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "1 crates have a denied license: readline 2.1.0 (GPL-3.0-or-later)"
+        );
+        assert_eq!(xtasks::ops::error::exit_code(&err), 12);
+    }
+}
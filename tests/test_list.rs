@@ -0,0 +1,122 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use clap::Command;
+    use std::sync::Arc;
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::{build_cli, list, main_with_args};
+
+    /// The table of the built-in tasks when only cargo-hack is missing.
+    const TABLE: &str = "\
+task            description [tools]
+list            list the tasks and whether the tools they need are installed
+config          show where the default settings of the tasks are read from
+completions     print the completion script of the tasks for a shell
+init            scaffold the xtask crate and the cargo xtask alias in the current workspace
+coverage (cov)  measure the test coverage of the workspace [cargo-tarpaulin]
+vars            print a diagnostics report of the workspace, toolchain, tools and environment
+doctor          check the toolchain, components, tools, git and disk space needed by the tasks
+fmt             format the workspace with rustfmt, or check that it is formatted
+clippy          lint the workspace with clippy and the lint sets of the CI checks
+clean           remove the artifacts generated by the tasks: the coverage reports and the xtask cache unless told otherwise
+audit           audit the dependencies for security advisories with cargo-audit [cargo-audit]
+outdated        report the dependencies with newer versions, semver-compatible or breaking
+vendor          vendor the dependencies with cargo vendor, or check that the vendored sources are up to date
+licenses        report the licenses of the dependencies, grouped by SPDX expression
+msrv            find the minimum supported Rust version by bisecting the toolchains [rustup]
+publish         publish the workspace crates to crates.io in dependency order
+wasm            build the crate for wasm32-unknown-unknown, then run wasm-bindgen and wasm-opt [wasm-bindgen]
+docker          build the container image with docker or podman, tagged with the version and the commit
+cross           build the release binaries for other targets with cross, cargo-zigbuild or cargo
+fuzz            run the cargo-fuzz targets for a time budget and report the new crashes [cargo-fuzz]
+profile         record a flamegraph of a binary, benchmark or test with cargo flamegraph [cargo-flamegraph]
+sort-deps       sort the dependency tables of the workspace manifests
+update          update the locked dependencies and optionally run the CI checks with them
+bench           run the criterion benchmarks and compare them with a baseline
+dist            build the release binaries and package them into archives with checksums [tar]
+changelog       generate the changelog section of the current version from the git history [git]
+release         bump the version, update the changelog, commit and tag a release [git]
+install         install the development tools: cargo-watch, cargo-hack, cargo-bloat, llvm-tools-preview and grcov
+dev             check and test the project with cargo watch on every change [cargo-watch]
+ci              run the CI checks: fmt, clippy, check, test and docs
+install-hooks   install a git hook that runs the CI checks [git]
+book            build, serve or test the mdBook user guide [mdbook]
+readme          generate the README from the crate-level documentation
+typos           check the workspace for spelling mistakes [typos]
+powerset (ps)   run clippy and the tests over combinations of features [cargo-hack (missing)]
+bloat           analyse the binaries with cargo-bloat: the largest crates, the largest functions or the slowest crates to build [cargo-bloat]
+bloat-fns       list the largest functions of the binaries [cargo-bloat]
+size-track      record the size of the release binaries at the current commit [git]
+docs (d)        build the API documentation, watching for changes [cargo-watch]
+";
+
+    /// Tests the table of the built-in tasks, with cargo-hack missing.
+    #[test]
+    fn test_list_table() {
+        let recorder =
+            Arc::new(RecordingRunner::default().missing("cargo-hack"));
+        let table = with_runner(recorder.clone(), || {
+            list::table(&list::tasks(&build_cli()))
+        });
+        assert_eq!(table, TABLE);
+        assert!(recorder.calls().is_empty());
+
+        let args: Vec<String> =
+            ["xtask", "list"].iter().map(ToString::to_string).collect();
+        with_runner(recorder, || main_with_args(&args)).unwrap();
+    }
+
+    /// Tests the JSON listing, which includes the tasks added to the
+    /// command-line interface.
+    #[test]
+    fn test_list_json() {
+        let cli = build_cli().subcommand(
+            Command::new("deploy").about("publish the crates"),
+        );
+        let recorder =
+            Arc::new(RecordingRunner::default().missing("cargo-bloat"));
+        let json = with_runner(recorder, || {
+            list::to_json(&list::tasks(&cli)).unwrap()
+        });
+        let tasks: serde_json::Value =
+            serde_json::from_str(&json).unwrap();
+        let tasks = tasks.as_array().unwrap();
+        assert_eq!(
+            tasks.len(),
+            cli.get_subcommands()
+                .filter(|task| !task.is_hide_set())
+                .count()
+        );
+        assert!(tasks.iter().all(|task| task["name"] != "bloat-deps"));
+        for task in tasks {
+            let fields: Vec<&String> =
+                task.as_object().unwrap().keys().collect();
+            assert_eq!(
+                fields,
+                ["aliases", "description", "name", "tools"]
+            );
+        }
+        let find = |name: &str| {
+            tasks.iter().find(|task| task["name"] == name).unwrap()
+        };
+        assert_eq!(
+            find("bloat-fns")["tools"],
+            serde_json::json!([{ "name": "cargo-bloat", "installed": false }])
+        );
+        assert_eq!(
+            find("powerset")["tools"],
+            serde_json::json!([{ "name": "cargo-hack", "installed": true }])
+        );
+        assert_eq!(
+            find("deploy"),
+            &serde_json::json!({
+                "name": "deploy",
+                "aliases": [],
+                "description": "publish the crates",
+                "tools": []
+            })
+        );
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use xtasks::loggers::{capture_logs, task_log, LogLevel};
+
+    /// Tests that logs are only captured inside `capture_logs`, in order.
+    #[test]
+    fn test_capture_logs() {
+        task_log(LogLevel::INFO, "Outside", "not captured");
+        let (answer, logs) = capture_logs(|| {
+            task_log(LogLevel::INFO, "Test", "first");
+            task_log(LogLevel::ERROR, "Test", "second");
+            42
+        });
+        assert_eq!(answer, 42);
+        let levels: Vec<(LogLevel, &str)> = logs
+            .iter()
+            .map(|log| (log.level.clone(), log.description.as_str()))
+            .collect();
+        assert_eq!(
+            levels,
+            [(LogLevel::INFO, "first"), (LogLevel::ERROR, "second")]
+        );
+        assert_eq!(logs[0].component, "Test");
+    }
+
+    /// Tests that nested captures each get their own logs.
+    #[test]
+    fn test_capture_logs_nested() {
+        let (inner, outer) = capture_logs(|| {
+            task_log(LogLevel::INFO, "Test", "outer");
+            let ((), inner) = capture_logs(|| {
+                task_log(LogLevel::INFO, "Test", "inner")
+            });
+            inner
+        });
+        assert_eq!(inner.len(), 1);
+        assert_eq!(inner[0].description, "inner");
+        assert_eq!(outer.len(), 1);
+        assert_eq!(outer[0].description, "outer");
+    }
+
+    /// Tests that `macro_execute_and_log!` logs its start and completion at
+    /// INFO, and failures at ERROR.
+    #[test]
+    fn test_execute_and_log_levels() {
+        use xtasks::macro_execute_and_log;
+        use xtasks::ops::cmd;
+
+        fn execute(program: &str) -> anyhow::Result<()> {
+            macro_execute_and_log!(
+                cmd!(program),
+                "xtasks",
+                "Test",
+                "start",
+                "complete",
+                "failed"
+            )
+        }
+
+        let (result, logs) = capture_logs(|| execute("true"));
+        assert!(result.is_ok());
+        let levels: Vec<LogLevel> =
+            logs.iter().map(|log| log.level.clone()).collect();
+        assert_eq!(levels, [LogLevel::INFO, LogLevel::INFO]);
+
+        let (result, logs) = capture_logs(|| execute("false"));
+        assert!(result.is_err());
+        assert_eq!(logs[1].level, LogLevel::ERROR);
+        assert_eq!(logs[1].description, "failed");
+    }
+}
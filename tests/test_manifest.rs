@@ -0,0 +1,136 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, os::unix::fs::MetadataExt, path::PathBuf};
+    use xtasks::ops::{
+        error::{exit_code, EXIT_LINT},
+        metadata::parse_workspace_manifests,
+    };
+    use xtasks::tasks::manifest::{sort_dependencies, sort_manifests};
+
+    const UNSORTED: &str = r#"[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+# Serialization.
+serde = { version = "1", features = ["derive"] }
+anyhow = "1.0" # Errors.
+clap = { version = "4", default-features = false }
+
+[dev-dependencies]
+tempfile = "3"
+assert_cmd = "2"
+
+[target.'cfg(unix)'.dependencies]
+nix = "0.27"
+libc = "0.2"
+
+[features]
+zeta = []
+alpha = []
+"#;
+
+    const SORTED: &str = r#"[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+anyhow = "1.0" # Errors.
+clap = { version = "4", default-features = false }
+# Serialization.
+serde = { version = "1", features = ["derive"] }
+
+[dev-dependencies]
+assert_cmd = "2"
+tempfile = "3"
+
+[target.'cfg(unix)'.dependencies]
+libc = "0.2"
+nix = "0.27"
+
+[features]
+zeta = []
+alpha = []
+"#;
+
+    /// Tests that the dependency tables are sorted with their comments and
+    /// inline tables, and that the other tables are left as they are.
+    #[test]
+    fn test_sort_preserves_comments() {
+        assert_eq!(sort_dependencies(UNSORTED).unwrap(), SORTED);
+        assert_eq!(sort_dependencies(SORTED).unwrap(), SORTED);
+        assert_eq!(
+            sort_dependencies(SORTED.trim_end()).unwrap(),
+            SORTED.trim_end()
+        );
+        assert!(sort_dependencies("[dependencies").is_err());
+    }
+
+    /// Tests that unsorted manifests are rewritten and sorted ones are not
+    /// written at all.
+    #[test]
+    fn test_sort_manifests_writes_unsorted_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let unsorted = dir.path().join("Cargo.toml");
+        let sorted = dir.path().join("sorted.toml");
+        fs::write(&unsorted, UNSORTED).unwrap();
+        fs::write(&sorted, SORTED).unwrap();
+        let inode = fs::metadata(&sorted).unwrap().ino();
+
+        let changed =
+            sort_manifests(&[unsorted.clone(), sorted.clone()], false)
+                .unwrap();
+        assert_eq!(changed, std::slice::from_ref(&unsorted));
+        assert_eq!(fs::read_to_string(&unsorted).unwrap(), SORTED);
+        assert_eq!(fs::metadata(&sorted).unwrap().ino(), inode);
+
+        assert!(sort_manifests(&[unsorted, sorted], false)
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Tests that check mode lists the unsorted manifests without writing.
+    #[test]
+    fn test_sort_manifests_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("Cargo.toml");
+        fs::write(&manifest, UNSORTED).unwrap();
+
+        let err = sort_manifests(std::slice::from_ref(&manifest), true)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "1 manifests have unsorted dependencies, run 'cargo xtask sort-deps' to sort them:\n  {}",
+                manifest.display()
+            )
+        );
+        assert_eq!(exit_code(&err), EXIT_LINT);
+        assert_eq!(fs::read_to_string(&manifest).unwrap(), UNSORTED);
+    }
+
+    /// Tests that the workspace manifests are the root one and those of the
+    /// members.
+    #[test]
+    fn test_parse_workspace_manifests() {
+        let metadata = serde_json::json!({
+            "packages": [
+                {"name": "root", "id": "root 0.1.0", "manifest_path": "/ws/Cargo.toml"},
+                {"name": "core", "id": "core 0.1.0", "manifest_path": "/ws/core/Cargo.toml"},
+                {"name": "serde", "id": "serde 1.0.0", "manifest_path": "/registry/serde/Cargo.toml"},
+            ],
+            "workspace_members": ["root 0.1.0", "core 0.1.0"],
+            "workspace_root": "/ws",
+        });
+        assert_eq!(
+            parse_workspace_manifests(&metadata.to_string()).unwrap(),
+            [
+                PathBuf::from("/ws/Cargo.toml"),
+                PathBuf::from("/ws/core/Cargo.toml")
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+    use xtasks::ops::manifest::with_rust_version;
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::msrv::{
+        bisect, cache_path, candidates, find_in, parse_minor, MsrvCache,
+    };
+
+    /// Tests that the bisection finds the boundary of a synthetic range in
+    /// a logarithmic number of checks.
+    #[test]
+    fn test_bisect_boundary() {
+        let versions = candidates("1.56", "1.80").unwrap();
+        assert_eq!(versions.len(), 25);
+        for boundary in [56, 57, 68, 79, 80] {
+            let mut checked = Vec::new();
+            let found = bisect(&versions, |version| {
+                checked.push(version.to_string());
+                Ok(parse_minor(version)? >= boundary)
+            })
+            .unwrap();
+            assert_eq!(found, Some(format!("1.{boundary}")));
+            assert!(checked.len() <= 5, "{checked:?}");
+        }
+    }
+
+    /// Tests that nothing is found when even the upper bound fails, and
+    /// that the errors of the check are returned.
+    #[test]
+    fn test_bisect_failures() {
+        let versions = candidates("1.60", "1.63").unwrap();
+        assert_eq!(bisect(&versions, |_| Ok(false)).unwrap(), None);
+        assert_eq!(
+            bisect(&versions, |_| Ok(true)).unwrap().as_deref(),
+            Some("1.60")
+        );
+        assert!(bisect(&versions, |_| Err(anyhow::anyhow!("offline")))
+            .is_err());
+        assert_eq!(bisect(&[], |_| Ok(true)).unwrap(), None);
+    }
+
+    /// Tests the parsing of the bounds.
+    #[test]
+    fn test_candidates() {
+        assert_eq!(
+            candidates("1.68.2", "1.70").unwrap(),
+            ["1.68", "1.69", "1.70"]
+        );
+        assert_eq!(
+            candidates("1.70", "1.68").unwrap_err().to_string(),
+            "The lower bound 1.70 is above the upper bound 1.68"
+        );
+        assert!(candidates("stable", "1.70").is_err());
+        assert!(parse_minor("1.70.0.1").is_err());
+        assert!(parse_minor("1.x").is_err());
+    }
+
+    /// Tests that the toolchains are checked through rustup and that the
+    /// results are cached for the same lockfile.
+    #[test]
+    fn test_find_caches_results() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("Cargo.lock"), "version = 3\n")
+            .unwrap();
+        let runner = || {
+            let mut runner = RecordingRunner::default();
+            for minor in 60..65 {
+                runner = runner.failing(format!(
+                    "rustup run 1.{minor} cargo check"
+                ));
+            }
+            Arc::new(runner)
+        };
+
+        let first = runner();
+        let found = with_runner(first.clone(), || {
+            find_in(dir.path(), "1.60", "1.70")
+        })
+        .unwrap();
+        assert_eq!(found, "1.65");
+        let checks: Vec<String> = first
+            .command_lines()
+            .into_iter()
+            .filter(|line| line.contains("cargo check"))
+            .collect();
+        assert!(!checks.is_empty());
+        assert!(checks[0].ends_with(&format!(
+            "check --all-features --locked --manifest-path {}",
+            dir.path().join("Cargo.toml").display()
+        )));
+
+        let cache_file = cache_path(dir.path());
+        let cache: MsrvCache = serde_json::from_str(
+            &fs::read_to_string(&cache_file).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(cache.results.len(), checks.len());
+        assert_eq!(cache.results.get("1.65"), Some(&true));
+
+        let second = runner();
+        let found = with_runner(second.clone(), || {
+            find_in(dir.path(), "1.60", "1.70")
+        })
+        .unwrap();
+        assert_eq!(found, "1.65");
+        assert!(second.command_lines().is_empty());
+
+        fs::write(dir.path().join("Cargo.lock"), "version = 4\n")
+            .unwrap();
+        let third = runner();
+        let _ = with_runner(third.clone(), || {
+            find_in(dir.path(), "1.60", "1.70")
+        })
+        .unwrap();
+        assert!(!third.command_lines().is_empty());
+    }
+
+    /// Tests that the upper bound failing is an error.
+    #[test]
+    fn test_find_without_working_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let runner =
+            Arc::new(RecordingRunner::default().failing("rustup run"));
+        let err =
+            with_runner(runner, || find_in(dir.path(), "1.60", "1.61"))
+                .unwrap_err();
+        assert!(err.to_string().contains("1.61"), "{err}");
+    }
+
+    /// Tests that the rust-version is replaced, or added after the version.
+    #[test]
+    fn test_with_rust_version() {
+        assert_eq!(
+            with_rust_version(
+                "[package]\nversion = \"0.1.0\"\nrust-version = \"1.56\" # msrv\n",
+                "1.70"
+            )
+            .unwrap(),
+            "[package]\nversion = \"0.1.0\"\nrust-version = \"1.70\" # msrv\n"
+        );
+        assert_eq!(
+            with_rust_version(
+                "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\nversion = \"1\"\n",
+                "1.70"
+            )
+            .unwrap(),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\nrust-version = \"1.70\"\n\n[dependencies]\nversion = \"1\"\n"
+        );
+        assert!(with_rust_version("[dependencies]\n", "1.70").is_err());
+    }
+}
@@ -122,4 +122,245 @@ mod tests {
         assert!(exists(tmp_dir.path().join("tmp1.txt")));
         assert!(exists(tmp_dir.path().join("tmp2.txt")));
     }
+
+    #[test]
+    fn test_backup_file() {
+        let tmp_dir = tempdir().unwrap();
+        let file = tmp_dir.path().join("Cargo.lock");
+        fs::write(&file, "original").unwrap();
+
+        let backup = backup_file(&file).unwrap();
+        fs::write(&file, "modified").unwrap();
+        backup.restore().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "original");
+
+        // Dropping the backup restores the file as well.
+        {
+            let _backup = backup_file(&file).unwrap();
+            fs::write(&file, "modified").unwrap();
+        }
+        assert_eq!(fs::read_to_string(&file).unwrap(), "original");
+
+        let backup = backup_file(&file).unwrap();
+        fs::write(&file, "kept").unwrap();
+        backup.keep().unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "kept");
+
+        // A file that did not exist is removed again.
+        let missing = tmp_dir.path().join("missing.lock");
+        let backup = backup_file(&missing).unwrap();
+        fs::write(&missing, "created").unwrap();
+        backup.restore().unwrap();
+        assert!(!exists(&missing));
+    }
+
+    /// Tests that the binary targets of the workspace members are listed, and
+    /// other targets left out.
+    #[test]
+    fn test_parse_binary_targets() {
+        use xtasks::ops::metadata::{
+            parse_binary_targets, BinaryTarget,
+        };
+
+        let json = r#"{
+            "packages": [
+                {
+                    "name": "app",
+                    "id": "app 0.1.0",
+                    "targets": [
+                        { "name": "app", "kind": ["lib"] },
+                        { "name": "app-cli", "kind": ["bin"] },
+                        { "name": "bench", "kind": ["bench"] }
+                    ]
+                },
+                {
+                    "name": "dep",
+                    "id": "dep 1.0.0",
+                    "targets": [{ "name": "dep", "kind": ["bin"] }]
+                }
+            ],
+            "workspace_members": ["app 0.1.0"]
+        }"#;
+        assert_eq!(
+            parse_binary_targets(json).unwrap(),
+            [BinaryTarget {
+                package: "app".to_string(),
+                name: "app-cli".to_string(),
+            }]
+        );
+    }
+
+    /// Tests that only workspace members are read from `cargo metadata` output.
+    #[test]
+    fn test_parse_workspace_members() {
+        use xtasks::ops::metadata::parse_workspace_members;
+
+        let json = r#"{
+            "packages": [
+                { "name": "app", "id": "app 0.1.0" },
+                { "name": "vendored", "id": "vendored 1.0.0" }
+            ],
+            "workspace_members": ["app 0.1.0"]
+        }"#;
+        assert_eq!(parse_workspace_members(json).unwrap(), ["app"]);
+        assert!(parse_workspace_members("not json").is_err());
+    }
+
+    /// Tests that the root package is the one at the workspace root, or the only
+    /// member of the workspace.
+    #[test]
+    fn test_parse_root_package() {
+        use xtasks::ops::metadata::parse_root_package;
+
+        let json = r#"{
+            "packages": [
+                {
+                    "name": "xtask",
+                    "id": "xtask 0.1.0",
+                    "manifest_path": "/ws/xtask/Cargo.toml"
+                },
+                {
+                    "name": "my-app",
+                    "id": "my-app 0.1.0",
+                    "manifest_path": "/ws/Cargo.toml"
+                }
+            ],
+            "workspace_members": ["xtask 0.1.0", "my-app 0.1.0"],
+            "workspace_root": "/ws"
+        }"#;
+        assert_eq!(parse_root_package(json).unwrap(), "my-app");
+        let virtual_manifest =
+            json.replace("/ws/Cargo.toml", "/ws/app/Cargo.toml");
+        assert!(parse_root_package(&virtual_manifest).is_err());
+        let single = r#"{
+            "packages": [{ "name": "lib", "id": "lib 0.1.0" }],
+            "workspace_members": ["lib 0.1.0"],
+            "workspace_root": "/ws"
+        }"#;
+        assert_eq!(parse_root_package(single).unwrap(), "lib");
+    }
+
+    /// Tests the platform command used to open reports in the browser.
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_open_command() {
+        assert_eq!(
+            open_command(Path::new("target/coverage/index.html"))
+                .to_string(),
+            "xdg-open target/coverage/index.html"
+        );
+    }
+
+    /// Tests that `retry` stops at the first success and returns the last error
+    /// once every attempt failed.
+    #[test]
+    fn test_retry() {
+        use std::time::Duration;
+
+        let mut calls = 0;
+        let result = retry(3, Duration::ZERO, || {
+            calls += 1;
+            anyhow::ensure!(calls == 2, "attempt {calls} failed");
+            Ok(calls)
+        });
+        assert_eq!(result.unwrap(), 2);
+
+        let mut calls = 0;
+        let err = retry(3, Duration::ZERO, || -> anyhow::Result<()> {
+            calls += 1;
+            anyhow::bail!("attempt {calls} failed")
+        })
+        .unwrap_err();
+        assert_eq!(calls, 3);
+        assert_eq!(err.to_string(), "attempt 3 failed");
+    }
+
+    /// Tests that a command running past its timeout is killed.
+    #[test]
+    #[cfg(unix)]
+    fn test_command_timeout() {
+        use std::time::{Duration, Instant};
+        use xtasks::ops::runner::{
+            CommandRunner, CommandSpec, DuctRunner,
+        };
+
+        let started = Instant::now();
+        let err = DuctRunner
+            .execute(
+                &CommandSpec::new("sleep")
+                    .arg("10")
+                    .timeout(Duration::from_millis(100)),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let output = DuctRunner
+            .execute(
+                &CommandSpec::new("true")
+                    .timeout(Duration::from_secs(10)),
+            )
+            .unwrap();
+        assert!(output.status.success());
+    }
+
+    /// Tests that the lines of a command are handed over as they are
+    /// printed, before the command exits.
+    #[test]
+    #[cfg(unix)]
+    fn test_command_streaming() {
+        use std::time::{Duration, Instant};
+        use xtasks::ops::runner::{
+            CommandRunner, CommandSpec, DuctRunner,
+        };
+
+        let started = Instant::now();
+        let mut lines = Vec::new();
+        let output = DuctRunner
+            .execute_streaming(
+                &CommandSpec::new("sh").args([
+                    "-c",
+                    "echo one; sleep 1; echo two; exit 3",
+                ]),
+                &mut |line| {
+                    lines.push((line.to_string(), started.elapsed()));
+                },
+            )
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(output.stdout, b"one\ntwo\n");
+        assert_eq!(lines[0].0, "one");
+        assert_eq!(lines[1].0, "two");
+        assert!(lines[0].1 < Duration::from_millis(900));
+    }
+
+    /// Tests that a tool found on the `PATH` is not installed again, unless
+    /// `XTASKS_FORCE_INSTALL` is set.
+    #[test]
+    fn test_ensure_tool_installed_skips_found_tools() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+        use xtasks::ops::tools::{
+            ensure_tool_installed, FORCE_INSTALL_ENV,
+        };
+
+        let recorder = Arc::new(RecordingRunner::default());
+        let installed = with_runner(recorder.clone(), || {
+            ensure_tool_installed("cargo-watch", &["cargo-watch"])
+        })
+        .unwrap();
+        assert!(!installed);
+        assert!(recorder.calls().is_empty());
+
+        std::env::set_var(FORCE_INSTALL_ENV, "1");
+        let installed = with_runner(recorder.clone(), || {
+            ensure_tool_installed("cargo-watch", &["cargo-watch"])
+        });
+        std::env::remove_var(FORCE_INSTALL_ENV);
+        assert!(installed.unwrap());
+        assert_eq!(
+            recorder.command_lines(),
+            ["cargo install cargo-watch --force"]
+        );
+    }
 }
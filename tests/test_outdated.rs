@@ -0,0 +1,279 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{Read, Write},
+        net::TcpListener,
+        sync::Arc,
+        thread,
+    };
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::outdated::{
+        outdated, parse_cargo_outdated, parse_lockfile_dependencies,
+        IndexLookupBuilder, OutdatedDependency,
+    };
+
+    /// The output of `cargo outdated --format json` for a workspace of two
+    /// packages sharing a dependency.
+    const CARGO_OUTDATED: &str = r#"{"crate_name":"app","dependencies":[{"name":"anyhow","project":"1.0.77","compat":"1.0.86","latest":"1.0.86","kind":"Normal","platform":null},{"name":"toml","project":"0.7.8","compat":"0.7.9","latest":"0.8.12","kind":"Normal","platform":null},{"name":"serde","project":"1.0.193","compat":"---","latest":"---","kind":"Normal","platform":null}]}
+{"crate_name":"xtask","dependencies":[{"name":"clap","project":"3.2.25","compat":"---","latest":"4.5.4","kind":"Normal","platform":null},{"name":"anyhow","project":"1.0.77","compat":"1.0.86","latest":"1.0.86","kind":"Normal","platform":null}]}
+"#;
+
+    /// Returns an outdated dependency.
+    fn dependency(
+        name: &str,
+        current: &str,
+        compatible: Option<&str>,
+        latest: &str,
+    ) -> OutdatedDependency {
+        OutdatedDependency {
+            name: name.to_string(),
+            current: current.to_string(),
+            compatible: compatible.map(str::to_string),
+            latest: latest.to_string(),
+        }
+    }
+
+    /// Tests that the cargo-outdated report is grouped into compatible and
+    /// breaking updates, without duplicates.
+    #[test]
+    fn test_parse_cargo_outdated() {
+        let report = parse_cargo_outdated(CARGO_OUTDATED).unwrap();
+        assert_eq!(report.source, "cargo-outdated");
+        assert_eq!(
+            report.dependencies,
+            [
+                dependency(
+                    "anyhow",
+                    "1.0.77",
+                    Some("1.0.86"),
+                    "1.0.86"
+                ),
+                dependency("clap", "3.2.25", None, "4.5.4"),
+                dependency("toml", "0.7.8", Some("0.7.9"), "0.8.12"),
+            ]
+        );
+        assert_eq!(report.compatible(), [&report.dependencies[0]]);
+        assert_eq!(
+            report.breaking(),
+            [&report.dependencies[1], &report.dependencies[2]]
+        );
+        assert_eq!(
+            report.to_string(),
+            "Semver-compatible updates (1):\n\
+             \x20 name    current  compatible  latest\n\
+             \x20 anyhow  1.0.77   1.0.86      1.0.86\n\
+             \n\
+             Breaking updates (2):\n\
+             \x20 name    current  compatible  latest\n\
+             \x20 clap    3.2.25   -           4.5.4\n\
+             \x20 toml    0.7.8    0.7.9       0.8.12\n\
+             Source: cargo-outdated"
+        );
+        assert!(parse_cargo_outdated("not json").is_err());
+    }
+
+    /// Tests that `--exit-code` fails when breaking updates exist.
+    #[test]
+    fn test_outdated_exit_code() {
+        let runner = || {
+            Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo outdated", CARGO_OUTDATED),
+            )
+        };
+        let report = with_runner(runner(), || outdated(false)).unwrap();
+        assert_eq!(report.breaking().len(), 2);
+        let err = with_runner(runner(), || outdated(true)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 dependencies have breaking updates: clap 4.5.4, toml 0.8.12"
+        );
+        assert_eq!(xtasks::ops::error::exit_code(&err), 12);
+    }
+
+    /// Tests that the direct registry dependencies of the workspace members
+    /// are read from the lockfile, with the version of a duplicated crate.
+    #[test]
+    fn test_parse_lockfile_dependencies() {
+        let lockfile = r#"
+version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["lib", "rand 0.8.5", "serde"]
+
+[[package]]
+name = "lib"
+version = "0.1.0"
+dependencies = ["rand 0.7.3"]
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.193"
+source = "sparse+https://index.crates.io/"
+dependencies = ["serde_derive"]
+
+[[package]]
+name = "serde_derive"
+version = "1.0.193"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+        assert_eq!(
+            parse_lockfile_dependencies(lockfile).unwrap(),
+            [
+                ("rand".to_string(), "0.7.3".to_string()),
+                ("rand".to_string(), "0.8.5".to_string()),
+                ("serde".to_string(), "1.0.193".to_string()),
+            ]
+        );
+        assert!(parse_lockfile_dependencies("[[package]").is_err());
+    }
+
+    /// Serves the sparse index `entries`, by path, to `requests` requests,
+    /// answering 404 for the other paths.
+    fn serve_index(
+        entries: HashMap<&'static str, &'static str>,
+        requests: usize,
+    ) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut paths = Vec::new();
+            for _ in 0..requests {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).unwrap();
+                    if read == 0 {
+                        break;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let (status, body) = entries
+                    .get(path.as_str())
+                    .map_or((404, ""), |body| (200, *body));
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+                paths.push(path);
+            }
+            paths.sort();
+            paths
+        });
+        (url, handle)
+    }
+
+    /// Tests the fallback on the sparse index: yanked and pre-release
+    /// versions are skipped, and crates missing from the index are ignored.
+    #[test]
+    fn test_sparse_index_fallback() {
+        let entries = HashMap::from([
+            (
+                "/an/yh/anyhow",
+                "{\"name\":\"anyhow\",\"vers\":\"1.0.77\",\"yanked\":false}\n\
+                 {\"name\":\"anyhow\",\"vers\":\"1.0.86\",\"yanked\":false}\n\
+                 {\"name\":\"anyhow\",\"vers\":\"1.0.87\",\"yanked\":true}\n",
+            ),
+            (
+                "/3/s/syn",
+                "{\"name\":\"syn\",\"vers\":\"1.0.109\",\"yanked\":false}\n\
+                 {\"name\":\"syn\",\"vers\":\"2.0.60\",\"yanked\":false}\n\
+                 {\"name\":\"syn\",\"vers\":\"3.0.0-alpha\",\"yanked\":false}\n",
+            ),
+            (
+                "/se/rd/serde",
+                "{\"name\":\"serde\",\"vers\":\"1.0.193\",\"yanked\":false}\n",
+            ),
+        ]);
+        let (url, server) = serve_index(entries, 4);
+        let dir = tempfile::tempdir().unwrap();
+        let lookup = IndexLookupBuilder::default()
+            .index_url(url.clone())
+            .concurrency(2_usize)
+            .cache_dir(dir.path().join("outdated"))
+            .build()
+            .unwrap();
+        let dependencies: Vec<(String, String)> = [
+            ("anyhow", "1.0.77"),
+            ("private", "0.1.0"),
+            ("serde", "1.0.193"),
+            ("syn", "1.0.100"),
+        ]
+        .iter()
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+        .collect();
+
+        let report = lookup.report(&dependencies).unwrap();
+        assert_eq!(report.source, format!("crates.io index ({url})"));
+        assert_eq!(
+            report.dependencies,
+            [
+                dependency(
+                    "anyhow",
+                    "1.0.77",
+                    Some("1.0.86"),
+                    "1.0.86"
+                ),
+                dependency("syn", "1.0.100", Some("1.0.109"), "2.0.60"),
+            ]
+        );
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "/3/s/syn",
+                "/an/yh/anyhow",
+                "/pr/iv/private",
+                "/se/rd/serde"
+            ]
+        );
+    }
+
+    /// Tests that an unreachable index is reported as an error naming the
+    /// crates, instead of an empty report.
+    #[test]
+    fn test_sparse_index_offline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let dir = tempfile::tempdir().unwrap();
+        let lookup = IndexLookupBuilder::default()
+            .index_url(url.clone())
+            .cache_dir(dir.path().to_path_buf())
+            .build()
+            .unwrap();
+        let err = lookup
+            .report(&[("anyhow".to_string(), "1.0.77".to_string())])
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with(&format!(
+                "Failed to fetch 1 of 1 crates from {url}: anyhow"
+            )),
+            "{err}"
+        );
+    }
+}
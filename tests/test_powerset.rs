@@ -70,4 +70,742 @@ mod tests {
             serde_json::from_str(&serialized).unwrap();
         assert_eq!(powerset, deserialized);
     }
+
+    /// Tests the `cargo hack` commands run through the current runner.
+    ///
+    /// # Steps
+    /// 1. Run a powerset test with a recording runner whose test command fails.
+    /// 2. Assert that the error names the failing command and that the doc tests
+    ///    were not run.
+    ///
+    /// # Expected Outcome
+    /// The commands are recorded in order and the run stops at the first failure.
+    #[test]
+    fn test_powerset_commands() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .failing("cargo hack --workspace"),
+        );
+        let err = runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(1)
+                .exclude_no_default_features(true)
+                .run()
+        })
+        .unwrap_err();
+        let common =
+            "--workspace --exclude xtask --feature-powerset --depth 1 \
+                      --exclude-no-default-features";
+        assert_eq!(
+            recorder.command_lines(),
+            [
+                "cargo hack --version".to_string(),
+                format!("cargo hack clippy {common} -- -D warnings"),
+                format!("cargo hack {common} test"),
+            ]
+        );
+        assert!(
+            err.to_string().contains("cargo hack --workspace"),
+            "{err}"
+        );
+    }
+
+    /// Tests that a failing exit status of each phase fails the run, naming the
+    /// phase and the feature combinations it covered.
+    #[test]
+    fn test_powerset_failing_phases() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let run = |failing: &str| {
+            let recorder =
+                Arc::new(RecordingRunner::default().failing(failing));
+            let result = runner::with_runner(recorder.clone(), || {
+                PowersetBuilder::new(2).run()
+            });
+            (result, recorder.calls().len())
+        };
+
+        let (result, calls) = run("cargo hack clippy");
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().starts_with(
+                "Powerset clippy failed for the combinations of up to 2 features: "
+            ),
+            "{err}"
+        );
+        assert_eq!(calls, 2);
+
+        let (result, calls) = run("cargo hack --workspace --exclude xtask --feature-powerset --depth 2 test --doc");
+        let err = result.unwrap_err();
+        assert!(
+            err.to_string().starts_with("Powerset doc test failed"),
+            "{err}"
+        );
+        assert!(
+            format!("{err:#}").ends_with("exited with exit status: 1")
+        );
+        assert_eq!(calls, 4);
+
+        assert!(run("cargo nothing").0.is_ok());
+    }
+
+    /// Tests that cargo-hack is installed before the run when it is missing.
+    #[test]
+    fn test_powerset_installs_cargo_hack() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let recorder =
+            Arc::new(RecordingRunner::default().missing("cargo-hack"));
+        runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(1).run()
+        })
+        .unwrap();
+        let lines = recorder.command_lines();
+        assert_eq!(lines[0], "cargo install cargo-hack --locked");
+        assert!(lines[1].starts_with("cargo hack "));
+    }
+
+    /// The output of `cargo metadata --no-deps` for a workspace whose package
+    /// declares the `json`, `yaml` and `tokio` features.
+    const METADATA: &str = r#"{
+        "packages": [
+            {
+                "name": "app",
+                "id": "app 0.1.0",
+                "features": { "default": ["json"], "json": [], "yaml": [], "tokio": [] }
+            },
+            {
+                "name": "vendored",
+                "id": "vendored 1.0.0",
+                "features": { "vendored-only": [] }
+            }
+        ],
+        "workspace_members": ["app 0.1.0"]
+    }"#;
+
+    /// Tests that each feature list maps to its cargo-hack argument in every
+    /// phase.
+    #[test]
+    fn test_powerset_feature_lists() {
+        let powerset = PowersetBuilder::new(2)
+            .exclude_features(vec!["tokio".to_string()])
+            .include_features(vec![
+                "json".to_string(),
+                "yaml".to_string(),
+            ])
+            .mutually_exclusive(vec![
+                vec!["json".to_string(), "yaml".to_string()],
+                vec!["default".to_string(), "tokio".to_string()],
+            ])
+            .build()
+            .unwrap();
+        let common = "--workspace --exclude xtask --feature-powerset --depth 2 \
+                      --exclude-features tokio --include-features json,yaml \
+                      --mutually-exclusive-features json,yaml \
+                      --mutually-exclusive-features default,tokio";
+        let lines: Vec<String> = powerset
+            .commands()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                format!("cargo hack clippy {common} -- -D warnings"),
+                format!("cargo hack {common} test"),
+                format!("cargo hack {common} test --doc"),
+            ]
+        );
+    }
+
+    /// Tests that a feature the workspace does not declare fails the run before
+    /// cargo-hack is invoked.
+    #[test]
+    fn test_powerset_unknown_feature() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA),
+        );
+        let err = runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(2)
+                .mutually_exclusive(vec![vec![
+                    "json".to_string(),
+                    "vendored-only".to_string(),
+                ]])
+                .run()
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown feature 'vendored-only', expected one of the workspace features: default, json, tokio, yaml"
+        );
+        assert_eq!(recorder.calls().len(), 1);
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA),
+        );
+        runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(2)
+                .exclude_features(vec!["tokio".to_string()])
+                .run()
+        })
+        .unwrap();
+        assert_eq!(recorder.calls().len(), 5);
+    }
+
+    /// Tests that `xtask` is excluded by default, also from configurations
+    /// written before the exclusions were configurable, and that the
+    /// exclusions and the allowlist map to cargo-hack arguments.
+    #[test]
+    fn test_powerset_packages() {
+        let powerset = PowersetBuilder::new(1).build().unwrap();
+        assert_eq!(powerset.exclude_packages, ["xtask"]);
+        assert!(powerset.packages.is_empty());
+        let legacy: Powerset = serde_json::from_str(
+            r#"{"depth":1,"exclude_no_default_features":false}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy, powerset);
+
+        let powerset = PowersetBuilder::new(1)
+            .exclude_packages(vec![
+                "xtask-runner".to_string(),
+                "bench".to_string(),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!(
+            powerset.commands()[1].to_string(),
+            "cargo hack --workspace --exclude xtask-runner --exclude bench \
+             --feature-powerset --depth 1 test"
+        );
+
+        let powerset = PowersetBuilder::new(1)
+            .packages(vec!["app".to_string(), "core".to_string()])
+            .build()
+            .unwrap();
+        assert_eq!(
+            powerset.commands()[1].to_string(),
+            "cargo hack --package app --package core --feature-powerset --depth 1 test"
+        );
+    }
+
+    /// Tests that configured packages must be workspace members, while the
+    /// default exclusion of `xtask` is not checked.
+    #[test]
+    fn test_powerset_unknown_package() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let run = |builder: &mut PowersetBuilder| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo metadata", METADATA),
+            );
+            let result =
+                runner::with_runner(recorder.clone(), || builder.run());
+            (result, recorder.command_lines())
+        };
+
+        let (result, lines) =
+            run(PowersetBuilder::new(1)
+                .packages(vec!["core".to_string()]));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unknown package 'core', expected one of the workspace members: app"
+        );
+        assert_eq!(lines.len(), 1);
+
+        let (result, _) = run(PowersetBuilder::new(1)
+            .exclude_packages(vec!["xtask-runner".to_string()]));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unknown package 'xtask-runner'"));
+
+        let (result, lines) = run(&mut PowersetBuilder::new(1));
+        result.unwrap();
+        assert!(lines
+            .iter()
+            .all(|line| line.starts_with("cargo hack")));
+    }
+
+    /// Tests the number of feature combinations for several feature sets and
+    /// configurations.
+    #[test]
+    fn test_powerset_feature_combinations() {
+        use std::collections::BTreeSet;
+
+        let features = |names: &[&str]| -> BTreeSet<String> {
+            names.iter().map(ToString::to_string).collect()
+        };
+        let strings = |names: &[&str]| -> Vec<String> {
+            names.iter().map(ToString::to_string).collect()
+        };
+        let abc = features(&["default", "a", "b", "c"]);
+        let count = |builder: &mut PowersetBuilder,
+                     set: &BTreeSet<String>| {
+            builder.build().unwrap().feature_combinations(set).len()
+        };
+
+        assert_eq!(count(&mut PowersetBuilder::new(1), &abc), 4);
+        assert_eq!(count(&mut PowersetBuilder::new(2), &abc), 7);
+        assert_eq!(count(&mut PowersetBuilder::new(3), &abc), 8);
+        assert_eq!(count(&mut PowersetBuilder::new(5), &abc), 8);
+        assert_eq!(
+            count(&mut PowersetBuilder::new(2), &features(&[])),
+            1
+        );
+        assert_eq!(
+            count(
+                PowersetBuilder::new(2)
+                    .exclude_no_default_features(true),
+                &abc
+            ),
+            6
+        );
+        assert_eq!(
+            count(
+                PowersetBuilder::new(2)
+                    .exclude_features(strings(&["c"])),
+                &abc
+            ),
+            4
+        );
+        assert_eq!(
+            count(
+                PowersetBuilder::new(2)
+                    .include_features(strings(&["a", "b"])),
+                &abc
+            ),
+            4
+        );
+        assert_eq!(
+            count(
+                PowersetBuilder::new(3)
+                    .mutually_exclusive(vec![strings(&["a", "b"])]),
+                &abc
+            ),
+            6
+        );
+
+        let combinations = PowersetBuilder::new(2)
+            .build()
+            .unwrap()
+            .feature_combinations(&features(&["x", "y"]));
+        assert_eq!(
+            combinations,
+            [
+                strings(&[]),
+                strings(&["x"]),
+                strings(&["y"]),
+                strings(&["x", "y"]),
+            ]
+        );
+    }
+
+    /// Tests that only the selected steps run, in their usual order.
+    #[test]
+    fn test_powerset_steps() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+        use xtasks::tasks::powerset::PowersetStep;
+
+        let recorder = Arc::new(RecordingRunner::default());
+        runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(1)
+                .steps(vec![
+                    PowersetStep::DocTest,
+                    PowersetStep::Clippy,
+                ])
+                .run()
+        })
+        .unwrap();
+        let lines = recorder.command_lines();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("cargo hack clippy"));
+        assert!(lines[2].ends_with("test --doc"));
+
+        assert_eq!(
+            "doc-test".parse::<PowersetStep>().unwrap(),
+            PowersetStep::DocTest
+        );
+        assert_eq!(
+            "bench".parse::<PowersetStep>().unwrap_err().to_string(),
+            "Unknown powerset step 'bench', expected one of: clippy, test, doc-test"
+        );
+    }
+
+    /// Tests that a dry run reads the features of the tested packages but runs
+    /// and installs nothing.
+    #[test]
+    fn test_powerset_dry_run() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .missing("cargo-hack"),
+        );
+        runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(2).dry_run(true).run()
+        })
+        .unwrap();
+        assert!(recorder
+            .command_lines()
+            .iter()
+            .all(|line| line.starts_with("cargo metadata")));
+
+        let matrix = runner::with_runner(recorder, || {
+            PowersetBuilder::new(2).build().unwrap().matrix()
+        })
+        .unwrap();
+        assert_eq!(matrix.len(), 1);
+        assert_eq!(matrix[0].0, "app");
+        // No features, 3 single features, and 3 pairs.
+        assert_eq!(matrix[0].1.len(), 7);
+    }
+
+    /// Tests that each combination is run separately, and that the summary
+    /// lists the failures first, is saved, and fails the run naming only the
+    /// failing combinations.
+    #[test]
+    fn test_powerset_summary() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+        use xtasks::tasks::powerset::{PowersetStep, PowersetSummary};
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("xtask").join("powerset.json");
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo metadata", METADATA)
+                .failing("cargo hack clippy --package app --no-default-features --features yaml")
+                .failing("cargo hack --package app --no-default-features test"),
+        );
+        let err = runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(1)
+                .exclude_features(vec!["tokio".to_string()])
+                .steps(vec![PowersetStep::Clippy, PowersetStep::Test])
+                .summary(true)
+                .summary_file(file.clone())
+                .run()
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Powerset failed for 2 of 3 combinations: \
+             app --no-default-features (test); app --features yaml (clippy)"
+        );
+        let hack_runs = recorder
+            .command_lines()
+            .iter()
+            .filter(|line| {
+                line.starts_with("cargo hack")
+                    && *line != "cargo hack --version"
+            })
+            .count();
+        assert_eq!(hack_runs, 6);
+
+        let summary: PowersetSummary = serde_json::from_str(
+            &std::fs::read_to_string(&file).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            summary.to_string(),
+            "combination                clippy    test
+app --no-default-features  ok        FAILED
+app --features yaml        FAILED    ok
+app --features json        ok        ok"
+        );
+        assert_eq!(summary.failures().count(), 2);
+    }
+
+    /// Tests that a depth of zero is rejected by the builder, and a negative
+    /// one when deserialized.
+    #[test]
+    fn test_powerset_invalid_depth() {
+        assert_eq!(
+            PowersetBuilder::new(0).build().unwrap_err().to_string(),
+            "Invalid powerset depth 0, expected an integer of at least 1"
+        );
+        let err = serde_json::from_str::<Powerset>(r#"{"depth":-3}"#)
+            .unwrap_err();
+        assert!(
+            err.to_string().starts_with(
+                "invalid powerset depth -3, expected an integer of at least 1"
+            ),
+            "{err}"
+        );
+        assert!(
+            serde_json::from_str::<Powerset>(r#"{"depth":0}"#).is_err()
+        );
+        assert_eq!(
+            serde_json::from_str::<Powerset>(
+                r#"{"depth":3,"exclude_no_default_features":false}"#
+            )
+            .unwrap()
+            .depth,
+            3
+        );
+    }
+
+    /// Tests that a depth above the number of combined features fails the run
+    /// before cargo-hack is invoked.
+    #[test]
+    fn test_powerset_depth_exceeds_features() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let run = |builder: &mut PowersetBuilder| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo metadata", METADATA),
+            );
+            let result =
+                runner::with_runner(recorder.clone(), || builder.run());
+            (result, recorder.command_lines())
+        };
+
+        let (result, lines) = run(&mut PowersetBuilder::new(4));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Powerset depth 4 exceeds the 3 features combined for the tested packages, use a depth of at most 3"
+        );
+        assert_eq!(
+            lines,
+            ["cargo metadata --format-version 1 --no-deps"]
+        );
+
+        let (result, _) = run(PowersetBuilder::new(3)
+            .exclude_features(vec!["tokio".to_string()]));
+        assert!(result.is_err());
+        let (result, _) = run(&mut PowersetBuilder::new(3));
+        result.unwrap();
+    }
+
+    /// Tests that `--no-dev-deps` is only passed to clippy, and `--locked` and
+    /// `--keep-going` to every step.
+    #[test]
+    fn test_powerset_cargo_hack_flags() {
+        let powerset = PowersetBuilder::new(1)
+            .no_dev_deps(true)
+            .locked(true)
+            .keep_going(true)
+            .build()
+            .unwrap();
+        let common = "--keep-going --workspace --exclude xtask --feature-powerset --depth 1";
+        let lines: Vec<String> = powerset
+            .commands()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                format!("cargo hack clippy {common} --no-dev-deps --locked -- -D warnings"),
+                format!("cargo hack {common} --locked test"),
+                format!("cargo hack {common} --locked test --doc"),
+            ]
+        );
+
+        let default = PowersetBuilder::new(1).build().unwrap();
+        assert!(default.commands().iter().all(|command| {
+            let line = command.to_string();
+            !line.contains("--no-dev-deps")
+                && !line.contains("--locked")
+                && !line.contains("--keep-going")
+        }));
+    }
+
+    /// Tests that with `keep_going` every step runs, and the error lists all
+    /// the failing ones.
+    #[test]
+    fn test_powerset_keep_going() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .failing("cargo hack clippy")
+                .failing("cargo hack --keep-going --workspace --exclude xtask --feature-powerset --depth 1 test --doc"),
+        );
+        let err = runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(1).keep_going(true).run()
+        })
+        .unwrap_err();
+        assert_eq!(recorder.calls().len(), 4);
+        let message = err.to_string();
+        assert!(
+            message.starts_with(
+                "2 powerset steps failed:\nPowerset clippy failed"
+            ),
+            "{message}"
+        );
+        assert!(
+            message.contains("\nPowerset doc test failed"),
+            "{message}"
+        );
+        assert!(!message.contains("Powerset test failed"), "{message}");
+    }
+
+    /// Tests that a recent cargo-hack is used as is, that an older one is
+    /// rejected with the command upgrading it, and that a missing one is
+    /// installed unless auto-installation is turned off.
+    #[test]
+    fn test_powerset_cargo_hack_version() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+        use xtasks::tasks::powerset::{
+            parse_cargo_hack_version, REQUIRED_VERSION,
+        };
+
+        assert_eq!(
+            parse_cargo_hack_version("cargo-hack 0.6.28-dev (abc)"),
+            Some((0, 6, 28))
+        );
+        assert_eq!(parse_cargo_hack_version("cargo-hack"), None);
+
+        let run = |version: &str| {
+            let recorder = Arc::new(
+                RecordingRunner::default()
+                    .with_stdout("cargo hack --version", version),
+            );
+            let result = runner::with_runner(recorder.clone(), || {
+                PowersetBuilder::new(1).run()
+            });
+            (result, recorder.command_lines())
+        };
+
+        let (result, lines) = run("cargo-hack 0.6.28\n");
+        result.unwrap();
+        assert_eq!(lines.len(), 4);
+
+        let (result, lines) =
+            run(&format!("cargo-hack {REQUIRED_VERSION}"));
+        result.unwrap();
+        assert_eq!(lines.len(), 4);
+
+        let (result, lines) = run("cargo-hack 0.5.9\n");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!(
+                "cargo-hack 0.5.9 is older than the required {REQUIRED_VERSION}, \
+                 upgrade it with 'cargo install cargo-hack --locked --force'"
+            )
+        );
+        assert_eq!(lines, ["cargo hack --version"]);
+
+        let recorder =
+            Arc::new(RecordingRunner::default().missing("cargo-hack"));
+        let err = runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(1).auto_install(false).run()
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "'cargo-hack' is not installed, install it with \
+             'cargo install cargo-hack --locked'"
+        );
+        assert!(recorder.calls().is_empty());
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .missing("cargo-hack")
+                .with_stdout(
+                    "cargo hack --version",
+                    "cargo-hack 0.6.28",
+                ),
+        );
+        runner::with_runner(recorder.clone(), || {
+            PowersetBuilder::new(1).run()
+        })
+        .unwrap();
+        let lines = recorder.command_lines();
+        assert_eq!(lines[0], "cargo install cargo-hack --locked");
+        assert_eq!(lines[1], "cargo hack --version");
+    }
+
+    /// Tests that a configuration read from JSON runs without a builder, and
+    /// that the default configuration matches the builder defaults.
+    #[test]
+    fn test_powerset_run_deserialized() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+
+        assert_eq!(
+            Powerset::default(),
+            PowersetBuilder::default().build().unwrap()
+        );
+
+        let powerset: Powerset = serde_json::from_str(
+            r#"{
+                "depth": 1,
+                "exclude_no_default_features": true,
+                "steps": ["Test"]
+            }"#,
+        )
+        .unwrap();
+        let recorder = Arc::new(RecordingRunner::default());
+        runner::with_runner(recorder.clone(), || powerset.run())
+            .unwrap();
+        assert_eq!(
+            recorder.command_lines(),
+            [
+                "cargo hack --version",
+                "cargo hack --workspace --exclude xtask --feature-powerset \
+                 --depth 1 --exclude-no-default-features test",
+            ]
+        );
+    }
+
+    /// Tests that the arguments after `--` reach the test steps verbatim.
+    #[test]
+    fn test_powerset_cli_test_args() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{self, RecordingRunner};
+        use xtasks::tasks::main_with_args;
+
+        let args: Vec<String> = [
+            "xtask",
+            "powerset",
+            "--only",
+            "test",
+            "--only",
+            "doc-test",
+            "--",
+            "--test-threads",
+            "1",
+            "--skip=slow test",
+        ]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+        let recorder = Arc::new(RecordingRunner::default());
+        runner::with_runner(recorder.clone(), || main_with_args(&args))
+            .unwrap();
+        let calls = recorder.calls();
+        let steps: Vec<_> = calls
+            .iter()
+            .filter(|call| {
+                call.args.contains(&"--feature-powerset".into())
+            })
+            .collect();
+        assert_eq!(steps.len(), 2);
+        for step in steps {
+            assert_eq!(
+                step.args[step.args.len() - 4..],
+                ["--", "--test-threads", "1", "--skip=slow test"]
+            );
+        }
+    }
 }
@@ -0,0 +1,109 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, sync::Arc};
+    use xtasks::ops::{
+        error::{exit_code, EXIT_TOOL_MISSING},
+        runner::{with_runner, RecordingRunner},
+    };
+    use xtasks::tasks::profile::{
+        check_perf, FlamegraphOptions, FlamegraphOptionsBuilder,
+        ProfileTarget, PERF_SYSCTL_FIX,
+    };
+
+    /// Returns the options profiling `target` with the program `args`.
+    fn options(
+        target: Option<ProfileTarget>,
+        args: &[&str],
+    ) -> FlamegraphOptions {
+        FlamegraphOptionsBuilder::default()
+            .target(target)
+            .args(
+                args.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+            )
+            .build()
+            .unwrap()
+    }
+
+    /// Tests the default output of the flamegraph.
+    #[test]
+    fn test_default_output() {
+        assert_eq!(
+            FlamegraphOptions::default().output,
+            PathBuf::from("target/xtask/flamegraph.svg")
+        );
+    }
+
+    /// Tests the command profiling the default binary and a named one.
+    #[test]
+    fn test_bin_command() {
+        assert_eq!(
+            options(None, &[]).command().to_string(),
+            "cargo flamegraph --output target/xtask/flamegraph.svg"
+        );
+        assert_eq!(
+            options(
+                Some(ProfileTarget::Bin("app".to_string())),
+                &["--input", "big.json"]
+            )
+            .command()
+            .to_string(),
+            "cargo flamegraph --output target/xtask/flamegraph.svg --bin app -- --input big.json"
+        );
+    }
+
+    /// Tests that benchmarks get the flag of the criterion harness, before
+    /// the filter.
+    #[test]
+    fn test_bench_command() {
+        let bench = Some(ProfileTarget::Bench("parse".to_string()));
+        assert_eq!(
+            options(bench.clone(), &[]).command().to_string(),
+            "cargo flamegraph --output target/xtask/flamegraph.svg --bench parse -- --bench"
+        );
+        assert_eq!(
+            options(bench, &["small"]).command().to_string(),
+            "cargo flamegraph --output target/xtask/flamegraph.svg --bench parse -- --bench small"
+        );
+        assert_eq!(
+            options(Some(ProfileTarget::Test("it".to_string())), &[])
+                .command()
+                .to_string(),
+            "cargo flamegraph --output target/xtask/flamegraph.svg --test it"
+        );
+    }
+
+    /// Tests the error when perf is missing.
+    #[test]
+    fn test_perf_missing() {
+        let runner =
+            Arc::new(RecordingRunner::default().missing("perf"));
+        let err =
+            with_runner(runner, || check_perf(Some(-1))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "cargo flamegraph samples with perf on Linux: install perf from the linux-tools package of the distribution, then let it sample with '{PERF_SYSCTL_FIX}'"
+            )
+        );
+        assert_eq!(exit_code(&err), EXIT_TOOL_MISSING);
+    }
+
+    /// Tests the paranoid levels perf may sample at.
+    #[test]
+    fn test_perf_paranoid() {
+        let runner = Arc::new(RecordingRunner::default());
+        with_runner(runner, || {
+            assert!(check_perf(None).is_ok());
+            assert!(check_perf(Some(2)).is_ok());
+            assert_eq!(
+                check_perf(Some(3)).unwrap_err().to_string(),
+                "kernel.perf_event_paranoid is 3, which keeps perf from sampling, lower it with 'sudo sysctl -w kernel.perf_event_paranoid=-1'"
+            );
+        });
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+    use xtasks::ops::metadata::{
+        parse_member_dependencies, WorkspaceMember,
+    };
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::publish::{
+        publish_order, publish_with, PublishOptions,
+        PublishOptionsBuilder, PublishStatus,
+    };
+
+    /// The output of `cargo metadata` for a workspace where `cli` depends
+    /// on `core` and `macros`, `macros` on `core`, `core` has a
+    /// development dependency on `testkit`, which is not published.
+    const METADATA: &str = r#"{
+        "packages": [
+            {"name": "cli", "version": "0.2.0", "id": "cli", "dependencies": [
+                {"name": "core", "kind": null},
+                {"name": "macros", "kind": null},
+                {"name": "anyhow", "kind": null}
+            ]},
+            {"name": "core", "version": "0.2.0", "id": "core", "dependencies": [
+                {"name": "testkit", "kind": "dev"},
+                {"name": "serde", "kind": null}
+            ]},
+            {"name": "macros", "version": "0.2.0", "id": "macros", "dependencies": [
+                {"name": "core", "kind": "build"}
+            ]},
+            {"name": "testkit", "version": "0.1.0", "id": "testkit", "publish": [], "dependencies": [
+                {"name": "core", "kind": null}
+            ]},
+            {"name": "anyhow", "version": "1.0.77", "id": "anyhow"}
+        ],
+        "workspace_members": ["cli", "core", "macros", "testkit"]
+    }"#;
+
+    /// Returns the members of the fixture.
+    fn members() -> Vec<WorkspaceMember> {
+        parse_member_dependencies(METADATA).unwrap()
+    }
+
+    /// Returns options checking the index without waiting.
+    fn options() -> PublishOptions {
+        PublishOptionsBuilder::default()
+            .wait_attempts(3_u32)
+            .wait_delay(Duration::ZERO)
+            .build()
+            .unwrap()
+    }
+
+    /// Returns the names of `members`.
+    fn names(members: &[WorkspaceMember]) -> Vec<&str> {
+        members.iter().map(|member| member.name.as_str()).collect()
+    }
+
+    /// Tests that the intra-workspace dependencies are read, without the
+    /// development dependencies and the registry ones.
+    #[test]
+    fn test_parse_member_dependencies() {
+        let members = members();
+        assert_eq!(
+            names(&members),
+            ["cli", "core", "macros", "testkit"]
+        );
+        assert_eq!(members[0].dependencies, ["core", "macros"]);
+        assert!(members[1].dependencies.is_empty());
+        assert_eq!(members[2].dependencies, ["core"]);
+        assert!(members[0].publishable);
+        assert!(!members[3].publishable);
+    }
+
+    /// Tests that every member comes after its dependencies, and that
+    /// cycles are reported.
+    #[test]
+    fn test_publish_order() {
+        assert_eq!(
+            names(&publish_order(&members()).unwrap()),
+            ["core", "macros", "testkit", "cli"]
+        );
+        let member =
+            |name: &str, dependencies: &[&str]| WorkspaceMember {
+                name: name.to_string(),
+                version: "0.1.0".to_string(),
+                dependencies: dependencies
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect(),
+                publishable: true,
+            };
+        let err = publish_order(&[
+            member("a", &["b"]),
+            member("b", &["c"]),
+            member("c", &["a"]),
+            member("d", &[]),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The workspace members depend on one another in a cycle: a, b, c"
+        );
+    }
+
+    /// Tests that the crates already on the index are skipped and the
+    /// others verified then published in order.
+    #[test]
+    fn test_publish_skips_existing() {
+        let runner = Arc::new(RecordingRunner::default());
+        let report = with_runner(runner.clone(), || {
+            publish_with(&options(), &members(), |name, _| {
+                Ok(name == "core" || publishes(&runner, name))
+            })
+        })
+        .unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "cargo publish --dry-run -p macros",
+                "cargo publish --dry-run -p cli",
+                "cargo publish -p macros",
+                "cargo publish -p cli",
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "crate    version  status\n\
+             core     0.2.0    already published\n\
+             macros   0.2.0    published\n\
+             testkit  0.1.0    excluded\n\
+             cli      0.2.0    published"
+        );
+    }
+
+    /// Returns `true` if `cargo publish -p name` ran on `runner`.
+    fn publishes(runner: &RecordingRunner, name: &str) -> bool {
+        runner
+            .command_lines()
+            .contains(&format!("cargo publish -p {name}"))
+    }
+
+    /// Tests that the task waits until a published version is on the
+    /// index before publishing the next crate.
+    #[test]
+    fn test_publish_waits_for_index() {
+        let runner = Arc::new(RecordingRunner::default());
+        let mut checks = 0;
+        let report = with_runner(runner.clone(), || {
+            publish_with(
+                &PublishOptionsBuilder::default()
+                    .only(vec!["core".to_string()])
+                    .wait_attempts(5_u32)
+                    .wait_delay(Duration::ZERO)
+                    .build()
+                    .unwrap(),
+                &members(),
+                |_, _| {
+                    checks += 1;
+                    Ok(checks > 3)
+                },
+            )
+        })
+        .unwrap();
+        assert_eq!(checks, 4);
+        assert_eq!(report.entries[0].status, PublishStatus::Published);
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "cargo publish --dry-run -p core",
+                "cargo publish -p core"
+            ]
+        );
+
+        let err =
+            with_runner(Arc::new(RecordingRunner::default()), || {
+                publish_with(&options(), &members(), |_, _| Ok(false))
+            })
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "core 0.2.0 was published but did not show up on the index"
+        );
+    }
+
+    /// Tests that a dry run only verifies the crates, and the filters.
+    #[test]
+    fn test_publish_dry_run_and_filters() {
+        let runner = Arc::new(RecordingRunner::default());
+        let opts = PublishOptionsBuilder::default()
+            .dry_run(true)
+            .skip(vec!["cli".to_string()])
+            .build()
+            .unwrap();
+        let report = with_runner(runner.clone(), || {
+            publish_with(&opts, &members(), |_, _| Ok(false))
+        })
+        .unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "cargo publish --dry-run -p core",
+                "cargo publish --dry-run -p macros"
+            ]
+        );
+        let statuses: Vec<PublishStatus> =
+            report.entries.iter().map(|entry| entry.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                PublishStatus::Verified,
+                PublishStatus::Verified,
+                PublishStatus::Excluded,
+                PublishStatus::Excluded,
+            ]
+        );
+
+        let opts = PublishOptionsBuilder::default()
+            .only(vec!["nope".to_string()])
+            .build()
+            .unwrap();
+        let err = publish_with(&opts, &members(), |_, _| Ok(false))
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Unknown crate 'nope'"));
+    }
+
+    /// Tests that a failed upload fails the task.
+    #[test]
+    fn test_publish_failure() {
+        let runner = Arc::new(
+            RecordingRunner::default().failing("cargo publish -p core"),
+        );
+        let err = with_runner(runner, || {
+            publish_with(&options(), &members(), |_, _| Ok(false))
+        })
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Failed to publish core 0.2.0"));
+        assert_eq!(xtasks::ops::error::exit_code(&err), 12);
+    }
+}
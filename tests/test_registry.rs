@@ -0,0 +1,216 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error as AnyError;
+    use clap::{Arg, Command};
+    use std::sync::{Arc, Mutex};
+    use xtasks::ops::runner::{self, with_runner, RecordingRunner};
+    use xtasks::tasks::{
+        build_cli, completion_script, list, main_with_args, XTasks,
+        TASK_ALIASES,
+    };
+
+    /// Returns the command line `line` as arguments.
+    fn args(line: &[&str]) -> Vec<String> {
+        line.iter().map(ToString::to_string).collect()
+    }
+
+    /// Returns a registry with the built-in tasks and a `deploy` task taking
+    /// an `--env` argument, which records its runs in `runs`.
+    fn registry(runs: &Arc<Mutex<Vec<String>>>) -> XTasks {
+        let runs = Arc::clone(runs);
+        XTasks::new()
+            .register_command(
+                Command::new("deploy").about("deploy the site").arg(
+                    Arg::new("env")
+                        .long("env")
+                        .default_value("staging")
+                        .help("the environment to deploy to"),
+                ),
+                move |matches| {
+                    let env = matches.get_one::<String>("env").unwrap();
+                    let dry_run = if runner::is_dry_run() {
+                        " (dry run)"
+                    } else {
+                        ""
+                    };
+                    runs.lock()
+                        .unwrap()
+                        .push(format!("{env}{dry_run}"));
+                    Ok(())
+                },
+            )
+            .register("fail", "always fail", |_| {
+                Err(AnyError::msg("custom failure"))
+            })
+            .with_builtin_tasks()
+    }
+
+    /// Tests that a custom task runs with its arguments and the global flags.
+    #[test]
+    fn test_custom_task() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let tasks = registry(&runs);
+        tasks.run_with_args(&args(&["xtask", "deploy"])).unwrap();
+        tasks
+            .run_with_args(&args(&[
+                "xtask",
+                "--dry-run",
+                "deploy",
+                "--env",
+                "production",
+            ]))
+            .unwrap();
+        assert_eq!(
+            *runs.lock().unwrap(),
+            ["staging", "production (dry run)"]
+        );
+        assert_eq!(
+            tasks
+                .run_with_args(&args(&["xtask", "fail"]))
+                .unwrap_err()
+                .to_string(),
+            "custom failure"
+        );
+    }
+
+    /// Tests that the custom tasks are listed after the built-in ones, which
+    /// still run.
+    #[test]
+    fn test_builtin_tasks() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let tasks = registry(&runs);
+        let cli = tasks.command();
+        let names: Vec<&str> =
+            cli.get_subcommands().map(Command::get_name).collect();
+        assert_eq!(names[0], "list");
+        assert!(names.contains(&"ci"));
+        assert_eq!(&names[names.len() - 2..], ["deploy", "fail"]);
+
+        let recorder = Arc::new(RecordingRunner::default());
+        let listed =
+            with_runner(recorder.clone(), || list::tasks(&cli));
+        let deploy =
+            listed.iter().find(|task| task.name == "deploy").unwrap();
+        assert_eq!(deploy.description, "deploy the site");
+
+        with_runner(recorder.clone(), || {
+            tasks.run_with_args(&args(&["xtask", "list"]))
+        })
+        .unwrap();
+        with_runner(recorder.clone(), || {
+            tasks.run_with_args(&args(&["xtask", "--dry-run", "ci"]))
+        })
+        .unwrap();
+        assert!(runs.lock().unwrap().is_empty());
+
+        let error = tasks
+            .run_with_args(&args(&["xtask", "deplyo"]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("did you mean 'deploy'?"));
+        assert!(error.contains("deploy         deploy the site"));
+    }
+
+    /// Tests a registry without the built-in tasks, and a custom task
+    /// replacing a built-in one.
+    #[test]
+    fn test_custom_only() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&runs);
+        let tasks =
+            XTasks::new().register("ci", "our own CI", move |_| {
+                recorded.lock().unwrap().push("ci".to_string());
+                Ok(())
+            });
+        assert!(!tasks.has_builtin_tasks());
+        assert_eq!(tasks.command().get_subcommands().count(), 1);
+        tasks.run_with_args(&args(&["xtask", "ci"])).unwrap();
+        assert_eq!(*runs.lock().unwrap(), ["ci"]);
+        let error = tasks
+            .run_with_args(&args(&["xtask", "vars"]))
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Unknown task 'vars'"));
+
+        let replaced = tasks.with_builtin_tasks();
+        let cli = replaced.command();
+        let ci: Vec<&Command> = cli
+            .get_subcommands()
+            .filter(|task| task.get_name() == "ci")
+            .collect();
+        assert_eq!(ci.len(), 1);
+        assert_eq!(
+            ci[0].get_about().unwrap().to_string(),
+            "our own CI"
+        );
+        replaced.run_with_args(&args(&["xtask", "ci"])).unwrap();
+        assert_eq!(runs.lock().unwrap().len(), 2);
+    }
+
+    /// Tests that the built-in aliases run their task and are completed.
+    #[test]
+    fn test_builtin_aliases() {
+        for (task, alias) in TASK_ALIASES {
+            let cli = build_cli();
+            let found = cli.find_subcommand(alias).unwrap();
+            assert_eq!(found.get_name(), task);
+        }
+        assert!(completion_script(clap_complete::Shell::Bash)
+            .contains("xtask,bd)"));
+
+        let recorder = Arc::new(RecordingRunner::default());
+        with_runner(recorder.clone(), || {
+            main_with_args(&args(&["xtask", "d", "--no-watch"]))
+        })
+        .unwrap();
+        assert_eq!(recorder.command_lines(), ["cargo doc --no-deps"]);
+    }
+
+    /// Tests the aliases of the custom tasks, and the error when a name or an
+    /// alias is taken twice.
+    #[test]
+    fn test_custom_aliases() {
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&runs);
+        let tasks = registry(&runs).register_command(
+            Command::new("publish").visible_alias("pub"),
+            move |_| {
+                recorded.lock().unwrap().push("publish".to_string());
+                Ok(())
+            },
+        );
+        tasks.validate().unwrap();
+        tasks.run_with_args(&args(&["xtask", "pub"])).unwrap();
+        assert_eq!(*runs.lock().unwrap(), ["publish"]);
+        let listed =
+            with_runner(Arc::new(RecordingRunner::default()), || {
+                list::tasks(&tasks.command())
+            });
+        assert_eq!(listed.last().unwrap().aliases, ["pub"]);
+
+        let taken = tasks.clone().register_command(
+            Command::new("deploy-docs").visible_alias("d"),
+            |_| Ok(()),
+        );
+        let error = taken
+            .run_with_args(&args(&["xtask", "deploy"]))
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "The task 'deploy-docs' cannot be called 'd', which is already taken by the task 'docs'"
+        );
+        assert_eq!(runs.lock().unwrap().len(), 1);
+
+        let shadowed =
+            tasks.register("cov", "a coverage of our own", |_| Ok(()));
+        assert!(shadowed
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("'cov'"));
+    }
+}
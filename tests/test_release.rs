@@ -0,0 +1,251 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::Path,
+        process::{Command, Output},
+        sync::Arc,
+    };
+    use xtasks::ops::manifest::with_version;
+    use xtasks::ops::runner::{
+        with_runner, CommandRunner, CommandSpec, DuctRunner,
+        RecordingRunner,
+    };
+    use xtasks::tasks::release::{
+        changelog_with_release, ReleaseBuilder, ReleaseVersion,
+    };
+    use xtasks::tasks::semver::Bump;
+
+    const CHANGELOG: &str =
+        "# Changelog\n\n## [Unreleased]\n\n- Added a task\n";
+
+    /// Runs `git` for real, so the release commits and tags in the test
+    /// repository, and records the other commands, e.g. `cargo publish`.
+    #[derive(Debug, Default)]
+    struct GitOnlyRunner {
+        recorder: RecordingRunner,
+    }
+
+    impl CommandRunner for GitOnlyRunner {
+        fn execute(
+            &self,
+            command: &CommandSpec,
+        ) -> std::io::Result<Output> {
+            if command.program == "git" {
+                DuctRunner.execute(command)
+            } else {
+                self.recorder.execute(command)
+            }
+        }
+    }
+
+    /// Runs `git` in `dir` and returns its trimmed output.
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {args:?} failed");
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// Creates a committed package at version 0.1.0 with a changelog.
+    fn repository() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let _ = git(dir.path(), &["init", "--quiet"]);
+        let _ = git(
+            dir.path(),
+            &["config", "user.email", "dev@example.com"],
+        );
+        let _ = git(dir.path(), &["config", "user.name", "Dev"]);
+        let _ = git(dir.path(), &["config", "commit.gpgsign", "false"]);
+        let _ = git(dir.path(), &["config", "tag.gpgsign", "false"]);
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("CHANGELOG.md"), CHANGELOG).unwrap();
+        let _ = git(dir.path(), &["add", "--all"]);
+        let _ =
+            git(dir.path(), &["commit", "--quiet", "-m", "Initial"]);
+        dir
+    }
+
+    /// Tests a release with every phase but CI: the version is bumped, the
+    /// changelog gets the new heading, the release is committed and tagged,
+    /// and the package is published.
+    #[test]
+    fn test_release_flow() {
+        let dir = repository();
+        let runner = Arc::new(GitOnlyRunner::default());
+        let release = ReleaseBuilder::default()
+            .version(ReleaseVersion::Bump(Bump::Minor))
+            .ci(false)
+            .publish(true)
+            .build()
+            .unwrap();
+        let version =
+            with_runner(runner.clone(), || release.run_in(dir.path()))
+                .unwrap();
+
+        assert_eq!(version, "0.2.0");
+        let manifest =
+            fs::read_to_string(dir.path().join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("version = \"0.2.0\""));
+        let changelog =
+            fs::read_to_string(dir.path().join("CHANGELOG.md"))
+                .unwrap();
+        assert!(changelog.contains("## [Unreleased]\n\n## [0.2.0] - "));
+        assert_eq!(
+            git(dir.path(), &["log", "-1", "--format=%s"]),
+            "Release 0.2.0"
+        );
+        assert_eq!(
+            git(dir.path(), &["cat-file", "-t", "v0.2.0"]),
+            "tag"
+        );
+        assert!(git(dir.path(), &["status", "--porcelain"]).is_empty());
+        let lines = runner.recorder.command_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("cargo publish --manifest-path"));
+    }
+
+    /// Tests that `--no-tag` and `--no-changelog` skip their phases and that
+    /// an explicit version is used as is.
+    #[test]
+    fn test_release_skipped_phases() {
+        let dir = repository();
+        let release = ReleaseBuilder::default()
+            .version("1.0.0".parse::<ReleaseVersion>().unwrap())
+            .ci(false)
+            .tag(false)
+            .changelog(false)
+            .build()
+            .unwrap();
+        let runner = Arc::new(GitOnlyRunner::default());
+        let _ =
+            with_runner(runner.clone(), || release.run_in(dir.path()))
+                .unwrap();
+
+        assert_eq!(git(dir.path(), &["tag", "--list"]), "");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("CHANGELOG.md"))
+                .unwrap(),
+            CHANGELOG
+        );
+        assert_eq!(
+            git(dir.path(), &["log", "-1", "--format=%s"]),
+            "Release 1.0.0"
+        );
+        assert!(runner.recorder.calls().is_empty());
+    }
+
+    /// Tests that a working tree with uncommitted changes is not released.
+    #[test]
+    fn test_release_dirty_tree() {
+        let dir = repository();
+        fs::write(dir.path().join("notes.txt"), "wip").unwrap();
+        let release = ReleaseBuilder::default()
+            .version(ReleaseVersion::Bump(Bump::Patch))
+            .ci(false)
+            .build()
+            .unwrap();
+        let error =
+            with_runner(Arc::new(GitOnlyRunner::default()), || {
+                release.run_in(dir.path())
+            })
+            .unwrap_err();
+        assert!(error.to_string().contains("notes.txt"));
+        assert_eq!(
+            git(dir.path(), &["rev-list", "--count", "HEAD"]),
+            "1"
+        );
+    }
+
+    /// Tests that a dry run prints the plan without touching the files, the
+    /// commits or the tags.
+    #[test]
+    fn test_release_dry_run() {
+        let dir = repository();
+        let release = ReleaseBuilder::default()
+            .version(ReleaseVersion::Bump(Bump::Major))
+            .ci(false)
+            .publish(true)
+            .dry_run(true)
+            .build()
+            .unwrap();
+        assert_eq!(release.run_in(dir.path()).unwrap(), "1.0.0");
+
+        assert!(fs::read_to_string(dir.path().join("Cargo.toml"))
+            .unwrap()
+            .contains("version = \"0.1.0\""));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("CHANGELOG.md"))
+                .unwrap(),
+            CHANGELOG
+        );
+        assert_eq!(
+            git(dir.path(), &["rev-list", "--count", "HEAD"]),
+            "1"
+        );
+        assert_eq!(git(dir.path(), &["tag", "--list"]), "");
+    }
+
+    /// Tests the release versions accepted on the command line.
+    #[test]
+    fn test_release_version() {
+        assert_eq!(
+            "patch".parse::<ReleaseVersion>().unwrap(),
+            ReleaseVersion::Bump(Bump::Patch)
+        );
+        assert_eq!(
+            "v2.0.0-rc.1".parse::<ReleaseVersion>().unwrap(),
+            ReleaseVersion::Exact("2.0.0-rc.1".to_string())
+        );
+        assert!("next".parse::<ReleaseVersion>().is_err());
+        assert_eq!(Bump::Patch.apply("0.1.9").unwrap(), "0.1.10");
+        assert_eq!(Bump::Major.apply("1.4.2").unwrap(), "2.0.0");
+        assert!(Bump::Minor.apply("one").is_err());
+    }
+
+    /// Tests the edit of the version in a workspace manifest.
+    #[test]
+    fn test_with_version_workspace() {
+        let manifest = "[workspace]\nmembers = [\"xtask\"]\n\n[workspace.package]\nversion = \"0.3.0\"\n\n[dependencies]\nserde = { version = \"1.0\" }\n";
+        assert_eq!(
+            with_version(manifest, "0.4.0").unwrap(),
+            manifest.replace("0.3.0", "0.4.0")
+        );
+        assert!(with_version(
+            "[package]\nversion.workspace = true\n",
+            "1.0.0"
+        )
+        .is_err());
+    }
+
+    /// Tests that the changelog needs an `Unreleased` heading.
+    #[test]
+    fn test_changelog_without_unreleased() {
+        assert!(changelog_with_release(
+            "# Changelog\n\n## [0.1.0]\n",
+            "0.2.0",
+            "2024-01-31"
+        )
+        .is_err());
+        assert_eq!(
+            changelog_with_release(
+                "## Unreleased",
+                "0.2.0",
+                "2024-01-31"
+            )
+            .unwrap(),
+            "## Unreleased\n\n## [0.2.0] - 2024-01-31\n"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Result,
+        os::unix::process::ExitStatusExt,
+        path::PathBuf,
+        process::{ExitStatus, Output},
+        sync::Arc,
+    };
+    use xtasks::{
+        ops::runner::{self, CommandRunner, CommandSpec},
+        tasks::semver::{check, parse_output, Bump, SemverReport},
+    };
+
+    const BREAKING_OUTPUT: &str = "\
+     Parsing app v0.3.1 (current)
+     Parsing app v0.3.0 (baseline)
+    Checking app v0.3.0 -> v0.3.1 (patch change)
+     Checked [   0.012s] 75 checks: 72 pass, 3 fail, 0 warn, 0 skip
+
+--- failure function_missing: pub fn removed or renamed ---
+
+Description:
+A publicly-visible function cannot be imported by its prior path.
+
+--- failure enum_variant_added: enum variant added on exhaustive enum ---
+
+Description:
+A publicly-visible enum without #[non_exhaustive] has a new variant.
+
+--- failure inherent_method_missing: pub method removed or renamed ---
+
+     Summary semver requires new major version: 2 major and 1 minor checks failed
+    Finished [   0.150s] app
+";
+
+    const CLEAN_OUTPUT: &str = "\
+    Checking app v0.3.0 -> v0.3.1 (patch change)
+     Checked [   0.012s] 75 checks: 75 pass, 0 skip
+     Summary no semver update required
+";
+
+    /// Tests that failed checks are counted from the summary and listed by id.
+    #[test]
+    fn test_parse_semver_output() {
+        let report = parse_output(BREAKING_OUTPUT);
+        assert_eq!(
+            report,
+            SemverReport {
+                major: 2,
+                minor: 1,
+                failures: vec![
+                    "function_missing".to_string(),
+                    "enum_variant_added".to_string(),
+                    "inherent_method_missing".to_string(),
+                ],
+            }
+        );
+        assert_eq!(report.required_bump(), Bump::Major);
+        assert_eq!(
+            report.to_string(),
+            "semver requires a new major version: 2 major and 1 minor checks failed"
+        );
+
+        let report = parse_output(CLEAN_OUTPUT);
+        assert!(report.is_compatible());
+        assert_eq!(report.required_bump(), Bump::Patch);
+    }
+
+    /// Tests that a release smaller than the required bump is refused.
+    #[test]
+    fn test_release_refusal() {
+        let breaking = parse_output(BREAKING_OUTPUT);
+        let err =
+            breaking.ensure_release_allowed(Bump::Patch).unwrap_err();
+        assert!(
+            err.to_string().starts_with(
+                "Refusing a patch release: cargo semver-checks found changes requiring a major release"
+            ),
+            "{err}"
+        );
+        assert!(breaking.ensure_release_allowed(Bump::Minor).is_err());
+        assert!(breaking.ensure_release_allowed(Bump::Major).is_ok());
+
+        let additive = SemverReport {
+            minor: 1,
+            ..SemverReport::default()
+        };
+        assert!(additive.ensure_release_allowed(Bump::Patch).is_err());
+        assert!(additive.ensure_release_allowed(Bump::Minor).is_ok());
+        assert!(parse_output(CLEAN_OUTPUT)
+            .ensure_release_allowed(Bump::Patch)
+            .is_ok());
+        assert_eq!("minor".parse::<Bump>().unwrap(), Bump::Minor);
+    }
+
+    /// Tests that a failing tool run is reported through the parsed report.
+    #[test]
+    fn test_semver_check_with_runner() {
+        struct SemverRunner;
+        impl CommandRunner for SemverRunner {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                assert_eq!(
+                    command.to_string(),
+                    "cargo semver-checks check-release --package app"
+                );
+                Ok(Output {
+                    status: ExitStatus::from_raw(1 << 8),
+                    stdout: BREAKING_OUTPUT.as_bytes().to_vec(),
+                    stderr: Vec::new(),
+                })
+            }
+
+            fn which(&self, program: &str) -> Option<PathBuf> {
+                Some(PathBuf::from(program))
+            }
+        }
+
+        let report =
+            runner::with_runner(Arc::new(SemverRunner), || {
+                check(Some("app"))
+            })
+            .unwrap();
+        assert_eq!(report.required_bump(), Bump::Major);
+    }
+}
@@ -0,0 +1,76 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use assert_cmd::Command;
+    use std::{sync::Arc, time::Duration};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::ops::summary::{footer, format_elapsed};
+    use xtasks::tasks::ci::CIBuilder;
+
+    /// Runs the CI checks with `runner`, returning the footer of the run.
+    fn ci_footer(runner: RecordingRunner, color: bool) -> String {
+        let result = with_runner(Arc::new(runner), || {
+            CIBuilder::default().locked(false).run()
+        });
+        footer("ci", &result, Duration::from_secs(222), color)
+    }
+
+    /// Tests the footer of a passing and of a failing task.
+    #[test]
+    fn test_footer() {
+        assert_eq!(
+            ci_footer(RecordingRunner::default(), false),
+            "✓ ci completed in 3m42s"
+        );
+        assert_eq!(
+            ci_footer(
+                RecordingRunner::default().failing("cargo clippy"),
+                false
+            ),
+            "✗ ci failed after 3m42s (step: clippy)"
+        );
+        assert_eq!(
+            ci_footer(RecordingRunner::default(), true),
+            "\x1b[32m✓\x1b[0m ci completed in 3m42s"
+        );
+        assert_eq!(
+            footer(
+                "coverage",
+                &Err(anyhow::Error::msg("no report")),
+                Duration::from_secs(12),
+                true
+            ),
+            "\x1b[31m✗\x1b[0m coverage failed after 12s"
+        );
+        assert_eq!(format_elapsed(Duration::from_millis(40)), "0.0s");
+        assert_eq!(format_elapsed(Duration::from_secs(60)), "1m00s");
+    }
+
+    /// Returns the stderr of the binary run with `args`.
+    fn stderr(args: &[&str]) -> String {
+        let output = Command::cargo_bin("xtasks")
+            .unwrap()
+            .args(args)
+            .output()
+            .unwrap();
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+
+    /// Tests that the binary prints the footer, without colors when stderr
+    /// is not a terminal, unless `--no-summary` or `--quiet` is given.
+    #[test]
+    fn test_binary_footer() {
+        let printed = stderr(&["vars"]);
+        assert!(
+            printed.starts_with("✓ vars completed in "),
+            "{printed}"
+        );
+        assert!(!printed.contains('\x1b'));
+        assert!(!stderr(&["--no-summary", "vars"]).contains("vars"));
+        assert!(!stderr(&["vars", "--quiet"]).contains("vars"));
+        assert!(stderr(&["--config-file", "missing.toml", "ci"])
+            .starts_with("✗ ci failed after "));
+    }
+}
@@ -28,4 +28,237 @@ mod tests {
         );
         assert!(result.is_ok());
     }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    /// Tests that `install` runs the installation of every tool, or of the
+    /// selected ones.
+    #[test]
+    fn test_main_with_install_command() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+
+        let run = |cli: &[&str]| {
+            let recorder = Arc::new(RecordingRunner::default());
+            with_runner(recorder.clone(), || {
+                main_with_args(&args(cli))
+            })
+            .unwrap();
+            recorder.command_lines()
+        };
+
+        assert_eq!(
+            run(&["xtask", "install"]),
+            [
+                "cargo install cargo-watch",
+                "cargo install cargo-hack",
+                "cargo install cargo-bloat",
+                "rustup component add llvm-tools-preview",
+                "cargo install grcov",
+            ]
+        );
+        assert_eq!(
+            run(&[
+                "xtask",
+                "install",
+                "--skip",
+                "grcov",
+                "--skip",
+                "llvm-tools-preview",
+            ]),
+            [
+                "cargo install cargo-watch",
+                "cargo install cargo-hack",
+                "cargo install cargo-bloat",
+            ]
+        );
+        assert_eq!(
+            run(&["xtask", "install", "--only", "cargo-hack"]),
+            ["cargo install cargo-hack"]
+        );
+
+        let err = xtasks::tasks::install_tools(&[], &args(&["watch"]))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown tool 'watch', expected one of: cargo-watch, \
+             cargo-hack, cargo-bloat, llvm-tools-preview, grcov"
+        );
+    }
+
+    /// Tests that `dev` runs cargo watch with the given command and clears
+    /// the screen on request.
+    #[test]
+    fn test_main_with_dev_command() {
+        use std::sync::Arc;
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+
+        let run = |cli: &[&str]| {
+            let recorder = Arc::new(RecordingRunner::default());
+            with_runner(recorder.clone(), || {
+                main_with_args(&args(cli))
+            })
+            .unwrap();
+            recorder.command_lines()
+        };
+
+        assert_eq!(
+            run(&["xtask", "dev"]),
+            ["cargo watch -x check -x test"]
+        );
+        assert_eq!(
+            run(&["xtask", "dev", "--cmd", "cargo-nightly", "--clear"]),
+            ["cargo-nightly watch --clear -x check -x test"]
+        );
+    }
+
+    /// Tests that the completion scripts cover the subcommands for every
+    /// shell, and that `completions --out` writes the script to a file.
+    #[test]
+    fn test_main_with_completions_command() {
+        use clap_complete::Shell;
+        use xtasks::tasks::completion_script;
+
+        for shell in
+            [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell]
+        {
+            let script = completion_script(shell);
+            for subcommand in ["powerset", "coverage", "bloat", "clean"]
+            {
+                assert!(
+                    script.contains(subcommand),
+                    "{shell}: {subcommand}"
+                );
+            }
+        }
+        assert!(completion_script(Shell::Bash).contains("--dry-run"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("xtask.fish");
+        main_with_args(&args(&[
+            "xtask",
+            "completions",
+            "fish",
+            "--out",
+            &out.to_string_lossy(),
+        ]))
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out).unwrap(),
+            completion_script(Shell::Fish)
+        );
+    }
+
+    /// Tests that an unknown task fails with the closest task as suggestion
+    /// and the list of tasks.
+    #[test]
+    fn test_main_with_unknown_task() {
+        use xtasks::tasks::{suggest_task, task_names};
+
+        let tasks = task_names();
+        assert_eq!(suggest_task("cii", &tasks), Some("ci"));
+        assert_eq!(suggest_task("powrset", &tasks), Some("powerset"));
+        assert_eq!(suggest_task("blat", &tasks), Some("bloat"));
+        assert_eq!(
+            suggest_task("bloat-dep", &tasks),
+            Some("bloat-fns")
+        );
+        assert_eq!(suggest_task("release-notes", &tasks), None);
+        assert_eq!(suggest_task("xx", &tasks), None);
+
+        let err =
+            main_with_args(&args(&["xtask", "covrage"])).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with(
+                "Unknown task 'covrage', did you mean 'coverage'?"
+            ),
+            "{message}"
+        );
+        for task in tasks {
+            assert!(
+                message.contains(&format!("\n  {task} ")),
+                "{task}"
+            );
+        }
+        let err =
+            main_with_args(&args(&["xtask", "deploy"])).unwrap_err();
+        assert!(err.to_string().starts_with("Unknown task 'deploy'\n"));
+    }
+
+    /// Tests that the binary prints its version, and only the output of the
+    /// task it runs.
+    #[test]
+    fn test_binary_output() {
+        let xtasks = |arg: &str| {
+            let output = std::process::Command::new(env!(
+                "CARGO_BIN_EXE_xtasks"
+            ))
+            .arg(arg)
+            .output()
+            .unwrap();
+            (output.status, String::from_utf8(output.stdout).unwrap())
+        };
+
+        let (status, stdout) = xtasks("--version");
+        assert!(status.success());
+        assert_eq!(
+            stdout,
+            format!("xtask {}\n", env!("CARGO_PKG_VERSION"))
+        );
+
+        let (status, stdout) = xtasks("vars");
+        assert!(status.success());
+        assert!(stdout.starts_with("root: "), "{stdout}");
+        assert!(!stdout.contains("Received subcommand"), "{stdout}");
+
+        let (status, _) = xtasks("cii");
+        assert!(!status.success());
+    }
+
+    /// Tests that without a task, the help is printed and the run fails when
+    /// not attached to a terminal, and that `--ci-default` runs the CI checks
+    /// with the configured settings.
+    #[test]
+    fn test_main_without_task() {
+        use std::{process::Stdio, sync::Arc};
+        use xtasks::ops::runner::{with_runner, RecordingRunner};
+
+        let output =
+            std::process::Command::new(env!("CARGO_BIN_EXE_xtasks"))
+                .stdin(Stdio::null())
+                .output()
+                .unwrap();
+        assert!(!output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("Usage: xtask [OPTIONS] [COMMAND]"));
+        assert!(String::from_utf8_lossy(&output.stderr)
+            .contains("No task given"));
+
+        let recorder = Arc::new(RecordingRunner::default());
+        with_runner(recorder.clone(), || {
+            main_with_args(&args(&["xtask", "--ci-default"]))
+        })
+        .unwrap();
+        assert_eq!(recorder.command_lines()[0], "cargo fmt -- --check");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xtask.toml");
+        std::fs::write(&path, "[ci]\nfix = true\n").unwrap();
+        let recorder = Arc::new(RecordingRunner::default());
+        with_runner(recorder.clone(), || {
+            main_with_args(&args(&[
+                "xtask",
+                "--config-file",
+                &path.to_string_lossy(),
+                "--ci-default",
+            ]))
+        })
+        .unwrap();
+        let lines = recorder.command_lines();
+        assert!(lines.contains(&"cargo fmt".to_string()), "{lines:?}");
+        assert!(!lines.contains(&"cargo fmt -- --check".to_string()));
+    }
 }
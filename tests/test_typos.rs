@@ -0,0 +1,206 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::Result,
+        os::unix::process::ExitStatusExt,
+        path::{Path, PathBuf},
+        process::{ExitStatus, Output},
+        sync::Arc,
+    };
+    use xtasks::{
+        ops::runner::{self, CommandRunner, CommandSpec, DuctRunner},
+        tasks::typos::{
+            check, config_file, ensure_no_typos, parse_output,
+            run_command, run_in, TypoFinding, TypoReport,
+        },
+    };
+
+    const TYPOS_OUTPUT: &str = r#"{"type":"binary_file","path":"assets/logo.png"}
+{"type":"typo","path":"src/lib.rs","line_num":12,"byte_offset":8,"typo":"recieve","corrections":["receive"]}
+{"type":"typo","path":"README.md","line_num":3,"byte_offset":0,"typo":"ba","corrections":["by","be"]}
+"#;
+
+    /// Tests that typo messages are parsed and other messages are skipped.
+    #[test]
+    fn test_parse_typos_output() {
+        assert_eq!(
+            parse_output(TYPOS_OUTPUT),
+            vec![
+                TypoFinding {
+                    path: PathBuf::from("src/lib.rs"),
+                    line: 12,
+                    found: "recieve".to_string(),
+                    suggestion: Some("receive".to_string()),
+                },
+                TypoFinding {
+                    path: PathBuf::from("README.md"),
+                    line: 3,
+                    found: "ba".to_string(),
+                    suggestion: Some("by or be".to_string()),
+                },
+            ]
+        );
+        assert!(parse_output("not json\n").is_empty());
+    }
+
+    /// Tests that findings are listed in the error, one per line.
+    #[test]
+    fn test_typos_error_rendering() {
+        assert!(ensure_no_typos(&[]).is_ok());
+        let err =
+            ensure_no_typos(&parse_output(TYPOS_OUTPUT)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "typos found 2 misspellings:\n  \
+             src/lib.rs:12: `recieve` should be `receive`\n  \
+             README.md:3: `ba` should be `by or be`"
+        );
+    }
+
+    /// Tests that the configuration file is passed through and findings are
+    /// returned rather than reported as a failed run.
+    #[test]
+    fn test_typos_check_with_runner() {
+        struct TyposRunner;
+        impl CommandRunner for TyposRunner {
+            fn execute(&self, command: &CommandSpec) -> Result<Output> {
+                assert_eq!(
+                    command.to_string(),
+                    "typos --format json --config _typos.toml"
+                );
+                Ok(Output {
+                    status: ExitStatus::from_raw(2 << 8),
+                    stdout: TYPOS_OUTPUT.as_bytes().to_vec(),
+                    stderr: Vec::new(),
+                })
+            }
+
+            fn which(&self, program: &str) -> Option<PathBuf> {
+                Some(PathBuf::from(program))
+            }
+        }
+
+        let findings =
+            runner::with_runner(Arc::new(TyposRunner), || {
+                check(Some(Path::new("_typos.toml")))
+            })
+            .unwrap();
+        assert_eq!(findings.len(), 2);
+    }
+
+    /// Tests the invocation in check and fix mode.
+    #[test]
+    fn test_typos_run_command() {
+        let paths = [PathBuf::from("src"), PathBuf::from("README.md")];
+        assert_eq!(
+            run_command(Some(Path::new("_typos.toml")), false, &paths)
+                .to_string(),
+            "typos --format json --config _typos.toml --exclude target/ src README.md"
+        );
+        assert_eq!(
+            run_command(None, true, &[PathBuf::from(".")]).to_string(),
+            "typos --format json --write-changes --exclude target/ ."
+        );
+    }
+
+    /// Tests that an existing configuration file of the workspace is used.
+    #[test]
+    fn test_typos_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(config_file(dir.path()), None);
+        fs::write(dir.path().join("_typos.toml"), "").unwrap();
+        assert_eq!(
+            config_file(dir.path()),
+            Some(dir.path().join("_typos.toml"))
+        );
+    }
+
+    /// A runner correcting `teh` in the files it is given, like
+    /// `typos --write-changes`, and running git for real.
+    struct FixingRunner;
+
+    impl CommandRunner for FixingRunner {
+        fn execute(&self, command: &CommandSpec) -> Result<Output> {
+            if command.program != "typos" {
+                return DuctRunner.execute(command);
+            }
+            assert!(command
+                .args
+                .contains(&"--write-changes".to_string()));
+            for arg in &command.args {
+                let path = Path::new(arg);
+                if let Ok(contents) = fs::read_to_string(path) {
+                    fs::write(path, contents.replace("teh", "the"))?;
+                }
+            }
+            Ok(Output {
+                status: ExitStatus::from_raw(2 << 8),
+                stdout: br#"{"type":"typo","path":"notes.md","line_num":1,"byte_offset":0,"typo":"ba","corrections":["by","be"]}"#.to_vec(),
+                stderr: Vec::new(),
+            })
+        }
+
+        fn which(&self, program: &str) -> Option<PathBuf> {
+            Some(PathBuf::from(program))
+        }
+    }
+
+    /// Tests that the files corrected in fix mode are read from the git
+    /// status, and that the misspellings left are reported.
+    #[test]
+    fn test_typos_fix_reports_corrected_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(root)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        fs::write(root.join("README.md"), "Read teh docs.\n").unwrap();
+        fs::write(root.join("notes.md"), "ba\n").unwrap();
+        git(&["add", "."]);
+        git(&[
+            "-c",
+            "user.name=xtasks",
+            "-c",
+            "user.email=xtasks@example.com",
+            "commit",
+            "-qm",
+            "init",
+        ]);
+
+        let paths = [root.join("README.md"), root.join("notes.md")];
+        let report =
+            runner::with_runner(Arc::new(FixingRunner), || {
+                run_in(root, None, true, &paths)
+            })
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(root.join("README.md")).unwrap(),
+            "Read the docs.\n"
+        );
+        assert_eq!(
+            report,
+            TypoReport {
+                findings: parse_output(
+                    r#"{"type":"typo","path":"notes.md","line_num":1,"byte_offset":0,"typo":"ba","corrections":["by","be"]}"#
+                ),
+                fixed: vec![PathBuf::from("README.md")],
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "Corrected 1 files:\n  README.md"
+        );
+    }
+}
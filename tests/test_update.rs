@@ -0,0 +1,242 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Error as AnyError;
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        process::Output,
+        sync::Arc,
+    };
+    use xtasks::ops::runner::{
+        with_runner, CommandRunner, CommandSpec, RecordingRunner,
+    };
+    use xtasks::tasks::update::{
+        diff_lockfiles, update_with, LockfileChange, UpdateOptions,
+        UpdateOptionsBuilder,
+    };
+
+    const BEFORE: &str = r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "syn 1.0.109", "syn 2.0.40", "rand"]
+
+[[package]]
+name = "rand"
+version = "0.8.5"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "serde"
+version = "1.0.193"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "1.0.109"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "2.0.40"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    const AFTER: &str = r#"version = 3
+
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = ["serde", "syn 1.0.109", "syn 2.0.48", "zerocopy"]
+
+[[package]]
+name = "serde"
+version = "1.0.200"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "1.0.109"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "2.0.48"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "zerocopy"
+version = "0.7.32"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    /// Returns the change of `name` from `old` to `new`.
+    fn change(
+        name: &str,
+        old: Option<&str>,
+        new: Option<&str>,
+    ) -> LockfileChange {
+        LockfileChange {
+            name: name.to_string(),
+            old: old.map(ToString::to_string),
+            new: new.map(ToString::to_string),
+        }
+    }
+
+    /// Tests the changes found between two lockfiles, with a crate locked
+    /// at two versions, an added crate and a removed one.
+    #[test]
+    fn test_diff_lockfiles() {
+        let diff = diff_lockfiles(BEFORE, AFTER).unwrap();
+        assert_eq!(
+            diff.changes,
+            [
+                change("rand", Some("0.8.5"), None),
+                change("serde", Some("1.0.193"), Some("1.0.200")),
+                change("syn", Some("2.0.40"), Some("2.0.48")),
+                change("zerocopy", None, Some("0.7.32")),
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "crate     old       new\n\
+             rand      0.8.5   \u{2192} -\n\
+             serde     1.0.193 \u{2192} 1.0.200\n\
+             syn       2.0.40  \u{2192} 2.0.48\n\
+             zerocopy  -       \u{2192} 0.7.32"
+        );
+        assert!(diff_lockfiles(BEFORE, BEFORE).unwrap().is_empty());
+        assert!(diff_lockfiles("", BEFORE).unwrap().changes.len() == 5);
+        assert!(diff_lockfiles("[[package]", BEFORE).is_err());
+    }
+
+    /// A runner recording the commands, where `cargo update` writes
+    /// [`AFTER`] to the lockfile.
+    struct UpdatingRunner {
+        recorder: RecordingRunner,
+        lockfile: PathBuf,
+    }
+
+    impl CommandRunner for UpdatingRunner {
+        fn execute(
+            &self,
+            command: &CommandSpec,
+        ) -> std::io::Result<Output> {
+            if command.to_string().starts_with("cargo update") {
+                fs::write(&self.lockfile, AFTER)?;
+            }
+            self.recorder.execute(command)
+        }
+    }
+
+    /// Runs the task in a workspace locked at [`BEFORE`], with a CI step
+    /// passing if `ci_passes` is set, and returns the error if any, the
+    /// lockfile afterwards, whether a backup was left behind, and the
+    /// commands run.
+    fn run(
+        opts: &UpdateOptions,
+        ci_passes: bool,
+    ) -> (Option<AnyError>, String, bool, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let lockfile = dir.path().join("Cargo.lock");
+        fs::write(&lockfile, BEFORE).unwrap();
+        let runner = Arc::new(UpdatingRunner {
+            recorder: RecordingRunner::default(),
+            lockfile: lockfile.clone(),
+        });
+        let mut ran = false;
+        let result = with_runner(runner.clone(), || {
+            update_with(dir.path(), opts, || {
+                ran = true;
+                if ci_passes {
+                    Ok(())
+                } else {
+                    Err(AnyError::msg("The 'test' step failed"))
+                }
+            })
+        });
+        assert_eq!(ran, opts.test);
+        (
+            result.err(),
+            fs::read_to_string(&lockfile).unwrap(),
+            dir.path().join("Cargo.lock.xtasks-backup").exists(),
+            runner.recorder.command_lines(),
+        )
+    }
+
+    /// Tests that the updated lockfile is kept when the CI checks pass or
+    /// are not run.
+    #[test]
+    fn test_update_keeps_lockfile() {
+        let (err, lockfile, backup, lines) =
+            run(&UpdateOptions::default(), false);
+        assert!(err.is_none());
+        assert_eq!(lockfile, AFTER);
+        assert!(!backup);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("cargo update --manifest-path "));
+
+        let test =
+            UpdateOptionsBuilder::default().test(true).build().unwrap();
+        let (err, lockfile, backup, _) = run(&test, true);
+        assert!(err.is_none());
+        assert_eq!(lockfile, AFTER);
+        assert!(!backup);
+    }
+
+    /// Tests that the lockfile is rolled back when the CI checks fail,
+    /// unless it is kept.
+    #[test]
+    fn test_update_rolls_back_on_failure() {
+        let test =
+            UpdateOptionsBuilder::default().test(true).build().unwrap();
+        let (err, lockfile, backup, _) = run(&test, false);
+        assert_eq!(
+            err.unwrap().to_string(),
+            "The CI checks failed with the updated dependencies, Cargo.lock was rolled back"
+        );
+        assert_eq!(lockfile, BEFORE);
+        assert!(!backup);
+
+        let keep = UpdateOptionsBuilder::default()
+            .test(true)
+            .keep(true)
+            .build()
+            .unwrap();
+        let (err, lockfile, backup, _) = run(&keep, false);
+        assert!(err
+            .unwrap()
+            .to_string()
+            .ends_with("Cargo.lock was kept as updated"));
+        assert_eq!(lockfile, AFTER);
+        assert!(!backup);
+    }
+
+    /// Tests the single-dependency update and its validation.
+    #[test]
+    fn test_update_package() {
+        let opts = UpdateOptionsBuilder::default()
+            .package("serde".to_string())
+            .precise("1.0.200".to_string())
+            .build()
+            .unwrap();
+        let (err, _, _, lines) = run(&opts, true);
+        assert!(err.is_none());
+        assert!(lines[0].ends_with(" -p serde --precise 1.0.200"));
+
+        let precise = UpdateOptionsBuilder::default()
+            .precise("1.0.200".to_string())
+            .build()
+            .unwrap();
+        let err = update_with(Path::new("."), &precise, || Ok(()))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "--precise requires the dependency to update with --package"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use std::{path::Path, sync::Arc};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::vars::{
+        parse_version, report, EnvReport, REPORTED_TOOLS, UNKNOWN,
+    };
+
+    /// The `cargo metadata` output of a workspace with two packages.
+    const METADATA: &str = r#"{
+        "packages": [
+            {"name": "app", "version": "1.2.3", "id": "app 1.2.3"},
+            {"name": "xtask", "version": "0.0.0", "id": "xtask 0.0.0"},
+            {"name": "serde", "version": "1.0.0", "id": "serde 1.0.0"}
+        ],
+        "workspace_members": ["app 1.2.3", "xtask 0.0.0"],
+        "workspace_root": "/work/app"
+    }"#;
+
+    /// Returns the report with mocked probes: `cargo hack` fails, `grcov`
+    /// is missing and `cargo bloat` prints no version.
+    fn mocked_report() -> EnvReport {
+        let recorder = RecordingRunner::default()
+            .with_stdout("cargo metadata", METADATA)
+            .with_stdout("rustc -vV", "rustc 1.75.0\nhost: wasm-test\n")
+            .with_stdout(
+                "rustc --version",
+                "rustc 1.75.0 (82e1608df)\n",
+            )
+            .with_stdout(
+                "cargo --version",
+                "cargo 1.75.0 (1d8b05cdd)\n",
+            )
+            .with_stdout("cargo watch", "cargo-watch 8.4.0\n")
+            .with_stdout(
+                "cargo tarpaulin",
+                "cargo-tarpaulin version: 0.27.3\n",
+            )
+            .failing("cargo hack")
+            .missing("grcov");
+        with_runner(Arc::new(recorder), report)
+    }
+
+    /// Tests the report built from the mocked probes.
+    #[test]
+    fn test_report() {
+        let report = mocked_report();
+        assert_eq!(report.root, Path::new("/work/app"));
+        let packages: Vec<(&str, &str)> = report
+            .packages
+            .iter()
+            .map(|package| {
+                (package.name.as_str(), package.version.as_str())
+            })
+            .collect();
+        assert_eq!(packages, [("app", "1.2.3"), ("xtask", "0.0.0")]);
+        assert_eq!(report.host, "wasm-test");
+        assert_eq!(report.rustc, "rustc 1.75.0 (82e1608df)");
+        assert_eq!(report.cargo, "cargo 1.75.0 (1d8b05cdd)");
+        let tools: Vec<(&str, bool, &str)> = report
+            .tools
+            .iter()
+            .map(|tool| {
+                (
+                    tool.name.as_str(),
+                    tool.installed,
+                    tool.version.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            tools,
+            [
+                ("cargo-watch", true, "8.4.0"),
+                ("cargo-tarpaulin", true, "0.27.3"),
+                ("cargo-bloat", true, UNKNOWN),
+                ("cargo-hack", true, UNKNOWN),
+                ("grcov", false, UNKNOWN),
+            ]
+        );
+
+        let text = report.to_string();
+        assert!(text
+            .starts_with("root: /work/app\npackages:\n  app 1.2.3\n"));
+        assert!(text.contains("\n  cargo-hack       unknown\n"));
+        assert!(text.contains("\n  grcov            not installed\n"));
+    }
+
+    /// Tests that the JSON report has the schema expected by tooling, and
+    /// that probes failing altogether leave the report usable.
+    #[test]
+    fn test_json_schema() {
+        let json: Value =
+            serde_json::from_str(&mocked_report().to_json().unwrap())
+                .unwrap();
+        let object = json.as_object().unwrap();
+        let mut keys: Vec<&str> =
+            object.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            [
+                "cargo", "ci", "env", "host", "packages", "root",
+                "rustc", "tools"
+            ]
+        );
+        for key in ["root", "host", "rustc", "cargo"] {
+            assert!(json[key].is_string(), "{key}");
+        }
+        assert!(json["ci"].is_null() || json["ci"].is_string());
+        assert!(json["env"].is_object());
+        assert_eq!(json["packages"][0]["name"], "app");
+        assert_eq!(json["packages"][0]["version"], "1.2.3");
+        let tools = json["tools"].as_array().unwrap();
+        assert_eq!(tools.len(), REPORTED_TOOLS.len());
+        for tool in tools {
+            assert!(tool["name"].is_string());
+            assert!(tool["installed"].is_boolean());
+            assert!(tool["version"].is_string());
+        }
+        let parsed: EnvReport =
+            serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(parsed, mocked_report());
+
+        let failing = RecordingRunner::default()
+            .failing("cargo")
+            .failing("rustc")
+            .failing("grcov");
+        let report = with_runner(Arc::new(failing), report);
+        assert!(report.packages.is_empty());
+        assert_eq!(report.host, UNKNOWN);
+        assert_eq!(report.rustc, UNKNOWN);
+        assert!(report
+            .tools
+            .iter()
+            .all(|tool| tool.version == UNKNOWN));
+    }
+
+    /// Tests the extraction of version numbers.
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("grcov v0.8.19\nmore").as_deref(),
+            Some("0.8.19")
+        );
+        assert_eq!(parse_version(""), None);
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, path::PathBuf, sync::Arc};
+    use xtasks::ops::{
+        diff_dirs,
+        runner::{with_runner, RecordingRunner},
+    };
+    use xtasks::tasks::vendor::{differing_crates, vendor_in};
+
+    /// Creates a workspace without dependencies, which cargo vendors
+    /// offline, with an existing cargo configuration.
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"fixture\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "").unwrap();
+        fs::create_dir_all(dir.path().join(".cargo")).unwrap();
+        fs::write(
+            dir.path().join(".cargo/config.toml"),
+            "[alias]\nxtask = \"run --package xtask --\"\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    /// Writes `contents` to `path` under `root`, creating its parents.
+    fn write(root: &Path, path: &str, contents: &str) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Tests that vendoring points the cargo configuration at the vendored
+    /// sources once, keeping its other settings.
+    #[test]
+    fn test_vendor_writes_config() {
+        let dir = workspace();
+        let config = dir.path().join(".cargo/config.toml");
+        vendor_in(dir.path(), Path::new("third-party"), false).unwrap();
+        let expected = "[alias]\nxtask = \"run --package xtask --\"\n\n\
+                        [source.crates-io]\nreplace-with = \"vendored-sources\"\n\n\
+                        [source.vendored-sources]\ndirectory = \"third-party\"\n";
+        assert_eq!(fs::read_to_string(&config).unwrap(), expected);
+        vendor_in(dir.path(), Path::new("third-party"), false).unwrap();
+        assert_eq!(fs::read_to_string(&config).unwrap(), expected);
+    }
+
+    /// Tests that the check passes when the committed sources match and
+    /// names the crates that differ otherwise.
+    #[test]
+    fn test_vendor_check() {
+        let dir = workspace();
+        vendor_in(dir.path(), Path::new("vendor"), true).unwrap();
+
+        write(dir.path(), "vendor/stale-1.0.0/src/lib.rs", "");
+        write(dir.path(), "vendor/stale-1.0.0/Cargo.toml", "");
+        write(
+            dir.path(),
+            "vendor/other-0.2.0/.cargo-checksum.json",
+            "{}",
+        );
+        let err = vendor_in(dir.path(), Path::new("vendor"), true)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The sources vendored in vendor are out of date for 2 crates: other-0.2.0, stale-1.0.0 (run `cargo xtask vendor`)"
+        );
+        assert_eq!(xtasks::ops::error::exit_code(&err), 12);
+        assert!(!dir.path().join("target/xtask/vendor").exists());
+    }
+
+    /// Tests the cargo vendor invocations of both modes.
+    #[test]
+    fn test_vendor_commands() {
+        let dir = workspace();
+        let runner = Arc::new(RecordingRunner::default());
+        with_runner(runner.clone(), || {
+            vendor_in(dir.path(), Path::new("vendor"), false)
+        })
+        .unwrap();
+        let root = dir.path().display();
+        assert_eq!(
+            runner.command_lines(),
+            [format!(
+                "cargo vendor --manifest-path {root}/Cargo.toml {root}/vendor"
+            )]
+        );
+    }
+
+    /// Tests that files missing on either side or with other contents
+    /// differ, and that a missing directory is empty.
+    #[test]
+    fn test_diff_dirs() {
+        let left = tempfile::tempdir().unwrap();
+        let right = tempfile::tempdir().unwrap();
+        write(left.path(), "a/same.rs", "same");
+        write(right.path(), "a/same.rs", "same");
+        write(left.path(), "a/changed.rs", "old");
+        write(right.path(), "a/changed.rs", "new");
+        write(left.path(), "b/removed.rs", "");
+        write(right.path(), "c/added.rs", "");
+        assert_eq!(
+            diff_dirs(left.path(), right.path()).unwrap(),
+            [
+                PathBuf::from("a/changed.rs"),
+                PathBuf::from("b/removed.rs"),
+                PathBuf::from("c/added.rs"),
+            ]
+        );
+        assert_eq!(
+            differing_crates(left.path(), &left.path().join("missing"))
+                .unwrap(),
+            ["a", "b"]
+        );
+    }
+}
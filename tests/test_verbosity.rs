@@ -0,0 +1,95 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// The log level is process-wide, so the tests changing it live in their own
+// test binary and in a single test.
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use xtasks::loggers::{
+        capture_logs, is_quiet, log_enabled, parse_log_level,
+        set_log_level, task_log, LogLevel,
+    };
+    use xtasks::macro_log_info;
+    use xtasks::ops::runner::{
+        self, with_runner, CommandSpec, RecordingRunner,
+    };
+    use xtasks::tasks::main_with_args;
+
+    /// Returns the levels of the logs emitted at INFO and ERROR.
+    fn emitted() -> Vec<LogLevel> {
+        let ((), logs) = capture_logs(|| {
+            macro_log_info!("test", "starting {}", 1);
+            task_log(LogLevel::ERROR, "test", "failed");
+        });
+        logs.into_iter().map(|log| log.level).collect()
+    }
+
+    /// Runs the `vars` subcommand with `flags` before it.
+    fn run(flags: &[&str]) {
+        let mut args = vec!["xtask".to_string()];
+        args.extend(flags.iter().map(ToString::to_string));
+        args.push("vars".to_string());
+        main_with_args(&args).unwrap();
+    }
+
+    /// Tests that `--quiet` drops the INFO logs but keeps the errors, that
+    /// `--verbose` enables the debug logs, and that the quiet level hides the
+    /// output of passing commands only.
+    #[test]
+    fn test_quiet_and_verbose_levels() {
+        set_log_level(None);
+        assert_eq!(emitted(), [LogLevel::INFO, LogLevel::ERROR]);
+        assert!(!log_enabled(&LogLevel::DEBUG));
+
+        run(&["--quiet"]);
+        assert!(is_quiet());
+        assert_eq!(emitted(), [LogLevel::ERROR]);
+
+        let recorder = Arc::new(
+            RecordingRunner::default()
+                .with_stdout("cargo build", "compiled")
+                .failing("cargo test"),
+        );
+        with_runner(recorder.clone(), || {
+            runner::run(&CommandSpec::new("cargo").arg("build"))
+                .unwrap();
+            assert!(runner::run(
+                &CommandSpec::new("cargo").arg("test")
+            )
+            .is_err());
+        });
+        assert!(recorder.calls().iter().all(|call| call.capture));
+
+        run(&["-v"]);
+        assert!(log_enabled(&LogLevel::DEBUG));
+        assert!(!log_enabled(&LogLevel::TRACE));
+        run(&["-vv"]);
+        assert!(log_enabled(&LogLevel::TRACE));
+
+        run(&[]);
+        assert!(!is_quiet());
+        assert_eq!(emitted(), [LogLevel::INFO, LogLevel::ERROR]);
+        let recorder = Arc::new(RecordingRunner::default());
+        with_runner(recorder.clone(), || {
+            runner::run(&CommandSpec::new("cargo").arg("build"))
+                .unwrap();
+        });
+        assert!(!recorder.calls()[0].capture);
+
+        set_log_level(parse_log_level("error").as_ref());
+        assert_eq!(emitted(), [LogLevel::ERROR]);
+        set_log_level(None);
+    }
+
+    /// Tests the names accepted by `XTASKS_LOG`.
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(parse_log_level("debug"), Some(LogLevel::DEBUG));
+        assert_eq!(parse_log_level(" INFO "), Some(LogLevel::INFO));
+        assert_eq!(parse_log_level("warn"), Some(LogLevel::WARNING));
+        assert_eq!(parse_log_level("error"), Some(LogLevel::ERROR));
+        assert_eq!(parse_log_level("off"), Some(LogLevel::NONE));
+        assert_eq!(parse_log_level("verbose"), None);
+    }
+}
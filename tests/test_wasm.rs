@@ -0,0 +1,153 @@
+// Copyright © 2023 xtasks. All rights reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path, sync::Arc};
+    use xtasks::ops::runner::{with_runner, RecordingRunner};
+    use xtasks::tasks::wasm::{
+        build, BindgenTarget, WasmOptionsBuilder, WasmSize,
+    };
+
+    /// Tests the pipeline of a release build with bindings for Node.js
+    /// and wasm-opt, when the target and the tools are installed.
+    #[test]
+    fn test_wasm_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = dir.path().join("target");
+        let out_dir = dir.path().join("pkg");
+        fs::create_dir_all(&out_dir).unwrap();
+        fs::write(out_dir.join("my_app_bg.wasm"), [0; 2048]).unwrap();
+        let opts = WasmOptionsBuilder::default()
+            .package(Some("my-app".to_string()))
+            .release(true)
+            .target_dir(target_dir.clone())
+            .out_dir(out_dir.clone())
+            .bindgen_target(BindgenTarget::Nodejs)
+            .opt_level(Some("z".to_string()))
+            .build()
+            .unwrap();
+        let runner = Arc::new(RecordingRunner::default().with_stdout(
+            "rustup target list",
+            "wasm32-unknown-unknown\n",
+        ));
+        let module =
+            with_runner(runner.clone(), || build(opts)).unwrap();
+        assert_eq!(module, out_dir.join("my_app_bg.wasm"));
+
+        let artifact = target_dir
+            .join("wasm32-unknown-unknown")
+            .join("release")
+            .join("my_app.wasm");
+        let display = |path: &Path| path.display().to_string();
+        assert_eq!(
+            runner.command_lines(),
+            [
+                "rustup target list --installed".to_string(),
+                format!(
+                    "cargo build --target wasm32-unknown-unknown -p my-app --target-dir {} --release",
+                    display(&target_dir)
+                ),
+                format!(
+                    "wasm-bindgen --target nodejs --out-dir {} {}",
+                    display(&out_dir),
+                    display(&artifact)
+                ),
+                format!(
+                    "wasm-opt -Oz {} -o {}",
+                    display(&module),
+                    display(&module)
+                ),
+            ]
+        );
+    }
+
+    /// Tests that the missing target and tools are installed first, and
+    /// that the post-processing steps can be skipped.
+    #[test]
+    fn test_wasm_installs_missing_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = dir.path().join("target");
+        let debug = target_dir.join("wasm32-unknown-unknown/debug");
+        fs::create_dir_all(&debug).unwrap();
+        fs::write(debug.join("app.wasm"), [0; 16]).unwrap();
+        let opts = WasmOptionsBuilder::default()
+            .package(Some("app".to_string()))
+            .target_dir(target_dir)
+            .bindgen(false)
+            .opt_level(Some("s".to_string()))
+            .build()
+            .unwrap();
+        let runner =
+            Arc::new(RecordingRunner::default().missing("wasm-opt"));
+        let module =
+            with_runner(runner.clone(), || build(opts)).unwrap();
+        assert_eq!(module, debug.join("app.wasm"));
+        let lines = runner.command_lines();
+        assert_eq!(
+            lines[1],
+            "rustup target add wasm32-unknown-unknown"
+        );
+        assert!(lines.contains(&"cargo install wasm-opt".to_string()));
+        assert!(!lines
+            .iter()
+            .any(|line| line.starts_with("wasm-bindgen")));
+    }
+
+    /// Tests that an unknown optimization level fails before building.
+    #[test]
+    fn test_wasm_rejects_unknown_level() {
+        let runner = Arc::new(RecordingRunner::default());
+        let err = with_runner(runner.clone(), || {
+            build(
+                WasmOptionsBuilder::default()
+                    .package(Some("app".to_string()))
+                    .opt_level(Some("9".to_string()))
+                    .build()
+                    .unwrap(),
+            )
+        })
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("Unknown wasm-opt level '9'"));
+        assert!(runner.command_lines().is_empty());
+    }
+
+    /// Tests the formatting of the size before and after optimization.
+    #[test]
+    fn test_wasm_size_report() {
+        assert_eq!(
+            WasmSize {
+                before: 2048,
+                after: None
+            }
+            .to_string(),
+            "2.0KiB"
+        );
+        assert_eq!(
+            WasmSize {
+                before: 2 * 1024 * 1024,
+                after: Some(1536 * 1024)
+            }
+            .to_string(),
+            "2.0MiB -> 1.5MiB (-512.0KiB, -25.0%)"
+        );
+        assert_eq!(
+            WasmSize {
+                before: 1000,
+                after: Some(1010)
+            }
+            .to_string(),
+            "1000B -> 1010B (+10B, +1.0%)"
+        );
+        assert_eq!(
+            WasmSize {
+                before: 0,
+                after: Some(0)
+            }
+            .to_string(),
+            "0B -> 0B (+0B, +0.0%)"
+        );
+    }
+}